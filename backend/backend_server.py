@@ -1,19 +1,42 @@
 import os
 import json
 import argparse
+import base64
+import calendar
+import difflib
+import email.utils
+import hashlib
+import hmac
+import ipaddress
+import random
+import re
+import asyncio
+import signal
 import socket
 import sqlite3
 import shutil
+import sys
+import threading
+import time
+import urllib.parse
+import urllib.request
 import uuid
-from concurrent.futures import ThreadPoolExecutor
+from collections import deque
+from concurrent.futures import Future, ThreadPoolExecutor
+from urllib.parse import urlparse
 
-from fastapi import FastAPI, UploadFile, File, Form, HTTPException
+from fastapi import FastAPI, UploadFile, File, Form, HTTPException, Request, WebSocket, WebSocketDisconnect
 from fastapi.middleware.cors import CORSMiddleware
-from fastapi.responses import FileResponse
+from fastapi.responses import FileResponse, JSONResponse, StreamingResponse
 
 from docx import Document
+import httpx
+import openai
 from openai import OpenAI
 
+# Checked by the desktop shell at startup; bump together with its supported range.
+BACKEND_VERSION = "0.1.0"
+
 # -----------------------------
 # Compatibility fix (Windows)
 # Some environments may not expose socket.AF_UNIX.
@@ -48,6 +71,12 @@ def work_dir(task_id: str) -> str:
     return d
 
 
+def uploads_dir() -> str:
+    d = os.path.join(data_dir(), "uploads")
+    os.makedirs(d, exist_ok=True)
+    return d
+
+
 def export_dir(task_id: str) -> str:
     d = os.path.join(data_dir(), "exports", task_id)
     os.makedirs(d, exist_ok=True)
@@ -99,11 +128,75 @@ def init_db():
       status TEXT NOT NULL
     )"""
     )
+    cur.execute(
+        """
+    CREATE TABLE IF NOT EXISTS usage(
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      ts REAL NOT NULL,
+      provider TEXT NOT NULL,
+      model TEXT NOT NULL,
+      task_id TEXT,
+      prompt_tokens INTEGER NOT NULL DEFAULT 0,
+      completion_tokens INTEGER NOT NULL DEFAULT 0,
+      chars_in INTEGER NOT NULL DEFAULT 0,
+      chars_out INTEGER NOT NULL DEFAULT 0
+    )"""
+    )
+    cur.execute("CREATE INDEX IF NOT EXISTS usage_ts ON usage(ts)")
+    cur.execute(
+        """
+    CREATE TABLE IF NOT EXISTS profiles(
+      id TEXT PRIMARY KEY,
+      name TEXT NOT NULL UNIQUE,
+      base_url TEXT NOT NULL DEFAULT '',
+      model TEXT NOT NULL DEFAULT '',
+      direction TEXT NOT NULL DEFAULT 'en->zh',
+      glossary TEXT NOT NULL DEFAULT '[]',
+      prompt_template TEXT NOT NULL DEFAULT '',
+      updated_at REAL NOT NULL
+    )"""
+    )
+    cur.execute(
+        """
+    CREATE TABLE IF NOT EXISTS tm(
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      source_lang TEXT NOT NULL,
+      target_lang TEXT NOT NULL,
+      source_text TEXT NOT NULL,
+      target_text TEXT NOT NULL,
+      project TEXT NOT NULL DEFAULT '',
+      creator TEXT NOT NULL DEFAULT '',
+      created_at REAL NOT NULL,
+      changed_at REAL NOT NULL,
+      meta TEXT NOT NULL DEFAULT '{}',
+      UNIQUE(source_lang, target_lang, source_text, target_text)
+    )"""
+    )
+    cur.execute("CREATE INDEX IF NOT EXISTS tm_changed ON tm(changed_at)")
+    cur.execute("CREATE INDEX IF NOT EXISTS tm_source ON tm(source_text)")
+    # character trigrams of each unit's source, for fuzzy lookups
+    cur.execute("CREATE TABLE IF NOT EXISTS tm_grams(gram TEXT NOT NULL, unit_id INTEGER NOT NULL)")
+    cur.execute("CREATE INDEX IF NOT EXISTS tm_grams_gram ON tm_grams(gram)")
+    cur.execute("CREATE INDEX IF NOT EXISTS tm_grams_unit ON tm_grams(unit_id)")
+    # keys of each unit's texts, to find units by while their texts are sealed
+    cur.execute(
+        "CREATE TABLE IF NOT EXISTS tm_keys(unit_id INTEGER PRIMARY KEY, source TEXT NOT NULL, target TEXT NOT NULL)"
+    )
+    cur.execute("CREATE INDEX IF NOT EXISTS tm_keys_source ON tm_keys(source)")
+    # what the memory gave each task: {band: {segments, words}}
+    cur.execute("CREATE TABLE IF NOT EXISTS tm_leverage(task_id TEXT PRIMARY KEY, stats TEXT NOT NULL)")
+    cur.execute(
+        """
+    CREATE TABLE IF NOT EXISTS active_profile(
+      id INTEGER PRIMARY KEY CHECK (id=1),
+      profile_id TEXT
+    )"""
+    )
     conn.commit()
     conn.close()
 
 
-def get_settings():
+def _settings_row():
     conn = db()
     row = conn.execute(
         "SELECT base_url, api_key, model FROM settings WHERE id=1"
@@ -112,6 +205,14 @@ def get_settings():
     return dict(row) if row else None
 
 
+def get_settings():
+    """Saved settings, with the API key from the OS keychain if there is one."""
+    s = _settings_row()
+    if s:
+        s["api_key"] = provider_key(s["base_url"], s["api_key"])
+    return s
+
+
 def upsert_settings(base_url: str, api_key: str, model: str):
     conn = db()
     conn.execute(
@@ -192,17 +293,320 @@ def apply_translations(src_docx: str, out_docx: str, locator_to_text: dict):
     doc.save(out_docx)
 
 
-def build_messages(text: str, direction: str):
+# ---------- glossary ----------
+# The shell's terminology base, `<data dir>/glossary.json` (see glossary.rs
+# there): [{direction, source, target, case_sensitive, forbidden, note}].
+# A term applies the other way round too, unless it is forbidden: then its
+# target must not be used in translations (of texts with its source, if it
+# has one). Re-read when the file changes.
+_glossary = {"mtime": None, "terms": []}
+
+
+def glossary_terms() -> list:
+    path = os.path.join(data_dir(), "glossary.json")
+    try:
+        mtime = os.path.getmtime(path)
+    except OSError:
+        return []
+    if mtime != _glossary["mtime"]:
+        try:
+            with open(path, encoding="utf-8") as f:
+                terms = json.load(f)
+        except (OSError, ValueError):
+            terms = []
+        _glossary.update(mtime=mtime, terms=terms if isinstance(terms, list) else [])
+    return _glossary["terms"]
+
+
+def term_occurs(term: str, text: str, case_sensitive: bool) -> bool:
+    """Whether `term` is in `text`; a term starting or ending in an ASCII
+    letter or digit only as a whole word there."""
+    if not term:
+        return False
+    pattern = re.escape(term)
+    if term[0].isascii() and term[0].isalnum():
+        pattern = r"(?<![A-Za-z0-9])" + pattern
+    if term[-1].isascii() and term[-1].isalnum():
+        pattern += r"(?![A-Za-z0-9])"
+    return re.search(pattern, text, 0 if case_sensitive else re.IGNORECASE) is not None
+
+
+def glossary_for(text: str, direction: str):
+    """The glossary's (source, target) pairs for a block's text, and the
+    targets it must not use."""
+    required, forbidden = [], []
+    for t in glossary_terms():
+        source, target = str(t.get("source") or ""), str(t.get("target") or "")
+        case = bool(t.get("case_sensitive"))
+        if t.get("forbidden"):
+            if t.get("direction") == direction and target and (not source or term_occurs(source, text, case)):
+                forbidden.append(target)
+            continue
+        if t.get("direction") != direction:
+            if "->".join(reversed(str(t.get("direction") or "").split("->"))) != direction:
+                continue
+            source, target = target, source
+        if target and term_occurs(source, text, case):
+            required.append((source, target))
+    return required, forbidden
+
+
+def build_messages(text: str, direction: str, profile=None, match=None):
+    """Chat messages for one block. The glossary's and a profile's terms
+    that occur in the text are added, and so is a fuzzy translation memory
+    `match`; the profile's prompt template ({target}, {text}) replaces the
+    default request."""
     target = "English" if direction == "zh->en" else "Chinese"
     system = (
         "You are a professional translator. Output only the translation. "
         "Do not add explanations or any extra text. "
-        "Preserve numbers, units, symbols, and formatting as much as possible."
+        "Preserve numbers, units, symbols, and formatting as much as possible. "
+        "Keep placeholders such as ⟦1⟧ and ⟦/1⟧ exactly as they are, around the same words."
     )
-    user = f"Translate the following text into {target}:\n\n{text}"
+    template = "Translate the following text into {target}:\n\n{text}"
+    terms = []
+    if profile:
+        terms = [(g["source"], g.get("target", "")) for g in profile["glossary"] if g.get("source") and g["source"] in text]
+        template = profile["prompt_template"] or template
+    # the profile's own translation of a term wins
+    glossary, forbidden = glossary_for(text, direction)
+    terms += [(s, t) for s, t in glossary if s not in {s for s, _ in terms}]
+    if terms:
+        system += "\nAlways use these translations for the following terms:\n" + "\n".join(
+            f"- {source} => {target}" for source, target in terms
+        )
+    if forbidden:
+        system += "\nNever use these terms in the translation:\n" + "\n".join(f"- {t}" for t in forbidden)
+    if match:
+        system += (
+            "\nA similar text was translated before; reuse its wording where it fits:\n"
+            f"Source: {match['source']}\nTranslation: {match['target']}"
+        )
+    # replace, not format: the text may contain braces
+    user = template.replace("{target}", target).replace("{text}", text)
     return [{"role": "system", "content": system}, {"role": "user", "content": user}]
 
 
+# ---------- usage ledger ----------
+def record_usage(s: dict, task_id, messages: list, out: str, usage=None):
+    """One provider call. Token counts come from the provider when it
+    reports them; characters are always recorded."""
+    conn = db()
+    try:
+        conn.execute(
+            "INSERT INTO usage(ts, provider, model, task_id, prompt_tokens, completion_tokens, chars_in, chars_out) "
+            "VALUES(?,?,?,?,?,?,?,?)",
+            (
+                time.time(),
+                (urlparse(s["base_url"]).hostname or s["base_url"]).lower(),
+                s["model"],
+                task_id,
+                getattr(usage, "prompt_tokens", 0) or 0,
+                getattr(usage, "completion_tokens", 0) or 0,
+                sum(len(m["content"]) for m in messages),
+                len(out),
+            ),
+        )
+        conn.commit()
+    finally:
+        conn.close()
+
+
+def _period_start(period: str):
+    now = time.localtime()
+    if period == "day":
+        start = (now.tm_year, now.tm_mon, now.tm_mday)
+    elif period == "week":
+        start = time.localtime(time.time() - now.tm_wday * 86400)[:3]
+    elif period == "month":
+        start = (now.tm_year, now.tm_mon, 1)
+    elif period == "all":
+        return 0.0
+    else:
+        raise HTTPException(400, "period must be day, week, month or all")
+    return time.mktime((*start, 0, 0, 0, 0, 0, -1))
+
+
+# ---------- encryption at rest ----------
+# With MVP_ENCRYPT_AT_REST=1, block texts and translation-memory texts in
+# the db and translation-cache entries are stored AES-256-GCM encrypted under keys derived (HKDF-SHA256)
+# from the secret the shell keeps in the OS keychain (MVP_DATA_KEY).
+# Sealed values carry a prefix, so plain and sealed data can coexist while
+# migrate_at_rest() converts existing data; switched off, it decrypts them
+# back as long as the secret is still handed over.
+SEALED = "enc1:"
+_data_secret = base64.b64decode(os.environ.pop("MVP_DATA_KEY", "") or "")
+_encrypt = os.environ.get("MVP_ENCRYPT_AT_REST") == "1" and bool(_data_secret)
+_ciphers = {}
+_macs = {}
+
+
+def _derive(purpose: str) -> bytes:
+    if not _data_secret:
+        raise RuntimeError("encrypted data found, but no data key was provided")
+    from cryptography.hazmat.primitives import hashes
+    from cryptography.hazmat.primitives.kdf.hkdf import HKDF
+
+    return HKDF(hashes.SHA256(), 32, None, f"ai-doc-translator {purpose} v1".encode()).derive(_data_secret)
+
+
+def _cipher(purpose: str):
+    if purpose not in _ciphers:
+        from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+        _ciphers[purpose] = AESGCM(_derive(purpose))
+    return _ciphers[purpose]
+
+
+def mac(text: str, purpose: str) -> str:
+    """HMAC-SHA256 of `text` under a key derived for `purpose`, to look
+    sealed values up by without unsealing them."""
+    if purpose not in _macs:
+        _macs[purpose] = _derive(purpose)
+    return hmac.new(_macs[purpose], text.encode("utf-8"), hashlib.sha256).hexdigest()
+
+
+def is_sealed(value) -> bool:
+    return isinstance(value, str) and value.startswith(SEALED)
+
+
+def seal(text, purpose: str = "db"):
+    if text is None or not _encrypt:
+        return text
+    nonce = os.urandom(12)
+    sealed = _cipher(purpose).encrypt(nonce, text.encode("utf-8"), None)
+    return SEALED + base64.b64encode(nonce + sealed).decode("ascii")
+
+
+def unseal(value, purpose: str = "db"):
+    if not is_sealed(value):
+        return value
+    raw = base64.b64decode(value[len(SEALED):])
+    return _cipher(purpose).decrypt(raw[:12], raw[12:], None).decode("utf-8")
+
+
+def open_block(row) -> dict:
+    b = dict(row)
+    for k in ("source_text", "translated_text"):
+        if k in b:
+            b[k] = unseal(b[k])
+    return b
+
+
+def migrate_at_rest():
+    """Brings existing block texts, translation-memory units and cache
+    entries in line with the current setting; runs once per launch, in the
+    background."""
+    if not _data_secret:
+        return
+    wanted = seal if _encrypt else (lambda v, purpose="db": v)
+
+    def convert(value, purpose="db"):
+        if value is None or is_sealed(value) == _encrypt:
+            return None
+        return wanted(unseal(value, purpose), purpose)
+
+    conn = db()
+    try:
+        rows = conn.execute("SELECT id, source_text, translated_text FROM blocks").fetchall()
+        for row in rows:
+            src, dst = convert(row["source_text"]), convert(row["translated_text"])
+            if src is not None or dst is not None:
+                conn.execute(
+                    "UPDATE blocks SET source_text=COALESCE(?, source_text), "
+                    "translated_text=COALESCE(?, translated_text) WHERE id=?",
+                    (src, dst, row["id"]),
+                )
+        rows = conn.execute("SELECT id, source_text, target_text FROM tm").fetchall()
+        for row in rows:
+            if is_sealed(row["source_text"]) == _encrypt:
+                continue
+            src, dst = unseal(row["source_text"]), unseal(row["target_text"])
+            try:
+                conn.execute(
+                    "UPDATE tm SET source_text=?, target_text=? WHERE id=?", (wanted(src), wanted(dst), row["id"])
+                )
+            except sqlite3.IntegrityError:
+                # stored twice while sealed: the plain copy is kept
+                for table, column in (("tm", "id"), ("tm_grams", "unit_id"), ("tm_keys", "unit_id")):
+                    conn.execute(f"DELETE FROM {table} WHERE {column}=?", (row["id"],))
+                continue
+            _tm_index(conn, row["id"], src, dst, _encrypt)
+        conn.commit()
+    except Exception as e:
+        print(f"encryption migration of the db failed: {e}", file=sys.stderr)
+    finally:
+        conn.close()
+    if not _cache_dir or not os.path.isdir(_cache_dir):
+        return
+    for root, _, files in os.walk(_cache_dir):
+        for name in files:
+            if name.endswith(".tmp"):
+                continue
+            path = os.path.join(root, name)
+            try:
+                with open(path, "r", encoding="utf-8") as f:
+                    converted = convert(f.read(), "cache")
+                if converted is not None:
+                    tmp = f"{path}.{uuid.uuid4().hex}.tmp"
+                    with open(tmp, "w", encoding="utf-8") as f:
+                        f.write(converted)
+                    stat = os.stat(path)
+                    os.replace(tmp, path)
+                    os.utime(path, (stat.st_atime, stat.st_mtime))  # keep the LRU order
+            except Exception:
+                # unreadable or sealed under a lost key: drop the entry
+                try:
+                    os.remove(path)
+                except OSError:
+                    pass
+
+
+# ---------- translation cache ----------
+# Directory owned by the desktop shell, which also enforces the size limit
+# (LRU by mtime). Unset when caching is off.
+_cache_dir = os.environ.get("MVP_TRANSLATION_CACHE_DIR")
+
+
+def _cache_path(s: dict, messages: list):
+    if not _cache_dir:
+        return None
+    key = json.dumps(
+        {"provider": s["base_url"], "model": s["model"], "messages": messages},
+        ensure_ascii=False,
+        sort_keys=True,
+    )
+    digest = hashlib.sha256(key.encode("utf-8")).hexdigest()
+    return os.path.join(_cache_dir, digest[:2], digest)
+
+
+def cache_get(s: dict, messages: list):
+    path = _cache_path(s, messages)
+    if not path:
+        return None
+    try:
+        with open(path, "r", encoding="utf-8") as f:
+            out = unseal(f.read(), "cache")
+        os.utime(path)  # marks the entry as recently used
+        return out
+    except Exception:
+        return None
+
+
+def cache_put(s: dict, messages: list, out: str):
+    path = _cache_path(s, messages)
+    if not path or not out:
+        return
+    try:
+        os.makedirs(os.path.dirname(path), exist_ok=True)
+        tmp = f"{path}.{uuid.uuid4().hex}.tmp"
+        with open(tmp, "w", encoding="utf-8") as f:
+            f.write(seal(out, "cache"))
+        os.replace(tmp, path)
+    except OSError:
+        pass
+
+
 # ---------- app ----------
 init_db()
 app = FastAPI(title="MVP Backend")
@@ -214,17 +618,116 @@ app.add_middleware(
     allow_headers=["*"],
 )
 
+# Shared secret from the desktop shell; without it (backend started by hand)
+# requests are not checked. Popped so child processes don't inherit it.
+AUTH_HEADER = "x-mvp-token"
+_auth_token = os.environ.pop("MVP_BACKEND_TOKEN", "")
+
+
+def _token_ok(value) -> bool:
+    return not _auth_token or hmac.compare_digest((value or "").encode(), _auth_token.encode())
+
+
+# Provider API keys the shell keeps in the OS keychain, by host: handed over
+# in MVP_API_KEYS at launch and on every provider-tagged request, so a key
+# saved later needs no restart. Kept in memory only, never in the db.
+PROVIDER_KEY_HEADER = "x-provider-api-key"
+PROVIDER_HOST_HEADER = "x-provider-host"
+
+
+def _load_api_keys():
+    try:
+        keys = json.loads(os.environ.pop("MVP_API_KEYS", "") or "{}")
+    except ValueError:
+        return {}
+    return {k.lower(): v for k, v in keys.items()} if isinstance(keys, dict) else {}
+
+
+_api_keys = _load_api_keys()
+_api_keys_lock = threading.Lock()
+
+
+def provider_key(base_url: str, given: str = "") -> str:
+    """The keychain key for base_url's host, else the given (legacy) one."""
+    host = (urlparse(base_url).hostname or "").lower()
+    with _api_keys_lock:
+        return _api_keys.get(host) or given
+
+
+@app.middleware("http")
+async def require_token(request, call_next):
+    if not _token_ok(request.headers.get(AUTH_HEADER)):
+        return JSONResponse({"detail": "unauthorized"}, status_code=401)
+    host = (request.headers.get(PROVIDER_HOST_HEADER) or "").lower()
+    key = request.headers.get(PROVIDER_KEY_HEADER)
+    if host and key:
+        with _api_keys_lock:
+            _api_keys[host] = key
+    return await call_next(request)
+
+
 executor = ThreadPoolExecutor(max_workers=2)
 
+# task id -> time the task last made progress; reported in heartbeats so the
+# shell can spot workers that are stuck while HTTP still answers
+_task_progress = {}
+_task_progress_lock = threading.Lock()
+
+
+def _touch_task(task_id: str):
+    with _task_progress_lock:
+        _task_progress[task_id] = time.monotonic()
+
+
+# task id -> {block id: translation so far} of the blocks whose translation
+# is streaming in; pushed to job-event subscribers as `segment-partial`
+# messages. Only this instance's tasks are seen.
+_partials = {}
+_partials_lock = threading.Lock()
+
+
+def _set_partial(task_id: str, block_id: str, text: str = None):
+    with _partials_lock:
+        blocks = _partials.setdefault(task_id, {})
+        if text is None:
+            blocks.pop(block_id, None)
+        else:
+            blocks[block_id] = text
+        if not blocks:
+            _partials.pop(task_id, None)
+
+
+def _task_partials(task_id: str) -> dict:
+    with _partials_lock:
+        return dict(_partials.get(task_id, {}))
+
+# set in main(); lets /api/shutdown stop uvicorn cleanly
+server = None
+
 
 @app.get("/api/health")
 def health():
     return {"ok": True}
 
 
+@app.get("/api/version")
+def version():
+    return {"version": BACKEND_VERSION}
+
+
+@app.post("/api/shutdown")
+def shutdown():
+    # Called by the desktop shell on exit. Let uvicorn finish the current
+    # requests and exit its loop instead of being killed mid-write.
+    executor.shutdown(wait=False, cancel_futures=True)
+    if server is not None:
+        server.should_exit = True
+    return {"ok": True}
+
+
 @app.get("/api/settings")
 def api_get_settings():
-    s = get_settings()
+    s = _settings_row()
     return s or {"base_url": "", "api_key": "", "model": ""}
 
 
@@ -233,7 +736,8 @@ def api_save_settings(payload: dict):
     base_url = normalize_base_url(payload.get("base_url") or "")
     api_key = (payload.get("api_key") or "").strip()
     model = (payload.get("model") or "").strip()
-    if not base_url or not api_key or not model:
+    # an empty api_key is fine when the keychain has one for this provider
+    if not base_url or not provider_key(base_url, api_key) or not model:
         raise HTTPException(400, "base_url/api_key/model required")
     upsert_settings(base_url, api_key, model)
     return {"ok": True, "base_url": base_url, "model": model}
@@ -242,7 +746,7 @@ def api_save_settings(payload: dict):
 @app.post("/api/models")
 def api_list_models(payload: dict):
     base_url = normalize_base_url(payload.get("base_url") or "")
-    api_key = (payload.get("api_key") or "").strip()
+    api_key = provider_key(base_url, (payload.get("api_key") or "").strip())
     if not base_url or not api_key:
         raise HTTPException(400, "base_url and api_key required")
 
@@ -264,12 +768,684 @@ def api_list_models(payload: dict):
     return {"base_url": base_url, "models": ids_sorted}
 
 
+# ---------- provider checks ----------
+# One cheap authenticated request per provider kind, so a bad key or
+# endpoint shows up before a job starts instead of halfway through it.
+def _check_request(kind: str, endpoint: str, key: str, region: str = ""):
+    """(url, headers, params, parse) for kind; parse(json) -> (models, languages)."""
+    def model_ids(j):
+        return sorted(m["id"] for m in j.get("data") or [] if m.get("id")), []
+
+    if kind in ("openai", "local"):
+        return (normalize_base_url(endpoint) + "/models",
+                {"Authorization": f"Bearer {key}"} if key else {}, {}, model_ids)
+    if kind == "azure":
+        return (endpoint + "/openai/models", {"api-key": key}, {"api-version": "2024-10-21"}, model_ids)
+    if kind == "anthropic":
+        return (endpoint + "/v1/models", {"x-api-key": key, "anthropic-version": "2023-06-01"},
+                {"limit": 1000}, model_ids)
+    if kind == "deepl":
+        return (endpoint + "/v2/languages", {"Authorization": f"DeepL-Auth-Key {key}"},
+                {"type": "target"}, lambda j: ([], sorted(lang["language"] for lang in j)))
+    if kind == "google":
+        return (endpoint + "/language/translate/v2/languages", {}, {"key": key},
+                lambda j: ([], sorted(lang["language"] for lang in j["data"]["languages"])))
+    if kind == "ollama":
+        return (endpoint + "/api/tags", {"Authorization": f"Bearer {key}"} if key else {}, {},
+                lambda j: (sorted(m["name"] for m in j.get("models") or [] if m.get("name")), []))
+    if kind == "azure-translator":
+        # the language list needs no key: detecting a word does, and costs next to nothing
+        headers = {"Ocp-Apim-Subscription-Key": key}
+        if region:
+            headers["Ocp-Apim-Subscription-Region"] = region
+        return (endpoint + "/detect", headers, {"api-version": "3.0"}, lambda j: ([], []))
+    raise HTTPException(400, f"unknown provider kind {kind}")
+
+
+@app.post("/api/providers/validate")
+def validate_provider(payload: dict):
+    """Checks {kind, endpoint, key_ref, region}: the key comes from the
+    keychain entry key_ref, region is Azure Translator's; returns {ok, status, message, latency_ms, models, languages}."""
+    kind = payload.get("kind") or ""
+    endpoint = (payload.get("endpoint") or "").strip().rstrip("/")
+    if not endpoint:
+        raise HTTPException(400, "endpoint required")
+    with _api_keys_lock:
+        key = _api_keys.get((payload.get("key_ref") or "").lower(), "")
+    if not key and kind not in ("local", "ollama"):
+        return {"ok": False, "status": None, "message": "no API key stored for this provider",
+                "latency_ms": None, "models": [], "languages": []}
+    url, headers, params, parse = _check_request(kind, endpoint, key, (payload.get("region") or "").strip())
+    started = time.monotonic()
+    try:
+        if kind == "azure-translator":
+            r = httpx.post(url, headers=headers, params=params, json=[{"Text": "ok"}], timeout=15)
+        else:
+            r = httpx.get(url, headers=headers, params=params, timeout=15)
+    except httpx.HTTPError as e:
+        return {"ok": False, "status": None, "message": f"cannot reach {endpoint}: {e}",
+                "latency_ms": None, "models": [], "languages": []}
+    latency_ms = int((time.monotonic() - started) * 1000)
+    if r.status_code in (401, 403):
+        message = "the API key was rejected"
+    elif r.status_code == 404:
+        message = "the endpoint does not look like a " + kind + " API"
+    elif r.status_code >= 400:
+        message = f"the provider answered {r.status_code}: {r.text[:200]}"
+    else:
+        try:
+            models, languages = parse(r.json())
+        except (ValueError, KeyError, TypeError):
+            return {"ok": False, "status": r.status_code, "message": "unexpected answer from the provider",
+                    "latency_ms": latency_ms, "models": [], "languages": []}
+        return {"ok": True, "status": r.status_code, "message": "ok", "latency_ms": latency_ms,
+                "models": models, "languages": languages}
+    return {"ok": False, "status": r.status_code, "message": message,
+            "latency_ms": latency_ms, "models": [], "languages": []}
+
+
+@app.get("/api/usage")
+def get_usage(period: str = "month", task_id: str = ""):
+    """Provider usage since the start of the local day/week/month, by
+    provider and model; optionally for one task only. Calls the provider
+    reported no tokens for are also summed apart (`estimated_*`), so their
+    tokens can be estimated from characters without the others'."""
+    since = _period_start(period)
+    unreported = "prompt_tokens=0 AND chars_in>0"
+    sql = (
+        "SELECT provider, model, COUNT(*) AS requests, SUM(prompt_tokens) AS prompt_tokens, "
+        "SUM(completion_tokens) AS completion_tokens, SUM(chars_in) AS chars_in, "
+        "SUM(chars_out) AS chars_out, "
+        f"SUM(CASE WHEN {unreported} THEN 1 ELSE 0 END) AS estimated_requests, "
+        f"SUM(CASE WHEN {unreported} THEN chars_in ELSE 0 END) AS estimated_chars_in, "
+        f"SUM(CASE WHEN {unreported} THEN chars_out ELSE 0 END) AS estimated_chars_out "
+        "FROM usage WHERE ts>=?"
+    )
+    params = [since]
+    if task_id:
+        sql += " AND task_id=?"
+        params.append(task_id)
+    conn = db()
+    rows = conn.execute(sql + " GROUP BY provider, model ORDER BY provider, model", params).fetchall()
+    conn.close()
+    return {"period": period, "since": since, "rows": [dict(r) for r in rows]}
+
+
+# ---------- profiles ----------
+# Named bundles of provider, language pair, glossary and prompt template.
+# Activating one makes its provider the current settings (the API key stays
+# the keychain's or the saved one for that host); its glossary and template
+# apply to every translation until another profile is activated.
+PROFILE_FIELDS = ("name", "base_url", "model", "direction", "glossary", "prompt_template")
+
+
+def _profile(row):
+    p = dict(row)
+    p["glossary"] = json.loads(p["glossary"] or "[]")
+    return p
+
+
+def _profile_values(payload: dict, current=None):
+    p = dict(current or {"base_url": "", "model": "", "direction": "en->zh", "glossary": [], "prompt_template": ""})
+    for k in PROFILE_FIELDS:
+        if k in payload:
+            p[k] = payload[k]
+    p["name"] = str(p.get("name") or "").strip()
+    if not p["name"]:
+        raise HTTPException(400, "name required")
+    if p["base_url"]:
+        p["base_url"] = normalize_base_url(p["base_url"])
+    if p["direction"] not in ("en->zh", "zh->en"):
+        raise HTTPException(400, "direction must be en->zh or zh->en")
+    glossary = p["glossary"]
+    if not isinstance(glossary, list) or not all(isinstance(g, dict) and g.get("source") for g in glossary):
+        raise HTTPException(400, "glossary must be a list of {source, target}")
+    p["glossary"] = [{"source": str(g["source"]), "target": str(g.get("target") or "")} for g in glossary]
+    return p
+
+
+def _save_profile(conn, profile_id: str, p: dict):
+    try:
+        conn.execute(
+            "INSERT INTO profiles(id, name, base_url, model, direction, glossary, prompt_template, updated_at) "
+            "VALUES(?,?,?,?,?,?,?,?) ON CONFLICT(id) DO UPDATE SET name=excluded.name, "
+            "base_url=excluded.base_url, model=excluded.model, direction=excluded.direction, "
+            "glossary=excluded.glossary, prompt_template=excluded.prompt_template, updated_at=excluded.updated_at",
+            (profile_id, p["name"], p["base_url"], p["model"], p["direction"],
+             json.dumps(p["glossary"], ensure_ascii=False), p["prompt_template"], time.time()),
+        )
+    except sqlite3.IntegrityError:
+        raise HTTPException(409, f"a profile named {p['name']} exists")
+    conn.commit()
+    return _profile(conn.execute("SELECT * FROM profiles WHERE id=?", (profile_id,)).fetchone())
+
+
+def _get_profile(conn, profile_id: str):
+    row = conn.execute("SELECT * FROM profiles WHERE id=?", (profile_id,)).fetchone()
+    if not row:
+        raise HTTPException(404, "profile not found")
+    return _profile(row)
+
+
+def active_profile():
+    conn = db()
+    row = conn.execute(
+        "SELECT p.* FROM active_profile a JOIN profiles p ON p.id = a.profile_id WHERE a.id=1"
+    ).fetchone()
+    conn.close()
+    return _profile(row) if row else None
+
+
+@app.get("/api/profiles")
+def list_profiles():
+    conn = db()
+    rows = conn.execute("SELECT * FROM profiles ORDER BY name COLLATE NOCASE").fetchall()
+    conn.close()
+    active = active_profile()
+    return {"active": active["id"] if active else None, "profiles": [_profile(r) for r in rows]}
+
+
+@app.post("/api/profiles")
+def create_profile(payload: dict):
+    conn = db()
+    try:
+        return _save_profile(conn, f"prof_{uuid.uuid4().hex}", _profile_values(payload))
+    finally:
+        conn.close()
+
+
+@app.patch("/api/profiles/{profile_id}")
+def update_profile(profile_id: str, payload: dict):
+    conn = db()
+    try:
+        current = _get_profile(conn, profile_id)
+        return _save_profile(conn, profile_id, _profile_values(payload, current))
+    finally:
+        conn.close()
+
+
+@app.post("/api/profiles/{profile_id}/clone")
+def clone_profile(profile_id: str, payload: dict):
+    conn = db()
+    try:
+        source = _get_profile(conn, profile_id)
+        name = payload.get("name") or f"{source['name']} (copy)"
+        return _save_profile(conn, f"prof_{uuid.uuid4().hex}", _profile_values({"name": name}, source))
+    finally:
+        conn.close()
+
+
+@app.delete("/api/profiles/{profile_id}")
+def delete_profile(profile_id: str):
+    conn = db()
+    try:
+        _get_profile(conn, profile_id)
+        conn.execute("DELETE FROM profiles WHERE id=?", (profile_id,))
+        conn.execute("UPDATE active_profile SET profile_id=NULL WHERE profile_id=?", (profile_id,))
+        conn.commit()
+    finally:
+        conn.close()
+    return {"ok": True}
+
+
+@app.put("/api/profiles/active")
+def activate_profile(payload: dict):
+    """Makes payload["id"] the active profile; null deactivates."""
+    profile_id = payload.get("id")
+    conn = db()
+    try:
+        profile = _get_profile(conn, profile_id) if profile_id else None
+        conn.execute(
+            "INSERT INTO active_profile(id, profile_id) VALUES(1, ?) "
+            "ON CONFLICT(id) DO UPDATE SET profile_id=excluded.profile_id",
+            (profile_id,),
+        )
+        conn.commit()
+    finally:
+        conn.close()
+    if profile and profile["base_url"] and profile["model"]:
+        s = _settings_row() or {}
+        same_host = urlparse(s.get("base_url") or "").hostname == urlparse(profile["base_url"]).hostname
+        upsert_settings(profile["base_url"], s.get("api_key", "") if same_host else "", profile["model"])
+    return {"active": profile}
+
+
+# ---------- translation memory ----------
+# Segment pairs for reuse. The shell imports them from TMX files and writes
+# them back out; languages are kept as the file had them (en-US, zh-CN) and
+# `meta` holds the rest of a unit (its id, props, notes, attributes, inline
+# codes) for the shell to give back on export.
+TMX_DATE = "%Y%m%dT%H%M%SZ"
+TM_PAGE = 1000
+
+
+def _tmx_time(value, default: float) -> float:
+    try:
+        return float(calendar.timegm(time.strptime(str(value or ""), TMX_DATE)))
+    except ValueError:
+        return default
+
+
+def _day(value: str, end: bool) -> float:
+    """Start of `value` (YYYY-MM-DD, UTC), or the end of it."""
+    try:
+        start = calendar.timegm(time.strptime(value, "%Y-%m-%d"))
+    except ValueError:
+        raise HTTPException(400, f"dates must be YYYY-MM-DD, got {value}")
+    return start + 86400 if end else start
+
+
+# Blocks are looked up in the memory before they go to a provider. A unit
+# with the same source text is used as it is, without a call; the best one
+# scoring at least MVP_TM_MIN_MATCH (0-100, set by the shell; 0 turns
+# lookups off) goes into the prompt for the model to adapt. Candidates are
+# the units sharing most character trigrams with the text, scored by
+# similarity; whitespace and case aside the same text scores 99. Blocks
+# edited by hand are stored as confirmed pairs, replacing the block's
+# earlier edit. With encryption at rest unit texts are sealed, and units
+# are found by HMACs of their texts and trigrams instead (see tm_keys);
+# languages, project, creator, dates and meta stay plain.
+TM_MIN_MATCH = int(os.environ.get("MVP_TM_MIN_MATCH") or 75)
+TM_CANDIDATES = 20
+TM_MAX_GRAMS = 400
+# lowest score of each leverage band
+TM_BANDS = ((100, "exact"), (95, "95-99"), (85, "85-94"), (75, "75-84"), (50, "50-74"), (0, "no match"))
+_LANG_SQL = (
+    "(lower(source_lang)=? OR lower(source_lang) LIKE ? || '-%') "
+    "AND (lower(target_lang)=? OR lower(target_lang) LIKE ? || '-%')"
+)
+
+
+def _tm_norm(text: str) -> str:
+    return " ".join(text.lower().split())
+
+
+def _grams(text: str, keyed=None) -> set:
+    """Trigrams of `text`; HMACs of them for sealed units (`keyed`, by
+    default whether new data is sealed)."""
+    t = _tm_norm(text)
+    grams = {t[i : i + 3] for i in range(max(1, len(t) - 2))} if t else set()
+    if _encrypt if keyed is None else keyed:
+        return {mac(g, "tm grams")[:16] for g in grams}
+    return grams
+
+
+def _tm_key(text: str, keyed=None) -> str:
+    if _encrypt if keyed is None else keyed:
+        return mac(text, "tm keys")
+    return hashlib.sha256(text.encode("utf-8")).hexdigest()
+
+
+def _tm_index(conn, unit_id: int, source_text: str, target_text: str, keyed=None):
+    """Indexes a unit by its plain texts."""
+    conn.execute("DELETE FROM tm_grams WHERE unit_id=?", (unit_id,))
+    conn.executemany(
+        "INSERT INTO tm_grams(gram, unit_id) VALUES(?,?)", [(g, unit_id) for g in _grams(source_text, keyed)]
+    )
+    conn.execute(
+        "INSERT OR REPLACE INTO tm_keys(unit_id, source, target) VALUES(?,?,?)",
+        (unit_id, _tm_key(source_text, keyed), _tm_key(target_text, keyed)),
+    )
+
+
+def _tm_find(conn, source_lang: str, target_lang: str, source_text: str, target_text: str):
+    """The id of the unit with these languages and texts, if there is one."""
+    row = conn.execute(
+        "SELECT tm.id FROM tm JOIN tm_keys ON tm_keys.unit_id=tm.id "
+        "WHERE source_lang=? AND target_lang=? AND tm_keys.source=? AND tm_keys.target=?",
+        (source_lang, target_lang, _tm_key(source_text), _tm_key(target_text)),
+    ).fetchone()
+    return row[0] if row else None
+
+
+def _tm_backfill():
+    """Indexes units stored before the index or its keys existed."""
+    conn = db()
+    try:
+        rows = conn.execute(
+            "SELECT id, source_text, target_text FROM tm WHERE id NOT IN (SELECT unit_id FROM tm_keys)"
+        ).fetchall()
+        for r in rows:
+            sealed = is_sealed(r["source_text"])
+            _tm_index(conn, r["id"], unseal(r["source_text"]), unseal(r["target_text"]), sealed)
+        conn.commit()
+    finally:
+        conn.close()
+
+
+def _tm_langs(direction: str) -> tuple:
+    source_lang, _, target_lang = direction.partition("->")
+    return (source_lang, source_lang, target_lang, target_lang)
+
+
+def _tm_score(a: str, b: str) -> int:
+    if a.strip() == b.strip():
+        return 100
+    a, b = _tm_norm(a), _tm_norm(b)
+    if a == b:
+        return 99
+    return min(98, round(difflib.SequenceMatcher(None, a, b, autojunk=False).ratio() * 100))
+
+
+def tm_lookup(conn, text: str, direction: str):
+    """The memory's best match for `text` as {score, source, target}, if it
+    scores at least TM_MIN_MATCH."""
+    if TM_MIN_MATCH <= 0 or not text.strip():
+        return None
+    langs = _tm_langs(direction)
+    exact = conn.execute(
+        f"SELECT tm.source_text, tm.target_text FROM tm_keys JOIN tm ON tm.id=tm_keys.unit_id "
+        f"WHERE tm_keys.source=? AND {_LANG_SQL} ORDER BY changed_at DESC LIMIT 1",
+        (_tm_key(text),) + langs,
+    ).fetchone()
+    if exact:
+        return {"score": 100, "source": unseal(exact[0]), "target": unseal(exact[1])}
+    grams = sorted(_grams(text))[:TM_MAX_GRAMS]
+    rows = conn.execute(
+        f"SELECT tm.source_text, tm.target_text FROM tm_grams JOIN tm ON tm.id=tm_grams.unit_id "
+        f"WHERE gram IN ({','.join('?' * len(grams))}) AND {_LANG_SQL} "
+        "GROUP BY tm.id ORDER BY COUNT(*) DESC, tm.changed_at DESC LIMIT ?",
+        grams + list(langs) + [TM_CANDIDATES],
+    ).fetchall()
+    best = None
+    for source, target in rows:
+        source, target = unseal(source), unseal(target)
+        # a length ratio below the threshold can't score above it
+        if min(len(source), len(text)) * 100 < TM_MIN_MATCH * max(len(source), len(text)):
+            continue
+        score = _tm_score(text, source)
+        if score >= TM_MIN_MATCH and (best is None or score > best["score"]):
+            best = {"score": score, "source": source, "target": target}
+    return best
+
+
+def tm_confirm(conn, task_id: str, block_id: str, source_text: str, target_text: str):
+    """Stores a block edited by hand in the memory."""
+    task = conn.execute("SELECT direction FROM tasks WHERE id=?", (task_id,)).fetchone()
+    if not task or not source_text.strip() or not target_text.strip():
+        return
+    source_lang, _, target_lang = task["direction"].partition("->")
+    meta = json.dumps({"block_id": block_id})
+    for (old,) in conn.execute("SELECT id FROM tm WHERE meta=?", (meta,)).fetchall():
+        conn.execute("DELETE FROM tm WHERE id=?", (old,))
+        conn.execute("DELETE FROM tm_grams WHERE unit_id=?", (old,))
+        conn.execute("DELETE FROM tm_keys WHERE unit_id=?", (old,))
+    now = time.time()
+    exists = _tm_find(conn, source_lang, target_lang, source_text, target_text)
+    if exists:
+        conn.execute("UPDATE tm SET changed_at=? WHERE id=?", (now, exists))
+        return
+    cur = conn.execute(
+        "INSERT INTO tm(source_lang, target_lang, source_text, target_text, created_at, changed_at, meta) "
+        "VALUES(?,?,?,?,?,?,?)",
+        (source_lang, target_lang, seal(source_text), seal(target_text), now, now, meta),
+    )
+    _tm_index(conn, cur.lastrowid, source_text, target_text)
+
+
+def _words(text: str) -> int:
+    return len(re.findall(r"[\u3040-\u30ff\u3400-\u9fff\uac00-\ud7af]|[^\W\u3040-\u30ff\u3400-\u9fff\uac00-\ud7af]+", text))
+
+
+def tm_band(score) -> str:
+    return next(name for low, name in TM_BANDS if (score or 0) >= low)
+
+
+def tm_record_leverage(conn, task_id: str, tally: dict):
+    """Adds {band: {segments, words}} to the task's leverage."""
+    row = conn.execute("SELECT stats FROM tm_leverage WHERE task_id=?", (task_id,)).fetchone()
+    stats = json.loads(row["stats"]) if row else {}
+    for band, counts in tally.items():
+        kept = stats.setdefault(band, {"segments": 0, "words": 0})
+        kept["segments"] += counts["segments"]
+        kept["words"] += counts["words"]
+    conn.execute(
+        "INSERT INTO tm_leverage(task_id, stats) VALUES(?,?) "
+        "ON CONFLICT(task_id) DO UPDATE SET stats=excluded.stats",
+        (task_id, json.dumps(stats)),
+    )
+
+
+_tm_backfill()
+
+
+def _tm_unit(row):
+    u = dict(row)
+    u["source_text"], u["target_text"] = unseal(u["source_text"]), unseal(u["target_text"])
+    u["meta"] = json.loads(u["meta"] or "{}")
+    u["created"] = time.strftime(TMX_DATE, time.gmtime(u.pop("created_at")))
+    u["changed"] = time.strftime(TMX_DATE, time.gmtime(u.pop("changed_at")))
+    return u
+
+
+@app.post("/api/tm/units")
+def add_tm_units(payload: dict):
+    """Adds payload["units"]; a unit with the same languages and texts as one
+    in the memory replaces its project, creator, dates and meta."""
+    units = payload.get("units")
+    if not isinstance(units, list):
+        raise HTTPException(400, "units must be a list")
+    added = updated = 0
+    now = time.time()
+    conn = db()
+    try:
+        for u in units:
+            key = tuple(str((u or {}).get(k) or "").strip() for k in ("source_lang", "target_lang"))
+            texts = tuple(str((u or {}).get(k) or "") for k in ("source_text", "target_text"))
+            if not all(key) or not all(t.strip() for t in texts):
+                raise HTTPException(400, "units need source_lang, target_lang, source_text and target_text")
+            created = _tmx_time(u.get("created"), now)
+            fields = (
+                str(u.get("project") or ""),
+                str(u.get("creator") or ""),
+                created,
+                _tmx_time(u.get("changed"), created),
+                json.dumps(u.get("meta") or {}, ensure_ascii=False),
+            )
+            exists = _tm_find(conn, *key, *texts)
+            if exists:
+                conn.execute(
+                    "UPDATE tm SET project=?, creator=?, created_at=?, changed_at=?, meta=? WHERE id=?",
+                    fields + (exists,),
+                )
+                updated += 1
+            else:
+                cur = conn.execute(
+                    "INSERT INTO tm(source_lang, target_lang, source_text, target_text, project, creator, "
+                    "created_at, changed_at, meta) VALUES(?,?,?,?,?,?,?,?,?)",
+                    key + (seal(texts[0]), seal(texts[1])) + fields,
+                )
+                _tm_index(conn, cur.lastrowid, *texts)
+                added += 1
+        conn.commit()
+    finally:
+        conn.close()
+    return {"added": added, "updated": updated}
+
+
+@app.get("/api/tm/units")
+def list_tm_units(
+    source_lang: str = "",
+    target_lang: str = "",
+    project: str = "",
+    since: str = "",
+    until: str = "",
+    offset: int = 0,
+    limit: int = TM_PAGE,
+):
+    """Units oldest first. Languages match their regional variants (en
+    matches en-US); `since` and `until` are days, inclusive, of the last
+    change."""
+    sql = (
+        "SELECT source_lang, target_lang, source_text, target_text, project, creator, "
+        "created_at, changed_at, meta FROM tm WHERE 1=1"
+    )
+    params = []
+    for column, lang in (("source_lang", source_lang), ("target_lang", target_lang)):
+        if lang:
+            sql += f" AND (lower({column})=lower(?) OR lower({column}) LIKE lower(?) || '-%')"
+            params += [lang, lang]
+    if project:
+        sql += " AND project=?"
+        params.append(project)
+    if since:
+        sql += " AND changed_at >= ?"
+        params.append(_day(since, False))
+    if until:
+        sql += " AND changed_at < ?"
+        params.append(_day(until, True))
+    sql += " ORDER BY id LIMIT ? OFFSET ?"
+    params += [max(1, min(limit, TM_PAGE)), max(0, offset)]
+    conn = db()
+    rows = conn.execute(sql, params).fetchall()
+    conn.close()
+    return [_tm_unit(r) for r in rows]
+
+
+def _upload_paths(upload_id: str):
+    if not upload_id.startswith("up_") or not upload_id[3:].isalnum():
+        raise HTTPException(400, "invalid upload id")
+    base = os.path.join(uploads_dir(), upload_id)
+    return base + ".part", base + ".json"
+
+
+def _upload_segments(upload_id: str):
+    return _upload_paths(upload_id)[0][: -len(".part")] + ".segments.jsonl"
+
+
+@app.post("/api/uploads")
+def start_upload(payload: dict):
+    """Starts a chunked upload, for documents too large for one request.
+    Chunks are appended with `PUT /api/uploads/{id}?offset=`; the finished
+    upload is passed to `POST /api/tasks` as `upload_id`."""
+    filename = os.path.basename(str(payload.get("filename") or ""))
+    size = payload.get("size")
+    if not filename or not isinstance(size, int) or size < 0:
+        raise HTTPException(400, "filename and size required")
+    upload_id = f"up_{uuid.uuid4().hex}"
+    part, meta = _upload_paths(upload_id)
+    open(part, "wb").close()
+    with open(meta, "w", encoding="utf-8") as f:
+        json.dump({"filename": filename, "size": size}, f)
+    return {"upload_id": upload_id}
+
+
+@app.put("/api/uploads/{upload_id}")
+async def put_upload_chunk(upload_id: str, offset: int, request: Request):
+    part, meta = _upload_paths(upload_id)
+    if not os.path.exists(meta):
+        raise HTTPException(404, "upload not found")
+    received = os.path.getsize(part)
+    if offset != received:
+        # lets the client resume after a lost response
+        raise HTTPException(409, f"expected offset {received}")
+    chunk = await request.body()
+    with open(meta, "r", encoding="utf-8") as f:
+        size = json.load(f)["size"]
+    if received + len(chunk) > size:
+        raise HTTPException(400, "chunk exceeds the declared size")
+    with open(part, "ab") as f:
+        f.write(chunk)
+    return {"received": received + len(chunk), "size": size}
+
+
+@app.post("/api/uploads/{upload_id}/segments")
+async def append_upload_segments(upload_id: str, request: Request):
+    """Appends a batch of segments ([{locator, text}]) to an upload, for
+    documents the shell segments while it reads them; `POST /api/tasks`
+    then takes these instead of a `segments` field."""
+    part, meta = _upload_paths(upload_id)
+    if not os.path.exists(meta):
+        raise HTTPException(404, "upload not found")
+    try:
+        batch = [{"locator": str(seg["locator"]), "text": str(seg["text"])} for seg in json.loads(await request.body())]
+    except (ValueError, TypeError, KeyError):
+        raise HTTPException(400, "segments must be a list of {locator, text}")
+    with open(_upload_segments(upload_id), "a", encoding="utf-8") as f:
+        for seg in batch:
+            f.write(json.dumps(seg, ensure_ascii=False) + "\n")
+    return {"received": len(batch)}
+
+
+@app.delete("/api/uploads/{upload_id}")
+def delete_upload(upload_id: str):
+    for path in (*_upload_paths(upload_id), _upload_segments(upload_id)):
+        if os.path.exists(path):
+            os.remove(path)
+    return {"ok": True}
+
+
+# .docx is segmented here; the desktop shell segments the rest and sends them.
+SUPPORTED_EXTENSIONS = (
+    ".docx", ".pdf", ".pptx", ".xlsx", ".odt", ".ods", ".odp", ".epub", ".srt", ".vtt", ".md", ".markdown", ".html", ".htm",
+    ".tex", ".rtf", ".po", ".pot", ".xlf", ".xliff", ".json", ".arb", ".yml", ".yaml", ".strings", ".txt",
+    ".csv", ".tsv", ".png", ".jpg", ".jpeg",
+)
+
+
+def _streamed_blocks(path: str, ext: str):
+    """The blocks of segments streamed to an upload, read a line at a time."""
+    order_no = 0
+    with open(path, "r", encoding="utf-8") as f:
+        for line in f:
+            seg = json.loads(line)
+            text = seg["text"].strip()
+            if text:
+                yield {"locator": seg["locator"], "kind": ext[1:], "source_text": text, "order_no": order_no}
+                order_no += 1
+
+
 @app.post("/api/tasks")
-async def create_task(file: UploadFile = File(...), direction: str = Form(...)):
+async def create_task(
+    file: UploadFile = File(None),
+    direction: str = Form(...),
+    upload_id: str = Form(None),
+    segments: str = Form(None),
+):
+    # Documents the desktop shell segments itself (always PDFs and
+    # PowerPoint decks, and Word files it reads from disk) arrive with
+    # their segments; the shell also
+    # writes their translated copy, so here they are only a list of blocks.
     if direction not in ("zh->en", "en->zh"):
         raise HTTPException(400, "direction must be zh->en or en->zh")
-    if not file.filename.lower().endswith(".docx"):
-        raise HTTPException(400, "only .docx supported in MVP")
+    if file is None and not upload_id:
+        raise HTTPException(400, "file or upload_id required")
+    if upload_id:
+        part, meta = _upload_paths(upload_id)
+        if not os.path.exists(meta):
+            raise HTTPException(404, "upload not found")
+        with open(meta, "r", encoding="utf-8") as f:
+            info = json.load(f)
+        if os.path.getsize(part) != info["size"]:
+            raise HTTPException(400, "upload is incomplete")
+        filename = info["filename"]
+    else:
+        filename = file.filename
+    ext = os.path.splitext(filename)[1].lower()
+    if ext not in SUPPORTED_EXTENSIONS:
+        raise HTTPException(
+            400, "only .docx, .pdf, .pptx, .xlsx, .odt, .ods, .odp, .epub, .srt, .vtt, .md, .html, .tex, .rtf, .po, .xlf, .json, .arb, "
+            ".yaml, .strings, .txt, .csv, .png and .jpg supported in MVP"
+        )
+    # segments streamed to the upload, for documents too large to send them at once
+    streamed = bool(upload_id) and os.path.exists(_upload_segments(upload_id))
+    if ext != ".docx" and not segments and not streamed:
+        raise HTTPException(400, f"{ext} documents need their segments")
+    if segments:
+        try:
+            shell_blocks = [
+                {
+                    "locator": str(seg["locator"]),
+                    "kind": ext[1:],
+                    "source_text": str(seg["text"]).strip(),
+                }
+                for seg in json.loads(segments)
+            ]
+        except (ValueError, TypeError, KeyError):
+            raise HTTPException(400, "segments must be a list of {locator, text}")
+        shell_blocks = [b for b in shell_blocks if b["source_text"]]
+        for i, b in enumerate(shell_blocks):
+            b["order_no"] = i
 
     s = get_settings()
     if not s or not s["base_url"] or not s["api_key"] or not s["model"]:
@@ -277,24 +1453,38 @@ async def create_task(file: UploadFile = File(...), direction: str = Form(...)):
 
     task_id = f"task_{uuid.uuid4().hex}"
     wd = work_dir(task_id)
-    src_path = os.path.join(wd, file.filename)
+    src_path = os.path.join(wd, filename)
 
-    with open(src_path, "wb") as f:
-        f.write(await file.read())
-
-    work_path = os.path.join(wd, "work.docx")
-    shutil.copy2(src_path, work_path)
-
-    blocks = extract_blocks(work_path)
+    if upload_id:
+        shutil.move(part, src_path)
+        os.remove(meta)
+    else:
+        with open(src_path, "wb") as f:
+            f.write(await file.read())
+
+    if streamed:
+        work_path = src_path
+        streamed_path = _upload_segments(upload_id)
+        blocks = _streamed_blocks(streamed_path, ext)
+    elif segments:
+        # no working copy: the source is what the shell rebuilds from
+        work_path = src_path
+        blocks = shell_blocks
+    else:
+        work_path = os.path.join(wd, "work.docx")
+        shutil.copy2(src_path, work_path)
+        blocks = extract_blocks(work_path)
 
     conn = db()
     conn.execute(
         "INSERT INTO tasks(id, filename, source_path, work_path, direction, status, progress, error) "
         "VALUES(?,?,?,?,?,?,?,?)",
-        (task_id, file.filename, src_path, work_path, direction, "created", 0.0, None),
+        (task_id, filename, src_path, work_path, direction, "created", 0.0, None),
     )
 
+    count = 0
     for b in blocks:
+        count += 1
         conn.execute(
             "INSERT INTO blocks(id, task_id, locator, kind, order_no, source_text, translated_text, status) "
             "VALUES(?,?,?,?,?,?,?,?)",
@@ -304,97 +1494,556 @@ async def create_task(file: UploadFile = File(...), direction: str = Form(...)):
                 b["locator"],
                 b["kind"],
                 b["order_no"],
-                b["source_text"],
+                seal(b["source_text"]),
                 None,
                 "pending",
             ),
         )
     conn.commit()
     conn.close()
+    if streamed:
+        os.remove(streamed_path)
 
-    return {"task_id": task_id, "blocks": len(blocks)}
+    return {"task_id": task_id, "blocks": count}
 
 
 @app.get("/api/tasks/{task_id}")
 def get_task(task_id: str):
     conn = db()
     row = conn.execute(
-        "SELECT id, status, progress, error, direction FROM tasks WHERE id=?",
+        "SELECT id, filename, status, progress, error, direction FROM tasks WHERE id=?",
         (task_id,),
     ).fetchone()
+    leverage = conn.execute("SELECT stats FROM tm_leverage WHERE task_id=?", (task_id,)).fetchone()
     conn.close()
     if not row:
         raise HTTPException(404, "task not found")
-    return dict(row)
+    # what the translation memory gave, by match band
+    return dict(row, leverage=json.loads(leverage["stats"]) if leverage else {})
 
 
-def _translate_task(task_id: str):
+# Name resolution from the desktop shell, {"doh_url", "hosts": {host: [ip]}}:
+# fixed addresses and/or DNS-over-HTTPS (JSON API) for provider hostnames,
+# for networks whose DNS blocks or poisons them. Applied by wrapping
+# socket.getaddrinfo, so httpx (and the OpenAI client) pick it up; TLS still
+# checks the original hostname.
+def _install_dns():
+    try:
+        cfg = json.loads(os.environ.get("MVP_DNS") or "{}")
+    except ValueError:
+        return
+    hosts = {k.lower(): v for k, v in (cfg.get("hosts") or {}).items()}
+    doh_url = cfg.get("doh_url") or ""
+    if not hosts and not doh_url:
+        return
+    doh_host = (urlparse(doh_url).hostname or "").lower()
+    system = socket.getaddrinfo
+    cache = {}  # host -> (ips, expires)
+    lock = threading.Lock()
+
+    def doh(host):
+        with lock:
+            hit = cache.get(host)
+        if hit and hit[1] > time.monotonic():
+            return hit[0]
+        ips, ttl = [], 30
+        for kind, code in (("A", 1), ("AAAA", 28)):
+            url = f"{doh_url}?{urllib.parse.urlencode({'name': host, 'type': kind})}"
+            req = urllib.request.Request(url, headers={"accept": "application/dns-json"})
+            with urllib.request.urlopen(req, timeout=5) as resp:
+                answers = [a for a in json.load(resp).get("Answer") or [] if a.get("type") == code]
+            ips = [a["data"] for a in answers]
+            if ips:
+                ttl = max(30, min(a.get("TTL", 0) for a in answers))
+                break
+        with lock:
+            cache[host] = (ips, time.monotonic() + ttl)
+        return ips
+
+    def lookup(host):
+        if not isinstance(host, str):
+            return None
+        host = host.rstrip(".").lower()
+        if host in hosts:
+            return hosts[host]
+        if not doh_url or host in (doh_host, "localhost"):
+            return None
+        try:
+            ipaddress.ip_address(host)
+            return None
+        except ValueError:
+            pass
+        try:
+            return doh(host)
+        except Exception as e:  # fall back to the system resolver
+            print(f"DoH lookup of {host} failed: {e}", file=sys.stderr)
+            return None
+
+    def getaddrinfo(host, port, family=0, type=0, proto=0, flags=0):
+        ips = lookup(host)
+        if ips:
+            out = []
+            for ip in ips:
+                try:
+                    out.extend(system(ip, port, family, type, proto, flags | socket.AI_NUMERICHOST))
+                except socket.gaierror:
+                    pass  # e.g. an IPv6 address with family=AF_INET
+            if out:
+                return out
+        return system(host, port, family, type, proto, flags)
+
+    socket.getaddrinfo = getaddrinfo
+
+
+_install_dns()
+
+
+# Per-provider limits from the desktop shell, {"host": {"requests_per_minute",
+# "burst"}}, already divided between its worker processes. The buckets are
+# shared by all translation threads of this process.
+def _load_rate_limits():
+    try:
+        limits = json.loads(os.environ.get("MVP_RATE_LIMITS") or "{}")
+    except ValueError:
+        return {}
+    return limits if isinstance(limits, dict) else {}
+
+
+_rate_limits = _load_rate_limits()
+_buckets = {}
+_buckets_lock = threading.Lock()
+
+
+def _throttle(base_url: str, cancelled: threading.Event = None):
+    """Blocks until the provider behind base_url has a request token, or
+    raises TaskCancelled once `cancelled` is set, so a cancelled task does
+    not take a token it would not use."""
+    host = (urlparse(base_url).hostname or "").lower()
+    limit = _rate_limits.get(host)
+    if not limit or not limit.get("requests_per_minute"):
+        return
+    rate = limit["requests_per_minute"] / 60.0
+    capacity = float(limit.get("burst") or 1)
+    while True:
+        with _buckets_lock:
+            now = time.monotonic()
+            tokens, updated = _buckets.get(host, (capacity, now))
+            tokens = min(capacity, tokens + (now - updated) * rate)
+            if tokens >= 1:
+                _buckets[host] = (tokens - 1, now)
+                return
+            _buckets[host] = (tokens, now)
+            wait = (1 - tokens) / rate
+        _pause(wait, cancelled)
+
+
+class TaskCancelled(Exception):
+    pass
+
+
+def _pause(secs: float, cancelled: threading.Event = None):
+    if cancelled is None:
+        time.sleep(secs)
+    elif cancelled.wait(secs):
+        raise TaskCancelled()
+
+
+# Retry / circuit breaker settings from the desktop shell.
+def _load_retry_config():
+    cfg = {
+        "max_retries": 5,
+        "base_delay_ms": 1000,
+        "max_delay_ms": 60000,
+        "breaker_threshold": 5,
+        "breaker_cooldown_secs": 60,
+    }
+    try:
+        cfg.update(json.loads(os.environ.get("MVP_PROVIDER_RETRY") or "{}"))
+    except (ValueError, TypeError):
+        pass
+    return cfg
+
+
+_retry = _load_retry_config()
+_breakers = {}  # host -> {"state", "failures", "opened_at"}
+_breakers_lock = threading.Lock()
+
+
+class ProviderUnavailable(Exception):
+    pass
+
+
+def _retryable(e: Exception) -> bool:
+    if isinstance(e, (openai.APIConnectionError, openai.APITimeoutError)):
+        return True
+    status = getattr(e, "status_code", None)
+    return status in (408, 409, 429) or (status is not None and status >= 500)
+
+
+def _retry_after(e: Exception):
+    """Seconds from the provider's Retry-After header, if any."""
+    response = getattr(e, "response", None)
+    value = response.headers.get("retry-after") if response is not None else None
+    if not value:
+        return None
+    try:
+        return max(0.0, float(value))
+    except ValueError:
+        pass
+    try:
+        return max(0.0, email.utils.parsedate_to_datetime(value).timestamp() - time.time())
+    except (TypeError, ValueError):
+        return None
+
+
+def _breaker_admit(host: str):
+    """Fails fast while the breaker is open; after the cooldown one trial
+    call is let through (half-open)."""
+    with _breakers_lock:
+        b = _breakers.setdefault(host, {"state": "closed", "failures": 0, "opened_at": 0.0})
+        if b["state"] == "closed":
+            return
+        retry_at = b["opened_at"] + _retry["breaker_cooldown_secs"]
+        if b["state"] == "open" and time.time() >= retry_at:
+            b["state"] = "half-open"
+            return
+        raise ProviderUnavailable(
+            f"{host} is unavailable after {b['failures']} failed calls; "
+            f"retrying after {time.strftime('%H:%M:%S', time.localtime(retry_at))}"
+        )
+
+
+def _breaker_record(host: str, ok: bool):
+    with _breakers_lock:
+        b = _breakers.setdefault(host, {"state": "closed", "failures": 0, "opened_at": 0.0})
+        if ok:
+            b.update(state="closed", failures=0)
+            return
+        b["failures"] += 1
+        if b["state"] == "half-open" or b["failures"] >= _retry["breaker_threshold"]:
+            b.update(state="open", opened_at=time.time())
+
+
+def provider_statuses():
+    with _breakers_lock:
+        return {
+            host: {
+                "type": "provider",
+                "provider": host,
+                "state": b["state"],
+                "failures": b["failures"],
+                "retry_at": b["opened_at"] + _retry["breaker_cooldown_secs"]
+                if b["state"] == "open"
+                else None,
+            }
+            for host, b in _breakers.items()
+        }
+
+
+def call_provider(s: dict, fn, cancelled: threading.Event = None):
+    """Runs one provider call with jittered exponential backoff (honoring
+    Retry-After) and a per-provider circuit breaker. Once `cancelled` is
+    set, a failed call or a backoff raises TaskCancelled instead."""
+    host = (urlparse(s["base_url"]).hostname or s["base_url"]).lower()
+    attempt = 0
+    while True:
+        _breaker_admit(host)
+        try:
+            result = fn()
+        except Exception as e:
+            if cancelled is not None and cancelled.is_set():
+                # most likely the aborted request, which says nothing of the provider
+                raise TaskCancelled() from e
+            if not _retryable(e):
+                raise
+            _breaker_record(host, False)
+            if attempt >= _retry["max_retries"]:
+                raise
+            delay = _retry_after(e)
+            if delay is None:
+                delay = min(_retry["max_delay_ms"], _retry["base_delay_ms"] * 2**attempt) / 1000.0
+                delay *= random.uniform(0.5, 1.0)
+            _pause(min(delay, _retry["max_delay_ms"] / 1000.0), cancelled)
+            attempt += 1
+            continue
+        _breaker_record(host, True)
+        return result
+
+
+# Segments a task translates at once when its run does not say, from the
+# desktop shell's settings.
+MAX_CONCURRENCY = 16
+
+
+def _load_concurrency():
+    try:
+        n = int(os.environ.get("MVP_SEGMENT_CONCURRENCY") or 1)
+    except ValueError:
+        return 1
+    return max(1, min(MAX_CONCURRENCY, n))
+
+
+_segment_concurrency = _load_concurrency()
+
+# task id -> {"cancelled": Event, "streams": the provider responses being
+# read and the run's HTTP client} of the run queued or translating here;
+# cancelling sets the event and closes them, which aborts the requests,
+# those still waiting for a response too. Each run gets its own
+# entry, so one cancelled earlier does not stop the next.
+_runs = {}
+_runs_lock = threading.Lock()
+
+
+def _new_run(task_id: str) -> dict:
+    run = {"cancelled": threading.Event(), "streams": set()}
+    with _runs_lock:
+        _runs[task_id] = run
+    return run
+
+
+def _abort(run: dict):
+    with _runs_lock:
+        run["cancelled"].set()
+        streams = list(run["streams"])
+    for stream in streams:
+        try:
+            stream.close()
+        except Exception:
+            pass  # its reader sees the stream end either way
+
+
+def _translate_task(run: dict, task_id: str, profile_id: str = None, concurrency: int = None):
+    """Translates the task's blocks, `concurrency` (by default
+    MVP_SEGMENT_CONCURRENCY) at once within the provider's rate limit, and
+    saves them in order, until all are or the task is cancelled: it then
+    stops as `cancelled` with the blocks saved so far kept, and a new run
+    goes on from there. `run` is the task's entry in _runs."""
+    cancelled = run["cancelled"]
+    workers = max(1, min(MAX_CONCURRENCY, concurrency or _segment_concurrency))
     conn = db()
     try:
         task = conn.execute("SELECT * FROM tasks WHERE id=?", (task_id,)).fetchone()
         if not task:
             return
+        if cancelled.is_set() or task["status"] == "cancelling":
+            # cancelled while it was queued
+            raise TaskCancelled()
 
         conn.execute("UPDATE tasks SET status=?, error=? WHERE id=?", ("running", None, task_id))
         conn.commit()
+        _touch_task(task_id)
 
         s = get_settings()
-        client = OpenAI(api_key=s["api_key"], base_url=s["base_url"])
+        if profile_id:
+            # the task's own profile, its provider too, as if activated
+            profile = _get_profile(conn, profile_id)
+            if profile["base_url"] and profile["model"]:
+                same_host = urlparse(s["base_url"]).hostname == urlparse(profile["base_url"]).hostname
+                s = dict(s, base_url=profile["base_url"], model=profile["model"])
+                s["api_key"] = provider_key(profile["base_url"], s["api_key"] if same_host else "")
+        else:
+            profile = active_profile()
+        http = openai.DefaultHttpxClient()
+        with _runs_lock:
+            run["streams"].add(http)
+        if cancelled.is_set():
+            raise TaskCancelled()
+        client = OpenAI(api_key=s["api_key"], base_url=s["base_url"], max_retries=0, http_client=http)
         model = s["model"]
 
-        blocks = conn.execute(
-            "SELECT * FROM blocks WHERE task_id=? ORDER BY order_no ASC", (task_id,)
-        ).fetchall()
+        blocks = [
+            open_block(b)
+            for b in conn.execute(
+                "SELECT * FROM blocks WHERE task_id=? ORDER BY order_no ASC", (task_id,)
+            ).fetchall()
+        ]
+
+        def ask(b, msgs):
+            # on the run's pool: only the provider is called here, the db
+            # connection stays with the run's thread
+            _throttle(s["base_url"], cancelled)
+
+            def streamed():
+                # a stream that breaks midway is retried from the start
+                parts, usage = [], None
+                stream = client.chat.completions.create(
+                    model=model,
+                    messages=msgs,
+                    temperature=0.2,
+                    stream=True,
+                    # the tokens used come on a last chunk without choices
+                    stream_options={"include_usage": True},
+                )
+                with _runs_lock:
+                    run["streams"].add(stream)
+                if cancelled.is_set():
+                    stream.close()
+                try:
+                    for chunk in stream:
+                        # Some providers report usage on the last chunk.
+                        usage = getattr(chunk, "usage", None) or usage
+                        delta = chunk.choices[0].delta.content if chunk.choices else None
+                        if delta:
+                            parts.append(delta)
+                            _set_partial(task_id, b["id"], "".join(parts))
+                finally:
+                    with _runs_lock:
+                        run["streams"].discard(stream)
+                    stream.close()
+                if cancelled.is_set():
+                    # the stream ended early because it was closed
+                    raise TaskCancelled()
+                return "".join(parts).strip(), usage
+
+            try:
+                out, usage = call_provider(s, streamed, cancelled)
+            finally:
+                _set_partial(task_id, b["id"])
+            record_usage(s, task_id, msgs, out, usage)
+            # cached at once, so one translated after a block that failed
+            # or was cancelled is not asked for again by the next run
+            cache_put(s, msgs, out)
+            return out
 
         total = len(blocks)
         done = 0
-
-        for b in blocks:
-            if b["status"] == "edited":
-                done += 1
-            elif b["translated_text"] and b["status"] == "translated":
-                done += 1
-            else:
-                msgs = build_messages(b["source_text"], task["direction"])
-                r = client.chat.completions.create(
-                    model=model, messages=msgs, temperature=0.2
-                )
-                out = (r.choices[0].message.content or "").strip()
-                conn.execute(
-                    "UPDATE blocks SET translated_text=?, status=? WHERE id=?",
-                    (out, "translated", b["id"]),
-                )
+        # (block, tm match, translation or its future; None when the block
+        # was done before), saved from the front as translations come in
+        window = deque()
+
+        def save_ready(wait: bool = False):
+            nonlocal done
+            while window:
+                b, match, out = window[0]
+                if isinstance(out, Future):
+                    if not (wait or out.done()):
+                        return
+                    out = out.result()
+                    wait = False
+                window.popleft()
+                if out is not None:
+                    conn.execute(
+                        "UPDATE blocks SET translated_text=?, status=? WHERE id=?",
+                        (seal(out), "translated", b["id"]),
+                    )
+                    # committed with the block, so a run that fails counts what it did
+                    tm_record_leverage(
+                        conn, task_id, {tm_band(match and match["score"]): {"segments": 1, "words": _words(b["source_text"])}}
+                    )
                 done += 1
-
-            progress = 1.0 if total == 0 else done / total
-            conn.execute("UPDATE tasks SET progress=? WHERE id=?", (progress, task_id))
-            conn.commit()
+                progress = 1.0 if total == 0 else done / total
+                conn.execute("UPDATE tasks SET progress=? WHERE id=?", (progress, task_id))
+                conn.commit()
+                _touch_task(task_id)
+
+        pool = ThreadPoolExecutor(max_workers=workers, thread_name_prefix="translate")
+        try:
+            for b in blocks:
+                # cancelled here, or at another instance (see cancel_task)
+                status = conn.execute("SELECT status FROM tasks WHERE id=?", (task_id,)).fetchone()
+                if cancelled.is_set() or (status and status["status"] == "cancelling"):
+                    raise TaskCancelled()
+                if b["status"] == "edited" or (b["translated_text"] and b["status"] == "translated"):
+                    window.append((b, None, None))
+                else:
+                    match = tm_lookup(conn, b["source_text"], task["direction"])
+                    msgs = build_messages(b["source_text"], task["direction"], profile, match)
+                    out = match["target"] if match and match["score"] == 100 else cache_get(s, msgs)
+                    if out is None:
+                        out = pool.submit(ask, b, msgs)
+                    window.append((b, match, out))
+                # at most `workers` requests at a time; the oldest is waited for
+                while sum(isinstance(w[2], Future) for w in window) >= workers:
+                    save_ready(wait=True)
+                save_ready()
+            while window:
+                save_ready(wait=True)
+        except BaseException:
+            # the blocks still translating stop with the run
+            _abort(run)
+            raise
+        finally:
+            pool.shutdown(wait=True, cancel_futures=True)
 
         conn.execute(
             "UPDATE tasks SET status=?, progress=? WHERE id=?", ("finished", 1.0, task_id)
         )
         conn.commit()
 
+    except TaskCancelled:
+        conn.rollback()
+        conn.execute("UPDATE tasks SET status=? WHERE id=?", ("cancelled", task_id))
+        conn.commit()
     except Exception as e:
         conn.execute("UPDATE tasks SET status=?, error=? WHERE id=?", ("error", str(e), task_id))
         conn.commit()
     finally:
+        with _runs_lock:
+            if _runs.get(task_id) is run:
+                _runs.pop(task_id)
+            clients = [h for h in run["streams"] if isinstance(h, httpx.Client)]
+        for http in clients:
+            http.close()
+        with _task_progress_lock:
+            _task_progress.pop(task_id, None)
         conn.close()
 
 
 @app.post("/api/tasks/{task_id}/run_translate")
-def run_translate(task_id: str):
+def run_translate(task_id: str, payload: dict = None):
+    """Translates the task with the active profile, or with
+    payload["profile_id"] when given, payload["concurrency"] blocks at once
+    when given."""
+    profile_id = (payload or {}).get("profile_id")
+    concurrency = (payload or {}).get("concurrency")
+    if concurrency is not None and (not isinstance(concurrency, int) or not 1 <= concurrency <= MAX_CONCURRENCY):
+        raise HTTPException(400, f"concurrency must be 1 to {MAX_CONCURRENCY}")
     conn = db()
-    row = conn.execute("SELECT status FROM tasks WHERE id=?", (task_id,)).fetchone()
-    conn.close()
-    if not row:
-        raise HTTPException(404, "task not found")
-    if row["status"] == "running":
-        return {"ok": True, "status": "running"}
-    executor.submit(_translate_task, task_id)
+    try:
+        row = conn.execute("SELECT status FROM tasks WHERE id=?", (task_id,)).fetchone()
+        if not row:
+            raise HTTPException(404, "task not found")
+        if profile_id:
+            _get_profile(conn, profile_id)
+        # queued at once, so a second request cannot start another run while
+        # this one waits for a worker
+        queued = conn.execute(
+            "UPDATE tasks SET status=? WHERE id=? AND status NOT IN ('queued', 'running', 'cancelling')",
+            ("queued", task_id),
+        ).rowcount
+        conn.commit()
+    finally:
+        conn.close()
+    if not queued:
+        raise HTTPException(409, f"task is already {row['status']}")
+    executor.submit(_translate_task, _new_run(task_id), task_id, profile_id, concurrency)
     return {"ok": True}
 
 
+@app.post("/api/tasks/{task_id}/cancel")
+def cancel_task(task_id: str):
+    """Stops the task's run: the requests in flight are aborted and the blocks
+    translated so far are kept, so run_translate goes on from them. A task
+    queued or run by another instance is marked `cancelling` and stops
+    before its next block."""
+    conn = db()
+    try:
+        row = conn.execute("SELECT status FROM tasks WHERE id=?", (task_id,)).fetchone()
+        if not row:
+            raise HTTPException(404, "task not found")
+        with _runs_lock:
+            run = _runs.get(task_id)
+        if run:
+            _abort(run)
+        active = row["status"] in ("queued", "running")
+        if run is None and active:
+            conn.execute("UPDATE tasks SET status=? WHERE id=?", ("cancelling", task_id))
+            conn.commit()
+        return {"ok": True, "running": run is not None or active}
+    finally:
+        conn.close()
+
+
 @app.get("/api/tasks/{task_id}/blocks")
 def list_blocks(task_id: str, offset: int = 0, limit: int = 2000):
     conn = db()
@@ -404,7 +2053,7 @@ def list_blocks(task_id: str, offset: int = 0, limit: int = 2000):
         (task_id, limit, offset),
     ).fetchall()
     conn.close()
-    return [dict(r) for r in rows]
+    return [open_block(r) for r in rows]
 
 
 @app.patch("/api/tasks/{task_id}/blocks/{block_id}")
@@ -415,7 +2064,7 @@ def patch_block(task_id: str, block_id: str, payload: dict):
 
     conn = db()
     row = conn.execute(
-        "SELECT id FROM blocks WHERE id=? AND task_id=?", (block_id, task_id)
+        "SELECT id, source_text FROM blocks WHERE id=? AND task_id=?", (block_id, task_id)
     ).fetchone()
     if not row:
         conn.close()
@@ -423,29 +2072,203 @@ def patch_block(task_id: str, block_id: str, payload: dict):
 
     conn.execute(
         "UPDATE blocks SET translated_text=?, status=? WHERE id=?",
-        (translated_text, "edited", block_id),
+        (seal(translated_text), "edited", block_id),
     )
+    tm_confirm(conn, task_id, block_id, unseal(row["source_text"]), translated_text)
     conn.commit()
     conn.close()
     return {"ok": True}
 
 
+def _sse(event: str, data) -> str:
+    return f"event: {event}\ndata: {json.dumps(data, ensure_ascii=False)}\n\n"
+
+
+@app.post("/api/tasks/{task_id}/blocks/{block_id}/translate_stream")
+def translate_block_stream(task_id: str, block_id: str):
+    """Re-translates one block, streaming `token` events as the model
+    produces them and a final `done` (or `error`). The result is saved only
+    when the stream completes, so a cancelled run leaves the block as is."""
+    conn = db()
+    row = conn.execute(
+        "SELECT b.source_text, t.direction FROM blocks b JOIN tasks t ON t.id = b.task_id "
+        "WHERE b.id=? AND b.task_id=?",
+        (block_id, task_id),
+    ).fetchone()
+    conn.close()
+    if not row:
+        raise HTTPException(404, "block not found")
+    s = get_settings()
+    if not s or not s.get("api_key") or not s.get("model"):
+        raise HTTPException(400, "settings are incomplete")
+
+    # Always asks the provider (the user wants a fresh translation), but the
+    # result replaces the cached one.
+    def events():
+        client = OpenAI(api_key=s["api_key"], base_url=s["base_url"], max_retries=0)
+        msgs = build_messages(unseal(row["source_text"]), row["direction"], active_profile())
+        parts = []
+        stream = None
+        try:
+            # only opening the stream is retried; a stream that breaks
+            # midway is reported to the user
+            stream = call_provider(
+                s,
+                lambda: client.chat.completions.create(
+                    model=s["model"],
+                    messages=msgs,
+                    temperature=0.2,
+                    stream=True,
+                    stream_options={"include_usage": True},
+                ),
+            )
+            usage = None
+            for chunk in stream:
+                # Some providers report usage on the last chunk.
+                usage = getattr(chunk, "usage", None) or usage
+                delta = chunk.choices[0].delta.content if chunk.choices else None
+                if delta:
+                    parts.append(delta)
+                    yield _sse("token", delta)
+            out = "".join(parts).strip()
+            record_usage(s, task_id, msgs, out, usage)
+            cache_put(s, msgs, out)
+            c = db()
+            c.execute(
+                "UPDATE blocks SET translated_text=?, status=? WHERE id=?",
+                (seal(out), "translated", block_id),
+            )
+            c.commit()
+            c.close()
+            yield _sse("done", {"translated_text": out})
+        except Exception as e:
+            yield _sse("error", str(e))
+        finally:
+            if stream is not None:
+                stream.close()
+
+    return StreamingResponse(
+        events(), media_type="text/event-stream", headers={"Cache-Control": "no-cache"}
+    )
+
+
+def _task_snapshot(task_id: str):
+    conn = db()
+    try:
+        task = conn.execute(
+            "SELECT id, status, progress, error FROM tasks WHERE id=?", (task_id,)
+        ).fetchone()
+        if not task:
+            return None
+        counts = conn.execute(
+            "SELECT COUNT(*) AS total, "
+            "SUM(CASE WHEN status IN ('translated', 'edited') THEN 1 ELSE 0 END) AS done "
+            "FROM blocks WHERE task_id=?",
+            (task_id,),
+        ).fetchone()
+        return {
+            "type": "task",
+            "task_id": task["id"],
+            "status": task["status"],
+            "progress": task["progress"],
+            "error": task["error"],
+            "blocks_total": counts["total"] or 0,
+            "blocks_done": counts["done"] or 0,
+        }
+    finally:
+        conn.close()
+
+
+@app.websocket("/api/ws/jobs")
+async def job_events(ws: WebSocket):
+    """Pushes a `task` message whenever a subscribed task changes, a
+    `segment-partial` message (`task_id`, `block_id`, `text`) as the
+    translation of one of its blocks streams in, and a `provider` message
+    when a provider's circuit breaker changes state.
+
+    Client messages: `{"type": "subscribe"|"unsubscribe", "task_id"}` and
+    `{"type": "ping"}`. Changes are read from the shared db, so tasks run by
+    other backend instances are reported too."""
+    if not _token_ok(ws.headers.get(AUTH_HEADER)):
+        await ws.close(code=1008)
+        return
+    await ws.accept()
+    subscribed = {}  # task id -> last snapshot sent
+    providers = {}  # host -> last status sent
+    partials = {}  # (task id, block id) -> last partial text sent
+
+    async def push_changes():
+        rounds = 0
+        while True:
+            # partial translations every round, the rest every other one
+            for task_id in list(subscribed):
+                for block_id, text in _task_partials(task_id).items():
+                    if partials.get((task_id, block_id)) != text:
+                        partials[(task_id, block_id)] = text
+                        await ws.send_json(
+                            {"type": "segment-partial", "task_id": task_id, "block_id": block_id, "text": text}
+                        )
+            rounds += 1
+            if rounds % 2:
+                await asyncio.sleep(0.25)
+                continue
+            for host, status in provider_statuses().items():
+                if status != providers.get(host):
+                    providers[host] = status
+                    await ws.send_json(status)
+            for task_id in list(subscribed):
+                snap = await asyncio.to_thread(_task_snapshot, task_id)
+                if snap is None:
+                    subscribed.pop(task_id, None)
+                    await ws.send_json({"type": "missing", "task_id": task_id})
+                elif snap != subscribed.get(task_id):
+                    subscribed[task_id] = snap
+                    await ws.send_json(snap)
+            live = {(t, b) for t in subscribed for b in _task_partials(t)}
+            for key in [k for k in partials if k not in live]:
+                partials.pop(key)
+            await asyncio.sleep(0.25)
+
+    pusher = asyncio.create_task(push_changes())
+    try:
+        while True:
+            try:
+                msg = json.loads(await ws.receive_text())
+            except ValueError:
+                continue
+            kind = msg.get("type") if isinstance(msg, dict) else None
+            if kind == "subscribe" and msg.get("task_id"):
+                # an empty entry makes the next round send a fresh snapshot
+                subscribed[str(msg["task_id"])] = None
+            elif kind == "unsubscribe":
+                subscribed.pop(str(msg.get("task_id")), None)
+            elif kind == "ping":
+                await ws.send_json({"type": "pong"})
+    except WebSocketDisconnect:
+        pass
+    finally:
+        pusher.cancel()
+
+
 @app.get("/api/tasks/{task_id}/export")
 def export_docx(task_id: str):
     conn = db()
     task = conn.execute(
-        "SELECT work_path, filename FROM tasks WHERE id=?", (task_id,)
+        "SELECT source_path, work_path, filename FROM tasks WHERE id=?", (task_id,)
     ).fetchone()
     if not task:
         conn.close()
         raise HTTPException(404, "task not found")
+    if task["work_path"] == task["source_path"]:
+        conn.close()
+        raise HTTPException(400, "this task is exported by the desktop shell")
 
     blocks = conn.execute(
         "SELECT locator, translated_text FROM blocks WHERE task_id=?", (task_id,)
     ).fetchall()
     conn.close()
 
-    locator_to_text = {b["locator"]: b["translated_text"] for b in blocks if b["translated_text"]}
+    locator_to_text = {b["locator"]: unseal(b["translated_text"]) for b in blocks if b["translated_text"]}
 
     out_path = os.path.join(export_dir(task_id), f"translated_{task['filename']}")
     apply_translations(task["work_path"], out_path, locator_to_text)
@@ -457,6 +2280,209 @@ def export_docx(task_id: str):
     )
 
 
+@app.get("/api/tasks/{task_id}/source")
+def get_source(task_id: str):
+    conn = db()
+    task = conn.execute(
+        "SELECT source_path, filename FROM tasks WHERE id=?", (task_id,)
+    ).fetchone()
+    conn.close()
+    if not task:
+        raise HTTPException(404, "task not found")
+    if not os.path.exists(task["source_path"]):
+        raise HTTPException(410, "the source document is gone")
+    return FileResponse(task["source_path"], filename=task["filename"])
+
+
+def _pid_alive(pid: int) -> bool:
+    if sys.platform == "win32":
+        # os.kill(pid, 0) would TerminateProcess on Windows
+        import ctypes
+
+        PROCESS_QUERY_LIMITED_INFORMATION = 0x1000
+        STILL_ACTIVE = 259
+        k32 = ctypes.windll.kernel32
+        h = k32.OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, False, pid)
+        if not h:
+            return False
+        code = ctypes.c_ulong()
+        ok = k32.GetExitCodeProcess(h, ctypes.byref(code))
+        k32.CloseHandle(h)
+        return bool(ok) and code.value == STILL_ACTIVE
+    try:
+        os.kill(pid, 0)
+    except ProcessLookupError:
+        return False
+    except PermissionError:
+        return True
+    return True
+
+
+def _watch_parent(parent_pid: int):
+    # Exit when the desktop shell dies (crash, task manager kill) so we
+    # don't linger as an orphan holding the port and the db.
+    if sys.platform.startswith("linux") and os.getppid() == parent_pid:
+        try:
+            import ctypes
+
+            PR_SET_PDEATHSIG = 1
+            ctypes.CDLL(None).prctl(PR_SET_PDEATHSIG, signal.SIGTERM)
+        except Exception:
+            pass
+
+    def loop():
+        while True:
+            time.sleep(1.0)
+            if not _pid_alive(parent_pid):
+                os._exit(0)
+
+    threading.Thread(target=loop, name="parent-watchdog", daemon=True).start()
+
+
+def _lower_integrity_level():
+    # Windows: drop our own token to Low integrity, so we can't write
+    # anywhere but Low-labelled locations (the shell labels the data dir).
+    import ctypes
+    from ctypes import wintypes
+
+    class SID_AND_ATTRIBUTES(ctypes.Structure):
+        _fields_ = [("Sid", ctypes.c_void_p), ("Attributes", wintypes.DWORD)]
+
+    TOKEN_QUERY = 0x0008
+    TOKEN_ADJUST_DEFAULT = 0x0080
+    TokenIntegrityLevel = 25
+    SE_GROUP_INTEGRITY = 0x20
+    advapi = ctypes.windll.advapi32
+    token = wintypes.HANDLE()
+    if not advapi.OpenProcessToken(
+        ctypes.windll.kernel32.GetCurrentProcess(),
+        TOKEN_QUERY | TOKEN_ADJUST_DEFAULT,
+        ctypes.byref(token),
+    ):
+        raise OSError("OpenProcessToken failed")
+    sid = ctypes.c_void_p()
+    if not advapi.ConvertStringSidToSidW("S-1-16-4096", ctypes.byref(sid)):
+        raise OSError("ConvertStringSidToSidW failed")
+    label = SID_AND_ATTRIBUTES(sid, SE_GROUP_INTEGRITY)
+    size = ctypes.sizeof(label) + advapi.GetLengthSid(sid)
+    ok = advapi.SetTokenInformation(token, TokenIntegrityLevel, ctypes.byref(label), size)
+    ctypes.windll.kernel32.LocalFree(sid)
+    ctypes.windll.kernel32.CloseHandle(token)
+    if not ok:
+        raise OSError("SetTokenInformation failed")
+
+
+def _drop_privileges_linux():
+    import ctypes
+
+    libc = ctypes.CDLL(None, use_errno=True)
+    PR_CAPBSET_DROP = 24
+    PR_SET_NO_NEW_PRIVS = 38
+    if libc.prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0:
+        raise OSError(ctypes.get_errno(), "PR_SET_NO_NEW_PRIVS failed")
+    # Clearing the bounding set needs CAP_SETPCAP; an unprivileged process
+    # has nothing to drop anyway.
+    for cap in range(64):
+        libc.prctl(PR_CAPBSET_DROP, cap, 0, 0, 0)
+
+
+def _enter_sandbox(allowed: list):
+    # Confines file access to the data dir (read/write) plus what the
+    # interpreter itself needs (read-only). Documents reach us as uploads,
+    # so nothing else on disk has to be reachable.
+    import tempfile
+
+    def norm(p):
+        return os.path.normcase(os.path.realpath(p))
+
+    writable = [norm(p) for p in allowed] + [norm(tempfile.gettempdir())]
+    readable = list(writable)
+    for p in [sys.prefix, sys.base_prefix, sys.exec_prefix, os.path.dirname(sys.executable)]:
+        readable.append(norm(p))
+    for p in sys.path:
+        if p:
+            readable.append(norm(p))
+    meipass = getattr(sys, "_MEIPASS", None)
+    if meipass:
+        readable.append(norm(meipass))
+    if sys.platform == "win32":
+        readable.append(norm(os.environ.get("SystemRoot", r"C:\Windows")))
+    else:
+        # shared libs, certificates, resolver config, /dev/urandom
+        readable += ["/usr", "/lib", "/lib64", "/etc", "/proc", "/sys", "/dev"]
+
+    def inside(path, roots):
+        try:
+            p = norm(os.fsdecode(path))
+        except Exception:
+            return False
+        return any(p == r or p.startswith(r.rstrip(os.sep) + os.sep) for r in roots)
+
+    write_flags = os.O_WRONLY | os.O_RDWR | os.O_APPEND | os.O_CREAT | os.O_TRUNC
+
+    def hook(event, args):
+        if event == "open":
+            path, mode, flags = args
+            if path is None or isinstance(path, int):
+                return
+            writing = any(c in (mode or "") for c in "wax+") or bool((flags or 0) & write_flags)
+            if not inside(path, writable if writing else readable):
+                raise PermissionError(f"sandbox: access to {path!r} denied")
+        elif event in ("os.remove", "os.rmdir", "os.mkdir", "os.rename", "shutil.rmtree"):
+            for path in args[:2] if event == "os.rename" else args[:1]:
+                if isinstance(path, (str, bytes, os.PathLike)) and not inside(path, writable):
+                    raise PermissionError(f"sandbox: modifying {path!r} denied")
+
+    if sys.platform.startswith("linux"):
+        _drop_privileges_linux()
+    elif sys.platform == "win32":
+        _lower_integrity_level()
+    sys.addaudithook(hook)
+
+
+def _start_heartbeat(interval: float):
+    # One stdout line per interval, consumed by the desktop shell. If the
+    # lines stop (the process is wedged) or stalled_secs keeps growing (a
+    # worker is stuck), the shell restarts us.
+    def loop():
+        while True:
+            now = time.monotonic()
+            with _task_progress_lock:
+                busy = len(_task_progress)
+                stalled = max((now - t for t in _task_progress.values()), default=0.0)
+            line = json.dumps({"busy": busy, "stalled_secs": int(stalled)})
+            try:
+                sys.stdout.write(f"@@heartbeat {line}\n")
+                sys.stdout.flush()
+            except Exception:
+                pass
+            time.sleep(interval)
+
+    threading.Thread(target=loop, name="heartbeat", daemon=True).start()
+
+
+def _install_extra_ca():
+    """Trust the shell's extra root CAs (MVP_EXTRA_CA_FILE) on top of the
+    default bundle, for httpx (openai) and requests alike."""
+    extra = os.environ.get("MVP_EXTRA_CA_FILE")
+    if not extra or not os.path.isfile(extra):
+        return
+    try:
+        import certifi
+
+        with open(certifi.where(), "r", encoding="utf-8") as f:
+            default = f.read()
+    except Exception:
+        default = ""
+    with open(extra, "r", encoding="utf-8") as f:
+        custom = f.read()
+    combined = os.path.join(data_dir(), "ca-bundle.pem")
+    with open(combined, "w", encoding="utf-8") as f:
+        f.write(default.rstrip("\n") + "\n" + custom)
+    os.environ["SSL_CERT_FILE"] = combined
+    os.environ["REQUESTS_CA_BUNDLE"] = combined
+
+
 def _atomic_write_json(path: str, data: dict):
     os.makedirs(os.path.dirname(path), exist_ok=True)
     tmp = path + ".tmp"
@@ -468,28 +2494,65 @@ def _atomic_write_json(path: str, data: dict):
 
 
 def main():
+    global server, executor
+
     parser = argparse.ArgumentParser()
     parser.add_argument("--host", default="127.0.0.1")
     parser.add_argument("--port-file", required=True)
+    parser.add_argument("--port", type=int, default=0)
+    # Unix domain socket to serve on instead of TCP
+    parser.add_argument("--uds", default=None)
+    parser.add_argument("--parent-pid", type=int, default=None)
+    parser.add_argument("--heartbeat-secs", type=float, default=0)
+    parser.add_argument("--sandbox", action="store_true")
+    parser.add_argument("--allow-path", action="append", default=[])
+    # user-tunable, see set_backend_args in the shell
+    parser.add_argument("--workers", type=int, default=2)
+    parser.add_argument("--log-level", default=None)
     args = parser.parse_args()
 
-    # Pick a free TCP port (avoid uvicorn fd-mode for Windows compatibility)
-    s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
-    s.bind((args.host, 0))
-    port = s.getsockname()[1]
-    s.close()
-
-    _atomic_write_json(args.port_file, {"host": args.host, "port": port, "pid": os.getpid()})
+    if args.workers != 2:
+        executor = ThreadPoolExecutor(max_workers=max(1, args.workers))
+
+    threading.Thread(target=migrate_at_rest, daemon=True).start()
+
+    if args.parent_pid:
+        _watch_parent(args.parent_pid)
+    if args.heartbeat_secs > 0:
+        _start_heartbeat(args.heartbeat_secs)
+    _install_extra_ca()
+    if args.sandbox:
+        _enter_sandbox([data_dir()] + args.allow_path)
+
+    port = args.port
+    if args.uds:
+        port = 0
+        if os.path.exists(args.uds):
+            os.unlink(args.uds)
+    elif not port:
+        # Pick a free TCP port (avoid uvicorn fd-mode for Windows compatibility)
+        s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+        s.bind((args.host, 0))
+        port = s.getsockname()[1]
+        s.close()
+
+    info = {"host": args.host, "port": port, "pid": os.getpid()}
+    if args.uds:
+        info["uds"] = args.uds
+    _atomic_write_json(args.port_file, info)
 
     import uvicorn
-    uvicorn.run(
+    config = uvicorn.Config(
         app,
         host=args.host,
         port=port,
-        log_level="info",
+        uds=args.uds,
+        log_level=(args.log_level or os.environ.get("MVP_LOG_LEVEL", "info")).lower(),
         access_log=False,
         use_colors=False,
     )
+    server = uvicorn.Server(config)
+    server.run()
 
 
 if __name__ == "__main__":
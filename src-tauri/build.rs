@@ -1,3 +1,36 @@
-﻿fn main() {
+﻿use sha2::{Digest, Sha256};
+use std::{env, fs, path::PathBuf};
+
+/// Hashes the bundled backend sidecar into `$OUT_DIR/backend-manifest.json`,
+/// which the app checks the binary against before every launch. The map is
+/// empty when no sidecar has been staged (plain `cargo build` in dev).
+fn write_backend_manifest() {
+  println!("cargo:rerun-if-changed=bin");
+  let target = env::var("TARGET").unwrap();
+  let exe = if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+    ".exe"
+  } else {
+    ""
+  };
+  let sidecar = PathBuf::from(format!("bin/mvp_backend-{target}{exe}"));
+
+  let manifest = match fs::read(&sidecar) {
+    Ok(bytes) => {
+      let hex: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+      format!(r#"{{"mvp_backend":"{hex}"}}"#)
+    }
+    Err(_) => "{}".to_string(),
+  };
+  let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("backend-manifest.json");
+  fs::write(out, manifest).unwrap();
+}
+
+fn main() {
+  write_backend_manifest();
+  // the key backend update manifests are signed with, see update.rs
+  println!("cargo:rerun-if-env-changed=MVP_UPDATE_PUBLIC_KEY");
   tauri_build::build()
 }
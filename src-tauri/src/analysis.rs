@@ -0,0 +1,286 @@
+//! `analyze_document`: what a document holds before it is sent anywhere,
+//! so its scope (and so its cost) is known before a task is created. The
+//! document is segmented as an upload would, without OCR: scanned PDF
+//! pages and images are counted instead of read.
+//!
+//! Words are counted as translators bill them: a run of letters or digits
+//! is one, and so is each CJK character. Languages come from the scripts
+//! of the letters and, for Latin letters, from the words of their segment
+//! (see `language`), counting as `en` when those do not tell.
+
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  config::StartupConfig,
+  docx, formats, language, ocr, ooxml,
+  pdftext::is_cjk,
+  proxy::ProxyError,
+  segments::{self, Segment},
+  settings::SettingsState,
+};
+
+/// Languages with less of the letters than this are left out.
+const MIN_SHARE: f64 = 0.05;
+/// Kana among this share of the Han and kana make Japanese of the Han too.
+const KANA_SHARE: f64 = 0.1;
+
+#[derive(Serialize)]
+pub struct LanguageShare {
+  /// `zh`, `ja`, `ko`, `ru`, `ar`, `el`, `he`, `th`, `hi` or a language
+  /// written in Latin letters, e.g. `en` or `fr`.
+  pub code: String,
+  /// Of the document's letters, from 0 to 1.
+  pub share: f64,
+}
+
+#[derive(Serialize)]
+pub struct DocumentStats {
+  /// The extension the document is read as, see `detect_format`.
+  pub format: String,
+  /// PDF pages, slides, sheets, Word pages as Word last counted them, or 1
+  /// for an image; `None` for formats without pages.
+  pub pages: Option<usize>,
+  /// Scanned PDF pages and images, read by OCR when translated and so not
+  /// in the counts below.
+  pub scanned_pages: usize,
+  pub segments: usize,
+  pub words: usize,
+  /// Not counting whitespace.
+  pub characters: usize,
+  /// Most of the letters first.
+  pub languages: Vec<LanguageShare>,
+  /// The direction that fits the main language, if it is one the app
+  /// translates from.
+  pub direction: Option<String>,
+  /// Share of the characters that are numbers, URLs, e-mail addresses and
+  /// placeholders: sent along, but left as they are.
+  pub non_translatable: f64,
+}
+
+#[derive(Default)]
+struct Tally {
+  segments: usize,
+  words: usize,
+  characters: usize,
+  fixed: usize,
+  letters: BTreeMap<&'static str, usize>,
+}
+
+fn script(c: char) -> Option<&'static str> {
+  Some(match c as u32 {
+    0x3040..=0x30FF => "ja",
+    0x1100..=0x11FF | 0xAC00..=0xD7AF => "ko",
+    0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => "zh",
+    0x0370..=0x03FF => "el",
+    0x0400..=0x04FF => "ru",
+    0x0590..=0x05FF => "he",
+    0x0600..=0x06FF => "ar",
+    0x0900..=0x097F => "hi",
+    0x0E00..=0x0E7F => "th",
+    0x0041..=0x024F if c.is_alphabetic() => "en",
+    _ => return None,
+  })
+}
+
+/// Whether `token` is left as it is by a translation.
+fn fixed(token: &str) -> bool {
+  let token = token.trim_matches(|c: char| "()[]<>,.;:!?\"'“”‘’".contains(c));
+  !token.chars().any(char::is_alphabetic)
+    || token.contains("://")
+    || token.starts_with("www.")
+    || token
+      .split_once('@')
+      .is_some_and(|(user, host)| !user.is_empty() && host.contains('.'))
+    || token.contains('⟦')
+    || token.starts_with('{') && token.ends_with('}')
+    || token.starts_with('%') && token.len() <= 3
+}
+
+impl Tally {
+  fn add(&mut self, text: &str) {
+    self.segments += 1;
+    let latin = if text.chars().any(|c| script(c) == Some("en")) {
+      language::detect(text)
+        .filter(|g| g.reliable && g.script == "Latin")
+        .map_or("en", |g| g.lang)
+    } else {
+      "en"
+    };
+    for token in text.split_whitespace() {
+      let characters = token.chars().count();
+      self.characters += characters;
+      // a URL or a number is one word, in no language
+      if fixed(token) {
+        self.fixed += characters;
+        self.words += usize::from(token.chars().any(char::is_alphanumeric));
+        continue;
+      }
+      let mut in_word = false;
+      for c in token.chars() {
+        if let Some(script) = script(c) {
+          let script = if script == "en" { latin } else { script };
+          *self.letters.entry(script).or_default() += 1;
+        }
+        if is_cjk(c) && c.is_alphanumeric() {
+          self.words += 1;
+          in_word = false;
+        } else if c.is_alphanumeric() {
+          self.words += usize::from(!in_word);
+          in_word = true;
+        } else if !(in_word && matches!(c, '\'' | '’' | '-')) {
+          in_word = false;
+        }
+      }
+    }
+  }
+
+  fn languages(&self) -> Vec<LanguageShare> {
+    let mut letters = self.letters.clone();
+    let kana = letters.get("ja").copied().unwrap_or(0);
+    let han = letters.get("zh").copied().unwrap_or(0);
+    if kana > 0 && kana as f64 >= (kana + han) as f64 * KANA_SHARE {
+      letters.remove("zh");
+      letters.insert("ja", kana + han);
+    }
+    let total: usize = letters.values().sum();
+    let mut out: Vec<LanguageShare> = letters
+      .into_iter()
+      .map(|(code, n)| LanguageShare {
+        code: code.to_string(),
+        share: n as f64 / total as f64,
+      })
+      .filter(|l| l.share >= MIN_SHARE)
+      .collect();
+    out.sort_by(|a, b| b.share.total_cmp(&a.share));
+    out
+  }
+}
+
+/// Whether `name` is `<prefix><number>.xml`.
+fn numbered(name: &str, prefix: &str) -> bool {
+  name
+    .strip_prefix(prefix)
+    .and_then(|rest| rest.strip_suffix(".xml"))
+    .is_some_and(|number| number.parse::<u32>().is_ok())
+}
+
+fn pages(path: &Path, extension: &str) -> Option<usize> {
+  match extension {
+    "pdf" => {
+      return pdf_extract::Document::load(path)
+        .ok()
+        .map(|d| d.get_pages().len())
+    }
+    "png" | "jpg" | "jpeg" => return Some(1),
+    "docx" | "pptx" | "xlsx" => {}
+    _ => return None,
+  }
+  let data = fs::read(path).ok()?;
+  let mut archive = ooxml::open(&data).ok()?;
+  match extension {
+    "pptx" => Some(
+      archive
+        .file_names()
+        .filter(|n| numbered(n, "ppt/slides/slide"))
+        .count(),
+    ),
+    "xlsx" => Some(
+      archive
+        .file_names()
+        .filter(|n| numbered(n, "xl/worksheets/sheet"))
+        .count(),
+    ),
+    _ => {
+      let app = ooxml::read_part(&mut archive, "docProps/app.xml").ok()?;
+      let app = String::from_utf8_lossy(&app);
+      app
+        .split_once("<Pages>")?
+        .1
+        .split_once("</Pages>")?
+        .0
+        .trim()
+        .parse()
+        .ok()
+    }
+  }
+}
+
+/// Reads the document at `path` as an upload would, without OCR, handing
+/// its segments to `each` a batch at a time. Returns the extension it is
+/// read as and the scanned pages left unread.
+pub fn read(
+  app: &AppHandle,
+  path: &Path,
+  mut each: impl FnMut(Vec<Segment>),
+) -> Result<(String, usize), String> {
+  let detected = formats::detect(path).ok_or_else(|| format!("Cannot read {}", path.display()))?;
+  if !detected.translatable {
+    return Err(format!(
+      "{} cannot be translated",
+      detected.name.as_deref().unwrap_or("This file")
+    ));
+  }
+  let extension = detected.extension;
+  let size = fs::metadata(path)
+    .map_err(|e| format!("Cannot read {}: {e}", path.display()))?
+    .len();
+  let stream_from = app.state::<StartupConfig>().stream_segment_mb.max(1) * 1024 * 1024;
+
+  if detected.kind == "image" {
+    return Ok((extension, 1));
+  }
+  if size >= stream_from && segments::streams(&extension) {
+    segments::stream(path, &extension, |batch, _| {
+      each(batch);
+      Ok(())
+    })?;
+    return Ok((extension, 0));
+  }
+  let ocr = ocr::Session::off(app);
+  let po_retranslate = app.state::<SettingsState>().get().po_retranslate;
+  match segments::extract(path, None, docx::Review::default(), po_retranslate, &ocr) {
+    Ok(found) => each(found.unwrap_or_default()),
+    // a PDF of scanned pages only
+    Err(_) if ocr.skipped() > 0 => {}
+    Err(e) => return Err(e),
+  }
+  Ok((extension, ocr.skipped()))
+}
+
+fn analyze(app: &AppHandle, path: &Path) -> Result<DocumentStats, String> {
+  let mut tally = Tally::default();
+  let (extension, scanned_pages) = read(app, path, |batch| {
+    batch.iter().for_each(|s| tally.add(&s.text))
+  })?;
+
+  let languages = tally.languages();
+  let direction = languages.first().and_then(|l| language::direction(&l.code));
+  Ok(DocumentStats {
+    pages: pages(path, &extension),
+    format: extension,
+    scanned_pages,
+    segments: tally.segments,
+    words: tally.words,
+    characters: tally.characters,
+    languages,
+    direction,
+    non_translatable: if tally.characters == 0 {
+      0.0
+    } else {
+      tally.fixed as f64 / tally.characters as f64
+    },
+  })
+}
+
+/// Counts of the document at `path`, to see a job's scope before it is
+/// created.
+#[tauri::command]
+pub async fn analyze_document(app: AppHandle, path: String) -> Result<DocumentStats, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    analyze(&app, Path::new(&path)).map_err(|e| ProxyError::new("invalid-request", e))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,243 @@
+//! Optional app lock. With a master password set, the app starts locked
+//! and locks again after `idle_minutes` without user input. While locked
+//! the shell refuses every command except [`OPEN`] ones, the backend
+//! scheme answers 423, and the main window's content is hidden, until
+//! `unlock` gets the password. Only an argon2 hash is stored, in
+//! `startup.json`.
+
+use argon2::{
+  password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+  Argon2,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+use tauri::{ipc::Invoke, AppHandle, Emitter, Manager, Runtime, Webview};
+
+use crate::{config, paths};
+
+/// Commands the locked UI still needs.
+const OPEN: &[&str] = &["unlock", "get_lock_status", "report_activity"];
+const CHECK_EVERY: Duration = Duration::from_secs(15);
+/// Slows down guessing; argon2 itself takes a moment too.
+const FAILED_DELAY: Duration = Duration::from_secs(1);
+const MIN_LENGTH: usize = 8;
+
+const HIDE: &str = "document.documentElement.dataset.locked = 'true';";
+const SHOW: &str = "delete document.documentElement.dataset.locked;";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppLockConfig {
+  /// Argon2 PHC string of the master password; none means no lock.
+  pub password_hash: Option<String>,
+  /// Lock after this long without input; 0 locks only on launch and on
+  /// `lock_app`.
+  pub idle_minutes: u64,
+}
+
+impl Default for AppLockConfig {
+  fn default() -> Self {
+    Self {
+      password_hash: None,
+      idle_minutes: 15,
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct LockStatus {
+  enabled: bool,
+  locked: bool,
+  idle_minutes: u64,
+}
+
+static CONFIG: Mutex<Option<AppLockConfig>> = Mutex::new(None);
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static LAST_ACTIVITY: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn current() -> AppLockConfig {
+  CONFIG.lock().unwrap().clone().unwrap_or_default()
+}
+
+fn touch() {
+  *LAST_ACTIVITY.lock().unwrap() = Some(Instant::now());
+}
+
+pub fn is_locked() -> bool {
+  LOCKED.load(Ordering::SeqCst)
+}
+
+/// Starts locked when a password is set.
+pub fn init(config: AppLockConfig) {
+  LOCKED.store(config.password_hash.is_some(), Ordering::SeqCst);
+  *CONFIG.lock().unwrap() = Some(config);
+  touch();
+}
+
+fn verify(hash: &str, password: &str) -> bool {
+  PasswordHash::new(hash)
+    .map(|parsed| {
+      Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
+    })
+    .unwrap_or(false)
+}
+
+fn set_locked<R: Runtime>(app: &AppHandle<R>, locked: bool) {
+  if LOCKED.swap(locked, Ordering::SeqCst) == locked {
+    return;
+  }
+  if !locked {
+    touch();
+  }
+  for window in app.webview_windows().values() {
+    let _ = window.eval(if locked { HIDE } else { SHOW });
+  }
+  let _ = app.emit(if locked { "app-locked" } else { "app-unlocked" }, ());
+}
+
+/// Wraps the command handler: refuses commands while locked.
+pub fn guard<R: Runtime>(
+  handler: impl Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+  move |invoke| {
+    if is_locked() && !OPEN.contains(&invoke.message.command()) {
+      invoke.resolver.reject("The app is locked");
+      return true;
+    }
+    handler(invoke)
+  }
+}
+
+/// Re-hides a page that (re)loads while locked.
+pub fn on_page_load<R: Runtime>(webview: &Webview<R>) {
+  if is_locked() {
+    let _ = webview.eval(HIDE);
+  }
+}
+
+/// Locks after `idle_minutes` without [`report_activity`].
+pub fn spawn_idle_monitor(app: AppHandle) {
+  thread::spawn(move || loop {
+    thread::sleep(CHECK_EVERY);
+    let config = current();
+    if config.password_hash.is_none() || config.idle_minutes == 0 || is_locked() {
+      continue;
+    }
+    let idle = LAST_ACTIVITY
+      .lock()
+      .unwrap()
+      .is_some_and(|t| t.elapsed() >= Duration::from_secs(config.idle_minutes * 60));
+    if idle {
+      set_locked(&app, true);
+    }
+  });
+}
+
+#[tauri::command]
+pub fn get_lock_status() -> LockStatus {
+  let config = current();
+  LockStatus {
+    enabled: config.password_hash.is_some(),
+    locked: is_locked(),
+    idle_minutes: config.idle_minutes,
+  }
+}
+
+#[tauri::command]
+pub async fn unlock(app: AppHandle, password: String) -> Result<(), String> {
+  let Some(hash) = current().password_hash else {
+    set_locked(&app, false);
+    return Ok(());
+  };
+  let ok = tauri::async_runtime::spawn_blocking(move || {
+    let ok = verify(&hash, &password);
+    if !ok {
+      thread::sleep(FAILED_DELAY);
+    }
+    ok
+  })
+  .await
+  .map_err(|e| e.to_string())?;
+  if !ok {
+    return Err("Wrong password".to_string());
+  }
+  set_locked(&app, false);
+  Ok(())
+}
+
+/// Locks now; does nothing without a master password.
+#[tauri::command]
+pub fn lock_app(app: AppHandle) {
+  if current().password_hash.is_some() {
+    set_locked(&app, true);
+  }
+}
+
+/// User input in the window; resets the idle timer. Ignored while locked.
+#[tauri::command]
+pub fn report_activity() {
+  if !is_locked() {
+    touch();
+  }
+}
+
+/// Sets, changes (`password`) or removes (`password` null) the master
+/// password and the idle timeout. Needs `current_password` when one is
+/// already set.
+#[tauri::command]
+pub async fn set_master_password(
+  app: AppHandle,
+  current_password: Option<String>,
+  password: Option<String>,
+  idle_minutes: u64,
+) -> Result<LockStatus, String> {
+  let old = current();
+  let password = password.filter(|p| !p.is_empty());
+  if password
+    .as_ref()
+    .is_some_and(|p| p.chars().count() < MIN_LENGTH)
+  {
+    return Err(format!(
+      "The master password needs at least {MIN_LENGTH} characters"
+    ));
+  }
+  let hash = tauri::async_runtime::spawn_blocking(move || {
+    if let Some(hash) = &old.password_hash {
+      if !verify(hash, current_password.as_deref().unwrap_or_default()) {
+        thread::sleep(FAILED_DELAY);
+        return Err("The current password is wrong".to_string());
+      }
+    }
+    password
+      .map(|p| {
+        Argon2::default()
+          .hash_password(p.as_bytes(), &SaltString::generate(&mut OsRng))
+          .map(|h| h.to_string())
+          .map_err(|e| format!("Cannot hash the password: {e}"))
+      })
+      .transpose()
+  })
+  .await
+  .map_err(|e| e.to_string())??;
+
+  let config = AppLockConfig {
+    password_hash: hash,
+    idle_minutes,
+  };
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "app_lock", value)
+    .map_err(|e| format!("Cannot save the app lock settings: {e}"))?;
+  *CONFIG.lock().unwrap() = Some(config);
+  touch();
+  Ok(get_lock_status())
+}
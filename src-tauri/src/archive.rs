@@ -0,0 +1,445 @@
+//! ZIP archives as a batch. `open_archive` unpacks one into a scratch
+//! folder and lists what is in it; `translate_archive` translates the
+//! entries chosen, a task each, and writes an archive of the same layout
+//! with their translated copies in place of the originals. Everything else
+//! is copied over as it was, still compressed.
+//!
+//! Entries are unpacked each into a folder of its own, named for the
+//! format they are read as (see `formats`), so parsers and the backend go
+//! by it. Entries whose path leaves the archive, that are larger than
+//! `max_document_mb` (whatever their header claims) or that no parser
+//! reads are listed but not unpacked.
+//!
+//! Scratch folders are `<data dir>/archives/<id>`, removed once the batch
+//! is written or dropped with `close_archive`, and at startup for any a
+//! crash left behind. Progress is reported as `archive-progress` events.
+
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  fs::{self, File},
+  io::{self, Read},
+  path::{Path, PathBuf},
+  sync::{Mutex, OnceLock},
+  thread,
+  time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+  config::StartupConfig,
+  docx, formats, profiles,
+  proxy::{invalid, ProxyError},
+  segments,
+  settings::SettingsState,
+  upload,
+};
+
+const MAX_ENTRIES: usize = 10_000;
+/// How often a running task is looked at.
+const POLL: Duration = Duration::from_secs(1);
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Archives opened and not yet translated, by id.
+#[derive(Default)]
+pub struct ArchiveState {
+  open: Mutex<HashMap<String, Opened>>,
+}
+
+/// A scratch folder, removed when dropped.
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
+
+struct Opened {
+  source: PathBuf,
+  scratch: Scratch,
+  entries: Vec<(ArchiveEntry, Option<PathBuf>)>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ArchiveEntry {
+  /// Its path in the archive, `/`-separated.
+  pub name: String,
+  pub size: u64,
+  /// The format it is read as, e.g. `Word document`.
+  pub format: Option<String>,
+  pub translatable: bool,
+  /// Why it cannot be translated, when it cannot.
+  pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveListing {
+  /// For `translate_archive` and `close_archive`.
+  pub id: String,
+  pub entries: Vec<ArchiveEntry>,
+}
+
+#[derive(Serialize)]
+pub struct EntryResult {
+  pub name: String,
+  pub task_id: Option<String>,
+  /// Why the original went into the output instead.
+  pub error: Option<String>,
+  /// Segments written with a warning (a subtitle over the limits, a tag
+  /// lost), see the format's own export.
+  pub warnings: usize,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveResult {
+  /// The archive written.
+  pub path: String,
+  pub entries: Vec<EntryResult>,
+}
+
+#[derive(Clone, Serialize)]
+struct ArchiveProgress<'a> {
+  id: &'a str,
+  name: &'a str,
+  /// Entries finished, and of how many chosen.
+  done: usize,
+  total: usize,
+  /// `uploading`, `translating` or `writing`.
+  stage: &'a str,
+  /// Of the entry's translation, from 0 to 1.
+  progress: f64,
+}
+
+/// Removes scratch folders left by an earlier run; called once at startup.
+pub fn init(data_dir: &Path) {
+  let root = data_dir.join("archives");
+  let _ = fs::remove_dir_all(&root);
+  let _ = ROOT.set(root);
+}
+
+/// Unpacks `entry` to `dir` if it can be translated.
+fn unpack(
+  entry: &mut zip::read::ZipFile<'_>,
+  dir: &Path,
+  max_bytes: u64,
+) -> Result<(ArchiveEntry, Option<PathBuf>), ProxyError> {
+  let mut listed = ArchiveEntry {
+    name: entry.name().to_string(),
+    size: entry.size(),
+    format: None,
+    translatable: false,
+    reason: None,
+  };
+  let Some(file_name) = entry
+    .enclosed_name()
+    .and_then(|p| p.file_name().map(|n| n.to_owned()))
+  else {
+    listed.reason = Some("its path leaves the archive".to_string());
+    return Ok((listed, None));
+  };
+  let too_large = || format!("larger than the {} MB limit", max_bytes / (1024 * 1024));
+  if entry.size() > max_bytes {
+    listed.reason = Some(too_large());
+    return Ok((listed, None));
+  }
+  let write_err = |e: io::Error| invalid(format!("Cannot unpack {}: {e}", listed.name));
+  fs::create_dir_all(dir).map_err(write_err)?;
+  let mut path = dir.join(file_name);
+  let written = io::copy(
+    &mut entry.by_ref().take(max_bytes + 1),
+    &mut File::create(&path).map_err(write_err)?,
+  )
+  .map_err(write_err)?;
+  let detected = (written <= max_bytes)
+    .then(|| formats::detect(&path))
+    .flatten();
+  match detected {
+    Some(detected) if detected.translatable => {
+      if detected.extension != detected.file_extension {
+        let named = path.with_extension(&detected.extension);
+        fs::rename(&path, &named).map_err(write_err)?;
+        path = named;
+      }
+      listed.format = detected.name;
+      listed.translatable = true;
+      Ok((listed, Some(path)))
+    }
+    found => {
+      let _ = fs::remove_dir_all(dir);
+      listed.format = found.as_ref().and_then(|d| d.name.clone());
+      listed.reason = Some(match found {
+        None if written > max_bytes => too_large(),
+        _ => "not a format that can be translated".to_string(),
+      });
+      Ok((listed, None))
+    }
+  }
+}
+
+fn open(app: &AppHandle, path: &Path) -> Result<(String, Opened), ProxyError> {
+  let root = ROOT
+    .get()
+    .ok_or_else(|| ProxyError::new("not-ready", "the app is still starting"))?;
+  let file =
+    File::open(path).map_err(|e| invalid(format!("Cannot read {}: {e}", path.display())))?;
+  let mut archive =
+    ZipArchive::new(file).map_err(|e| invalid(format!("Not a ZIP archive: {e}")))?;
+  if archive.len() > MAX_ENTRIES {
+    return Err(invalid(format!(
+      "The archive has {} entries, more than the {MAX_ENTRIES} a batch can take",
+      archive.len()
+    )));
+  }
+  let max_bytes = app.state::<StartupConfig>().max_document_mb.max(1) * 1024 * 1024;
+  let id = uuid::Uuid::new_v4().simple().to_string();
+  let scratch = Scratch(root.join(&id));
+  let mut entries = Vec::new();
+  for index in 0..archive.len() {
+    let mut entry = archive
+      .by_index(index)
+      .map_err(|e| invalid(format!("Cannot read the archive: {e}")))?;
+    if entry.is_dir() {
+      continue;
+    }
+    entries.push(unpack(
+      &mut entry,
+      &scratch.0.join(index.to_string()),
+      max_bytes,
+    )?);
+  }
+  Ok((
+    id,
+    Opened {
+      source: path.to_path_buf(),
+      scratch,
+      entries,
+    },
+  ))
+}
+
+/// Translates the document at `path` and writes its translated copy to
+/// `dest`; returns the task and how many segments had warnings.
+fn translate_entry(
+  app: &AppHandle,
+  id: &str,
+  path: &Path,
+  direction: &str,
+  dest: &Path,
+  progress: &dyn Fn(&str, f64),
+) -> Result<(String, usize), ProxyError> {
+  progress("uploading", 0.0);
+  let created = upload::upload(
+    app,
+    &format!("archive-{id}"),
+    path,
+    direction,
+    None,
+    docx::Review::default(),
+  )?;
+  let task_id = created["task_id"]
+    .as_str()
+    .ok_or_else(|| ProxyError::new("http", "the backend did not create a task"))?
+    .to_string();
+  profiles::call(
+    app,
+    "POST",
+    &format!("/api/tasks/{task_id}/run_translate"),
+    Some(serde_json::json!({
+      "concurrency": app.state::<SettingsState>().get().segment_concurrency,
+    })),
+  )?;
+  loop {
+    thread::sleep(POLL);
+    let task = profiles::call(app, "GET", &format!("/api/tasks/{task_id}"), None)?;
+    match task["status"].as_str() {
+      Some("finished") => break,
+      Some("error") => {
+        return Err(ProxyError::new(
+          "http",
+          task["error"].as_str().unwrap_or("the translation failed"),
+        ))
+      }
+      Some("cancelled") => return Err(ProxyError::new("http", "the translation was cancelled")),
+      _ => progress("translating", task["progress"].as_f64().unwrap_or(0.0)),
+    }
+  }
+  progress("writing", 1.0);
+  if let Some(dir) = dest.parent() {
+    fs::create_dir_all(dir).map_err(|e| invalid(format!("Cannot write {}: {e}", dir.display())))?;
+  }
+  let warnings = segments::export(app, &task_id, dest)?;
+  Ok((task_id, warnings))
+}
+
+/// Writes `source` to `dest` with the entries in `translated` replaced by
+/// the files they name; the others are copied as they are.
+fn write(source: &Path, dest: &Path, translated: &HashMap<&str, PathBuf>) -> Result<(), String> {
+  let write_err = |e: &dyn std::fmt::Display| format!("Cannot write {}: {e}", dest.display());
+  let file = File::open(source).map_err(|e| format!("Cannot read {}: {e}", source.display()))?;
+  let mut archive = ZipArchive::new(file).map_err(|e| write_err(&e))?;
+  let mut zip = ZipWriter::new(File::create(dest).map_err(|e| write_err(&e))?);
+  for i in 0..archive.len() {
+    let entry = archive.by_index_raw(i).map_err(|e| write_err(&e))?;
+    match translated.get(entry.name()) {
+      Some(path) => {
+        let name = entry.name().to_string();
+        let options = SimpleFileOptions::default()
+          .compression_method(CompressionMethod::Deflated)
+          .large_file(fs::metadata(path).is_ok_and(|m| m.len() >= u32::MAX as u64));
+        drop(entry);
+        zip.start_file(name, options).map_err(|e| write_err(&e))?;
+        let mut copy = File::open(path).map_err(|e| write_err(&e))?;
+        io::copy(&mut copy, &mut zip).map_err(|e| write_err(&e))?;
+      }
+      None => zip.raw_copy_file(entry).map_err(|e| write_err(&e))?,
+    }
+  }
+  zip.finish().map_err(|e| write_err(&e))?;
+  Ok(())
+}
+
+fn translate(
+  app: &AppHandle,
+  id: &str,
+  names: &[String],
+  direction: &str,
+  dest: Option<&Path>,
+) -> Result<ArchiveResult, ProxyError> {
+  // taken out, so its scratch folder goes with it however this ends
+  let opened = app
+    .state::<ArchiveState>()
+    .open
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .remove(id)
+    .ok_or_else(|| invalid(format!("No open archive {id}")))?;
+  let chosen: Vec<(&str, &Path)> = opened
+    .entries
+    .iter()
+    .filter(|(entry, _)| names.contains(&entry.name))
+    .filter_map(|(entry, path)| Some((entry.name.as_str(), path.as_deref()?)))
+    .collect();
+  if chosen.is_empty() {
+    return Err(invalid(
+      "None of the chosen entries can be translated".to_string(),
+    ));
+  }
+  let dest = match dest {
+    Some(dest) => dest.to_path_buf(),
+    None => {
+      let stem = opened
+        .source
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+      opened
+        .source
+        .with_file_name(format!("{stem}.translated.zip"))
+    }
+  };
+  let out = opened.scratch.0.join("out");
+  let mut translated = HashMap::new();
+  let mut results = Vec::new();
+  for (done, (name, path)) in chosen.iter().enumerate() {
+    let progress = |stage: &str, progress: f64| {
+      let _ = app.emit(
+        "archive-progress",
+        ArchiveProgress {
+          id,
+          name,
+          done,
+          total: chosen.len(),
+          stage,
+          progress,
+        },
+      );
+    };
+    let copy = out
+      .join(done.to_string())
+      .join(path.file_name().unwrap_or_default());
+    let mut result = EntryResult {
+      name: name.to_string(),
+      task_id: None,
+      error: None,
+      warnings: 0,
+    };
+    match translate_entry(app, id, path, direction, &copy, &progress) {
+      Ok((task_id, warnings)) => {
+        result.task_id = Some(task_id);
+        result.warnings = warnings;
+        translated.insert(*name, copy);
+      }
+      Err(e) => result.error = Some(e.message),
+    }
+    results.push(result);
+  }
+  if let Err(e) = write(&opened.source, &dest, &translated) {
+    let _ = fs::remove_file(&dest);
+    return Err(invalid(e));
+  }
+  Ok(ArchiveResult {
+    path: dest.display().to_string(),
+    entries: results,
+  })
+}
+
+/// Unpacks the ZIP archive at `path` and lists its entries.
+#[tauri::command]
+pub async fn open_archive(app: AppHandle, path: String) -> Result<ArchiveListing, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let (id, opened) = open(&app, Path::new(&path))?;
+    let entries = opened
+      .entries
+      .iter()
+      .map(|(entry, _)| entry.clone())
+      .collect();
+    app
+      .state::<ArchiveState>()
+      .open
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .insert(id.clone(), opened);
+    Ok(ArchiveListing { id, entries })
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Translates the entries `names` of archive `id` in `direction` and
+/// writes the archive with their translations to `path`, by default
+/// `<name>.translated.zip` next to the original. An entry that fails keeps
+/// its original; the archive is closed either way.
+#[tauri::command]
+pub async fn translate_archive(
+  app: AppHandle,
+  id: String,
+  names: Vec<String>,
+  direction: String,
+  path: Option<String>,
+) -> Result<ArchiveResult, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    translate(
+      &app,
+      &id,
+      &names,
+      &direction,
+      path.as_deref().map(Path::new),
+    )
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Drops archive `id` without translating it, removing its scratch folder.
+#[tauri::command]
+pub fn close_archive(app: AppHandle, id: String) {
+  app
+    .state::<ArchiveState>()
+    .open
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .remove(&id);
+}
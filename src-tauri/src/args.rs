@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::{config, paths};
+
+/// Extra flags appended to every backend spawn, persisted as
+/// `backend_args` in `startup.json`.
+pub struct BackendArgs(Mutex<Vec<String>>);
+
+impl BackendArgs {
+  pub fn new(args: Vec<String>) -> Self {
+    Self(Mutex::new(args))
+  }
+
+  pub fn get(&self) -> Vec<String> {
+    self.0.lock().unwrap().clone()
+  }
+}
+
+type ValueCheck = fn(&str) -> bool;
+
+/// Flags users may set, with a check for their value. Everything the shell
+/// passes itself (`--port`, `--port-file`, ...) is deliberately absent.
+const ALLOWED: &[(&str, ValueCheck)] = &[
+  ("--workers", |v| {
+    v.parse::<u32>().is_ok_and(|n| (1..=32).contains(&n))
+  }),
+  ("--log-level", |v| {
+    matches!(
+      v,
+      "critical" | "error" | "warning" | "info" | "debug" | "trace"
+    )
+  }),
+];
+
+/// Checks `args` against [`ALLOWED`] and normalizes `--flag=value` into
+/// two entries.
+pub fn validate(args: &[String]) -> Result<Vec<String>, String> {
+  let mut out = Vec::new();
+  let mut iter = args.iter().map(|a| a.trim()).filter(|a| !a.is_empty());
+  while let Some(arg) = iter.next() {
+    let (flag, value) = match arg.split_once('=') {
+      Some((flag, value)) => (flag, Some(value)),
+      None => (arg, None),
+    };
+    let Some((_, check)) = ALLOWED.iter().find(|(name, _)| *name == flag) else {
+      return Err(format!("Unsupported backend argument: {flag}"));
+    };
+    let Some(value) = value.or_else(|| iter.next()) else {
+      return Err(format!("{flag} needs a value"));
+    };
+    if !check(value) {
+      return Err(format!("Invalid value for {flag}: {value}"));
+    }
+    out.push(flag.to_string());
+    out.push(value.to_string());
+  }
+  Ok(out)
+}
+
+#[tauri::command]
+pub fn get_backend_args(state: State<BackendArgs>) -> Vec<String> {
+  state.get()
+}
+
+/// Validates and stores extra backend flags, e.g. `["--workers", "4"]`.
+/// They apply from the next (re)start of the backend.
+#[tauri::command]
+pub fn set_backend_args(
+  app: AppHandle,
+  state: State<BackendArgs>,
+  args: Vec<String>,
+) -> Result<Vec<String>, String> {
+  let args = validate(&args)?;
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "backend_args", args.clone().into())
+    .map_err(|e| format!("Cannot save backend arguments: {e}"))?;
+  *state.0.lock().unwrap() = args.clone();
+  Ok(args)
+}
@@ -0,0 +1,116 @@
+//! Encryption at rest for block and translation-memory texts in the task
+//! database, the translation cache and the job queue (`encrypt_at_rest` in
+//! the settings). The backend does the AES-GCM work for its data, as it
+//! reads and writes it; the shell keeps the random secret its keys are
+//! derived from in the OS credential store and hands it over at launch.
+//! The shell seals the paths and errors of its own `jobs.db` the same way
+//! (see [`seal`]).
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+  aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM},
+  hkdf,
+};
+use std::{collections::BTreeMap, sync::Mutex};
+use tauri::{AppHandle, Manager};
+
+use crate::{keychain, logs::BackendLog, settings::SettingsState};
+
+pub const KEY_ENV: &str = "MVP_DATA_KEY";
+pub const ENABLED_ENV: &str = "MVP_ENCRYPT_AT_REST";
+const SECRET: &str = "data-encryption-key";
+/// Prefix of sealed values, as the backend writes them.
+const SEALED: &str = "enc1:";
+
+/// The shell's key, once the secret has been read.
+static KEY: Mutex<Option<LessSafeKey>> = Mutex::new(None);
+
+/// The stored secret, created on first use when `create` is set.
+fn secret(create: bool) -> Result<Option<String>, String> {
+  if let Some(secret) = keychain::app_secret(SECRET)? {
+    return Ok(Some(secret));
+  }
+  if !create {
+    return Ok(None);
+  }
+  let mut raw = [0u8; 32];
+  getrandom::getrandom(&mut raw).map_err(|e| format!("No randomness available: {e}"))?;
+  let secret = STANDARD.encode(raw);
+  keychain::set_app_secret(SECRET, &secret)?;
+  Ok(Some(secret))
+}
+
+/// The secret goes along even with encryption off, so the backend can
+/// still read, and decrypt back, data sealed earlier.
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let enabled = app.state::<SettingsState>().get().encrypt_at_rest;
+  let mut env = BTreeMap::new();
+  match secret(enabled) {
+    Ok(Some(secret)) => {
+      env.insert(KEY_ENV.to_string(), secret);
+      env.insert(
+        ENABLED_ENV.to_string(),
+        if enabled { "1" } else { "0" }.to_string(),
+      );
+    }
+    Ok(None) => {}
+    Err(_) if !enabled => {}
+    Err(e) => app.state::<BackendLog>().append(
+      "shell",
+      format!("encryption at rest unavailable, data is stored unencrypted: {e}").as_bytes(),
+    ),
+  }
+  env
+}
+
+/// The key the shell seals with: AES-256-GCM, derived (HKDF-SHA256) from
+/// the secret like the backend's keys but for a purpose of its own.
+fn key(create: bool) -> Option<LessSafeKey> {
+  let mut cached = KEY.lock().unwrap_or_else(|e| e.into_inner());
+  if cached.is_none() {
+    let secret = STANDARD.decode(secret(create).ok()??).ok()?;
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &[]).extract(&secret);
+    let okm = prk
+      .expand(&[b"ai-doc-translator shell v1"], &AES_256_GCM)
+      .ok()?;
+    *cached = Some(LessSafeKey::new(UnboundKey::from(okm)));
+  }
+  cached.clone()
+}
+
+/// `text` sealed as the backend seals its values while encryption at rest
+/// is on, else as it is. Left as it is when there is no key, which
+/// `backend_env` reports.
+pub fn seal(app: &AppHandle, text: &str) -> String {
+  if !app.state::<SettingsState>().get().encrypt_at_rest {
+    return text.to_string();
+  }
+  let mut nonce = [0u8; 12];
+  let Some(key) = key(true).filter(|_| getrandom::getrandom(&mut nonce).is_ok()) else {
+    return text.to_string();
+  };
+  let mut data = text.as_bytes().to_vec();
+  if key
+    .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+    .is_err()
+  {
+    return text.to_string();
+  }
+  format!("{SEALED}{}", STANDARD.encode([&nonce[..], &data].concat()))
+}
+
+/// `value` unsealed, or as it is when it was not sealed; `None` when it was
+/// sealed under a secret that is gone.
+pub fn unseal(value: &str) -> Option<String> {
+  let Some(sealed) = value.strip_prefix(SEALED) else {
+    return Some(value.to_string());
+  };
+  let raw = STANDARD.decode(sealed).ok()?;
+  let (nonce, data) = raw.split_at_checked(12)?;
+  let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+  let mut data = data.to_vec();
+  let plain = key(false)?
+    .open_in_place(nonce, Aad::empty(), &mut data)
+    .ok()?;
+  String::from_utf8(plain.to_vec()).ok()
+}
@@ -0,0 +1,33 @@
+//! Per-session shared secret between the shell and the backends it spawns,
+//! so other local processes can't use the API on 127.0.0.1. The backend
+//! gets it via [`ENV`]; the shell adds [`HEADER`] to everything it sends
+//! (see `transport`). No command hands it to the webview.
+
+use std::sync::OnceLock;
+
+pub const ENV: &str = "MVP_BACKEND_TOKEN";
+pub const HEADER: &str = "x-mvp-token";
+
+static TOKEN: OnceLock<String> = OnceLock::new();
+
+/// 244 random bits from the OS RNG, generated on first use.
+pub fn token() -> &'static str {
+  TOKEN.get_or_init(|| {
+    format!(
+      "{}{}",
+      uuid::Uuid::new_v4().simple(),
+      uuid::Uuid::new_v4().simple()
+    )
+  })
+}
+
+/// `headers` with [`HEADER`] set to our token, replacing any value the
+/// caller (e.g. the webview) supplied.
+pub fn with_token(headers: &[(String, String)]) -> Vec<(String, String)> {
+  headers
+    .iter()
+    .filter(|(name, _)| !name.eq_ignore_ascii_case(HEADER))
+    .cloned()
+    .chain(Some((HEADER.to_string(), token().to_string())))
+    .collect()
+}
@@ -0,0 +1,897 @@
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::BTreeMap,
+  fs,
+  net::TcpListener,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tauri::{async_runtime::Receiver, AppHandle, Emitter, Manager, State};
+use tauri_plugin_shell::{
+  process::{Command, CommandChild, CommandEvent},
+  ShellExt,
+};
+
+use crate::{
+  args::BackendArgs,
+  atrest, auth, boxed_err, cache, certs,
+  config::StartupConfig,
+  diagnostics, dns,
+  heartbeat::{self, Heartbeat},
+  integrity::{self, TamperedBackend},
+  jobs, keychain,
+  logs::BackendLog,
+  os, outbound, paths, pool, priority,
+  quarantine::{self, BackendBlocked},
+  ratelimit, resilience,
+  status::{self, BackendState, BackendStatus},
+  tmx,
+  transport::{self, Listen},
+  update,
+  version::{self, IncompatibleBackend},
+};
+
+#[derive(Default)]
+pub struct BackendProcess {
+  child: Mutex<Option<Running>>,
+  // why the initial startup failed, for a frontend that missed the event
+  startup_error: Mutex<Option<String>>,
+  port_file: Mutex<Option<PathBuf>>,
+  // exit code of the last unexpected exit, for diagnostics
+  last_exit: Mutex<Option<i32>>,
+  stopping: AtomicBool,
+  // serializes user-requested restarts
+  restart: Mutex<()>,
+}
+
+/// A spawned backend process.
+pub struct Running {
+  child: CommandChild,
+  // set by the event thread once the process is gone
+  exited: Arc<AtomicBool>,
+}
+
+impl Running {
+  pub fn pid(&self) -> u32 {
+    self.child.pid()
+  }
+
+  pub fn has_exited(&self) -> bool {
+    self.exited.load(Ordering::SeqCst)
+  }
+}
+
+/// Called on the event thread with the pid and exit code once a backend
+/// process terminates.
+pub type ExitHandler = Box<dyn FnOnce(&AppHandle, u32, Option<i32>) + Send>;
+
+/// Reports handshake stages as `backend-starting` events. Pool workers
+/// start silently so the frontend only sees the primary backend.
+#[derive(Clone, Copy)]
+pub struct Progress<'a> {
+  app: &'a AppHandle,
+  start: Instant,
+  report: bool,
+}
+
+impl<'a> Progress<'a> {
+  pub fn new(app: &'a AppHandle, report: bool) -> Self {
+    Self {
+      app,
+      start: Instant::now(),
+      report,
+    }
+  }
+
+  fn elapsed(&self) -> Duration {
+    self.start.elapsed()
+  }
+
+  fn stage(&self, stage: &'static str) {
+    if !self.report {
+      return;
+    }
+    status::set(
+      self.app,
+      match stage {
+        "spawning" | "waiting-port-file" => BackendStatus::Launching,
+        _ => BackendStatus::WaitingHealth,
+      },
+    );
+    let _ = self.app.emit(
+      "backend-starting",
+      StartingPayload {
+        stage,
+        elapsed_ms: self.start.elapsed().as_millis() as u64,
+      },
+    );
+  }
+}
+
+#[derive(Deserialize)]
+struct PortInfo {
+  host: String,
+  port: u16,
+  pid: i64,
+  /// Set when the backend listens on a Unix domain socket instead.
+  #[serde(default)]
+  uds: Option<String>,
+}
+
+const PORT_FILE_PREFIX: &str = "backend-port-";
+
+#[derive(Clone, Serialize)]
+struct StartingPayload {
+  stage: &'static str,
+  elapsed_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct ReadyPayload {
+  base_url: String,
+}
+
+#[derive(Clone, Serialize)]
+struct FailedPayload {
+  error: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum BackendEvent {
+  Connected {
+    base_url: String,
+  },
+  Reconnecting {
+    attempt: u32,
+    exit_code: Option<i32>,
+  },
+  Failed {
+    attempt: u32,
+    error: String,
+  },
+  Healthy {
+    latency_ms: u64,
+  },
+  Degraded {
+    latency_ms: Option<u64>,
+    failures: u32,
+  },
+  Down {
+    failures: u32,
+  },
+}
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Overrides the backend binary location, e.g. for a dev build of the backend.
+const BACKEND_PATH_ENV: &str = "MVP_BACKEND_PATH";
+
+/// Name of the bundled sidecar, see `bundle.externalBin` in tauri.conf.json.
+const BACKEND_SIDECAR: &str = "mvp_backend";
+
+/// The command to run plus the binary it runs, for error diagnosis.
+fn backend_command(app: &AppHandle) -> Result<(Command, PathBuf), Box<dyn std::error::Error>> {
+  let configured = std::env::var_os(BACKEND_PATH_ENV)
+    .map(|p| (BACKEND_PATH_ENV, PathBuf::from(p)))
+    .or_else(|| {
+      let cfg = app.state::<StartupConfig>();
+      cfg
+        .backend_path
+        .as_ref()
+        .map(|p| ("backend_path", PathBuf::from(p)))
+    });
+  if let Some((source, p)) = configured {
+    if !p.is_file() {
+      return Err(boxed_err(format!(
+        "{source} points to a missing backend binary: {}",
+        p.display()
+      )));
+    }
+    return Ok((app.shell().command(&p), p));
+  }
+  if let Some(p) = update::installed(app)? {
+    return Ok((app.shell().command(&p), p));
+  }
+  let path = integrity::sidecar_path(BACKEND_SIDECAR)?;
+  quarantine::check_present(&path).map_err(|e| quarantine::report(app, e))?;
+  if let Err(e) = integrity::verify(BACKEND_SIDECAR, &path) {
+    // an unreadable binary is more likely locked by a scanner than tampered
+    if e.actual.is_none() {
+      if let Some(blocked) = fs::File::open(&path)
+        .err()
+        .and_then(|io| quarantine::diagnose(&path, &io))
+      {
+        return Err(quarantine::report(app, blocked));
+      }
+    }
+    let _ = app.emit("backend-integrity-failed", e.clone());
+    return Err(Box::new(e));
+  }
+  let cmd = app
+    .shell()
+    .sidecar(BACKEND_SIDECAR)
+    .map_err(|e| boxed_err(format!("Cannot resolve backend sidecar: {e}")))?;
+  Ok((cmd, path))
+}
+
+fn atomic_write(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let tmp = path.with_extension("tmp");
+  fs::write(&tmp, content)?;
+  fs::rename(tmp, path)?;
+  Ok(())
+}
+
+/// Waits for the port file and the health endpoint. Gives up early if the
+/// process dies instead of sitting out the whole timeout.
+pub fn wait_for_backend(
+  progress: Progress,
+  running: &Running,
+  port_file: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+  let cfg = progress.app.state::<StartupConfig>();
+  let timeout = cfg.timeout();
+
+  // 1) wait port-file
+  progress.stage("waiting-port-file");
+  let port_info: PortInfo = loop {
+    if running.has_exited() {
+      return Err(boxed_err("Backend exited during startup"));
+    }
+    if progress.elapsed() > timeout {
+      return Err(boxed_err("Timeout waiting for backend port-file"));
+    }
+    if let Ok(s) = fs::read_to_string(port_file) {
+      if let Ok(info) = serde_json::from_str::<PortInfo>(&s) {
+        break info;
+      }
+    }
+    std::thread::sleep(cfg.port_file_poll());
+  };
+
+  let base = match &port_info.uds {
+    Some(socket) => transport::socket_base_url(Path::new(socket)),
+    None => format!("http://{}:{}", port_info.host, port_info.port),
+  };
+
+  // 2) wait for the health endpoint
+  wait_for_health(progress, &base)?;
+  Ok(base)
+}
+
+fn wait_for_health(progress: Progress, base: &str) -> Result<(), Box<dyn std::error::Error>> {
+  let cfg = progress.app.state::<StartupConfig>();
+  let timeout = cfg.timeout();
+
+  progress.stage("waiting-health");
+  let mut failures = 0u32;
+  loop {
+    if progress.elapsed() > timeout {
+      return Err(boxed_err(format!(
+        "Timeout waiting for backend {}",
+        cfg.health_path
+      )));
+    }
+    let ok = transport::get(
+      &format!("{}{}", base, cfg.health_path),
+      cfg.health_probe_timeout(),
+    )
+    .map(|r| r.is_success())
+    .unwrap_or(false);
+
+    if ok {
+      break;
+    }
+    failures += 1;
+    if cfg.tcp_fallback_after > 0
+      && failures >= cfg.tcp_fallback_after
+      && transport::reachable(base, cfg.health_probe_timeout())
+    {
+      progress.app.state::<BackendLog>().append(
+        "shell",
+        format!(
+          "{} not answering; treating TCP connect as ready",
+          cfg.health_path
+        )
+        .as_bytes(),
+      );
+      break;
+    }
+    std::thread::sleep(cfg.health_poll());
+  }
+
+  Ok(())
+}
+
+/// Refuses backends outside the supported version range and reports them
+/// with a structured `backend-incompatible` event.
+pub fn check_version(progress: Progress, base_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+  progress.stage("checking-version");
+  version::check(base_url).map(|_| ()).map_err(|e| {
+    let _ = progress.app.emit("backend-incompatible", e.clone());
+    Box::new(e) as Box<dyn std::error::Error>
+  })
+}
+
+/// Removes port files left behind by previous sessions: those whose backend
+/// pid is gone, plus unreadable/temporary ones older than a minute.
+pub fn collect_stale_port_files(data_dir: &Path) {
+  let Ok(entries) = fs::read_dir(data_dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if !name.starts_with(PORT_FILE_PREFIX) {
+      continue;
+    }
+    let path = entry.path();
+    let stale = match fs::read_to_string(&path)
+      .ok()
+      .and_then(|s| serde_json::from_str::<PortInfo>(&s).ok())
+    {
+      Some(info) => u32::try_from(info.pid).map_or(true, |pid| !os::pid_alive(pid)),
+      None => entry
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age > Duration::from_secs(60)),
+    };
+    if stale {
+      let _ = fs::remove_file(path);
+    }
+  }
+}
+
+/// Uses a backend we did not spawn; there is no child to supervise, so the
+/// health monitor is the only thing watching it.
+fn connect_external(app: &AppHandle, base_url: &str) -> Result<String, Box<dyn std::error::Error>> {
+  let progress = Progress::new(app, true);
+  wait_for_health(progress, base_url)
+    .map_err(|e| boxed_err(format!("External backend {base_url} is not reachable: {e}")))?;
+  check_version(progress, base_url)?;
+  status::set(
+    app,
+    BackendStatus::Ready {
+      base_url: base_url.to_string(),
+    },
+  );
+  Ok(base_url.to_string())
+}
+
+/// Picks a fresh port file path in the data dir and checks it is writable.
+pub fn new_port_file(app: &AppHandle) -> Result<PathBuf, Box<dyn std::error::Error>> {
+  let data_dir = paths::data_dir(app)?;
+  fs::create_dir_all(&data_dir)?;
+
+  let port_file = data_dir.join(format!("{}{}.json", PORT_FILE_PREFIX, uuid::Uuid::new_v4()));
+  if port_file.exists() {
+    let _ = fs::remove_file(&port_file);
+  }
+
+  // quick write check (optional)
+  atomic_write(&port_file, "")?;
+  let _ = fs::remove_file(&port_file);
+  Ok(port_file)
+}
+
+/// Variables set on top of the inherited environment for every backend
+/// process.
+fn backend_env(app: &AppHandle) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+  let mut env = app.state::<StartupConfig>().backend_env.clone();
+  let data_dir = paths::data_dir(app)?;
+  env.insert(
+    "MVP_DATA_DIR".to_string(),
+    data_dir.to_string_lossy().into_owned(),
+  );
+  Ok(env)
+}
+
+pub fn looks_secret(name: &str) -> bool {
+  let name = name.to_ascii_uppercase();
+  ["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL"]
+    .iter()
+    .any(|s| name.contains(s))
+}
+
+/// The environment a backend process is started with, for debugging.
+/// Values that look like secrets are masked.
+#[tauri::command]
+pub fn get_backend_env(app: AppHandle) -> Result<BTreeMap<String, String>, String> {
+  let mut env: BTreeMap<String, String> = std::env::vars().collect();
+  env.extend(backend_env(&app).map_err(|e| e.to_string())?);
+  env.extend(outbound::backend_env(false));
+  env.extend(certs::backend_env());
+  env.extend(ratelimit::backend_env(&app));
+  env.extend(cache::backend_env(&app));
+  env.extend(resilience::backend_env(&app));
+  env.extend(dns::backend_env());
+  env.extend(keychain::backend_env());
+  env.extend(atrest::backend_env(&app));
+  env.extend(tmx::backend_env(&app));
+  env.extend(jobs::backend_env(&app));
+  mask_secrets(&mut env);
+  Ok(env)
+}
+
+/// Masks the values of variables that look like secrets.
+pub fn mask_secrets(env: &mut BTreeMap<String, String>) {
+  for (name, value) in env.iter_mut() {
+    if looks_secret(name) && !value.is_empty() {
+      *value = "********".to_string();
+    }
+  }
+}
+
+/// Where backend instance `index` (0 is the primary) should listen.
+pub fn listen_addr(app: &AppHandle, index: u32) -> Result<Listen, Box<dyn std::error::Error>> {
+  if app.state::<StartupConfig>().transport == "uds" {
+    match transport::new_socket_path() {
+      Some(socket) => return Ok(Listen::Unix(socket)),
+      None => app
+        .state::<BackendLog>()
+        .append("shell", b"Unix domain sockets unavailable here; using TCP"),
+    }
+  }
+  backend_port(app, index).map(Listen::Tcp)
+}
+
+/// Port for backend instance `index` (0 is the primary). With no fixed
+/// `backend_port`, binds port 0 to find a free one and releases it again,
+/// so concurrently starting instances don't race for the same port.
+fn backend_port(app: &AppHandle, index: u32) -> Result<u16, Box<dyn std::error::Error>> {
+  let fixed = app.state::<StartupConfig>().backend_port;
+  if fixed != 0 {
+    return u16::try_from(u32::from(fixed) + index)
+      .map_err(|_| boxed_err(format!("No port left for backend worker {index}")));
+  }
+  let listener = TcpListener::bind(("127.0.0.1", 0))
+    .map_err(|e| boxed_err(format!("Failed to find a free port: {e}")))?;
+  Ok(listener.local_addr()?.port())
+}
+
+/// Spawns one backend process. `log_tag` prefixes its lines in the backend
+/// log; `on_exit` runs once the process terminates.
+pub fn spawn_process(
+  app: &AppHandle,
+  listen: &Listen,
+  port_file: &Path,
+  log_tag: &str,
+  on_exit: ExitHandler,
+) -> Result<Running, Box<dyn std::error::Error>> {
+  let cfg = app.state::<StartupConfig>();
+  let heartbeat_secs = cfg.heartbeat_secs;
+  let (mut cmd, binary) = backend_command(app)?;
+  if cfg.sandbox {
+    let data_dir = paths::data_dir(app)?;
+    os::label_low_integrity(&data_dir)
+      .map_err(|e| boxed_err(format!("Cannot prepare sandboxed data dir: {e}")))?;
+    cmd = cmd.current_dir(data_dir).arg("--sandbox");
+    for dir in &cfg.sandbox_allow {
+      cmd = cmd.args(["--allow-path", dir]);
+    }
+  }
+  cmd = match listen {
+    Listen::Tcp(port) => cmd.args(["--port", &port.to_string()]),
+    Listen::Unix(socket) => cmd.args(["--uds", socket.to_string_lossy().as_ref()]),
+  };
+  let (rx, child) = cmd
+    .args([
+      "--heartbeat-secs",
+      &heartbeat_secs.to_string(),
+      "--host",
+      "127.0.0.1",
+      "--port-file",
+      port_file.to_string_lossy().as_ref(),
+      "--parent-pid",
+      &std::process::id().to_string(),
+    ])
+    .args(app.state::<BackendArgs>().get())
+    .envs(backend_env(app)?)
+    .envs(outbound::backend_env(true))
+    .envs(certs::backend_env())
+    .envs(ratelimit::backend_env(app))
+    .envs(cache::backend_env(app))
+    .envs(resilience::backend_env(app))
+    .envs(dns::backend_env())
+    .envs(keychain::backend_env())
+    .envs(atrest::backend_env(app))
+    .envs(tmx::backend_env(app))
+    .envs(jobs::backend_env(app))
+    // kept out of backend_env so get_backend_env can't show it
+    .env(auth::ENV, auth::token())
+    .spawn()
+    .map_err(|e| match &e {
+      tauri_plugin_shell::Error::Io(io) => match quarantine::diagnose(&binary, io) {
+        Some(blocked) => quarantine::report(app, blocked),
+        None => boxed_err(format!("Failed to spawn backend: {e}")),
+      },
+      _ => boxed_err(format!("Failed to spawn backend: {e}")),
+    })?;
+  if let Err(e) = os::bind_to_app(child.pid()) {
+    // not fatal: the backend's own parent watchdog still covers us
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot bind backend to app lifetime: {e}").as_bytes(),
+    );
+  }
+
+  let exited = Arc::new(AtomicBool::new(false));
+  let heartbeat = Arc::new(Heartbeat::default());
+  watch_events(
+    app.clone(),
+    child.pid(),
+    exited.clone(),
+    heartbeat.clone(),
+    log_tag.to_string(),
+    rx,
+    Box::new(move |app: &AppHandle, pid, code| {
+      // scanners often delete the binary right after it was run
+      if let Err(e) = quarantine::check_present(&binary) {
+        let _ = app.emit("backend-blocked", e);
+      }
+      on_exit(app, pid, code);
+    }),
+  );
+  if heartbeat_secs > 0 {
+    heartbeat::spawn_watchdog(
+      app.clone(),
+      child.pid(),
+      exited.clone(),
+      heartbeat,
+      log_tag.to_string(),
+    );
+  }
+  Ok(Running { child, exited })
+}
+
+/// Spawns the backend, waits for the port-file/health handshake and
+/// publishes the base URL. The child is killed again if the handshake fails.
+pub fn launch(app: &AppHandle) -> Result<String, Box<dyn std::error::Error>> {
+  let result = launch_once(app);
+  if result.is_err() && !is_stopping(app) {
+    status::set(app, BackendStatus::Crashed { code: None });
+  }
+  result
+}
+
+fn launch_once(app: &AppHandle) -> Result<String, Box<dyn std::error::Error>> {
+  if let Some(url) = app.state::<StartupConfig>().backend_url.clone() {
+    return connect_external(app, &url);
+  }
+
+  let progress = Progress::new(app, true);
+  progress.stage("spawning");
+
+  let port_file = new_port_file(app)?;
+  let listen = listen_addr(app, 0)?;
+  let running = spawn_process(app, &listen, &port_file, "", Box::new(on_primary_exit))?;
+  let handshake = wait_for_backend(progress, &running, &port_file)
+    .and_then(|url| check_version(progress, &url).map(|_| url));
+
+  let proc = app.state::<BackendProcess>();
+  *proc.child.lock().unwrap() = Some(running);
+  *proc.port_file.lock().unwrap() = Some(port_file);
+
+  let base_url = match handshake {
+    Ok(url) => url,
+    Err(e) => {
+      if !e.is::<IncompatibleBackend>() {
+        diagnostics::collect_quietly(app, &format!("handshake failed: {e}"), None);
+      }
+      // don't leave a half-started backend behind
+      kill_child(app);
+      return Err(e);
+    }
+  };
+  if is_stopping(app) {
+    // app quit while we were waiting for the handshake
+    kill_child(app);
+    return Err(boxed_err("Backend launch aborted: app is shutting down"));
+  }
+  status::set(
+    app,
+    BackendStatus::Ready {
+      base_url: base_url.clone(),
+    },
+  );
+  priority::on_launch(app);
+
+  Ok(base_url)
+}
+
+/// Errors that relaunching the same binary won't fix.
+fn is_fatal(e: &(dyn std::error::Error + 'static)) -> bool {
+  e.is::<IncompatibleBackend>() || e.is::<TamperedBackend>() || e.is::<BackendBlocked>()
+}
+
+/// Like [`launch`], but retries failed handshakes per `launch_retries`.
+fn launch_with_retries(app: &AppHandle) -> Result<String, Box<dyn std::error::Error>> {
+  let retries = app.state::<StartupConfig>().launch_retries;
+  let mut attempt = 0;
+  loop {
+    match launch(app) {
+      Ok(url) => return Ok(url),
+      Err(e) if attempt >= retries || is_stopping(app) || is_fatal(e.as_ref()) => return Err(e),
+      Err(_) => attempt += 1,
+    }
+  }
+}
+
+/// Runs the first launch off the setup thread so the window shows up
+/// immediately; the frontend follows `backend-starting` / `backend-ready` /
+/// `backend-failed`.
+pub fn spawn_startup(app: AppHandle) {
+  std::thread::spawn(move || match launch_with_retries(&app) {
+    Ok(base_url) => {
+      let _ = app.emit("backend-ready", ReadyPayload { base_url });
+      pool::start(&app);
+    }
+    Err(e) => {
+      let error = e.to_string();
+      *app.state::<BackendProcess>().startup_error.lock().unwrap() = Some(error.clone());
+      let _ = app.emit("backend-failed", FailedPayload { error });
+    }
+  });
+}
+
+#[tauri::command]
+pub fn get_backend_startup_error(proc: State<BackendProcess>) -> Option<String> {
+  proc.startup_error.lock().unwrap().clone()
+}
+
+/// Asks the backend to exit via `/api/shutdown` (SIGTERM as a fallback on
+/// Unix), waits up to the grace period, then force-kills it. The backend
+/// writes to its db, so an abrupt kill is the last resort.
+pub fn terminate_child(running: Running, base_url: Option<&str>, grace: Duration) {
+  if running.exited.load(Ordering::SeqCst) {
+    return;
+  }
+
+  let requested = base_url
+    .map(|base| {
+      transport::post(&format!("{}/api/shutdown", base), Duration::from_secs(1))
+        .map(|r| r.is_success())
+        .unwrap_or(false)
+    })
+    .unwrap_or(false);
+
+  #[cfg(unix)]
+  if !requested {
+    unsafe {
+      libc::kill(running.child.pid() as libc::pid_t, libc::SIGTERM);
+    }
+  }
+  #[cfg(not(unix))]
+  let grace = if requested { grace } else { Duration::ZERO };
+
+  let start = Instant::now();
+  while start.elapsed() < grace {
+    if running.exited.load(Ordering::SeqCst) {
+      return;
+    }
+    std::thread::sleep(Duration::from_millis(50));
+  }
+
+  let _ = running.child.kill();
+}
+
+fn kill_child(app: &AppHandle) {
+  let proc = app.state::<BackendProcess>();
+  let running = proc.child.lock().unwrap().take();
+  if let Some(running) = running {
+    let base_url = app.state::<BackendState>().base_url();
+    let grace = app.state::<StartupConfig>().shutdown_grace();
+    terminate_child(running, base_url.as_deref(), grace);
+  }
+  if let Some(port_file) = proc.port_file.lock().unwrap().take() {
+    let _ = fs::remove_file(port_file);
+  }
+  status::set(app, BackendStatus::NotStarted);
+}
+
+pub fn current_port_file(app: &AppHandle) -> Option<PathBuf> {
+  app
+    .state::<BackendProcess>()
+    .port_file
+    .lock()
+    .unwrap()
+    .clone()
+}
+
+pub fn last_exit_code(app: &AppHandle) -> Option<i32> {
+  *app.state::<BackendProcess>().last_exit.lock().unwrap()
+}
+
+/// Pid of the backend process we spawned, if one is running.
+pub fn current_pid(app: &AppHandle) -> Option<u32> {
+  let proc = app.state::<BackendProcess>();
+  let guard = proc.child.lock().unwrap();
+  guard.as_ref().map(|r| r.pid())
+}
+
+/// Stops the backend (and pool workers) for good; the supervisor will not
+/// restart them.
+pub fn stop(app: &AppHandle) {
+  app
+    .state::<BackendProcess>()
+    .stopping
+    .store(true, Ordering::SeqCst);
+  pool::stop_all(app);
+  kill_child(app);
+}
+
+/// Last-resort teardown for [`crate::cleanup`]: kills the backend tree
+/// without the graceful shutdown request and removes its port file. Uses
+/// `try_lock` throughout since it may run while a lock is poisoned or held.
+pub fn emergency_stop(app: &AppHandle) {
+  let proc = app.state::<BackendProcess>();
+  proc.stopping.store(true, Ordering::SeqCst);
+  if let Ok(guard) = proc.child.try_lock() {
+    if let Some(running) = guard.as_ref() {
+      os::kill_tree(running.pid());
+    }
+  }
+  if let Ok(guard) = proc.port_file.try_lock() {
+    if let Some(port_file) = guard.as_ref() {
+      let _ = fs::remove_file(port_file);
+    }
+  }
+  pool::emergency_stop(app);
+}
+
+pub fn is_stopping(app: &AppHandle) -> bool {
+  app
+    .state::<BackendProcess>()
+    .stopping
+    .load(Ordering::SeqCst)
+}
+
+/// Drains the sidecar's lifecycle events. Output goes to the rotating
+/// backend log; `Terminated` is handed to `on_exit`.
+fn watch_events(
+  app: AppHandle,
+  pid: u32,
+  exited: Arc<AtomicBool>,
+  heartbeat: Arc<Heartbeat>,
+  log_tag: String,
+  mut rx: Receiver<CommandEvent>,
+  on_exit: ExitHandler,
+) {
+  let stdout = format!("{log_tag}stdout");
+  let stderr = format!("{log_tag}stderr");
+  std::thread::spawn(move || {
+    let mut on_exit = Some(on_exit);
+    while let Some(event) = rx.blocking_recv() {
+      match event {
+        CommandEvent::Stdout(line) if heartbeat.observe(&line) => {}
+        CommandEvent::Stdout(line) => app.state::<BackendLog>().append(&stdout, &line),
+        CommandEvent::Stderr(line) => app.state::<BackendLog>().append(&stderr, &line),
+        CommandEvent::Terminated(payload) => {
+          exited.store(true, Ordering::SeqCst);
+          if let Some(f) = on_exit.take() {
+            f(&app, pid, payload.code);
+          }
+        }
+        _ => {}
+      }
+    }
+  });
+}
+
+fn on_primary_exit(app: &AppHandle, pid: u32, exit_code: Option<i32>) {
+  // only react if this is still the current backend; a deliberate
+  // kill takes the child out of the state first
+  let proc = app.state::<BackendProcess>();
+  let current = {
+    let mut guard = proc.child.lock().unwrap();
+    if guard.as_ref().map(|r| r.pid()) == Some(pid) {
+      guard.take();
+      true
+    } else {
+      false
+    }
+  };
+  if current && !is_stopping(app) {
+    *proc.last_exit.lock().unwrap() = exit_code;
+    diagnostics::collect_quietly(app, "backend exited unexpectedly", exit_code);
+    status::set(app, BackendStatus::Crashed { code: exit_code });
+    supervise(app, exit_code);
+  }
+}
+
+/// Relaunches a crashed backend with exponential backoff until it comes
+/// back or the app shuts down.
+fn supervise(app: &AppHandle, exit_code: Option<i32>) {
+  let mut attempt = 0u32;
+  let mut backoff = Duration::from_secs(1);
+  loop {
+    attempt += 1;
+    let _ = app.emit(
+      "backend-status",
+      BackendEvent::Reconnecting { attempt, exit_code },
+    );
+    std::thread::sleep(backoff);
+    if is_stopping(app) {
+      return;
+    }
+
+    match launch(app) {
+      Ok(base_url) => {
+        let _ = app.emit("backend-status", BackendEvent::Connected { base_url });
+        return;
+      }
+      Err(e) => {
+        let _ = app.emit(
+          "backend-status",
+          BackendEvent::Failed {
+            attempt,
+            error: e.to_string(),
+          },
+        );
+        if is_fatal(e.as_ref()) {
+          return;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+      }
+    }
+  }
+}
+
+/// Stops the backend and its workers, runs `swap` while none of them is
+/// running, then brings everything back up. On a failed swap the old
+/// binary is relaunched and the swap error returned.
+pub fn replace(
+  app: &AppHandle,
+  swap: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+  let proc = app.state::<BackendProcess>();
+  let _guard = proc.restart.lock().unwrap();
+  if is_stopping(app) {
+    return Err(boxed_err("App is shutting down"));
+  }
+
+  pool::stop_all(app);
+  kill_child(app);
+  let swapped = swap();
+  let base_url = launch_with_retries(app)?;
+  let _ = app.emit(
+    "backend-status",
+    BackendEvent::Connected {
+      base_url: base_url.clone(),
+    },
+  );
+  pool::start(app);
+  swapped.map(|_| base_url)
+}
+
+/// Stops the current backend and launches a fresh one, e.g. after a hang.
+#[tauri::command]
+pub async fn restart_backend(app: AppHandle) -> Result<String, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let proc = app.state::<BackendProcess>();
+    let _guard = proc.restart.lock().unwrap();
+    if is_stopping(&app) {
+      return Err("App is shutting down".to_string());
+    }
+
+    kill_child(&app);
+    let base_url = launch_with_retries(&app).map_err(|e| e.to_string())?;
+    let _ = app.emit(
+      "backend-status",
+      BackendEvent::Connected {
+        base_url: base_url.clone(),
+      },
+    );
+    Ok(base_url)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
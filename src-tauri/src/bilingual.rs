@@ -0,0 +1,102 @@
+//! Bilingual tables of a task for reviewers who work in a spreadsheet: a
+//! row per segment with its source, translation and status. CSV, or TSV
+//! when the path ends in `.tsv`, quoted as RFC 4180 says and with CRLF
+//! line ends.
+//!
+//! Excel reads a CSV as UTF-8 only when it starts with a byte order mark,
+//! which other tools may show as a stray character, so the encoding is a
+//! choice: `utf-8-bom`, `utf-8` or `utf-16le` (with its mark, which Excel
+//! opens tab-separated files in correctly).
+
+use std::{fs, path::Path};
+use tauri::AppHandle;
+
+use crate::{proxy::ProxyError, segments};
+
+#[derive(Clone, Copy)]
+enum Encoding {
+  Utf8Bom,
+  Utf8,
+  Utf16Le,
+}
+
+/// `field` quoted if it needs to be; a leading `=`, `+`, `-` or `@` gets
+/// an apostrophe so a spreadsheet shows it instead of running a formula.
+fn field(field: &str, delimiter: char) -> String {
+  let field = if field.starts_with(['=', '+', '-', '@']) {
+    format!("'{field}")
+  } else {
+    field.to_string()
+  };
+  if field.contains([delimiter, '"', '\n', '\r']) || field != field.trim() {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field
+  }
+}
+
+fn table(app: &AppHandle, job_id: &str, delimiter: char) -> Result<String, ProxyError> {
+  let rows = segments::rows(app, job_id)?;
+  let direction = segments::info(app, job_id)?.direction;
+  let (source, target) = direction.split_once("->").unwrap_or(("source", "target"));
+  let mut out = String::new();
+  let mut line = |fields: [&str; 3]| {
+    let fields: Vec<String> = fields.iter().map(|f| field(f, delimiter)).collect();
+    out.push_str(&fields.join(&delimiter.to_string()));
+    out.push_str("\r\n");
+  };
+  line([
+    &format!("Source ({source})"),
+    &format!("Target ({target})"),
+    "Status",
+  ]);
+  for row in &rows {
+    line([
+      &row.source,
+      row.translation.as_deref().unwrap_or_default(),
+      &row.status,
+    ]);
+  }
+  Ok(out)
+}
+
+/// Writes task `job_id`, whatever its document type, to `path` as a
+/// bilingual table in `encoding` (`utf-8-bom`, `utf-8` or `utf-16le`).
+#[tauri::command]
+pub async fn export_bilingual_csv(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+  encoding: String,
+) -> Result<(), ProxyError> {
+  let encoding = match encoding.as_str() {
+    "utf-8-bom" => Encoding::Utf8Bom,
+    "utf-8" => Encoding::Utf8,
+    "utf-16le" => Encoding::Utf16Le,
+    other => {
+      return Err(ProxyError::new(
+        "invalid-request",
+        format!("Unsupported encoding {other}: use utf-8-bom, utf-8 or utf-16le"),
+      ))
+    }
+  };
+  let tsv = Path::new(&path)
+    .extension()
+    .is_some_and(|e| e.eq_ignore_ascii_case("tsv"));
+  tauri::async_runtime::spawn_blocking(move || {
+    let table = table(&app, &job_id, if tsv { '\t' } else { ',' })?;
+    let bytes = match encoding {
+      Encoding::Utf8Bom => ["\u{feff}", &table].concat().into_bytes(),
+      Encoding::Utf8 => table.into_bytes(),
+      Encoding::Utf16Le => "\u{feff}"
+        .encode_utf16()
+        .chain(table.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect(),
+    };
+    fs::write(&path, bytes)
+      .map_err(|e| ProxyError::new("invalid-request", format!("Cannot write {path}: {e}")))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,179 @@
+//! Settings bundles: one JSON file with the shareable settings and every
+//! profile (provider, model, direction, glossary, prompt template), so a
+//! team can hand out a standard configuration. Secrets (API keys, proxy
+//! and certificate passwords, the master password) and machine-specific
+//! values such as the last document folder are never included.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  profiles,
+  proxy::{invalid, ProxyError},
+  settings::{Settings, SettingsState},
+};
+
+const FORMAT: &str = "ai-doc-translator-settings";
+const VERSION: u32 = 1;
+/// Keys of [`Settings`] that travel in a bundle.
+const SHARED_SETTINGS: &[&str] = &["direction", "theme", "encrypt_at_rest"];
+/// Profile fields written to a bundle; ids and timestamps are per install.
+const PROFILE_FIELDS: &[&str] = &[
+  "name",
+  "base_url",
+  "model",
+  "direction",
+  "glossary",
+  "prompt_template",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+  format: String,
+  version: u32,
+  #[serde(default)]
+  settings: Map<String, Value>,
+  #[serde(default)]
+  profiles: Vec<Map<String, Value>>,
+  /// Name of the profile to activate on import.
+  #[serde(default)]
+  active_profile: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportSummary {
+  settings: Settings,
+  profiles_created: usize,
+  profiles_updated: usize,
+  active_profile: Option<String>,
+}
+
+fn pick(source: &Map<String, Value>, keys: &[&str]) -> Map<String, Value> {
+  keys
+    .iter()
+    .filter_map(|k| source.get(*k).map(|v| (k.to_string(), v.clone())))
+    .collect()
+}
+
+fn export(app: &AppHandle, path: &Path) -> Result<(), ProxyError> {
+  let settings =
+    serde_json::to_value(app.state::<SettingsState>().get()).map_err(|e| invalid(e.to_string()))?;
+  let listed = profiles::call(app, "GET", "/api/profiles", None)?;
+  let all = listed["profiles"].as_array().cloned().unwrap_or_default();
+  let active = listed["active"].as_str();
+  let active_profile = all
+    .iter()
+    .find(|p| p["id"].as_str().is_some_and(|id| Some(id) == active))
+    .and_then(|p| p["name"].as_str())
+    .map(str::to_string);
+  let bundle = Bundle {
+    format: FORMAT.to_string(),
+    version: VERSION,
+    settings: pick(settings.as_object().unwrap(), SHARED_SETTINGS),
+    profiles: all
+      .iter()
+      .filter_map(Value::as_object)
+      .map(|p| pick(p, PROFILE_FIELDS))
+      .collect(),
+    active_profile,
+  };
+  let json = serde_json::to_vec_pretty(&bundle).map_err(|e| invalid(e.to_string()))?;
+  fs::write(path, json).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))
+}
+
+fn import(app: &AppHandle, path: &Path) -> Result<ImportSummary, ProxyError> {
+  let bytes =
+    fs::read(path).map_err(|e| invalid(format!("Cannot read {}: {e}", path.display())))?;
+  let bundle: Bundle =
+    serde_json::from_slice(&bytes).map_err(|e| invalid(format!("Not a settings bundle: {e}")))?;
+  if bundle.format != FORMAT {
+    return Err(invalid("Not a settings bundle"));
+  }
+  if bundle.version > VERSION {
+    return Err(invalid(format!(
+      "The bundle is from a newer release (version {})",
+      bundle.version
+    )));
+  }
+
+  let settings = app
+    .state::<SettingsState>()
+    .update(app, Value::Object(pick(&bundle.settings, SHARED_SETTINGS)))
+    .map_err(invalid)?;
+
+  let listed = profiles::call(app, "GET", "/api/profiles", None)?;
+  let mut existing: Vec<(String, String)> = listed["profiles"]
+    .as_array()
+    .into_iter()
+    .flatten()
+    .filter_map(|p| {
+      Some((
+        p["name"].as_str()?.to_string(),
+        p["id"].as_str()?.to_string(),
+      ))
+    })
+    .collect();
+  let (mut created, mut updated) = (0, 0);
+  for profile in &bundle.profiles {
+    let fields = pick(profile, PROFILE_FIELDS);
+    let Some(name) = fields
+      .get("name")
+      .and_then(Value::as_str)
+      .map(str::to_string)
+    else {
+      return Err(invalid("A profile in the bundle has no name"));
+    };
+    match existing.iter().find(|(other, _)| other == &name) {
+      Some((_, id)) => {
+        profiles::call(
+          app,
+          "PATCH",
+          &format!("/api/profiles/{id}"),
+          Some(Value::Object(fields)),
+        )?;
+        updated += 1;
+      }
+      None => {
+        let saved = profiles::call(app, "POST", "/api/profiles", Some(Value::Object(fields)))?;
+        if let Some(id) = saved["id"].as_str() {
+          existing.push((name, id.to_string()));
+        }
+        created += 1;
+      }
+    }
+  }
+
+  if let Some(name) = &bundle.active_profile {
+    if let Some((_, id)) = existing.iter().find(|(other, _)| other == name) {
+      profiles::activate(app, Some(id))?;
+    }
+  }
+  Ok(ImportSummary {
+    settings,
+    profiles_created: created,
+    profiles_updated: updated,
+    active_profile: bundle.active_profile,
+  })
+}
+
+/// Writes the bundle to `path`.
+#[tauri::command]
+pub async fn export_settings_bundle(app: AppHandle, path: String) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Applies the bundle at `path`: its settings, and its profiles by name
+/// (existing ones are updated, others created; none are deleted).
+#[tauri::command]
+pub async fn import_settings_bundle(
+  app: AppHandle,
+  path: String,
+) -> Result<ImportSummary, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || import(&app, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
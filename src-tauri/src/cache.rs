@@ -0,0 +1,149 @@
+//! Content-addressed disk cache of provider translations in
+//! `<data dir>/translation-cache`. The backend reads and writes entries (one
+//! file per SHA-256 of source segment, direction, provider, model and
+//! prompt, see [`ENV`]) and touches them on hits; the shell owns the size
+//! limit and evicts least recently used entries.
+
+use serde::Serialize;
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+  time::{Duration, SystemTime},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{backend, config::StartupConfig};
+
+pub const ENV: &str = "MVP_TRANSLATION_CACHE_DIR";
+const EVICT_INTERVAL: Duration = Duration::from_secs(60);
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Serialize)]
+pub struct CacheStats {
+  pub entries: usize,
+  pub bytes: u64,
+  /// Size limit from `translation_cache_mb`; 0 when caching is off.
+  pub max_bytes: u64,
+}
+
+struct Entry {
+  path: PathBuf,
+  len: u64,
+  used: SystemTime,
+}
+
+fn dir() -> Result<&'static Path, String> {
+  DIR
+    .get()
+    .map(PathBuf::as_path)
+    .ok_or_else(|| "translation cache is not initialized".to_string())
+}
+
+fn max_bytes(app: &AppHandle) -> u64 {
+  app.state::<StartupConfig>().translation_cache_mb * 1024 * 1024
+}
+
+/// Entries are sharded by the first two hex digits of their key.
+fn entries(dir: &Path) -> Vec<Entry> {
+  let Ok(shards) = fs::read_dir(dir) else {
+    return Vec::new();
+  };
+  shards
+    .flatten()
+    .filter_map(|shard| fs::read_dir(shard.path()).ok())
+    .flat_map(|files| files.flatten())
+    .filter_map(|file| {
+      let meta = file.metadata().ok()?;
+      if !meta.is_file() || file.path().extension().is_some_and(|e| e == "tmp") {
+        return None;
+      }
+      Some(Entry {
+        path: file.path(),
+        len: meta.len(),
+        used: meta.modified().ok()?,
+      })
+    })
+    .collect()
+}
+
+/// Drops least recently used entries until the cache fits in `limit`.
+fn evict(dir: &Path, limit: u64) -> usize {
+  let mut entries = entries(dir);
+  let mut total: u64 = entries.iter().map(|e| e.len).sum();
+  entries.sort_by_key(|e| e.used);
+  let mut removed = 0;
+  for entry in entries {
+    if total <= limit {
+      break;
+    }
+    if fs::remove_file(&entry.path).is_ok() {
+      total -= entry.len;
+      removed += 1;
+    }
+  }
+  removed
+}
+
+fn stats(app: &AppHandle) -> Result<CacheStats, String> {
+  let entries = entries(dir()?);
+  Ok(CacheStats {
+    entries: entries.len(),
+    bytes: entries.iter().map(|e| e.len).sum(),
+    max_bytes: max_bytes(app),
+  })
+}
+
+/// Sets up `<data dir>/translation-cache`; called once at startup.
+pub fn init(data_dir: &Path) {
+  let dir = data_dir.join("translation-cache");
+  let _ = fs::create_dir_all(&dir);
+  let _ = DIR.set(dir);
+}
+
+/// `MVP_TRANSLATION_CACHE_DIR` for backends, unless caching is off.
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let mut env = BTreeMap::new();
+  if let (Ok(dir), true) = (dir(), max_bytes(app) > 0) {
+    env.insert(ENV.to_string(), dir.display().to_string());
+  }
+  env
+}
+
+/// Keeps the cache within `translation_cache_mb` for the whole session.
+pub fn spawn_evictor(app: AppHandle) {
+  let limit = max_bytes(&app);
+  let Ok(dir) = dir() else {
+    return;
+  };
+  if limit == 0 {
+    return;
+  }
+  std::thread::spawn(move || {
+    while !backend::is_stopping(&app) {
+      evict(dir, limit);
+      std::thread::sleep(EVICT_INTERVAL);
+    }
+  });
+}
+
+#[tauri::command]
+pub async fn get_translation_cache_size(app: AppHandle) -> Result<CacheStats, String> {
+  tauri::async_runtime::spawn_blocking(move || stats(&app))
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Removes every cached translation; the next run asks the provider again.
+#[tauri::command]
+pub async fn clear_translation_cache(app: AppHandle) -> Result<CacheStats, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let dir = dir()?;
+    evict(dir, 0);
+    stats(&app)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
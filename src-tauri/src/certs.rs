@@ -0,0 +1,164 @@
+//! Extra root CAs (corporate TLS interception) trusted next to the system
+//! store. Stored one PEM per certificate in `<data dir>/certs`; the
+//! backend gets them all in one bundle via [`ENV`].
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Mutex, OnceLock},
+};
+
+pub const ENV: &str = "MVP_EXTRA_CA_FILE";
+const BUNDLE: &str = "extra-ca-bundle.pem";
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Parsed copies of the stored certificates, for reqwest.
+static ROOTS: Mutex<Vec<reqwest::Certificate>> = Mutex::new(Vec::new());
+
+#[derive(Serialize)]
+pub struct CaCertificate {
+  /// SHA-256 of the PEM body; also the file name.
+  pub id: String,
+  pub pem: String,
+}
+
+fn dir() -> Result<&'static Path, String> {
+  DIR
+    .get()
+    .map(PathBuf::as_path)
+    .ok_or_else(|| "certificate store is not initialized".to_string())
+}
+
+/// Splits `text` into its `CERTIFICATE` blocks.
+fn pem_blocks(text: &str) -> Vec<String> {
+  const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+  const END: &str = "-----END CERTIFICATE-----";
+  let mut blocks = Vec::new();
+  let mut rest = text;
+  while let Some(start) = rest.find(BEGIN) {
+    let Some(len) = rest[start..].find(END) else {
+      break;
+    };
+    let end = start + len + END.len();
+    blocks.push(format!("{}\n", rest[start..end].trim()));
+    rest = &rest[end..];
+  }
+  blocks
+}
+
+fn fingerprint(pem: &str) -> String {
+  let body: String = pem
+    .lines()
+    .filter(|l| !l.starts_with("-----"))
+    .flat_map(|l| l.trim().chars())
+    .collect();
+  Sha256::digest(body.as_bytes())
+    .iter()
+    .map(|b| format!("{b:02x}"))
+    .collect()
+}
+
+fn stored(dir: &Path) -> BTreeMap<String, String> {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return BTreeMap::new();
+  };
+  entries
+    .flatten()
+    .filter_map(|e| {
+      let name = e.file_name().to_string_lossy().into_owned();
+      let id = name.strip_suffix(".pem")?.to_string();
+      if name == BUNDLE {
+        return None;
+      }
+      Some((id, fs::read_to_string(e.path()).ok()?))
+    })
+    .collect()
+}
+
+/// Re-parses the store and rewrites the backend bundle.
+fn reload(dir: &Path) {
+  let certs = stored(dir);
+  *ROOTS.lock().unwrap() = certs
+    .values()
+    .filter_map(|pem| reqwest::Certificate::from_pem(pem.as_bytes()).ok())
+    .collect();
+  let bundle = dir.join(BUNDLE);
+  if certs.is_empty() {
+    let _ = fs::remove_file(bundle);
+  } else {
+    let _ = fs::write(bundle, certs.into_values().collect::<String>());
+  }
+}
+
+/// Loads `<data dir>/certs`; called once at startup.
+pub fn init(data_dir: &Path) {
+  let dir = data_dir.join("certs");
+  reload(&dir);
+  let _ = DIR.set(dir);
+}
+
+/// The extra roots, for [`reqwest::blocking::ClientBuilder::add_root_certificate`].
+pub fn roots() -> Vec<reqwest::Certificate> {
+  ROOTS.lock().unwrap().clone()
+}
+
+/// `MVP_EXTRA_CA_FILE` for backends when extra roots are configured.
+pub fn backend_env() -> BTreeMap<String, String> {
+  let mut env = BTreeMap::new();
+  if let Ok(dir) = dir() {
+    let bundle = dir.join(BUNDLE);
+    if bundle.is_file() {
+      env.insert(ENV.to_string(), bundle.display().to_string());
+    }
+  }
+  env
+}
+
+#[tauri::command]
+pub fn list_ca_certificates() -> Result<Vec<CaCertificate>, String> {
+  Ok(
+    stored(dir()?)
+      .into_iter()
+      .map(|(id, pem)| CaCertificate { id, pem })
+      .collect(),
+  )
+}
+
+/// Adds every certificate in `pem` (one or a bundle) to the store. The
+/// shell trusts them at once; backends from their next (re)start.
+#[tauri::command]
+pub fn add_ca_certificate(pem: String) -> Result<Vec<String>, String> {
+  let dir = dir()?;
+  let blocks = pem_blocks(&pem);
+  if blocks.is_empty() {
+    return Err("No PEM certificate found (expected -----BEGIN CERTIFICATE-----)".to_string());
+  }
+  for block in &blocks {
+    reqwest::Certificate::from_pem(block.as_bytes())
+      .map_err(|e| format!("Invalid certificate: {e}"))?;
+  }
+  fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+  let mut ids = Vec::new();
+  for block in blocks {
+    let id = fingerprint(&block);
+    fs::write(dir.join(format!("{id}.pem")), block)
+      .map_err(|e| format!("Cannot save certificate: {e}"))?;
+    ids.push(id);
+  }
+  reload(dir);
+  Ok(ids)
+}
+
+#[tauri::command]
+pub fn remove_ca_certificate(id: String) -> Result<(), String> {
+  let dir = dir()?;
+  if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(format!("Unknown certificate: {id}"));
+  }
+  fs::remove_file(dir.join(format!("{id}.pem"))).map_err(|e| e.to_string())?;
+  reload(dir);
+  Ok(())
+}
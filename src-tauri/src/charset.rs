@@ -0,0 +1,260 @@
+//! Character sets of plain-text inputs (`.txt` and subtitles). A file with
+//! a byte order mark, or that is valid UTF-8, is read as such; any other is
+//! guessed by `chardetng`, which tells GBK, Big5, Shift-JIS, Windows-1251
+//! and the like apart. A wrong guess can be overridden per file.
+//!
+//! Overrides are keyed by the content's SHA-256, so an export (which gets
+//! the source back from the backend, not from disk) reads it the same way,
+//! and kept in `<data dir>/text-encodings.json` across restarts.
+//!
+//! Files too large to hold are read through a [`Reader`], which decodes
+//! them a buffer at a time the same way.
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+  cell::Cell,
+  collections::HashMap,
+  fs::{self, File},
+  io::{self, Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+  rc::Rc,
+  sync::{Mutex, OnceLock},
+};
+
+use crate::{integrity, proxy::ProxyError};
+
+/// Bytes the detector looks at; enough for any real text file to tell.
+const SAMPLE: usize = 1 << 20;
+const PREVIEW_CHARS: usize = 400;
+/// What a [`Reader`] reads from the file at a time.
+const READ_BUFFER: usize = 64 * 1024;
+
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+/// SHA-256 (hex) to encoding name.
+static OVERRIDES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+pub struct Decoded {
+  pub text: String,
+  pub encoding: &'static Encoding,
+  pub bom: bool,
+}
+
+#[derive(Serialize)]
+pub struct TextEncoding {
+  /// What the file is read as, e.g. `GBK`, `UTF-8`.
+  pub encoding: String,
+  /// Set with [`set_text_encoding`] rather than detected.
+  pub overridden: bool,
+  /// The start of the file as read, to see whether it is mojibake.
+  pub preview: String,
+}
+
+/// Loads the overrides; called once at startup.
+pub fn init(data_dir: &Path) {
+  let file = data_dir.join("text-encodings.json");
+  let saved = fs::read(&file)
+    .ok()
+    .and_then(|data| serde_json::from_slice(&data).ok())
+    .unwrap_or_default();
+  *OVERRIDES.lock().unwrap_or_else(|e| e.into_inner()) = Some(saved);
+  let _ = FILE.set(file);
+}
+
+fn key(data: &[u8]) -> String {
+  Sha256::digest(data)
+    .iter()
+    .map(|b| format!("{b:02x}"))
+    .collect()
+}
+
+fn override_for(key: &str) -> Option<&'static Encoding> {
+  let overrides = OVERRIDES.lock().unwrap_or_else(|e| e.into_inner());
+  let name = overrides.as_ref()?.get(key)?;
+  Encoding::for_label(name.as_bytes())
+}
+
+fn overridden(data: &[u8]) -> Option<&'static Encoding> {
+  override_for(&key(data))
+}
+
+/// Like [`guess`] from the start of a file only; `whole` if that is all
+/// of it.
+fn guess_sample(sample: &[u8], whole: bool) -> &'static Encoding {
+  match std::str::from_utf8(sample) {
+    Ok(_) => return UTF_8,
+    // a character cut off at the end of the sample
+    Err(e) if !whole && e.error_len().is_none() => return UTF_8,
+    Err(_) => {}
+  }
+  let mut detector = EncodingDetector::new();
+  detector.feed(sample, whole);
+  detector.guess(None, true)
+}
+
+/// What `data` looks like without a byte order mark.
+pub fn guess(data: &[u8]) -> &'static Encoding {
+  if std::str::from_utf8(data).is_ok() {
+    return UTF_8;
+  }
+  let mut detector = EncodingDetector::new();
+  let sample = &data[..data.len().min(SAMPLE)];
+  detector.feed(sample, sample.len() == data.len());
+  detector.guess(None, true)
+}
+
+/// `data` as text: in its override's encoding, else by its byte order mark,
+/// else as guessed. Undecodable bytes become U+FFFD.
+pub fn decode(data: &[u8]) -> Decoded {
+  let bom = Encoding::for_bom(data);
+  let encoding = overridden(data)
+    .or(bom.map(|(encoding, _)| encoding))
+    .unwrap_or_else(|| guess(data));
+  let (text, _) = match bom {
+    Some((bom_encoding, len)) if bom_encoding == encoding => {
+      encoding.decode_without_bom_handling(&data[len..])
+    }
+    _ => encoding.decode_without_bom_handling(data),
+  };
+  Decoded {
+    text: text.into_owned(),
+    encoding,
+    bom: bom.is_some(),
+  }
+}
+
+/// A text file as UTF-8, decoded as [`decode`] would but a buffer at a
+/// time; the byte order mark is left out.
+pub struct Reader {
+  file: File,
+  decoder: encoding_rs::Decoder,
+  raw: Vec<u8>,
+  out: Vec<u8>,
+  at: usize,
+  done: bool,
+  read: Rc<Cell<u64>>,
+}
+
+impl Reader {
+  pub fn open(path: &Path) -> io::Result<Reader> {
+    // overrides are by the hash of all of it
+    let key = integrity::sha256_file(path)?;
+    let mut file = File::open(path)?;
+    let mut sample = Vec::with_capacity(SAMPLE);
+    file.by_ref().take(SAMPLE as u64).read_to_end(&mut sample)?;
+    let whole = file.read(&mut [0])? == 0;
+
+    let bom = Encoding::for_bom(&sample);
+    let encoding = override_for(&key)
+      .or(bom.map(|(encoding, _)| encoding))
+      .unwrap_or_else(|| guess_sample(&sample, whole));
+    let skip = match bom {
+      Some((bom_encoding, len)) if bom_encoding == encoding => len as u64,
+      _ => 0,
+    };
+    file.seek(SeekFrom::Start(skip))?;
+    Ok(Reader {
+      file,
+      decoder: encoding.new_decoder_without_bom_handling(),
+      raw: vec![0; READ_BUFFER],
+      out: Vec::new(),
+      at: 0,
+      done: false,
+      read: Rc::new(Cell::new(skip)),
+    })
+  }
+
+  /// Bytes of the file read so far; shared, so it can be watched while
+  /// the reader is borrowed by a parser.
+  pub fn progress(&self) -> Rc<Cell<u64>> {
+    self.read.clone()
+  }
+}
+
+impl Read for Reader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.at == self.out.len() {
+      if self.done {
+        return Ok(0);
+      }
+      let n = self.file.read(&mut self.raw)?;
+      self.read.set(self.read.get() + n as u64);
+      let last = n == 0;
+      let room = self.decoder.max_utf8_buffer_length(n).unwrap_or(n * 3 + 16);
+      self.out.resize(room, 0);
+      let (_, _, written, _) = self
+        .decoder
+        .decode_to_utf8(&self.raw[..n], &mut self.out, last);
+      self.out.truncate(written);
+      self.at = 0;
+      self.done = last;
+    }
+    let n = buf.len().min(self.out.len() - self.at);
+    buf[..n].copy_from_slice(&self.out[self.at..self.at + n]);
+    self.at += n;
+    Ok(n)
+  }
+}
+
+fn describe(data: &[u8]) -> TextEncoding {
+  let overridden = overridden(data).is_some();
+  let decoded = decode(data);
+  TextEncoding {
+    encoding: decoded.encoding.name().to_string(),
+    overridden,
+    preview: decoded.text.chars().take(PREVIEW_CHARS).collect(),
+  }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, ProxyError> {
+  fs::read(path).map_err(|e| ProxyError::new("invalid-request", format!("Cannot read {path}: {e}")))
+}
+
+/// How the text file at `path` is read.
+#[tauri::command]
+pub async fn get_text_encoding(path: String) -> Result<TextEncoding, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || Ok(describe(&read(&path)?)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Reads the text file at `path` as `encoding` (a WHATWG label such as
+/// `gbk`, `big5`, `shift_jis`, `windows-1251`) from now on, or as detected
+/// again when `None`; returns how it reads now.
+#[tauri::command]
+pub async fn set_text_encoding(
+  path: String,
+  encoding: Option<String>,
+) -> Result<TextEncoding, ProxyError> {
+  let chosen = match encoding.as_deref().map(str::trim).filter(|e| !e.is_empty()) {
+    Some(label) => Some(
+      Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| ProxyError::new("invalid-request", format!("Unknown encoding {label}")))?,
+    ),
+    None => None,
+  };
+  tauri::async_runtime::spawn_blocking(move || {
+    let data = read(&path)?;
+    let mut overrides = OVERRIDES.lock().unwrap_or_else(|e| e.into_inner());
+    let map = overrides.get_or_insert_with(HashMap::new);
+    match chosen {
+      Some(encoding) => map.insert(key(&data), encoding.name().to_string()),
+      None => map.remove(&key(&data)),
+    };
+    if let Some(file) = FILE.get() {
+      let json = serde_json::to_vec_pretty(&*map).unwrap_or_default();
+      fs::write(file, json).map_err(|e| {
+        ProxyError::new(
+          "invalid-request",
+          format!("Cannot save {}: {e}", file.display()),
+        )
+      })?;
+    }
+    drop(overrides);
+    Ok(describe(&data))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,106 @@
+//! Best-effort teardown when the shell dies abnormally, and the
+//! `--cleanup` mode that reaps backends left behind by earlier crashes.
+
+use std::{ffi::OsStr, sync::OnceLock};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use tauri::{AppHandle, Manager};
+
+use crate::{backend, logs::BackendLog, os};
+
+/// Command-line flag that runs [`kill_orphans`] instead of the app.
+pub const FLAG: &str = "--cleanup";
+
+const BACKEND_NAME: &str = "mvp_backend";
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+/// Kills the backend and workers, removes their port files and syncs the
+/// log. Never blocks on a lock, so it is safe from a panic hook.
+fn emergency_cleanup(reason: &str) {
+  let Some(app) = APP.get() else {
+    return;
+  };
+  backend::emergency_stop(app);
+  if let Some(log) = app.try_state::<BackendLog>() {
+    log.append("shell", format!("emergency cleanup: {reason}").as_bytes());
+    log.flush();
+  }
+}
+
+/// Installs a panic hook (chained before the default one) and, on Unix,
+/// SIGTERM/SIGINT/SIGHUP handlers that clean up and exit. On Windows the
+/// kill-on-close job object already reaps the backend, and the panic hook
+/// covers the port file and log.
+pub fn install(app: &AppHandle) {
+  if APP.set(app.clone()).is_err() {
+    return;
+  }
+  let previous = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    emergency_cleanup(&format!("shell panicked: {info}"));
+    previous(info);
+  }));
+
+  #[cfg(unix)]
+  {
+    use signal_hook::{
+      consts::{SIGHUP, SIGINT, SIGTERM},
+      iterator::Signals,
+    };
+    match Signals::new([SIGTERM, SIGINT, SIGHUP]) {
+      Ok(mut signals) => {
+        std::thread::spawn(move || {
+          if let Some(sig) = signals.forever().next() {
+            emergency_cleanup(&format!("received signal {sig}"));
+            std::process::exit(128 + sig);
+          }
+        });
+      }
+      Err(e) => app.state::<BackendLog>().append(
+        "shell",
+        format!("cannot install signal handlers: {e}").as_bytes(),
+      ),
+    }
+  }
+}
+
+/// The `--parent-pid` a backend was started with.
+fn parent_pid_arg(cmd: &[std::ffi::OsString]) -> Option<u32> {
+  let i = cmd.iter().position(|a| a == OsStr::new("--parent-pid"))?;
+  cmd.get(i + 1)?.to_str()?.parse().ok()
+}
+
+/// Kills `mvp_backend` processes whose shell (`--parent-pid`) is gone and
+/// returns how many trees were killed. Backends started by hand, without
+/// `--parent-pid`, are left alone.
+pub fn kill_orphans() -> usize {
+  let mut sys = System::new();
+  sys.refresh_processes_specifics(
+    ProcessesToUpdate::All,
+    true,
+    ProcessRefreshKind::nothing().with_cmd(UpdateKind::Always),
+  );
+  let mut killed = 0;
+  for (pid, proc) in sys.processes() {
+    let name = proc.name().to_string_lossy();
+    if !name.starts_with(BACKEND_NAME) {
+      continue;
+    }
+    // the PyInstaller child goes down with its bootloader's tree
+    let parent_is_backend = proc
+      .parent()
+      .and_then(|p| sys.process(p))
+      .is_some_and(|p| p.name().to_string_lossy().starts_with(BACKEND_NAME));
+    if parent_is_backend {
+      continue;
+    }
+    match parent_pid_arg(proc.cmd()) {
+      Some(parent) if !os::pid_alive(parent) => {
+        os::kill_tree(pid.as_u32());
+        killed += 1;
+      }
+      _ => {}
+    }
+  }
+  killed
+}
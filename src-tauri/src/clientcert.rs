@@ -0,0 +1,135 @@
+//! Client certificate for remote backends (`backend_url`) that require
+//! mutual TLS. Presented by every reqwest client `transport` builds for a
+//! non-loopback backend, i.e. health checks, proxied and streamed requests.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::{fs, sync::Mutex};
+use tauri::AppHandle;
+
+use crate::{config, paths};
+
+const MASK: &str = "********";
+
+static IDENTITY: Mutex<Option<reqwest::Identity>> = Mutex::new(None);
+static CURRENT: Mutex<Option<ClientCertConfig>> = Mutex::new(None);
+
+/// `client_cert` in `startup.json`. An empty `path` means none.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientCertConfig {
+  /// A PKCS#12 bundle (`.p12`/`.pfx`), or a PEM file with the certificate
+  /// chain and, unless `key_path` is set, the private key.
+  pub path: String,
+  /// PEM private key, when it is kept apart from the certificate.
+  pub key_path: String,
+  /// Password of a PKCS#12 bundle. Encrypted PEM keys are not supported.
+  pub password: String,
+}
+
+impl ClientCertConfig {
+  pub fn redacted(&self) -> Self {
+    let mut cfg = self.clone();
+    if !cfg.password.is_empty() {
+      cfg.password = MASK.to_string();
+    }
+    cfg
+  }
+
+  fn is_pkcs12(&self) -> bool {
+    let path = self.path.to_ascii_lowercase();
+    path.ends_with(".p12") || path.ends_with(".pfx")
+  }
+
+  /// Reads and parses the configured files; `None` when none are set.
+  pub fn load(&self) -> Result<Option<reqwest::Identity>, String> {
+    if self.path.trim().is_empty() {
+      return Ok(None);
+    }
+    let read = |path: &str| fs::read(path.trim()).map_err(|e| format!("Cannot read {path}: {e}"));
+    let mut pem = if self.is_pkcs12() {
+      pkcs12_to_pem(&read(&self.path)?, &self.password)?
+    } else {
+      read(&self.path)?
+    };
+    if !self.key_path.trim().is_empty() {
+      pem.push(b'\n');
+      pem.extend(read(&self.key_path)?);
+    }
+    reqwest::Identity::from_pem(&pem)
+      .map(Some)
+      .map_err(|e| format!("Invalid client certificate: {e}"))
+  }
+}
+
+fn pem_block(label: &str, der: &[u8]) -> String {
+  let b64 = base64::engine::general_purpose::STANDARD.encode(der);
+  let lines: Vec<&str> = b64
+    .as_bytes()
+    .chunks(64)
+    .map(|l| std::str::from_utf8(l).unwrap_or_default())
+    .collect();
+  format!(
+    "-----BEGIN {label}-----\n{}\n-----END {label}-----\n",
+    lines.join("\n")
+  )
+}
+
+/// rustls only takes PEM identities, so the bundle is converted.
+fn pkcs12_to_pem(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+  let store = p12_keystore::KeyStore::from_pkcs12(data, password)
+    .map_err(|e| format!("Cannot open PKCS#12 bundle (wrong password?): {e}"))?;
+  let (_, chain) = store
+    .private_key_chain()
+    .ok_or("PKCS#12 bundle holds no private key")?;
+  let mut pem: String = chain
+    .chain()
+    .iter()
+    .map(|cert| pem_block("CERTIFICATE", cert.as_der()))
+    .collect();
+  pem.push_str(&pem_block("PRIVATE KEY", chain.key()));
+  Ok(pem.into_bytes())
+}
+
+/// Makes `cfg` the active client certificate; called at startup and on
+/// changes.
+pub fn init(cfg: ClientCertConfig) -> Result<(), String> {
+  let identity = cfg.load();
+  *CURRENT.lock().unwrap() = Some(cfg);
+  *IDENTITY.lock().unwrap() = identity?;
+  Ok(())
+}
+
+pub fn identity() -> Option<reqwest::Identity> {
+  IDENTITY.lock().unwrap().clone()
+}
+
+fn current() -> ClientCertConfig {
+  CURRENT.lock().unwrap().clone().unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_client_certificate() -> ClientCertConfig {
+  current().redacted()
+}
+
+/// Checks, saves and activates the client certificate; an empty `path`
+/// removes it. Sending the masked password back keeps the stored one.
+#[tauri::command]
+pub fn set_client_certificate(
+  app: AppHandle,
+  mut config: ClientCertConfig,
+) -> Result<ClientCertConfig, String> {
+  config.path = config.path.trim().to_string();
+  config.key_path = config.key_path.trim().to_string();
+  if config.password == MASK {
+    config.password = current().password;
+  }
+  config.load()?;
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "client_cert", value)
+    .map_err(|e| format!("Cannot save client certificate settings: {e}"))?;
+  init(config.clone())?;
+  Ok(config.redacted())
+}
@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+use tauri::State;
+
+use crate::{
+  applock::AppLockConfig, backend, clientcert::ClientCertConfig, dns::DnsConfig,
+  outbound::ProxyConfig, providers::ProviderConfig, ratelimit::RateLimitConfig,
+  resilience::RetryConfig, usage::UsageConfig,
+};
+
+/// Backend startup/shutdown knobs. Layered, later wins: defaults,
+/// `config.toml` (pre-provisioned by IT; never written by the app),
+/// `startup.json` in the app data dir (what the UI saves), `AIDT_*` and
+/// then `MVP_*` environment variables.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupConfig {
+  /// Total time allowed for the port-file + health handshake.
+  pub timeout_secs: u64,
+  /// Extra launch attempts after a failed handshake.
+  pub launch_retries: u32,
+  pub port_file_poll_ms: u64,
+  pub health_poll_ms: u64,
+  pub health_probe_timeout_ms: u64,
+  /// Readiness/health endpoint.
+  pub health_path: String,
+  /// Failed health probes after which a plain TCP connect counts as ready,
+  /// for backend builds without the health endpoint. 0 disables this.
+  pub tcp_fallback_after: u32,
+  /// How long the backend may take to exit after `/api/shutdown` before
+  /// it is force-killed.
+  pub shutdown_grace_ms: u64,
+  /// Interval of the backend's stdout heartbeat; 0 disables the watchdog.
+  pub heartbeat_secs: u64,
+  /// Missing heartbeats for this long means the backend is wedged.
+  pub heartbeat_timeout_secs: u64,
+  /// A translation task without progress for this long counts as a stuck
+  /// worker. 0 disables the check.
+  pub worker_stall_secs: u64,
+  /// Number of backend processes to run; requests are spread across them.
+  pub backend_workers: u32,
+  /// Fixed port for the primary backend; workers use the ports after it.
+  /// 0 picks a free port on each launch.
+  pub backend_port: u16,
+  /// `"tcp"` or `"uds"` (Unix domain socket; falls back to TCP where
+  /// unsupported).
+  pub transport: String,
+  /// Run backends at below-normal CPU / low I/O priority.
+  pub low_priority: bool,
+  /// Confine the backend to the data dir: it runs from there, and restricts
+  /// its own file access (Low integrity on Windows, no new privileges or
+  /// capabilities on Linux). Documents reach it as uploads.
+  pub sandbox: bool,
+  /// Extra directories a sandboxed backend may use.
+  pub sandbox_allow: Vec<String>,
+  /// Use an already running backend (dev hot-reload, central deployments)
+  /// instead of spawning the sidecar.
+  pub backend_url: Option<String>,
+  /// Extra environment for backend processes (proxies, provider keys, log
+  /// level). `MVP_BACKEND_ENV_<NAME>` variables are passed on as `<NAME>`.
+  pub backend_env: BTreeMap<String, String>,
+  /// Extra backend flags, see `set_backend_args`.
+  pub backend_args: Vec<String>,
+  /// Proxy for provider and update traffic, see `set_proxy_config`.
+  pub proxy: ProxyConfig,
+  /// Internet connectivity check interval; 0 disables the monitor.
+  pub network_poll_secs: u64,
+  /// URLs probed for connectivity; any HTTP answer from one of them
+  /// counts as online.
+  pub network_probe_urls: Vec<String>,
+  /// Provider throttling, see `set_rate_limits`.
+  pub rate_limits: RateLimitConfig,
+  /// Size limit of the translation cache; 0 turns caching off.
+  pub translation_cache_mb: u64,
+  /// Prices and monthly budget, see `set_usage_config`.
+  pub usage: UsageConfig,
+  /// Backoff and circuit breaker for provider calls.
+  pub provider_retry: RetryConfig,
+  /// Documents larger than this are uploaded in chunks of this size.
+  pub upload_chunk_mb: u64,
+  /// Plain text, CSV and subtitle files from this size on are segmented
+  /// while they are read, their segments sent up in batches.
+  pub stream_segment_mb: u64,
+  /// Client certificate for a mutual-TLS `backend_url`, see
+  /// `set_client_certificate`.
+  pub client_cert: ClientCertConfig,
+  /// Backend binary to run instead of the bundled sidecar;
+  /// `MVP_BACKEND_PATH` takes precedence.
+  pub backend_path: Option<String>,
+  /// Where `backend.log` goes; defaults to `logs` in the data dir.
+  pub log_dir: Option<String>,
+  /// Size at which the log is rotated.
+  pub log_max_mb: u64,
+  /// Translation direction new installs start with, e.g. `zh->en`; users
+  /// change theirs in the settings.
+  pub default_direction: String,
+  /// Host overrides and DNS-over-HTTPS, see `set_dns_config`.
+  pub dns: DnsConfig,
+  /// Providers with an API key in the OS credential store (the keys
+  /// themselves are never written here), see `set_api_key`.
+  pub api_key_providers: Vec<String>,
+  /// Master password hash and idle timeout, see `set_master_password`.
+  pub app_lock: AppLockConfig,
+  /// Configured translation providers, see `save_provider`.
+  pub providers: Vec<ProviderConfig>,
+  /// Largest document accepted by drag and drop.
+  pub max_document_mb: u64,
+  /// TrueType font (`.ttf`/`.ttc`) for translated PDFs, tried before the
+  /// system's CJK fonts.
+  pub pdf_font: Option<PathBuf>,
+  /// Tesseract binary for OCR, instead of the bundled one or the one on
+  /// `PATH`; `MVP_TESSERACT_PATH` takes precedence.
+  pub tesseract_path: Option<PathBuf>,
+  /// Jobs from the queue (see `enqueue_job`) translated at the same time.
+  pub job_workers: u32,
+}
+
+impl Default for StartupConfig {
+  fn default() -> Self {
+    Self {
+      timeout_secs: 25,
+      launch_retries: 0,
+      port_file_poll_ms: 80,
+      health_poll_ms: 100,
+      health_probe_timeout_ms: 800,
+      health_path: "/api/health".to_string(),
+      tcp_fallback_after: 5,
+      shutdown_grace_ms: 5000,
+      heartbeat_secs: 2,
+      heartbeat_timeout_secs: 20,
+      worker_stall_secs: 900,
+      backend_workers: 1,
+      backend_port: 0,
+      transport: "tcp".to_string(),
+      low_priority: false,
+      sandbox: false,
+      sandbox_allow: Vec::new(),
+      backend_url: None,
+      backend_env: BTreeMap::new(),
+      backend_args: Vec::new(),
+      proxy: ProxyConfig::default(),
+      network_poll_secs: 15,
+      network_probe_urls: vec![
+        "https://integrate.api.nvidia.com/".to_string(),
+        "https://api.openai.com/".to_string(),
+      ],
+      rate_limits: RateLimitConfig::default(),
+      translation_cache_mb: 256,
+      usage: UsageConfig::default(),
+      provider_retry: RetryConfig::default(),
+      upload_chunk_mb: 8,
+      stream_segment_mb: 64,
+      client_cert: ClientCertConfig::default(),
+      backend_path: None,
+      log_dir: None,
+      log_max_mb: 5,
+      default_direction: "en->zh".to_string(),
+      dns: DnsConfig::default(),
+      api_key_providers: Vec::new(),
+      app_lock: AppLockConfig::default(),
+      providers: Vec::new(),
+      max_document_mb: 100,
+      pdf_font: None,
+      tesseract_path: None,
+      job_workers: 2,
+    }
+  }
+}
+
+fn env_override<T: std::str::FromStr>(name: &str, value: &mut T) {
+  if let Some(v) = std::env::var(name).ok().and_then(|s| s.trim().parse().ok()) {
+    *value = v;
+  }
+}
+
+const CONFIG_FILE: &str = "config.toml";
+const CONFIG_ARG: &str = "--config";
+const ENV_PREFIX: &str = "AIDT_";
+
+/// `--config <path>` (or `--config=<path>`), else `config.toml` in the data
+/// dir.
+fn config_file(data_dir: &Path) -> PathBuf {
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    if arg == CONFIG_ARG {
+      if let Some(path) = args.next() {
+        return PathBuf::from(path);
+      }
+    } else if let Some(path) = arg.strip_prefix("--config=") {
+      return PathBuf::from(path);
+    }
+  }
+  data_dir.join(CONFIG_FILE)
+}
+
+/// RFC 7386 merge patch: objects merge, `null` resets a key, anything else
+/// replaces it.
+pub fn merge(target: &mut Value, patch: Value) {
+  match patch {
+    Value::Object(patch) => {
+      if !target.is_object() {
+        *target = Value::Object(Default::default());
+      }
+      let target = target.as_object_mut().unwrap();
+      for (key, value) in patch {
+        if value.is_null() {
+          target.remove(&key);
+        } else {
+          merge(target.entry(key).or_insert(Value::Null), value);
+        }
+      }
+    }
+    patch => *target = patch,
+  }
+}
+
+/// `AIDT_<KEY>` sets `<key>`, with `__` between nesting levels:
+/// `AIDT_PROXY__URL` is `proxy.url`. Values go in as strings where the key
+/// holds a string, else parsed as JSON (numbers, booleans, lists) when
+/// they can be.
+fn apply_env_overrides(doc: &mut Value) {
+  for (name, raw) in std::env::vars() {
+    let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+      continue;
+    };
+    let path: Vec<String> = key.split("__").map(str::to_ascii_lowercase).collect();
+    if path.iter().any(String::is_empty) {
+      continue;
+    }
+    let pointer = format!("/{}", path.join("/"));
+    let value = match doc.pointer(&pointer) {
+      Some(Value::String(_)) => Value::String(raw),
+      _ => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+    };
+    let patch = path
+      .iter()
+      .rev()
+      .fold(value, |v, k| serde_json::json!({ k: v }));
+    merge(doc, patch);
+  }
+}
+
+fn read_layer(path: &Path, toml: bool, warnings: &mut Vec<String>) -> Option<Value> {
+  let text = fs::read_to_string(path).ok()?;
+  let parsed = if toml {
+    toml::from_str::<toml::Value>(&text)
+      .map_err(|e| e.to_string())
+      .and_then(|v| serde_json::to_value(v).map_err(|e| e.to_string()))
+  } else {
+    serde_json::from_str::<Value>(&text).map_err(|e| e.to_string())
+  };
+  match parsed {
+    Ok(v) if v.is_object() => Some(v),
+    Ok(_) => None,
+    Err(e) => {
+      warnings.push(format!("ignoring {}: {e}", path.display()));
+      None
+    }
+  }
+}
+
+impl StartupConfig {
+  /// The layered config (see the type docs), plus warnings about layers
+  /// that could not be used.
+  pub fn load(data_dir: &Path) -> (Self, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut doc = serde_json::to_value(StartupConfig::default()).unwrap_or_default();
+    for (path, toml) in [
+      (config_file(data_dir), true),
+      (data_dir.join("startup.json"), false),
+    ] {
+      if let Some(layer) = read_layer(&path, toml, &mut warnings) {
+        merge(&mut doc, layer);
+      }
+    }
+    apply_env_overrides(&mut doc);
+    let mut cfg: StartupConfig = serde_json::from_value(doc).unwrap_or_else(|e| {
+      warnings.push(format!("invalid configuration, using defaults: {e}"));
+      StartupConfig::default()
+    });
+
+    env_override("MVP_STARTUP_TIMEOUT_SECS", &mut cfg.timeout_secs);
+    env_override("MVP_STARTUP_RETRIES", &mut cfg.launch_retries);
+    env_override("MVP_STARTUP_PORT_FILE_POLL_MS", &mut cfg.port_file_poll_ms);
+    env_override("MVP_STARTUP_HEALTH_POLL_MS", &mut cfg.health_poll_ms);
+    env_override(
+      "MVP_STARTUP_HEALTH_PROBE_TIMEOUT_MS",
+      &mut cfg.health_probe_timeout_ms,
+    );
+    if let Ok(path) = std::env::var("MVP_STARTUP_HEALTH_PATH") {
+      cfg.health_path = path;
+    }
+    cfg.health_path = format!("/{}", cfg.health_path.trim().trim_start_matches('/'));
+    env_override(
+      "MVP_STARTUP_TCP_FALLBACK_AFTER",
+      &mut cfg.tcp_fallback_after,
+    );
+    env_override("MVP_SHUTDOWN_GRACE_MS", &mut cfg.shutdown_grace_ms);
+    env_override("MVP_HEARTBEAT_SECS", &mut cfg.heartbeat_secs);
+    env_override(
+      "MVP_HEARTBEAT_TIMEOUT_SECS",
+      &mut cfg.heartbeat_timeout_secs,
+    );
+    env_override("MVP_WORKER_STALL_SECS", &mut cfg.worker_stall_secs);
+    env_override("MVP_BACKEND_WORKERS", &mut cfg.backend_workers);
+    env_override("MVP_BACKEND_PORT", &mut cfg.backend_port);
+    env_override("MVP_BACKEND_TRANSPORT", &mut cfg.transport);
+    env_override("MVP_BACKEND_LOW_PRIORITY", &mut cfg.low_priority);
+    env_override("MVP_BACKEND_SANDBOX", &mut cfg.sandbox);
+    if let Ok(url) = std::env::var("MVP_BACKEND_URL") {
+      cfg.backend_url = Some(url);
+    }
+    cfg.backend_url = cfg
+      .backend_url
+      .map(|u| u.trim().trim_end_matches('/').to_string())
+      .filter(|u| !u.is_empty());
+    env_override("MVP_NETWORK_POLL_SECS", &mut cfg.network_poll_secs);
+    env_override(
+      "MVP_MAX_CONCURRENT_REQUESTS",
+      &mut cfg.rate_limits.max_concurrent,
+    );
+    env_override("MVP_TRANSLATION_CACHE_MB", &mut cfg.translation_cache_mb);
+    env_override("MVP_STREAM_SEGMENT_MB", &mut cfg.stream_segment_mb);
+    env_override("MVP_JOB_WORKERS", &mut cfg.job_workers);
+    env_override(
+      "MVP_PROVIDER_MAX_RETRIES",
+      &mut cfg.provider_retry.max_retries,
+    );
+    env_override("MVP_DOH_URL", &mut cfg.dns.doh_url);
+    if let Ok(url) = std::env::var("MVP_PROXY_URL") {
+      cfg.proxy.mode = "manual".to_string();
+      cfg.proxy.url = url;
+    }
+    for (key, value) in std::env::vars() {
+      if let Some(name) = key.strip_prefix("MVP_BACKEND_ENV_") {
+        if !name.is_empty() {
+          cfg.backend_env.insert(name.to_string(), value);
+        }
+      }
+    }
+    (cfg, warnings)
+  }
+
+  pub fn timeout(&self) -> Duration {
+    Duration::from_secs(self.timeout_secs)
+  }
+
+  pub fn port_file_poll(&self) -> Duration {
+    Duration::from_millis(self.port_file_poll_ms)
+  }
+
+  pub fn health_poll(&self) -> Duration {
+    Duration::from_millis(self.health_poll_ms)
+  }
+
+  pub fn health_probe_timeout(&self) -> Duration {
+    Duration::from_millis(self.health_probe_timeout_ms)
+  }
+
+  pub fn shutdown_grace(&self) -> Duration {
+    Duration::from_millis(self.shutdown_grace_ms)
+  }
+}
+
+/// Rewrites one top-level key of `startup.json`, leaving the rest (and
+/// unknown keys) as they are.
+pub fn persist_key(
+  data_dir: &Path,
+  key: &str,
+  value: Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let path = data_dir.join("startup.json");
+  let mut cfg: Value = fs::read_to_string(&path)
+    .ok()
+    .and_then(|s| serde_json::from_str(&s).ok())
+    .filter(Value::is_object)
+    .unwrap_or_else(|| Value::Object(Default::default()));
+  cfg[key] = value;
+  fs::create_dir_all(data_dir)?;
+  fs::write(path, serde_json::to_string_pretty(&cfg)?)?;
+  Ok(())
+}
+
+#[tauri::command]
+pub fn get_startup_config(cfg: State<StartupConfig>) -> StartupConfig {
+  let mut cfg = cfg.inner().clone();
+  cfg.proxy = cfg.proxy.redacted();
+  cfg.client_cert = cfg.client_cert.redacted();
+  if cfg.app_lock.password_hash.is_some() {
+    cfg.app_lock.password_hash = Some("********".to_string());
+  }
+  // provider keys from `MVP_BACKEND_ENV_*`
+  backend::mask_secrets(&mut cfg.backend_env);
+  cfg
+}
@@ -0,0 +1,212 @@
+//! CSV and TSV tables read and rebuilt in the shell. Every cell with words
+//! is a segment; numbers, dates, codes and the cells around them are
+//! written back as they were, quoting included, and translated cells are
+//! quoted as RFC 4180 says where they need it. The delimiter is whichever
+//! of `,`, `;` and tab the first record has most of outside quotes, since
+//! spreadsheets in some locales write `;`.
+//!
+//! The file is read in its encoding (see `charset`) and written as UTF-8,
+//! with a byte order mark when the source had one or was in another
+//! encoding, which is also what Excel needs to open it as UTF-8.
+//!
+//! Locators are `csv:<record>:<field>`, both counted from 0. Large files
+//! are segmented a record at a time by [`stream`], to the same locators.
+
+use encoding_rs::UTF_8;
+use std::{
+  fs,
+  io::{self, BufRead},
+  ops::Range,
+  path::Path,
+};
+use tauri::AppHandle;
+
+use crate::{
+  charset,
+  proxy::ProxyError,
+  segments::{self, Segment},
+};
+
+const DELIMITERS: [char; 3] = [',', ';', '\t'];
+
+struct Field {
+  /// Where the field is in its record, quotes included.
+  span: Range<usize>,
+  value: String,
+}
+
+/// Reads the next record into `record`, line end included; a quoted field
+/// may span lines. `false` at the end.
+fn next_record(reader: &mut dyn BufRead, record: &mut String) -> io::Result<bool> {
+  record.clear();
+  loop {
+    if reader.read_line(record)? == 0 {
+      return Ok(!record.is_empty());
+    }
+    if record.matches('"').count().is_multiple_of(2) {
+      return Ok(true);
+    }
+  }
+}
+
+/// The delimiter of a table whose first record is `record`.
+fn delimiter(record: &str) -> char {
+  let mut quoted = false;
+  let mut counts = [0usize; DELIMITERS.len()];
+  for c in record.chars() {
+    if c == '"' {
+      quoted = !quoted;
+    } else if let Some(i) = DELIMITERS.iter().position(|&d| d == c).filter(|_| !quoted) {
+      counts[i] += 1;
+    }
+  }
+  // the first of the most common, so `,` when there are none
+  let most = counts.iter().copied().max().unwrap_or(0);
+  DELIMITERS[counts.iter().position(|&n| n == most).unwrap_or(0)]
+}
+
+fn fields(record: &str, delimiter: char) -> Vec<Field> {
+  let body = record.trim_end_matches(['\n', '\r']);
+  let mut out = Vec::new();
+  let mut value = String::new();
+  let mut start = 0;
+  let mut quoted = false;
+  let mut chars = body.char_indices().peekable();
+  while let Some((i, c)) = chars.next() {
+    if quoted {
+      if c != '"' {
+        value.push(c);
+      } else if chars.next_if(|&(_, next)| next == '"').is_some() {
+        value.push('"');
+      } else {
+        quoted = false;
+      }
+    } else if c == delimiter {
+      out.push(Field {
+        span: start..i,
+        value: std::mem::take(&mut value),
+      });
+      start = i + c.len_utf8();
+    } else if c == '"' && i == start {
+      quoted = true;
+    } else {
+      value.push(c);
+    }
+  }
+  out.push(Field {
+    span: start..body.len(),
+    value,
+  });
+  out
+}
+
+/// `text` as a field, quoted if it needs to be.
+fn quote(text: &str, delimiter: char) -> String {
+  if text.contains([delimiter, '"', '\n', '\r']) || text != text.trim() {
+    format!("\"{}\"", text.replace('"', "\"\""))
+  } else {
+    text.to_string()
+  }
+}
+
+/// [`segments`] of the table `reader` gives, handed to `emit` a record at a
+/// time; only the record being read is held.
+pub fn stream(
+  reader: &mut dyn BufRead,
+  emit: &mut dyn FnMut(Segment) -> Result<(), String>,
+) -> Result<(), String> {
+  let mut record = String::new();
+  let mut separator = None;
+  let mut index = 0;
+  while next_record(reader, &mut record).map_err(|e| format!("Cannot read the table: {e}"))? {
+    let separator = *separator.get_or_insert_with(|| delimiter(&record));
+    for (column, field) in fields(&record, separator).into_iter().enumerate() {
+      if field.value.chars().any(char::is_alphabetic) {
+        emit(Segment {
+          locator: format!("csv:{index}:{column}"),
+          text: field.value,
+        })?;
+      }
+    }
+    index += 1;
+  }
+  Ok(())
+}
+
+/// The values of every record of the table in `text`, e.g. a glossary.
+pub fn records(text: &str) -> Vec<Vec<String>> {
+  let mut reader = text.as_bytes();
+  let mut record = String::new();
+  let mut separator = None;
+  let mut out = Vec::new();
+  while next_record(&mut reader, &mut record).unwrap_or(false) {
+    let separator = *separator.get_or_insert_with(|| delimiter(&record));
+    out.push(
+      fields(&record, separator)
+        .into_iter()
+        .map(|f| f.value)
+        .collect(),
+    );
+  }
+  out
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let text = charset::decode(&data).text;
+  let mut out = Vec::new();
+  stream(&mut text.as_bytes(), &mut |segment| {
+    out.push(segment);
+    Ok(())
+  })?;
+  Ok(out)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let decoded = charset::decode(&task.source);
+  let mut reader = decoded.text.as_bytes();
+
+  let mut out = String::with_capacity(decoded.text.len());
+  if decoded.bom || decoded.encoding != UTF_8 {
+    out.push('\u{feff}');
+  }
+  let mut record = String::new();
+  let mut separator = None;
+  let mut index = 0;
+  // reading from memory cannot fail
+  while next_record(&mut reader, &mut record).unwrap_or(false) {
+    let separator = *separator.get_or_insert_with(|| delimiter(&record));
+    let mut at = 0;
+    for (column, field) in fields(&record, separator).iter().enumerate() {
+      let locator = format!("csv:{index}:{column}");
+      let Some(translation) = translations.get(locator.as_str()) else {
+        continue;
+      };
+      out.push_str(&record[at..field.span.start]);
+      out.push_str(&quote(translation.trim(), separator));
+      at = field.span.end;
+    }
+    out.push_str(&record[at..]);
+    index += 1;
+  }
+  fs::write(path, out).map_err(|e| {
+    ProxyError::new(
+      "invalid-request",
+      format!("Cannot write {}: {e}", path.display()),
+    )
+  })
+}
+
+/// Writes the translated copy of CSV or TSV task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_csv(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
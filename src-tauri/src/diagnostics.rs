@@ -0,0 +1,161 @@
+use serde::Serialize;
+use std::{
+  fs::{self, File},
+  io::Write,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use sysinfo::System;
+use tauri::{AppHandle, Manager};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+  backend, config::StartupConfig, logs::BackendLog, paths, settings::SettingsState,
+  status::BackendState,
+};
+
+const LOG_LINES: usize = 2000;
+const KEEP_BUNDLES: usize = 10;
+
+#[derive(Serialize)]
+struct Summary {
+  reason: String,
+  exit_code: Option<i32>,
+  created_at: String,
+  app_version: String,
+  os: String,
+  os_version: Option<String>,
+  kernel_version: Option<String>,
+  arch: &'static str,
+  portable: bool,
+  base_url: Option<String>,
+  port_file: Option<String>,
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC.
+fn timestamp() -> String {
+  let secs = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+  // civil-from-days (Howard Hinnant)
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  format!(
+    "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+    rem / 3600,
+    rem / 60 % 60,
+    rem % 60
+  )
+}
+
+/// Startup config as written to the bundle, with secret-looking
+/// `backend_env` values masked.
+fn redacted_config(app: &AppHandle) -> StartupConfig {
+  let mut cfg = app.state::<StartupConfig>().inner().clone();
+  for (name, value) in cfg.backend_env.iter_mut() {
+    if backend::looks_secret(name) {
+      *value = "********".to_string();
+    }
+  }
+  cfg.proxy = cfg.proxy.redacted();
+  cfg.client_cert = cfg.client_cert.redacted();
+  cfg
+}
+
+fn prune(dir: &Path) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  let mut bundles: Vec<PathBuf> = entries
+    .flatten()
+    .map(|e| e.path())
+    .filter(|p| p.extension().is_some_and(|e| e == "zip"))
+    .collect();
+  // names sort by time
+  bundles.sort();
+  let excess = bundles.len().saturating_sub(KEEP_BUNDLES);
+  for old in &bundles[..excess] {
+    let _ = fs::remove_file(old);
+  }
+}
+
+/// Writes `diagnostics/diagnostics-<timestamp>.zip` into the data dir with
+/// the port file, recent backend output, exit code, OS info and app
+/// version. Only the newest bundles are kept.
+pub fn collect(
+  app: &AppHandle,
+  reason: &str,
+  exit_code: Option<i32>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+  let dir = paths::data_dir(app)?.join("diagnostics");
+  fs::create_dir_all(&dir)?;
+
+  let port_file = backend::current_port_file(app).and_then(|p| fs::read_to_string(p).ok());
+  let summary = Summary {
+    reason: reason.to_string(),
+    exit_code,
+    created_at: timestamp(),
+    app_version: app.package_info().version.to_string(),
+    os: std::env::consts::OS.to_string(),
+    os_version: System::long_os_version(),
+    kernel_version: System::kernel_version(),
+    arch: std::env::consts::ARCH,
+    portable: paths::is_portable(),
+    base_url: app.state::<BackendState>().base_url(),
+    port_file,
+  };
+
+  let path = dir.join(format!("diagnostics-{}.zip", summary.created_at));
+  let mut zip = ZipWriter::new(File::create(&path)?);
+  let opts = SimpleFileOptions::default();
+  zip.start_file("summary.json", opts)?;
+  zip.write_all(&serde_json::to_vec_pretty(&summary)?)?;
+  zip.start_file("startup.json", opts)?;
+  zip.write_all(&serde_json::to_vec_pretty(&redacted_config(app))?)?;
+  zip.start_file("settings.json", opts)?;
+  zip.write_all(&serde_json::to_vec_pretty(
+    &app.state::<SettingsState>().get(),
+  )?)?;
+  zip.start_file("backend.log", opts)?;
+  for line in app.state::<BackendLog>().tail(LOG_LINES) {
+    zip.write_all(line.as_bytes())?;
+    zip.write_all(b"\n")?;
+  }
+  zip.finish()?;
+
+  prune(&dir);
+  Ok(path)
+}
+
+/// Like [`collect`], but only logs a failure; used on the crash paths.
+pub fn collect_quietly(app: &AppHandle, reason: &str, exit_code: Option<i32>) {
+  if let Err(e) = collect(app, reason, exit_code) {
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot write diagnostics bundle: {e}").as_bytes(),
+    );
+  }
+}
+
+/// Collects a bundle on demand so users can attach it to bug reports.
+/// Returns the path of the zip.
+#[tauri::command]
+pub async fn create_diagnostics_bundle(app: AppHandle) -> Result<String, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let exit_code = backend::last_exit_code(&app);
+    collect(&app, "requested by user", exit_code)
+      .map(|p| p.to_string_lossy().into_owned())
+      .map_err(|e| e.to_string())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,164 @@
+//! Native pickers for documents to translate and for where results go.
+//! Both start in the folder used last time (kept in the settings) and
+//! return what the UI needs to show and validate a choice, not bare paths.
+
+use serde::Serialize;
+use std::{
+  fs::{self, File},
+  path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::{
+  formats::{self, FORMATS},
+  settings::SettingsState,
+};
+
+#[derive(Clone, Serialize)]
+pub struct PickedDocument {
+  pub path: String,
+  pub name: String,
+  pub size: u64,
+  /// Lower-case, without the dot; empty if none.
+  pub extension: String,
+  /// From the content: `docx`, `pptx`, `xlsx`, `odt`, `ods`, `odp`, `epub`, `pdf`, `image`, `srt`, `vtt`,
+  /// `markdown`, `html`, `latex`, `rtf`, `po`, `xliff`, `json`, `yaml`,
+  /// `strings`, `text` or `unknown`. May disagree with `extension` for misnamed files.
+  pub detected_type: String,
+  /// The extension the document is read as, which is `extension` unless
+  /// the content says otherwise; empty if no parser fits it.
+  pub read_as: String,
+  pub translatable: bool,
+}
+
+#[derive(Serialize)]
+pub struct PickedDir {
+  path: String,
+  writable: bool,
+}
+
+pub fn describe(path: &Path) -> Option<PickedDocument> {
+  let size = fs::metadata(path).ok()?.len();
+  let extension = formats::extension(path);
+  let sniffed = formats::sniff(path)?;
+  let read_as = formats::read_as(&extension, &sniffed);
+  let translatable = formats::format(&read_as).is_some_and(|f| f.translatable);
+  Some(PickedDocument {
+    path: path.display().to_string(),
+    name: path
+      .file_name()
+      .map(|n| n.to_string_lossy().into_owned())
+      .unwrap_or_default(),
+    size,
+    extension,
+    detected_type: sniffed.kind.to_string(),
+    read_as,
+    translatable,
+  })
+}
+
+fn writable(dir: &Path) -> bool {
+  let probe = dir.join(".aidt-write-test");
+  let ok = File::create(&probe).is_ok();
+  let _ = fs::remove_file(&probe);
+  ok
+}
+
+fn remember(app: &AppHandle, key: &str, dir: &Path) {
+  let _ = app
+    .state::<SettingsState>()
+    .update(app, serde_json::json!({ key: dir.display().to_string() }));
+}
+
+/// Asks for one or more documents; empty if cancelled.
+#[tauri::command]
+pub async fn pick_documents(app: AppHandle) -> Vec<PickedDocument> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let all: Vec<&str> = FORMATS.iter().flat_map(|f| f.extensions).copied().collect();
+    let mut dialog = app
+      .dialog()
+      .file()
+      .set_title("Documents to translate")
+      .add_filter("Supported documents", &all);
+    for format in FORMATS {
+      dialog = dialog.add_filter(format.name, format.extensions);
+    }
+    if let Some(dir) = app.state::<SettingsState>().get().last_document_dir {
+      dialog = dialog.set_directory(dir);
+    }
+    let paths: Vec<PathBuf> = dialog
+      .blocking_pick_files()
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|p| p.into_path().ok())
+      .collect();
+    if let Some(dir) = paths.first().and_then(|p| p.parent()) {
+      remember(&app, "last_document_dir", dir);
+    }
+    paths.iter().filter_map(|p| describe(p)).collect()
+  })
+  .await
+  .unwrap_or_default()
+}
+
+/// Asks for a ZIP archive to translate entries of; `None` if cancelled.
+#[tauri::command]
+pub async fn pick_archive(app: AppHandle) -> Option<String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let mut dialog = app
+      .dialog()
+      .file()
+      .set_title("Archive to translate")
+      .add_filter("ZIP archive", &["zip"]);
+    if let Some(dir) = app.state::<SettingsState>().get().last_document_dir {
+      dialog = dialog.set_directory(dir);
+    }
+    let path = dialog.blocking_pick_file()?.into_path().ok()?;
+    if let Some(dir) = path.parent() {
+      remember(&app, "last_document_dir", dir);
+    }
+    Some(path.display().to_string())
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+/// Asks for a folder to translate the documents in; `None` if cancelled.
+#[tauri::command]
+pub async fn pick_folder(app: AppHandle) -> Option<String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let mut dialog = app.dialog().file().set_title("Folder to translate");
+    if let Some(dir) = app.state::<SettingsState>().get().last_document_dir {
+      dialog = dialog.set_directory(dir);
+    }
+    let dir = dialog.blocking_pick_folder()?.into_path().ok()?;
+    remember(&app, "last_document_dir", &dir);
+    Some(dir.display().to_string())
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+/// Asks for the folder translated documents are written to; `None` if
+/// cancelled.
+#[tauri::command]
+pub async fn pick_output_dir(app: AppHandle) -> Option<PickedDir> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let mut dialog = app.dialog().file().set_title("Save translations to");
+    if let Some(dir) = app.state::<SettingsState>().get().last_output_dir {
+      dialog = dialog.set_directory(dir);
+    }
+    let dir = dialog.blocking_pick_folder()?.into_path().ok()?;
+    remember(&app, "last_output_dir", &dir);
+    Some(PickedDir {
+      path: dir.display().to_string(),
+      writable: writable(&dir),
+    })
+  })
+  .await
+  .ok()
+  .flatten()
+}
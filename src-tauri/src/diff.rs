@@ -0,0 +1,266 @@
+//! `diff_translations`: what changed between two translations of the same
+//! document, e.g. before and after the prompt was edited or the provider
+//! switched. Segments are aligned by locator and source text, as a line
+//! diff aligns lines, so a document segmented a little differently still
+//! lines up; those of one task only are `added` or `removed`. A `changed`
+//! segment comes with a word diff of its two translations.
+//!
+//! Both diffs are Myers' (shortest edit script) over a bounded number of
+//! edits. Translations that differ in more words than that are diffed as
+//! wholes; tasks whose sources differ in more segments are not diffed.
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{pdftext::is_cjk, proxy::ProxyError, segments};
+
+/// Segments the sources of two tasks may differ in.
+const MAX_SEGMENT_EDITS: usize = 1000;
+/// Words two translations of a segment are diffed by at most.
+const MAX_WORD_EDITS: usize = 500;
+
+#[derive(Debug, PartialEq)]
+enum Edit {
+  /// Indexes into the first and second sequence.
+  Same(usize, usize),
+  Removed(usize),
+  Added(usize),
+}
+
+/// The edits turning `a` into `b`, in order, or `None` if it takes more
+/// than `max`.
+fn edits<T: PartialEq>(a: &[T], b: &[T], max: usize) -> Option<Vec<Edit>> {
+  let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+  let suffix = a[prefix..]
+    .iter()
+    .rev()
+    .zip(b[prefix..].iter().rev())
+    .take_while(|(x, y)| x == y)
+    .count();
+  let (n, m) = (a.len() - prefix - suffix, b.len() - prefix - suffix);
+  let (inner_a, inner_b) = (&a[prefix..prefix + n], &b[prefix..prefix + m]);
+
+  let mut out: Vec<Edit> = (0..prefix).map(|i| Edit::Same(i, i)).collect();
+  let mut middle = Vec::new();
+  if n + m > 0 {
+    let max = max.min(n + m);
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // v around the diagonals reachable, before each round
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = None;
+    'rounds: for d in 0..=max as isize {
+      trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+      for k in (-d..=d).step_by(2) {
+        let at = |k: isize| v[(k + offset) as usize];
+        let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+          at(k + 1)
+        } else {
+          at(k - 1) + 1
+        };
+        let mut y = x - k;
+        while x < n as isize && y < m as isize && inner_a[x as usize] == inner_b[y as usize] {
+          x += 1;
+          y += 1;
+        }
+        v[(k + offset) as usize] = x;
+        if x >= n as isize && y >= m as isize {
+          found = Some(d);
+          break 'rounds;
+        }
+      }
+    }
+    let mut d = found?;
+    let (mut x, mut y) = (n as isize, m as isize);
+    loop {
+      let before = &trace[d as usize];
+      let at = |k: isize| before[(k + d + 1) as usize];
+      let k = x - y;
+      let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+        k + 1
+      } else {
+        k - 1
+      };
+      let prev_x = at(prev_k);
+      let prev_y = prev_x - prev_k;
+      while x > prev_x && y > prev_y {
+        x -= 1;
+        y -= 1;
+        middle.push(Edit::Same(prefix + x as usize, prefix + y as usize));
+      }
+      if d == 0 {
+        break;
+      }
+      if x == prev_x {
+        middle.push(Edit::Added(prefix + prev_y as usize));
+      } else {
+        middle.push(Edit::Removed(prefix + prev_x as usize));
+      }
+      (x, y) = (prev_x, prev_y);
+      d -= 1;
+    }
+  }
+  out.extend(middle.into_iter().rev());
+  out.extend((0..suffix).map(|i| Edit::Same(prefix + n + i, prefix + m + i)));
+  Some(out)
+}
+
+/// Words, runs of spaces, and single CJK characters and punctuation.
+fn tokens(text: &str) -> Vec<&str> {
+  let mut out = Vec::new();
+  let mut start = 0;
+  let class = |c: char| {
+    if c.is_whitespace() {
+      1
+    } else if c.is_alphanumeric() && !is_cjk(c) {
+      2
+    } else {
+      0
+    }
+  };
+  let mut prev = None;
+  for (i, c) in text.char_indices() {
+    let this = class(c);
+    if i > start && (this == 0 || prev != Some(this)) {
+      out.push(&text[start..i]);
+      start = i;
+    }
+    prev = Some(this);
+  }
+  if start < text.len() {
+    out.push(&text[start..]);
+  }
+  out
+}
+
+#[derive(Serialize)]
+pub struct Piece {
+  /// `same`, `removed` (only in the first translation) or `added`.
+  pub op: &'static str,
+  pub text: String,
+}
+
+fn pieces(a: &str, b: &str) -> Vec<Piece> {
+  let (ta, tb) = (tokens(a), tokens(b));
+  let Some(edits) = edits(&ta, &tb, MAX_WORD_EDITS) else {
+    return vec![
+      Piece {
+        op: "removed",
+        text: a.to_string(),
+      },
+      Piece {
+        op: "added",
+        text: b.to_string(),
+      },
+    ];
+  };
+  let mut out: Vec<Piece> = Vec::new();
+  for edit in edits {
+    let (op, text) = match edit {
+      Edit::Same(i, _) => ("same", ta[i]),
+      Edit::Removed(i) => ("removed", ta[i]),
+      Edit::Added(j) => ("added", tb[j]),
+    };
+    match out.last_mut() {
+      Some(last) if last.op == op => last.text.push_str(text),
+      _ => out.push(Piece {
+        op,
+        text: text.to_string(),
+      }),
+    }
+  }
+  out
+}
+
+#[derive(Serialize)]
+pub struct SegmentDiff {
+  /// `changed`, `added` (only in the second task) or `removed`.
+  pub change: &'static str,
+  pub locator: Option<String>,
+  pub source: String,
+  /// The translation in each task; `None` where it has none (yet).
+  pub a: Option<String>,
+  pub b: Option<String>,
+  /// For `changed` segments, from `a` to `b`.
+  pub pieces: Vec<Piece>,
+}
+
+#[derive(Serialize)]
+pub struct TranslationDiff {
+  pub unchanged: usize,
+  pub changed: usize,
+  pub added: usize,
+  pub removed: usize,
+  /// The segments that differ, in document order.
+  pub segments: Vec<SegmentDiff>,
+}
+
+fn diff(app: &AppHandle, job_a: &str, job_b: &str) -> Result<TranslationDiff, ProxyError> {
+  let rows_a = segments::rows(app, job_a)?;
+  let rows_b = segments::rows(app, job_b)?;
+  let key = |row: &segments::Row| (row.locator.clone(), row.source.trim().to_string());
+  let keys_a: Vec<_> = rows_a.iter().map(key).collect();
+  let keys_b: Vec<_> = rows_b.iter().map(key).collect();
+  let edits = edits(&keys_a, &keys_b, MAX_SEGMENT_EDITS).ok_or_else(|| {
+    ProxyError::new(
+      "invalid-request",
+      format!(
+        "The tasks do not look like translations of the same document: over {MAX_SEGMENT_EDITS} segments differ"
+      ),
+    )
+  })?;
+  let mut out = TranslationDiff {
+    unchanged: 0,
+    changed: 0,
+    added: 0,
+    removed: 0,
+    segments: Vec::new(),
+  };
+  for edit in edits {
+    let (change, row, a, b) = match edit {
+      Edit::Same(i, j) => {
+        let (a, b) = (&rows_a[i].translation, &rows_b[j].translation);
+        if a.as_deref().map(str::trim) == b.as_deref().map(str::trim) {
+          out.unchanged += 1;
+          continue;
+        }
+        out.changed += 1;
+        ("changed", &rows_a[i], a.clone(), b.clone())
+      }
+      Edit::Removed(i) => {
+        out.removed += 1;
+        ("removed", &rows_a[i], rows_a[i].translation.clone(), None)
+      }
+      Edit::Added(j) => {
+        out.added += 1;
+        ("added", &rows_b[j], None, rows_b[j].translation.clone())
+      }
+    };
+    let pieces = match (change, &a, &b) {
+      ("changed", Some(a), Some(b)) => pieces(a, b),
+      _ => Vec::new(),
+    };
+    out.segments.push(SegmentDiff {
+      change,
+      locator: row.locator.clone(),
+      source: row.source.clone(),
+      a,
+      b,
+      pieces,
+    });
+  }
+  Ok(out)
+}
+
+/// What changed from translation task `job_a` to `job_b` of the same
+/// document.
+#[tauri::command]
+pub async fn diff_translations(
+  app: AppHandle,
+  job_a: String,
+  job_b: String,
+) -> Result<TranslationDiff, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || diff(&app, &job_a, &job_b))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
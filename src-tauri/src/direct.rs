@@ -0,0 +1,289 @@
+//! Translating without the backend: texts (e.g. from the clipboard) and
+//! small documents sent straight to a configured provider through its
+//! `Translator` (see `providers`), for when the backend is missing or
+//! still starting. Documents are segmented as an upload would be, and the
+//! translated copy is written under the output policy like an export.
+//!
+//! Nothing is kept of these translations: no task, translation memory,
+//! cache or usage, and the glossary and profiles, which the backend
+//! applies, are left out.
+
+use std::{fs, path::Path, time::Duration};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  docx, formats, ocr,
+  output::{self, Exported},
+  providers::{self, Translator},
+  proxy::{invalid, ProxyError},
+  ratelimit::RateLimiter,
+  segmentation,
+  segments::{self, Local, Row, TaskInfo},
+  settings::SettingsState,
+};
+
+/// Larger documents are translated as tasks.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+const MAX_SEGMENTS: usize = 500;
+/// Waited at most for a provider's rate limit, per request.
+const THROTTLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The provider's translator and host, and the languages of `direction`
+/// (the settings' when `None`).
+fn setup(
+  app: &AppHandle,
+  provider_id: &str,
+  model: Option<&str>,
+  direction: Option<&str>,
+) -> Result<(Box<dyn Translator>, String, String, String), ProxyError> {
+  let provider = providers::find(provider_id).map_err(invalid)?;
+  let model = model.map_or_else(
+    || provider.models.first().cloned().unwrap_or_default(),
+    str::to_string,
+  );
+  let translator = providers::translator(&provider, &model).map_err(invalid)?;
+  let direction = direction.map_or_else(
+    || app.state::<SettingsState>().get().direction,
+    str::to_string,
+  );
+  let (source, target) = direction
+    .split_once("->")
+    .ok_or_else(|| invalid(format!("Invalid direction {direction}")))?;
+  Ok((
+    translator,
+    provider.host().unwrap_or_default(),
+    source.to_string(),
+    target.to_string(),
+  ))
+}
+
+/// A text to send: all of `texts[text]` or a chunk of it, with what goes
+/// before its translation when the chunks are put back together.
+struct Part {
+  text: usize,
+  joiner: &'static str,
+  source: String,
+}
+
+/// `texts` cut into the parts sent: texts too long for the translator's
+/// window in chunks of whole sentences (see `segmentation`), blank ones
+/// not at all.
+fn parts(texts: &[String], translator: &dyn Translator, source: &str, target: &str) -> Vec<Part> {
+  let mut out = Vec::new();
+  for (i, text) in texts.iter().enumerate() {
+    if text.trim().is_empty() {
+      continue;
+    }
+    let max = translator
+      .max_tokens()
+      .filter(|&max| segmentation::tokens(text) > max);
+    let Some(max) = max else {
+      out.push(Part {
+        text: i,
+        joiner: "",
+        source: text.clone(),
+      });
+      continue;
+    };
+    let mut end = None;
+    for range in segmentation::chunks(text, source, max) {
+      let joiner = match end {
+        None => "",
+        Some(end) if text[end..range.start].contains('\n') => "\n",
+        Some(_) if matches!(target, "zh" | "ja") => "",
+        Some(_) => " ",
+      };
+      end = Some(range.end);
+      out.push(Part {
+        text: i,
+        joiner,
+        source: text[range].to_string(),
+      });
+    }
+  }
+  out
+}
+
+/// `texts` translated in as few requests as the translator takes, within
+/// the provider's rate limit.
+fn translate_all(
+  app: &AppHandle,
+  translator: &dyn Translator,
+  host: &str,
+  texts: &[String],
+  source: &str,
+  target: &str,
+) -> Result<Vec<String>, ProxyError> {
+  let (max_texts, max_chars) = translator.batch();
+  let parts = parts(texts, translator, source, target);
+  // blank texts stay as they are
+  let mut out = texts.to_vec();
+  for part in &parts {
+    out[part.text].clear();
+  }
+  let mut start = 0;
+  while start < parts.len() {
+    let mut end = start + 1;
+    let mut chars = parts[start].source.chars().count();
+    while end < parts.len() && end - start < max_texts {
+      let next = parts[end].source.chars().count();
+      if chars + next > max_chars {
+        break;
+      }
+      chars += next;
+      end += 1;
+    }
+    app
+      .state::<RateLimiter>()
+      .throttle(host, THROTTLE_TIMEOUT)
+      .map_err(|e| ProxyError::new("rate-limited", e))?;
+    let batch: Vec<String> = parts[start..end].iter().map(|p| p.source.clone()).collect();
+    let translated = translator.translate(&batch, source, target)?;
+    for (part, translation) in parts[start..end].iter().zip(translated) {
+      out[part.text].push_str(part.joiner);
+      out[part.text].push_str(&translation);
+    }
+    start = end;
+  }
+  Ok(out)
+}
+
+fn new_id() -> Result<String, ProxyError> {
+  let mut raw = [0u8; 8];
+  getrandom::getrandom(&mut raw)
+    .map_err(|e| ProxyError::new("unreachable", format!("No randomness available: {e}")))?;
+  Ok(format!(
+    "direct_{}",
+    raw.iter().map(|b| format!("{b:02x}")).collect::<String>()
+  ))
+}
+
+fn translate_document(
+  app: &AppHandle,
+  path: &Path,
+  provider_id: &str,
+  model: Option<&str>,
+  direction: Option<&str>,
+) -> Result<Exported, ProxyError> {
+  let size = fs::metadata(path)
+    .map_err(|e| invalid(format!("Cannot read {}: {e}", path.display())))?
+    .len();
+  if size > MAX_FILE_BYTES {
+    return Err(invalid(format!(
+      "{} is too large to translate without the backend",
+      path.display()
+    )));
+  }
+  let (translator, host, source, target) = setup(app, provider_id, model, direction)?;
+  let settings = app.state::<SettingsState>().get();
+  let job_id = new_id()?;
+  let direction = format!("{source}->{target}");
+  let extension = formats::route(path);
+  let ocr = ocr::Session::new(app, &job_id, &direction);
+  let found = segments::extract(
+    path,
+    None,
+    docx::Review::default(),
+    settings.po_retranslate,
+    &ocr,
+  )
+  .map_err(invalid)?
+  .ok_or_else(|| invalid(format!(".{extension} documents are read by the backend")))?;
+  // chunks the model's window takes, when it is smaller
+  let max_tokens = match (segments::chunked(&extension), translator.max_tokens()) {
+    (false, _) => 0,
+    (true, Some(window)) if settings.max_segment_tokens == 0 => window,
+    (true, window) => settings
+      .max_segment_tokens
+      .min(window.unwrap_or(usize::MAX)),
+  };
+  let found = segmentation::split(found, &source, max_tokens);
+  if found.len() > MAX_SEGMENTS {
+    return Err(invalid(format!(
+      "{} has over {MAX_SEGMENTS} segments; translate it as a task",
+      path.display()
+    )));
+  }
+
+  let texts: Vec<String> = found.iter().map(|s| s.text.clone()).collect();
+  let translated = translate_all(app, translator.as_ref(), &host, &texts, &source, &target)?;
+  let rows = found
+    .into_iter()
+    .zip(translated)
+    .enumerate()
+    .map(|(i, (segment, translation))| Row {
+      id: i.to_string(),
+      locator: Some(segment.locator),
+      status: "translated".to_string(),
+      source: segment.text,
+      translation: Some(translation).filter(|t| !t.trim().is_empty()),
+    })
+    .collect();
+  let source_data =
+    fs::read(path).map_err(|e| invalid(format!("Cannot read {}: {e}", path.display())))?;
+  segments::hold(
+    &job_id,
+    Local {
+      info: TaskInfo {
+        filename: path
+          .file_name()
+          .map(|n| n.to_string_lossy().into_owned())
+          .unwrap_or_default(),
+        direction,
+      },
+      source: source_data,
+      rows,
+    },
+  );
+  // next to the document, unless the policy names a folder
+  let dir = settings
+    .output_dir
+    .is_none()
+    .then(|| path.parent())
+    .flatten();
+  let exported = output::export_in(app, &job_id, dir);
+  segments::release(&job_id);
+  exported
+}
+
+/// `texts` translated by provider `provider_id` (with `model`, or its
+/// first) in `direction`, or the settings' direction, without the backend.
+#[tauri::command]
+pub async fn translate_text(
+  app: AppHandle,
+  provider_id: String,
+  model: Option<String>,
+  texts: Vec<String>,
+  direction: Option<String>,
+) -> Result<Vec<String>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let (translator, host, source, target) =
+      setup(&app, &provider_id, model.as_deref(), direction.as_deref())?;
+    translate_all(&app, translator.as_ref(), &host, &texts, &source, &target)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Translates the document at `path` as [`translate_text`] does and writes
+/// its translated copy under the output policy, or next to it.
+#[tauri::command]
+pub async fn translate_file(
+  app: AppHandle,
+  path: String,
+  provider_id: String,
+  model: Option<String>,
+  direction: Option<String>,
+) -> Result<Exported, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    translate_document(
+      &app,
+      Path::new(&path),
+      &provider_id,
+      model.as_deref(),
+      direction.as_deref(),
+    )
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
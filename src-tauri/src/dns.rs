@@ -0,0 +1,225 @@
+//! Name resolution for outbound traffic where the system DNS blocks or
+//! poisons provider domains: fixed host addresses, and/or a DNS-over-HTTPS
+//! endpoint speaking the JSON API (`application/dns-json`, as Cloudflare
+//! and Google offer). Applies to the shell's outbound clients; the backend
+//! gets the same settings via [`ENV`] for its provider calls.
+
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{BTreeMap, HashMap},
+  net::{IpAddr, SocketAddr},
+  sync::{Arc, Mutex, OnceLock},
+  time::{Duration, Instant},
+};
+use tauri::AppHandle;
+
+use crate::{config, paths};
+
+pub const ENV: &str = "MVP_DNS";
+const DOH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Floor for answers with a tiny or missing TTL.
+const MIN_TTL: Duration = Duration::from_secs(30);
+const A: u16 = 1;
+const AAAA: u16 = 28;
+
+/// DoH answers with their expiry, by hostname.
+type Cache = HashMap<String, (Vec<IpAddr>, Instant)>;
+
+static CURRENT: Mutex<Option<DnsConfig>> = Mutex::new(None);
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// `dns` in `startup.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+  /// DoH endpoint, e.g. `https://1.1.1.1/dns-query`; empty for system DNS.
+  /// Use an IP address or a name the system DNS still resolves.
+  pub doh_url: String,
+  /// Fixed addresses by hostname; these win over DoH.
+  pub hosts: BTreeMap<String, Vec<String>>,
+}
+
+impl DnsConfig {
+  pub fn validate(&self) -> Result<(), String> {
+    if !self.doh_url.is_empty() {
+      let url = reqwest::Url::parse(&self.doh_url).map_err(|e| format!("Invalid DoH URL: {e}"))?;
+      if url.scheme() != "https" {
+        return Err("The DoH URL must use https".to_string());
+      }
+    }
+    for (host, addrs) in &self.hosts {
+      if addrs.is_empty() {
+        return Err(format!("No address given for {host}"));
+      }
+      for addr in addrs {
+        addr
+          .parse::<IpAddr>()
+          .map_err(|_| format!("{addr} (for {host}) is not an IP address"))?;
+      }
+    }
+    Ok(())
+  }
+
+  fn normalized(mut self) -> Self {
+    self.doh_url = self.doh_url.trim().to_string();
+    self.hosts = self
+      .hosts
+      .into_iter()
+      .map(|(host, addrs)| {
+        (
+          host.trim().trim_end_matches('.').to_ascii_lowercase(),
+          addrs.into_iter().map(|a| a.trim().to_string()).collect(),
+        )
+      })
+      .filter(|(host, _)| !host.is_empty())
+      .collect();
+    self
+  }
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+  #[serde(rename = "type")]
+  kind: u16,
+  data: String,
+  #[serde(rename = "TTL", default)]
+  ttl: u64,
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+  #[serde(rename = "Answer", default)]
+  answer: Vec<DohAnswer>,
+}
+
+fn doh_client() -> reqwest::Result<reqwest::Client> {
+  static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+  if let Some(client) = CLIENT.get() {
+    return Ok(client.clone());
+  }
+  let client = reqwest::Client::builder().timeout(DOH_TIMEOUT).build()?;
+  Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+async fn query(
+  doh_url: &str,
+  host: &str,
+  kind: u16,
+) -> Result<(Vec<IpAddr>, Duration), reqwest::Error> {
+  let resp: DohResponse = doh_client()?
+    .get(doh_url)
+    .query(&[
+      ("name", host),
+      ("type", if kind == A { "A" } else { "AAAA" }),
+    ])
+    .header(reqwest::header::ACCEPT, "application/dns-json")
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+  let answers: Vec<&DohAnswer> = resp.answer.iter().filter(|a| a.kind == kind).collect();
+  let ttl = answers
+    .iter()
+    .map(|a| Duration::from_secs(a.ttl))
+    .min()
+    .unwrap_or_default()
+    .max(MIN_TTL);
+  let ips = answers.iter().filter_map(|a| a.data.parse().ok()).collect();
+  Ok((ips, ttl))
+}
+
+struct DohResolver(String);
+
+impl reqwest::dns::Resolve for DohResolver {
+  fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+    let doh_url = self.0.clone();
+    let host = name.as_str().to_ascii_lowercase();
+    Box::pin(async move {
+      let cached = CACHE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|c| c.get(&host))
+        .filter(|(_, expires)| *expires > Instant::now())
+        .map(|(ips, _)| ips.clone());
+      let ips = match cached {
+        Some(ips) => ips,
+        None => {
+          let (mut ips, mut ttl) = query(&doh_url, &host, A).await?;
+          if ips.is_empty() {
+            (ips, ttl) = query(&doh_url, &host, AAAA).await?;
+          }
+          if ips.is_empty() {
+            return Err(format!("{host}: no address via DoH").into());
+          }
+          CACHE
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(host, (ips.clone(), Instant::now() + ttl));
+          ips
+        }
+      };
+      let addrs: reqwest::dns::Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+      Ok(addrs)
+    })
+  }
+}
+
+/// Makes `cfg` the active DNS setting; called at startup and on changes.
+pub fn init(cfg: DnsConfig) {
+  *CURRENT.lock().unwrap() = Some(cfg);
+  *CACHE.lock().unwrap() = None;
+}
+
+fn current() -> DnsConfig {
+  CURRENT.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// `builder` with the host overrides and DoH resolver applied.
+pub fn apply(mut builder: reqwest::blocking::ClientBuilder) -> reqwest::blocking::ClientBuilder {
+  let cfg = current();
+  for (host, addrs) in &cfg.hosts {
+    let addrs: Vec<SocketAddr> = addrs
+      .iter()
+      .filter_map(|a| a.parse().ok())
+      .map(|ip| SocketAddr::new(ip, 0))
+      .collect();
+    builder = builder.resolve_to_addrs(host, &addrs);
+  }
+  if !cfg.doh_url.is_empty() {
+    builder = builder.dns_resolver(Arc::new(DohResolver(cfg.doh_url)));
+  }
+  builder
+}
+
+pub fn backend_env() -> BTreeMap<String, String> {
+  let cfg = current();
+  let mut env = BTreeMap::new();
+  if !cfg.doh_url.is_empty() || !cfg.hosts.is_empty() {
+    if let Ok(json) = serde_json::to_string(&cfg) {
+      env.insert(ENV.to_string(), json);
+    }
+  }
+  env
+}
+
+#[tauri::command]
+pub fn get_dns_config() -> DnsConfig {
+  current()
+}
+
+/// Saves and activates DNS settings. The shell's clients use them at once;
+/// backends from their next (re)start.
+#[tauri::command]
+pub fn set_dns_config(app: AppHandle, config: DnsConfig) -> Result<DnsConfig, String> {
+  let config = config.normalized();
+  config.validate()?;
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "dns", value)
+    .map_err(|e| format!("Cannot save DNS settings: {e}"))?;
+  init(config.clone());
+  Ok(config)
+}
@@ -0,0 +1,193 @@
+//! Word documents read and rebuilt in the shell. Segments are the
+//! paragraphs of the body (tables and text boxes included), headers,
+//! footers, footnotes, endnotes and review comments; the translated copy is
+//! the source package with only those parts rewritten (see `ooxml`), so
+//! styles, numbering, images and section layout come through untouched.
+//!
+//! Review marks are kept either way a task chooses ([`Review`]). Comments
+//! keep their anchors, and a paragraph with tracked changes keeps them: it
+//! is either left as it is, or translated in both its versions, before and
+//! after the changes, and rebuilt as a tracked replacement of one by the
+//! other, so accepting or rejecting the changes still gives either.
+//!
+//! Locators are `docx:<part>#<paragraph index>`, e.g.
+//! `docx:word/document.xml#12`, with `@original` or `@current` after the
+//! index for the two versions of a paragraph with tracked changes.
+
+use std::{collections::HashMap, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  ooxml::{self, Markup, Replacement, Revisions},
+  protected,
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+const WORD: Markup = Markup {
+  paragraph: b"w:p",
+  run: b"w:r",
+  run_properties: b"w:rPr",
+  text: b"w:t",
+  line_break: b"w:br",
+  break_in_run: true,
+  tab: Some(b"w:tab"),
+  preserve_space: true,
+  revisions: Some(Revisions {
+    inserted: &[b"w:ins", b"w:moveTo"],
+    deleted: &[b"w:del", b"w:moveFrom"],
+    deleted_text: b"w:delText",
+    properties_change: b"w:rPrChange",
+    id: "w:id",
+  }),
+};
+
+/// What a task does with review comments and tracked changes.
+#[derive(Clone, Copy, Default)]
+pub enum Review {
+  /// Comments are translated, and paragraphs with tracked changes in both
+  /// versions.
+  #[default]
+  Translate,
+  /// Comments and paragraphs with tracked changes stay in the source
+  /// language, for the reviewer to take up.
+  Keep,
+}
+
+impl Review {
+  /// From the `review` option of an upload; `translate` when not given.
+  pub fn parse(value: Option<&str>) -> Result<Self, String> {
+    match value.map(str::trim) {
+      None | Some("" | "translate") => Ok(Review::Translate),
+      Some("keep") => Ok(Review::Keep),
+      Some(other) => Err(format!(
+        "Unknown review option {other}: use translate or keep"
+      )),
+    }
+  }
+}
+
+/// Parts with translatable text, body first.
+fn text_parts(names: impl Iterator<Item = String>, review: Review) -> Vec<String> {
+  let kinds: &[&str] = match review {
+    Review::Translate => &[
+      "document",
+      "header",
+      "footer",
+      "footnotes",
+      "endnotes",
+      "comments",
+    ],
+    Review::Keep => &["document", "header", "footer", "footnotes", "endnotes"],
+  };
+  let rank = |name: &str| {
+    let stem = name.strip_prefix("word/")?.strip_suffix(".xml")?;
+    let kind = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    kinds.iter().position(|k| *k == kind)
+  };
+  let mut parts: Vec<(usize, String)> = names.filter_map(|n| rank(&n).map(|r| (r, n))).collect();
+  parts.sort();
+  parts.into_iter().map(|(_, n)| n).collect()
+}
+
+/// The part, paragraph index and version (`original` or `current`, for
+/// paragraphs with tracked changes) of `locator`.
+fn parse_locator(locator: &str) -> Option<(&str, usize, Option<&str>)> {
+  let (part, index) = locator.strip_prefix("docx:")?.rsplit_once('#')?;
+  let (index, version) = match index.split_once('@') {
+    Some((index, version)) => (index, Some(version)),
+    None => (index, None),
+  };
+  Some((part, index.parse().ok()?, version))
+}
+
+pub fn segments(path: &Path, review: Review) -> Result<Vec<Segment>, String> {
+  let data = protected::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut archive = ooxml::open(&data)?;
+  let mut out = Vec::new();
+  for part in text_parts(archive.file_names().map(str::to_string), review) {
+    let xml = ooxml::read_part(&mut archive, &part)?;
+    let (paragraphs, revised) = ooxml::read(&xml, &WORD)?;
+    let mut texts: Vec<(usize, String, String)> = Vec::new();
+    for (index, text) in paragraphs {
+      if !revised.iter().any(|r| r.index == index) {
+        texts.push((index, format!("docx:{part}#{index}"), text));
+      }
+    }
+    if let Review::Translate = review {
+      for r in revised {
+        texts.push((
+          r.index,
+          format!("docx:{part}#{}@original", r.index),
+          r.original,
+        ));
+        texts.push((
+          r.index,
+          format!("docx:{part}#{}@current", r.index),
+          r.current,
+        ));
+      }
+    }
+    // the two versions of a paragraph stay in order
+    texts.sort_by_key(|(index, _, _)| *index);
+    for (_, locator, text) in texts {
+      // page numbers and other fields without words
+      if !text.chars().any(char::is_alphabetic) {
+        continue;
+      }
+      out.push(Segment {
+        locator,
+        text: text.trim().to_string(),
+      });
+    }
+  }
+  Ok(out)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let mut by_part: HashMap<&str, HashMap<usize, Replacement>> = HashMap::new();
+  for (locator, text) in &blocks {
+    let Some((part, index, version)) = parse_locator(locator) else {
+      continue;
+    };
+    let paragraphs = by_part.entry(part).or_default();
+    let Some(version) = version else {
+      paragraphs.insert(index, Replacement::Text(text.clone()));
+      continue;
+    };
+    let entry = paragraphs.entry(index).or_insert(Replacement::Revised {
+      original: None,
+      current: None,
+    });
+    if let Replacement::Revised { original, current } = entry {
+      match version {
+        "original" => *original = Some(text.clone()),
+        "current" => *current = Some(text.clone()),
+        _ => {}
+      }
+    }
+  }
+  let mut archive = ooxml::open(&source).map_err(invalid)?;
+  let mut replaced = HashMap::new();
+  for (part, paragraphs) in &by_part {
+    let xml = ooxml::read_part(&mut archive, part).map_err(invalid)?;
+    replaced.insert(
+      part.to_string(),
+      ooxml::rewrite_part(&xml, &WORD, paragraphs).map_err(invalid)?,
+    );
+  }
+  ooxml::rewrite(&source, path, &replaced).map_err(invalid)
+}
+
+/// Writes the translated copy of Word task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_docx(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
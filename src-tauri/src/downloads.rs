@@ -0,0 +1,312 @@
+//! Large asset downloads (local MT models, OCR language packs) into
+//! `<data dir>/downloads`. Data goes to `<dest>.part` first, so a paused or
+//! interrupted download resumes with an HTTP range request; the file only
+//! takes its final name once its SHA-256 matches.
+
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  fs::{self, OpenOptions},
+  io::{Read, Write},
+  path::{Component, Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{boxed_err, integrity, outbound, paths};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Minimum gap between two `download-progress` events of one download.
+const PROGRESS_EVERY: Duration = Duration::from_millis(250);
+
+struct Download {
+  url: String,
+  dest: PathBuf,
+  sha256: String,
+  running: bool,
+  paused: Arc<AtomicBool>,
+  cancelled: Arc<AtomicBool>,
+}
+
+/// Downloads started this session, by caller-chosen id. Paused ones stay
+/// here until resumed or cancelled.
+#[derive(Default)]
+pub struct DownloadState(Mutex<HashMap<String, Download>>);
+
+/// Payload of `download-progress`. `state` is `downloading`, `verifying`,
+/// `paused`, `done`, `cancelled` or `failed`.
+#[derive(Clone, Serialize)]
+struct Progress<'a> {
+  id: &'a str,
+  state: &'static str,
+  downloaded: u64,
+  total: Option<u64>,
+  path: Option<String>,
+  error: Option<String>,
+}
+
+enum Outcome {
+  Done(PathBuf),
+  Paused(u64),
+  Cancelled,
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+  let mut name = dest.file_name().unwrap_or_default().to_os_string();
+  name.push(".part");
+  dest.with_file_name(name)
+}
+
+/// `name` must stay inside the downloads dir.
+fn resolve(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+  let rel = Path::new(name);
+  if name.is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+    return Err(format!(
+      "Invalid destination {name}: use a relative path inside the downloads folder"
+    ));
+  }
+  let dir = paths::data_dir(app)
+    .map_err(|e| e.to_string())?
+    .join("downloads");
+  Ok(dir.join(rel))
+}
+
+fn emit(app: &AppHandle, id: &str, state: &'static str, downloaded: u64, total: Option<u64>) {
+  let _ = app.emit(
+    "download-progress",
+    Progress {
+      id,
+      state,
+      downloaded,
+      total,
+      path: None,
+      error: None,
+    },
+  );
+}
+
+fn fetch(
+  app: &AppHandle,
+  id: &str,
+  url: &str,
+  dest: &Path,
+  sha256: &str,
+  paused: &AtomicBool,
+  cancelled: &AtomicBool,
+) -> Result<Outcome, Box<dyn std::error::Error>> {
+  let part = part_path(dest);
+  if let Some(parent) = dest.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+  let client = outbound::builder()?
+    .timeout(None)
+    .connect_timeout(CONNECT_TIMEOUT)
+    .build()?;
+  let mut req = client.get(url);
+  if offset > 0 {
+    req = req.header(reqwest::header::RANGE, format!("bytes={offset}-"));
+  }
+  let mut resp = req.send()?;
+  // 416: the part file already holds the whole body
+  let complete = offset > 0 && resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE;
+  let mut downloaded = offset;
+  let mut total = None;
+  if !complete {
+    resp.error_for_status_ref()?;
+    let resumed = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+      // the server ignored the range; start over
+      downloaded = 0;
+    }
+    total = resp.content_length().map(|len| len + downloaded);
+    let mut file = OpenOptions::new()
+      .create(true)
+      .write(true)
+      .append(resumed)
+      .truncate(!resumed)
+      .open(&part)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut last_event = Instant::now();
+    emit(app, id, "downloading", downloaded, total);
+    loop {
+      if cancelled.load(Ordering::SeqCst) {
+        drop(file);
+        let _ = fs::remove_file(&part);
+        return Ok(Outcome::Cancelled);
+      }
+      if paused.load(Ordering::SeqCst) {
+        file.sync_all()?;
+        return Ok(Outcome::Paused(downloaded));
+      }
+      let n = resp.read(&mut buf)?;
+      if n == 0 {
+        break;
+      }
+      file.write_all(&buf[..n])?;
+      downloaded += n as u64;
+      if last_event.elapsed() >= PROGRESS_EVERY {
+        emit(app, id, "downloading", downloaded, total);
+        last_event = Instant::now();
+      }
+    }
+    file.sync_all()?;
+  }
+
+  emit(app, id, "verifying", downloaded, total);
+  if let Err(e) = integrity::verify_against(&part, sha256) {
+    let _ = fs::remove_file(&part);
+    return Err(boxed_err(format!(
+      "Checksum mismatch: expected {}, got {}",
+      e.expected,
+      e.actual.unwrap_or_default()
+    )));
+  }
+  fs::rename(&part, dest)?;
+  Ok(Outcome::Done(dest.to_path_buf()))
+}
+
+fn run(app: AppHandle, id: String) {
+  let (url, dest, sha256, paused, cancelled) = {
+    let state = app.state::<DownloadState>();
+    let downloads = state.0.lock().unwrap();
+    let Some(d) = downloads.get(&id) else {
+      return;
+    };
+    (
+      d.url.clone(),
+      d.dest.clone(),
+      d.sha256.clone(),
+      d.paused.clone(),
+      d.cancelled.clone(),
+    )
+  };
+  let result = fetch(&app, &id, &url, &dest, &sha256, &paused, &cancelled);
+  let state = app.state::<DownloadState>();
+  let mut downloads = state.0.lock().unwrap();
+  let mut progress = Progress {
+    id: &id,
+    state: "failed",
+    downloaded: 0,
+    total: None,
+    path: None,
+    error: None,
+  };
+  match result {
+    Ok(Outcome::Paused(downloaded)) => {
+      if let Some(d) = downloads.get_mut(&id) {
+        d.running = false;
+      }
+      progress.state = "paused";
+      progress.downloaded = downloaded;
+    }
+    Ok(Outcome::Done(path)) => {
+      downloads.remove(&id);
+      progress.state = "done";
+      progress.downloaded = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+      progress.path = Some(path.display().to_string());
+    }
+    Ok(Outcome::Cancelled) => {
+      downloads.remove(&id);
+      progress.state = "cancelled";
+    }
+    Err(e) => {
+      // the part file is kept, so starting again resumes
+      if let Some(d) = downloads.get_mut(&id) {
+        d.running = false;
+      }
+      progress.downloaded = fs::metadata(part_path(&dest)).map(|m| m.len()).unwrap_or(0);
+      progress.error = Some(e.to_string());
+    }
+  }
+  drop(downloads);
+  let _ = app.emit("download-progress", progress);
+}
+
+/// Starts (or resumes, if a `.part` file exists) downloading `url` to
+/// `dest` under the downloads folder. Progress arrives as
+/// `download-progress` events filtered by `id`; the final file is only
+/// created when it matches `sha256`.
+#[tauri::command]
+pub fn start_download(
+  app: AppHandle,
+  state: State<DownloadState>,
+  id: String,
+  url: String,
+  dest: String,
+  sha256: String,
+) -> Result<(), String> {
+  let dest = resolve(&app, &dest)?;
+  let sha256 = sha256.trim().to_ascii_lowercase();
+  if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err("sha256 must be 64 hex digits".to_string());
+  }
+  let url = url.trim().to_string();
+  reqwest::Url::parse(&url).map_err(|e| format!("Invalid URL: {e}"))?;
+  {
+    let mut downloads = state.0.lock().unwrap();
+    if downloads.get(&id).is_some_and(|d| d.running) {
+      return Err(format!("Download {id} is already running"));
+    }
+    if downloads
+      .iter()
+      .any(|(other, d)| other != &id && d.dest == dest)
+    {
+      return Err(format!("Another download writes to {}", dest.display()));
+    }
+    downloads.insert(
+      id.clone(),
+      Download {
+        url,
+        dest,
+        sha256,
+        running: true,
+        paused: Arc::new(AtomicBool::new(false)),
+        cancelled: Arc::new(AtomicBool::new(false)),
+      },
+    );
+  }
+  std::thread::spawn(move || run(app, id));
+  Ok(())
+}
+
+/// Stops a running download, keeping what arrived so far.
+#[tauri::command]
+pub fn pause_download(state: State<DownloadState>, id: String) -> Result<(), String> {
+  match state.0.lock().unwrap().get(&id) {
+    Some(d) if d.running => {
+      d.paused.store(true, Ordering::SeqCst);
+      Ok(())
+    }
+    Some(_) => Ok(()),
+    None => Err(format!("Unknown download: {id}")),
+  }
+}
+
+/// Stops a download and deletes its partial data.
+#[tauri::command]
+pub fn cancel_download(
+  app: AppHandle,
+  state: State<DownloadState>,
+  id: String,
+) -> Result<(), String> {
+  let mut downloads = state.0.lock().unwrap();
+  match downloads.get(&id) {
+    Some(d) if d.running => {
+      d.cancelled.store(true, Ordering::SeqCst);
+      Ok(())
+    }
+    Some(_) => {
+      let d = downloads.remove(&id).unwrap();
+      let _ = fs::remove_file(part_path(&d.dest));
+      drop(downloads);
+      emit(&app, &id, "cancelled", 0, None);
+      Ok(())
+    }
+    None => Err(format!("Unknown download: {id}")),
+  }
+}
@@ -0,0 +1,357 @@
+//! EPUB books read and rebuilt in the shell, like `docx`. A book is a zip
+//! of XHTML chapters listed by its package document (the OPF); segments
+//! are the text blocks of each chapter (paragraphs, headings, list items,
+//! table cells and the like), chapter by chapter in spine order. Only
+//! chapters are rewritten, so the spine, table of contents, metadata,
+//! styles and images stay as they were, and so does the leading stored
+//! `mimetype` entry readers check for.
+//!
+//! A translated block keeps its element, attributes and inline markup:
+//! the translation goes into its first piece of text, the rest of its text
+//! is emptied, and line breaks in the translation become `<br/>`.
+//! Locators are `epub:<chapter>#<block index>`, e.g.
+//! `epub:OEBPS/text/ch03.xhtml#17`.
+
+use quick_xml::{
+  events::{BytesStart, BytesText, Event},
+  Reader, Writer,
+};
+use serde::Serialize;
+use std::{collections::HashMap, fs, io::Cursor, path::Path};
+use tauri::{AppHandle, Emitter};
+use zip::ZipArchive;
+
+use crate::{
+  ooxml::{self, Paragraphs},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+const CONTAINER: &str = "META-INF/container.xml";
+/// Elements whose text is one segment; text in a nested block is its own.
+const BLOCKS: &[&[u8]] = &[
+  b"p",
+  b"h1",
+  b"h2",
+  b"h3",
+  b"h4",
+  b"h5",
+  b"h6",
+  b"li",
+  b"dt",
+  b"dd",
+  b"th",
+  b"td",
+  b"caption",
+  b"figcaption",
+  b"blockquote",
+  b"pre",
+  b"div",
+  b"section",
+  b"aside",
+];
+/// Elements whose text is not prose: code, styles, ruby annotations.
+const SKIPPED: &[&[u8]] = &[b"script", b"style", b"rt", b"rp"];
+
+/// Payload of `epub-export-progress`; `done` of `total` chapters written.
+#[derive(Clone, Serialize)]
+struct Progress<'a> {
+  job_id: &'a str,
+  done: usize,
+  total: usize,
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+  e.try_get_attribute(key)
+    .ok()
+    .flatten()
+    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// `href` as a zip entry name: without its fragment and percent-decoded.
+fn decode_href(href: &str) -> String {
+  let href = href.split('#').next().unwrap_or_default().as_bytes();
+  let mut out = Vec::with_capacity(href.len());
+  let mut i = 0;
+  while i < href.len() {
+    let hex = href
+      .get(i + 1..i + 3)
+      .and_then(|h| std::str::from_utf8(h).ok())
+      .and_then(|h| u8::from_str_radix(h, 16).ok());
+    match (href[i], hex) {
+      (b'%', Some(byte)) => {
+        out.push(byte);
+        i += 3;
+      }
+      (byte, _) => {
+        out.push(byte);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The chapters' entry names in spine order.
+fn chapters(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Vec<String>, String> {
+  let invalid = |e: quick_xml::Error| format!("Invalid EPUB package: {e}");
+  let xml = ooxml::read_part(archive, CONTAINER)?;
+  let mut reader = Reader::from_reader(xml.as_slice());
+  let package = loop {
+    match reader.read_event().map_err(invalid)? {
+      Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+        if let Some(path) = attribute(&e, "full-path") {
+          break path;
+        }
+      }
+      Event::Eof => return Err("The EPUB names no package document".to_string()),
+      _ => {}
+    }
+  };
+
+  let xml = ooxml::read_part(archive, &package)?;
+  let mut reader = Reader::from_reader(xml.as_slice());
+  // manifest id -> (href, media type), and the spine's ids
+  let mut items = HashMap::new();
+  let mut spine = Vec::new();
+  loop {
+    match reader.read_event().map_err(invalid)? {
+      Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+        b"item" => {
+          if let (Some(id), Some(href)) = (attribute(&e, "id"), attribute(&e, "href")) {
+            items.insert(id, (href, attribute(&e, "media-type").unwrap_or_default()));
+          }
+        }
+        b"itemref" => spine.extend(attribute(&e, "idref")),
+        _ => {}
+      },
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  let mut out: Vec<String> = Vec::new();
+  for id in spine {
+    let Some((href, media_type)) = items.get(&id) else {
+      continue;
+    };
+    if !matches!(media_type.as_str(), "application/xhtml+xml" | "text/html") {
+      continue;
+    }
+    let name = ooxml::resolve(&package, &decode_href(href));
+    if !out.contains(&name) {
+      out.push(name);
+    }
+  }
+  Ok(out)
+}
+
+struct Block<'a> {
+  index: usize,
+  text: String,
+  replacement: Option<&'a str>,
+  written: bool,
+  /// `<pre>`, whose line breaks are in its text.
+  preformatted: bool,
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid chapter XHTML: {e}")
+}
+
+/// Whitespace collapsed as a reader would show it, keeping line breaks.
+fn normalize(block: &Block) -> String {
+  if block.preformatted {
+    return block.text.trim().to_string();
+  }
+  block
+    .text
+    .split('\n')
+    .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+    .collect::<Vec<_>>()
+    .join("\n")
+    .trim()
+    .to_string()
+}
+
+/// Reads chapter `xml`, writing it back with `replacements` when given.
+/// Returns every non-empty block of the body with its index (blocks
+/// counted in document order, nested ones included).
+fn walk(
+  xml: &[u8],
+  replacements: Option<&HashMap<usize, String>>,
+) -> Result<(Paragraphs, Vec<u8>), String> {
+  let mut reader = Reader::from_reader(xml);
+  let mut writer = replacements.map(|_| Writer::new(Vec::with_capacity(xml.len())));
+  let mut found = Vec::new();
+  // one entry per open element: whether it is a block
+  let mut open: Vec<bool> = Vec::new();
+  let mut blocks: Vec<Block> = Vec::new();
+  let mut count = 0;
+  let (mut in_body, mut skipped) = (false, 0);
+  loop {
+    let event = reader.read_event().map_err(xml_err)?;
+    let mut keep = true;
+    match &event {
+      Event::Start(e) => {
+        let name = e.local_name();
+        let is_block = in_body && skipped == 0 && BLOCKS.contains(&name.as_ref());
+        if name.as_ref() == b"body" {
+          in_body = true;
+        } else if in_body && SKIPPED.contains(&name.as_ref()) {
+          skipped += 1;
+        }
+        if is_block {
+          blocks.push(Block {
+            index: count,
+            text: String::new(),
+            replacement: replacements.and_then(|r| r.get(&count)).map(String::as_str),
+            written: false,
+            preformatted: name.as_ref() == b"pre",
+          });
+          count += 1;
+        }
+        open.push(is_block);
+      }
+      Event::End(e) => {
+        let name = e.local_name();
+        if name.as_ref() == b"body" {
+          in_body = false;
+        } else if in_body && SKIPPED.contains(&name.as_ref()) {
+          skipped -= 1;
+        }
+        if open.pop() == Some(true) {
+          if let Some(block) = blocks.pop() {
+            let text = normalize(&block);
+            if !text.is_empty() {
+              found.push((block.index, text));
+            }
+          }
+        }
+      }
+      Event::Empty(e) if e.local_name().as_ref() == b"br" && skipped == 0 => {
+        if let Some(block) = blocks.last_mut() {
+          block.text.push('\n');
+          // the translation brings its own
+          keep = block.replacement.is_none();
+        }
+      }
+      Event::Text(t) if skipped == 0 => {
+        if let Some(block) = blocks.last_mut() {
+          let text = t.unescape().map_err(xml_err)?;
+          if block.preformatted {
+            block.text.push_str(&text);
+          } else {
+            // source line wrapping, not a break
+            block.text.push_str(&text.replace('\n', " "));
+          }
+          if let (Some(replacement), false) = (block.replacement, text.trim().is_empty()) {
+            keep = false;
+            if !block.written {
+              block.written = true;
+              let lines: Vec<&str> = if block.preformatted {
+                vec![replacement]
+              } else {
+                replacement.split('\n').collect()
+              };
+              if let Some(w) = writer.as_mut() {
+                for (i, line) in lines.into_iter().enumerate() {
+                  if i > 0 {
+                    w.write_event(Event::Empty(BytesStart::new("br")))
+                      .map_err(xml_err)?;
+                  }
+                  w.write_event(Event::Text(BytesText::new(line)))
+                    .map_err(xml_err)?;
+                }
+              }
+            }
+          }
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+    if keep {
+      if let Some(w) = writer.as_mut() {
+        w.write_event(event).map_err(xml_err)?;
+      }
+    }
+  }
+  // nested blocks end first
+  found.sort_by_key(|(index, _)| *index);
+  Ok((found, writer.map(Writer::into_inner).unwrap_or_default()))
+}
+
+fn parse_locator(locator: &str) -> Option<(&str, usize)> {
+  let (part, index) = locator.strip_prefix("epub:")?.rsplit_once('#')?;
+  Some((part, index.parse().ok()?))
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut archive = ooxml::open(&data)?;
+  let mut out = Vec::new();
+  for chapter in chapters(&mut archive)? {
+    let xml = ooxml::read_part(&mut archive, &chapter)?;
+    for (index, text) in walk(&xml, None)?.0 {
+      // page numbers, ornaments and other blocks without words
+      if !text.chars().any(char::is_alphabetic) {
+        continue;
+      }
+      out.push(Segment {
+        locator: format!("epub:{chapter}#{index}"),
+        text,
+      });
+    }
+  }
+  Ok(out)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let mut by_chapter: HashMap<&str, HashMap<usize, String>> = HashMap::new();
+  for (locator, text) in &blocks {
+    if let Some((chapter, index)) = parse_locator(locator) {
+      by_chapter
+        .entry(chapter)
+        .or_default()
+        .insert(index, text.clone());
+    }
+  }
+  let mut archive = ooxml::open(&source).map_err(invalid)?;
+  let chapters = chapters(&mut archive).map_err(invalid)?;
+  let total = chapters.len();
+  let progress = |done| {
+    let _ = app.emit(
+      "epub-export-progress",
+      Progress {
+        job_id,
+        done,
+        total,
+      },
+    );
+  };
+  progress(0);
+  let mut replaced = HashMap::new();
+  for (done, chapter) in chapters.into_iter().enumerate() {
+    if let Some(texts) = by_chapter.get(chapter.as_str()) {
+      let xml = ooxml::read_part(&mut archive, &chapter).map_err(invalid)?;
+      let (_, rewritten) = walk(&xml, Some(texts)).map_err(invalid)?;
+      replaced.insert(chapter, rewritten);
+    }
+    progress(done + 1);
+  }
+  ooxml::rewrite(&source, path, &replaced).map_err(invalid)
+}
+
+/// Writes the translated copy of EPUB task `job_id` to `path`, reporting
+/// `epub-export-progress` events as chapters are done.
+#[tauri::command]
+pub async fn export_translated_epub(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,161 @@
+//! `estimate_job`: what translating a document will cost and how long it
+//! will take, worked out locally before a job is confirmed. The document
+//! is read and split as an upload would (see `analysis::read` and
+//! `segmentation`); each segment is one provider request carrying the
+//! backend's instructions and the text, and is answered by a translation.
+//!
+//! Tokens are counted by `segmentation::tokens_for`: with the model's own
+//! BPE vocabulary for OpenAI models, estimated for others.
+//!
+//! Prices are the user's (see `set_usage_config`) or, for models it has
+//! none for, list prices of well-known ones in US dollars.
+
+use serde::Serialize;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  analysis, formats,
+  proxy::ProxyError,
+  ratelimit::RateLimiter,
+  segmentation, segments,
+  settings::SettingsState,
+  usage::{self, Price, UsageState},
+};
+
+/// Tokens of the backend's instructions and the chat framing, per request.
+const PROMPT_TOKENS: u64 = 75;
+/// Tokens a translation has per token of its source, by direction.
+const OUTPUT_RATIO: &[(&str, f64)] = &[("en->zh", 1.5), ("zh->en", 0.8)];
+/// Time a request takes before the translation comes, and how fast it
+/// comes then.
+const LATENCY_SECONDS: f64 = 1.5;
+const OUTPUT_TOKENS_PER_SECOND: f64 = 50.0;
+
+/// List prices per million input and output tokens, by host and model
+/// prefix; the longest prefix wins.
+const LIST_PRICES: &[(&str, &str, f64, f64)] = &[
+  ("api.openai.com", "gpt-4o-mini", 0.15, 0.60),
+  ("api.openai.com", "gpt-4o", 2.50, 10.00),
+  ("api.openai.com", "gpt-4.1-nano", 0.10, 0.40),
+  ("api.openai.com", "gpt-4.1-mini", 0.40, 1.60),
+  ("api.openai.com", "gpt-4.1", 2.00, 8.00),
+  ("api.openai.com", "gpt-3.5-turbo", 0.50, 1.50),
+  ("api.deepseek.com", "deepseek-chat", 0.27, 1.10),
+  ("api.deepseek.com", "deepseek-reasoner", 0.55, 2.19),
+];
+
+#[derive(Serialize)]
+pub struct Estimate {
+  pub provider: String,
+  pub model: String,
+  /// Provider requests, one per segment or chunk.
+  pub requests: usize,
+  /// Read by OCR when translated, and so not counted.
+  pub scanned_pages: usize,
+  pub input_tokens: u64,
+  pub output_tokens: u64,
+  /// `None` when there is no price for the model.
+  pub cost: Option<f64>,
+  pub currency: String,
+  /// `configured` or `list`, see the module docs.
+  pub prices: Option<&'static str>,
+  /// Translating one request after another, as a job does, within the
+  /// provider's rate limit.
+  pub seconds: f64,
+}
+
+fn list_price(provider: &str, model: &str) -> Option<Price> {
+  LIST_PRICES
+    .iter()
+    .filter(|(host, prefix, ..)| *host == provider && model.starts_with(prefix))
+    .max_by_key(|(_, prefix, ..)| prefix.len())
+    .map(|&(_, _, input, output)| Price {
+      input_per_million_tokens: input,
+      output_per_million_tokens: output,
+    })
+}
+
+fn estimate(app: &AppHandle, path: &Path, provider: &str, model: &str) -> Result<Estimate, String> {
+  let settings = app.state::<SettingsState>().get();
+  let source_lang = settings.direction.split_once("->").map_or("", |(s, _)| s);
+  let max_tokens = if segments::chunked(&formats::route(path)) {
+    settings.max_segment_tokens
+  } else {
+    0
+  };
+  let mut requests = 0;
+  let mut text_tokens = 0;
+  let (_, scanned_pages) = analysis::read(app, path, |batch| {
+    for segment in segmentation::split(batch, source_lang, max_tokens) {
+      requests += 1;
+      text_tokens += segmentation::tokens_for(&segment.text, model) as u64;
+    }
+  })?;
+
+  let ratio = OUTPUT_RATIO
+    .iter()
+    .find(|(direction, _)| *direction == settings.direction)
+    .map_or(1.0, |(_, ratio)| *ratio);
+  let input_tokens = text_tokens + PROMPT_TOKENS * requests as u64;
+  let output_tokens = (text_tokens as f64 * ratio).round() as u64;
+
+  let usage = app.state::<UsageState>().config();
+  let (price, currency, prices) = match usage::price(&usage, provider, model) {
+    Some(price) => (
+      Some(price.clone()),
+      usage.currency.clone(),
+      Some("configured"),
+    ),
+    None => match list_price(provider, model) {
+      Some(price) => (Some(price), "USD".to_string(), Some("list")),
+      None => (None, usage.currency.clone(), None),
+    },
+  };
+  let cost = price.map(|p| {
+    (input_tokens as f64 * p.input_per_million_tokens
+      + output_tokens as f64 * p.output_per_million_tokens)
+      / 1_000_000.0
+  });
+
+  let working = requests as f64 * LATENCY_SECONDS + output_tokens as f64 / OUTPUT_TOKENS_PER_SECOND;
+  let limited = app
+    .state::<RateLimiter>()
+    .config()
+    .providers
+    .get(provider)
+    .map_or(0.0, |limit| {
+      requests as f64 * 60.0 / f64::from(limit.requests_per_minute)
+    });
+
+  Ok(Estimate {
+    provider: provider.to_string(),
+    model: model.to_string(),
+    requests,
+    scanned_pages,
+    input_tokens,
+    output_tokens,
+    cost,
+    currency,
+    prices,
+    seconds: working.max(limited),
+  })
+}
+
+/// Estimated tokens, cost and duration of translating the document at
+/// `doc_id` (its path) with `model` at `provider` (its host, as in the
+/// prices), in the direction in the settings.
+#[tauri::command]
+pub async fn estimate_job(
+  app: AppHandle,
+  doc_id: String,
+  provider: String,
+  model: String,
+) -> Result<Estimate, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    estimate(&app, Path::new(&doc_id), &provider.to_lowercase(), &model)
+      .map_err(|e| ProxyError::new("invalid-request", e))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
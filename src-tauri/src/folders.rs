@@ -0,0 +1,220 @@
+//! Folders translated as a batch: `translate_folder` walks a directory
+//! tree, queues a job (see `jobs`) for every document in it that can be
+//! translated and `include`/`exclude` let through, and has each
+//! translated copy written to the output folder under the same
+//! subfolders as its original, named by the output template. The batch
+//! is reported as `folder-progress` events while its jobs run.
+//!
+//! Filters are glob patterns (`*`, `?`, `[...]`, `**`) matched against
+//! paths relative to the folder, with `/` between names, e.g. `*.docx` or
+//! `drafts/**`; `*` matches across folders too. A folder an `exclude`
+//! pattern matches is not walked at all. Hidden files and folders, and
+//! links to folders, are skipped.
+
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  formats,
+  jobs::{self, Placement},
+  proxy::{invalid, ProxyError},
+  settings::SettingsState,
+};
+
+/// Documents one batch queues at most.
+const MAX_DOCUMENTS: usize = 10_000;
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct FolderOptions {
+  /// Patterns a document must match one of; all documents when empty.
+  include: Vec<String>,
+  exclude: Vec<String>,
+  /// Defaults to `<folder>.<target language>` next to the folder.
+  output_dir: Option<String>,
+  /// Defaults to the one in the settings.
+  direction: Option<String>,
+  priority: i32,
+}
+
+#[derive(Serialize)]
+pub struct Skipped {
+  path: String,
+  reason: String,
+}
+
+#[derive(Serialize)]
+pub struct FolderBatch {
+  /// Carried by the batch's `folder-progress` events and jobs.
+  id: String,
+  output_dir: String,
+  jobs: Vec<jobs::Job>,
+  /// Files the filters let through that cannot be translated.
+  skipped: Vec<Skipped>,
+}
+
+struct Filters {
+  include: Vec<Pattern>,
+  exclude: Vec<Pattern>,
+}
+
+impl Filters {
+  fn new(options: &FolderOptions) -> Result<Self, ProxyError> {
+    let compile = |patterns: &[String]| {
+      patterns
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| Pattern::new(p.trim()).map_err(|e| invalid(format!("Invalid pattern {p}: {e}"))))
+        .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(Self {
+      include: compile(&options.include)?,
+      exclude: compile(&options.exclude)?,
+    })
+  }
+
+  fn excluded(&self, relative: &str) -> bool {
+    self.exclude.iter().any(|p| p.matches(relative))
+  }
+
+  fn included(&self, relative: &str) -> bool {
+    self.include.is_empty() || self.include.iter().any(|p| p.matches(relative))
+  }
+}
+
+/// `path` relative to `root`, with `/` between names.
+fn relative(root: &Path, path: &Path) -> String {
+  let relative = path.strip_prefix(root).unwrap_or(path);
+  relative
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// Adds the files under `dir` the filters let through to `found`, in name
+/// order; `skip` (the output folder) is not walked.
+fn walk(
+  root: &Path,
+  dir: &Path,
+  skip: &Path,
+  filters: &Filters,
+  found: &mut Vec<PathBuf>,
+) -> Result<(), ProxyError> {
+  let read_err = |e: std::io::Error| invalid(format!("Cannot read {}: {e}", dir.display()));
+  let mut entries = fs::read_dir(dir)
+    .map_err(read_err)?
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(read_err)?;
+  entries.sort_by_key(|e| e.file_name());
+  for entry in entries {
+    let path = entry.path();
+    if entry.file_name().to_string_lossy().starts_with('.') || path == skip {
+      continue;
+    }
+    let Ok(kind) = entry.file_type() else {
+      continue;
+    };
+    let relative = relative(root, &path);
+    if filters.excluded(&relative) {
+      continue;
+    }
+    if kind.is_dir() {
+      walk(root, &path, skip, filters, found)?;
+    } else if path.is_file() && filters.included(&relative) {
+      if found.len() == MAX_DOCUMENTS {
+        return Err(invalid(format!(
+          "{} has more than {MAX_DOCUMENTS} documents; narrow it down with include patterns",
+          root.display()
+        )));
+      }
+      found.push(path);
+    }
+  }
+  Ok(())
+}
+
+fn translate(
+  app: &AppHandle,
+  path: &Path,
+  options: FolderOptions,
+) -> Result<FolderBatch, ProxyError> {
+  if !path.is_dir() {
+    return Err(invalid(format!("{} is not a folder", path.display())));
+  }
+  let filters = Filters::new(&options)?;
+  let direction = options
+    .direction
+    .unwrap_or_else(|| app.state::<SettingsState>().get().direction);
+  let output_dir = match options.output_dir {
+    Some(dir) => PathBuf::from(dir),
+    None => {
+      let target = direction.split_once("->").map_or("translated", |(_, t)| t);
+      let name = path.file_name().unwrap_or_default().to_string_lossy();
+      path.with_file_name(format!("{name}.{target}"))
+    }
+  };
+  if output_dir == path {
+    return Err(invalid(
+      "The output folder must not be the folder translated".to_string(),
+    ));
+  }
+  let mut found = Vec::new();
+  walk(path, path, &output_dir, &filters, &mut found)?;
+  let id = uuid::Uuid::new_v4().simple().to_string();
+  let mut documents = Vec::new();
+  let mut skipped = Vec::new();
+  for file in found {
+    match formats::format(&formats::route(&file)) {
+      Some(format) if format.translatable => {
+        let dir = file
+          .parent()
+          .and_then(|d| d.strip_prefix(path).ok())
+          .map(|d| output_dir.join(d))
+          .unwrap_or_else(|| output_dir.clone());
+        documents.push((
+          file,
+          Placement {
+            folder: Some(id.clone()),
+            output_dir: Some(dir),
+            ..Default::default()
+          },
+        ));
+      }
+      format => skipped.push(Skipped {
+        reason: match format {
+          Some(format) => format!("{} files cannot be translated yet", format.name),
+          None => format!("unsupported file type .{}", formats::extension(&file)),
+        },
+        path: file.display().to_string(),
+      }),
+    }
+  }
+  Ok(FolderBatch {
+    id,
+    output_dir: output_dir.display().to_string(),
+    jobs: jobs::enqueue(app, documents, &direction, options.priority),
+    skipped,
+  })
+}
+
+/// Queues a job for every document in the folder at `path` and its
+/// subfolders that `options` let through, the translations going to the
+/// output folder in the same layout.
+#[tauri::command]
+pub async fn translate_folder(
+  app: AppHandle,
+  path: String,
+  options: Option<FolderOptions>,
+) -> Result<FolderBatch, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    translate(&app, Path::new(&path), options.unwrap_or_default())
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
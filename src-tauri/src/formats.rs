@@ -0,0 +1,463 @@
+//! What a document is. The formats the app offers, and the sniffing that
+//! tells them apart by content, so a file with a wrong or missing extension
+//! still goes to the right parser: PDFs and images by their signature,
+//! Office, OpenDocument and EPUB files (all zips) by the parts they contain
+//! (password-protected Office files by their extension), text by its byte
+//! order mark and then its content.
+//!
+//! Content only overrides the extension when it is certain: a binary
+//! format, or text whose extension names no format at all. A `.txt` file
+//! that happens to start with `# ` is still read as plain text.
+
+use serde::Serialize;
+use std::{borrow::Cow, fs::File, io::Read, path::Path};
+
+use crate::{charset, proxy::ProxyError};
+
+/// Formats the pickers offer; `translatable` ones the backend accepts today.
+pub const FORMATS: &[Format] = &[
+  Format {
+    name: "Word document",
+    extensions: &["docx"],
+    kind: "docx",
+    translatable: true,
+  },
+  Format {
+    name: "PowerPoint presentation",
+    extensions: &["pptx"],
+    kind: "pptx",
+    translatable: true,
+  },
+  Format {
+    name: "Excel workbook",
+    extensions: &["xlsx"],
+    kind: "xlsx",
+    translatable: true,
+  },
+  Format {
+    name: "OpenDocument text",
+    extensions: &["odt"],
+    kind: "odt",
+    translatable: true,
+  },
+  Format {
+    name: "OpenDocument spreadsheet",
+    extensions: &["ods"],
+    kind: "ods",
+    translatable: true,
+  },
+  Format {
+    name: "OpenDocument presentation",
+    extensions: &["odp"],
+    kind: "odp",
+    translatable: true,
+  },
+  Format {
+    name: "EPUB e-book",
+    extensions: &["epub"],
+    kind: "epub",
+    translatable: true,
+  },
+  Format {
+    name: "PDF",
+    extensions: &["pdf"],
+    kind: "pdf",
+    translatable: true,
+  },
+  Format {
+    name: "SubRip subtitles",
+    extensions: &["srt"],
+    kind: "srt",
+    translatable: true,
+  },
+  Format {
+    name: "WebVTT subtitles",
+    extensions: &["vtt"],
+    kind: "vtt",
+    translatable: true,
+  },
+  Format {
+    name: "Markdown",
+    extensions: &["md", "markdown"],
+    kind: "markdown",
+    translatable: true,
+  },
+  Format {
+    name: "HTML page",
+    extensions: &["html", "htm"],
+    kind: "html",
+    translatable: true,
+  },
+  Format {
+    name: "LaTeX document",
+    extensions: &["tex"],
+    kind: "latex",
+    translatable: true,
+  },
+  Format {
+    name: "RTF document",
+    extensions: &["rtf"],
+    kind: "rtf",
+    translatable: true,
+  },
+  Format {
+    name: "gettext catalog",
+    extensions: &["po", "pot"],
+    kind: "po",
+    translatable: true,
+  },
+  Format {
+    name: "XLIFF",
+    extensions: &["xlf", "xliff"],
+    kind: "xliff",
+    translatable: true,
+  },
+  Format {
+    name: "JSON resources",
+    extensions: &["json"],
+    kind: "json",
+    translatable: true,
+  },
+  Format {
+    name: "Flutter ARB",
+    extensions: &["arb"],
+    kind: "json",
+    translatable: true,
+  },
+  Format {
+    name: "YAML resources",
+    extensions: &["yml", "yaml"],
+    kind: "yaml",
+    translatable: true,
+  },
+  Format {
+    name: "Apple strings",
+    extensions: &["strings"],
+    kind: "strings",
+    translatable: true,
+  },
+  Format {
+    name: "Image",
+    extensions: &["png", "jpg", "jpeg"],
+    kind: "image",
+    translatable: true,
+  },
+  Format {
+    name: "CSV table",
+    extensions: &["csv", "tsv"],
+    kind: "csv",
+    translatable: true,
+  },
+  Format {
+    name: "Plain text",
+    extensions: &["txt"],
+    kind: "text",
+    translatable: true,
+  },
+];
+
+pub struct Format {
+  pub name: &'static str,
+  pub extensions: &'static [&'static str],
+  /// What [`sniff`] calls its content.
+  pub kind: &'static str,
+  pub translatable: bool,
+}
+
+/// Enough of the file to recognize its format.
+const SNIFF_BYTES: usize = 8192;
+/// How far into the file a PDF header may be; some writers put junk first.
+const PDF_HEADER_WITHIN: usize = 1024;
+/// Kinds told by signature or container, never guessed.
+const CERTAIN: &[&str] = &[
+  "pdf", "image", "docx", "pptx", "xlsx", "odt", "ods", "odp", "epub",
+];
+
+/// What the content of a file is.
+pub struct Sniffed {
+  /// `docx`, `pptx`, `xlsx`, `odt`, `ods`, `odp`, `epub`, `pdf`, `image`,
+  /// `srt`, `vtt`, `markdown`, `html`, `latex`, `rtf`, `po`, `xliff`,
+  /// `json`, `yaml`, `strings`, `text` or `unknown`.
+  pub kind: &'static str,
+  /// The extension such content has, e.g. `jpg` for a JPEG; empty for
+  /// `unknown`.
+  pub extension: &'static str,
+  /// The encoding named by the byte order mark text starts with.
+  pub bom: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct DetectedFormat {
+  /// What the content is; see [`Sniffed::kind`].
+  pub kind: String,
+  /// The extension the file is read as; empty if no parser fits it.
+  pub extension: String,
+  /// The file's own extension, lower-case, without the dot.
+  pub file_extension: String,
+  /// The format it is read as, e.g. `Word document`.
+  pub name: Option<String>,
+  /// `UTF-8`, `UTF-16LE`, `UTF-16BE`, `UTF-32LE` or `UTF-32BE` for text
+  /// with a byte order mark.
+  pub bom: Option<String>,
+  pub translatable: bool,
+}
+
+/// The offered format files with `extension` are.
+pub fn format(extension: &str) -> Option<&'static Format> {
+  FORMATS.iter().find(|f| f.extensions.contains(&extension))
+}
+
+/// Lower-case, without the dot; empty if none.
+pub fn extension(path: &Path) -> String {
+  path
+    .extension()
+    .map(|e| e.to_string_lossy().to_ascii_lowercase())
+    .unwrap_or_default()
+}
+
+fn bom(head: &[u8]) -> Option<(&'static str, usize)> {
+  // UTF-32LE starts like UTF-16LE, so it goes first
+  [
+    ("UTF-8", &b"\xef\xbb\xbf"[..]),
+    ("UTF-32LE", b"\xff\xfe\0\0"),
+    ("UTF-32BE", b"\0\0\xfe\xff"),
+    ("UTF-16LE", b"\xff\xfe"),
+    ("UTF-16BE", b"\xfe\xff"),
+  ]
+  .into_iter()
+  .find(|(_, mark)| head.starts_with(mark))
+  .map(|(name, mark)| (name, mark.len()))
+}
+
+/// The sample as text, or `None` if it is binary.
+fn text<'a>(head: &'a [u8], bom: Option<(&str, usize)>) -> Option<Cow<'a, str>> {
+  let utf32 = |rest: &[u8], word: fn([u8; 4]) -> u32| {
+    rest
+      .chunks_exact(4)
+      .map(|c| char::from_u32(word([c[0], c[1], c[2], c[3]])).unwrap_or('\u{fffd}'))
+      .collect::<String>()
+  };
+  let (encoding, rest) = match bom {
+    Some((name, len)) => (name, &head[len..]),
+    None => ("", head),
+  };
+  Some(match encoding {
+    "UTF-16LE" => encoding_rs::UTF_16LE.decode_without_bom_handling(rest).0,
+    "UTF-16BE" => encoding_rs::UTF_16BE.decode_without_bom_handling(rest).0,
+    "UTF-32LE" => utf32(rest, u32::from_le_bytes).into(),
+    "UTF-32BE" => utf32(rest, u32::from_be_bytes).into(),
+    _ => match std::str::from_utf8(rest) {
+      Ok(text) => text.into(),
+      // a character cut off at the end of the sample
+      Err(e) if e.error_len().is_none() => std::str::from_utf8(&rest[..e.valid_up_to()])
+        .unwrap_or_default()
+        .into(),
+      // GBK, Shift-JIS and other legacy text; binary files have NULs
+      Err(_) if !rest.contains(&0) => charset::guess(rest).decode(rest).0,
+      Err(_) => return None,
+    },
+  })
+}
+
+/// Office files and EPUB books are zips: EPUB and OpenDocument files name
+/// themselves in a `mimetype` entry, Office files their main part in
+/// `[Content_Types].xml`. `None` if
+/// the archive cannot be read, e.g. because only its start is there.
+fn container(path: &Path) -> Option<&'static str> {
+  let mut archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+  let mut read = |name: &str| {
+    let mut text = String::new();
+    archive
+      .by_name(name)
+      .ok()?
+      .take(1 << 20)
+      .read_to_string(&mut text)
+      .ok()?;
+    Some(text)
+  };
+  let mimetype = read("mimetype").unwrap_or_default();
+  match mimetype.trim() {
+    "application/epub+zip" => return Some("epub"),
+    "application/vnd.oasis.opendocument.text" => return Some("odt"),
+    "application/vnd.oasis.opendocument.spreadsheet" => return Some("ods"),
+    "application/vnd.oasis.opendocument.presentation" => return Some("odp"),
+    _ => {}
+  }
+  let types = read("[Content_Types].xml").unwrap_or_default();
+  let names: Vec<&str> = archive.file_names().collect();
+  let has = |main_type: &str, part: &str| types.contains(main_type) || names.contains(&part);
+  Some(
+    if has("wordprocessingml.document.main", "word/document.xml") {
+      "docx"
+    } else if has("presentationml.presentation.main", "ppt/presentation.xml") {
+      "pptx"
+    } else if has("spreadsheetml.sheet.main", "xl/workbook.xml") {
+      "xlsx"
+    } else if names.contains(&"META-INF/container.xml") {
+      "epub"
+    } else {
+      "unknown"
+    },
+  )
+}
+
+/// Guesses from the first entries for archives [`container`] cannot open.
+fn zip_head(head: &[u8]) -> &'static str {
+  let has = |needle: &[u8]| head.windows(needle.len()).any(|w| w == needle);
+  if has(b"application/epub+zip") {
+    "epub"
+  } else if has(b"opendocument.text") {
+    "odt"
+  } else if has(b"opendocument.spreadsheet") {
+    "ods"
+  } else if has(b"opendocument.presentation") {
+    "odp"
+  } else if has(b"word/") {
+    "docx"
+  } else if has(b"ppt/") {
+    "pptx"
+  } else if has(b"xl/") {
+    "xlsx"
+  } else {
+    "unknown"
+  }
+}
+
+/// What text is: its markers first, the extension only for formats with
+/// none (YAML, Markdown).
+fn text_kind(text: &str, extension: &str) -> &'static str {
+  let text = text.trim_start_matches('\u{feff}');
+  let lower = text.trim_start().to_ascii_lowercase();
+  if text.contains('\0') {
+    "unknown"
+  } else if text.starts_with("{\\rtf") {
+    "rtf"
+  } else if text.starts_with("WEBVTT") {
+    "vtt"
+  } else if lower.starts_with('<') && text.contains("<xliff") {
+    "xliff"
+  } else if lower.starts_with('<') && lower.contains("<html") || lower.starts_with("<!doctype html")
+  {
+    "html"
+  } else if text.contains("\\documentclass") || text.contains("\\begin{document}") {
+    "latex"
+  } else if text.contains("\nmsgid \"") || text.starts_with("msgid \"") {
+    "po"
+  } else if text.contains(" --> ") && text.trim_start().starts_with(|c: char| c.is_ascii_digit()) {
+    "srt"
+  } else if lower.starts_with('{') {
+    "json"
+  } else if text.contains("\" = \"") && text.contains("\";") {
+    "strings"
+  } else if matches!(extension, "yml" | "yaml") || text.starts_with("---") {
+    "yaml"
+  } else if matches!(extension, "md" | "markdown") || text.starts_with("# ") {
+    "markdown"
+  } else {
+    "text"
+  }
+}
+
+/// What the file at `path` is; `None` if it cannot be read.
+pub fn sniff(path: &Path) -> Option<Sniffed> {
+  let mut head = Vec::with_capacity(SNIFF_BYTES);
+  File::open(path)
+    .ok()?
+    .take(SNIFF_BYTES as u64)
+    .read_to_end(&mut head)
+    .ok()?;
+  let found = |kind: &'static str, extension: &'static str| {
+    Some(Sniffed {
+      kind,
+      extension,
+      bom: None,
+    })
+  };
+  let within = &head[..head.len().min(PDF_HEADER_WITHIN)];
+  if within.windows(5).any(|w| w == b"%PDF-") {
+    return found("pdf", "pdf");
+  }
+  if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+    return found("image", "png");
+  }
+  if head.starts_with(b"\xff\xd8\xff") {
+    return found("image", "jpg");
+  }
+  if head.starts_with(b"PK\x03\x04") {
+    let kind = container(path).unwrap_or_else(|| zip_head(&head));
+    return found(kind, canonical(kind));
+  }
+  if head.starts_with(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+    // an Office file saved with a password, see `protected`; which one
+    // only the extension tells before it is decrypted
+    let encrypted = cfb::open(path).is_ok_and(|file| file.is_stream("/EncryptedPackage"));
+    let kind = match format(&extension(path)).map(|f| f.kind) {
+      _ if !encrypted => "unknown",
+      Some(kind @ ("docx" | "pptx" | "xlsx")) => kind,
+      _ => "docx",
+    };
+    return found(kind, canonical(kind));
+  }
+  let bom = bom(&head);
+  let kind = match text(&head, bom) {
+    Some(text) => text_kind(&text, &extension(path)),
+    None => "unknown",
+  };
+  Some(Sniffed {
+    kind,
+    extension: canonical(kind),
+    bom: bom.map(|(name, _)| name),
+  })
+}
+
+/// The usual extension of `kind`; empty for `unknown`.
+fn canonical(kind: &str) -> &'static str {
+  FORMATS
+    .iter()
+    .find(|f| f.kind == kind)
+    .map_or("", |f| f.extensions[0])
+}
+
+/// The extension a file named `.<extension>` with `sniffed` content is read
+/// as; empty if none fits.
+pub fn read_as(extension: &str, sniffed: &Sniffed) -> String {
+  let certain = |kind| CERTAIN.contains(&kind);
+  match format(extension) {
+    Some(f) if f.kind == sniffed.kind => extension.to_string(),
+    _ if sniffed.kind == "unknown" => String::new(),
+    // a text format that one of the text heuristics took for another
+    Some(f) if !certain(f.kind) && !certain(sniffed.kind) => extension.to_string(),
+    _ => sniffed.extension.to_string(),
+  }
+}
+
+/// The extension the file at `path` is read as; see [`read_as`].
+pub fn route(path: &Path) -> String {
+  sniff(path).map_or_else(String::new, |s| read_as(&extension(path), &s))
+}
+
+pub fn detect(path: &Path) -> Option<DetectedFormat> {
+  let sniffed = sniff(path)?;
+  let file_extension = extension(path);
+  let extension = read_as(&file_extension, &sniffed);
+  let format = format(&extension);
+  Some(DetectedFormat {
+    kind: sniffed.kind.to_string(),
+    file_extension,
+    name: format.map(|f| f.name.to_string()),
+    bom: sniffed.bom.map(str::to_string),
+    translatable: format.is_some_and(|f| f.translatable),
+    extension,
+  })
+}
+
+/// What the file at `path` is by its content, and the parser it goes to.
+#[tauri::command]
+pub async fn detect_format(path: String) -> Result<DetectedFormat, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    detect(Path::new(&path))
+      .ok_or_else(|| ProxyError::new("invalid-request", format!("Cannot read {path}")))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,506 @@
+//! The glossary: term pairs every translation is held to, kept in
+//! `<data dir>/glossary.json`, which the backend reads too. The terms of a
+//! block's text go into its prompt (see `build_messages` there), and
+//! `check_glossary` flags, once a task is translated, the blocks whose
+//! translations miss a term or use a forbidden one.
+//!
+//! A term is for a direction and applies the other way round too, its
+//! target then being the source. A forbidden term names a target that must
+//! not be used: in translations of texts with its source when it has one,
+//! else in any; it applies in its own direction only. Terms starting or
+//! ending in an ASCII letter or digit match whole words only, and unless
+//! case-sensitive, in any case.
+//!
+//! Terms are imported from CSV (columns `source`, `target`,
+//! `case_sensitive`, `forbidden` and `note`, or the direction's language
+//! codes for the first two; without a header, source, target and note)
+//! and from TBX, deprecated and superseded terms becoming forbidden ones.
+//! An imported term the glossary has replaces it.
+
+use quick_xml::{
+  escape::unescape,
+  events::{BytesStart, Event},
+  Reader,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  borrow::Cow,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Mutex, OnceLock},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  charset, delimited,
+  proxy::{invalid, ProxyError},
+  segments,
+  settings::SettingsState,
+};
+
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+static TERMS: Mutex<Vec<Term>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Term {
+  id: String,
+  /// `en->zh` or `zh->en`.
+  direction: String,
+  /// Empty only for a term forbidden in every translation.
+  source: String,
+  target: String,
+  case_sensitive: bool,
+  /// `target` must not be used, see the module docs.
+  forbidden: bool,
+  note: String,
+}
+
+/// A term to save; without `id`, one the glossary has is replaced, else it
+/// is added.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct TermInput {
+  id: Option<String>,
+  direction: String,
+  source: String,
+  target: String,
+  case_sensitive: bool,
+  forbidden: bool,
+  note: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct GlossaryImport {
+  pub added: usize,
+  pub updated: usize,
+  /// Rows or entries without a term in both languages.
+  pub skipped: usize,
+}
+
+#[derive(Serialize)]
+pub struct Violation {
+  pub block_id: String,
+  /// Of the block in the document, counted from 0.
+  pub index: usize,
+  /// `missing` (the source has the term, the translation not its target)
+  /// or `forbidden` (the translation uses a forbidden term).
+  pub kind: &'static str,
+  pub source: String,
+  pub target: String,
+}
+
+impl Term {
+  /// The source and target of the term in texts translated in
+  /// `direction`, if it applies to them.
+  fn applied(&self, direction: &str) -> Option<(&str, &str)> {
+    if self.direction == direction {
+      return Some((&self.source, &self.target));
+    }
+    let reversed = self
+      .direction
+      .split_once("->")
+      .is_some_and(|(from, to)| format!("{to}->{from}") == direction);
+    (reversed && !self.forbidden).then_some((&self.target, &self.source))
+  }
+
+  /// Whether `other` is this term: one translation per source, and any
+  /// number of forbidden ones.
+  fn same(&self, other: &Term) -> bool {
+    self.direction == other.direction
+      && self.forbidden == other.forbidden
+      && self.source.to_lowercase() == other.source.to_lowercase()
+      && (!self.forbidden || self.target.to_lowercase() == other.target.to_lowercase())
+  }
+}
+
+fn check_direction(direction: &str) -> Result<(), ProxyError> {
+  if matches!(direction, "en->zh" | "zh->en") {
+    Ok(())
+  } else {
+    Err(invalid(format!("Unsupported direction {direction}")))
+  }
+}
+
+fn terms() -> std::sync::MutexGuard<'static, Vec<Term>> {
+  TERMS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Written next to the file and renamed, as the backend may be reading it.
+fn save(terms: &[Term]) -> Result<(), ProxyError> {
+  let Some(file) = FILE.get() else {
+    return Ok(());
+  };
+  let tmp = file.with_extension("json.tmp");
+  serde_json::to_vec_pretty(terms)
+    .map_err(|e| e.to_string())
+    .and_then(|data| fs::write(&tmp, data).map_err(|e| e.to_string()))
+    .and_then(|()| fs::rename(&tmp, file).map_err(|e| e.to_string()))
+    .map_err(|e| ProxyError::new("io", format!("Cannot save the glossary: {e}")))
+}
+
+/// Loads the glossary; called once at startup.
+pub fn init(data_dir: &Path) {
+  let file = data_dir.join("glossary.json");
+  let saved: Vec<Term> = fs::read(&file)
+    .ok()
+    .and_then(|data| serde_json::from_slice(&data).ok())
+    .unwrap_or_default();
+  *terms() = saved;
+  let _ = FILE.set(file);
+}
+
+/// Whether `term` is in `text`, see the module docs.
+fn occurs(term: &str, text: &str, case_sensitive: bool) -> bool {
+  if term.is_empty() {
+    return false;
+  }
+  let (term, text) = if case_sensitive {
+    (Cow::Borrowed(term), Cow::Borrowed(text))
+  } else {
+    (
+      Cow::Owned(term.to_lowercase()),
+      Cow::Owned(text.to_lowercase()),
+    )
+  };
+  let word = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+  let whole_start = word(term.chars().next());
+  let whole_end = word(term.chars().last());
+  text.match_indices(term.as_ref()).any(|(at, found)| {
+    let joined_before = whole_start && word(text[..at].chars().next_back());
+    let joined_after = whole_end && word(text[at + found.len()..].chars().next());
+    !joined_before && !joined_after
+  })
+}
+
+/// Adds `term`, or replaces the one it is; `true` when added.
+fn upsert(terms: &mut Vec<Term>, mut term: Term) -> (Term, bool) {
+  match terms.iter_mut().find(|t| t.same(&term)) {
+    Some(existing) => {
+      term.id = std::mem::take(&mut existing.id);
+      *existing = term.clone();
+      (term, false)
+    }
+    None => {
+      term.id = uuid::Uuid::new_v4().simple().to_string();
+      terms.push(term.clone());
+      (term, true)
+    }
+  }
+}
+
+fn boolean(value: &str) -> bool {
+  matches!(
+    value.trim().to_ascii_lowercase().as_str(),
+    "1" | "true" | "yes" | "y" | "x"
+  )
+}
+
+fn from_csv(text: &str, direction: &str) -> (Vec<Term>, usize) {
+  let mut records = delimited::records(text).into_iter().peekable();
+  let (source_lang, target_lang) = direction.split_once("->").unwrap_or_default();
+  let header: Vec<String> = records
+    .peek()
+    .map(|r| r.iter().map(|f| f.trim().to_lowercase()).collect())
+    .unwrap_or_default();
+  let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+  let columns = match (
+    column(&["source", source_lang]),
+    column(&["target", target_lang]),
+  ) {
+    (Some(source), Some(target)) => {
+      records.next();
+      [
+        Some(source),
+        Some(target),
+        column(&["case_sensitive"]),
+        column(&["forbidden"]),
+        column(&["note"]),
+      ]
+    }
+    _ => [Some(0), Some(1), None, None, Some(2)],
+  };
+  let mut out = Vec::new();
+  let mut skipped = 0;
+  for record in records {
+    let field = |i: Option<usize>| {
+      i.and_then(|i| record.get(i))
+        .map_or("", |f| f.trim())
+        .to_string()
+    };
+    let term = Term {
+      direction: direction.to_string(),
+      source: field(columns[0]),
+      target: field(columns[1]),
+      case_sensitive: boolean(&field(columns[2])),
+      forbidden: boolean(&field(columns[3])),
+      note: field(columns[4]),
+      ..Default::default()
+    };
+    if term.target.is_empty() || term.source.is_empty() && !term.forbidden {
+      skipped += usize::from(record.iter().any(|f| !f.trim().is_empty()));
+      continue;
+    }
+    out.push(term);
+  }
+  (out, skipped)
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid TBX: {e}")
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+  e.try_get_attribute(key)
+    .ok()
+    .flatten()
+    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// `zh-CN` as `zh`.
+fn language(tag: &str) -> String {
+  tag
+    .split(['-', '_'])
+    .next()
+    .unwrap_or_default()
+    .to_ascii_lowercase()
+}
+
+/// A concept's terms as (language, term, deprecated), and its definition.
+#[derive(Default)]
+struct Entry {
+  terms: Vec<(String, String, bool)>,
+  note: String,
+}
+
+impl Entry {
+  fn terms(self, direction: &str) -> Vec<Term> {
+    let (source_lang, target_lang) = direction.split_once("->").unwrap_or_default();
+    let source = self
+      .terms
+      .iter()
+      .find(|(lang, _, deprecated)| lang == source_lang && !deprecated)
+      .map_or("", |(_, term, _)| term.as_str());
+    let mut out = Vec::new();
+    let mut translated = false;
+    for (_, target, deprecated) in self.terms.iter().filter(|(lang, ..)| lang == target_lang) {
+      // the first allowed term is the translation; others are admitted
+      if !deprecated && (translated || source.is_empty()) {
+        continue;
+      }
+      translated |= !deprecated;
+      out.push(Term {
+        direction: direction.to_string(),
+        source: source.to_string(),
+        target: target.clone(),
+        forbidden: *deprecated,
+        note: self.note.clone(),
+        ..Default::default()
+      });
+    }
+    out
+  }
+}
+
+/// TBX 2 (`termEntry`, `langSet`, `tig`) and 3 (`conceptEntry`,
+/// `langSec`, `termSec`) alike.
+fn from_tbx(text: &str, direction: &str) -> Result<(Vec<Term>, usize), String> {
+  let mut reader = Reader::from_reader(text.as_bytes());
+  let mut out = Vec::new();
+  let mut skipped = 0;
+  let mut entry: Option<Entry> = None;
+  let mut lang = String::new();
+  let mut seen_root = false;
+  loop {
+    match reader.read_event().map_err(xml_err)? {
+      Event::Start(e) => match e.local_name().as_ref() {
+        b"martif" | b"tbx" => seen_root = true,
+        b"termEntry" | b"conceptEntry" => entry = Some(Entry::default()),
+        b"langSet" | b"langSec" => {
+          lang = language(
+            &attribute(&e, "xml:lang")
+              .or_else(|| attribute(&e, "lang"))
+              .unwrap_or_default(),
+          )
+        }
+        name @ (b"term" | b"termNote" | b"descrip") => {
+          let kind = attribute(&e, "type").unwrap_or_default();
+          let raw = reader.read_text(e.name()).map_err(xml_err)?;
+          let value = unescape(&raw).map_err(xml_err)?.trim().to_string();
+          let Some(entry) = &mut entry else {
+            continue;
+          };
+          match name {
+            b"term" if !value.is_empty() => entry.terms.push((lang.clone(), value, false)),
+            b"termNote" if kind == "administrativeStatus" => {
+              if let Some(last) = entry.terms.last_mut() {
+                last.2 = value.starts_with("deprecated") || value.starts_with("superseded");
+              }
+            }
+            b"descrip" if kind == "definition" && entry.note.is_empty() => entry.note = value,
+            _ => {}
+          }
+        }
+        _ => {}
+      },
+      Event::End(e) if matches!(e.local_name().as_ref(), b"termEntry" | b"conceptEntry") => {
+        if let Some(entry) = entry.take() {
+          let terms = entry.terms(direction);
+          skipped += usize::from(terms.is_empty());
+          out.extend(terms);
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  if !seen_root {
+    return Err(xml_err("no <martif> or <tbx> element"));
+  }
+  Ok((out, skipped))
+}
+
+#[tauri::command]
+pub fn list_glossary() -> Vec<Term> {
+  terms().clone()
+}
+
+#[tauri::command]
+pub fn save_term(term: TermInput) -> Result<Term, ProxyError> {
+  check_direction(&term.direction)?;
+  let input = Term {
+    id: term.id.clone().unwrap_or_default(),
+    direction: term.direction,
+    source: term.source.trim().to_string(),
+    target: term.target.trim().to_string(),
+    case_sensitive: term.case_sensitive,
+    forbidden: term.forbidden,
+    note: term.note.trim().to_string(),
+  };
+  if input.target.is_empty() || input.source.is_empty() && !input.forbidden {
+    return Err(invalid(
+      "A term needs a source and a target, a forbidden one at least a target".to_string(),
+    ));
+  }
+  let mut terms = terms();
+  let saved = match term.id {
+    Some(id) => {
+      let existing = terms
+        .iter_mut()
+        .find(|t| t.id == id)
+        .ok_or_else(|| invalid(format!("Unknown term {id}")))?;
+      *existing = input.clone();
+      input
+    }
+    None => upsert(&mut terms, input).0,
+  };
+  save(&terms)?;
+  Ok(saved)
+}
+
+#[tauri::command]
+pub fn delete_term(id: String) -> Result<Vec<Term>, ProxyError> {
+  let mut terms = terms();
+  terms.retain(|t| t.id != id);
+  save(&terms)?;
+  Ok(terms.clone())
+}
+
+/// Adds the terms of the CSV or TBX file at `path` for `direction` (the
+/// one in the settings when `None`).
+#[tauri::command]
+pub async fn import_glossary(
+  app: AppHandle,
+  path: String,
+  direction: Option<String>,
+) -> Result<GlossaryImport, ProxyError> {
+  let direction = direction.unwrap_or_else(|| app.state::<SettingsState>().get().direction);
+  check_direction(&direction)?;
+  tauri::async_runtime::spawn_blocking(move || {
+    let path = Path::new(&path);
+    let data =
+      fs::read(path).map_err(|e| invalid(format!("Cannot read {}: {e}", path.display())))?;
+    let text = charset::decode(&data).text;
+    let tbx = matches!(
+      path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .as_deref(),
+      Some("tbx" | "xml")
+    );
+    let (found, skipped) = if tbx {
+      from_tbx(&text, &direction).map_err(invalid)?
+    } else {
+      from_csv(&text, &direction)
+    };
+    let mut terms = terms();
+    let mut report = GlossaryImport {
+      skipped,
+      ..Default::default()
+    };
+    for term in found {
+      if upsert(&mut terms, term).1 {
+        report.added += 1;
+      } else {
+        report.updated += 1;
+      }
+    }
+    save(&terms)?;
+    Ok(report)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// How block `index` of a task translated in `direction` breaks the
+/// glossary; nothing while it has no translation.
+pub fn violations(direction: &str, index: usize, row: &segments::Row) -> Vec<Violation> {
+  let Some(translation) = &row.translation else {
+    return Vec::new();
+  };
+  let mut out = Vec::new();
+  for term in terms().iter() {
+    let Some((source, target)) = term.applied(direction) else {
+      continue;
+    };
+    let case = term.case_sensitive;
+    let kind = if term.forbidden {
+      let applies = source.is_empty() || occurs(source, &row.source, case);
+      if !applies || !occurs(target, translation, case) {
+        continue;
+      }
+      "forbidden"
+    } else {
+      if !occurs(source, &row.source, case) || occurs(target, translation, case) {
+        continue;
+      }
+      "missing"
+    };
+    out.push(Violation {
+      block_id: row.id.clone(),
+      index,
+      kind,
+      source: source.to_string(),
+      target: target.to_string(),
+    });
+  }
+  out
+}
+
+/// The blocks of task `task_id` whose translations break the glossary.
+#[tauri::command]
+pub async fn check_glossary(app: AppHandle, task_id: String) -> Result<Vec<Violation>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let direction = segments::info(&app, &task_id)?.direction;
+    let rows = segments::rows(&app, &task_id)?;
+    Ok(
+      rows
+        .iter()
+        .enumerate()
+        .flat_map(|(index, row)| violations(&direction, index, row))
+        .collect(),
+    )
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+  backend::{self, BackendEvent},
+  config::StartupConfig,
+  status::{self, BackendState, BackendStatus},
+  transport,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// A successful probe slower than this counts as degraded.
+const SLOW_PROBE: Duration = Duration::from_millis(1500);
+/// Consecutive failed probes before the backend is reported down.
+const DOWN_AFTER: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Health {
+  Healthy,
+  Degraded,
+  Down,
+}
+
+/// Polls the health endpoint for the whole session and emits `backend-status`
+/// whenever the health level changes.
+pub fn spawn_monitor(app: AppHandle) {
+  std::thread::spawn(move || {
+    let cfg = app.state::<StartupConfig>().inner().clone();
+    let mut last: Option<Health> = None;
+    let mut failures = 0u32;
+
+    loop {
+      std::thread::sleep(POLL_INTERVAL);
+      if backend::is_stopping(&app) {
+        return;
+      }
+      // the supervisor reports its own status while relaunching
+      let Some(base) = app.state::<BackendState>().base_url() else {
+        last = None;
+        failures = 0;
+        continue;
+      };
+
+      let start = Instant::now();
+      let ok = transport::get(&format!("{}{}", base, cfg.health_path), PROBE_TIMEOUT)
+        .map(|r| {
+          // a backend without the endpoint still answers HTTP
+          r.is_success() || (r.status == 404 && cfg.tcp_fallback_after > 0)
+        })
+        .unwrap_or(false);
+      let latency = start.elapsed();
+      let latency_ms = latency.as_millis() as u64;
+
+      let (health, event) = if ok {
+        failures = 0;
+        if latency > SLOW_PROBE {
+          (
+            Health::Degraded,
+            BackendEvent::Degraded {
+              latency_ms: Some(latency_ms),
+              failures,
+            },
+          )
+        } else {
+          (Health::Healthy, BackendEvent::Healthy { latency_ms })
+        }
+      } else {
+        failures += 1;
+        if failures >= DOWN_AFTER {
+          (Health::Down, BackendEvent::Down { failures })
+        } else {
+          (
+            Health::Degraded,
+            BackendEvent::Degraded {
+              latency_ms: None,
+              failures,
+            },
+          )
+        }
+      };
+
+      status::update(&app, |current| match (current, health) {
+        (BackendStatus::Degraded { base_url }, Health::Healthy) => Some(BackendStatus::Ready {
+          base_url: base_url.clone(),
+        }),
+        (BackendStatus::Ready { base_url }, Health::Degraded | Health::Down) => {
+          Some(BackendStatus::Degraded {
+            base_url: base_url.clone(),
+          })
+        }
+        _ => None,
+      });
+      if last != Some(health) {
+        last = Some(health);
+        let _ = app.emit("backend-status", event);
+      }
+    }
+  });
+}
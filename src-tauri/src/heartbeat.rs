@@ -0,0 +1,84 @@
+use serde::Deserialize;
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{config::StartupConfig, logs::BackendLog, os};
+
+const PREFIX: &[u8] = b"@@heartbeat ";
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct Payload {
+  stalled_secs: u64,
+}
+
+struct Beat {
+  at: Instant,
+  stalled_secs: u64,
+}
+
+/// Last heartbeat line seen on one backend's stdout.
+#[derive(Default)]
+pub struct Heartbeat(Mutex<Option<Beat>>);
+
+impl Heartbeat {
+  /// Records `line` if it is a heartbeat; those are not logged.
+  pub fn observe(&self, line: &[u8]) -> bool {
+    let Some(rest) = line.strip_prefix(PREFIX) else {
+      return false;
+    };
+    let stalled_secs = serde_json::from_slice::<Payload>(rest.trim_ascii())
+      .map(|p| p.stalled_secs)
+      .unwrap_or(0);
+    *self.0.lock().unwrap() = Some(Beat {
+      at: Instant::now(),
+      stalled_secs,
+    });
+    true
+  }
+}
+
+/// Kills the backend when its heartbeats stop or report a worker stuck for
+/// longer than `worker_stall_secs`; the exit then goes through the usual
+/// supervisor path. Only armed after the first heartbeat, so backends that
+/// don't send any are left alone.
+pub fn spawn_watchdog(
+  app: AppHandle,
+  pid: u32,
+  exited: Arc<AtomicBool>,
+  heartbeat: Arc<Heartbeat>,
+  log_tag: String,
+) {
+  let cfg = app.state::<StartupConfig>().inner().clone();
+  let timeout = Duration::from_secs(cfg.heartbeat_timeout_secs);
+  std::thread::spawn(move || loop {
+    std::thread::sleep(CHECK_INTERVAL);
+    if exited.load(Ordering::SeqCst) {
+      return;
+    }
+    let reason = match heartbeat.0.lock().unwrap().as_ref() {
+      None => None,
+      Some(beat) if beat.at.elapsed() > timeout => {
+        Some(format!("no heartbeat for {}s", beat.at.elapsed().as_secs()))
+      }
+      Some(beat) if cfg.worker_stall_secs > 0 && beat.stalled_secs > cfg.worker_stall_secs => Some(
+        format!("a worker made no progress for {}s", beat.stalled_secs),
+      ),
+      Some(_) => None,
+    };
+    if let Some(reason) = reason {
+      app.state::<BackendLog>().append(
+        &format!("{log_tag}shell"),
+        format!("backend pid {pid} looks hung ({reason}); killing it").as_bytes(),
+      );
+      os::kill_tree(pid);
+      return;
+    }
+  });
+}
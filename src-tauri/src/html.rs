@@ -0,0 +1,540 @@
+//! HTML pages read and rebuilt in the shell, like `markdown`: the
+//! translated copy is the source with each segment's text spliced in, so
+//! markup, whitespace, scripts and styles outside segments stay byte for
+//! byte.
+//!
+//! Segments are the runs of text and inline elements between block
+//! boundaries, plus the `alt`, `title`, `placeholder` and `aria-label`
+//! attributes and the page's meta descriptions. Inline tags go to the
+//! translator as placeholders, `⟦1⟧here⟦/1⟧` for an element and `⟦2⟧` for
+//! a break, image or code; a translation whose placeholders no longer
+//! nest is written without its inline formatting and reported. `pre`,
+//! `textarea`, SVG and MathML content is left alone.
+//!
+//! Locators are `html:<start>-<end>`, the byte range of the segment's text
+//! or attribute value in the source.
+
+use quick_xml::escape::{escape, partial_escape, unescape};
+use std::{collections::HashMap, fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{balanced, marker, pieces, Piece, Role, Tag, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+/// Elements that flow with the text; any other tag ends a segment.
+const INLINE: &[&str] = &[
+  "a", "abbr", "b", "bdi", "bdo", "big", "br", "cite", "data", "del", "dfn", "em", "font", "i",
+  "img", "ins", "label", "mark", "q", "s", "small", "span", "strike", "strong", "sub", "sup",
+  "time", "tt", "u", "wbr",
+];
+/// Inline elements kept whole, content included.
+const LOCKED: &[&str] = &["code", "kbd", "samp"];
+/// Elements whose content is never translated.
+const SKIPPED: &[&str] = &[
+  "pre", "script", "style", "textarea", "svg", "math", "template",
+];
+/// Elements whose content is not markup.
+const RAW: &[&str] = &["script", "style", "textarea"];
+const VOID: &[&str] = &[
+  "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+  "track", "wbr",
+];
+const ATTRIBUTES: &[&str] = &["alt", "title", "placeholder", "aria-label"];
+/// `<meta>` names and properties whose `content` is prose.
+const META: &[&str] = &[
+  "description",
+  "keywords",
+  "og:title",
+  "og:description",
+  "twitter:title",
+  "twitter:description",
+];
+
+enum Kind {
+  Text,
+  /// Name (lower-case), attributes with their value ranges and whether
+  /// the value is quoted, and whether the tag closes itself.
+  Start(String, Vec<(String, Range<usize>, bool)>, bool),
+  End(String),
+  /// Comments, doctypes, processing instructions and raw text.
+  Other,
+}
+
+struct Token {
+  range: Range<usize>,
+  kind: Kind,
+}
+
+/// The tag at `at` (a `<`), or `None` if the `<` is text.
+fn tag(source: &str, at: usize) -> Option<Token> {
+  let rest = &source[at..];
+  let b = rest.as_bytes();
+  let until =
+    |pattern: &str, extra: usize| rest.find(pattern).map_or(source.len(), |i| at + i + extra);
+  if rest.starts_with("<!--") {
+    return Some(Token {
+      range: at..until("-->", 3),
+      kind: Kind::Other,
+    });
+  }
+  if rest.starts_with("<!") || rest.starts_with("<?") {
+    return Some(Token {
+      range: at..until(">", 1),
+      kind: Kind::Other,
+    });
+  }
+  let closing = rest.starts_with("</");
+  let name_start = if closing { 2 } else { 1 };
+  if !b.get(name_start)?.is_ascii_alphabetic() {
+    return None;
+  }
+  let name_len = rest[name_start..]
+    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+    .unwrap_or(rest.len() - name_start);
+  let name = rest[name_start..name_start + name_len].to_ascii_lowercase();
+  if closing {
+    return Some(Token {
+      range: at..until(">", 1),
+      kind: Kind::End(name),
+    });
+  }
+  let mut attributes = Vec::new();
+  let mut i = name_start + name_len;
+  let skip_space = |i: &mut usize| {
+    while b.get(*i).is_some_and(u8::is_ascii_whitespace) {
+      *i += 1;
+    }
+  };
+  loop {
+    skip_space(&mut i);
+    match b.get(i) {
+      None => {
+        return Some(Token {
+          range: at..source.len(),
+          kind: Kind::Start(name, attributes, false),
+        })
+      }
+      Some(b'>') => {
+        return Some(Token {
+          range: at..at + i + 1,
+          kind: Kind::Start(name, attributes, false),
+        })
+      }
+      Some(b'/') if b.get(i + 1) == Some(&b'>') => {
+        return Some(Token {
+          range: at..at + i + 2,
+          kind: Kind::Start(name, attributes, true),
+        })
+      }
+      Some(b'/') => {
+        i += 1;
+        continue;
+      }
+      Some(_) => {}
+    }
+    let key_start = i;
+    while b
+      .get(i)
+      .is_some_and(|c| !c.is_ascii_whitespace() && !matches!(c, b'=' | b'>' | b'/'))
+    {
+      i += 1;
+    }
+    let key = rest[key_start..i].to_ascii_lowercase();
+    skip_space(&mut i);
+    if b.get(i) != Some(&b'=') {
+      attributes.push((key, at + i..at + i, false));
+      continue;
+    }
+    i += 1;
+    skip_space(&mut i);
+    match b.get(i) {
+      Some(&quote) if quote == b'"' || quote == b'\'' => {
+        let end = rest[i + 1..]
+          .find(quote as char)
+          .map_or(rest.len(), |n| i + 1 + n);
+        attributes.push((key, at + i + 1..at + end, true));
+        i = (end + 1).min(rest.len());
+      }
+      _ => {
+        let value_start = i;
+        while b
+          .get(i)
+          .is_some_and(|c| !c.is_ascii_whitespace() && *c != b'>')
+        {
+          i += 1;
+        }
+        attributes.push((key, at + value_start..at + i, false));
+      }
+    }
+  }
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+  let mut out = Vec::new();
+  let (mut at, mut text) = (0, 0);
+  while let Some(found) = source[at..].find('<') {
+    let start = at + found;
+    let Some(token) = tag(source, start) else {
+      at = start + 1;
+      continue;
+    };
+    if text < start {
+      out.push(Token {
+        range: text..start,
+        kind: Kind::Text,
+      });
+    }
+    at = token.range.end;
+    let raw = match &token.kind {
+      Kind::Start(name, _, false) if RAW.contains(&name.as_str()) => Some(format!("</{name}")),
+      _ => None,
+    };
+    out.push(token);
+    if let Some(end_tag) = raw {
+      let end = source[at..]
+        .to_ascii_lowercase()
+        .find(&end_tag)
+        .map_or(source.len(), |i| at + i);
+      if at < end {
+        out.push(Token {
+          range: at..end,
+          kind: Kind::Other,
+        });
+      }
+      at = end;
+    }
+    text = at;
+  }
+  if text < source.len() {
+    out.push(Token {
+      range: text..source.len(),
+      kind: Kind::Text,
+    });
+  }
+  out
+}
+
+struct Unit {
+  range: Range<usize>,
+  /// The text to translate, placeholders included.
+  text: String,
+  /// Placeholder `n` at `n - 1`; none for attributes.
+  tags: Vec<Tag>,
+  attribute_quoted: Option<bool>,
+}
+
+fn decode(text: &str) -> String {
+  unescape(text).map_or_else(|_| text.to_string(), |t| t.into_owned())
+}
+
+fn collapse(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The segment of inline tokens `run`, trimmed of surrounding whitespace.
+fn inline_unit(source: &str, tokens: &[Token], run: &[(usize, Option<usize>)]) -> Option<Unit> {
+  let is_blank = |&(i, _): &(usize, Option<usize>)| {
+    matches!(tokens[i].kind, Kind::Text) && source[tokens[i].range.clone()].trim().is_empty()
+  };
+  let first = run.iter().position(|t| !is_blank(t))?;
+  let last = run.iter().rposition(|t| !is_blank(t))?;
+  let run = &run[first..=last];
+  if !run
+    .iter()
+    .any(|&(i, _)| matches!(tokens[i].kind, Kind::Text))
+  {
+    return None;
+  }
+
+  // pair start and end tags, innermost first
+  let mut pairs: HashMap<usize, usize> = HashMap::new();
+  let mut open: Vec<(usize, &str)> = Vec::new();
+  for (n, &(i, _)) in run.iter().enumerate() {
+    match &tokens[i].kind {
+      Kind::Start(name, _, false) if !VOID.contains(&name.as_str()) => open.push((n, name)),
+      Kind::End(name) => {
+        if let Some(at) = open.iter().rposition(|(_, open)| open == name) {
+          pairs.insert(open[at].0, n);
+          open.truncate(at);
+        }
+      }
+      _ => {}
+    }
+  }
+  let closes: HashMap<usize, usize> = pairs.iter().map(|(&s, &e)| (e, s)).collect();
+
+  let trimmed = |i: usize| {
+    let range = tokens[i].range.clone();
+    let text = &source[range.clone()];
+    let start = range.start + (text.len() - text.trim_start().len());
+    let end = range.end - (text.len() - text.trim_end().len());
+    start..end
+  };
+  let (i0, _) = run[0];
+  let (i1, end1) = run[run.len() - 1];
+  let start = match tokens[i0].kind {
+    Kind::Text => trimmed(i0).start,
+    _ => tokens[i0].range.start,
+  };
+  let end = match (&tokens[i1].kind, end1) {
+    (_, Some(end)) => tokens[end].range.end,
+    (Kind::Text, None) => trimmed(i1).end,
+    _ => tokens[i1].range.end,
+  };
+
+  let mut text = String::new();
+  let mut tags: Vec<Tag> = Vec::new();
+  // placeholder number of each paired start tag, by run position
+  let mut numbers: HashMap<usize, usize> = HashMap::new();
+  for (n, &(i, locked_end)) in run.iter().enumerate() {
+    let range = tokens[i].range.clone();
+    let (range, role) = match (&tokens[i].kind, locked_end) {
+      (_, Some(end)) => (range.start..tokens[end].range.end, Role::Whole),
+      (Kind::Text, None) => {
+        text.push_str(&decode(&source[range]));
+        continue;
+      }
+      _ if closes.contains_key(&n) => {
+        let number = numbers[&closes[&n]];
+        tags[number - 1].1 = Role::Element(range);
+        text.push_str(&marker(number, true));
+        continue;
+      }
+      _ if pairs.contains_key(&n) => {
+        numbers.insert(n, tags.len() + 1);
+        // the end tag comes later
+        (range, Role::Whole)
+      }
+      (Kind::Start(name, _, false), None) if !VOID.contains(&name.as_str()) => (range, Role::Opens),
+      (Kind::End(_), None) => (range, Role::Closes),
+      _ => (range, Role::Whole),
+    };
+    tags.push((range, role));
+    text.push_str(&marker(tags.len(), false));
+  }
+  Some(Unit {
+    range: start..end,
+    text: collapse(&text),
+    tags,
+    attribute_quoted: None,
+  })
+}
+
+fn units(source: &str) -> Vec<Unit> {
+  let tokens = tokenize(source);
+  let mut out = Vec::new();
+  // token indexes of the current run, with where a locked element ends
+  let mut run: Vec<(usize, Option<usize>)> = Vec::new();
+  let mut i = 0;
+  while i < tokens.len() {
+    let token = &tokens[i];
+    match &token.kind {
+      Kind::Start(name, attributes, closed) => {
+        let meta_prose = name == "meta"
+          && attributes.iter().any(|(key, value, _)| {
+            matches!(key.as_str(), "name" | "property")
+              && META.contains(&source[value.clone()].to_ascii_lowercase().as_str())
+          });
+        for (key, value, quoted) in attributes {
+          let prose = ATTRIBUTES.contains(&key.as_str()) || meta_prose && key == "content";
+          let text = collapse(&decode(&source[value.clone()]));
+          if prose && text.chars().any(char::is_alphabetic) {
+            out.push(Unit {
+              range: value.clone(),
+              text,
+              tags: Vec::new(),
+              attribute_quoted: Some(*quoted),
+            });
+          }
+        }
+        let end_of = |from: usize| {
+          tokens[from..]
+            .iter()
+            .position(|t| matches!(&t.kind, Kind::End(n) if n == name))
+            .map(|n| from + n)
+        };
+        if *closed || VOID.contains(&name.as_str()) {
+          if INLINE.contains(&name.as_str()) {
+            run.push((i, None));
+          } else {
+            out.extend(inline_unit(source, &tokens, &std::mem::take(&mut run)));
+          }
+        } else if LOCKED.contains(&name.as_str()) {
+          match end_of(i + 1) {
+            Some(end) => {
+              run.push((i, Some(end)));
+              i = end;
+            }
+            None => run.push((i, None)),
+          }
+        } else if SKIPPED.contains(&name.as_str()) {
+          out.extend(inline_unit(source, &tokens, &std::mem::take(&mut run)));
+          i = end_of(i + 1).unwrap_or(tokens.len());
+        } else if INLINE.contains(&name.as_str()) {
+          run.push((i, None));
+        } else {
+          out.extend(inline_unit(source, &tokens, &std::mem::take(&mut run)));
+        }
+      }
+      Kind::End(name) if INLINE.contains(&name.as_str()) => run.push((i, None)),
+      Kind::End(_) => out.extend(inline_unit(source, &tokens, &std::mem::take(&mut run))),
+      Kind::Text => run.push((i, None)),
+      // comments and the like inside text stay where they are
+      Kind::Other if !run.is_empty() => run.push((i, None)),
+      Kind::Other => {}
+    }
+    i += 1;
+  }
+  out.extend(inline_unit(source, &tokens, &run));
+  out.sort_by_key(|u| u.range.start);
+  out
+}
+
+/// `source[range]` with the translated attributes inside it.
+fn render(source: &str, range: &Range<usize>, attributes: &[(Range<usize>, String)]) -> String {
+  let mut out = String::new();
+  let mut at = range.start;
+  for (value, text) in attributes {
+    if value.start >= at && value.end <= range.end {
+      out.push_str(&source[at..value.start]);
+      out.push_str(text);
+      at = value.end;
+    }
+  }
+  out.push_str(&source[at..range.end]);
+  out
+}
+
+fn read(data: &[u8]) -> Result<&str, String> {
+  std::str::from_utf8(data).map_err(|_| "HTML pages must be UTF-8".to_string())
+}
+
+fn locator(range: &Range<usize>) -> String {
+  format!("html:{}-{}", range.start, range.end)
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  Ok(
+    units(read(&data)?)
+      .into_iter()
+      // numbers, lone placeholders and other segments without words
+      .filter(|u| u.text.chars().any(char::is_alphabetic))
+      .map(|u| Segment {
+        locator: locator(&u.range),
+        text: u.text,
+      })
+      .collect(),
+  )
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let source = read(&task.source).map_err(invalid)?;
+  let units = units(source);
+  let translated = |u: &Unit| translations.get(locator(&u.range).as_str()).copied();
+
+  let mut attributes: Vec<(Range<usize>, String)> = units
+    .iter()
+    .filter_map(|u| {
+      let quoted = u.attribute_quoted?;
+      let value = escape(translated(u)?).into_owned();
+      Some((
+        u.range.clone(),
+        if quoted {
+          value
+        } else {
+          format!("\"{value}\"")
+        },
+      ))
+    })
+    .collect();
+  attributes.sort_by_key(|(range, _)| range.start);
+
+  let mut warnings = Vec::new();
+  let mut replaced: Vec<(Range<usize>, String)> = Vec::new();
+  for unit in units.iter().filter(|u| u.attribute_quoted.is_none()) {
+    let Some(translation) = translated(unit) else {
+      continue;
+    };
+    let tag = |range: &Range<usize>| render(source, range, &attributes);
+    let pieces = pieces(translation);
+    let mut out = String::new();
+    if balanced(&pieces, &unit.tags) {
+      for piece in pieces {
+        match piece {
+          Piece::Text(text) => out.push_str(&partial_escape(text)),
+          Piece::Tag(number, false) => out.push_str(&tag(&unit.tags[number - 1].0)),
+          Piece::Tag(number, true) => {
+            if let (_, Role::Element(end)) = &unit.tags[number - 1] {
+              out.push_str(&tag(end));
+            }
+          }
+        }
+      }
+    } else {
+      warnings.push(Warning {
+        excerpt: unit.text.chars().take(40).collect(),
+        message: "the inline tags did not survive translation; written without them".to_string(),
+      });
+      // unmatched tags stay so the page still nests; elements go
+      let text: String = pieces
+        .iter()
+        .filter_map(|p| match p {
+          Piece::Text(text) => Some(*text),
+          Piece::Tag(..) => None,
+        })
+        .collect();
+      let kept = |keep: fn(&Role) -> bool| -> String {
+        unit
+          .tags
+          .iter()
+          .filter(|(_, role)| keep(role))
+          .map(|(range, _)| tag(range))
+          .collect()
+      };
+      out.push_str(&kept(|role| matches!(role, Role::Opens)));
+      out.push_str(&partial_escape(collapse(&text).as_str()));
+      out.push_str(&kept(|role| matches!(role, Role::Whole | Role::Closes)));
+    }
+    replaced.push((unit.range.clone(), out));
+  }
+  // attributes of block tags, outside any text segment
+  for (range, value) in &attributes {
+    if !replaced
+      .iter()
+      .any(|(r, _)| r.start <= range.start && range.end <= r.end)
+    {
+      replaced.push((range.clone(), value.clone()));
+    }
+  }
+  replaced.sort_by_key(|(range, _)| range.start);
+
+  let mut out = String::with_capacity(source.len());
+  let mut at = 0;
+  for (range, text) in replaced {
+    out.push_str(&source[at..range.start]);
+    out.push_str(&text);
+    at = range.end;
+  }
+  out.push_str(&source[at..]);
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// Writes the translated copy of HTML task `job_id` to `path`. Returns the
+/// segments written without their inline tags.
+#[tauri::command]
+pub async fn export_translated_html(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,404 @@
+//! Screenshots and other PNG or JPEG images. Their text is read by OCR
+//! (see `ocr`) and each translation drawn over its region: on a fill of
+//! the colour around the region, in the colour of the source text, wrapped
+//! and shrunk to fit. Images are set in the fonts PDFs are (see
+//! `pdfexport`). The export can put the original and the translation side
+//! by side instead.
+//!
+//! Images under 1600 pixels wide are taken for screenshots and read at
+//! twice their size: Tesseract misses small print at screen resolution.
+//!
+//! Locators are `img:<left>,<top>,<right>,<bottom>:<line height>`, in
+//! pixels of the source.
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::{fs, io::Cursor, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  ocr::{self, PageProgress},
+  pdfexport,
+  pdftext::is_cjk,
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+/// Narrower images are read at twice their size.
+const UPSCALE_BELOW: u32 = 1600;
+/// What screenshots are shown at, and scans are usually made at.
+const SCREEN_DPI: u32 = 96;
+const SCAN_DPI: u32 = 300;
+const LINE_HEIGHT: f32 = 1.2;
+/// Translations that do not fit their region are shrunk down to this
+/// share of the source's size, and overflow below it after that.
+const MIN_SCALE: f32 = 0.5;
+/// Pixels around a region painted over too, for antialiased edges.
+const PAD: u32 = 2;
+/// Between the original and the translation side by side.
+const GAP: u32 = 16;
+
+struct Region {
+  left: u32,
+  top: u32,
+  right: u32,
+  bottom: u32,
+  line_height: u32,
+}
+
+impl Region {
+  fn parse(locator: &str) -> Option<Region> {
+    let (edges, line_height) = locator.strip_prefix("img:")?.split_once(':')?;
+    let edges: Vec<u32> = edges
+      .split(',')
+      .map(|n| n.parse().ok())
+      .collect::<Option<_>>()?;
+    let [left, top, right, bottom] = edges[..] else {
+      return None;
+    };
+    Some(Region {
+      left,
+      top,
+      right,
+      bottom,
+      line_height: line_height.parse().ok()?,
+    })
+  }
+
+  fn format(&self) -> String {
+    format!(
+      "img:{},{},{},{}:{}",
+      self.left, self.top, self.right, self.bottom, self.line_height
+    )
+  }
+}
+
+pub fn segments(path: &Path, ocr: &ocr::Session) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let source = image::load_from_memory(&data).map_err(|e| format!("Cannot read the image: {e}"))?;
+  let (scale, dpi, input) = if source.width() < UPSCALE_BELOW {
+    let larger = source.resize_exact(
+      source.width() * 2,
+      source.height() * 2,
+      imageops::FilterType::CatmullRom,
+    );
+    let mut png = Cursor::new(Vec::new());
+    larger
+      .write_to(&mut png, ImageFormat::Png)
+      .map_err(|e| format!("Cannot prepare the image for OCR: {e}"))?;
+    (2.0, SCREEN_DPI * 2, png.into_inner())
+  } else {
+    (1.0, SCAN_DPI, data)
+  };
+
+  let mut progress = PageProgress {
+    page: 1,
+    done: 1,
+    total: 1,
+    confidence: None,
+    blocks: 0,
+    error: None,
+  };
+  let found = match ocr.recognize(&input, dpi) {
+    Ok(found) => found,
+    Err(e) => {
+      progress.error = Some(e.clone());
+      ocr.report(progress);
+      return Err(e);
+    }
+  };
+  let px = |v: f64| (v / scale).round().max(0.0) as u32;
+  let out: Vec<Segment> = found
+    .paragraphs
+    .into_iter()
+    .map(|p| Segment {
+      locator: Region {
+        left: px(p.left),
+        top: px(p.top),
+        right: px(p.right),
+        bottom: px(p.bottom),
+        line_height: px(p.line_height).max(1),
+      }
+      .format(),
+      text: p.text,
+    })
+    .collect();
+  progress.confidence = found.confidence;
+  progress.blocks = out.len();
+  ocr.report(progress);
+  Ok(out)
+}
+
+fn distance(a: &Rgba<u8>, b: &Rgba<u8>) -> u32 {
+  a.0[..3]
+    .iter()
+    .zip(&b.0[..3])
+    .map(|(x, y)| u32::from(x.abs_diff(*y)))
+    .sum()
+}
+
+/// The background around `region` (the median of the pixels just outside
+/// it) and its text colour (the mean of the pixels inside that differ most
+/// from the background).
+fn colours(image: &RgbaImage, region: &Region) -> (Rgba<u8>, Rgba<u8>) {
+  let (width, height) = image.dimensions();
+  let left = region.left.saturating_sub(PAD + 1);
+  let top = region.top.saturating_sub(PAD + 1);
+  let right = (region.right + PAD + 1).min(width - 1);
+  let bottom = (region.bottom + PAD + 1).min(height - 1);
+  let mut ring = Vec::new();
+  for x in left..=right {
+    ring.push(*image.get_pixel(x, top));
+    ring.push(*image.get_pixel(x, bottom));
+  }
+  for y in top..=bottom {
+    ring.push(*image.get_pixel(left, y));
+    ring.push(*image.get_pixel(right, y));
+  }
+  let mut background = Rgba([255, 255, 255, 255]);
+  for channel in 0..3 {
+    ring.sort_by_key(|p| p.0[channel]);
+    background.0[channel] = ring[ring.len() / 2].0[channel];
+  }
+
+  let inside: Vec<&Rgba<u8>> = (region.top..region.bottom.min(height))
+    .flat_map(|y| (region.left..region.right.min(width)).map(move |x| (x, y)))
+    .map(|(x, y)| image.get_pixel(x, y))
+    .collect();
+  let far = inside
+    .iter()
+    .map(|p| distance(p, &background))
+    .max()
+    .unwrap_or(0);
+  if far < 48 {
+    // nothing stands out: plain contrast
+    let light = background.0[..3].iter().map(|&c| u32::from(c)).sum::<u32>() > 384;
+    let text = if light { 0 } else { 255 };
+    return (background, Rgba([text, text, text, 255]));
+  }
+  let ink: Vec<_> = inside
+    .into_iter()
+    .filter(|p| distance(p, &background) * 10 >= far * 7)
+    .collect();
+  let mut text = Rgba([0, 0, 0, 255]);
+  for channel in 0..3 {
+    let sum: usize = ink.iter().map(|p| usize::from(p.0[channel])).sum();
+    text.0[channel] = (sum / ink.len()) as u8;
+  }
+  (background, text)
+}
+
+/// The font of `fonts` to draw `c` in.
+fn font_for<'a>(fonts: &'a [FontRef<'a>], c: char) -> &'a FontRef<'a> {
+  fonts
+    .iter()
+    .find(|f| f.glyph_id(c).0 != 0)
+    .unwrap_or(&fonts[0])
+}
+
+fn width(fonts: &[FontRef], text: &str, size: f32) -> f32 {
+  text
+    .chars()
+    .map(|c| {
+      let font = font_for(fonts, c).as_scaled(PxScale::from(size));
+      font.h_advance(font.glyph_id(c))
+    })
+    .sum()
+}
+
+/// Words, and CJK characters one by one, each with the space after it.
+fn tokens(text: &str) -> Vec<String> {
+  let mut out: Vec<String> = Vec::new();
+  let mut open = false;
+  for c in text.chars() {
+    if c.is_whitespace() {
+      if let Some(last) = out.last_mut() {
+        last.push(' ');
+      }
+      open = false;
+    } else if is_cjk(c) {
+      out.push(c.to_string());
+      open = false;
+    } else if open {
+      out.last_mut().unwrap().push(c);
+    } else {
+      out.push(c.to_string());
+      open = true;
+    }
+  }
+  out
+}
+
+fn wrap(fonts: &[FontRef], text: &str, size: f32, max_width: f32) -> Vec<String> {
+  let mut lines = vec![String::new()];
+  for token in tokens(text) {
+    let line = lines.last_mut().unwrap();
+    let candidate = format!("{line}{token}");
+    if line.is_empty() || width(fonts, candidate.trim_end(), size) <= max_width {
+      *line = candidate;
+    } else {
+      lines.push(token);
+    }
+  }
+  lines.iter().map(|l| l.trim_end().to_string()).collect()
+}
+
+/// Wrapped lines and the size they fit `region` at.
+fn fit(fonts: &[FontRef], text: &str, region: &Region) -> (Vec<String>, f32) {
+  let full = region.line_height as f32;
+  let max_width = region.right.saturating_sub(region.left).max(1) as f32;
+  let max_height = region
+    .bottom
+    .saturating_sub(region.top)
+    .max(region.line_height) as f32;
+  let mut size = full;
+  loop {
+    let lines = wrap(fonts, text, size, max_width);
+    let fits = lines.len() as f32 * size * LINE_HEIGHT <= max_height * 1.1
+      && lines.iter().all(|l| width(fonts, l, size) <= max_width);
+    if fits || size * 0.9 < full * MIN_SCALE {
+      return (lines, size);
+    }
+    size *= 0.9;
+  }
+}
+
+fn blend(image: &mut RgbaImage, x: u32, y: u32, colour: Rgba<u8>, coverage: f32) {
+  if x >= image.width() || y >= image.height() {
+    return;
+  }
+  let pixel = image.get_pixel_mut(x, y);
+  let a = coverage.clamp(0.0, 1.0);
+  for channel in 0..3 {
+    let over = f32::from(colour.0[channel]);
+    let under = f32::from(pixel.0[channel]);
+    pixel.0[channel] = (over * a + under * (1.0 - a)).round() as u8;
+  }
+}
+
+/// Paints over `region` of `out` and draws `text` in it.
+fn draw(out: &mut RgbaImage, source: &RgbaImage, fonts: &[FontRef], region: &Region, text: &str) {
+  let (background, colour) = colours(source, region);
+  let (width, height) = out.dimensions();
+  for y in region.top.saturating_sub(PAD)..(region.bottom + PAD).min(height) {
+    for x in region.left.saturating_sub(PAD)..(region.right + PAD).min(width) {
+      out.put_pixel(x, y, background);
+    }
+  }
+  let (lines, size) = fit(fonts, text, region);
+  let scale = PxScale::from(size);
+  for (n, line) in lines.iter().enumerate() {
+    let mut x = region.left as f32;
+    let y = region.top as f32 + n as f32 * size * LINE_HEIGHT;
+    for c in line.chars() {
+      let font = font_for(fonts, c).as_scaled(scale);
+      let glyph = font
+        .glyph_id(c)
+        .with_scale_and_position(scale, point(x, y + font.ascent()));
+      x += font.h_advance(glyph.id);
+      let Some(outlined) = font.outline_glyph(glyph) else {
+        continue;
+      };
+      let bounds = outlined.px_bounds();
+      outlined.draw(|gx, gy, coverage| {
+        let (px, py) = (bounds.min.x + gx as f32, bounds.min.y + gy as f32);
+        if px >= 0.0 && py >= 0.0 {
+          blend(out, px as u32, py as u32, colour, coverage);
+        }
+      });
+    }
+  }
+}
+
+pub fn export(
+  app: &AppHandle,
+  job_id: &str,
+  path: &Path,
+  side_by_side: bool,
+) -> Result<(), ProxyError> {
+  let data = segments::source(app, job_id)?;
+  let format =
+    image::guess_format(&data).map_err(|e| invalid(format!("Cannot read the image: {e}")))?;
+  let source = image::load_from_memory_with_format(&data, format)
+    .map_err(|e| invalid(format!("Cannot read the image: {e}")))?
+    .to_rgba8();
+  let loaded = pdfexport::load_fonts(app);
+  let fonts: Vec<FontRef> = loaded
+    .iter()
+    .filter_map(|(_, data)| FontRef::try_from_slice(data).ok())
+    .collect();
+  if fonts.is_empty() {
+    return Err(invalid(
+      "No font for the translated text: set pdf_font to a TrueType font (.ttf or .ttc)",
+    ));
+  }
+
+  let mut out = source.clone();
+  for row in segments::rows(app, job_id)? {
+    let (Some(locator), Some(translation)) = (row.locator, row.translation) else {
+      continue;
+    };
+    if let Some(region) = Region::parse(&locator) {
+      draw(&mut out, &source, &fonts, &region, translation.trim());
+    }
+  }
+  if side_by_side {
+    let (width, height) = source.dimensions();
+    let mut both = RgbaImage::from_pixel(width * 2 + GAP, height, Rgba([255, 255, 255, 255]));
+    imageops::replace(&mut both, &source, 0, 0);
+    imageops::replace(&mut both, &out, i64::from(width + GAP), 0);
+    out = both;
+  }
+
+  let format = ImageFormat::from_path(path).unwrap_or(format);
+  let written = match format {
+    // no alpha channel
+    ImageFormat::Jpeg => DynamicImage::ImageRgba8(out)
+      .to_rgb8()
+      .save_with_format(path, format),
+    _ => out.save_with_format(path, format),
+  };
+  written.map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))
+}
+
+/// Writes the translated copy of PNG image task `job_id` to `path`; with
+/// `side_by_side`, next to the original.
+#[tauri::command]
+pub async fn export_translated_png(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+  side_by_side: Option<bool>,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    export(
+      &app,
+      &job_id,
+      Path::new(&path),
+      side_by_side.unwrap_or(false),
+    )
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Writes the translated copy of JPEG image task `job_id` to `path`; with
+/// `side_by_side`, next to the original.
+#[tauri::command]
+pub async fn export_translated_jpg(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+  side_by_side: Option<bool>,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    export(
+      &app,
+      &job_id,
+      Path::new(&path),
+      side_by_side.unwrap_or(false),
+    )
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
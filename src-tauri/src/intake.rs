@@ -0,0 +1,144 @@
+//! Files dropped onto a window. The shell checks each one (a regular file
+//! with a translatable extension, under `max_document_mb`, whose content
+//! matches its extension), hashes it, skips ones already queued, and
+//! reports the outcome as a `documents-dropped` event. Accepted documents
+//! stay queued until the UI takes them with `dequeue_document`.
+
+use serde::Serialize;
+use std::{
+  path::{Path, PathBuf},
+  sync::Mutex,
+  thread,
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{
+  config::StartupConfig,
+  dialogs::{self, PickedDocument},
+  formats, integrity,
+};
+
+#[derive(Default)]
+pub struct IntakeState {
+  queued: Mutex<Vec<QueuedDocument>>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct QueuedDocument {
+  #[serde(flatten)]
+  document: PickedDocument,
+  sha256: String,
+}
+
+#[derive(Serialize)]
+struct Duplicate {
+  path: String,
+  sha256: String,
+  /// The queued copy with the same content.
+  queued_path: String,
+}
+
+#[derive(Serialize)]
+struct Rejected {
+  path: String,
+  reason: String,
+}
+
+/// Payload of `documents-dropped`.
+#[derive(Default, Serialize)]
+struct Dropped {
+  accepted: Vec<QueuedDocument>,
+  duplicates: Vec<Duplicate>,
+  rejected: Vec<Rejected>,
+}
+
+fn check(path: &Path, max_bytes: u64) -> Result<PickedDocument, String> {
+  if path.is_dir() {
+    return Err("folders cannot be dropped; drop the documents in it".to_string());
+  }
+  let document = dialogs::describe(path).ok_or("the file cannot be read")?;
+  let Some(format) = formats::format(&document.read_as) else {
+    return Err(match formats::format(&document.extension) {
+      Some(named) => format!(
+        "the content is not a valid {} (looks like {})",
+        named.name, document.detected_type
+      ),
+      None => format!("unsupported file type .{}", document.extension),
+    });
+  };
+  if !format.translatable {
+    return Err(format!("{} files cannot be translated yet", format.name));
+  }
+  if document.size > max_bytes {
+    return Err(format!(
+      "{} MB is over the {} MB limit",
+      document.size / (1024 * 1024),
+      max_bytes / (1024 * 1024)
+    ));
+  }
+  Ok(document)
+}
+
+fn intake(app: &AppHandle, paths: Vec<PathBuf>) {
+  let max_bytes = app.state::<StartupConfig>().max_document_mb.max(1) * 1024 * 1024;
+  let mut dropped = Dropped::default();
+  for path in paths {
+    let shown = path.display().to_string();
+    let document = match check(&path, max_bytes) {
+      Ok(document) => document,
+      Err(reason) => {
+        dropped.rejected.push(Rejected {
+          path: shown,
+          reason,
+        });
+        continue;
+      }
+    };
+    let sha256 = match integrity::sha256_file(&path) {
+      Ok(hash) => hash,
+      Err(e) => {
+        dropped.rejected.push(Rejected {
+          path: shown,
+          reason: format!("the file cannot be read: {e}"),
+        });
+        continue;
+      }
+    };
+    let state = app.state::<IntakeState>();
+    let mut queued = state.queued.lock().unwrap();
+    if let Some(existing) = queued.iter().find(|q| q.sha256 == sha256) {
+      dropped.duplicates.push(Duplicate {
+        path: shown,
+        sha256,
+        queued_path: existing.document.path.clone(),
+      });
+      continue;
+    }
+    let entry = QueuedDocument { document, sha256 };
+    queued.push(entry.clone());
+    dropped.accepted.push(entry);
+  }
+  let _ = app.emit("documents-dropped", &dropped);
+}
+
+/// Checks dropped files off the event loop; hashing large ones takes a
+/// while.
+pub fn on_drop(app: &AppHandle, paths: Vec<PathBuf>) {
+  let app = app.clone();
+  thread::spawn(move || intake(&app, paths));
+}
+
+#[tauri::command]
+pub fn list_queued_documents(state: State<IntakeState>) -> Vec<QueuedDocument> {
+  state.queued.lock().unwrap().clone()
+}
+
+/// Takes a document off the queue (uploaded or discarded), so dropping it
+/// again is no longer a duplicate.
+#[tauri::command]
+pub fn dequeue_document(state: State<IntakeState>, sha256: String) -> bool {
+  let mut queued = state.queued.lock().unwrap();
+  let before = queued.len();
+  queued.retain(|q| q.sha256 != sha256);
+  queued.len() != before
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+  collections::HashMap,
+  fmt,
+  fs::File,
+  io::{self, Read},
+  path::{Path, PathBuf},
+};
+
+/// SHA-256 of the sidecar as bundled, written by `build.rs`.
+const MANIFEST: &str = include_str!(concat!(env!("OUT_DIR"), "/backend-manifest.json"));
+
+/// Payload of the `backend-integrity-failed` event and the launch error;
+/// like a version mismatch, relaunching won't help.
+#[derive(Debug, Clone, Serialize)]
+pub struct TamperedBackend {
+  pub path: String,
+  pub expected: String,
+  /// `None` if the binary could not be read at all.
+  pub actual: Option<String>,
+}
+
+impl fmt::Display for TamperedBackend {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.actual {
+      Some(actual) => write!(
+        f,
+        "Backend binary {} failed its integrity check (sha256 {actual}, expected {})",
+        self.path, self.expected
+      ),
+      None => write!(f, "Backend binary {} is missing or unreadable", self.path),
+    }
+  }
+}
+
+impl std::error::Error for TamperedBackend {}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+  let mut file = File::open(path)?;
+  let mut hasher = Sha256::new();
+  let mut buf = [0u8; 64 * 1024];
+  loop {
+    let n = file.read(&mut buf)?;
+    if n == 0 {
+      break;
+    }
+    hasher.update(&buf[..n]);
+  }
+  Ok(
+    hasher
+      .finalize()
+      .iter()
+      .map(|b| format!("{b:02x}"))
+      .collect(),
+  )
+}
+
+/// Where the shell plugin looks for the sidecar: next to our executable.
+pub fn sidecar_path(name: &str) -> io::Result<PathBuf> {
+  let exe = std::env::current_exe()?;
+  let dir = exe
+    .parent()
+    .ok_or_else(|| io::Error::other("executable has no parent directory"))?;
+  Ok(dir.join(format!("{name}{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// The bundled hash for `name`; `None` in dev builds made without a staged
+/// sidecar.
+pub fn expected_hash(name: &str) -> Option<String> {
+  let manifest: HashMap<String, String> = serde_json::from_str(MANIFEST).ok()?;
+  manifest.get(name).cloned()
+}
+
+/// Checks `path` against the manifest entry for `name`.
+pub fn verify(name: &str, path: &Path) -> Result<(), TamperedBackend> {
+  match expected_hash(name) {
+    Some(expected) => verify_against(path, &expected),
+    None => Ok(()),
+  }
+}
+
+pub fn verify_against(path: &Path, expected: &str) -> Result<(), TamperedBackend> {
+  let expected = expected.trim().to_ascii_lowercase();
+  let actual = sha256_file(path).ok();
+  if actual.as_deref() == Some(expected.as_str()) {
+    return Ok(());
+  }
+  Err(TamperedBackend {
+    path: path.display().to_string(),
+    expected,
+    actual,
+  })
+}
@@ -0,0 +1,250 @@
+//! Keeps a WebSocket open to the backend's `/api/ws/jobs` and relays it:
+//! backend messages become `job-event` events (circuit-breaker changes also
+//! `provider-status`), the translation of a block as it streams in a
+//! `segment-partial` event, and `send_job_message` writes to the socket.
+//! The job queue (see `jobs`) subscribes the tasks of its running jobs
+//! too, so their partial translations are reported whether or not the
+//! webview watches them.
+//! Reconnects with backoff and follows the backend across restarts;
+//! subscriptions are replayed on every new connection.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+  collections::BTreeSet,
+  io,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, Sender},
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tungstenite::{client::IntoClientRequest, http::HeaderValue, Message, WebSocket};
+
+use crate::{
+  auth, backend, boxed_err, jobs,
+  logs::BackendLog,
+  status::BackendState,
+  transport::{self, Conn, Connected},
+  usage,
+};
+
+const PATH: &str = "/api/ws/jobs";
+/// How often the session loop wakes to send queued messages.
+const POLL: Duration = Duration::from_millis(100);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// A session that lasted this long resets the backoff.
+const STABLE_AFTER: Duration = Duration::from_secs(10);
+
+pub struct JobEvents {
+  tx: Mutex<Sender<String>>,
+  /// Task ids the webview subscribed to, replayed after reconnects.
+  subscriptions: Mutex<BTreeSet<String>>,
+  /// Those of running jobs, subscribed by the queue.
+  job_tasks: Mutex<BTreeSet<String>>,
+  connected: AtomicBool,
+}
+
+#[derive(Clone, Serialize)]
+struct Connection {
+  connected: bool,
+}
+
+/// The translation of a segment so far, the whole of it each time.
+#[derive(Clone, Serialize)]
+struct Partial {
+  /// The queued job translating the task, if one does (see `jobs`).
+  job_id: Option<String>,
+  task_id: String,
+  segment_id: String,
+  text: String,
+}
+
+/// A backend `segment-partial` message as the event the webview gets.
+fn partial(payload: &Value) -> Option<Partial> {
+  let field = |name: &str| payload[name].as_str().map(str::to_string);
+  let task_id = field("task_id")?;
+  Some(Partial {
+    job_id: jobs::job_for_task(&task_id),
+    task_id,
+    segment_id: field("block_id")?,
+    text: field("text")?,
+  })
+}
+
+fn set_connected(app: &AppHandle, connected: bool) {
+  let state = app.state::<JobEvents>();
+  if state.connected.swap(connected, Ordering::SeqCst) != connected {
+    let _ = app.emit("job-events-connection", Connection { connected });
+  }
+}
+
+fn subscribe_message(task_id: &str) -> String {
+  serde_json::json!({ "type": "subscribe", "task_id": task_id }).to_string()
+}
+
+fn open(base_url: &str) -> Result<WebSocket<Conn>, Box<dyn std::error::Error>> {
+  let Some(Connected { conn, host, .. }) = transport::connect(base_url)? else {
+    return Err(boxed_err("job events need a plain http or socket backend"));
+  };
+  conn.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+  let mut request = format!("ws://{host}{PATH}").into_client_request()?;
+  request
+    .headers_mut()
+    .insert(auth::HEADER, HeaderValue::from_str(auth::token())?);
+  let (ws, _) = tungstenite::client(request, conn)
+    .map_err(|e| boxed_err(format!("WebSocket handshake failed: {e}")))?;
+  ws.get_ref().set_read_timeout(Some(POLL))?;
+  Ok(ws)
+}
+
+/// One connection, until it fails or the backend moves to another URL.
+fn session(
+  app: &AppHandle,
+  base_url: &str,
+  rx: &Receiver<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let mut ws = open(base_url)?;
+  set_connected(app, true);
+  let state = app.state::<JobEvents>();
+  let replay: Vec<String> = state
+    .subscriptions
+    .lock()
+    .unwrap()
+    .union(&state.job_tasks.lock().unwrap())
+    .map(|id| subscribe_message(id))
+    .collect();
+  for msg in replay {
+    ws.send(Message::text(msg))?;
+  }
+
+  loop {
+    if backend::is_stopping(app)
+      || app.state::<BackendState>().base_url().as_deref() != Some(base_url)
+    {
+      let _ = ws.close(None);
+      return Ok(());
+    }
+    while let Ok(msg) = rx.try_recv() {
+      ws.send(Message::text(msg))?;
+    }
+    match ws.read() {
+      Ok(Message::Text(text)) => {
+        let payload =
+          serde_json::from_str::<Value>(text.as_str()).unwrap_or(Value::String(text.to_string()));
+        if payload["type"] == "task"
+          && matches!(payload["status"].as_str(), Some("finished" | "error"))
+        {
+          usage::check_budget(app);
+        }
+        if payload["type"] == "segment-partial" {
+          // frequent, and of no use to `job-event` listeners
+          if let Some(partial) = partial(&payload) {
+            let _ = app.emit("segment-partial", partial);
+          }
+          continue;
+        }
+        if payload["type"] == "provider" {
+          let _ = app.emit("provider-status", payload.clone());
+        }
+        let _ = app.emit("job-event", payload);
+      }
+      Ok(Message::Close(_)) => return Ok(()),
+      Ok(_) => {}
+      Err(tungstenite::Error::Io(e))
+        if matches!(
+          e.kind(),
+          io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ) => {}
+      Err(e) => return Err(Box::new(e)),
+    }
+  }
+}
+
+/// Starts the bridge thread; it idles until the backend is ready.
+pub fn spawn(app: AppHandle) {
+  let (tx, rx) = mpsc::channel();
+  app.manage(JobEvents {
+    tx: Mutex::new(tx),
+    subscriptions: Mutex::new(BTreeSet::new()),
+    job_tasks: Mutex::new(BTreeSet::new()),
+    connected: AtomicBool::new(false),
+  });
+  std::thread::spawn(move || {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+      if backend::is_stopping(&app) {
+        return;
+      }
+      let Some(base_url) = app.state::<BackendState>().base_url() else {
+        std::thread::sleep(Duration::from_millis(500));
+        continue;
+      };
+      let started = Instant::now();
+      if let Err(e) = session(&app, &base_url, &rx) {
+        app.state::<BackendLog>().append(
+          "shell",
+          format!("job events connection lost: {e}").as_bytes(),
+        );
+      }
+      set_connected(&app, false);
+      if started.elapsed() > STABLE_AFTER {
+        backoff = Duration::from_secs(1);
+      }
+      std::thread::sleep(backoff);
+      backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+  });
+}
+
+/// Sends one JSON message to the backend's job-events socket, e.g.
+/// `{"type": "subscribe", "task_id": "..."}`. Queued while disconnected.
+#[tauri::command]
+pub fn send_job_message(state: State<JobEvents>, message: Value) -> Result<(), String> {
+  let task_id = message.get("task_id").and_then(Value::as_str);
+  match (message.get("type").and_then(Value::as_str), task_id) {
+    (Some("subscribe"), Some(id)) => {
+      state.subscriptions.lock().unwrap().insert(id.to_string());
+    }
+    (Some("unsubscribe"), Some(id)) => {
+      state.subscriptions.lock().unwrap().remove(id);
+      // a running job still wants its task's messages
+      if state.job_tasks.lock().unwrap().contains(id) {
+        return Ok(());
+      }
+    }
+    _ => {}
+  }
+  state
+    .tx
+    .lock()
+    .unwrap()
+    .send(message.to_string())
+    .map_err(|e| e.to_string())
+}
+
+/// Subscribes to task `task_id` for the job queue, or with `watch` false
+/// drops that subscription, unless the webview has one too.
+pub fn watch_task(app: &AppHandle, task_id: &str, watch: bool) {
+  let state = app.state::<JobEvents>();
+  let kind = if watch {
+    state.job_tasks.lock().unwrap().insert(task_id.to_string());
+    "subscribe"
+  } else {
+    state.job_tasks.lock().unwrap().remove(task_id);
+    if state.subscriptions.lock().unwrap().contains(task_id) {
+      return;
+    }
+    "unsubscribe"
+  };
+  let message = serde_json::json!({ "type": kind, "task_id": task_id });
+  let _ = state.tx.lock().unwrap().send(message.to_string());
+}
+
+#[tauri::command]
+pub fn get_job_events_connected(state: State<JobEvents>) -> bool {
+  state.connected.load(Ordering::SeqCst)
+}
@@ -0,0 +1,810 @@
+//! A queue of whole-document translations run by the shell: `enqueue_job`
+//! adds a document with a priority, and `job_workers` worker threads take
+//! the highest-priority queued job (the oldest among equals) and run it
+//! like an archive entry: upload, translate on the backend, then write the
+//! translated copy where the output policy says (see `output`). Every
+//! change is reported as a `job-progress` event carrying the job, and for
+//! jobs of a `translate_folder` batch also as a `folder-progress` event
+//! summing up the batch.
+//!
+//! `pause_job` holds a queued job back; a running one lets go of its
+//! worker and its backend task is stopped, so no provider requests are
+//! made while it is paused. `cancel_job` stops a job and its backend task
+//! too; either way the provider request in flight is aborted and the
+//! blocks translated so far are kept by the backend, so a job that is
+//! resumed (`resume_job`) in the same session runs its task again from
+//! them. Nothing is written for a cancelled job.
+//!
+//! A job's task translates `concurrency` segments at once, by default
+//! `segment_concurrency` from the settings as they are when it starts
+//! (runs without one use the value the backend was started with, see
+//! [`ENV`]); its requests still go through the provider's rate limit, and
+//! the translations are saved in document order.
+//!
+//! The queue is kept in the `jobs` table of `<data dir>/jobs.db`, a SQLite
+//! database: every change of state writes the jobs it touched in one
+//! transaction, and a job that finishes is deleted. Queued, running and
+//! paused jobs are loaded again at startup, running ones queued again;
+//! their backend tasks did not outlive the backend, so they start over
+//! from the document. Finished jobs are listed for the session only. A
+//! `jobs.json` left by an earlier release is moved into the table. With
+//! encryption at rest the paths and errors are sealed (see `atrest`); the
+//! table is rewritten at startup to follow the setting.
+
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Condvar, Mutex, MutexGuard, OnceLock},
+  thread,
+  time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+  atrest,
+  config::StartupConfig,
+  docx, formats, job_events,
+  logs::BackendLog,
+  output, profiles,
+  proxy::{invalid, ProxyError},
+  settings::SettingsState,
+  upload,
+};
+
+pub const ENV: &str = "MVP_SEGMENT_CONCURRENCY";
+/// Most segments a task translates at once.
+pub const MAX_CONCURRENCY: u32 = 16;
+
+/// How often a running task is looked at.
+const POLL: Duration = Duration::from_secs(1);
+/// How long a worker waits before trying again while the backend is down.
+const NOT_READY_RETRY: Duration = Duration::from_secs(5);
+
+static DB: OnceLock<Mutex<Connection>> = OnceLock::new();
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS jobs(
+  id TEXT PRIMARY KEY,
+  seq INTEGER NOT NULL,
+  priority INTEGER NOT NULL,
+  state TEXT NOT NULL,
+  path TEXT NOT NULL,
+  direction TEXT NOT NULL,
+  folder TEXT,
+  output_dir TEXT,
+  profile_id TEXT,
+  concurrency INTEGER,
+  done_dir TEXT,
+  error TEXT
+)";
+const COLUMNS: &str = "id, seq, priority, state, path, direction, folder, output_dir, \
+  profile_id, concurrency, done_dir, error";
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+  jobs: Vec::new(),
+  next_seq: 0,
+});
+/// Signalled when a job is queued.
+static WAKE: Condvar = Condvar::new();
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JobState {
+  Queued,
+  Running,
+  Paused,
+  Cancelled,
+  Done,
+  Failed,
+}
+
+impl JobState {
+  fn finished(self) -> bool {
+    matches!(self, Self::Cancelled | Self::Done | Self::Failed)
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Self::Queued => "queued",
+      Self::Running => "running",
+      Self::Paused => "paused",
+      Self::Cancelled => "cancelled",
+      Self::Done => "done",
+      Self::Failed => "failed",
+    }
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    [
+      Self::Queued,
+      Self::Running,
+      Self::Paused,
+      Self::Cancelled,
+      Self::Done,
+      Self::Failed,
+    ]
+    .into_iter()
+    .find(|s| s.name() == name)
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Job {
+  id: String,
+  path: String,
+  direction: String,
+  /// Higher runs first.
+  priority: i32,
+  state: JobState,
+  /// Order of enqueueing, among jobs of the same priority.
+  seq: u64,
+  /// The backend task, once one translates the document.
+  task_id: Option<String>,
+  /// `uploading`, `translating` or `writing` while running.
+  stage: Option<String>,
+  progress: f64,
+  /// Where the translated copy was written.
+  output: Option<String>,
+  /// Segments and words by translation memory match band, see `tmx`.
+  leverage: Option<Value>,
+  /// The `translate_folder` batch the job is part of.
+  folder: Option<String>,
+  /// Where the translated copy goes instead of the output policy's folder.
+  output_dir: Option<String>,
+  /// The profile translating the document instead of the active one.
+  profile_id: Option<String>,
+  /// Segments translated at once instead of `segment_concurrency`.
+  concurrency: Option<u32>,
+  /// Where the document is moved once translated.
+  done_dir: Option<String>,
+  error: Option<String>,
+  /// Counts the times a worker took the job, so one that let go of it
+  /// on a pause does not carry on after it was resumed and taken again.
+  #[serde(skip)]
+  run: u64,
+}
+
+impl Job {
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+}
+
+struct Queue {
+  jobs: Vec<Job>,
+  next_seq: u64,
+}
+
+/// Payload of `folder-progress`.
+#[derive(Clone, Serialize)]
+struct FolderProgress<'a> {
+  id: &'a str,
+  total: usize,
+  done: usize,
+  failed: usize,
+  cancelled: usize,
+  /// Of the whole batch, finished jobs counting in full.
+  progress: f64,
+}
+
+/// Reports `job` as changed, and its batch with it; `queue` is let go of
+/// first.
+fn emit(app: &AppHandle, queue: MutexGuard<'_, Queue>, job: &Job) {
+  let folder = job.folder.as_deref().map(|id| {
+    let batch: Vec<&Job> = queue
+      .jobs
+      .iter()
+      .filter(|j| j.folder.as_deref() == Some(id))
+      .collect();
+    let count = |state| batch.iter().filter(|j| j.state == state).count();
+    let progress = batch
+      .iter()
+      .map(|j| if j.state.finished() { 1.0 } else { j.progress })
+      .sum::<f64>();
+    FolderProgress {
+      id,
+      total: batch.len(),
+      done: count(JobState::Done),
+      failed: count(JobState::Failed),
+      cancelled: count(JobState::Cancelled),
+      progress: progress / batch.len().max(1) as f64,
+    }
+  });
+  drop(queue);
+  let _ = app.emit("job-progress", job);
+  if let Some(folder) = folder {
+    let _ = app.emit("folder-progress", folder);
+  }
+}
+
+fn queue() -> MutexGuard<'static, Queue> {
+  QUEUE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn check_concurrency(concurrency: u32) -> Result<(), String> {
+  if !(1..=MAX_CONCURRENCY).contains(&concurrency) {
+    return Err(format!(
+      "Segments translated at once must be 1 to {MAX_CONCURRENCY}; got {concurrency}"
+    ));
+  }
+  Ok(())
+}
+
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let concurrency = app.state::<SettingsState>().get().segment_concurrency;
+  BTreeMap::from([(ENV.to_string(), concurrency.to_string())])
+}
+
+/// Writes `jobs` in one transaction: unfinished ones as they are now,
+/// finished ones deleted.
+fn write<'a>(
+  app: &AppHandle,
+  db: &mut Connection,
+  jobs: impl IntoIterator<Item = &'a Job>,
+) -> rusqlite::Result<()> {
+  let seal = |value: &Option<String>| value.as_deref().map(|v| atrest::seal(app, v));
+  let tx = db.transaction()?;
+  for job in jobs {
+    if job.state.finished() {
+      tx.execute("DELETE FROM jobs WHERE id = ?1", [&job.id])?;
+      continue;
+    }
+    tx.execute(
+      &format!("INSERT OR REPLACE INTO jobs({COLUMNS}) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"),
+      params![
+        job.id,
+        job.seq as i64,
+        job.priority,
+        job.state.name(),
+        atrest::seal(app, &job.path),
+        job.direction,
+        seal(&job.folder),
+        seal(&job.output_dir),
+        job.profile_id,
+        job.concurrency,
+        seal(&job.done_dir),
+        seal(&job.error),
+      ],
+    )?;
+  }
+  tx.commit()
+}
+
+/// Saves `jobs`, so the unfinished ones are there after a restart.
+fn save<'a>(app: &AppHandle, jobs: impl IntoIterator<Item = &'a Job>) {
+  let Some(db) = DB.get() else {
+    return;
+  };
+  let mut db = db.lock().unwrap_or_else(|e| e.into_inner());
+  if let Err(e) = write(app, &mut db, jobs) {
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot save the job queue: {e}").as_bytes(),
+    );
+  }
+}
+
+fn read(row: &Row) -> rusqlite::Result<Job> {
+  let state: String = row.get(3)?;
+  Ok(Job {
+    id: row.get(0)?,
+    seq: row.get::<_, i64>(1)? as u64,
+    priority: row.get(2)?,
+    // saved by a later release
+    state: JobState::parse(&state).unwrap_or(JobState::Paused),
+    path: row.get(4)?,
+    direction: row.get(5)?,
+    folder: row.get(6)?,
+    output_dir: row.get(7)?,
+    profile_id: row.get(8)?,
+    concurrency: row.get(9)?,
+    done_dir: row.get(10)?,
+    error: row.get(11)?,
+    task_id: None,
+    stage: None,
+    progress: 0.0,
+    output: None,
+    leverage: None,
+    run: 0,
+  })
+}
+
+/// Opens the sealed values of `job`; `None` when they were sealed under a
+/// secret that is gone.
+fn unseal(job: &mut Job) -> Option<()> {
+  job.path = atrest::unseal(&job.path)?;
+  for value in [
+    &mut job.folder,
+    &mut job.output_dir,
+    &mut job.done_dir,
+    &mut job.error,
+  ]
+  .into_iter()
+  .flatten()
+  {
+    *value = atrest::unseal(value)?;
+  }
+  Some(())
+}
+
+/// Opens `jobs.db`, moving a `jobs.json` of an earlier release into it,
+/// queues running jobs again and writes them all back as the encryption
+/// setting has it.
+fn open(app: &AppHandle, data_dir: &Path) -> rusqlite::Result<(Connection, Vec<Job>)> {
+  let mut db = Connection::open(data_dir.join("jobs.db"))?;
+  db.execute_batch(SCHEMA)?;
+  let legacy = data_dir.join("jobs.json");
+  if let Some(jobs) = fs::read(&legacy)
+    .ok()
+    .and_then(|data| serde_json::from_slice::<Vec<Job>>(&data).ok())
+  {
+    write(app, &mut db, &jobs)?;
+    let _ = fs::remove_file(&legacy);
+  }
+  db.execute(
+    "UPDATE jobs SET state = 'queued' WHERE state = 'running'",
+    [],
+  )?;
+  let mut jobs = db
+    .prepare(&format!("SELECT {COLUMNS} FROM jobs ORDER BY seq"))?
+    .query_map([], read)?
+    .collect::<rusqlite::Result<Vec<Job>>>()?;
+  for job in &mut jobs {
+    if unseal(job).is_none() {
+      // unreadable: deleted by the write below
+      job.state = JobState::Failed;
+    }
+  }
+  write(app, &mut db, &jobs)?;
+  Ok((db, jobs))
+}
+
+/// Loads the saved queue; called once at startup. Without the database
+/// jobs are kept for the session only.
+pub fn init(app: &AppHandle, data_dir: &Path) {
+  let Ok((db, mut jobs)) = open(app, data_dir) else {
+    return;
+  };
+  jobs.retain(|j| !j.state.finished());
+  let mut queue = queue();
+  queue.next_seq = jobs.iter().map(|j| j.seq + 1).max().unwrap_or(0);
+  queue.jobs = jobs;
+  let _ = DB.set(Mutex::new(db));
+}
+
+/// Applies `change` to job `id` and reports it; saved when `persist`.
+fn update(app: &AppHandle, id: &str, persist: bool, change: impl FnOnce(&mut Job)) -> Option<Job> {
+  let mut queue = queue();
+  let job = queue.jobs.iter_mut().find(|j| j.id == id)?;
+  change(job);
+  let job = job.clone();
+  if persist {
+    save(app, [&job]);
+  }
+  emit(app, queue, &job);
+  Some(job)
+}
+
+/// Whether `job` is still meant to run by the worker that took it: not
+/// paused, cancelled or taken again since.
+fn running(job: &Job) -> bool {
+  queue()
+    .jobs
+    .iter()
+    .any(|j| j.id == job.id && j.run == job.run && j.state == JobState::Running)
+}
+
+/// Waits for the next queued job and marks it running.
+fn take(app: &AppHandle) -> Job {
+  let mut queue = queue();
+  loop {
+    let next = queue
+      .jobs
+      .iter_mut()
+      .filter(|j| j.state == JobState::Queued)
+      .max_by(|a, b| a.priority.cmp(&b.priority).then(b.seq.cmp(&a.seq)));
+    if let Some(job) = next {
+      job.state = JobState::Running;
+      job.run += 1;
+      job.error = None;
+      let job = job.clone();
+      save(app, [&job]);
+      emit(app, queue, &job);
+      return job;
+    }
+    queue = WAKE.wait(queue).unwrap_or_else(|e| e.into_inner());
+  }
+}
+
+/// Creates and starts the backend task for `job`'s document.
+fn start(app: &AppHandle, job: &Job) -> Result<String, ProxyError> {
+  let path = Path::new(&job.path);
+  let created = upload::upload(
+    app,
+    &format!("job-{}", job.id),
+    path,
+    &job.direction,
+    None,
+    docx::Review::default(),
+  )?;
+  let task_id = created["task_id"]
+    .as_str()
+    .ok_or_else(|| ProxyError::new("http", "the backend did not create a task"))?
+    .to_string();
+  output::remember(&task_id, path);
+  translate(app, job, &task_id)?;
+  Ok(task_id)
+}
+
+/// Runs backend task `task_id` with `job`'s profile and concurrency, the
+/// settings' when it has none; blocks already translated are skipped.
+fn translate(app: &AppHandle, job: &Job, task_id: &str) -> Result<(), ProxyError> {
+  let mut body = serde_json::Map::new();
+  if let Some(id) = &job.profile_id {
+    body.insert("profile_id".to_string(), id.as_str().into());
+  }
+  let concurrency = job
+    .concurrency
+    .unwrap_or_else(|| app.state::<SettingsState>().get().segment_concurrency);
+  body.insert("concurrency".to_string(), concurrency.into());
+  profiles::call(
+    app,
+    "POST",
+    &format!("/api/tasks/{task_id}/run_translate"),
+    Some(Value::Object(body)),
+  )?;
+  Ok(())
+}
+
+/// Runs `job` until it is done, fails, or is paused or cancelled, with
+/// its task's messages subscribed to (see `job_events`) meanwhile.
+fn run(app: &AppHandle, job: &Job) -> Result<(), ProxyError> {
+  let mut watched = None;
+  let result = run_watched(app, job, &mut watched);
+  if let Some(task_id) = watched {
+    job_events::watch_task(app, &task_id, false);
+  }
+  result
+}
+
+fn run_watched(app: &AppHandle, job: &Job, watched: &mut Option<String>) -> Result<(), ProxyError> {
+  let progress = |stage: &str, progress: f64| {
+    update(app, &job.id, false, |j| {
+      if j.state == JobState::Running && j.run == job.run {
+        j.stage = Some(stage.to_string());
+        j.progress = progress;
+      }
+    });
+  };
+  let task_id = match &job.task_id {
+    Some(task_id) => task_id.clone(),
+    None => {
+      progress("uploading", 0.0);
+      let task_id = start(app, job)?;
+      // the job may have been paused or cancelled while the task was made,
+      // when there was no task to cancel yet
+      let mut still_running = false;
+      update(app, &job.id, true, |j| {
+        if j.run == job.run {
+          j.task_id = Some(task_id.clone());
+          still_running = j.state == JobState::Running;
+        }
+      });
+      if !still_running {
+        cancel_task(app, &task_id);
+        return Ok(());
+      }
+      task_id
+    }
+  };
+  job_events::watch_task(app, &task_id, true);
+  *watched = Some(task_id.clone());
+  // a job resumed after a pause or cancel runs its task again once it has stopped,
+  // from the blocks it kept
+  let mut resumable = job.task_id.is_some();
+  let leverage = loop {
+    if !running(job) {
+      return Ok(());
+    }
+    let mut task = profiles::call(app, "GET", &format!("/api/tasks/{task_id}"), None)?;
+    match task["status"].as_str() {
+      Some("finished") => break task["leverage"].take(),
+      Some("error") => {
+        return Err(ProxyError::new(
+          "http",
+          task["error"].as_str().unwrap_or("the translation failed"),
+        ))
+      }
+      Some("cancelled") if resumable => {
+        resumable = false;
+        translate(app, job, &task_id)?;
+      }
+      Some("cancelled") => return Err(ProxyError::new("http", "the translation was cancelled")),
+      _ => progress("translating", task["progress"].as_f64().unwrap_or(0.0)),
+    }
+    thread::sleep(POLL);
+  };
+  if !running(job) {
+    return Ok(());
+  }
+  progress("writing", 1.0);
+  let exported = output::export_in(app, &task_id, job.output_dir.as_deref().map(Path::new))?;
+  if let Some(dir) = &job.done_dir {
+    let moved = settle(Path::new(&job.path), Path::new(dir)).map_err(|e| {
+      invalid(format!(
+        "Translated, but cannot move {} to {dir}: {e}",
+        job.path
+      ))
+    })?;
+    output::remember(&task_id, &moved);
+  }
+  update(app, &job.id, true, |j| {
+    if j.state == JobState::Running && j.run == job.run {
+      j.state = JobState::Done;
+      j.stage = None;
+      j.output = (exported.output.action != "skip").then_some(exported.output.path);
+      j.leverage = Some(leverage);
+    }
+  });
+  Ok(())
+}
+
+fn worker(app: AppHandle) {
+  loop {
+    let job = take(&app);
+    if let Err(e) = run(&app, &job) {
+      let retry = e.kind == "not-ready";
+      update(&app, &job.id, true, |j| {
+        if j.state != JobState::Running || j.run != job.run {
+          return;
+        }
+        if retry {
+          j.state = JobState::Queued;
+          j.stage = None;
+        } else {
+          j.state = JobState::Failed;
+          j.error = Some(e.message);
+        }
+      });
+      if retry {
+        thread::sleep(NOT_READY_RETRY);
+        WAKE.notify_all();
+      }
+    }
+  }
+}
+
+/// Starts the `job_workers` worker threads.
+pub fn spawn_workers(app: AppHandle) {
+  let count = app.state::<StartupConfig>().job_workers.max(1);
+  for _ in 0..count {
+    let app = app.clone();
+    thread::spawn(move || worker(app));
+  }
+}
+
+/// Queues the document at `path` for translation in `direction` (by
+/// default the one in the settings), `concurrency` segments at once (by
+/// default `segment_concurrency`); jobs with a higher `priority` run
+/// first, 0 by default.
+#[tauri::command]
+pub fn enqueue_job(
+  app: AppHandle,
+  path: String,
+  direction: Option<String>,
+  priority: Option<i32>,
+  concurrency: Option<u32>,
+) -> Result<Job, ProxyError> {
+  let document = Path::new(&path);
+  if !document.is_file() {
+    return Err(invalid(format!("{path} is not a file")));
+  }
+  if !formats::format(&formats::route(document)).is_some_and(|f| f.translatable) {
+    return Err(invalid(format!("{path} cannot be translated")));
+  }
+  if let Some(concurrency) = concurrency {
+    check_concurrency(concurrency).map_err(invalid)?;
+  }
+  let direction = direction.unwrap_or_else(|| app.state::<SettingsState>().get().direction);
+  let placement = Placement {
+    concurrency,
+    ..Default::default()
+  };
+  let mut jobs = enqueue(
+    &app,
+    vec![(document.to_path_buf(), placement)],
+    &direction,
+    priority.unwrap_or(0),
+  );
+  Ok(jobs.remove(0))
+}
+
+/// How a job differs from one queued with `enqueue_job`.
+#[derive(Default)]
+pub struct Placement {
+  /// The `translate_folder` batch.
+  pub folder: Option<String>,
+  pub output_dir: Option<PathBuf>,
+  pub profile_id: Option<String>,
+  pub concurrency: Option<u32>,
+  pub done_dir: Option<PathBuf>,
+}
+
+/// Moves the document at `path` into `dir`, under a free name.
+fn settle(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+  fs::create_dir_all(dir)?;
+  let mut dest = dir.join(path.file_name().unwrap_or_default());
+  if dest.exists() {
+    dest = output::free(&dest).ok_or_else(|| std::io::Error::other("no free name"))?;
+  }
+  fs::rename(path, &dest)?;
+  Ok(dest)
+}
+
+/// The job that translates backend task `task_id`, if any.
+pub fn job_for_task(task_id: &str) -> Option<String> {
+  queue()
+    .jobs
+    .iter()
+    .find(|j| j.task_id.as_deref() == Some(task_id))
+    .map(|j| j.id.clone())
+}
+
+/// Whether a job for the document at `path` has yet to finish.
+pub fn pending(path: &Path) -> bool {
+  queue()
+    .jobs
+    .iter()
+    .any(|j| !j.state.finished() && Path::new(&j.path) == path)
+}
+
+/// How job `id` ended: where its translated copy went, if anywhere, or
+/// why there is none. `None` while it runs or waits, or if it is not
+/// known.
+pub fn outcome(id: &str) -> Option<Result<Option<String>, String>> {
+  let queue = queue();
+  let job = queue.jobs.iter().find(|j| j.id == id)?;
+  match job.state {
+    JobState::Done => Some(Ok(job.output.clone())),
+    JobState::Failed => Some(Err(job.error.clone().unwrap_or_default())),
+    JobState::Cancelled => Some(Err("cancelled".to_string())),
+    _ => None,
+  }
+}
+
+/// Queues `documents`, known to be translatable, each placed as it says;
+/// they are saved in one transaction.
+pub fn enqueue(
+  app: &AppHandle,
+  documents: Vec<(PathBuf, Placement)>,
+  direction: &str,
+  priority: i32,
+) -> Vec<Job> {
+  let mut queue = queue();
+  let mut jobs = Vec::with_capacity(documents.len());
+  for (path, placement) in documents {
+    jobs.push(Job {
+      id: uuid::Uuid::new_v4().simple().to_string(),
+      path: path.display().to_string(),
+      direction: direction.to_string(),
+      priority,
+      state: JobState::Queued,
+      seq: queue.next_seq,
+      task_id: None,
+      stage: None,
+      progress: 0.0,
+      output: None,
+      leverage: None,
+      folder: placement.folder,
+      output_dir: placement.output_dir.map(|d| d.display().to_string()),
+      profile_id: placement.profile_id,
+      concurrency: placement.concurrency,
+      done_dir: placement.done_dir.map(|d| d.display().to_string()),
+      error: None,
+      run: 0,
+    });
+    queue.next_seq += 1;
+  }
+  queue.jobs.extend(jobs.iter().cloned());
+  save(app, &jobs);
+  drop(queue);
+  for job in &jobs {
+    emit(app, self::queue(), job);
+  }
+  WAKE.notify_all();
+  jobs
+}
+
+/// Moves job `id` from one of the states `from` to `to`.
+fn transition(
+  app: &AppHandle,
+  id: &str,
+  from: &[JobState],
+  to: JobState,
+  verb: &str,
+) -> Result<Job, ProxyError> {
+  let mut allowed = true;
+  let job = update(app, id, true, |j| {
+    allowed = from.contains(&j.state);
+    if allowed {
+      j.state = to;
+      j.stage = None;
+    }
+  })
+  .ok_or_else(|| invalid(format!("No job {id}")))?;
+  if !allowed {
+    return Err(invalid(format!("Job {id} cannot be {verb}")));
+  }
+  Ok(job)
+}
+
+/// Holds job `id` back until it is resumed, stopping its backend task.
+#[tauri::command]
+pub fn pause_job(app: AppHandle, id: String) -> Result<Job, ProxyError> {
+  let job = transition(
+    &app,
+    &id,
+    &[JobState::Queued, JobState::Running],
+    JobState::Paused,
+    "paused",
+  )?;
+  if let Some(task_id) = &job.task_id {
+    cancel_task(&app, task_id);
+  }
+  Ok(job)
+}
+
+/// Queues paused or cancelled job `id` again.
+#[tauri::command]
+pub fn resume_job(app: AppHandle, id: String) -> Result<Job, ProxyError> {
+  let job = transition(
+    &app,
+    &id,
+    &[JobState::Paused, JobState::Cancelled],
+    JobState::Queued,
+    "resumed",
+  )?;
+  WAKE.notify_one();
+  Ok(job)
+}
+
+/// Stops job `id` unless it has finished, and its backend task with it.
+#[tauri::command]
+pub fn cancel_job(app: AppHandle, id: String) -> Result<Job, ProxyError> {
+  let job = transition(
+    &app,
+    &id,
+    &[JobState::Queued, JobState::Running, JobState::Paused],
+    JobState::Cancelled,
+    "cancelled",
+  )?;
+  if let Some(task_id) = &job.task_id {
+    cancel_task(&app, task_id);
+  }
+  Ok(job)
+}
+
+/// Asks the backend to stop task `task_id`, from a thread of its own.
+fn cancel_task(app: &AppHandle, task_id: &str) {
+  let (app, task_id) = (app.clone(), task_id.to_string());
+  thread::spawn(move || {
+    let cancelled = profiles::call(&app, "POST", &format!("/api/tasks/{task_id}/cancel"), None);
+    if let Err(e) = cancelled {
+      app.state::<BackendLog>().append(
+        "shell",
+        format!("cannot cancel task {task_id}: {}", e.message).as_bytes(),
+      );
+    }
+  });
+}
+
+/// Every job of this session and those saved from earlier ones, in the
+/// order they run.
+#[tauri::command]
+pub fn list_jobs() -> Vec<Job> {
+  let mut jobs = queue().jobs.clone();
+  jobs.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.seq.cmp(&b.seq)));
+  jobs
+}
@@ -0,0 +1,198 @@
+//! Provider API keys in the OS credential store (Windows Credential
+//! Manager, macOS Keychain, Secret Service via libsecret/KWallet), one
+//! entry per provider host. `startup.json` only lists which providers have
+//! a key. The backend gets the keys in [`ENV`] at launch, and requests
+//! tagged with a provider carry its key in [`HEADER`], so a key saved
+//! while the backend runs is used without a restart.
+
+use serde::Serialize;
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::Mutex,
+};
+use tauri::AppHandle;
+
+use crate::{config, paths};
+
+const SERVICE: &str = "ai-doc-translator";
+/// Separate from provider entries, so no host name can reach these.
+const APP_SERVICE: &str = "ai-doc-translator-app";
+pub const ENV: &str = "MVP_API_KEYS";
+pub const HEADER: &str = "x-provider-api-key";
+const HOST_HEADER: &str = "x-provider-host";
+
+/// Providers with a stored key, as listed in `startup.json`.
+static PROVIDERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Keys read from the store this session, so proxied requests do not hit
+/// it (and possibly an OS prompt) every time.
+static KEYS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+#[derive(Serialize)]
+pub struct ApiKeyStatus {
+  provider: String,
+  stored: bool,
+}
+
+fn normalize(provider: &str) -> Result<String, String> {
+  let provider = provider.trim().trim_end_matches('.').to_ascii_lowercase();
+  if provider.is_empty() {
+    return Err("Provider host is required".to_string());
+  }
+  Ok(provider)
+}
+
+fn entry(provider: &str) -> Result<keyring::Entry, String> {
+  keyring::Entry::new(SERVICE, provider)
+    .map_err(|e| format!("OS credential store unavailable: {e}"))
+}
+
+fn load(provider: &str) -> Option<String> {
+  if let Some(key) = KEYS.lock().unwrap().as_ref().and_then(|k| k.get(provider)) {
+    return Some(key.clone());
+  }
+  let key = entry(provider).ok()?.get_password().ok()?;
+  KEYS
+    .lock()
+    .unwrap()
+    .get_or_insert_with(HashMap::new)
+    .insert(provider.to_string(), key.clone());
+  Some(key)
+}
+
+fn save_providers(app: &AppHandle, update: impl FnOnce(&mut Vec<String>)) -> Result<(), String> {
+  let mut providers = PROVIDERS.lock().unwrap();
+  update(&mut providers);
+  providers.sort();
+  providers.dedup();
+  let data_dir = paths::data_dir(app).map_err(|e| e.to_string())?;
+  config::persist_key(
+    &data_dir,
+    "api_key_providers",
+    serde_json::json!(*providers),
+  )
+  .map_err(|e| format!("Cannot save the provider list: {e}"))
+}
+
+/// A secret of the app itself (not a provider key), e.g. the data
+/// encryption key; `None` if none is stored yet.
+pub fn app_secret(name: &str) -> Result<Option<String>, String> {
+  let entry = keyring::Entry::new(APP_SERVICE, name)
+    .map_err(|e| format!("OS credential store unavailable: {e}"))?;
+  match entry.get_password() {
+    Ok(secret) => Ok(Some(secret)),
+    Err(keyring::Error::NoEntry) => Ok(None),
+    Err(e) => Err(format!(
+      "Cannot read {name} from the OS credential store: {e}"
+    )),
+  }
+}
+
+pub fn set_app_secret(name: &str, secret: &str) -> Result<(), String> {
+  keyring::Entry::new(APP_SERVICE, name)
+    .and_then(|entry| entry.set_password(secret))
+    .map_err(|e| format!("Cannot store {name} in the OS credential store: {e}"))
+}
+
+pub fn delete_app_secret(name: &str) -> Result<(), String> {
+  let entry = keyring::Entry::new(APP_SERVICE, name)
+    .map_err(|e| format!("OS credential store unavailable: {e}"))?;
+  match entry.delete_credential() {
+    Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+    Err(e) => Err(format!(
+      "Cannot delete {name} from the OS credential store: {e}"
+    )),
+  }
+}
+
+/// The key stored for `provider`, for the shell's own provider calls.
+pub fn api_key(provider: &str) -> Option<String> {
+  load(&normalize(provider).ok()?)
+}
+
+pub fn init(providers: Vec<String>) {
+  *PROVIDERS.lock().unwrap() = providers;
+}
+
+/// Headers for a request to the backend, with the provider's key added
+/// when one is stored.
+pub fn request_headers(
+  headers: BTreeMap<String, String>,
+  provider: Option<&str>,
+) -> Vec<(String, String)> {
+  let mut headers: Vec<(String, String)> = headers
+    .into_iter()
+    .filter(|(name, _)| {
+      !name.eq_ignore_ascii_case(HEADER) && !name.eq_ignore_ascii_case(HOST_HEADER)
+    })
+    .collect();
+  if let Some(provider) = provider.and_then(|p| normalize(p).ok()) {
+    if let Some(key) = load(&provider) {
+      headers.push((HEADER.to_string(), key));
+      headers.push((HOST_HEADER.to_string(), provider));
+    }
+  }
+  headers
+}
+
+pub fn backend_env() -> BTreeMap<String, String> {
+  let providers = PROVIDERS.lock().unwrap().clone();
+  let keys: BTreeMap<String, String> = providers
+    .into_iter()
+    .filter_map(|p| load(&p).map(|key| (p, key)))
+    .collect();
+  let mut env = BTreeMap::new();
+  if !keys.is_empty() {
+    if let Ok(json) = serde_json::to_string(&keys) {
+      env.insert(ENV.to_string(), json);
+    }
+  }
+  env
+}
+
+#[tauri::command]
+pub fn set_api_key(app: AppHandle, provider: String, key: String) -> Result<ApiKeyStatus, String> {
+  let provider = normalize(&provider)?;
+  let key = key.trim().to_string();
+  if key.is_empty() {
+    return Err("API key is empty".to_string());
+  }
+  entry(&provider)?
+    .set_password(&key)
+    .map_err(|e| format!("Cannot store the API key: {e}"))?;
+  KEYS
+    .lock()
+    .unwrap()
+    .get_or_insert_with(HashMap::new)
+    .insert(provider.clone(), key);
+  save_providers(&app, |p| p.push(provider.clone()))?;
+  Ok(ApiKeyStatus {
+    provider,
+    stored: true,
+  })
+}
+
+/// Whether a key is stored for `provider`; the key itself never leaves
+/// the shell.
+#[tauri::command]
+pub fn get_api_key_status(provider: String) -> Result<ApiKeyStatus, String> {
+  let provider = normalize(&provider)?;
+  let stored = load(&provider).is_some();
+  Ok(ApiKeyStatus { provider, stored })
+}
+
+#[tauri::command]
+pub fn delete_api_key(app: AppHandle, provider: String) -> Result<ApiKeyStatus, String> {
+  let provider = normalize(&provider)?;
+  match entry(&provider)?.delete_credential() {
+    Ok(()) | Err(keyring::Error::NoEntry) => {}
+    Err(e) => return Err(format!("Cannot delete the API key: {e}")),
+  }
+  if let Some(keys) = KEYS.lock().unwrap().as_mut() {
+    keys.remove(&provider);
+  }
+  save_providers(&app, |p| p.retain(|other| other != &provider))?;
+  Ok(ApiKeyStatus {
+    provider,
+    stored: false,
+  })
+}
@@ -0,0 +1,212 @@
+//! `detect_language`: the language of a text, told in the shell from its
+//! letters and the trigrams of its words (see the `whatlang` crate), so a
+//! source language can be filled in as soon as a file is picked. Texts
+//! mixing languages are split into sentences (see `segmentation`), each
+//! detected on its own; a sentence too short to tell joins the one before
+//! it when it is in the same script.
+//!
+//! Languages are ISO 639-1 codes (`en`, `zh`), ISO 639-3 for those without
+//! one. Han without kana is taken for Chinese.
+
+use serde::Serialize;
+use std::ops::Range;
+use whatlang::{Lang, Script};
+
+use crate::segmentation;
+
+/// ISO 639-1 codes of the languages told apart.
+const CODES: &[(Lang, &str)] = &[
+  (Lang::Afr, "af"),
+  (Lang::Aka, "ak"),
+  (Lang::Amh, "am"),
+  (Lang::Ara, "ar"),
+  (Lang::Aze, "az"),
+  (Lang::Bel, "be"),
+  (Lang::Ben, "bn"),
+  (Lang::Bul, "bg"),
+  (Lang::Cat, "ca"),
+  (Lang::Ces, "cs"),
+  (Lang::Cmn, "zh"),
+  (Lang::Dan, "da"),
+  (Lang::Deu, "de"),
+  (Lang::Ell, "el"),
+  (Lang::Eng, "en"),
+  (Lang::Epo, "eo"),
+  (Lang::Est, "et"),
+  (Lang::Fin, "fi"),
+  (Lang::Fra, "fr"),
+  (Lang::Guj, "gu"),
+  (Lang::Heb, "he"),
+  (Lang::Hin, "hi"),
+  (Lang::Hrv, "hr"),
+  (Lang::Hun, "hu"),
+  (Lang::Hye, "hy"),
+  (Lang::Ind, "id"),
+  (Lang::Ita, "it"),
+  (Lang::Jav, "jv"),
+  (Lang::Jpn, "ja"),
+  (Lang::Kan, "kn"),
+  (Lang::Kat, "ka"),
+  (Lang::Khm, "km"),
+  (Lang::Kor, "ko"),
+  (Lang::Lat, "la"),
+  (Lang::Lav, "lv"),
+  (Lang::Lit, "lt"),
+  (Lang::Mal, "ml"),
+  (Lang::Mar, "mr"),
+  (Lang::Mkd, "mk"),
+  (Lang::Mya, "my"),
+  (Lang::Nep, "ne"),
+  (Lang::Nld, "nl"),
+  (Lang::Nob, "nb"),
+  (Lang::Ori, "or"),
+  (Lang::Pan, "pa"),
+  (Lang::Pes, "fa"),
+  (Lang::Pol, "pl"),
+  (Lang::Por, "pt"),
+  (Lang::Ron, "ro"),
+  (Lang::Rus, "ru"),
+  (Lang::Sin, "si"),
+  (Lang::Slk, "sk"),
+  (Lang::Slv, "sl"),
+  (Lang::Sna, "sn"),
+  (Lang::Spa, "es"),
+  (Lang::Srp, "sr"),
+  (Lang::Swe, "sv"),
+  (Lang::Tam, "ta"),
+  (Lang::Tel, "te"),
+  (Lang::Tgl, "tl"),
+  (Lang::Tha, "th"),
+  (Lang::Tuk, "tk"),
+  (Lang::Tur, "tr"),
+  (Lang::Ukr, "uk"),
+  (Lang::Urd, "ur"),
+  (Lang::Uzb, "uz"),
+  (Lang::Vie, "vi"),
+  (Lang::Yid, "yi"),
+  (Lang::Zul, "zu"),
+];
+
+fn code(lang: Lang) -> &'static str {
+  CODES
+    .iter()
+    .find(|(l, _)| *l == lang)
+    .map_or_else(|| lang.code(), |(_, code)| code)
+}
+
+fn script_name(script: Script) -> String {
+  match script {
+    Script::Mandarin => "Han".to_string(),
+    script => script.name().to_string(),
+  }
+}
+
+pub struct Guess {
+  pub lang: &'static str,
+  pub script: String,
+  /// From 0 to 1.
+  pub confidence: f64,
+  /// Whether the text was long and distinct enough to go by.
+  pub reliable: bool,
+}
+
+/// The language of `text`, `None` when it has no letters.
+pub fn detect(text: &str) -> Option<Guess> {
+  let info = whatlang::detect(text)?;
+  Some(Guess {
+    lang: code(info.lang()),
+    script: script_name(info.script()),
+    confidence: info.confidence(),
+    reliable: info.is_reliable(),
+  })
+}
+
+/// The direction that translates from `lang`, if it is one the app
+/// translates from.
+pub fn direction(lang: &str) -> Option<String> {
+  match lang {
+    "zh" => Some("zh->en".to_string()),
+    "en" => Some("en->zh".to_string()),
+    _ => None,
+  }
+}
+
+#[derive(Serialize)]
+pub struct Span {
+  /// Character offsets into the text, end exclusive.
+  pub start: usize,
+  pub end: usize,
+  /// `None` for a text without letters.
+  pub lang: Option<String>,
+  pub script: Option<String>,
+  /// That of its most certain sentence.
+  pub confidence: f64,
+}
+
+#[derive(Serialize)]
+pub struct Detection {
+  /// `None` for a text without letters.
+  pub lang: Option<String>,
+  pub script: Option<String>,
+  pub confidence: f64,
+  pub reliable: bool,
+  /// The direction to translate the text in, see [`direction`].
+  pub direction: Option<String>,
+  /// Runs of sentences in one language, in order; one for the whole text
+  /// when it is in one language.
+  pub segments: Vec<Span>,
+}
+
+fn spans(text: &str, lang: &str) -> Vec<(Range<usize>, Option<Guess>)> {
+  let mut out: Vec<(Range<usize>, Option<Guess>)> = Vec::new();
+  for range in segmentation::sentences(text, lang) {
+    let guess = detect(&text[range.clone()]);
+    if let Some((last, last_guess)) = out.last_mut() {
+      // a sentence without letters goes with any language
+      let joins = match (&*last_guess, &guess) {
+        (Some(a), Some(b)) => a.lang == b.lang || !b.reliable && a.script == b.script,
+        _ => true,
+      };
+      if joins {
+        last.end = range.end;
+        match last_guess {
+          Some(a) => {
+            if let Some(b) = guess {
+              a.confidence = a.confidence.max(b.confidence);
+            }
+          }
+          None => *last_guess = guess,
+        }
+        continue;
+      }
+    }
+    out.push((range, guess));
+  }
+  out
+}
+
+/// The language and script of `text`, and of each run of it in one
+/// language.
+#[tauri::command]
+pub fn detect_language(text: String) -> Detection {
+  let whole = detect(&text);
+  let chars = |byte: usize| text[..byte].chars().count();
+  let segments = spans(&text, whole.as_ref().map_or("", |g| g.lang))
+    .into_iter()
+    .map(|(range, guess)| Span {
+      start: chars(range.start),
+      end: chars(range.end),
+      confidence: guess.as_ref().map_or(0.0, |g| g.confidence),
+      lang: guess.as_ref().map(|g| g.lang.to_string()),
+      script: guess.map(|g| g.script),
+    })
+    .collect();
+  Detection {
+    direction: whole.as_ref().and_then(|g| direction(g.lang)),
+    confidence: whole.as_ref().map_or(0.0, |g| g.confidence),
+    reliable: whole.as_ref().is_some_and(|g| g.reliable),
+    lang: whole.as_ref().map(|g| g.lang.to_string()),
+    script: whole.map(|g| g.script),
+    segments,
+  }
+}
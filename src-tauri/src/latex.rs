@@ -0,0 +1,608 @@
+//! LaTeX sources read and rebuilt in the shell, like `html`: the
+//! translated copy is the source with each segment's text spliced in, so
+//! the preamble, commands and layout outside segments stay byte for byte.
+//!
+//! Segments are the paragraphs of the document body, list items, table
+//! cells, and the titles of sections, captions and the document. Inside a
+//! segment, math (`$…$`, `\(…\)`, `\[…\]`, `equation`, `align` and the
+//! like), citations, references, labels and other commands go to the
+//! translator as placeholders: `⟦1⟧here⟦/1⟧` for text markup such as
+//! `\emph{here}` or a footnote, `⟦2⟧` for anything kept whole. A
+//! translation whose placeholders did not all survive keeps the source
+//! text and is reported. Verbatim, listings and TikZ pictures are left
+//! alone.
+//!
+//! Locators are `tex:<start>-<end>`, the byte range of the segment's text
+//! in the source.
+
+use std::{collections::HashMap, fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{balanced, marker, pieces, Piece, Role, Tag, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+/// Commands whose argument is a segment of its own.
+const HEADINGS: &[&str] = &[
+  "part",
+  "chapter",
+  "section",
+  "subsection",
+  "subsubsection",
+  "paragraph",
+  "subparagraph",
+  "caption",
+  "title",
+  "subtitle",
+];
+/// Commands whose argument flows with the text around it.
+const INLINE: &[&str] = &[
+  "emph",
+  "textbf",
+  "textit",
+  "textsl",
+  "textsc",
+  "textup",
+  "textmd",
+  "textrm",
+  "textsf",
+  "underline",
+  "uline",
+  "footnote",
+  "mbox",
+  "text",
+];
+/// Environments kept whole inside the text around them (starred too).
+const MATH: &[&str] = &[
+  "equation",
+  "align",
+  "alignat",
+  "flalign",
+  "gather",
+  "multline",
+  "eqnarray",
+  "math",
+  "displaymath",
+];
+/// Environments whose content is never translated.
+const SKIPPED: &[&str] = &[
+  "verbatim",
+  "Verbatim",
+  "lstlisting",
+  "minted",
+  "comment",
+  "tikzpicture",
+  "filecontents",
+  "thebibliography",
+];
+/// Environments whose cells (`&`, `\\`) are segments.
+const TABLES: &[&str] = &[
+  "tabular",
+  "tabular*",
+  "tabularx",
+  "longtable",
+  "array",
+  "tabu",
+];
+/// Characters written `\%` and the like in text.
+const SPECIAL: &str = "#$%&_{}";
+
+#[derive(Clone, Copy)]
+enum Item {
+  Text,
+  /// `\%` and the like, read as the character.
+  Escaped,
+  /// A command without arguments or a comment; left out at a segment's
+  /// edges.
+  Layout,
+  /// Math or a command with its arguments.
+  Whole,
+  /// `\emph{` or a bare `{`.
+  Open,
+  /// The `}` of an `Open`, with its index when in the same run.
+  Close(Option<usize>),
+}
+
+enum Brace {
+  /// Around text: the run it opened in and its index there.
+  Pair(usize, usize),
+  /// A heading's argument, a segment of its own.
+  Heading,
+  /// Outside any segment.
+  Plain,
+}
+
+struct Unit {
+  range: Range<usize>,
+  /// The text to translate, placeholders included.
+  text: String,
+  /// Placeholder `n` at `n - 1`.
+  tags: Vec<Tag>,
+}
+
+fn collapse(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The end of the `{…}` or `[…]` group starting at `at`.
+fn group_end(b: &[u8], at: usize) -> usize {
+  let close = if b[at] == b'[' { b']' } else { b'}' };
+  let mut depth = 0;
+  let mut i = at + 1;
+  while i < b.len() {
+    match b[i] {
+      b'\\' => i += 1,
+      b'{' => depth += 1,
+      b'}' if depth > 0 => depth -= 1,
+      c if c == close && depth == 0 => return i + 1,
+      _ => {}
+    }
+    i += 1;
+  }
+  b.len()
+}
+
+/// The end of the arguments right after `at`, optional ones included.
+fn args_end(b: &[u8], mut at: usize) -> usize {
+  while matches!(b.get(at), Some(b'{' | b'[')) {
+    at = group_end(b, at);
+  }
+  at
+}
+
+/// The end of `pattern` searched from `at`, or of the source.
+fn until(source: &str, at: usize, pattern: &str) -> usize {
+  source[at.min(source.len())..]
+    .find(pattern)
+    .map_or(source.len(), |i| at + i + pattern.len())
+}
+
+/// The end of `$…$` opened at `at`.
+fn inline_math_end(b: &[u8], at: usize) -> usize {
+  let mut i = at + 1;
+  while i < b.len() {
+    match b[i] {
+      b'\\' => i += 1,
+      b'$' => return i + 1,
+      _ => {}
+    }
+    i += 1;
+  }
+  b.len()
+}
+
+struct Scanner<'a> {
+  source: &'a str,
+  out: Vec<Unit>,
+  run: Vec<(Range<usize>, Item)>,
+  /// Runs ended so far, telling a brace's run from the current one.
+  runs: usize,
+  braces: Vec<Brace>,
+  envs: Vec<String>,
+  in_body: bool,
+}
+
+impl Scanner<'_> {
+  fn collecting(&self) -> bool {
+    self.in_body || self.braces.iter().any(|b| matches!(b, Brace::Heading))
+  }
+
+  fn push(&mut self, range: Range<usize>, item: Item) {
+    if self.collecting() {
+      self.run.push((range, item));
+    }
+  }
+
+  fn in_table(&self) -> bool {
+    self
+      .envs
+      .last()
+      .is_some_and(|env| TABLES.contains(&env.as_str()))
+  }
+
+  fn flush(&mut self) {
+    let run = std::mem::take(&mut self.run);
+    self.runs += 1;
+    self.out.extend(unit(self.source, &run));
+  }
+
+  /// The command at `at` (a backslash); returns where scanning goes on.
+  fn command(&mut self, at: usize) -> usize {
+    let (source, b) = (self.source, self.source.as_bytes());
+    let name_start = at + 1;
+    let Some(first) = source[name_start..].chars().next() else {
+      self.push(at..source.len(), Item::Layout);
+      return source.len();
+    };
+    let mut end = name_start + first.len_utf8();
+    if first.is_ascii_alphabetic() || first == '@' {
+      while b
+        .get(end)
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'@')
+      {
+        end += 1;
+      }
+    }
+    let name = &source[name_start..end];
+    if SPECIAL.contains(name) {
+      self.push(at..end, Item::Escaped);
+      return end;
+    }
+    if b.get(end) == Some(&b'*') && (first.is_ascii_alphabetic() || name == "\\") {
+      end += 1;
+    }
+    match name {
+      "begin" | "end" if b.get(end) == Some(&b'{') => {
+        let group = group_end(b, end);
+        let env = source[end + 1..group.saturating_sub(1).max(end + 1)].trim();
+        let base = env.trim_end_matches('*');
+        if name == "end" {
+          self.flush();
+          if env == "document" {
+            self.in_body = false;
+          } else if self.envs.last().is_some_and(|e| e == env) {
+            self.envs.pop();
+          }
+          return group;
+        }
+        let close = format!("\\end{{{env}}}");
+        if env == "document" {
+          self.flush();
+          self.in_body = true;
+          group
+        } else if MATH.contains(&base) {
+          let end = until(source, group, &close);
+          self.push(at..end, Item::Whole);
+          end
+        } else if SKIPPED.contains(&base) {
+          self.flush();
+          until(source, group, &close)
+        } else {
+          self.flush();
+          self.envs.push(env.to_string());
+          args_end(b, group)
+        }
+      }
+      "verb" => {
+        let delimiter = source[end..].chars().next();
+        let end = delimiter.map_or(source.len(), |d| {
+          until(source, end + d.len_utf8(), &d.to_string())
+        });
+        self.push(at..end, Item::Whole);
+        end
+      }
+      "(" | "[" => {
+        let end = until(source, end, if name == "(" { "\\)" } else { "\\]" });
+        self.push(at..end, Item::Whole);
+        end
+      }
+      "item" | "par" => {
+        self.flush();
+        if name == "item" && b.get(end) == Some(&b'[') {
+          group_end(b, end)
+        } else {
+          end
+        }
+      }
+      "\\" => {
+        let end = if b.get(end) == Some(&b'[') {
+          group_end(b, end)
+        } else {
+          end
+        };
+        if self.in_table() {
+          self.flush();
+        } else {
+          self.push(at..end, Item::Layout);
+        }
+        end
+      }
+      _ if HEADINGS.contains(&name) => {
+        self.flush();
+        let end = if b.get(end) == Some(&b'[') {
+          group_end(b, end)
+        } else {
+          end
+        };
+        if b.get(end) == Some(&b'{') {
+          self.braces.push(Brace::Heading);
+          end + 1
+        } else {
+          end
+        }
+      }
+      _ if INLINE.contains(&name) && b.get(end) == Some(&b'{') => {
+        self.open(at..end + 1);
+        end + 1
+      }
+      // the address stays, the link text is translated
+      "href" if b.get(end) == Some(&b'{') => {
+        let url = group_end(b, end);
+        if b.get(url) == Some(&b'{') {
+          self.open(at..url + 1);
+          url + 1
+        } else {
+          self.push(at..url, Item::Whole);
+          url
+        }
+      }
+      _ => {
+        let args = args_end(b, end);
+        let item = if args > end {
+          Item::Whole
+        } else {
+          Item::Layout
+        };
+        self.push(at..args, item);
+        args
+      }
+    }
+  }
+
+  fn open(&mut self, range: Range<usize>) {
+    if self.collecting() {
+      self.braces.push(Brace::Pair(self.runs, self.run.len()));
+      self.run.push((range, Item::Open));
+    } else {
+      self.braces.push(Brace::Plain);
+    }
+  }
+
+  fn close(&mut self, at: usize) {
+    match self.braces.pop() {
+      Some(Brace::Pair(run, index)) => {
+        let index = (run == self.runs).then_some(index);
+        self.push(at..at + 1, Item::Close(index));
+      }
+      Some(Brace::Heading) => self.flush(),
+      Some(Brace::Plain) | None => {}
+    }
+  }
+
+  fn scan(mut self) -> Vec<Unit> {
+    let (source, b) = (self.source, self.source.as_bytes());
+    let mut i = 0;
+    while i < b.len() {
+      i = match b[i] {
+        b'\\' => self.command(i),
+        // with its line break, which the text after it must not lose
+        b'%' => {
+          let end = source[i..].find('\n').map_or(source.len(), |n| i + n + 1);
+          self.push(i..end, Item::Layout);
+          end
+        }
+        b'$' => {
+          let end = if b.get(i + 1) == Some(&b'$') {
+            until(source, i + 2, "$$")
+          } else {
+            inline_math_end(b, i)
+          };
+          self.push(i..end, Item::Whole);
+          end
+        }
+        b'{' => {
+          self.open(i..i + 1);
+          i + 1
+        }
+        b'}' => {
+          self.close(i);
+          i + 1
+        }
+        b'&' if self.in_table() => {
+          self.flush();
+          i + 1
+        }
+        b'&' => {
+          self.push(i..i + 1, Item::Whole);
+          i + 1
+        }
+        b'\n' => {
+          let next = source[i + 1..]
+            .find(|c: char| !matches!(c, ' ' | '\t' | '\r'))
+            .map_or(source.len(), |n| i + 1 + n);
+          // a blank line, or one after a comment that took its line break
+          let line = source[..i].rfind('\n').map_or(0, |n| n + 1);
+          if b.get(next) == Some(&b'\n') || i > 0 && source[line..i].trim().is_empty() {
+            self.flush();
+            next
+          } else {
+            self.push(i..i + 1, Item::Text);
+            i + 1
+          }
+        }
+        _ => {
+          let end = source[i..]
+            .find(['\\', '%', '$', '{', '}', '&', '\n'])
+            .map_or(source.len(), |n| i + n);
+          self.push(i..end, Item::Text);
+          end
+        }
+      };
+    }
+    self.flush();
+    self.out
+  }
+}
+
+/// The segment of `run`, trimmed of whitespace and layout at its edges.
+fn unit(source: &str, run: &[(Range<usize>, Item)]) -> Option<Unit> {
+  let is_edge = |(range, item): &(Range<usize>, Item)| match item {
+    Item::Text => source[range.clone()].trim().is_empty(),
+    Item::Layout => true,
+    _ => false,
+  };
+  let first = run.iter().position(|i| !is_edge(i))?;
+  let last = run.iter().rposition(|i| !is_edge(i))?;
+  let run = &run[first..=last];
+  if !run
+    .iter()
+    .any(|(range, item)| matches!(item, Item::Text) && !source[range.clone()].trim().is_empty())
+  {
+    return None;
+  }
+  let trimmed = |(range, item): &(Range<usize>, Item)| {
+    let text = &source[range.clone()];
+    match item {
+      Item::Text => {
+        range.start + (text.len() - text.trim_start().len())
+          ..range.end - (text.len() - text.trim_end().len())
+      }
+      _ => range.clone(),
+    }
+  };
+  let range = trimmed(&run[0]).start..trimmed(&run[run.len() - 1]).end;
+
+  let matched: Vec<usize> = run
+    .iter()
+    .filter_map(|(_, item)| match item {
+      Item::Close(Some(index)) => Some(index - first),
+      _ => None,
+    })
+    .collect();
+  let mut text = String::new();
+  let mut tags: Vec<Tag> = Vec::new();
+  // placeholder number of each paired opening, by run position
+  let mut numbers: HashMap<usize, usize> = HashMap::new();
+  for (n, (range, item)) in run.iter().enumerate() {
+    let role = match item {
+      Item::Text => {
+        text.push_str(&source[range.clone()]);
+        continue;
+      }
+      Item::Escaped => {
+        text.push_str(&source[range.start + 1..range.end]);
+        continue;
+      }
+      Item::Close(Some(index)) => {
+        let number = numbers[&(index - first)];
+        tags[number - 1].1 = Role::Element(range.clone());
+        text.push_str(&marker(number, true));
+        continue;
+      }
+      Item::Open if matched.contains(&n) => {
+        numbers.insert(n, tags.len() + 1);
+        // the closing brace comes later
+        Role::Whole
+      }
+      Item::Open => Role::Opens,
+      Item::Close(None) => Role::Closes,
+      Item::Layout | Item::Whole => Role::Whole,
+    };
+    tags.push((range.clone(), role));
+    text.push_str(&marker(tags.len(), false));
+  }
+  Some(Unit {
+    range,
+    text: collapse(&text),
+    tags,
+  })
+}
+
+fn units(source: &str) -> Vec<Unit> {
+  Scanner {
+    source,
+    out: Vec::new(),
+    run: Vec::new(),
+    runs: 0,
+    braces: Vec::new(),
+    envs: Vec::new(),
+    // a file meant for `\input` has no preamble
+    in_body: !source.contains("\\begin{document}"),
+  }
+  .scan()
+}
+
+/// Translated text with LaTeX's special characters escaped.
+fn escape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '\\' => out.push_str("\\textbackslash{}"),
+      '^' => out.push_str("\\^{}"),
+      '\n' => out.push(' '),
+      c if SPECIAL.contains(c) => {
+        out.push('\\');
+        out.push(c);
+      }
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+fn read(data: &[u8]) -> Result<&str, String> {
+  std::str::from_utf8(data).map_err(|_| "LaTeX sources must be UTF-8".to_string())
+}
+
+fn locator(range: &Range<usize>) -> String {
+  format!("tex:{}-{}", range.start, range.end)
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  Ok(
+    units(read(&data)?)
+      .into_iter()
+      // numbers, lone placeholders and other segments without words
+      .filter(|u| u.text.chars().any(char::is_alphabetic))
+      .map(|u| Segment {
+        locator: locator(&u.range),
+        text: u.text,
+      })
+      .collect(),
+  )
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let source = read(&task.source).map_err(invalid)?;
+  let mut warnings = Vec::new();
+  let mut out = String::with_capacity(source.len());
+  let mut at = 0;
+  for unit in units(source) {
+    let Some(translation) = translations.get(locator(&unit.range).as_str()) else {
+      continue;
+    };
+    let pieces = pieces(translation);
+    if !balanced(&pieces, &unit.tags) {
+      warnings.push(Warning {
+        excerpt: unit.text.chars().take(40).collect(),
+        message: "placeholders for commands or math did not survive translation; kept the source"
+          .to_string(),
+      });
+      continue;
+    }
+    out.push_str(&source[at..unit.range.start]);
+    for piece in pieces {
+      match piece {
+        Piece::Text(text) => out.push_str(&escape(text)),
+        Piece::Tag(number, false) => out.push_str(&source[unit.tags[number - 1].0.clone()]),
+        Piece::Tag(number, true) => {
+          if let (_, Role::Element(end)) = &unit.tags[number - 1] {
+            out.push_str(&source[end.clone()]);
+          }
+        }
+      }
+    }
+    at = unit.range.end;
+  }
+  out.push_str(&source[at..]);
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// Writes the translated copy of LaTeX task `job_id` to `path`. Returns the
+/// segments kept in the source language.
+#[tauri::command]
+pub async fn export_translated_tex(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
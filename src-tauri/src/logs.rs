@@ -0,0 +1,118 @@
+use std::{
+  collections::VecDeque,
+  fs::{self, File, OpenOptions},
+  io::{self, BufRead, BufReader, Write},
+  path::PathBuf,
+  sync::Mutex,
+};
+use tauri::State;
+
+const KEEP_ROTATED: usize = 4;
+
+/// Rotating log of the backend's stdout/stderr:
+/// `backend.log`, `backend.log.1` (newest rotated) ... `backend.log.4`.
+pub struct BackendLog {
+  dir: PathBuf,
+  max_bytes: u64,
+  file: Mutex<Option<File>>,
+}
+
+impl BackendLog {
+  pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+    Self {
+      dir,
+      max_bytes,
+      file: Mutex::new(None),
+    }
+  }
+
+  fn path(&self, index: usize) -> PathBuf {
+    if index == 0 {
+      self.dir.join("backend.log")
+    } else {
+      self.dir.join(format!("backend.log.{index}"))
+    }
+  }
+
+  fn rotate(&self) -> io::Result<()> {
+    let _ = fs::remove_file(self.path(KEEP_ROTATED));
+    for i in (0..KEEP_ROTATED).rev() {
+      let from = self.path(i);
+      if from.exists() {
+        fs::rename(from, self.path(i + 1))?;
+      }
+    }
+    Ok(())
+  }
+
+  fn open(&self) -> io::Result<File> {
+    fs::create_dir_all(&self.dir)?;
+    OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(self.path(0))
+  }
+
+  /// Appends one line of backend output, prefixed with the stream name.
+  pub fn append(&self, stream: &str, line: &[u8]) {
+    let mut guard = self.file.lock().unwrap();
+    let _ = (|| -> io::Result<()> {
+      if let Some(f) = guard.as_ref() {
+        if f.metadata()?.len() >= self.max_bytes {
+          *guard = None;
+          self.rotate()?;
+        }
+      }
+      if guard.is_none() {
+        *guard = Some(self.open()?);
+      }
+      let f = guard.as_mut().unwrap();
+      f.write_all(format!("[{stream}] ").as_bytes())?;
+      f.write_all(line)?;
+      f.write_all(b"\n")
+    })();
+  }
+
+  /// Syncs the current log file to disk. Skips it if another thread holds
+  /// the log, so it is safe to call from a panic hook.
+  pub fn flush(&self) {
+    if let Ok(guard) = self.file.try_lock() {
+      if let Some(f) = guard.as_ref() {
+        let _ = f.sync_data();
+      }
+    }
+  }
+
+  /// Returns up to `lines` most recent lines, oldest first, reading into
+  /// rotated files when the current one is short.
+  pub fn tail(&self, lines: usize) -> Vec<String> {
+    let mut out: VecDeque<String> = VecDeque::with_capacity(lines);
+    for i in 0..=KEEP_ROTATED {
+      if out.len() >= lines {
+        break;
+      }
+      let Ok(f) = File::open(self.path(i)) else {
+        continue;
+      };
+      let mut chunk: VecDeque<String> = VecDeque::new();
+      for line in BufReader::new(f).lines().map_while(Result::ok) {
+        if chunk.len() == lines {
+          chunk.pop_front();
+        }
+        chunk.push_back(line);
+      }
+      while let Some(line) = chunk.pop_back() {
+        if out.len() >= lines {
+          break;
+        }
+        out.push_front(line);
+      }
+    }
+    out.into()
+  }
+}
+
+#[tauri::command]
+pub fn get_backend_logs(log: State<BackendLog>, lines: usize) -> Vec<String> {
+  log.tail(lines.min(5000))
+}
@@ -1,134 +1,384 @@
-use serde::Deserialize;
-use std::{
-  fs,
-  io,
-  path::{Path, PathBuf},
-  process::Command,
-  sync::Mutex,
-  time::{Duration, Instant},
-};
-use tauri::{Manager, State};
+mod analysis;
+mod applock;
+mod archive;
+mod args;
+mod atrest;
+mod auth;
+mod backend;
+mod bilingual;
+mod bundle;
+mod cache;
+mod certs;
+mod charset;
+mod cleanup;
+mod clientcert;
+mod config;
+mod delimited;
+mod diagnostics;
+mod dialogs;
+mod diff;
+mod direct;
+mod dns;
+mod docx;
+mod downloads;
+mod epub;
+mod estimate;
+mod folders;
+mod formats;
+mod glossary;
+mod health;
+mod heartbeat;
+mod html;
+mod imagetext;
+mod intake;
+mod integrity;
+mod job_events;
+mod jobs;
+mod keychain;
+mod language;
+mod latex;
+mod logs;
+mod markdown;
+mod metrics;
+mod network;
+mod ocr;
+mod odf;
+mod ooxml;
+mod os;
+mod outbound;
+mod output;
+mod paths;
+mod pdfexport;
+mod pdfscan;
+mod pdftext;
+mod placeholders;
+mod plaintext;
+mod po;
+mod pool;
+mod pptx;
+mod priority;
+mod profiles;
+mod protected;
+mod providers;
+mod proxy;
+mod qa;
+mod quarantine;
+mod ratelimit;
+mod resilience;
+mod resources;
+mod review;
+mod rtf;
+mod segmentation;
+mod segments;
+mod settings;
+mod status;
+mod stream;
+mod subtitles;
+mod tmx;
+mod transport;
+mod update;
+mod upload;
+mod usage;
+mod version;
+mod watch;
+mod xliff;
+mod xlsx;
 
-#[derive(Default)]
-struct BackendState(Mutex<Option<String>>);
+use std::{io, path::PathBuf};
+use tauri::{webview::PageLoadEvent, DragDropEvent, Manager, RunEvent, State, WindowEvent};
 
-#[derive(Deserialize)]
-struct PortInfo {
-  host: String,
-  port: u16,
-  pid: i64,
-}
+use archive::ArchiveState;
+use args::BackendArgs;
+use backend::BackendProcess;
+use config::StartupConfig;
+use downloads::DownloadState;
+use intake::IntakeState;
+use logs::BackendLog;
+use metrics::MetricsState;
+use network::NetworkState;
+use pool::WorkerPool;
+use priority::PriorityState;
+use ratelimit::{RateLimitConfig, RateLimiter};
+use settings::SettingsState;
+use status::BackendState;
+use stream::StreamState;
+use update::UpdateState;
+use usage::UsageState;
 
 #[tauri::command]
 fn get_backend_base_url(state: State<BackendState>) -> Option<String> {
-  state.0.lock().unwrap().clone()
+  state.base_url()
 }
 
 fn boxed_err(msg: impl Into<String>) -> Box<dyn std::error::Error> {
-  Box::new(io::Error::new(io::ErrorKind::Other, msg.into()))
-}
-
-fn backend_exe_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
-  let exe = std::env::current_exe()?;
-  let dir = exe
-    .parent()
-    .ok_or_else(|| boxed_err("Cannot determine executable directory"))?;
-  Ok(dir.join("mvp_backend.exe"))
-}
-
-fn atomic_write(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
-  if let Some(parent) = path.parent() {
-    fs::create_dir_all(parent)?;
-  }
-  let tmp = path.with_extension("tmp");
-  fs::write(&tmp, content)?;
-  fs::rename(tmp, path)?;
-  Ok(())
+  Box::new(io::Error::other(msg.into()))
 }
 
-fn wait_for_backend(port_file: &Path) -> Result<String, Box<dyn std::error::Error>> {
-  let start = Instant::now();
-  let timeout = Duration::from_secs(25);
-
-  // 1) wait port-file
-  let port_info: PortInfo = loop {
-    if start.elapsed() > timeout {
-      return Err(boxed_err("Timeout waiting for backend port-file"));
-    }
-    if let Ok(s) = fs::read_to_string(port_file) {
-      if let Ok(info) = serde_json::from_str::<PortInfo>(&s) {
-        break info;
-      }
-    }
-    std::thread::sleep(Duration::from_millis(80));
-  };
-
-  let base = format!("http://{}:{}", port_info.host, port_info.port);
-
-  // 2) wait /api/health
-  let client = reqwest::blocking::Client::new();
-  loop {
-    if start.elapsed() > timeout {
-      return Err(boxed_err("Timeout waiting for backend /api/health"));
-    }
-    let ok = client
-      .get(format!("{}/api/health", base))
-      .timeout(Duration::from_millis(800))
-      .send()
-      .map(|r| r.status().is_success())
-      .unwrap_or(false);
-
-    if ok {
-      break;
-    }
-    std::thread::sleep(Duration::from_millis(100));
+fn main() {
+  if std::env::args().any(|a| a == cleanup::FLAG) {
+    let killed = cleanup::kill_orphans();
+    println!("killed {killed} orphaned backend process(es)");
+    return;
   }
+  paths::redirect_webview_data();
 
-  Ok(base)
-}
-
-fn main() {
-  tauri::Builder::default()
+  let app = tauri::Builder::default()
+    .plugin(tauri_plugin_shell::init())
+    .plugin(tauri_plugin_dialog::init())
+    .register_asynchronous_uri_scheme_protocol(transport::SCHEME, transport::handle_protocol)
     .manage(BackendState::default())
-    .invoke_handler(tauri::generate_handler![get_backend_base_url])
+    .manage(BackendProcess::default())
+    .manage(MetricsState::default())
+    .manage(WorkerPool::default())
+    .manage(UpdateState::default())
+    .manage(StreamState::default())
+    .manage(NetworkState::default())
+    .manage(DownloadState::default())
+    .manage(IntakeState::default())
+    .manage(ArchiveState::default())
+    .invoke_handler(applock::guard(tauri::generate_handler![
+      applock::get_lock_status,
+      applock::lock_app,
+      applock::report_activity,
+      applock::set_master_password,
+      applock::unlock,
+      args::get_backend_args,
+      args::set_backend_args,
+      get_backend_base_url,
+      backend::restart_backend,
+      backend::get_backend_startup_error,
+      backend::get_backend_env,
+      bundle::export_settings_bundle,
+      bundle::import_settings_bundle,
+      cache::clear_translation_cache,
+      cache::get_translation_cache_size,
+      clientcert::get_client_certificate,
+      clientcert::set_client_certificate,
+      certs::list_ca_certificates,
+      certs::add_ca_certificate,
+      certs::remove_ca_certificate,
+      config::get_startup_config,
+      diagnostics::create_diagnostics_bundle,
+      dialogs::pick_documents,
+      dialogs::pick_output_dir,
+      dialogs::pick_archive,
+      dialogs::pick_folder,
+      output::preview_output_paths,
+      output::export_translated,
+      review::export_bilingual_docx,
+      diff::diff_translations,
+      dns::get_dns_config,
+      dns::set_dns_config,
+      downloads::cancel_download,
+      downloads::pause_download,
+      downloads::start_download,
+      intake::dequeue_document,
+      intake::list_queued_documents,
+      job_events::send_job_message,
+      job_events::get_job_events_connected,
+      jobs::cancel_job,
+      jobs::enqueue_job,
+      jobs::list_jobs,
+      jobs::pause_job,
+      jobs::resume_job,
+      keychain::delete_api_key,
+      keychain::get_api_key_status,
+      keychain::set_api_key,
+      metrics::get_backend_metrics,
+      network::get_network_status,
+      outbound::get_proxy_config,
+      outbound::set_proxy_config,
+      outbound::set_proxy_credentials,
+      docx::export_translated_docx,
+      pdfexport::export_translated_pdf,
+      pdftext::extract_pdf_text,
+      ocr::get_ocr_status,
+      pptx::export_translated_pptx,
+      odf::export_translated_odf,
+      imagetext::export_translated_png,
+      imagetext::export_translated_jpg,
+      epub::export_translated_epub,
+      folders::translate_folder,
+      glossary::list_glossary,
+      glossary::save_term,
+      glossary::delete_term,
+      glossary::import_glossary,
+      glossary::check_glossary,
+      qa::run_qa,
+      formats::detect_format,
+      analysis::analyze_document,
+      language::detect_language,
+      estimate::estimate_job,
+      archive::open_archive,
+      archive::translate_archive,
+      archive::close_archive,
+      subtitles::export_translated_srt,
+      subtitles::export_translated_vtt,
+      markdown::export_translated_md,
+      plaintext::export_translated_txt,
+      delimited::export_translated_csv,
+      charset::get_text_encoding,
+      charset::set_text_encoding,
+      html::export_translated_html,
+      latex::export_translated_tex,
+      rtf::export_translated_rtf,
+      segmentation::segment_text,
+      po::export_translated_po,
+      xliff::export_translated_xlf,
+      xliff::export_xliff,
+      bilingual::export_bilingual_csv,
+      tmx::import_tmx,
+      tmx::export_tmx,
+      resources::export_translated_json,
+      resources::export_translated_arb,
+      resources::export_translated_yaml,
+      resources::export_translated_strings,
+      xlsx::export_translated_xlsx,
+      pool::pick_backend_url,
+      priority::set_backend_priority,
+      profiles::activate_profile,
+      profiles::clone_profile,
+      profiles::create_profile,
+      profiles::delete_profile,
+      profiles::list_profiles,
+      profiles::update_profile,
+      providers::delete_provider,
+      providers::list_providers,
+      providers::save_provider,
+      providers::validate_provider,
+      providers::list_ollama_models,
+      providers::pull_ollama_model,
+      direct::translate_text,
+      direct::translate_file,
+      proxy::proxy_request,
+      ratelimit::get_rate_limits,
+      ratelimit::set_rate_limits,
+      settings::get_settings,
+      settings::update_settings,
+      status::get_backend_status,
+      stream::proxy_stream,
+      stream::cancel_stream,
+      update::update_backend,
+      upload::pick_document,
+      upload::upload_document,
+      protected::provide_document_password,
+      usage::get_usage_config,
+      usage::get_usage_report,
+      usage::set_usage_config,
+      watch::add_watch_folder,
+      watch::list_watch_folders,
+      watch::remove_watch_folder,
+      logs::get_backend_logs
+    ]))
+    .on_page_load(|webview, payload| {
+      if payload.event() == PageLoadEvent::Finished {
+        applock::on_page_load(webview);
+      }
+    })
+    .on_window_event(|window, event| match event {
+      WindowEvent::Destroyed if window.label() == "main" => {
+        backend::stop(window.app_handle());
+      }
+      WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) if !applock::is_locked() => {
+        intake::on_drop(window.app_handle(), paths.clone());
+      }
+      _ => {}
+    })
     .setup(|app| {
       // The setup closure must return Result<(), Box<dyn Error>>
-      let data_dir = app.path().app_data_dir()?;
-      fs::create_dir_all(&data_dir)?;
-
-      let port_file = data_dir.join(format!("backend-port-{}.json", uuid::Uuid::new_v4()));
-      if port_file.exists() {
-        let _ = fs::remove_file(&port_file);
+      let data_dir = paths::data_dir(app)?;
+      let (startup, warnings) = StartupConfig::load(&data_dir);
+      let log_dir = startup
+        .log_dir
+        .as_ref()
+        .map_or_else(|| data_dir.join("logs"), PathBuf::from);
+      app.manage(BackendLog::new(
+        log_dir,
+        startup.log_max_mb.max(1) * 1024 * 1024,
+      ));
+      for warning in warnings {
+        app
+          .state::<BackendLog>()
+          .append("shell", warning.as_bytes());
       }
-
-      // quick write check (optional)
-      atomic_write(&port_file, "")?;
-      let _ = fs::remove_file(&port_file);
-
-      let backend = backend_exe_path()?;
-      if !backend.exists() {
-        return Err(boxed_err(format!(
-          "Missing backend exe next to app: {}",
-          backend.display()
-        )));
+      app.manage(SettingsState::load(&data_dir, &startup.default_direction));
+      cleanup::install(app.handle());
+      app.manage(PriorityState::new(startup.low_priority));
+      let backend_args = args::validate(&startup.backend_args).unwrap_or_else(|e| {
+        app
+          .state::<BackendLog>()
+          .append("shell", format!("ignoring backend_args: {e}").as_bytes());
+        Vec::new()
+      });
+      app.manage(BackendArgs::new(backend_args));
+      if let Err(e) = startup.proxy.validate() {
+        app
+          .state::<BackendLog>()
+          .append("shell", format!("ignoring proxy settings: {e}").as_bytes());
+      } else {
+        if let Some(warning) = outbound::adopt_credentials(&data_dir, &startup.proxy) {
+          app
+            .state::<BackendLog>()
+            .append("shell", warning.as_bytes());
+        }
+        outbound::init(startup.proxy.clone());
       }
+      let rate_limits = startup.rate_limits.validate().map_or_else(
+        |e| {
+          app
+            .state::<BackendLog>()
+            .append("shell", format!("ignoring rate_limits: {e}").as_bytes());
+          RateLimitConfig::default()
+        },
+        |()| startup.rate_limits.clone(),
+      );
+      app.manage(RateLimiter::new(rate_limits));
+      if let Err(e) = clientcert::init(startup.client_cert.clone()) {
+        app.state::<BackendLog>().append(
+          "shell",
+          format!("ignoring client certificate: {e}").as_bytes(),
+        );
+      }
+      if let Err(e) = startup.dns.validate() {
+        app
+          .state::<BackendLog>()
+          .append("shell", format!("ignoring dns settings: {e}").as_bytes());
+      } else {
+        dns::init(startup.dns.clone());
+      }
+      keychain::init(startup.api_key_providers.clone());
+      providers::init(startup.providers.clone());
+      applock::init(startup.app_lock.clone());
+      app.manage(UsageState::new(startup.usage.clone()));
+      app.manage(startup);
+      backend::collect_stale_port_files(&data_dir);
+      transport::init(&data_dir);
+      certs::init(&data_dir);
+      cache::init(&data_dir);
+      charset::init(&data_dir);
+      archive::init(&data_dir);
+      output::init(&data_dir);
+      jobs::init(app.handle(), &data_dir);
+      watch::init(&data_dir);
+      glossary::init(&data_dir);
 
-      // spawn backend
-      Command::new(&backend)
-        .args([
-          "--host",
-          "127.0.0.1",
-          "--port-file",
-          port_file.to_string_lossy().as_ref(),
-        ])
-        .env("MVP_DATA_DIR", data_dir.to_string_lossy().as_ref())
-        .spawn()?;
-
-      let base_url = wait_for_backend(&port_file)?;
-      *app.state::<BackendState>().0.lock().unwrap() = Some(base_url);
+      backend::spawn_startup(app.handle().clone());
+      health::spawn_monitor(app.handle().clone());
+      job_events::spawn(app.handle().clone());
+      jobs::spawn_workers(app.handle().clone());
+      watch::spawn(app.handle().clone());
+      network::spawn_monitor(app.handle().clone());
+      cache::spawn_evictor(app.handle().clone());
+      applock::spawn_idle_monitor(app.handle().clone());
 
       Ok(())
     })
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application");
+
+  app.run(|app, event| {
+    if let RunEvent::Exit = event {
+      backend::stop(app);
+    }
+  });
 }
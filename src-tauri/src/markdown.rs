@@ -0,0 +1,296 @@
+//! Markdown read and rebuilt in the shell. Segments are the prose blocks
+//! (paragraphs, headings, list items and table cells); code blocks, HTML
+//! blocks and front matter are never sent. Inside a block, inline code,
+//! link destinations, autolinks and bare URLs, images, inline HTML, math
+//! and line breaks go to the translator as numbered placeholders (`⟦1⟧`)
+//! and are put back verbatim.
+//!
+//! The translated copy is the source with each block's text spliced in,
+//! so everything between blocks (markers, indentation, fences, blank
+//! lines) stays byte for byte. Locators are `md:<start>-<end>`, the byte
+//! range of the block's text in the source.
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use std::{fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{CLOSE, OPEN},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+struct Block {
+  range: Range<usize>,
+  /// The text to translate, with placeholders.
+  text: String,
+  /// What placeholder `n` stands for, at `n - 1`.
+  locked: Vec<String>,
+}
+
+/// A block being read: its extent so far and the spans to lock
+/// (`true`) or read as a space (`false`, soft line breaks).
+#[derive(Default)]
+struct Open {
+  range: Option<Range<usize>>,
+  spans: Vec<(Range<usize>, bool)>,
+}
+
+impl Open {
+  fn touch(&mut self, range: &Range<usize>) {
+    self.range = Some(match self.range.take() {
+      Some(r) => r.start.min(range.start)..r.end.max(range.end),
+      None => range.clone(),
+    });
+  }
+
+  fn finish(self, source: &str) -> Option<Block> {
+    let range = self.range?;
+    let mut spans = self.spans;
+    spans.sort_by_key(|(span, _)| span.start);
+    let (mut text, mut locked) = (String::new(), Vec::new());
+    let mut at = range.start;
+    for (span, lock) in spans {
+      // inside a span already taken
+      if span.start < at || span.end > range.end {
+        continue;
+      }
+      text.push_str(&source[at..span.start]);
+      if lock {
+        locked.push(source[span.clone()].to_string());
+        text.push_str(&format!("{OPEN}{}{CLOSE}", locked.len()));
+      } else {
+        text.push(' ');
+      }
+      at = span.end;
+    }
+    text.push_str(&source[at..range.end]);
+    Some(Block {
+      range,
+      text: text.trim().to_string(),
+      locked,
+    })
+  }
+}
+
+/// Byte ranges of the URLs in `text`, which starts at `offset`.
+fn urls(text: &str, offset: usize) -> Vec<Range<usize>> {
+  let mut out = Vec::new();
+  let mut from = 0;
+  while let Some(found) = ["https://", "http://", "www."]
+    .iter()
+    .filter_map(|scheme| text[from..].find(scheme))
+    .min()
+  {
+    let start = from + found;
+    let length = text[start..]
+      .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '（' | '）'))
+      .unwrap_or(text.len() - start);
+    let url = text[start..start + length].trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+    out.push(offset + start..offset + start + url.len());
+    from = start + length.max(1);
+  }
+  out
+}
+
+fn blocks(source: &str) -> Vec<Block> {
+  let options = Options::ENABLE_TABLES
+    | Options::ENABLE_FOOTNOTES
+    | Options::ENABLE_STRIKETHROUGH
+    | Options::ENABLE_TASKLISTS
+    | Options::ENABLE_MATH
+    | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+    | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+  let mut out = Vec::new();
+  let mut open: Option<Open> = None;
+  // a line break waiting for the next event, where its line's text starts
+  let mut line_break: Option<(usize, bool)> = None;
+  // open links: where their text ended so far, and their end
+  let mut links: Vec<(usize, usize)> = Vec::new();
+  // inside an image or autolink, locked whole
+  let mut skipped = 0;
+  for (event, range) in Parser::new_ext(source, options).into_offset_iter() {
+    if skipped > 0 {
+      match event {
+        Event::Start(_) => skipped += 1,
+        Event::End(_) => skipped -= 1,
+        _ => {}
+      }
+      continue;
+    }
+    match &event {
+      Event::Start(tag) if is_text_block(&tag.to_end()) => {
+        out.extend(open.take().and_then(|b| b.finish(source)));
+        open = Some(Open::default());
+        line_break = None;
+        continue;
+      }
+      Event::End(tag) if is_text_block(tag) => {
+        out.extend(open.take().and_then(|b| b.finish(source)));
+        continue;
+      }
+      // code and HTML blocks, nested lists, front matter
+      Event::Start(_) if is_block_level(&event) => {
+        out.extend(open.take().and_then(|b| b.finish(source)));
+        continue;
+      }
+      _ => {}
+    }
+    let Some(block) = open.as_mut() else {
+      continue;
+    };
+    if let Some((start, hard)) = line_break.take() {
+      block.spans.push((start..range.start, hard));
+    }
+    if let Some(link) = links.last_mut() {
+      if !matches!(event, Event::End(TagEnd::Link)) {
+        link.0 = link.0.max(range.end);
+      }
+    }
+    block.touch(&range);
+    match event {
+      Event::Text(_) => {
+        let spans = urls(&source[range.clone()], range.start);
+        block.spans.extend(spans.into_iter().map(|url| (url, true)));
+      }
+      Event::Code(_)
+      | Event::InlineHtml(_)
+      | Event::Html(_)
+      | Event::InlineMath(_)
+      | Event::DisplayMath(_)
+      | Event::FootnoteReference(_)
+      | Event::TaskListMarker(_) => block.spans.push((range, true)),
+      Event::SoftBreak => line_break = Some((range.start, false)),
+      Event::HardBreak => line_break = Some((range.start, true)),
+      Event::Start(Tag::Image { .. })
+      | Event::Start(Tag::Link {
+        link_type: LinkType::Autolink | LinkType::Email,
+        ..
+      }) => {
+        block.spans.push((range, true));
+        skipped = 1;
+      }
+      // the text after `[`
+      Event::Start(Tag::Link { .. }) => links.push((range.start + 1, range.end)),
+      // `](destination "title")` or `][reference]`
+      Event::End(TagEnd::Link) => {
+        if let Some((text_end, end)) = links.pop() {
+          if text_end < end {
+            block.spans.push((text_end..end, true));
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  out.extend(open.and_then(|b| b.finish(source)));
+  out
+}
+
+/// Blocks whose own inline content is a segment.
+fn is_text_block(tag: &TagEnd) -> bool {
+  matches!(
+    tag,
+    TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::TableCell | TagEnd::Item
+  )
+}
+
+/// Blocks that end the text of a block they are in.
+fn is_block_level(event: &Event) -> bool {
+  matches!(
+    event,
+    Event::Start(
+      Tag::List(_)
+        | Tag::BlockQuote(_)
+        | Tag::CodeBlock(_)
+        | Tag::HtmlBlock
+        | Tag::Table(_)
+        | Tag::FootnoteDefinition(_)
+        | Tag::MetadataBlock(_)
+    )
+  )
+}
+
+/// `translation` with its placeholders replaced by what they stand for;
+/// any the translator dropped go at the end rather than be lost.
+fn restore(translation: &str, locked: &[String]) -> String {
+  let mut out = String::new();
+  let mut used = vec![false; locked.len()];
+  let mut rest = translation;
+  while let Some(open) = rest.find(OPEN) {
+    out.push_str(&rest[..open]);
+    let after = &rest[open + OPEN.len_utf8()..];
+    let piece = after
+      .split_once(CLOSE)
+      .and_then(|(n, tail)| Some((n.trim().parse::<usize>().ok()?.checked_sub(1)?, tail)))
+      .filter(|(n, _)| *n < locked.len());
+    match piece {
+      Some((n, tail)) => {
+        out.push_str(&locked[n]);
+        used[n] = true;
+        rest = tail;
+      }
+      None => {
+        out.push(OPEN);
+        rest = after;
+      }
+    }
+  }
+  out.push_str(rest);
+  for (piece, _) in locked.iter().zip(used).filter(|(_, used)| !used) {
+    out.push(' ');
+    out.push_str(piece);
+  }
+  out
+}
+
+fn read(data: &[u8]) -> Result<&str, String> {
+  std::str::from_utf8(data).map_err(|_| "Markdown files must be UTF-8".to_string())
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  Ok(
+    blocks(read(&data)?)
+      .into_iter()
+      // numbers, placeholders alone and other blocks without words
+      .filter(|b| b.text.chars().any(char::is_alphabetic))
+      .map(|b| Segment {
+        locator: format!("md:{}-{}", b.range.start, b.range.end),
+        text: b.text,
+      })
+      .collect(),
+  )
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let source = read(&task.source).map_err(invalid)?;
+  let mut out = String::with_capacity(source.len());
+  let mut at = 0;
+  for block in blocks(source) {
+    let locator = format!("md:{}-{}", block.range.start, block.range.end);
+    let Some(translation) = translations.get(locator.as_str()) else {
+      continue;
+    };
+    out.push_str(&source[at..block.range.start]);
+    // a line break would leave the block (or its quote or list item)
+    out.push_str(&restore(&translation.replace('\n', " "), &block.locked));
+    at = block.range.end;
+  }
+  out.push_str(&source[at..]);
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))
+}
+
+/// Writes the translated copy of Markdown task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_md(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,66 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri::{AppHandle, State};
+
+use crate::backend;
+
+/// Keeps the previous sample around: CPU% is computed between two refreshes.
+#[derive(Default)]
+pub struct MetricsState(Mutex<Option<System>>);
+
+#[derive(Serialize)]
+pub struct BackendMetrics {
+  pub pid: u32,
+  /// Percent of one core since the previous call (can exceed 100).
+  pub cpu_percent: f32,
+  pub rss_bytes: u64,
+  pub uptime_secs: u64,
+}
+
+fn refresh(sys: &mut System, pid: Pid) {
+  sys.refresh_processes_specifics(
+    ProcessesToUpdate::Some(&[pid]),
+    true,
+    ProcessRefreshKind::nothing().with_cpu().with_memory(),
+  );
+}
+
+/// `None` while no backend process is running (or it is external).
+#[tauri::command]
+pub async fn get_backend_metrics(
+  app: AppHandle,
+  state: State<'_, MetricsState>,
+) -> Result<Option<BackendMetrics>, String> {
+  let Some(raw_pid) = backend::current_pid(&app) else {
+    return Ok(None);
+  };
+  let pid = Pid::from_u32(raw_pid);
+
+  let first_sample = {
+    let mut guard = state.0.lock().unwrap();
+    let first = guard.is_none();
+    refresh(guard.get_or_insert_with(System::new), pid);
+    first
+  };
+  if first_sample {
+    // CPU usage needs a baseline sample to diff against
+    sleep_off_runtime(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+    refresh(state.0.lock().unwrap().as_mut().unwrap(), pid);
+  }
+
+  let guard = state.0.lock().unwrap();
+  let Some(proc) = guard.as_ref().and_then(|sys| sys.process(pid)) else {
+    return Ok(None);
+  };
+  Ok(Some(BackendMetrics {
+    pid: raw_pid,
+    cpu_percent: proc.cpu_usage(),
+    rss_bytes: proc.memory(),
+    uptime_secs: proc.run_time(),
+  }))
+}
+
+async fn sleep_off_runtime(d: std::time::Duration) {
+  let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(d)).await;
+}
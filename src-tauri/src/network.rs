@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{backend, config::StartupConfig, outbound};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Re-check sooner while offline, so queued work resumes quickly.
+const OFFLINE_POLL: Duration = Duration::from_secs(5);
+/// Consecutive failed rounds before reporting offline.
+const OFFLINE_AFTER: u32 = 2;
+
+/// Internet reachability as seen from the shell, through the configured
+/// proxy. Independent of the local backend's health.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NetworkStatus {
+  /// `None` until the first check finished.
+  pub online: Option<bool>,
+  pub latency_ms: Option<u64>,
+  /// Why the last round failed, while offline.
+  pub error: Option<String>,
+  /// Unix time of the last check, in milliseconds.
+  pub checked_at_ms: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct NetworkState(Mutex<NetworkStatus>);
+
+/// One round over the probe URLs. Any HTTP answer counts: a 401 from a
+/// provider still proves the route works.
+fn probe(urls: &[String]) -> Result<Duration, String> {
+  let client = outbound::builder()
+    .and_then(|b| b.timeout(PROBE_TIMEOUT).build())
+    .map_err(|e| e.to_string())?;
+  let mut last_error = "no probe URLs configured".to_string();
+  for url in urls {
+    let start = Instant::now();
+    match client.head(url).send() {
+      Ok(_) => return Ok(start.elapsed()),
+      Err(e) => last_error = format!("{url}: {e}"),
+    }
+  }
+  Err(last_error)
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+/// Checks connectivity every `network_poll_secs` for the whole session and
+/// emits `network-status` when going online or offline.
+pub fn spawn_monitor(app: AppHandle) {
+  let cfg = app.state::<StartupConfig>().inner().clone();
+  if cfg.network_poll_secs == 0 || cfg.network_probe_urls.is_empty() {
+    return;
+  }
+  let interval = Duration::from_secs(cfg.network_poll_secs);
+  std::thread::spawn(move || {
+    let mut failures = 0u32;
+    loop {
+      if backend::is_stopping(&app) {
+        return;
+      }
+      let result = probe(&cfg.network_probe_urls);
+      let state = app.state::<NetworkState>();
+      let (changed, status) = {
+        let mut current = state.0.lock().unwrap();
+        let was_online = current.online;
+        match result {
+          Ok(latency) => {
+            failures = 0;
+            *current = NetworkStatus {
+              online: Some(true),
+              latency_ms: Some(latency.as_millis() as u64),
+              error: None,
+              checked_at_ms: Some(now_ms()),
+            };
+          }
+          Err(e) => {
+            failures += 1;
+            current.checked_at_ms = Some(now_ms());
+            if failures >= OFFLINE_AFTER || current.online.is_none() {
+              *current = NetworkStatus {
+                online: Some(false),
+                latency_ms: None,
+                error: Some(e),
+                checked_at_ms: current.checked_at_ms,
+              };
+            }
+          }
+        }
+        (current.online != was_online, current.clone())
+      };
+      if changed {
+        let _ = app.emit("network-status", status.clone());
+      }
+      std::thread::sleep(if status.online == Some(false) {
+        OFFLINE_POLL.min(interval)
+      } else {
+        interval
+      });
+    }
+  });
+}
+
+#[tauri::command]
+pub fn get_network_status(state: State<NetworkState>) -> NetworkStatus {
+  state.0.lock().unwrap().clone()
+}
@@ -0,0 +1,468 @@
+//! OCR of scanned pages with Tesseract, run as a child process: the binary
+//! at `MVP_TESSERACT_PATH` or `tesseract_path` if set, else a `tesseract`
+//! sidecar bundled next to the app, else the one on `PATH`.
+//!
+//! Language packs (`<lang>.traineddata`, e.g. from tessdata_fast) go to
+//! `tessdata` in the downloads folder, see `start_download`; packs that
+//! came with a system Tesseract work too. The languages follow the task's
+//! source language (`eng`, or `chi_sim+eng` for Chinese) unless the
+//! `ocr_languages` setting names others.
+//!
+//! Each page read is reported as an `ocr-progress` event with the mean
+//! confidence of its words.
+
+use serde::Serialize;
+use std::{
+  cell::{Cell, OnceCell},
+  collections::BTreeMap,
+  fs,
+  io::Write,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{config::StartupConfig, integrity, paths, pdftext::is_cjk, settings::SettingsState};
+
+/// Overrides the Tesseract binary, like `MVP_BACKEND_PATH` the backend's.
+const TESSERACT_PATH_ENV: &str = "MVP_TESSERACT_PATH";
+const TESSERACT: &str = "tesseract";
+#[cfg(target_os = "windows")]
+const INSTALLED: &[&str] = &[
+  r"C:\Program Files\Tesseract-OCR\tesseract.exe",
+  r"C:\Program Files (x86)\Tesseract-OCR\tesseract.exe",
+];
+#[cfg(target_os = "macos")]
+const INSTALLED: &[&str] = &["/opt/homebrew/bin/tesseract", "/usr/local/bin/tesseract"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const INSTALLED: &[&str] = &["/usr/bin/tesseract", "/usr/local/bin/tesseract"];
+/// Paragraphs read with less confidence are almost always specks, photos
+/// or ruled lines taken for letters.
+const MIN_CONFIDENCE: f64 = 30.0;
+
+/// A paragraph as recognized; boxes are in pixels of the image.
+pub struct Paragraph {
+  pub text: String,
+  pub left: f64,
+  pub top: f64,
+  pub right: f64,
+  pub bottom: f64,
+  /// Mean height of its lines.
+  pub line_height: f64,
+}
+
+pub struct Recognized {
+  pub paragraphs: Vec<Paragraph>,
+  /// Mean confidence (0 to 100) of the words found; `None` without any.
+  pub confidence: Option<f64>,
+}
+
+/// How a scanned page went, as reported in `ocr-progress`.
+#[derive(Clone, Serialize)]
+pub struct PageProgress {
+  /// 1-based page number.
+  pub page: u32,
+  /// This is the `done`th of `total` scanned pages.
+  pub done: usize,
+  pub total: usize,
+  pub confidence: Option<f64>,
+  /// Segments found on the page.
+  pub blocks: usize,
+  pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct Progress<'a> {
+  id: &'a str,
+  #[serde(flatten)]
+  page: PageProgress,
+}
+
+#[derive(Serialize)]
+pub struct OcrStatus {
+  /// The Tesseract binary in use, `None` if there is none.
+  pub engine: Option<String>,
+  /// Why there is no engine.
+  pub error: Option<String>,
+  /// Installed language packs, in the downloads folder or the engine's own.
+  pub languages: Vec<String>,
+  /// Where downloaded packs go.
+  pub tessdata: String,
+}
+
+struct Setup {
+  engine: PathBuf,
+  /// `None` for the engine's own packs.
+  tessdata: Option<PathBuf>,
+  languages: String,
+}
+
+/// OCR for one upload; the engine is looked up on the first page.
+pub struct Session<'a> {
+  app: &'a AppHandle,
+  /// Tags the `ocr-progress` events, like `upload-progress` ones.
+  id: &'a str,
+  direction: &'a str,
+  setup: OnceCell<Result<Setup, String>>,
+  /// Reads nothing; see [`Session::off`].
+  off: bool,
+  skipped: Cell<usize>,
+}
+
+fn command(engine: &Path) -> Command {
+  let mut cmd = Command::new(engine);
+  cmd
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    cmd.creation_flags(CREATE_NO_WINDOW);
+  }
+  cmd
+}
+
+fn engine(app: &AppHandle) -> Result<PathBuf, String> {
+  let configured = std::env::var_os(TESSERACT_PATH_ENV)
+    .map(|p| (TESSERACT_PATH_ENV, PathBuf::from(p)))
+    .or_else(|| {
+      let cfg = app.state::<StartupConfig>();
+      cfg.tesseract_path.clone().map(|p| ("tesseract_path", p))
+    });
+  if let Some((source, p)) = configured {
+    if !p.is_file() {
+      return Err(format!(
+        "{source} points to a missing Tesseract binary: {}",
+        p.display()
+      ));
+    }
+    return Ok(p);
+  }
+  if let Ok(bundled) = integrity::sidecar_path(TESSERACT) {
+    if bundled.is_file() {
+      integrity::verify(TESSERACT, &bundled).map_err(|e| {
+        format!(
+          "The bundled Tesseract does not match its checksum: expected {}, got {}",
+          e.expected,
+          e.actual.unwrap_or_default()
+        )
+      })?;
+      return Ok(bundled);
+    }
+  }
+  let name = format!("{TESSERACT}{}", std::env::consts::EXE_SUFFIX);
+  std::env::var_os("PATH")
+    .iter()
+    .flat_map(std::env::split_paths)
+    .map(|dir| dir.join(&name))
+    .chain(INSTALLED.iter().map(PathBuf::from))
+    .find(|p| p.is_file())
+    .ok_or_else(|| "No OCR engine: install Tesseract or set tesseract_path".to_string())
+}
+
+fn tessdata(app: &AppHandle) -> Result<PathBuf, String> {
+  Ok(
+    paths::data_dir(app)
+      .map_err(|e| e.to_string())?
+      .join("downloads")
+      .join("tessdata"),
+  )
+}
+
+/// The packs in `dir`.
+fn downloaded(dir: &Path) -> Vec<String> {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return Vec::new();
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.file_name().to_string_lossy().into_owned();
+      name.strip_suffix(".traineddata").map(str::to_string)
+    })
+    .filter(|lang| lang != "osd")
+    .collect()
+}
+
+/// The packs the engine has of its own.
+fn builtin(engine: &Path) -> Vec<String> {
+  let Ok(output) = command(engine).arg("--list-langs").output() else {
+    return Vec::new();
+  };
+  // Tesseract 3 lists them on stderr; the first line says where they are
+  let listed = if output.stdout.is_empty() {
+    output.stderr
+  } else {
+    output.stdout
+  };
+  String::from_utf8_lossy(&listed)
+    .lines()
+    .skip(1)
+    .map(str::trim)
+    .filter(|lang| !lang.is_empty() && *lang != "osd" && !lang.contains(' '))
+    .map(str::to_string)
+    .collect()
+}
+
+/// Tesseract languages for documents in `direction`'s source language.
+fn languages_for(direction: &str) -> &'static str {
+  match direction.split("->").next().map(str::trim) {
+    Some("zh") => "chi_sim+eng",
+    _ => "eng",
+  }
+}
+
+impl<'a> Session<'a> {
+  pub fn new(app: &'a AppHandle, id: &'a str, direction: &'a str) -> Self {
+    Session {
+      app,
+      id,
+      direction,
+      setup: OnceCell::new(),
+      off: false,
+      skipped: Cell::new(0),
+    }
+  }
+
+  /// A session that reads no image and reports nothing, only counting the
+  /// images it was given; for a look at a document without OCR's cost.
+  pub fn off(app: &'a AppHandle) -> Self {
+    Session {
+      off: true,
+      ..Session::new(app, "", "")
+    }
+  }
+
+  /// Images not read because the session is [`Session::off`].
+  pub fn skipped(&self) -> usize {
+    self.skipped.get()
+  }
+
+  /// The engine, and where the packs for the languages wanted are; uses
+  /// whichever of the downloads folder and the engine's own packs has more
+  /// of them, as long as it has the first.
+  fn resolve(&self) -> Result<Setup, String> {
+    let engine = engine(self.app)?;
+    let configured = self.app.state::<SettingsState>().get().ocr_languages;
+    let wanted: Vec<String> = match configured.trim() {
+      "" => languages_for(self.direction),
+      custom => custom,
+    }
+    .split('+')
+    .map(str::trim)
+    .filter(|lang| !lang.is_empty())
+    .map(str::to_string)
+    .collect();
+    let primary = wanted.first().cloned().unwrap_or_else(|| "eng".to_string());
+    let dir = tessdata(self.app)?;
+    let choices = [
+      (Some(dir.clone()), downloaded(&dir)),
+      (None, builtin(&engine)),
+    ];
+    let (tessdata, available) = choices
+      .into_iter()
+      .filter(|(_, langs)| langs.contains(&primary))
+      .max_by_key(|(_, langs)| wanted.iter().filter(|l| langs.contains(l)).count())
+      .ok_or_else(|| {
+        format!(
+          "The OCR language pack {primary} is not installed: download {primary}.traineddata \
+           to {}",
+          dir.display()
+        )
+      })?;
+    let languages = wanted
+      .iter()
+      .filter(|l| available.contains(l))
+      .cloned()
+      .collect::<Vec<_>>()
+      .join("+");
+    Ok(Setup {
+      engine,
+      tessdata,
+      languages,
+    })
+  }
+
+  /// Reads the text off `image` (any format Tesseract reads: JPEG, PNG,
+  /// TIFF, PNM), scanned at `dpi`.
+  pub fn recognize(&self, image: &[u8], dpi: u32) -> Result<Recognized, String> {
+    if self.off {
+      self.skipped.set(self.skipped.get() + 1);
+      return Err("OCR is off".to_string());
+    }
+    let setup = self
+      .setup
+      .get_or_init(|| self.resolve())
+      .as_ref()
+      .map_err(String::clone)?;
+    let mut cmd = command(&setup.engine);
+    cmd
+      .args(["stdin", "stdout", "-l", &setup.languages, "--psm", "3"])
+      .args(["--dpi", &dpi.to_string()])
+      .stdin(Stdio::piped());
+    if let Some(dir) = &setup.tessdata {
+      cmd.arg("--tessdata-dir").arg(dir);
+    }
+    let mut child = cmd
+      .arg("tsv")
+      .spawn()
+      .map_err(|e| format!("Cannot run {}: {e}", setup.engine.display()))?;
+    // Tesseract reads the whole image before it writes anything
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin
+        .write_all(image)
+        .map_err(|e| format!("Cannot pass the page to Tesseract: {e}"))?;
+    }
+    let output = child
+      .wait_with_output()
+      .map_err(|e| format!("Tesseract failed: {e}"))?;
+    if !output.status.success() {
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      return Err(format!("Tesseract failed: {}", stderr.trim()));
+    }
+    Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+  }
+
+  pub fn report(&self, page: PageProgress) {
+    if self.off {
+      return;
+    }
+    let _ = self
+      .app
+      .emit("ocr-progress", Progress { id: self.id, page });
+  }
+}
+
+/// Appends a word to a line, without a space between CJK characters.
+fn join_word(line: &mut String, word: &str) {
+  let prev = line.chars().last();
+  if prev.is_some_and(|c| !(is_cjk(c) && word.chars().next().is_some_and(is_cjk))) {
+    line.push(' ');
+  }
+  line.push_str(word);
+}
+
+#[derive(Default)]
+struct Line {
+  text: String,
+  top: f64,
+  bottom: f64,
+}
+
+#[derive(Default)]
+struct Gathered {
+  lines: Vec<Line>,
+  left: f64,
+  top: f64,
+  right: f64,
+  bottom: f64,
+  confidence: f64,
+  words: usize,
+  /// Tesseract's number of the last line, which restart in each paragraph.
+  line: u32,
+}
+
+/// Tesseract's TSV output: a row per page, block, paragraph, line and word
+/// (level 1 to 5), words with their box and confidence.
+fn parse_tsv(tsv: &str) -> Recognized {
+  let mut paragraphs: BTreeMap<(u32, u32), Gathered> = BTreeMap::new();
+  let (mut total, mut words) = (0.0, 0);
+  for row in tsv.lines().skip(1) {
+    let cols: Vec<&str> = row.split('\t').collect();
+    if cols.len() < 12 || cols[0] != "5" {
+      continue;
+    }
+    let text = cols[11].trim();
+    let num = |i: usize| cols[i].trim().parse::<f64>().unwrap_or_default();
+    let confidence = num(10);
+    if text.is_empty() || confidence < 0.0 {
+      continue;
+    }
+    let key = (num(2) as u32, num(3) as u32);
+    let line_num = num(4) as u32;
+    let (left, top) = (num(6), num(7));
+    let (right, bottom) = (left + num(8), top + num(9));
+    total += confidence;
+    words += 1;
+    let para = paragraphs.entry(key).or_default();
+    if para.words == 0 {
+      (para.left, para.top, para.right, para.bottom) = (left, top, right, bottom);
+    }
+    para.left = para.left.min(left);
+    para.top = para.top.min(top);
+    para.right = para.right.max(right);
+    para.bottom = para.bottom.max(bottom);
+    para.confidence += confidence;
+    para.words += 1;
+    if para.lines.is_empty() || para.line != line_num {
+      para.line = line_num;
+      para.lines.push(Line {
+        text: String::new(),
+        top,
+        bottom,
+      });
+    }
+    let line = para.lines.last_mut().unwrap();
+    join_word(&mut line.text, text);
+    line.top = line.top.min(top);
+    line.bottom = line.bottom.max(bottom);
+  }
+  let paragraphs = paragraphs
+    .into_values()
+    .filter(|p| p.confidence / p.words as f64 >= MIN_CONFIDENCE)
+    .filter_map(|p| {
+      let line_height =
+        p.lines.iter().map(|l| l.bottom - l.top).sum::<f64>() / p.lines.len() as f64;
+      let mut lines = p.lines.into_iter().map(|l| l.text);
+      let mut text = lines.next()?;
+      for line in lines {
+        crate::pdftext::join_line(&mut text, &line);
+      }
+      text
+        .chars()
+        .any(char::is_alphanumeric)
+        .then_some(Paragraph {
+          text,
+          left: p.left,
+          top: p.top,
+          right: p.right,
+          bottom: p.bottom,
+          line_height,
+        })
+    })
+    .collect();
+  Recognized {
+    paragraphs,
+    confidence: (words > 0).then(|| total / words as f64),
+  }
+}
+
+fn status(app: &AppHandle) -> OcrStatus {
+  let dir = tessdata(app).unwrap_or_default();
+  let mut languages = downloaded(&dir);
+  let (engine, error) = match engine(app) {
+    Ok(engine) => {
+      languages.extend(builtin(&engine));
+      (Some(engine.display().to_string()), None)
+    }
+    Err(e) => (None, Some(e)),
+  };
+  languages.sort();
+  languages.dedup();
+  OcrStatus {
+    engine,
+    error,
+    languages,
+    tessdata: dir.display().to_string(),
+  }
+}
+
+/// The OCR engine found and its language packs.
+#[tauri::command]
+pub async fn get_ocr_status(app: AppHandle) -> Result<OcrStatus, String> {
+  tauri::async_runtime::spawn_blocking(move || status(&app))
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,315 @@
+//! OpenDocument files (LibreOffice's ODT, ODS and ODP) read and rebuilt in
+//! the shell, like the Office formats in `ooxml`. All three keep their text
+//! in `text:p` and `text:h` paragraphs: a text document's body, a
+//! spreadsheet's cells and a presentation's frames and notes in
+//! `content.xml`, headers and footers in `styles.xml`. Segments are those
+//! paragraphs, the body first; comments and recorded changes are left as
+//! they are.
+//!
+//! Unlike OOXML, characters sit right in the paragraph or in its spans,
+//! with runs of spaces, tabs and line breaks as elements (`text:s`,
+//! `text:tab`, `text:line-break`). A translated paragraph gets the
+//! translation where its first characters were, in that span's style, and
+//! loses its other characters; its spans, links, frames and notes stay.
+//! The package is rewritten with only those two parts replaced, so the
+//! `mimetype` entry stays first and stored, as ODF wants.
+//!
+//! Locators are `odf:<part>#<paragraph index>`, e.g. `odf:content.xml#7`.
+
+use quick_xml::{
+  events::{BytesStart, BytesText, Event},
+  Reader, Writer,
+};
+use std::{collections::HashMap, fs, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  ooxml::{self, Paragraphs},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+const PARTS: [&str; 2] = ["content.xml", "styles.xml"];
+/// Elements whose text is not the paragraph's: note numbers, comments, the
+/// deleted text of recorded changes, and image titles and descriptions.
+const SKIPPED: [&[u8]; 5] = [
+  b"text:note-citation",
+  b"office:annotation",
+  b"text:tracked-changes",
+  b"svg:title",
+  b"svg:desc",
+];
+
+struct Paragraph<'a> {
+  index: usize,
+  text: String,
+  replacement: Option<&'a str>,
+  written: bool,
+  /// Whether the text so far ends in collapsible white space, or is none.
+  space: bool,
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid document XML: {e}")
+}
+
+fn is_paragraph(name: quick_xml::name::QName) -> bool {
+  matches!(name.as_ref(), b"text:p" | b"text:h")
+}
+
+/// What a `text:s`, `text:tab` or `text:line-break` element stands for.
+fn character(e: &BytesStart) -> Option<String> {
+  Some(match e.name().as_ref() {
+    b"text:s" => {
+      let count = e
+        .try_get_attribute("text:c")
+        .ok()
+        .flatten()
+        .and_then(|a| String::from_utf8_lossy(&a.value).parse().ok())
+        .unwrap_or(1);
+      " ".repeat(count)
+    }
+    b"text:tab" => "\t".to_string(),
+    b"text:line-break" => "\n".to_string(),
+    _ => return None,
+  })
+}
+
+/// Writes `text` as paragraph content: tabs and breaks as their elements,
+/// and spaces that ODF would collapse (all but the first of a run, and
+/// those at the start of a line) as `text:s`.
+fn write_text(w: &mut Writer<Vec<u8>>, text: &str) -> std::io::Result<()> {
+  let mut piece = String::new();
+  let mut spaces = 0;
+  // whether a single space here is kept as it is
+  let mut after_text = false;
+  let flush = |w: &mut Writer<Vec<u8>>, piece: &mut String, spaces: &mut usize, after_text| {
+    if *spaces == 0 {
+      return Ok(());
+    }
+    if after_text {
+      piece.push(' ');
+      *spaces -= 1;
+    }
+    if *spaces > 0 {
+      w.write_event(Event::Text(BytesText::new(piece)))?;
+      piece.clear();
+      let mut s = BytesStart::new("text:s");
+      if *spaces > 1 {
+        s.push_attribute(("text:c", spaces.to_string().as_str()));
+      }
+      w.write_event(Event::Empty(s))?;
+      *spaces = 0;
+    }
+    Ok::<_, std::io::Error>(())
+  };
+  for c in text.chars() {
+    match c {
+      ' ' => spaces += 1,
+      '\t' | '\n' => {
+        flush(w, &mut piece, &mut spaces, after_text)?;
+        w.write_event(Event::Text(BytesText::new(&piece)))?;
+        piece.clear();
+        let name = if c == '\t' {
+          "text:tab"
+        } else {
+          "text:line-break"
+        };
+        w.write_event(Event::Empty(BytesStart::new(name)))?;
+        after_text = c == '\t';
+      }
+      // characters XML 1.0 cannot hold at all
+      c if c < ' ' => {}
+      c => {
+        flush(w, &mut piece, &mut spaces, after_text)?;
+        piece.push(c);
+        after_text = true;
+      }
+    }
+  }
+  flush(w, &mut piece, &mut spaces, after_text)?;
+  w.write_event(Event::Text(BytesText::new(&piece)))
+}
+
+/// Reads `xml`, writing it back with `replacements` when given. Returns
+/// every non-empty paragraph with its index, in document order.
+fn walk(
+  xml: &[u8],
+  replacements: Option<&HashMap<usize, String>>,
+) -> Result<(Paragraphs, Vec<u8>), String> {
+  let mut reader = Reader::from_reader(xml);
+  let mut writer = replacements.map(|_| Writer::new(Vec::with_capacity(xml.len())));
+  let mut found = Vec::new();
+  let mut stack: Vec<Paragraph> = Vec::new();
+  let mut count = 0;
+  // depth of an element whose text is kept out of the paragraphs
+  let mut skipping: Option<usize> = None;
+  // depth of an element being dropped, with everything in it
+  let mut dropping: Option<usize> = None;
+  let mut depth = 0;
+
+  loop {
+    let event = reader.read_event().map_err(xml_err)?;
+    if let Event::Eof = event {
+      break;
+    }
+    let before = depth;
+    match &event {
+      Event::Start(_) => depth += 1,
+      Event::End(_) => depth -= 1,
+      _ => {}
+    }
+    if let Some(until) = dropping {
+      if depth == until {
+        dropping = None;
+      }
+      continue;
+    }
+    if let Some(until) = skipping {
+      if depth == until {
+        skipping = None;
+      }
+    } else {
+      let mut keep = true;
+      let mut text = false;
+      match &event {
+        Event::Start(e) if SKIPPED.contains(&e.name().as_ref()) => skipping = Some(before),
+        Event::Start(e) if is_paragraph(e.name()) => {
+          stack.push(Paragraph {
+            index: count,
+            text: String::new(),
+            replacement: replacements.and_then(|r| r.get(&count)).map(String::as_str),
+            written: false,
+            space: true,
+          });
+          count += 1;
+        }
+        Event::Empty(e) if is_paragraph(e.name()) => count += 1,
+        Event::End(e) if is_paragraph(e.name()) => {
+          if let Some(p) = stack.pop() {
+            if !p.text.trim().is_empty() {
+              found.push((p.index, p.text));
+            }
+          }
+        }
+        Event::Text(t) if !stack.is_empty() => {
+          let p = stack.last_mut().unwrap();
+          for c in t.unescape().map_err(xml_err)?.chars() {
+            // white space in the XML is collapsed, as ODF renders it
+            if matches!(c, ' ' | '\t' | '\n' | '\r') {
+              if !p.space {
+                p.text.push(' ');
+              }
+              p.space = true;
+            } else {
+              p.text.push(c);
+              p.space = false;
+            }
+          }
+          text = true;
+        }
+        Event::CData(t) if !stack.is_empty() => {
+          let p = stack.last_mut().unwrap();
+          p.text.push_str(&String::from_utf8_lossy(t));
+          p.space = false;
+          text = true;
+        }
+        Event::Start(e) | Event::Empty(e) if !stack.is_empty() => {
+          if let Some(c) = character(e) {
+            let p = stack.last_mut().unwrap();
+            p.space = c == "\n";
+            p.text.push_str(&c);
+            text = true;
+            if p.replacement.is_some() && matches!(event, Event::Start(_)) {
+              dropping = Some(before);
+            }
+          }
+        }
+        _ => {}
+      }
+      if let (true, Some(p), Some(w)) = (text, stack.last_mut(), writer.as_mut()) {
+        if let Some(replacement) = p.replacement {
+          // the translation carries its own spaces and breaks
+          keep = false;
+          if !p.written {
+            p.written = true;
+            write_text(w, replacement).map_err(xml_err)?;
+          }
+        }
+      }
+      if !keep {
+        continue;
+      }
+    }
+    if let Some(w) = writer.as_mut() {
+      w.write_event(event).map_err(xml_err)?;
+    }
+  }
+  // nested paragraphs (notes, text boxes) end first
+  found.sort_by_key(|(index, _)| *index);
+  Ok((found, writer.map(Writer::into_inner).unwrap_or_default()))
+}
+
+fn parse_locator(locator: &str) -> Option<(&str, usize)> {
+  let (part, index) = locator.strip_prefix("odf:")?.rsplit_once('#')?;
+  Some((part, index.parse().ok()?))
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut archive = ooxml::open(&data)?;
+  let mut out = Vec::new();
+  for part in PARTS {
+    if archive.index_for_name(part).is_none() {
+      continue;
+    }
+    let xml = ooxml::read_part(&mut archive, part)?;
+    for (index, text) in walk(&xml, None)?.0 {
+      // numbers in cells, page numbers and other fields without words
+      if !text.chars().any(char::is_alphabetic) {
+        continue;
+      }
+      out.push(Segment {
+        locator: format!("odf:{part}#{index}"),
+        text: text.trim().to_string(),
+      });
+    }
+  }
+  Ok(out)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let mut by_part: HashMap<&str, HashMap<usize, String>> = HashMap::new();
+  for (locator, text) in &blocks {
+    if let Some((part, index)) = parse_locator(locator) {
+      by_part.entry(part).or_default().insert(index, text.clone());
+    }
+  }
+  let mut archive = ooxml::open(&source).map_err(invalid)?;
+  let mut replaced = HashMap::new();
+  for (part, texts) in &by_part {
+    if !PARTS.contains(part) {
+      continue;
+    }
+    let xml = ooxml::read_part(&mut archive, part).map_err(invalid)?;
+    replaced.insert(
+      part.to_string(),
+      walk(&xml, Some(texts)).map_err(invalid)?.1,
+    );
+  }
+  ooxml::rewrite(&source, path, &replaced).map_err(invalid)
+}
+
+/// Writes the translated copy of OpenDocument task `job_id` (a text
+/// document, spreadsheet or presentation) to `path`.
+#[tauri::command]
+pub async fn export_translated_odf(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
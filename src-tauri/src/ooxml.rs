@@ -0,0 +1,710 @@
+//! Office Open XML packages: zip files of XML parts. Word and PowerPoint
+//! share the text model, paragraphs of runs whose text elements hold the
+//! characters, so both read and rewrite paragraphs here and only differ in
+//! the element names ([`Markup`]).
+//!
+//! A translated paragraph keeps its properties and runs: the translation
+//! goes into its first text element, in that run's formatting, and the
+//! other text elements are emptied. Parts are rewritten event by event, so
+//! everything else in them stays byte for byte.
+
+use quick_xml::{
+  events::{BytesStart, BytesText, Event},
+  Reader, Writer,
+};
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{Cursor, Read, Write},
+  path::Path,
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Qualified element names of a format's text model.
+pub struct Markup {
+  pub paragraph: &'static [u8],
+  pub run: &'static [u8],
+  pub run_properties: &'static [u8],
+  pub text: &'static [u8],
+  /// A line break inside a paragraph, read as `\n`.
+  pub line_break: &'static [u8],
+  /// Whether breaks go inside runs (Word) or between them (DrawingML).
+  pub break_in_run: bool,
+  /// A tab element, read as `\t`; formats that keep tabs in the text have
+  /// none.
+  pub tab: Option<&'static [u8]>,
+  /// Whether text elements need `xml:space="preserve"` to keep edge spaces.
+  pub preserve_space: bool,
+  pub revisions: Option<Revisions>,
+}
+
+/// Tracked changes, in formats that record them.
+pub struct Revisions {
+  /// Elements around inserted runs.
+  pub inserted: &'static [&'static [u8]],
+  /// Elements around deleted runs.
+  pub deleted: &'static [&'static [u8]],
+  /// The text element of deleted runs.
+  pub deleted_text: &'static [u8],
+  /// The record of a run's formatting before a change, left out of the
+  /// runs written for a tracked replacement.
+  pub properties_change: &'static [u8],
+  /// The attribute numbering changes, unique in a part.
+  pub id: &'static str,
+}
+
+/// The run being read: its start tag and its properties' events, to open
+/// an equal run after a break that has to go between runs.
+type Run = (BytesStart<'static>, Vec<Event<'static>>);
+
+/// Paragraph indexes with their text.
+pub type Paragraphs = Vec<(usize, String)>;
+
+/// A paragraph with tracked changes: its text before and after them.
+pub struct Revised {
+  pub index: usize,
+  /// Deleted runs in, inserted ones out.
+  pub original: String,
+  /// As the document reads now.
+  pub current: String,
+}
+
+/// What a paragraph gets instead of its text.
+pub enum Replacement {
+  /// Text in the first text element; any tracked changes keep their
+  /// place but lose their text.
+  Text(String),
+  /// A tracked replacement of the whole paragraph: its `original` text
+  /// deleted and its `current` text inserted, so accepting or rejecting
+  /// the changes gives either. `None` keeps the paragraph's own version.
+  Revised {
+    original: Option<String>,
+    current: Option<String>,
+  },
+}
+
+struct Paragraph<'a> {
+  index: usize,
+  text: String,
+  /// The text before tracked changes, and whether there were any.
+  original: String,
+  revised: bool,
+  replacement: Option<&'a Replacement>,
+  written: bool,
+  /// Depth inside the paragraph element, where its children start.
+  depth: usize,
+  children: usize,
+  /// The child holding the first text, and the properties of its run.
+  first_text_child: Option<usize>,
+  properties: Option<Vec<Event<'static>>>,
+  insertion: Option<BytesStart<'static>>,
+  deletion: Option<BytesStart<'static>>,
+}
+
+/// A paragraph as read, for rewriting it as a tracked replacement.
+struct Found {
+  index: usize,
+  text: String,
+  original: Option<String>,
+  first_text_child: Option<usize>,
+  properties: Vec<Event<'static>>,
+  insertion: Option<BytesStart<'static>>,
+  deletion: Option<BytesStart<'static>>,
+}
+
+/// What a rewrite needs from reading the part first.
+struct Prepass {
+  found: HashMap<usize, Found>,
+  next_id: u64,
+}
+
+/// Only plain breaks and tabs are text; page and column breaks, tab stops
+/// and the like have a `type` or `val`.
+fn is_plain(e: &BytesStart) -> bool {
+  !e.attributes()
+    .flatten()
+    .any(|a| matches!(a.key.local_name().as_ref(), b"type" | b"val"))
+}
+
+fn with_preserve(e: &BytesStart, markup: &Markup) -> BytesStart<'static> {
+  if !markup.preserve_space {
+    return e.clone().into_owned();
+  }
+  let mut out = BytesStart::new(String::from_utf8_lossy(e.name().as_ref()).into_owned());
+  out.extend_attributes(
+    e.attributes()
+      .flatten()
+      .filter(|a| a.key.as_ref() != b"xml:space"),
+  );
+  out.push_attribute(("xml:space", "preserve"));
+  out
+}
+
+/// Writes `text` as the content of text element `e`, with its breaks
+/// (and tabs, where the format has an element for them) as elements
+/// between text elements.
+fn write_text(
+  w: &mut Writer<Vec<u8>>,
+  e: &BytesStart,
+  run: Option<&Run>,
+  markup: &Markup,
+  text: &str,
+) -> std::io::Result<()> {
+  w.write_event(Event::Start(with_preserve(e, markup)))?;
+  let mut piece = String::new();
+  for c in text.chars() {
+    let element = match c {
+      '\n' => Some(markup.line_break),
+      '\t' => markup.tab,
+      _ => None,
+    };
+    let Some(element) = element else {
+      piece.push(c);
+      continue;
+    };
+    w.write_event(Event::Text(BytesText::new(&piece)))?;
+    piece.clear();
+    w.write_event(Event::End(e.to_end()))?;
+    let between_runs = c == '\n' && !markup.break_in_run;
+    if let (true, Some((start, _))) = (between_runs, run) {
+      w.write_event(Event::End(start.to_end()))?;
+    }
+    let name = String::from_utf8_lossy(element).into_owned();
+    w.write_event(Event::Empty(BytesStart::new(name)))?;
+    if let (true, Some((start, properties))) = (between_runs, run) {
+      w.write_event(Event::Start(start.clone()))?;
+      for event in properties {
+        w.write_event(event.clone())?;
+      }
+    }
+    w.write_event(Event::Start(with_preserve(e, markup)))?;
+  }
+  w.write_event(Event::Text(BytesText::new(&piece)))
+}
+
+/// Writes `found`'s tracked replacement: a deleted run with `original`,
+/// then an inserted one with `current`, in the formatting of its first
+/// text. The changes they replace keep their marks but are emptied.
+fn write_revision(
+  w: &mut Writer<Vec<u8>>,
+  markup: &Markup,
+  revisions: &Revisions,
+  found: &Found,
+  texts: [&str; 2],
+  next_id: &mut u64,
+) -> std::io::Result<()> {
+  let name = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+  let kinds = [
+    (
+      revisions.deleted[0],
+      &found.deletion,
+      revisions.deleted_text,
+    ),
+    (revisions.inserted[0], &found.insertion, markup.text),
+  ];
+  for (text, (wrapper, own, element)) in texts.into_iter().zip(kinds) {
+    if text.trim().is_empty() {
+      continue;
+    }
+    // the author and date of the change, or of the other kind
+    let mut start = BytesStart::new(name(wrapper));
+    if let Some(like) = own
+      .as_ref()
+      .or(found.deletion.as_ref())
+      .or(found.insertion.as_ref())
+    {
+      start.extend_attributes(
+        like
+          .attributes()
+          .flatten()
+          .filter(|a| a.key.as_ref() != revisions.id.as_bytes()),
+      );
+    }
+    start.push_attribute((revisions.id, next_id.to_string().as_str()));
+    *next_id += 1;
+    w.write_event(Event::Start(start.clone()))?;
+    let run = BytesStart::new(name(markup.run));
+    w.write_event(Event::Start(run.clone()))?;
+    for event in &found.properties {
+      w.write_event(event.clone())?;
+    }
+    let text_element = BytesStart::new(name(element));
+    write_text(w, &text_element, None, markup, text)?;
+    w.write_event(Event::End(text_element.to_end()))?;
+    w.write_event(Event::End(run.to_end()))?;
+    w.write_event(Event::End(start.to_end()))?;
+  }
+  Ok(())
+}
+
+/// `properties` without the record of earlier formatting, whose ids the
+/// runs it came from keep.
+fn without_change(
+  properties: &[Event<'static>],
+  revisions: Option<&Revisions>,
+) -> Vec<Event<'static>> {
+  let Some(change) = revisions.map(|r| r.properties_change) else {
+    return properties.to_vec();
+  };
+  let mut out = Vec::new();
+  let mut inside = 0;
+  for event in properties {
+    match event {
+      Event::Start(e) if inside > 0 || e.name().as_ref() == change => inside += 1,
+      Event::End(_) if inside > 0 => inside -= 1,
+      Event::Empty(e) if e.name().as_ref() == change => {}
+      _ if inside > 0 => {}
+      _ => out.push(event.clone()),
+    }
+  }
+  out
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid document XML: {e}")
+}
+
+/// Reads `xml`, writing it back with `replacements` when given (with what
+/// reading it found first). Returns every paragraph with text or tracked
+/// changes by index (paragraphs counted in document order, nested ones
+/// included), and the first free change id.
+fn walk(
+  xml: &[u8],
+  markup: &Markup,
+  rewrite: Option<(&HashMap<usize, Replacement>, &mut Prepass)>,
+) -> Result<(Vec<Found>, u64, Vec<u8>), String> {
+  let mut reader = Reader::from_reader(xml);
+  let (replacements, mut prepass) = match rewrite {
+    Some((r, p)) => (Some(r), Some(p)),
+    None => (None, None),
+  };
+  let mut writer = replacements.map(|_| Writer::new(Vec::with_capacity(xml.len())));
+  let revisions = markup.revisions.as_ref();
+  let mut found = Vec::new();
+  let mut stack: Vec<Paragraph> = Vec::new();
+  let mut count = 0;
+  let mut max_id = 0;
+  let mut in_text = false;
+  let mut in_deleted_text = false;
+  let mut run: Option<Run> = None;
+  // depth of the run properties being captured into `run`
+  let mut capturing: Option<usize> = None;
+  // depth of an element being dropped, with everything in it
+  let mut dropping: Option<usize> = None;
+  // depth of the insertion or deletion being read, and which it is
+  let mut change: Option<(usize, bool)> = None;
+  let mut depth = 0;
+
+  loop {
+    let event = reader.read_event().map_err(xml_err)?;
+    if let Some(until) = dropping {
+      match &event {
+        Event::Start(_) => depth += 1,
+        Event::End(_) => {
+          depth -= 1;
+          if depth == until {
+            dropping = None;
+          }
+        }
+        Event::Eof => return Err(xml_err("unexpected end")),
+        _ => {}
+      }
+      continue;
+    }
+    if let (Some(revisions), Event::Start(e) | Event::Empty(e)) = (revisions, &event) {
+      if let Ok(Some(id)) = e.try_get_attribute(revisions.id) {
+        if let Ok(id) = String::from_utf8_lossy(&id.value).parse::<u64>() {
+          max_id = max_id.max(id);
+        }
+      }
+    }
+    let replacement = stack.last().and_then(|p| p.replacement);
+    let replacing = replacement.is_some();
+    let tracked = matches!(replacement, Some(Replacement::Revised { .. })) && revisions.is_some();
+    let mut keep = true;
+    // where a tracked replacement goes: before the child with the first text
+    if let (Event::Start(_) | Event::Empty(_), Some(p)) = (&event, stack.last_mut()) {
+      if depth == p.depth {
+        let child = p.children;
+        p.children += 1;
+        let first = prepass
+          .as_ref()
+          .and_then(|pre| pre.found.get(&p.index))
+          .and_then(|f| f.first_text_child);
+        if let (true, false, Some(w)) =
+          (tracked && first == Some(child), p.written, writer.as_mut())
+        {
+          p.written = true;
+          write_tracked(w, markup, p, prepass.as_deref_mut()).map_err(xml_err)?;
+        }
+      }
+    }
+    match &event {
+      Event::Start(e) if e.name().as_ref() == markup.paragraph => {
+        stack.push(Paragraph {
+          index: count,
+          text: String::new(),
+          original: String::new(),
+          revised: false,
+          replacement: replacements.and_then(|r| r.get(&count)),
+          written: false,
+          depth: depth + 1,
+          children: 0,
+          first_text_child: None,
+          properties: None,
+          insertion: None,
+          deletion: None,
+        });
+        count += 1;
+      }
+      Event::End(e) if e.name().as_ref() == markup.paragraph => {
+        if let Some(mut p) = stack.pop() {
+          // a tracked replacement with no text element to go before
+          if let (true, false, Some(w)) = (tracked, p.written, writer.as_mut()) {
+            write_tracked(w, markup, &p, prepass.as_deref_mut()).map_err(xml_err)?;
+            p.written = true;
+          }
+          if !p.text.trim().is_empty() || p.revised {
+            found.push(Found {
+              index: p.index,
+              original: p.revised.then_some(p.original),
+              text: p.text,
+              first_text_child: p.first_text_child,
+              properties: without_change(&p.properties.unwrap_or_default(), revisions),
+              insertion: p.insertion,
+              deletion: p.deletion,
+            });
+          }
+        }
+      }
+      Event::Start(e)
+        if change.is_none()
+          && !stack.is_empty()
+          && revisions.is_some_and(|r| {
+            r.inserted.contains(&e.name().as_ref()) || r.deleted.contains(&e.name().as_ref())
+          }) =>
+      {
+        let inserted = revisions.is_some_and(|r| r.inserted.contains(&e.name().as_ref()));
+        change = Some((depth, inserted));
+        let p = stack.last_mut().unwrap();
+        let slot = if inserted {
+          &mut p.insertion
+        } else {
+          &mut p.deletion
+        };
+        slot.get_or_insert_with(|| e.clone().into_owned());
+      }
+      Event::Start(e) if e.name().as_ref() == markup.text && !stack.is_empty() => {
+        in_text = true;
+        let p = stack.last_mut().unwrap();
+        if let (Some(Replacement::Text(text)), Some(w), false) =
+          (p.replacement, writer.as_mut(), p.written)
+        {
+          p.written = true;
+          keep = false;
+          write_text(w, e, run.as_ref(), markup, text).map_err(xml_err)?;
+        }
+      }
+      Event::End(e) if e.name().as_ref() == markup.text => in_text = false,
+      Event::Start(e)
+        if !stack.is_empty() && revisions.is_some_and(|r| e.name().as_ref() == r.deleted_text) =>
+      {
+        in_deleted_text = true;
+      }
+      Event::End(e) if revisions.is_some_and(|r| e.name().as_ref() == r.deleted_text) => {
+        in_deleted_text = false;
+      }
+      Event::Start(e) if e.name().as_ref() == markup.run => {
+        run = Some((e.clone().into_owned(), Vec::new()));
+      }
+      Event::End(e) if e.name().as_ref() == markup.run => run = None,
+      Event::Text(_) | Event::CData(_) if in_text || in_deleted_text => {
+        let text = match &event {
+          Event::Text(t) => t.unescape().map_err(xml_err)?.into_owned(),
+          Event::CData(t) => String::from_utf8_lossy(t).into_owned(),
+          _ => unreachable!(),
+        };
+        if let Some(p) = stack.last_mut() {
+          p.push(&text, change, in_deleted_text);
+          if p.first_text_child.is_none() {
+            p.first_text_child = p.children.checked_sub(1);
+            p.properties = Some(run.as_ref().map(|r| r.1.clone()).unwrap_or_default());
+          }
+        }
+        // deleted text stays where only the first text element is replaced
+        keep = !(tracked || replacing && in_text);
+      }
+      Event::Start(e) | Event::Empty(e)
+        if !stack.is_empty()
+          && !in_text
+          && is_plain(e)
+          && (e.name().as_ref() == markup.line_break || markup.tab == Some(e.name().as_ref())) =>
+      {
+        let p = stack.last_mut().unwrap();
+        let c = if e.name().as_ref() == markup.line_break {
+          "\n"
+        } else {
+          "\t"
+        };
+        p.push(c, change, false);
+        if replacing {
+          // the translation carries its own breaks
+          keep = false;
+          if matches!(event, Event::Start(_)) {
+            dropping = Some(depth);
+          }
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+    match &event {
+      Event::Start(_) => depth += 1,
+      Event::End(_) => {
+        depth -= 1;
+        if change.is_some_and(|(at, _)| at == depth) {
+          change = None;
+        }
+      }
+      _ => {}
+    }
+    if let Some((_, properties)) = run.as_mut() {
+      match (&event, capturing) {
+        (Event::Start(e), None) if e.name().as_ref() == markup.run_properties => {
+          capturing = Some(depth - 1);
+          properties.push(event.clone().into_owned());
+        }
+        (Event::Empty(e), None) if e.name().as_ref() == markup.run_properties => {
+          properties.push(event.clone().into_owned());
+        }
+        (_, Some(start)) => {
+          properties.push(event.clone().into_owned());
+          if matches!(event, Event::End(_)) && depth == start {
+            capturing = None;
+          }
+        }
+        _ => {}
+      }
+    }
+    if keep {
+      if let Some(w) = writer.as_mut() {
+        w.write_event(event).map_err(xml_err)?;
+      }
+    }
+  }
+  // nested paragraphs end first
+  found.sort_by_key(|f| f.index);
+  Ok((
+    found,
+    max_id + 1,
+    writer.map(Writer::into_inner).unwrap_or_default(),
+  ))
+}
+
+impl Paragraph<'_> {
+  /// Adds text read inside `change` (a deletion or insertion, if any).
+  fn push(&mut self, text: &str, change: Option<(usize, bool)>, deleted_text: bool) {
+    let deleted = deleted_text || change.is_some_and(|(_, inserted)| !inserted);
+    let inserted = change.is_some_and(|(_, inserted)| inserted);
+    if !deleted {
+      self.text.push_str(text);
+    }
+    if !inserted {
+      self.original.push_str(text);
+    }
+    self.revised |= deleted || inserted;
+  }
+}
+
+/// Writes paragraph `p`'s tracked replacement, as read in `prepass`.
+fn write_tracked(
+  w: &mut Writer<Vec<u8>>,
+  markup: &Markup,
+  p: &Paragraph,
+  prepass: Option<&mut Prepass>,
+) -> std::io::Result<()> {
+  let (Some(Replacement::Revised { original, current }), Some(revisions), Some(prepass)) =
+    (p.replacement, markup.revisions.as_ref(), prepass)
+  else {
+    return Ok(());
+  };
+  let Some(found) = prepass.found.get(&p.index) else {
+    return Ok(());
+  };
+  let texts = [
+    original
+      .as_deref()
+      .or(found.original.as_deref())
+      .unwrap_or_default(),
+    current.as_deref().unwrap_or(&found.text),
+  ];
+  write_revision(w, markup, revisions, found, texts, &mut prepass.next_id)
+}
+
+/// The non-empty paragraphs of the part, with their indexes.
+pub fn paragraphs(xml: &[u8], markup: &Markup) -> Result<Paragraphs, String> {
+  read(xml, markup).map(|(paragraphs, _)| paragraphs)
+}
+
+/// The non-empty paragraphs of the part as they read now, and those with
+/// tracked changes (maybe empty now) with their text before them.
+pub fn read(xml: &[u8], markup: &Markup) -> Result<(Paragraphs, Vec<Revised>), String> {
+  let (found, _, _) = walk(xml, markup, None)?;
+  let mut paragraphs = Vec::new();
+  let mut revised = Vec::new();
+  for f in found {
+    if let Some(original) = f.original {
+      revised.push(Revised {
+        index: f.index,
+        original,
+        current: f.text.clone(),
+      });
+    }
+    if !f.text.trim().is_empty() {
+      paragraphs.push((f.index, f.text));
+    }
+  }
+  Ok((paragraphs, revised))
+}
+
+/// The part with the paragraphs at the keys of `replacements` holding
+/// their values instead.
+pub fn replace(
+  xml: &[u8],
+  markup: &Markup,
+  replacements: &HashMap<usize, String>,
+) -> Result<Vec<u8>, String> {
+  let replacements = replacements
+    .iter()
+    .map(|(index, text)| (*index, Replacement::Text(text.clone())))
+    .collect();
+  rewrite_part(xml, markup, &replacements)
+}
+
+/// The part with the paragraphs at the keys of `replacements` rewritten
+/// as they say.
+pub fn rewrite_part(
+  xml: &[u8],
+  markup: &Markup,
+  replacements: &HashMap<usize, Replacement>,
+) -> Result<Vec<u8>, String> {
+  let tracked = replacements
+    .values()
+    .any(|r| matches!(r, Replacement::Revised { .. }));
+  let mut prepass = Prepass {
+    found: HashMap::new(),
+    next_id: 0,
+  };
+  if tracked {
+    let (found, next_id, _) = walk(xml, markup, None)?;
+    prepass = Prepass {
+      found: found.into_iter().map(|f| (f.index, f)).collect(),
+      next_id,
+    };
+  }
+  walk(xml, markup, Some((replacements, &mut prepass))).map(|(_, _, out)| out)
+}
+
+/// Where `target` (relative to `part`'s folder, or to the package root
+/// with a leading `/`) points, as a part name.
+pub fn resolve(part: &str, target: &str) -> String {
+  let mut path: Vec<&str> = match target.strip_prefix('/') {
+    Some(_) => Vec::new(),
+    None => part.split('/').collect(),
+  };
+  path.pop();
+  for segment in target.trim_start_matches('/').split('/') {
+    match segment {
+      ".." => {
+        path.pop();
+      }
+      "." | "" => {}
+      segment => path.push(segment),
+    }
+  }
+  path.join("/")
+}
+
+/// The parts `part` relates to, by relationship id, in the order listed.
+/// External targets (links) are left out.
+pub fn relationships(
+  archive: &mut ZipArchive<Cursor<&[u8]>>,
+  part: &str,
+) -> Result<Vec<(String, String)>, String> {
+  let (dir, name) = part.rsplit_once('/').unwrap_or(("", part));
+  let rels = format!("{dir}/_rels/{name}.rels");
+  if archive.index_for_name(&rels).is_none() {
+    return Ok(Vec::new());
+  }
+  let xml = read_part(archive, &rels)?;
+  let mut reader = Reader::from_reader(xml.as_slice());
+  let mut out = Vec::new();
+  loop {
+    match reader.read_event().map_err(xml_err)? {
+      Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+        let attribute = |key: &[u8]| {
+          e.try_get_attribute(key)
+            .ok()
+            .flatten()
+            .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+        };
+        if attribute(b"TargetMode").as_deref() == Some("External") {
+          continue;
+        }
+        if let (Some(id), Some(target)) = (attribute(b"Id"), attribute(b"Target")) {
+          out.push((id, resolve(part, &target)));
+        }
+      }
+      Event::Eof => return Ok(out),
+      _ => {}
+    }
+  }
+}
+
+pub fn open(data: &[u8]) -> Result<ZipArchive<Cursor<&[u8]>>, String> {
+  ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Not a valid document package: {e}"))
+}
+
+pub fn read_part(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>, String> {
+  let mut part = archive
+    .by_name(name)
+    .map_err(|e| format!("Cannot read {name}: {e}"))?;
+  let mut out = Vec::with_capacity(part.size() as usize);
+  part
+    .read_to_end(&mut out)
+    .map_err(|e| format!("Cannot read {name}: {e}"))?;
+  Ok(out)
+}
+
+/// Writes the package `source` to `dest` with the parts in `replaced`
+/// instead of its own; the others are copied as they are, still
+/// compressed and in their order.
+pub fn rewrite(
+  source: &[u8],
+  dest: &Path,
+  replaced: &HashMap<String, Vec<u8>>,
+) -> Result<(), String> {
+  let write_err = |e: &dyn std::fmt::Display| format!("Cannot write {}: {e}", dest.display());
+  let mut archive = open(source)?;
+  let file = File::create(dest).map_err(|e| write_err(&e))?;
+  let mut zip = ZipWriter::new(file);
+  for i in 0..archive.len() {
+    let entry = archive.by_index_raw(i).map_err(|e| write_err(&e))?;
+    match replaced.get(entry.name()) {
+      Some(data) => {
+        let name = entry.name().to_string();
+        drop(entry);
+        zip
+          .start_file(
+            name,
+            SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+          )
+          .map_err(|e| write_err(&e))?;
+        zip.write_all(data).map_err(|e| write_err(&e))?;
+      }
+      None => zip.raw_copy_file(entry).map_err(|e| write_err(&e))?,
+    }
+  }
+  zip.finish().map_err(|e| write_err(&e))?;
+  Ok(())
+}
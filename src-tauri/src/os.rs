@@ -0,0 +1,353 @@
+//! Small platform-specific process and file system helpers.
+
+#[cfg(windows)]
+use std::sync::OnceLock;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// Whether a process with this pid currently exists.
+#[cfg(unix)]
+pub fn pid_alive(pid: u32) -> bool {
+  // EPERM means it exists but belongs to someone else
+  unsafe {
+    libc::kill(pid as libc::pid_t, 0) == 0
+      || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+  }
+}
+
+/// Whether a process with this pid currently exists.
+#[cfg(windows)]
+pub fn pid_alive(pid: u32) -> bool {
+  use windows_sys::Win32::{
+    Foundation::{CloseHandle, STILL_ACTIVE},
+    System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+  };
+  unsafe {
+    let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+    if handle.is_null() {
+      return false;
+    }
+    let mut code = 0u32;
+    let ok = GetExitCodeProcess(handle, &mut code) != 0;
+    CloseHandle(handle);
+    ok && code == STILL_ACTIVE as u32
+  }
+}
+
+/// `roots` plus all their descendants, parents before children: the
+/// PyInstaller bootloader runs the actual Python interpreter as a child.
+pub fn process_tree(roots: &[u32]) -> Vec<u32> {
+  let mut tree = roots.to_vec();
+  if tree.is_empty() {
+    return tree;
+  }
+  let mut sys = System::new();
+  sys.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+  let mut i = 0;
+  while i < tree.len() {
+    let parent = Pid::from_u32(tree[i]);
+    for (pid, proc) in sys.processes() {
+      if proc.parent() == Some(parent) && !tree.contains(&pid.as_u32()) {
+        tree.push(pid.as_u32());
+      }
+    }
+    i += 1;
+  }
+  tree
+}
+
+/// Force-kills one process.
+#[cfg(unix)]
+pub fn kill_pid(pid: u32) -> std::io::Result<()> {
+  unsafe {
+    if libc::kill(pid as libc::pid_t, libc::SIGKILL) != 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+/// Force-kills one process.
+#[cfg(windows)]
+pub fn kill_pid(pid: u32) -> std::io::Result<()> {
+  use windows_sys::Win32::{
+    Foundation::CloseHandle,
+    System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE},
+  };
+  unsafe {
+    let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+    if handle.is_null() {
+      return Err(std::io::Error::last_os_error());
+    }
+    let ok = TerminateProcess(handle, 1);
+    CloseHandle(handle);
+    if ok == 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+/// Force-kills `pid` and everything below it, children first.
+pub fn kill_tree(pid: u32) {
+  for p in process_tree(&[pid]).into_iter().rev() {
+    let _ = kill_pid(p);
+  }
+}
+
+// HANDLE is a raw pointer; the job lives for the whole process.
+#[cfg(windows)]
+struct Job(isize);
+
+/// One job object for the app with KILL_ON_JOB_CLOSE: its handle is never
+/// closed by us, so Windows closes it (and kills every assigned process)
+/// when the shell exits for any reason, crashes included.
+#[cfg(windows)]
+fn kill_on_close_job() -> Option<isize> {
+  use windows_sys::Win32::System::JobObjects::{
+    CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+    JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+  };
+  static JOB: OnceLock<Option<Job>> = OnceLock::new();
+  JOB
+    .get_or_init(|| unsafe {
+      let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+      if job.is_null() {
+        return None;
+      }
+      let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+      info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+      let ok = SetInformationJobObject(
+        job,
+        JobObjectExtendedLimitInformation,
+        &info as *const _ as *const _,
+        std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+      );
+      (ok != 0).then_some(Job(job as isize))
+    })
+    .as_ref()
+    .map(|j| j.0)
+}
+
+/// Makes the OS reap `pid` (and its children) when the shell goes away.
+#[cfg(windows)]
+pub fn bind_to_app(pid: u32) -> std::io::Result<()> {
+  use windows_sys::Win32::{
+    Foundation::CloseHandle,
+    System::{
+      JobObjects::AssignProcessToJobObject,
+      Threading::{OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE},
+    },
+  };
+  let Some(job) = kill_on_close_job() else {
+    return Err(std::io::Error::last_os_error());
+  };
+  unsafe {
+    let handle = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+    if handle.is_null() {
+      return Err(std::io::Error::last_os_error());
+    }
+    let ok = AssignProcessToJobObject(job as _, handle);
+    CloseHandle(handle);
+    if ok == 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+/// Makes the OS reap `pid` when the shell goes away.
+///
+/// The shell plugin spawns without a `pre_exec` hook, so PR_SET_PDEATHSIG
+/// cannot be set from here; instead the backend gets `--parent-pid` and
+/// sets it on itself / watches the parent (see backend_server.py).
+#[cfg(unix)]
+pub fn bind_to_app(_pid: u32) -> std::io::Result<()> {
+  Ok(())
+}
+
+/// Gives `dir` (inherited by everything below it) a Low mandatory label,
+/// so a sandboxed backend that dropped to Low integrity can still write
+/// there and nowhere else.
+#[cfg(windows)]
+pub fn label_low_integrity(dir: &std::path::Path) -> std::io::Result<()> {
+  use std::os::windows::ffi::OsStrExt;
+  use windows_sys::Win32::{
+    Foundation::{LocalFree, ERROR_SUCCESS},
+    Security::{
+      Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SetNamedSecurityInfoW,
+        SDDL_REVISION_1, SE_FILE_OBJECT,
+      },
+      GetSecurityDescriptorSacl, ACL, LABEL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+    },
+  };
+  // mandatory label: Low, no write-up, inherited by files and folders
+  let sddl: Vec<u16> = "S:(ML;OICI;NW;;;LW)"
+    .encode_utf16()
+    .chain(Some(0))
+    .collect();
+  let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+  unsafe {
+    let mut sd: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+    if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+      sddl.as_ptr(),
+      SDDL_REVISION_1,
+      &mut sd,
+      std::ptr::null_mut(),
+    ) == 0
+    {
+      return Err(std::io::Error::last_os_error());
+    }
+    let (mut present, mut defaulted) = (0, 0);
+    let mut sacl: *mut ACL = std::ptr::null_mut();
+    let result = if GetSecurityDescriptorSacl(sd, &mut present, &mut sacl, &mut defaulted) == 0 {
+      Err(std::io::Error::last_os_error())
+    } else {
+      match SetNamedSecurityInfoW(
+        path.as_ptr(),
+        SE_FILE_OBJECT,
+        LABEL_SECURITY_INFORMATION,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        std::ptr::null(),
+        sacl,
+      ) {
+        ERROR_SUCCESS => Ok(()),
+        code => Err(std::io::Error::from_raw_os_error(code as i32)),
+      }
+    };
+    LocalFree(sd as _);
+    result
+  }
+}
+
+/// Unix has no integrity levels; the sandbox relies on file permissions
+/// and the backend's own restrictions there.
+#[cfg(unix)]
+pub fn label_low_integrity(_dir: &std::path::Path) -> std::io::Result<()> {
+  Ok(())
+}
+
+/// Lowers (or restores) CPU and I/O priority of one process.
+///
+/// Restoring needs privileges on Unix (nice can only go up), so that
+/// direction may fail with EPERM.
+#[cfg(unix)]
+pub fn set_background_priority(pid: u32, background: bool) -> std::io::Result<()> {
+  let nice = if background { 10 } else { 0 };
+  unsafe {
+    if libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) != 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  #[cfg(target_os = "linux")]
+  {
+    // ioprio_set(IOPRIO_WHO_PROCESS, pid, IDLE or BE/4)
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    let prio = if background {
+      3 << IOPRIO_CLASS_SHIFT
+    } else {
+      (2 << IOPRIO_CLASS_SHIFT) | 4
+    };
+    unsafe {
+      if libc::syscall(libc::SYS_ioprio_set, 1, pid as libc::c_int, prio) != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Lowers (or restores) the priority class of one process. Windows lowers
+/// I/O priority along with the below-normal class.
+#[cfg(windows)]
+pub fn set_background_priority(pid: u32, background: bool) -> std::io::Result<()> {
+  use windows_sys::Win32::{
+    Foundation::CloseHandle,
+    System::Threading::{
+      OpenProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+      PROCESS_SET_INFORMATION,
+    },
+  };
+  let class = if background {
+    BELOW_NORMAL_PRIORITY_CLASS
+  } else {
+    NORMAL_PRIORITY_CLASS
+  };
+  unsafe {
+    let handle = OpenProcess(PROCESS_SET_INFORMATION, 0, pid);
+    if handle.is_null() {
+      return Err(std::io::Error::last_os_error());
+    }
+    let ok = SetPriorityClass(handle, class);
+    CloseHandle(handle);
+    if ok == 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+/// Whether `dir` is on a network share, where changes made from other
+/// machines raise no file system notifications.
+#[cfg(target_os = "linux")]
+pub fn network_share(dir: &std::path::Path) -> bool {
+  use std::os::unix::ffi::OsStrExt;
+  // NFS, SMB, CIFS, SMB2, 9P, AFS and Ceph
+  const REMOTE: &[u32] = &[
+    0x6969, 0x517b, 0xff534d42, 0xfe534d42, 0x01021997, 0x5346414f, 0x00c36400,
+  ];
+  let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+    return false;
+  };
+  let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+  unsafe { libc::statfs(path.as_ptr(), &mut stat) == 0 && REMOTE.contains(&(stat.f_type as u32)) }
+}
+
+/// Whether `dir` is on a network share, where changes made from other
+/// machines raise no file system notifications.
+#[cfg(target_os = "macos")]
+pub fn network_share(dir: &std::path::Path) -> bool {
+  use std::os::unix::ffi::OsStrExt;
+  const REMOTE: &[&[u8]] = &[b"nfs", b"smbfs", b"afpfs", b"webdav", b"cifs"];
+  let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+    return false;
+  };
+  let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+    return false;
+  }
+  let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+  REMOTE.contains(&name.to_bytes())
+}
+
+/// Other Unix systems are taken to have dependable notifications.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+pub fn network_share(_dir: &std::path::Path) -> bool {
+  false
+}
+
+/// Whether `dir` is on a network share (a UNC path or a mapped drive),
+/// where changes made from other machines may raise no notifications.
+#[cfg(windows)]
+pub fn network_share(dir: &std::path::Path) -> bool {
+  use std::{os::windows::ffi::OsStrExt, path::Component, path::Prefix};
+  use windows_sys::Win32::{
+    Storage::FileSystem::GetDriveTypeW, System::WindowsProgramming::DRIVE_REMOTE,
+  };
+  let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+  match dir.components().next() {
+    Some(Component::Prefix(prefix)) => match prefix.kind() {
+      Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+      Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+        let root: Vec<u16> = std::ffi::OsStr::new(&format!("{}:\\", letter as char))
+          .encode_wide()
+          .chain([0])
+          .collect();
+        unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+      }
+      _ => false,
+    },
+    _ => false,
+  }
+}
@@ -0,0 +1,290 @@
+//! Proxy and TLS trust settings for traffic leaving the machine: the shell's own reqwest
+//! clients (updates, remote backends) and, via the usual `*_PROXY`
+//! variables, the backend's calls to translation providers.
+//!
+//! Proxy credentials live in the OS credential store, not in `startup.json`
+//! or `config.toml`; they are merged in whenever a client or the backend
+//! environment is built.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Mutex};
+use tauri::AppHandle;
+
+use crate::{certs, config, dns, keychain, paths};
+
+const MASK: &str = "********";
+/// Always reached directly, whatever the bypass list says.
+const LOOPBACK: &[&str] = &["localhost", "127.0.0.1", "::1"];
+/// Credential store entry holding [`ProxyCredentials`] as JSON.
+const SECRET: &str = "proxy-credentials";
+
+static CURRENT: Mutex<Option<ProxyConfig>> = Mutex::new(None);
+/// Read from the credential store once per session; `Some(None)` when
+/// none are stored.
+static CREDENTIALS: Mutex<Option<Option<ProxyCredentials>>> = Mutex::new(None);
+
+/// `proxy` in `startup.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+  /// `"system"` (OS / environment settings), `"none"` or `"manual"`.
+  pub mode: String,
+  /// Manual proxy: `http://host:port`, `https://...`, `socks5://...`, or
+  /// `socks5h://...` to resolve names on the proxy.
+  pub url: String,
+  /// Hosts, domains (`.corp.example`) or CIDRs that skip the proxy.
+  pub bypass: Vec<String>,
+  /// Basic auth for the manual proxy, filled in from the credential store
+  /// (see `set_proxy_credentials`) and never saved with the rest; values
+  /// found in a config file are moved to the store at startup. NTLM/Kerberos
+  /// proxies are not supported by reqwest or httpx: point `url` at a local
+  /// relay such as Px or Cntlm instead.
+  #[serde(skip_serializing_if = "String::is_empty")]
+  pub username: String,
+  #[serde(skip_serializing_if = "String::is_empty")]
+  pub password: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProxyCredentials {
+  pub username: String,
+  pub password: String,
+}
+
+impl Default for ProxyConfig {
+  fn default() -> Self {
+    Self {
+      mode: "system".to_string(),
+      url: String::new(),
+      bypass: Vec::new(),
+      username: String::new(),
+      password: String::new(),
+    }
+  }
+}
+
+impl ProxyConfig {
+  /// Copy with the password masked, for anything the webview or a
+  /// diagnostics bundle gets to see.
+  pub fn redacted(&self) -> Self {
+    let mut cfg = self.clone();
+    if !cfg.password.is_empty() {
+      cfg.password = MASK.to_string();
+    }
+    cfg
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    match self.mode.as_str() {
+      "system" | "none" => Ok(()),
+      "manual" => {
+        let url =
+          reqwest::Url::parse(self.url.trim()).map_err(|e| format!("Invalid proxy URL: {e}"))?;
+        if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+          return Err(format!(
+            "Unsupported proxy scheme {}: use http, https, socks5 or socks5h",
+            url.scheme()
+          ));
+        }
+        if url.host_str().is_none() {
+          return Err("Proxy URL needs a host".to_string());
+        }
+        Ok(())
+      }
+      other => Err(format!(
+        "Unknown proxy mode {other}: use system, none or manual"
+      )),
+    }
+  }
+
+  fn no_proxy(&self) -> String {
+    self
+      .bypass
+      .iter()
+      .map(|h| h.trim())
+      .filter(|h| !h.is_empty())
+      .chain(LOOPBACK.iter().copied())
+      .collect::<Vec<_>>()
+      .join(",")
+  }
+
+  /// Proxy URL with the credentials embedded, as `*_PROXY` expects.
+  fn url_with_credentials(&self) -> String {
+    let Ok(mut url) = reqwest::Url::parse(self.url.trim()) else {
+      return self.url.trim().to_string();
+    };
+    if !self.username.is_empty() {
+      let _ = url.set_username(&self.username);
+      let _ = url.set_password(Some(&self.password));
+    }
+    url.to_string()
+  }
+}
+
+fn credentials() -> Option<ProxyCredentials> {
+  let mut cached = CREDENTIALS.lock().unwrap();
+  cached
+    .get_or_insert_with(|| {
+      keychain::app_secret(SECRET)
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+    })
+    .clone()
+}
+
+fn store_credentials(creds: Option<ProxyCredentials>) -> Result<(), String> {
+  match &creds {
+    Some(c) => {
+      let json = serde_json::to_string(c).map_err(|e| e.to_string())?;
+      keychain::set_app_secret(SECRET, &json)?;
+    }
+    None => keychain::delete_app_secret(SECRET)?,
+  }
+  *CREDENTIALS.lock().unwrap() = Some(creds);
+  Ok(())
+}
+
+/// Moves credentials that came with the startup config into the credential
+/// store and drops them from `startup.json`. Returns a warning when that
+/// happened (they may still sit in `config.toml`, which the app never
+/// writes) or failed.
+pub fn adopt_credentials(data_dir: &std::path::Path, cfg: &ProxyConfig) -> Option<String> {
+  if cfg.username.is_empty() && cfg.password.is_empty() {
+    return None;
+  }
+  let creds = ProxyCredentials {
+    username: cfg.username.clone(),
+    password: cfg.password.clone(),
+  };
+  if let Err(e) = store_credentials(Some(creds)) {
+    return Some(format!(
+      "cannot move proxy credentials to the OS store: {e}"
+    ));
+  }
+  let mut stripped = cfg.clone();
+  stripped.username.clear();
+  stripped.password.clear();
+  if let Ok(value) = serde_json::to_value(&stripped) {
+    let _ = config::persist_key(data_dir, "proxy", value);
+  }
+  Some(
+    "moved proxy credentials to the OS credential store; remove any left in config.toml"
+      .to_string(),
+  )
+}
+
+/// Makes `cfg` the active proxy setting; called at startup and on changes.
+/// Credentials come from the store, not from `cfg`.
+pub fn init(mut cfg: ProxyConfig) {
+  cfg.username.clear();
+  cfg.password.clear();
+  *CURRENT.lock().unwrap() = Some(cfg);
+}
+
+/// The active setting with the stored credentials merged in.
+fn current() -> ProxyConfig {
+  let mut cfg = CURRENT.lock().unwrap().clone().unwrap_or_default();
+  if let Some(creds) = credentials() {
+    cfg.username = creds.username;
+    cfg.password = creds.password;
+  }
+  cfg
+}
+
+/// A reqwest builder with the proxy setting, extra root CAs and DNS
+/// overrides applied.
+/// In `system` mode reqwest reads the environment and, on Windows and
+/// macOS, the OS settings itself.
+pub fn builder() -> reqwest::Result<reqwest::blocking::ClientBuilder> {
+  let cfg = current();
+  let builder = certs::roots()
+    .into_iter()
+    .fold(reqwest::blocking::Client::builder(), |b, cert| {
+      b.add_root_certificate(cert)
+    });
+  let builder = dns::apply(builder);
+  Ok(match cfg.mode.as_str() {
+    "none" => builder.no_proxy(),
+    "manual" => {
+      let mut proxy = reqwest::Proxy::all(cfg.url.trim())?
+        .no_proxy(reqwest::NoProxy::from_string(&cfg.no_proxy()));
+      if !cfg.username.is_empty() {
+        proxy = proxy.basic_auth(&cfg.username, &cfg.password);
+      }
+      builder.proxy(proxy)
+    }
+    _ => builder,
+  })
+}
+
+pub fn client() -> reqwest::Result<reqwest::blocking::Client> {
+  builder()?.build()
+}
+
+/// Environment that makes the backend (httpx, requests, urllib) use the
+/// same proxy. `system` mode sets nothing: Python reads the environment
+/// and the OS settings on its own. With `reveal` false the password is
+/// masked, for display.
+pub fn backend_env(reveal: bool) -> BTreeMap<String, String> {
+  let cfg = current();
+  let mut env = BTreeMap::new();
+  match cfg.mode.as_str() {
+    "none" => {
+      env.insert("NO_PROXY".to_string(), "*".to_string());
+    }
+    "manual" => {
+      let shown = if reveal { cfg.clone() } else { cfg.redacted() };
+      let url = shown.url_with_credentials();
+      for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+        env.insert(name.to_string(), url.clone());
+      }
+      env.insert("NO_PROXY".to_string(), cfg.no_proxy());
+    }
+    _ => {}
+  }
+  env
+}
+
+#[tauri::command]
+pub fn get_proxy_config() -> ProxyConfig {
+  current().redacted()
+}
+
+/// Validates, saves and activates proxy settings. The shell's clients use
+/// them at once; backends pick them up on their next (re)start. `username`
+/// and `password` are ignored here, see [`set_proxy_credentials`].
+#[tauri::command]
+pub fn set_proxy_config(app: AppHandle, mut config: ProxyConfig) -> Result<ProxyConfig, String> {
+  config.mode = config.mode.trim().to_ascii_lowercase();
+  config.url = config.url.trim().to_string();
+  config.username.clear();
+  config.password.clear();
+  config.validate()?;
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "proxy", value)
+    .map_err(|e| format!("Cannot save proxy settings: {e}"))?;
+  init(config);
+  Ok(current().redacted())
+}
+
+/// Stores the manual proxy's credentials in the OS credential store; an
+/// empty `username` removes them, the masked password keeps the stored
+/// one. Applies like [`set_proxy_config`].
+#[tauri::command]
+pub fn set_proxy_credentials(username: String, password: String) -> Result<ProxyConfig, String> {
+  let username = username.trim().to_string();
+  let creds = if username.is_empty() {
+    None
+  } else {
+    let password = if password == MASK {
+      credentials().map(|c| c.password).unwrap_or_default()
+    } else {
+      password
+    };
+    Some(ProxyCredentials { username, password })
+  };
+  store_credentials(creds).map_err(|e| format!("Cannot store the proxy credentials: {e}"))?;
+  Ok(current().redacted())
+}
@@ -0,0 +1,283 @@
+//! Where translated copies go when no path is given: the output policy in
+//! the settings. `output_template` names the file, e.g. the default
+//! `{stem}.{target_lang}.{ext}` turns `report.docx` translated to Chinese
+//! into `report.zh.docx`; it may name subfolders with `/`. The file goes
+//! next to the document it was translated from, or into `output_dir` when
+//! one is set. `output_conflict` says what happens when it already exists:
+//! `overwrite` it, `skip` the export, or `rename` to `report.zh (2).docx`.
+//!
+//! The shell remembers which document each task was created from, in
+//! `<data dir>/task-sources.json`; tasks it does not know (created from the
+//! browser) go to `output_dir`, or the folder last saved to.
+
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Component, Path, PathBuf},
+  sync::{Mutex, OnceLock},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{formats, proxy::ProxyError, segments, settings::SettingsState};
+
+pub const PLACEHOLDERS: [&str; 5] = ["stem", "ext", "source_lang", "target_lang", "direction"];
+/// Tried before a conflict is reported instead of renamed around.
+const MAX_RENAMES: u32 = 999;
+
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+/// Task id to the path of the document it was created from.
+static SOURCES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+#[derive(Serialize)]
+pub struct OutputPath {
+  /// `translated`, `xliff`, `bilingual` or `review`, as the export
+  /// buttons.
+  pub kind: &'static str,
+  pub path: String,
+  /// `write`, `overwrite`, `skip` (the file exists and is left alone) or
+  /// `rename` (`path` is the free name found).
+  pub action: &'static str,
+}
+
+/// Loads the task sources; called once at startup.
+pub fn init(data_dir: &Path) {
+  let file = data_dir.join("task-sources.json");
+  let saved = fs::read(&file)
+    .ok()
+    .and_then(|data| serde_json::from_slice(&data).ok())
+    .unwrap_or_default();
+  *SOURCES.lock().unwrap_or_else(|e| e.into_inner()) = Some(saved);
+  let _ = FILE.set(file);
+}
+
+/// Records that task `job_id` was created from the document at `source`.
+pub fn remember(job_id: &str, source: &Path) {
+  let mut sources = SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+  let map = sources.get_or_insert_with(HashMap::new);
+  map.insert(job_id.to_string(), source.display().to_string());
+  if let Some(file) = FILE.get() {
+    let _ = fs::write(file, serde_json::to_vec_pretty(&*map).unwrap_or_default());
+  }
+}
+
+fn source_of(job_id: &str) -> Option<PathBuf> {
+  let sources = SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+  sources.as_ref()?.get(job_id).map(PathBuf::from)
+}
+
+/// Checks an `output_template`: known placeholders, and a relative path
+/// that stays in the folder it is written to.
+pub fn validate_template(template: &str) -> Result<(), String> {
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    let end = rest[start..]
+      .find('}')
+      .ok_or_else(|| format!("Unclosed {{ in output template {template}"))?;
+    let name = &rest[start + 1..start + end];
+    if !PLACEHOLDERS.contains(&name) {
+      return Err(format!(
+        "Unknown placeholder {{{name}}} in output template: use {}",
+        PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+      ));
+    }
+    rest = &rest[start + end + 1..];
+  }
+  let path = Path::new(template);
+  if template.trim().is_empty()
+    || template.ends_with(['/', '\\'])
+    || !path.components().all(|c| matches!(c, Component::Normal(_)))
+  {
+    return Err(format!(
+      "Output template {template} must be a file name or a path inside the output folder"
+    ));
+  }
+  Ok(())
+}
+
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+  values
+    .iter()
+    .fold(template.to_string(), |name, (key, value)| {
+      name.replace(&format!("{{{key}}}"), value)
+    })
+}
+
+/// `path` with ` (n)` after its stem for the first n that is free.
+pub fn free(path: &Path) -> Option<PathBuf> {
+  let stem = path.file_stem()?.to_string_lossy();
+  let ext = path
+    .extension()
+    .map(|e| format!(".{}", e.to_string_lossy()))
+    .unwrap_or_default();
+  (2..=MAX_RENAMES)
+    .map(|n| path.with_file_name(format!("{stem} ({n}){ext}")))
+    .find(|p| !p.exists())
+}
+
+/// The extension task `job_id`'s document is read as.
+fn document(app: &AppHandle, job_id: &str) -> Result<String, ProxyError> {
+  Ok(match source_of(job_id) {
+    Some(source) => formats::route(&source),
+    None => formats::extension(Path::new(&segments::info(app, job_id)?.filename)),
+  })
+}
+
+/// Where export `kind` of task `job_id` goes under the output policy, in
+/// a file with extension `ext`.
+pub fn plan(
+  app: &AppHandle,
+  job_id: &str,
+  kind: &'static str,
+  ext: &str,
+) -> Result<OutputPath, ProxyError> {
+  plan_in(app, job_id, kind, ext, None)
+}
+
+/// [`plan`], with the file named by the template going into `dir` when
+/// one is given instead of the policy's folder.
+fn plan_in(
+  app: &AppHandle,
+  job_id: &str,
+  kind: &'static str,
+  ext: &str,
+  dir: Option<&Path>,
+) -> Result<OutputPath, ProxyError> {
+  let settings = app.state::<SettingsState>().get();
+  let info = segments::info(app, job_id)?;
+  let source = source_of(job_id);
+  let dir = dir
+    .map(Path::to_path_buf)
+    .or_else(|| settings.output_dir.map(PathBuf::from))
+    .or_else(|| {
+      source
+        .as_ref()
+        .and_then(|s| s.parent())
+        .map(Path::to_path_buf)
+    })
+    .or_else(|| settings.last_output_dir.map(PathBuf::from))
+    .ok_or_else(|| {
+      ProxyError::new(
+        "invalid-request",
+        "Choose an output folder: the document this task was created from is not known",
+      )
+    })?;
+  let named = source
+    .as_deref()
+    .and_then(Path::file_stem)
+    .map(|s| s.to_string_lossy().into_owned())
+    .unwrap_or_else(|| {
+      let filename = Path::new(&info.filename);
+      filename
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+    });
+  let (source_lang, target_lang) = info.direction.split_once("->").unwrap_or(("", ""));
+  let relative = fill(
+    &settings.output_template,
+    &[
+      ("stem", &named),
+      ("ext", ext),
+      ("source_lang", source_lang),
+      ("target_lang", target_lang),
+      ("direction", &info.direction.replace("->", "-")),
+    ],
+  );
+  let path = dir.join(relative);
+  let (path, action) = if !path.exists() {
+    (path, "write")
+  } else {
+    match settings.output_conflict.as_str() {
+      "overwrite" => (path, "overwrite"),
+      "skip" => (path, "skip"),
+      _ => {
+        let renamed = free(&path).ok_or_else(|| {
+          ProxyError::new(
+            "invalid-request",
+            format!("{} and {MAX_RENAMES} renamed copies exist", path.display()),
+          )
+        })?;
+        (renamed, "rename")
+      }
+    }
+  };
+  Ok(OutputPath {
+    kind,
+    path: path.display().to_string(),
+    action,
+  })
+}
+
+/// Where the exports of task `job_id` would go under the output policy:
+/// its translated copy (when the shell can write its format), XLIFF,
+/// bilingual table and bilingual Word document.
+#[tauri::command]
+pub async fn preview_output_paths(
+  app: AppHandle,
+  job_id: String,
+) -> Result<Vec<OutputPath>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let document = document(&app, &job_id)?;
+    let mut out = Vec::new();
+    if formats::format(&document).is_some() {
+      out.push(plan(&app, &job_id, "translated", &document)?);
+    }
+    out.push(plan(&app, &job_id, "xliff", "xlf")?);
+    out.push(plan(&app, &job_id, "bilingual", "bilingual.csv")?);
+    out.push(plan(&app, &job_id, "review", "review.docx")?);
+    Ok(out)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+#[derive(Serialize)]
+pub struct Exported {
+  #[serde(flatten)]
+  pub output: OutputPath,
+  /// Segments written with a warning, see the format's own export.
+  pub warnings: usize,
+}
+
+/// Writes the translated copy of task `job_id` where the output policy
+/// says; nothing is written when it says `skip`.
+pub fn export(app: &AppHandle, job_id: &str) -> Result<Exported, ProxyError> {
+  export_in(app, job_id, None)
+}
+
+/// [`export`] into `dir` instead of the policy's folder, when given.
+pub fn export_in(
+  app: &AppHandle,
+  job_id: &str,
+  dir: Option<&Path>,
+) -> Result<Exported, ProxyError> {
+  let output = plan_in(app, job_id, "translated", &document(app, job_id)?, dir)?;
+  if output.action == "skip" {
+    return Ok(Exported {
+      output,
+      warnings: 0,
+    });
+  }
+  let path = Path::new(&output.path);
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir).map_err(|e| {
+      ProxyError::new(
+        "invalid-request",
+        format!("Cannot create {}: {e}", dir.display()),
+      )
+    })?;
+  }
+  let warnings = segments::export(app, job_id, path)?;
+  Ok(Exported { output, warnings })
+}
+
+/// Writes the translated copy of task `job_id` under the output policy,
+/// for when no path is given.
+#[tauri::command]
+pub async fn export_translated(app: AppHandle, job_id: String) -> Result<Exported, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,48 @@
+//! Where the app keeps its data. Normally the OS app-data dir; in portable
+//! mode (a [`FLAG_FILE`] next to the executable, or [`FLAG`] on the command
+//! line) a `data` folder beside the executable instead, so the app runs
+//! from a USB stick without writing to the host's profile. Settings, port
+//! files, logs, caches and the backend's database all follow, as they live
+//! under this dir. API keys still go to the host's OS credential store.
+
+use std::{
+  env,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
+use tauri::{Manager, Runtime};
+
+pub const FLAG: &str = "--portable";
+pub const FLAG_FILE: &str = "portable.flag";
+
+fn portable_dir() -> Option<&'static Path> {
+  static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+  DIR
+    .get_or_init(|| {
+      let exe_dir = env::current_exe().ok()?.parent()?.to_path_buf();
+      let portable = env::args().any(|a| a == FLAG) || exe_dir.join(FLAG_FILE).is_file();
+      portable.then(|| exe_dir.join("data"))
+    })
+    .as_deref()
+}
+
+pub fn is_portable() -> bool {
+  portable_dir().is_some()
+}
+
+/// The app's data dir, see the module docs.
+pub fn data_dir<R: Runtime, M: Manager<R>>(app: &M) -> tauri::Result<PathBuf> {
+  match portable_dir() {
+    Some(dir) => Ok(dir.to_path_buf()),
+    None => app.path().app_data_dir(),
+  }
+}
+
+/// Keeps the webview's own profile (cookies, local storage, caches) in the
+/// portable data dir too; must run before the first window opens.
+pub fn redirect_webview_data() {
+  #[cfg(windows)]
+  if let Some(dir) = portable_dir() {
+    env::set_var("WEBVIEW2_USER_DATA_FOLDER", dir.join("webview"));
+  }
+}
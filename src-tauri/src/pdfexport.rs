@@ -0,0 +1,686 @@
+//! `export_translated_pdf`: writes a PDF task's translations back into the
+//! original PDF. The source's text is removed and each block's translation
+//! drawn in the block's box, wrapped and shrunk to fit, so images, vector
+//! graphics, annotations and the page layout stay as they were. On scanned
+//! pages, whose text was read by OCR and is part of the image, each box is
+//! painted white first.
+//!
+//! The source's own fonts are subsets without the target language's glyphs,
+//! so the text is set in TrueType fonts from the system (`pdf_font` first,
+//! then common CJK fonts), each character in the first one that has it.
+//! Fonts are embedded whole, which adds their size to the output.
+
+use pdf_extract::{
+  content::{Content, Operation},
+  Dictionary, Document, Object, ObjectId, Stream, StringFormat,
+};
+use serde::Serialize;
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fmt::Write as _,
+  fs,
+  path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Emitter, Manager};
+use ttf_parser::{Face, GlyphId};
+
+use crate::{
+  config::StartupConfig,
+  pdftext::{is_cjk, Locator},
+  proxy::{invalid, ProxyError},
+  segments,
+};
+
+const LINE_HEIGHT: f64 = 1.2;
+/// Translations that do not fit their box are shrunk down to this share of
+/// the source's size, and overflow below it after that.
+const MIN_SCALE: f64 = 0.6;
+/// Tried in order after `pdf_font`.
+#[cfg(target_os = "windows")]
+const SYSTEM_FONTS: &[&str] = &[
+  r"C:\Windows\Fonts\msyh.ttc",
+  r"C:\Windows\Fonts\Deng.ttf",
+  r"C:\Windows\Fonts\simhei.ttf",
+  r"C:\Windows\Fonts\simsun.ttc",
+  r"C:\Windows\Fonts\arial.ttf",
+];
+#[cfg(target_os = "macos")]
+const SYSTEM_FONTS: &[&str] = &[
+  "/Library/Fonts/Arial Unicode.ttf",
+  "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+  "/System/Library/Fonts/Supplemental/Songti.ttc",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const SYSTEM_FONTS: &[&str] = &[
+  "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+  "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+  "/usr/share/fonts/truetype/wqy/wqy-zenhei.ttc",
+  "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+  "/usr/share/fonts/google-droid/DroidSansFallbackFull.ttf",
+  "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+];
+
+/// Payload of `pdf-export-progress`; `done` of `total` pages written.
+#[derive(Clone, Serialize)]
+struct Progress<'a> {
+  job_id: &'a str,
+  done: usize,
+  total: usize,
+}
+
+struct Block {
+  at: Locator,
+  text: String,
+}
+
+/// A font in use, with the glyphs drawn in it.
+struct PdfFont<'a> {
+  /// Name in the pages' font resources.
+  resource: String,
+  base_name: String,
+  data: &'a [u8],
+  face: Face<'a>,
+  /// Glyph id to the character it shows.
+  used: BTreeMap<u16, char>,
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+  Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+  Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+/// The first font of a collection (`.ttc`) as a font file of its own, as
+/// PDF embeds single fonts only; other files as they are.
+fn standalone(data: Vec<u8>) -> Option<Vec<u8>> {
+  if !data.starts_with(b"ttcf") {
+    return Some(data);
+  }
+  let start = read_u32(&data, 12)? as usize;
+  let tables = read_u16(&data, start + 4)? as usize;
+  let records = data.get(start + 12..start + 12 + tables * 16)?;
+  let mut out = data.get(start..start + 12)?.to_vec();
+  out.extend_from_slice(records);
+  for i in 0..tables {
+    let record = start + 12 + i * 16;
+    let (offset, length) = (
+      read_u32(&data, record + 8)? as usize,
+      read_u32(&data, record + 12)? as usize,
+    );
+    let new_offset = out.len() as u32;
+    out[12 + i * 16 + 8..12 + i * 16 + 12].copy_from_slice(&new_offset.to_be_bytes());
+    out.extend_from_slice(data.get(offset..offset + length)?);
+    while out.len() % 4 != 0 {
+      out.push(0);
+    }
+  }
+  Some(out)
+}
+
+/// The readable TrueType-outline fonts among `pdf_font` and
+/// [`SYSTEM_FONTS`]. CFF-based OpenType fonts are skipped: their glyph ids
+/// are not CIDs.
+pub fn load_fonts(app: &AppHandle) -> Vec<(PathBuf, Vec<u8>)> {
+  let configured = app.state::<StartupConfig>().pdf_font.clone();
+  configured
+    .into_iter()
+    .chain(SYSTEM_FONTS.iter().map(PathBuf::from))
+    .filter_map(|path| {
+      let data = standalone(fs::read(&path).ok()?)?;
+      let face = Face::parse(&data, 0).ok()?;
+      face.tables().glyf?;
+      Some((path, data))
+    })
+    .collect()
+}
+
+fn base_name(face: &Face, path: &Path, index: usize) -> String {
+  let name = face
+    .names()
+    .into_iter()
+    .find(|n| n.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+    .and_then(|n| n.to_string())
+    .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+    .unwrap_or_default();
+  let name: String = name
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+    .collect();
+  if name.is_empty() {
+    format!("AIDTFont{index}")
+  } else {
+    name
+  }
+}
+
+/// The font that draws `c` and its glyph there.
+fn glyph(fonts: &[PdfFont], c: char) -> Option<(usize, GlyphId)> {
+  fonts
+    .iter()
+    .enumerate()
+    .find_map(|(i, f)| f.face.glyph_index(c).filter(|g| g.0 != 0).map(|g| (i, g)))
+}
+
+/// Width of `text` at `size` points; characters no font has take none.
+fn width(fonts: &[PdfFont], text: &str, size: f64) -> f64 {
+  text
+    .chars()
+    .filter_map(|c| glyph(fonts, c))
+    .map(|(i, g)| {
+      let face = &fonts[i].face;
+      face.glyph_hor_advance(g).unwrap_or(0) as f64 / face.units_per_em() as f64 * size
+    })
+    .sum()
+}
+
+/// Words, and CJK characters one at a time, as line breaks may fall
+/// between any two of those. Each comes with whether a space precedes it.
+fn tokens(text: &str) -> Vec<(bool, String)> {
+  let mut out = Vec::new();
+  let mut word = String::new();
+  let mut space = false;
+  for c in text.chars() {
+    if c.is_whitespace() || is_cjk(c) {
+      if !word.is_empty() {
+        out.push((space, std::mem::take(&mut word)));
+        space = false;
+      }
+      if c.is_whitespace() {
+        space = !out.is_empty();
+      } else {
+        out.push((space, c.to_string()));
+        space = false;
+      }
+    } else {
+      word.push(c);
+    }
+  }
+  if !word.is_empty() {
+    out.push((space, word));
+  }
+  out
+}
+
+fn wrap(fonts: &[PdfFont], text: &str, size: f64, max_width: f64) -> Vec<String> {
+  let mut lines = Vec::new();
+  let mut line = String::new();
+  for (space, token) in tokens(text) {
+    let candidate = if line.is_empty() {
+      token.clone()
+    } else if space {
+      format!("{line} {token}")
+    } else {
+      format!("{line}{token}")
+    };
+    if !line.is_empty() && width(fonts, &candidate, size) > max_width {
+      lines.push(std::mem::replace(&mut line, token));
+    } else {
+      line = candidate;
+    }
+  }
+  if !line.is_empty() {
+    lines.push(line);
+  }
+  lines
+}
+
+/// Lines of `block` and the size that fits them into its box.
+fn fit(fonts: &[PdfFont], block: &Block) -> (Vec<String>, f64) {
+  let at = &block.at;
+  // a little slack, as the source's lines rarely fill the box exactly
+  let max_width = (at.right - at.left).max(at.font_size) * 1.02;
+  let max_height = at.bottom - at.top;
+  let mut size = at.font_size;
+  loop {
+    let lines = wrap(fonts, &block.text, size, max_width);
+    let height = size * (1.0 + LINE_HEIGHT * lines.len().saturating_sub(1) as f64);
+    if height <= max_height * 1.05 || size * 0.9 < at.font_size * MIN_SCALE {
+      return (lines, size);
+    }
+    size *= 0.9;
+  }
+}
+
+fn hex(glyphs: &[u16]) -> String {
+  glyphs.iter().fold(String::from("<"), |mut s, g| {
+    let _ = write!(s, "{g:04X}");
+    s
+  }) + ">"
+}
+
+/// Content-stream operators drawing `blocks` on a page `height` points
+/// tall; records the glyphs used in `fonts`.
+fn draw(fonts: &mut [PdfFont], blocks: &[Block], height: f64, cover: bool) -> String {
+  let mut ops = String::from("q 0 g\n");
+  for block in blocks {
+    if cover {
+      let at = &block.at;
+      let _ = writeln!(
+        ops,
+        "1 g {:.2} {:.2} {:.2} {:.2} re f 0 g",
+        at.left,
+        height - at.bottom,
+        at.right - at.left,
+        at.bottom - at.top
+      );
+    }
+    let (lines, size) = fit(fonts, block);
+    for (n, line) in lines.iter().enumerate() {
+      let baseline = block.at.top + size * 0.8 + n as f64 * size * LINE_HEIGHT;
+      let _ = writeln!(ops, "BT {:.2} {:.2} Td", block.at.left, height - baseline);
+      let mut current: Option<(usize, Vec<u16>)> = None;
+      let flush = |ops: &mut String, run: Option<(usize, Vec<u16>)>, fonts: &[PdfFont]| {
+        if let Some((font, glyphs)) = run {
+          let _ = writeln!(
+            ops,
+            "/{} {size:.2} Tf {} Tj",
+            fonts[font].resource,
+            hex(&glyphs)
+          );
+        }
+      };
+      for c in line.chars() {
+        let Some((font, g)) = glyph(fonts, c) else {
+          continue;
+        };
+        fonts[font].used.insert(g.0, c);
+        match &mut current {
+          Some((f, glyphs)) if *f == font => glyphs.push(g.0),
+          _ => {
+            let run = current.replace((font, vec![g.0]));
+            flush(&mut ops, run, fonts);
+          }
+        }
+      }
+      flush(&mut ops, current.take(), fonts);
+      ops.push_str("ET\n");
+    }
+  }
+  ops.push_str("Q\n");
+  ops
+}
+
+/// Removes the text-showing operators from a content stream, keeping
+/// everything else, and tells whether there were any. The original content
+/// is wrapped in `q`/`Q`, so its graphics state does not leak into the
+/// appended text.
+fn strip_text(content: &[u8]) -> Result<(Vec<u8>, bool), String> {
+  let content = Content::decode(content).map_err(|e| format!("Cannot read a page: {e}"))?;
+  let count = content.operations.len();
+  let mut operations = vec![Operation::new("q", Vec::new())];
+  operations.extend(
+    content
+      .operations
+      .into_iter()
+      .filter(|op| !matches!(op.operator.as_str(), "Tj" | "TJ" | "'" | "\"")),
+  );
+  let had_text = operations.len() - 1 < count;
+  operations.push(Operation::new("Q", Vec::new()));
+  let stripped = Content { operations }
+    .encode()
+    .map_err(|e| format!("Cannot write a page: {e}"))?;
+  Ok((stripped, had_text))
+}
+
+/// Form XObjects used by a page, directly or from other forms.
+fn forms(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+  let mut seen = HashSet::new();
+  let mut pending: Vec<&Dictionary> = match doc.get_page_resources(page_id) {
+    Ok((inline, ids)) => inline
+      .into_iter()
+      .chain(ids.iter().filter_map(|id| doc.get_dictionary(*id).ok()))
+      .collect(),
+    Err(_) => Vec::new(),
+  };
+  let mut out = Vec::new();
+  while let Some(resources) = pending.pop() {
+    let Ok(xobjects) = resources
+      .get(b"XObject")
+      .and_then(|o| doc.dereference(o))
+      .and_then(|(_, o)| o.as_dict())
+    else {
+      continue;
+    };
+    for (_, xobject) in xobjects.iter() {
+      let Ok(id) = xobject.as_reference() else {
+        continue;
+      };
+      let Ok(Object::Stream(stream)) = doc.get_object(id) else {
+        continue;
+      };
+      if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form".as_slice())
+        || !seen.insert(id)
+      {
+        continue;
+      }
+      out.push(id);
+      if let Ok((_, Object::Dictionary(resources))) = stream
+        .dict
+        .get(b"Resources")
+        .and_then(|o| doc.dereference(o))
+      {
+        pending.push(resources);
+      }
+    }
+  }
+  out
+}
+
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+  let mut node = doc.get_dictionary(page_id).ok()?;
+  loop {
+    if let Ok(value) = node.get(key) {
+      return Some(value);
+    }
+    node = doc
+      .get_dictionary(node.get(b"Parent").ok()?.as_reference().ok()?)
+      .ok()?;
+  }
+}
+
+fn page_height(doc: &Document, page_id: ObjectId) -> f64 {
+  inherited(doc, page_id, b"MediaBox")
+    .and_then(|o| doc.dereference(o).ok())
+    .and_then(|(_, o)| o.as_array().ok())
+    .and_then(|a| {
+      let n: Vec<f64> = a
+        .iter()
+        .filter_map(|v| v.as_float().ok())
+        .map(f64::from)
+        .collect();
+      (n.len() == 4).then(|| n[3] - n[1])
+    })
+    .unwrap_or(792.0)
+}
+
+/// Adds the fonts to the page's resources. Resources the page inherits are
+/// copied onto it first, so adding to them does not hide the rest.
+fn add_fonts(
+  doc: &mut Document,
+  page_id: ObjectId,
+  fonts: &[(String, ObjectId)],
+) -> Result<(), String> {
+  let err = |e: pdf_extract::Error| format!("Cannot update a page: {e}");
+  let own = doc.get_dictionary(page_id).map_err(err)?.has(b"Resources");
+  if !own {
+    if let Some(resources) = inherited(doc, page_id, b"Resources").cloned() {
+      doc
+        .get_dictionary_mut(page_id)
+        .map_err(err)?
+        .set("Resources", resources);
+    }
+  }
+  let font_ref = match doc.get_or_create_resources(page_id).map_err(err)? {
+    Object::Dictionary(resources) => match resources.get(b"Font") {
+      Ok(Object::Reference(id)) => Some(*id),
+      Ok(_) => None,
+      Err(_) => {
+        resources.set("Font", Dictionary::new());
+        None
+      }
+    },
+    _ => return Err("A page has invalid resources".to_string()),
+  };
+  let font_dict = match font_ref {
+    Some(id) => doc.get_dictionary_mut(id).map_err(err)?,
+    None => match doc.get_or_create_resources(page_id).map_err(err)? {
+      Object::Dictionary(resources) => resources
+        .get_mut(b"Font")
+        .and_then(Object::as_dict_mut)
+        .map_err(err)?,
+      _ => return Err("A page has invalid resources".to_string()),
+    },
+  };
+  for (name, id) in fonts {
+    font_dict.set(name.as_bytes().to_vec(), Object::Reference(*id));
+  }
+  Ok(())
+}
+
+fn to_unicode(used: &BTreeMap<u16, char>) -> Vec<u8> {
+  let mut cmap = String::from(
+    "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+     /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+     /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+     1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+  );
+  let used: Vec<_> = used.iter().collect();
+  for chunk in used.chunks(100) {
+    let _ = writeln!(cmap, "{} beginbfchar", chunk.len());
+    for (g, c) in chunk {
+      let unicode: String = c
+        .encode_utf16(&mut [0; 2])
+        .iter()
+        .map(|u| format!("{u:04X}"))
+        .collect();
+      let _ = writeln!(cmap, "<{g:04X}> <{unicode}>");
+    }
+    cmap.push_str("endbfchar\n");
+  }
+  cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+  cmap.into_bytes()
+}
+
+/// Embeds `font` as a Type0 font with Identity-H encoding, so content
+/// streams address glyphs by id.
+fn embed(doc: &mut Document, font: &PdfFont) -> ObjectId {
+  let face = &font.face;
+  let scale = 1000.0 / face.units_per_em() as f64;
+  let units = |v: i16| Object::Integer((v as f64 * scale).round() as i64);
+  let bbox = face.global_bounding_box();
+
+  let mut file = Stream::new(
+    Dictionary::from_iter([("Length1", Object::Integer(font.data.len() as i64))]),
+    font.data.to_vec(),
+  );
+  let _ = file.compress();
+  let file_id = doc.add_object(file);
+  let descriptor_id = doc.add_object(Dictionary::from_iter([
+    ("Type", Object::Name(b"FontDescriptor".to_vec())),
+    (
+      "FontName",
+      Object::Name(font.base_name.clone().into_bytes()),
+    ),
+    ("Flags", Object::Integer(4)),
+    (
+      "FontBBox",
+      Object::Array(vec![
+        units(bbox.x_min),
+        units(bbox.y_min),
+        units(bbox.x_max),
+        units(bbox.y_max),
+      ]),
+    ),
+    ("ItalicAngle", Object::Integer(0)),
+    ("Ascent", units(face.ascender())),
+    ("Descent", units(face.descender())),
+    (
+      "CapHeight",
+      units(face.capital_height().unwrap_or(face.ascender())),
+    ),
+    ("StemV", Object::Integer(80)),
+    ("FontFile2", Object::Reference(file_id)),
+  ]));
+
+  let mut widths = Vec::new();
+  for g in font.used.keys() {
+    let advance = face.glyph_hor_advance(GlyphId(*g)).unwrap_or(0) as f64 * scale;
+    widths.push(Object::Integer(*g as i64));
+    widths.push(Object::Array(vec![Object::Integer(advance.round() as i64)]));
+  }
+  let cid_font_id = doc.add_object(Dictionary::from_iter([
+    ("Type", Object::Name(b"Font".to_vec())),
+    ("Subtype", Object::Name(b"CIDFontType2".to_vec())),
+    (
+      "BaseFont",
+      Object::Name(font.base_name.clone().into_bytes()),
+    ),
+    (
+      "CIDSystemInfo",
+      Object::Dictionary(Dictionary::from_iter([
+        (
+          "Registry",
+          Object::String(b"Adobe".to_vec(), StringFormat::Literal),
+        ),
+        (
+          "Ordering",
+          Object::String(b"Identity".to_vec(), StringFormat::Literal),
+        ),
+        ("Supplement", Object::Integer(0)),
+      ])),
+    ),
+    ("FontDescriptor", Object::Reference(descriptor_id)),
+    ("DW", Object::Integer(1000)),
+    ("W", Object::Array(widths)),
+    ("CIDToGIDMap", Object::Name(b"Identity".to_vec())),
+  ]));
+
+  let mut cmap = Stream::new(Dictionary::new(), to_unicode(&font.used));
+  let _ = cmap.compress();
+  let cmap_id = doc.add_object(cmap);
+  doc.add_object(Dictionary::from_iter([
+    ("Type", Object::Name(b"Font".to_vec())),
+    ("Subtype", Object::Name(b"Type0".to_vec())),
+    (
+      "BaseFont",
+      Object::Name(font.base_name.clone().into_bytes()),
+    ),
+    ("Encoding", Object::Name(b"Identity-H".to_vec())),
+    (
+      "DescendantFonts",
+      Object::Array(vec![Object::Reference(cid_font_id)]),
+    ),
+    ("ToUnicode", Object::Reference(cmap_id)),
+  ]))
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let source = task.source;
+  let mut blocks: BTreeMap<u32, Vec<Block>> = BTreeMap::new();
+  for (locator, text) in task.blocks {
+    if let Some(at) = Locator::parse(&locator) {
+      blocks.entry(at.page).or_default().push(Block { at, text });
+    }
+  }
+  let mut doc =
+    Document::load_mem(&source).map_err(|e| invalid(format!("Cannot read the PDF: {e}")))?;
+  if doc.is_encrypted() {
+    doc
+      .decrypt("")
+      .map_err(|_| invalid("The PDF is password protected"))?;
+  }
+
+  let loaded = load_fonts(app);
+  let mut fonts: Vec<PdfFont> = loaded
+    .iter()
+    .enumerate()
+    .filter_map(|(i, (path, data))| {
+      let face = Face::parse(data, 0).ok()?;
+      Some(PdfFont {
+        resource: format!("AIDT{i}"),
+        base_name: base_name(&face, path, i),
+        data,
+        face,
+        used: BTreeMap::new(),
+      })
+    })
+    .collect();
+  if fonts.is_empty() {
+    return Err(invalid(
+      "No font for the translated text: set pdf_font to a TrueType font (.ttf or .ttc)",
+    ));
+  }
+
+  let pages = doc.get_pages();
+  let total = pages.len();
+  let progress = |done| {
+    let _ = app.emit(
+      "pdf-export-progress",
+      Progress {
+        job_id,
+        done,
+        total,
+      },
+    );
+  };
+  progress(0);
+
+  // lay out every page first: the fonts' widths and mappings list the
+  // glyphs used, and pages refer to the fonts
+  let mut drawn = Vec::new();
+  // whether each form stripped so far had text
+  let mut stripped_forms = HashMap::new();
+  for (number, page_id) in &pages {
+    let Some(page_blocks) = blocks.get(number) else {
+      continue;
+    };
+    let content = doc
+      .get_page_content(*page_id)
+      .map_err(|e| invalid(format!("Cannot read page {number}: {e}")))?;
+    let (content, mut had_text) = strip_text(&content).map_err(invalid)?;
+    for form in forms(&doc, *page_id) {
+      if let Some(form_text) = stripped_forms.get(&form) {
+        had_text |= form_text;
+        continue;
+      }
+      let mut form_text = false;
+      if let Ok(Object::Stream(stream)) = doc.get_object_mut(form) {
+        let plain = stream
+          .decompressed_content()
+          .unwrap_or_else(|_| stream.content.clone());
+        if let Ok((stripped, text)) = strip_text(&plain) {
+          form_text = text;
+          stream.set_plain_content(stripped);
+          let _ = stream.compress();
+        }
+      }
+      stripped_forms.insert(form, form_text);
+      had_text |= form_text;
+    }
+    let text = draw(
+      &mut fonts,
+      page_blocks,
+      page_height(&doc, *page_id),
+      !had_text,
+    );
+    drawn.push((*page_id, content, text));
+  }
+
+  let font_ids: Vec<(String, ObjectId)> = fonts
+    .iter()
+    .filter(|f| !f.used.is_empty())
+    .map(|f| (f.resource.clone(), embed(&mut doc, f)))
+    .collect();
+  let mut done = total - drawn.len();
+  progress(done);
+  for (page_id, content, text) in drawn {
+    doc
+      .change_page_content(page_id, content)
+      .and_then(|()| doc.add_page_contents(page_id, text.into_bytes()))
+      .map_err(|e| invalid(format!("Cannot update a page: {e}")))?;
+    add_fonts(&mut doc, page_id, &font_ids).map_err(invalid)?;
+    done += 1;
+    progress(done);
+  }
+
+  doc
+    .save(path)
+    .map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(())
+}
+
+/// Writes the translated PDF of PDF task `job_id` to `path`, reporting
+/// `pdf-export-progress` events as pages are done.
+#[tauri::command]
+pub async fn export_translated_pdf(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,518 @@
+//! The image of a scanned PDF page, for OCR: the largest image the page
+//! draws, directly or from a form, in a format Tesseract reads. JPEG and
+//! JPEG 2000 data is passed on as it is, CCITT fax data wrapped in a TIFF
+//! and raw samples written as PNM; JBIG2 is not supported. The image's
+//! placement comes along, so boxes found in it map back to page points.
+
+use pdf_extract::{content::Content, Dictionary, Document, Object, ObjectId, Stream};
+
+/// `[a b c d e f]` as in PDF's `cm`.
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+/// Forms inside forms followed for images.
+const MAX_DEPTH: usize = 4;
+
+pub struct Scan {
+  /// The encoded image.
+  pub image: Vec<u8>,
+  /// In pixels.
+  pub width: u32,
+  pub height: u32,
+  /// Maps the image's unit square to page space.
+  matrix: Matrix,
+}
+
+impl Scan {
+  /// The resolution the image is drawn at.
+  pub fn dpi(&self) -> u32 {
+    let [a, b, ..] = self.matrix;
+    let points = a.hypot(b).max(1.0);
+    (f64::from(self.width) * 72.0 / points)
+      .round()
+      .clamp(70.0, 2400.0) as u32
+  }
+
+  /// Pixel `(x, y)` of the image, from its top-left corner, in points
+  /// from the top-left corner of a page `page_height` high.
+  pub fn to_page(&self, x: f64, y: f64, page_height: f64) -> (f64, f64) {
+    let u = x / f64::from(self.width);
+    let v = 1.0 - y / f64::from(self.height);
+    let [a, b, c, d, e, f] = self.matrix;
+    (a * u + c * v + e, page_height - (b * u + d * v + f))
+  }
+
+  /// The box of pixels `left..right`, `top..bottom` on the page.
+  pub fn box_on_page(
+    &self,
+    (left, top, right, bottom): (f64, f64, f64, f64),
+    page_height: f64,
+  ) -> (f64, f64, f64, f64) {
+    let corners = [
+      self.to_page(left, top, page_height),
+      self.to_page(right, top, page_height),
+      self.to_page(left, bottom, page_height),
+      self.to_page(right, bottom, page_height),
+    ];
+    corners.iter().fold(
+      (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+      |(l, t, r, b), &(x, y)| (l.min(x), t.min(y), r.max(x), b.max(y)),
+    )
+  }
+}
+
+/// `m` applied after `n`.
+fn multiply(m: Matrix, n: Matrix) -> Matrix {
+  [
+    m[0] * n[0] + m[1] * n[2],
+    m[0] * n[1] + m[1] * n[3],
+    m[2] * n[0] + m[3] * n[2],
+    m[2] * n[1] + m[3] * n[3],
+    m[4] * n[0] + m[5] * n[2] + n[4],
+    m[4] * n[1] + m[5] * n[3] + n[5],
+  ]
+}
+
+fn matrix(doc: &Document, object: &Object) -> Option<Matrix> {
+  let array = doc.dereference(object).ok()?.1.as_array().ok()?;
+  let numbers: Vec<f64> = array
+    .iter()
+    .map(|n| n.as_float().ok().map(f64::from))
+    .collect::<Option<_>>()?;
+  numbers.try_into().ok()
+}
+
+/// Resource dictionaries, innermost first.
+#[derive(Clone)]
+struct Resources<'a>(Vec<&'a Dictionary>);
+
+impl<'a> Resources<'a> {
+  /// The page's own resources, or those it inherits from the nearest
+  /// page tree node that has some.
+  fn of_page(doc: &'a Document, page_id: ObjectId) -> Self {
+    let mut node = doc.get_dictionary(page_id).ok();
+    for _ in 0..32 {
+      let Some(dict) = node else {
+        break;
+      };
+      if let Ok(resources) = dict.get_deref(b"Resources", doc).and_then(Object::as_dict) {
+        return Resources(vec![resources]);
+      }
+      node = dict
+        .get_deref(b"Parent", doc)
+        .and_then(Object::as_dict)
+        .ok();
+    }
+    Resources(Vec::new())
+  }
+
+  /// Named resource `name` in category `category`, e.g. `XObject`.
+  fn get(&self, doc: &'a Document, category: &[u8], name: &[u8]) -> Option<&'a Object> {
+    self.0.iter().find_map(|dict| {
+      let entries = dict.get_deref(category, doc).ok()?.as_dict().ok()?;
+      entries.get_deref(name, doc).ok()
+    })
+  }
+
+  fn inside(&self, dict: Option<&'a Dictionary>) -> Self {
+    let mut inner = self.clone();
+    if let Some(dict) = dict {
+      inner.0.insert(0, dict);
+    }
+    inner
+  }
+}
+
+/// Images drawn by `content`, with their placement.
+fn placements<'a>(
+  doc: &'a Document,
+  resources: &Resources<'a>,
+  content: &[u8],
+  ctm: Matrix,
+  depth: usize,
+  out: &mut Vec<(&'a Stream, Resources<'a>, Matrix)>,
+) {
+  let Ok(content) = Content::decode(content) else {
+    return;
+  };
+  let mut stack = Vec::new();
+  let mut ctm = ctm;
+  for op in content.operations {
+    match op.operator.as_str() {
+      "q" => stack.push(ctm),
+      "Q" => ctm = stack.pop().unwrap_or(ctm),
+      "cm" => {
+        if let Some(m) = matrix(doc, &Object::Array(op.operands)) {
+          ctm = multiply(m, ctm);
+        }
+      }
+      "Do" => {
+        let Some(name) = op.operands.first().and_then(|n| n.as_name().ok()) else {
+          continue;
+        };
+        let Some(Ok(stream)) = resources.get(doc, b"XObject", name).map(Object::as_stream) else {
+          continue;
+        };
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+          Ok(b"Image") => out.push((stream, resources.clone(), ctm)),
+          Ok(b"Form") if depth < MAX_DEPTH => {
+            let form_matrix = stream
+              .dict
+              .get(b"Matrix")
+              .ok()
+              .and_then(|m| matrix(doc, m))
+              .unwrap_or(IDENTITY);
+            let inner = resources.inside(
+              stream
+                .dict
+                .get_deref(b"Resources", doc)
+                .and_then(Object::as_dict)
+                .ok(),
+            );
+            let content = stream
+              .decompressed_content()
+              .unwrap_or_else(|_| stream.content.clone());
+            placements(
+              doc,
+              &inner,
+              &content,
+              multiply(form_matrix, ctm),
+              depth + 1,
+              out,
+            );
+          }
+          _ => {}
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+fn area(m: &Matrix) -> f64 {
+  (m[0] * m[3] - m[1] * m[2]).abs()
+}
+
+fn int(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<i64> {
+  dict.get_deref(key, doc).ok()?.as_i64().ok()
+}
+
+enum Space {
+  Gray,
+  Rgb,
+  Cmyk,
+  /// A separation: more ink is darker.
+  Ink,
+  Indexed {
+    base: Box<Space>,
+    palette: Vec<u8>,
+  },
+}
+
+impl Space {
+  fn components(&self) -> usize {
+    match self {
+      Space::Gray | Space::Ink | Space::Indexed { .. } => 1,
+      Space::Rgb => 3,
+      Space::Cmyk => 4,
+    }
+  }
+
+  /// Channels written out: gray or RGB.
+  fn channels(&self) -> usize {
+    match self {
+      Space::Gray | Space::Ink => 1,
+      Space::Rgb | Space::Cmyk => 3,
+      Space::Indexed { base, .. } => base.channels(),
+    }
+  }
+
+  /// Appends the pixel of 8-bit components `samples`.
+  fn push(&self, samples: &[u8], out: &mut Vec<u8>) {
+    match self {
+      Space::Gray | Space::Rgb => out.extend_from_slice(samples),
+      Space::Ink => out.push(255 - samples[0]),
+      Space::Cmyk => {
+        let k = u16::from(samples[3]);
+        for &c in &samples[..3] {
+          out.push(255 - (u16::from(c) + k).min(255) as u8);
+        }
+      }
+      Space::Indexed { base, palette } => {
+        let n = base.components();
+        let at = usize::from(samples[0]) * n;
+        match palette.get(at..at + n) {
+          Some(entry) => base.push(entry, out),
+          None => out.resize(out.len() + base.channels(), 0),
+        }
+      }
+    }
+  }
+}
+
+fn color_space(
+  doc: &Document,
+  resources: &Resources,
+  object: &Object,
+  depth: usize,
+) -> Result<Space, String> {
+  let unsupported = || "The page image's color space is not supported".to_string();
+  if depth > MAX_DEPTH {
+    return Err(unsupported());
+  }
+  let object = doc.dereference(object).map_err(|e| e.to_string())?.1;
+  if let Ok(name) = object.as_name() {
+    return match name {
+      b"DeviceGray" | b"CalGray" | b"G" => Ok(Space::Gray),
+      b"DeviceRGB" | b"CalRGB" | b"RGB" => Ok(Space::Rgb),
+      b"DeviceCMYK" | b"CMYK" => Ok(Space::Cmyk),
+      _ => match resources.get(doc, b"ColorSpace", name) {
+        Some(named) => color_space(doc, resources, named, depth + 1),
+        None => Err(unsupported()),
+      },
+    };
+  }
+  let array = object.as_array().map_err(|_| unsupported())?;
+  let family = array
+    .first()
+    .and_then(|f| f.as_name().ok())
+    .unwrap_or_default();
+  let arg = |i: usize| -> Result<&Object, String> {
+    let object = array.get(i).ok_or_else(unsupported)?;
+    Ok(doc.dereference(object).map_err(|e| e.to_string())?.1)
+  };
+  match family {
+    b"CalGray" => Ok(Space::Gray),
+    b"CalRGB" => Ok(Space::Rgb),
+    b"Separation" => Ok(Space::Ink),
+    b"ICCBased" => {
+      let profile = arg(1)?.as_stream().map_err(|_| unsupported())?;
+      match int(doc, &profile.dict, b"N") {
+        Some(1) => Ok(Space::Gray),
+        Some(3) => Ok(Space::Rgb),
+        Some(4) => Ok(Space::Cmyk),
+        _ => Err(unsupported()),
+      }
+    }
+    b"Indexed" | b"I" => {
+      let base = color_space(doc, resources, arg(1)?, depth + 1)?;
+      let palette = match arg(3)? {
+        Object::String(bytes, _) => bytes.clone(),
+        Object::Stream(stream) => stream
+          .decompressed_content()
+          .unwrap_or_else(|_| stream.content.clone()),
+        _ => return Err(unsupported()),
+      };
+      Ok(Space::Indexed {
+        base: Box::new(base),
+        palette,
+      })
+    }
+    _ => Err(unsupported()),
+  }
+}
+
+/// Raw samples of `width` by `height` pixels as PNM: PBM for 1-bit gray,
+/// PGM for gray, PPM for color.
+fn pnm(
+  samples: &[u8],
+  width: usize,
+  height: usize,
+  bpc: usize,
+  space: &Space,
+  inverted: bool,
+) -> Result<Vec<u8>, String> {
+  let n = space.components();
+  let row_bytes = (width * n * bpc).div_ceil(8);
+  if !matches!(bpc, 1 | 2 | 4 | 8 | 16) || samples.len() < row_bytes * height {
+    return Err("The page image is truncated or malformed".to_string());
+  }
+  let indexed = matches!(space, Space::Indexed { .. });
+  if bpc == 1 && matches!(space, Space::Gray | Space::Ink) {
+    // PBM's rows are padded to bytes like PDF's, and 1 is black
+    let flip = matches!(space, Space::Gray) != inverted;
+    let mut out = format!("P4\n{width} {height}\n").into_bytes();
+    out.extend(
+      samples[..row_bytes * height]
+        .iter()
+        .map(|b| if flip { !b } else { *b }),
+    );
+    return Ok(out);
+  }
+  let magic = if space.channels() == 1 { "P5" } else { "P6" };
+  let mut out = format!("{magic}\n{width} {height}\n255\n").into_bytes();
+  let max = (1u32 << bpc) - 1;
+  let mut pixel = vec![0u8; n];
+  for row in samples.chunks(row_bytes).take(height) {
+    for x in 0..width {
+      for (i, component) in pixel.iter_mut().enumerate() {
+        let bit = (x * n + i) * bpc;
+        let mut value = 0u32;
+        for b in bit..bit + bpc {
+          value = (value << 1) | u32::from((row[b / 8] >> (7 - b % 8)) & 1);
+        }
+        *component = if indexed {
+          value.min(255) as u8
+        } else {
+          let scaled = (value * 255 / max) as u8;
+          if inverted {
+            255 - scaled
+          } else {
+            scaled
+          }
+        };
+      }
+      space.push(&pixel, &mut out);
+    }
+  }
+  Ok(out)
+}
+
+/// CCITT fax data in a one-strip little-endian TIFF.
+fn tiff(data: &[u8], width: u32, height: u32, k: i64, black_is_1: bool) -> Vec<u8> {
+  // group 4 is K < 0; group 3 is 1-D at K = 0, else mixed 1-D/2-D
+  let (compression, options_tag, options) = match k {
+    k if k < 0 => (4, 293, 0),
+    0 => (3, 292, 0),
+    _ => (3, 292, 1),
+  };
+  let entries: [(u16, u16, u32); 10] = [
+    (256, 4, width),
+    (257, 4, height),
+    (258, 3, 1),
+    (259, 3, compression),
+    // white is zero, unless the image is drawn the other way round
+    (262, 3, u32::from(black_is_1)),
+    (273, 4, 8 + 2 + 10 * 12 + 4),
+    (277, 3, 1),
+    (278, 4, height),
+    (279, 4, data.len() as u32),
+    (options_tag, 4, options),
+  ];
+  let mut out = b"II*\0".to_vec();
+  out.extend(8u32.to_le_bytes());
+  out.extend((entries.len() as u16).to_le_bytes());
+  for (tag, kind, value) in entries {
+    out.extend(tag.to_le_bytes());
+    out.extend(kind.to_le_bytes());
+    out.extend(1u32.to_le_bytes());
+    out.extend(value.to_le_bytes());
+  }
+  out.extend(0u32.to_le_bytes());
+  out.extend_from_slice(data);
+  out
+}
+
+fn decode(doc: &Document, stream: &Stream, resources: &Resources) -> Result<Scan, String> {
+  let dict = &stream.dict;
+  let malformed = || "The page image is malformed".to_string();
+  let width = int(doc, dict, b"Width").ok_or_else(malformed)?;
+  let height = int(doc, dict, b"Height").ok_or_else(malformed)?;
+  let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+    return Err(malformed());
+  };
+  let filters: Vec<Vec<u8>> = stream
+    .filters()
+    .map(|f| f.into_iter().map(<[u8]>::to_vec).collect())
+    .unwrap_or_default();
+  let codec = filters
+    .last()
+    .filter(|f| {
+      matches!(
+        f.as_slice(),
+        b"DCTDecode" | b"DCT" | b"JPXDecode" | b"CCITTFaxDecode" | b"CCF" | b"JBIG2Decode"
+      )
+    })
+    .cloned();
+  let data = match &codec {
+    Some(_) if filters.len() == 1 => stream.content.clone(),
+    Some(_) => {
+      let mut outer = stream.clone();
+      let rest = filters[..filters.len() - 1]
+        .iter()
+        .map(|f| Object::Name(f.clone()))
+        .collect();
+      outer.dict.set("Filter", Object::Array(rest));
+      outer.dict.remove(b"DecodeParms");
+      outer.decompressed_content().map_err(|e| e.to_string())?
+    }
+    None if filters.is_empty() => stream.content.clone(),
+    None => stream.decompressed_content().map_err(|e| e.to_string())?,
+  };
+  let decode_array: Vec<f64> = dict
+    .get_deref(b"Decode", doc)
+    .and_then(Object::as_array)
+    .map(|a| {
+      a.iter()
+        .filter_map(|n| n.as_float().ok().map(f64::from))
+        .collect()
+    })
+    .unwrap_or_default();
+  let inverted = decode_array.len() >= 2 && decode_array[0] > decode_array[1];
+  let image = match codec.as_deref() {
+    Some(b"DCTDecode" | b"DCT" | b"JPXDecode") => data,
+    Some(b"CCITTFaxDecode" | b"CCF") => {
+      let params = match dict.get_deref(b"DecodeParms", doc) {
+        Ok(Object::Array(all)) => all.last().and_then(|p| p.as_dict().ok()),
+        Ok(params) => params.as_dict().ok(),
+        Err(_) => None,
+      };
+      let param = |key: &[u8]| params.and_then(|p| p.get(key).ok());
+      let k = param(b"K").and_then(|k| k.as_i64().ok()).unwrap_or(0);
+      let black_is_1 = param(b"BlackIs1")
+        .and_then(|b| b.as_bool().ok())
+        .unwrap_or(false);
+      let columns = param(b"Columns")
+        .and_then(|c| c.as_i64().ok())
+        .and_then(|c| u32::try_from(c).ok())
+        .unwrap_or(width);
+      tiff(&data, columns, height, k, black_is_1 != inverted)
+    }
+    Some(_) => return Err("JBIG2 page images are not supported".to_string()),
+    None => {
+      let mask = dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false);
+      let (bpc, space) = if mask {
+        (1, Space::Gray)
+      } else {
+        let space = dict
+          .get(b"ColorSpace")
+          .map_err(|_| malformed())
+          .and_then(|cs| color_space(doc, resources, cs, 0))?;
+        let bpc = int(doc, dict, b"BitsPerComponent").unwrap_or(8);
+        (bpc as usize, space)
+      };
+      pnm(
+        &data,
+        width as usize,
+        height as usize,
+        bpc,
+        &space,
+        inverted,
+      )?
+    }
+  };
+  Ok(Scan {
+    image,
+    width,
+    height,
+    matrix: IDENTITY,
+  })
+}
+
+/// The largest image page `page_id` draws, or `None` if it draws none.
+pub fn scan(doc: &Document, page_id: ObjectId) -> Option<Result<Scan, String>> {
+  let resources = Resources::of_page(doc, page_id);
+  let content = doc.get_page_content(page_id).ok()?;
+  let mut found = Vec::new();
+  placements(doc, &resources, &content, IDENTITY, 0, &mut found);
+  let (stream, resources, placed) = found
+    .into_iter()
+    .max_by(|a, b| area(&a.2).total_cmp(&area(&b.2)))?;
+  Some(decode(doc, stream, &resources).map(|scan| Scan {
+    matrix: placed,
+    ..scan
+  }))
+}
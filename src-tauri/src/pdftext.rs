@@ -0,0 +1,352 @@
+//! `extract_pdf_text`: the text of a PDF, page by page, as runs with their
+//! positions, read in the shell so the UI can preview and segment a PDF
+//! without sending it through the backend. Scanned pages have no text and
+//! come back without runs.
+//!
+//! [`segments`] groups the runs into paragraph-like blocks for translation,
+//! and has the text of scanned pages read by OCR (see `ocr`, `pdfscan`).
+//! Each block's locator records its page and box, which is all
+//! `pdfexport` needs to put the translation back in the same place.
+
+use pdf_extract::{Document, MediaBox, OutputDev, OutputError, Transform};
+use serde::Serialize;
+use std::{panic, path::Path};
+
+use crate::{
+  ocr::{self, PageProgress},
+  pdfscan, protected,
+  segments::Segment,
+};
+
+/// Coordinates are PDF points from the page's top-left corner; `y` is the
+/// baseline.
+#[derive(Serialize)]
+pub struct TextRun {
+  text: String,
+  x: f64,
+  y: f64,
+  width: f64,
+  font_size: f64,
+}
+
+#[derive(Serialize)]
+pub struct PdfPage {
+  /// 1-based.
+  number: u32,
+  width: f64,
+  height: f64,
+  runs: Vec<TextRun>,
+}
+
+/// Where a segment is: its 1-based page and box in points from the page's
+/// top-left corner, as `pdf:<page>:<left>,<top>,<right>,<bottom>:<font
+/// size>`.
+pub struct Locator {
+  pub page: u32,
+  pub left: f64,
+  pub top: f64,
+  pub right: f64,
+  pub bottom: f64,
+  pub font_size: f64,
+}
+
+impl Locator {
+  pub fn parse(locator: &str) -> Option<Locator> {
+    let mut parts = locator.strip_prefix("pdf:")?.split(':');
+    let page = parts.next()?.parse().ok()?;
+    let edges: Vec<f64> = parts
+      .next()?
+      .split(',')
+      .map(|n| n.parse().ok())
+      .collect::<Option<_>>()?;
+    let font_size = parts.next()?.parse().ok()?;
+    let [left, top, right, bottom] = edges[..] else {
+      return None;
+    };
+    Some(Locator {
+      page,
+      left,
+      top,
+      right,
+      bottom,
+      font_size,
+    })
+  }
+
+  fn format(&self) -> String {
+    format!(
+      "pdf:{}:{:.1},{:.1},{:.1},{:.1}:{:.1}",
+      self.page, self.left, self.top, self.right, self.bottom, self.font_size
+    )
+  }
+}
+
+#[derive(Default)]
+struct Collector {
+  pages: Vec<PdfPage>,
+  /// Flips PDF's bottom-up y axis.
+  page_height: f64,
+  run: Option<TextRun>,
+  /// Set between words, so the next character may add a space.
+  word_start: bool,
+}
+
+impl Collector {
+  fn flush(&mut self) {
+    if let Some(run) = self.run.take() {
+      if !run.text.trim().is_empty() {
+        if let Some(page) = self.pages.last_mut() {
+          page.runs.push(run);
+        }
+      }
+    }
+  }
+}
+
+impl OutputDev for Collector {
+  fn begin_page(
+    &mut self,
+    page_num: u32,
+    media_box: &MediaBox,
+    _art_box: Option<(f64, f64, f64, f64)>,
+  ) -> Result<(), OutputError> {
+    self.page_height = media_box.ury - media_box.lly;
+    self.pages.push(PdfPage {
+      number: page_num,
+      width: media_box.urx - media_box.llx,
+      height: self.page_height,
+      runs: Vec::new(),
+    });
+    Ok(())
+  }
+
+  fn end_page(&mut self) -> Result<(), OutputError> {
+    self.flush();
+    Ok(())
+  }
+
+  fn output_character(
+    &mut self,
+    trm: &Transform,
+    width: f64,
+    _spacing: f64,
+    font_size: f64,
+    char: &str,
+  ) -> Result<(), OutputError> {
+    let (x, y) = (trm.m31, self.page_height - trm.m32);
+    // the font size as scaled by the text matrix
+    let size = ((font_size * (trm.m11 + trm.m21)) * (font_size * (trm.m12 + trm.m22)))
+      .abs()
+      .sqrt();
+    let advance = width * size;
+    if let Some(run) = &mut self.run {
+      let end = run.x + run.width;
+      let same_line = (y - run.y).abs() <= run.font_size * 0.5;
+      if same_line && x >= end - run.font_size && x - end <= run.font_size {
+        if self.word_start && x > end + size * 0.1 {
+          run.text.push(' ');
+        }
+        run.text.push_str(char);
+        run.width = (x + advance - run.x).max(run.width);
+        self.word_start = false;
+        return Ok(());
+      }
+    }
+    self.flush();
+    self.run = Some(TextRun {
+      text: char.to_string(),
+      x,
+      y,
+      width: advance,
+      font_size: size,
+    });
+    self.word_start = false;
+    Ok(())
+  }
+
+  fn begin_word(&mut self) -> Result<(), OutputError> {
+    self.word_start = true;
+    Ok(())
+  }
+
+  fn end_word(&mut self) -> Result<(), OutputError> {
+    Ok(())
+  }
+
+  fn end_line(&mut self) -> Result<(), OutputError> {
+    Ok(())
+  }
+}
+
+pub fn is_cjk(c: char) -> bool {
+  matches!(c as u32, 0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF)
+}
+
+/// Appends the next run or line of a block.
+pub fn join_line(text: &mut String, next: &str) {
+  let prev = text.chars().last();
+  let first = next.chars().next();
+  if prev == Some('-') && first.is_some_and(char::is_lowercase) {
+    // a word hyphenated across the line break
+    text.pop();
+  } else if !(prev.is_some_and(is_cjk) && first.is_some_and(is_cjk)) {
+    text.push(' ');
+  }
+  text.push_str(next);
+}
+
+/// Consecutive runs of about the same size form one block while each
+/// continues the line before it or starts the next line below it, within
+/// the block's columns.
+fn blocks(page: &PdfPage) -> Vec<Segment> {
+  let mut out: Vec<(Locator, String)> = Vec::new();
+  let mut last_baseline = f64::NAN;
+  for run in &page.runs {
+    let size = run.font_size.max(1.0);
+    let (top, bottom) = (run.y - size * 0.8, run.y + size * 0.2);
+    let (left, right) = (run.x, run.x + run.width);
+    let text = run.text.trim();
+    if let Some((b, block)) = out.last_mut() {
+      let similar = (b.font_size - size).abs() <= size * 0.2;
+      let same_line = (run.y - last_baseline).abs() <= size * 0.5 && left - b.right <= size * 2.0;
+      let next_line =
+        run.y > last_baseline && top - b.bottom <= size * 0.8 && left <= b.right && right >= b.left;
+      if similar && (same_line || next_line) {
+        join_line(block, text);
+        b.left = b.left.min(left);
+        b.right = b.right.max(right);
+        b.top = b.top.min(top);
+        b.bottom = b.bottom.max(bottom);
+        last_baseline = run.y;
+        continue;
+      }
+    }
+    out.push((
+      Locator {
+        page: page.number,
+        left,
+        top,
+        right,
+        bottom,
+        font_size: size,
+      },
+      text.to_string(),
+    ));
+    last_baseline = run.y;
+  }
+  out
+    .into_iter()
+    .map(|(locator, text)| Segment {
+      locator: locator.format(),
+      text,
+    })
+    .collect()
+}
+
+/// The text a scan of `page` reads, in blocks.
+fn ocr_blocks(page: &PdfPage, scan: &pdfscan::Scan, found: &ocr::Recognized) -> Vec<Segment> {
+  found
+    .paragraphs
+    .iter()
+    .map(|p| {
+      let (left, top, right, bottom) =
+        scan.box_on_page((p.left, p.top, p.right, p.bottom), page.height);
+      let (_, line_top) = scan.to_page(0.0, 0.0, page.height);
+      let (_, line_bottom) = scan.to_page(0.0, p.line_height, page.height);
+      let locator = Locator {
+        page: page.number,
+        left,
+        top,
+        right,
+        bottom,
+        font_size: (line_bottom - line_top).abs().max(1.0),
+      };
+      Segment {
+        locator: locator.format(),
+        text: p.text.clone(),
+      }
+    })
+    .collect()
+}
+
+fn extract(path: &Path) -> Result<Vec<PdfPage>, String> {
+  Ok(load(path)?.1)
+}
+
+fn load(path: &Path) -> Result<(Document, Vec<PdfPage>), String> {
+  let data = protected::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut doc = Document::load_mem(&data).map_err(|e| format!("Cannot read the PDF: {e}"))?;
+  let mut collector = Collector::default();
+  // the parser panics on some malformed files instead of failing
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+    if doc.is_encrypted() {
+      pdf_extract::output_doc_encrypted(&mut doc, &mut collector, "")
+    } else {
+      pdf_extract::output_doc(&doc, &mut collector)
+    }
+  }));
+  match result {
+    Ok(Ok(())) => Ok((doc, collector.pages)),
+    Ok(Err(e)) => Err(format!("Cannot extract text from the PDF: {e:?}")),
+    Err(_) => Err("The PDF is malformed".to_string()),
+  }
+}
+
+/// The PDF's text in blocks, page by page. Pages without text that draw
+/// an image are read by OCR, each reported as it is done; when none of the
+/// PDF could be read, the first OCR failure is the error.
+pub fn segments(path: &Path, ocr: &ocr::Session) -> Result<Vec<Segment>, String> {
+  let (doc, pages) = load(path)?;
+  let ids = doc.get_pages();
+  let scans: Vec<_> = pages
+    .iter()
+    .map(|page| match ids.get(&page.number) {
+      Some(id) if page.runs.is_empty() => pdfscan::scan(&doc, *id),
+      _ => None,
+    })
+    .collect();
+  let total = scans.iter().flatten().count();
+  let mut out = Vec::new();
+  let mut failure = None;
+  let mut done = 0;
+  for (page, scan) in pages.iter().zip(scans) {
+    let Some(scan) = scan else {
+      out.extend(blocks(page));
+      continue;
+    };
+    done += 1;
+    let mut progress = PageProgress {
+      page: page.number,
+      done,
+      total,
+      confidence: None,
+      blocks: 0,
+      error: None,
+    };
+    match scan.and_then(|scan| Ok((ocr.recognize(&scan.image, scan.dpi())?, scan))) {
+      Ok((found, scan)) => {
+        let found_blocks = ocr_blocks(page, &scan, &found);
+        progress.confidence = found.confidence;
+        progress.blocks = found_blocks.len();
+        out.extend(found_blocks);
+      }
+      Err(e) => {
+        progress.error = Some(format!("Page {}: {e}", page.number));
+        failure.get_or_insert_with(|| progress.error.clone().unwrap_or_default());
+      }
+    }
+    ocr.report(progress);
+  }
+  match failure {
+    Some(e) if out.is_empty() => Err(e),
+    _ => Ok(out),
+  }
+}
+
+/// Every page of the PDF at `path` with its text runs, in content order.
+#[tauri::command]
+pub async fn extract_pdf_text(path: String) -> Result<Vec<PdfPage>, String> {
+  tauri::async_runtime::spawn_blocking(move || extract(Path::new(&path)))
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,291 @@
+//! Numbered placeholders for markup inside a segment, shared by the
+//! formats that splice translations back into their source (`html`,
+//! `latex`, `po`, `resources`). Markup that wraps words goes to the
+//! translator as a pair, `⟦1⟧words⟦/1⟧`; markup kept whole (a break, an
+//! image, a formula, a `%s`) as `⟦2⟧`. A translation is only written with
+//! its markup when every placeholder came back once and the pairs still
+//! nest.
+
+use serde::Serialize;
+use std::ops::Range;
+
+pub const OPEN: char = '⟦';
+pub const CLOSE: char = '⟧';
+
+/// What a placeholder stands for besides its source range.
+pub enum Role {
+  /// Markup wrapping words, with the range of its end.
+  Element(Range<usize>),
+  /// The start of markup that ends after the segment.
+  Opens,
+  /// The end of markup that started before it.
+  Closes,
+  /// Markup kept whole.
+  Whole,
+}
+
+/// A placeholder's source range and role.
+pub type Tag = (Range<usize>, Role);
+
+/// Placeholder `number`, or its closing one.
+pub fn marker(number: usize, closing: bool) -> String {
+  let slash = if closing { "/" } else { "" };
+  format!("{OPEN}{slash}{number}{CLOSE}")
+}
+
+/// A segment written without its markup.
+#[derive(Serialize)]
+pub struct Warning {
+  /// The start of the source text.
+  pub excerpt: String,
+  pub message: String,
+}
+
+pub enum Piece<'a> {
+  Text(&'a str),
+  /// Placeholder number, and whether it is the closing one.
+  Tag(usize, bool),
+}
+
+pub fn pieces(translation: &str) -> Vec<Piece<'_>> {
+  let mut out = Vec::new();
+  let mut rest = translation;
+  while let Some(open) = rest.find(OPEN) {
+    let after = &rest[open + OPEN.len_utf8()..];
+    let marker = after.split_once(CLOSE).and_then(|(inside, tail)| {
+      let inside = inside.trim();
+      let (closing, number) = match inside.strip_prefix('/') {
+        Some(number) => (true, number.trim()),
+        None => (false, inside),
+      };
+      Some((number.parse().ok()?, closing, tail))
+    });
+    match marker {
+      Some((number, closing, tail)) => {
+        out.push(Piece::Text(&rest[..open]));
+        out.push(Piece::Tag(number, closing));
+        rest = tail;
+      }
+      None => {
+        out.push(Piece::Text(&rest[..open + OPEN.len_utf8()]));
+        rest = after;
+      }
+    }
+  }
+  out.push(Piece::Text(rest));
+  out
+}
+
+/// Whether every placeholder of `tags` is in `pieces` once, elements'
+/// end placeholders after their start and properly nested.
+pub fn balanced(pieces: &[Piece], tags: &[Tag]) -> bool {
+  let mut seen = vec![(0, 0); tags.len()];
+  let mut open = Vec::new();
+  for piece in pieces {
+    let Piece::Tag(number, closing) = *piece else {
+      continue;
+    };
+    let Some((_, role)) = number.checked_sub(1).and_then(|n| tags.get(n)) else {
+      return false;
+    };
+    let count = &mut seen[number - 1];
+    match (closing, role) {
+      (false, Role::Element(_)) => {
+        count.0 += 1;
+        open.push(number);
+      }
+      (false, _) => count.0 += 1,
+      (true, Role::Element(_)) => {
+        count.1 += 1;
+        if open.pop() != Some(number) {
+          return false;
+        }
+      }
+      (true, _) => return false,
+    }
+  }
+  seen.iter().zip(tags).all(|(&(opens, closes), (_, role))| {
+    opens == 1 && closes == usize::from(matches!(role, Role::Element(_)))
+  })
+}
+
+/// An ICU plural or select being read by [`formats`].
+enum Icu {
+  /// Between its cases.
+  Cases { plural: bool },
+  /// In a case, whose words are text.
+  Case { plural: bool },
+}
+
+/// `text` with its format placeholders as `⟦n⟧`, and their ranges: printf
+/// conversions (`%s`, `%1$d`, `%(name)s`, `%@`), named arguments (`{0}`,
+/// `{name}`, `{{ name }}`, `%{name}`, `${name}`), the `%%` and `{{`
+/// escapes, and the syntax of ICU plurals and selects, whose cases stay
+/// text: `{count, plural, one{# file} other{# files}}` is sent as
+/// `⟦1⟧ file⟦2⟧ files⟦3⟧`.
+pub fn formats(text: &str) -> (String, Vec<Tag>) {
+  let b = text.as_bytes();
+  let mut spans: Vec<Range<usize>> = Vec::new();
+  let mut icu = Vec::new();
+  let mut i = 0;
+  while i < b.len() {
+    let end = match (b[i], icu.last()) {
+      (_, Some(&Icu::Cases { plural })) => match case_start(text, i) {
+        Some(end) if b[end - 1] == b'}' => {
+          icu.pop();
+          Some(end)
+        }
+        Some(end) => {
+          icu.push(Icu::Case { plural });
+          Some(end)
+        }
+        None => {
+          icu.clear();
+          None
+        }
+      },
+      (b'}', Some(Icu::Case { .. })) => {
+        icu.pop();
+        Some(i + 1)
+      }
+      (b'#', Some(Icu::Case { plural: true })) => Some(i + 1),
+      (b'%', _) if b.get(i + 1) == Some(&b'%') => Some(i + 2),
+      (b'%' | b'$', _) if b.get(i + 1) == Some(&b'{') => name_end(text, i + 1),
+      (b'%', _) => printf_end(b, i),
+      (b'{', _) if b.get(i + 1) == Some(&b'{') => text[i..]
+        .find("}}")
+        .map(|n| i + n + 2)
+        .filter(|&e| is_name(text[i + 2..e - 2].trim()))
+        .or(Some(i + 2)),
+      (b'{', _) => match argument(text, i) {
+        Some((end, Some(plural))) => {
+          icu.push(Icu::Cases { plural });
+          Some(end)
+        }
+        Some((end, None)) => Some(end),
+        None => None,
+      },
+      _ => None,
+    };
+    match end {
+      Some(end) => {
+        match spans.last_mut() {
+          Some(last) if last.end == i => last.end = end,
+          _ => spans.push(i..end),
+        }
+        i = end;
+      }
+      None => i += text[i..].chars().next().map_or(1, char::len_utf8),
+    }
+  }
+  let mut out = String::with_capacity(text.len());
+  let mut at = 0;
+  for (n, span) in spans.iter().enumerate() {
+    out.push_str(&text[at..span.start]);
+    out.push_str(&marker(n + 1, false));
+    at = span.end;
+  }
+  out.push_str(&text[at..]);
+  (
+    out,
+    spans.into_iter().map(|span| (span, Role::Whole)).collect(),
+  )
+}
+
+/// `translation` of `source` (both as [`formats`] reads them) with its
+/// placeholders put back; `None` unless it kept every one exactly once.
+pub fn unlock(source: &str, translation: &str) -> Option<String> {
+  let (_, tags) = formats(source);
+  let pieces = pieces(translation);
+  if !balanced(&pieces, &tags) {
+    return None;
+  }
+  let mut out = String::new();
+  for piece in pieces {
+    match piece {
+      Piece::Text(text) => out.push_str(text),
+      Piece::Tag(number, _) => out.push_str(&source[tags[number - 1].0.clone()]),
+    }
+  }
+  Some(out)
+}
+
+fn is_name(name: &str) -> bool {
+  !name.is_empty() && !name.contains(|c: char| c.is_whitespace() || c == '{' || c == '}')
+}
+
+/// The end of the `{name}` at `at`.
+fn name_end(text: &str, at: usize) -> Option<usize> {
+  let end = at + text[at..].find('}')? + 1;
+  is_name(&text[at + 1..end - 1]).then_some(end)
+}
+
+/// The end of the argument at `at` (a `{`), or of the head of an ICU
+/// plural or select (`{count, plural,`) with whether it is a plural.
+fn argument(text: &str, at: usize) -> Option<(usize, Option<bool>)> {
+  let rest = &text[at + 1..];
+  let stop = rest.find(['}', ','])?;
+  if !is_name(rest[..stop].trim()) {
+    return None;
+  }
+  if rest.as_bytes()[stop] == b'}' {
+    return Some((at + stop + 2, None));
+  }
+  let after = &rest[stop + 1..];
+  let kind_end = after.find(['}', ',', '{'])?;
+  let kind = after[..kind_end].trim();
+  let next = at + 1 + stop + 1 + kind_end;
+  match (kind, after.as_bytes()[kind_end]) {
+    ("plural" | "selectordinal", b',') => Some((next + 1, Some(true))),
+    ("select", b',') => Some((next + 1, Some(false))),
+    // `{n, number}`, `{d, date, short}`: kept whole
+    (_, b'{') => None,
+    _ if is_name(kind) => text[at..].find('}').map(|n| (at + n + 1, None)),
+    _ => None,
+  }
+}
+
+/// Between the cases of an ICU argument, the end of the next case's
+/// start (` one{`, ` =0 {`, and a plural's `offset:1`), or of the
+/// argument (`}`).
+fn case_start(text: &str, at: usize) -> Option<usize> {
+  let mut i = at + (text[at..].len() - text[at..].trim_start().len());
+  if let Some(rest) = text[i..].strip_prefix("offset:") {
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    i += "offset:".len() + digits;
+    i += text[i..].len() - text[i..].trim_start().len();
+  }
+  let rest = &text[i..];
+  if rest.starts_with('}') {
+    return Some(i + 1);
+  }
+  let selector = rest
+    .strip_prefix('=')
+    .unwrap_or(rest)
+    .find(|c: char| !c.is_alphanumeric() && c != '_')
+    .map(|n| n + usize::from(rest.starts_with('=')))?;
+  let after = &rest[selector..];
+  let brace = after.len() - after.trim_start().len();
+  (selector > 0 && after[brace..].starts_with('{')).then_some(i + selector + brace + 1)
+}
+
+/// The end of the printf conversion at `at` (a `%`), e.g. `%s`, `%-5.2f`,
+/// `%1$s`, `%(name)s`; `None` for a lone `%`.
+fn printf_end(b: &[u8], at: usize) -> Option<usize> {
+  let mut i = at + 1;
+  if b.get(i) == Some(&b'(') {
+    i += b[i..].iter().position(|&c| c == b')')? + 1;
+  }
+  while b
+    .get(i)
+    .is_some_and(|c| c.is_ascii_digit() || b"$-+#.*'".contains(c))
+  {
+    i += 1;
+  }
+  while b.get(i).is_some_and(|c| b"hlLqjzt".contains(c)) {
+    i += 1;
+  }
+  b.get(i)
+    .filter(|c| b"diouxXeEfFgGaAcspn@".contains(c))
+    .map(|_| i + 1)
+}
@@ -0,0 +1,163 @@
+//! Plain text read and rebuilt in the shell. Segments are paragraphs, runs
+//! of lines between blank ones; the blank lines and the indentation before
+//! a paragraph are written back as they were, and line breaks inside one
+//! are the translation's.
+//!
+//! The file is read in its encoding (see `charset`) and written as UTF-8,
+//! with a byte order mark when the source had one or was in another
+//! encoding, so Notepad and the like do not take it for the old code page.
+//!
+//! Locators are `txt:<start>-<end>`, the paragraph's byte range in the text
+//! as UTF-8. Large files are segmented a line at a time by [`stream`], to
+//! the same locators.
+
+use encoding_rs::UTF_8;
+use std::{fs, io::BufRead, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  charset,
+  proxy::ProxyError,
+  segments::{self, Segment},
+};
+
+/// The ranges of the paragraphs of `text`, without surrounding whitespace.
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+  let mut out = Vec::new();
+  let mut current: Option<Range<usize>> = None;
+  let mut at = 0;
+  for line in text.split_inclusive('\n') {
+    let content = line.trim_end_matches(['\n', '\r']);
+    if content.trim().is_empty() {
+      out.extend(current.take());
+    } else {
+      let start = at + (content.len() - content.trim_start().len());
+      let end = at + content.trim_end().len();
+      match &mut current {
+        Some(range) => range.end = end,
+        None => current = Some(start..end),
+      }
+    }
+    at += line.len();
+  }
+  out.extend(current);
+  out
+}
+
+/// The paragraph at `range` of the text, of which `text` is the part from
+/// `from` on; `None` if it has no words.
+fn segment(text: &str, from: usize, range: Range<usize>) -> Option<Segment> {
+  let paragraph = &text[range.start - from..range.end - from];
+  paragraph.chars().any(char::is_alphabetic).then(|| Segment {
+    locator: format!("txt:{}-{}", range.start, range.end),
+    text: paragraph.replace("\r\n", "\n"),
+  })
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let text = charset::decode(&data).text;
+  Ok(
+    paragraphs(&text)
+      .into_iter()
+      .filter_map(|range| segment(&text, 0, range))
+      .collect(),
+  )
+}
+
+/// [`segments`] of the text `reader` gives, handed to `emit` as each
+/// paragraph ends; only the paragraph being read is held.
+pub fn stream(
+  reader: &mut dyn BufRead,
+  emit: &mut dyn FnMut(Segment) -> Result<(), String>,
+) -> Result<(), String> {
+  let mut line = String::new();
+  let mut at = 0;
+  // the lines of the paragraph being read, which start at `from`
+  let mut lines = String::new();
+  let mut from = 0;
+  let mut current: Option<Range<usize>> = None;
+  loop {
+    line.clear();
+    if reader
+      .read_line(&mut line)
+      .map_err(|e| format!("Cannot read the text: {e}"))?
+      == 0
+    {
+      break;
+    }
+    let content = line.trim_end_matches(['\n', '\r']);
+    if content.trim().is_empty() {
+      if let Some(segment) = current
+        .take()
+        .and_then(|range| segment(&lines, from, range))
+      {
+        emit(segment)?;
+      }
+    } else {
+      let start = at + (content.len() - content.trim_start().len());
+      let end = at + content.trim_end().len();
+      match &mut current {
+        Some(range) => range.end = end,
+        None => {
+          current = Some(start..end);
+          lines.clear();
+          from = at;
+        }
+      }
+      lines.push_str(&line);
+    }
+    at += line.len();
+  }
+  match current.and_then(|range| segment(&lines, from, range)) {
+    Some(segment) => emit(segment),
+    None => Ok(()),
+  }
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let decoded = charset::decode(&task.source);
+  let text = decoded.text.as_str();
+  let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+  let mut out = String::with_capacity(text.len());
+  if decoded.bom || decoded.encoding != UTF_8 {
+    out.push('\u{feff}');
+  }
+  let mut at = 0;
+  for range in paragraphs(text) {
+    let locator = format!("txt:{}-{}", range.start, range.end);
+    let Some(translation) = translations.get(locator.as_str()) else {
+      continue;
+    };
+    out.push_str(&text[at..range.start]);
+    out.push_str(
+      &translation
+        .trim()
+        .replace("\r\n", "\n")
+        .replace('\n', newline),
+    );
+    at = range.end;
+  }
+  out.push_str(&text[at..]);
+  fs::write(path, out).map_err(|e| {
+    ProxyError::new(
+      "invalid-request",
+      format!("Cannot write {}: {e}", path.display()),
+    )
+  })
+}
+
+/// Writes the translated copy of text task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_txt(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
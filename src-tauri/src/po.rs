@@ -0,0 +1,427 @@
+//! gettext catalogs (PO, and POT templates) read and rebuilt in the shell.
+//! Segments are the `msgid`s (and `msgid_plural`s) of entries without a
+//! translation; fuzzy entries count as untranslated, and with the
+//! `po_retranslate` setting every entry is sent. The header and obsolete
+//! (`#~`) entries never are.
+//!
+//! The translated copy is the source with the `msgstr` lines of translated
+//! entries rewritten, so comments, references, flags, contexts and
+//! layout elsewhere stay as they were. A machine translation is for a
+//! translator to review, so those entries get the `fuzzy` flag. Plural
+//! entries fill every `msgstr[n]`: the first from the singular, the rest
+//! (or the only one, for languages with one form) from the plural. A
+//! template's header gets the target `Language`, its `Plural-Forms` and
+//! the UTF-8 charset when they are still blank.
+//!
+//! printf and brace placeholders (`%s`, `%(name)d`, `{0}`, `%%`) go to the
+//! translator as `⟦1⟧`; an entry that loses one stays untranslated and is
+//! reported. Locators are `po:<entry>` and `po:<entry>:plural`, entries
+//! (the header included) counted from 0.
+
+use std::{fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{formats, unlock, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+struct Entry {
+  /// The `#,` line, if any.
+  flags: Option<usize>,
+  /// Where a new `#,` line goes: after the translator, extracted and
+  /// reference comments.
+  flags_at: usize,
+  fuzzy: bool,
+  msgctxt: Option<String>,
+  msgid: String,
+  msgid_plural: Option<String>,
+  /// `msgstr`, or `msgstr[0]`, `msgstr[1]`… for plurals.
+  msgstr: Vec<String>,
+  /// The lines of the `msgstr` fields, the last of the entry.
+  msgstr_lines: Range<usize>,
+}
+
+impl Entry {
+  fn is_header(&self) -> bool {
+    self.msgid.is_empty() && self.msgctxt.is_none()
+  }
+}
+
+struct Catalog<'a> {
+  lines: Vec<&'a str>,
+  crlf: bool,
+  entries: Vec<Entry>,
+}
+
+fn unquote(line: &str) -> Option<String> {
+  let inner = line.trim().strip_prefix('"')?.strip_suffix('"')?;
+  let mut out = String::with_capacity(inner.len());
+  let mut chars = inner.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next()? {
+      'n' => out.push('\n'),
+      't' => out.push('\t'),
+      'r' => out.push('\r'),
+      'a' => out.push('\u{7}'),
+      'b' => out.push('\u{8}'),
+      'f' => out.push('\u{c}'),
+      'v' => out.push('\u{b}'),
+      other => out.push(other),
+    }
+  }
+  Some(out)
+}
+
+fn quote(text: &str) -> String {
+  let mut out = String::with_capacity(text.len() + 2);
+  out.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\t' => out.push_str("\\t"),
+      '\r' => out.push_str("\\r"),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  out
+}
+
+/// `keyword value` as gettext writes it: text with line breaks inside it
+/// goes on lines of its own after an empty string.
+fn field(keyword: &str, value: &str) -> Vec<String> {
+  let body = value.strip_suffix('\n').unwrap_or(value);
+  if !body.contains('\n') {
+    return vec![format!("{keyword} {}", quote(value))];
+  }
+  let mut out = vec![format!("{keyword} \"\"")];
+  out.extend(value.split_inclusive('\n').map(quote));
+  out
+}
+
+fn parse(data: &[u8]) -> Result<Catalog<'_>, String> {
+  let text = std::str::from_utf8(data)
+    .map_err(|_| "PO files must be UTF-8".to_string())?
+    .trim_start_matches('\u{feff}');
+  let crlf = text.contains("\r\n");
+  let lines: Vec<&str> = text.lines().collect();
+  let mut entries = Vec::new();
+  let mut start = 0;
+  while start < lines.len() {
+    if lines[start].trim().is_empty() {
+      start += 1;
+      continue;
+    }
+    let end = lines[start..]
+      .iter()
+      .position(|l| l.trim().is_empty())
+      .map_or(lines.len(), |n| start + n);
+    if let Some(entry) = parse_entry(&lines, start..end)? {
+      entries.push(entry);
+    }
+    start = end;
+  }
+  Ok(Catalog {
+    lines,
+    crlf,
+    entries,
+  })
+}
+
+/// A field continuation strings add to.
+#[derive(Clone, Copy)]
+enum Field {
+  Context,
+  Id,
+  Plural,
+  /// The last `msgstr`.
+  Translation,
+}
+
+/// The entry in `lines[range]`, or `None` for comments and obsolete
+/// entries.
+fn parse_entry(lines: &[&str], range: Range<usize>) -> Result<Option<Entry>, String> {
+  let invalid = |n: usize| format!("Invalid PO file: line {}: {}", n + 1, lines[n].trim());
+  let mut entry = Entry {
+    flags: None,
+    flags_at: range.start,
+    fuzzy: false,
+    msgctxt: None,
+    msgid: String::new(),
+    msgid_plural: None,
+    msgstr: Vec::new(),
+    msgstr_lines: range.end..range.end,
+  };
+  let mut has_msgid = false;
+  let mut current = None;
+  for n in range.clone() {
+    let line = lines[n].trim();
+    if let Some(comment) = line.strip_prefix('#') {
+      current = None;
+      if let Some(flags) = comment.strip_prefix(',') {
+        entry.flags = Some(n);
+        entry.fuzzy = flags.split(',').any(|f| f.trim() == "fuzzy");
+      }
+      if entry.flags.is_none() && !comment.starts_with(['|', '~']) && !has_msgid {
+        entry.flags_at = n + 1;
+      }
+      continue;
+    }
+    let (field, value) = match line.strip_prefix('"') {
+      Some(_) => (current.ok_or_else(|| invalid(n))?, line),
+      None => {
+        let (keyword, value) = line
+          .split_once(char::is_whitespace)
+          .ok_or_else(|| invalid(n))?;
+        let keyword = keyword.split('[').next().unwrap_or_default();
+        let field = match keyword {
+          "msgctxt" => Field::Context,
+          "msgid" => Field::Id,
+          "msgid_plural" => Field::Plural,
+          "msgstr" => Field::Translation,
+          _ => return Err(invalid(n)),
+        };
+        match field {
+          Field::Translation => {
+            if entry.msgstr.is_empty() {
+              entry.msgstr_lines.start = n;
+            }
+            entry.msgstr.push(String::new());
+          }
+          // the translations come last
+          _ if !entry.msgstr.is_empty() => return Err(invalid(n)),
+          Field::Id => has_msgid = true,
+          _ => {}
+        }
+        (field, value)
+      }
+    };
+    let text = unquote(value).ok_or_else(|| invalid(n))?;
+    let target = match field {
+      Field::Context => entry.msgctxt.get_or_insert_with(String::new),
+      Field::Id => &mut entry.msgid,
+      Field::Plural => entry.msgid_plural.get_or_insert_with(String::new),
+      Field::Translation => entry.msgstr.last_mut().ok_or_else(|| invalid(n))?,
+    };
+    target.push_str(&text);
+    current = Some(field);
+  }
+  Ok(has_msgid.then_some(entry))
+}
+
+/// Whether `entry` is sent for translation.
+fn wanted(entry: &Entry, retranslate: bool) -> bool {
+  !entry.is_header() && (retranslate || entry.fuzzy || entry.msgstr.iter().all(String::is_empty))
+}
+
+/// Each sent text of `entry` with its locator suffix.
+fn texts(entry: &Entry) -> Vec<(&'static str, &str)> {
+  let mut out = vec![("", entry.msgid.as_str())];
+  out.extend(entry.msgid_plural.as_deref().map(|p| (":plural", p)));
+  out
+}
+
+pub fn segments(path: &Path, retranslate: bool) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let catalog = parse(&data)?;
+  let mut out = Vec::new();
+  for (index, entry) in catalog.entries.iter().enumerate() {
+    if !wanted(entry, retranslate) {
+      continue;
+    }
+    for (suffix, text) in texts(entry) {
+      let (text, _) = formats(text);
+      // numbers, placeholders alone and other messages without words
+      if text.chars().any(char::is_alphabetic) {
+        out.push(Segment {
+          locator: format!("po:{index}{suffix}"),
+          text,
+        });
+      }
+    }
+  }
+  Ok(out)
+}
+
+/// `translation` with `source`'s placeholders back, and its leading and
+/// trailing line breaks, which `msgfmt -c` checks.
+fn restore(source: &str, translation: &str) -> Option<String> {
+  let out = unlock(source, translation)?;
+  let body = out.trim_matches('\n');
+  let lead = if source.starts_with('\n') { "\n" } else { "" };
+  let tail = if source.ends_with('\n') { "\n" } else { "" };
+  Some(format!("{lead}{body}{tail}"))
+}
+
+/// The target language's header values, from the task's direction.
+fn language(direction: &str) -> (&'static str, &'static str) {
+  match direction {
+    "zh->en" => ("en", "nplurals=2; plural=(n != 1);"),
+    _ => ("zh_CN", "nplurals=1; plural=0;"),
+  }
+}
+
+/// Header `msgstr` with blank template fields filled in for `direction`.
+fn fill_header(header: &str, direction: &str) -> String {
+  let (language, plural_forms) = language(direction);
+  let mut out = String::new();
+  let mut has_plural_forms = false;
+  for line in header.split_inclusive('\n') {
+    let (key, value) = line.split_once(':').unwrap_or((line, ""));
+    let value = value.trim();
+    let filled = match key.trim() {
+      "Language" if value.is_empty() => format!("Language: {language}\n"),
+      "Plural-Forms" => {
+        has_plural_forms = true;
+        if value.contains("INTEGER") {
+          format!("Plural-Forms: {plural_forms}\n")
+        } else {
+          line.to_string()
+        }
+      }
+      "Content-Type" if value.contains("CHARSET") => line.replace("CHARSET", "UTF-8"),
+      _ => line.to_string(),
+    };
+    out.push_str(&filled);
+  }
+  if !has_plural_forms {
+    if !out.is_empty() && !out.ends_with('\n') {
+      out.push('\n');
+    }
+    out.push_str(&format!("Plural-Forms: {plural_forms}\n"));
+  }
+  out
+}
+
+/// `nplurals` of a header, or 2 if it has none.
+fn plurals(header: &str) -> usize {
+  header
+    .split("nplurals=")
+    .nth(1)
+    .and_then(|rest| rest.split(';').next())
+    .and_then(|n| n.trim().parse().ok())
+    .filter(|&n| n > 0)
+    .unwrap_or(2)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let direction = segments::info(app, job_id)?.direction;
+  let translations = task.translations();
+  let catalog = parse(&task.source).map_err(invalid)?;
+
+  let header = catalog.entries.iter().find(|e| e.is_header());
+  let header_msgstr = header.map(|h| fill_header(&h.msgstr.concat(), &direction));
+  let nplurals = header_msgstr.as_deref().map_or(2, plurals);
+
+  let mut warnings = Vec::new();
+  // replaced line ranges, and lines to add before a line
+  let mut replaced: Vec<(Range<usize>, Vec<String>)> = Vec::new();
+  for (index, entry) in catalog.entries.iter().enumerate() {
+    if entry.is_header() {
+      match &header_msgstr {
+        Some(msgstr) if *msgstr != entry.msgstr.concat() => {
+          replaced.push((entry.msgstr_lines.clone(), field("msgstr", msgstr)))
+        }
+        _ => {}
+      }
+      continue;
+    }
+    let mut restored = Vec::new();
+    for (suffix, text) in texts(entry) {
+      let sent = formats(text).0;
+      let translation = translations
+        .get(format!("po:{index}{suffix}").as_str())
+        // not translated yet
+        .filter(|t| **t != sent);
+      let Some(translation) = translation else {
+        continue;
+      };
+      match restore(text, translation) {
+        Some(restored_text) => restored.push((suffix, restored_text)),
+        None => warnings.push(Warning {
+          excerpt: text.chars().take(40).collect(),
+          message: "placeholders did not survive translation; left untranslated".to_string(),
+        }),
+      }
+    }
+    let singular = restored.iter().find(|(s, _)| s.is_empty());
+    let Some((_, singular)) = singular else {
+      continue;
+    };
+    let lines = match &entry.msgid_plural {
+      None => field("msgstr", singular),
+      Some(_) => {
+        let plural = restored
+          .iter()
+          .find(|(s, _)| !s.is_empty())
+          .map_or(singular, |(_, p)| p);
+        // a language with one form uses it for any number
+        (0..nplurals)
+          .flat_map(|n| {
+            field(
+              &format!("msgstr[{n}]"),
+              if n == 0 && nplurals > 1 {
+                singular
+              } else {
+                plural
+              },
+            )
+          })
+          .collect()
+      }
+    };
+    if !entry.fuzzy {
+      match entry.flags {
+        Some(n) => {
+          let flags = catalog.lines[n].trim_start()[2..].trim();
+          let line = match flags {
+            "" => "#, fuzzy".to_string(),
+            flags => format!("#, fuzzy, {flags}"),
+          };
+          replaced.push((n..n + 1, vec![line]));
+        }
+        None => replaced.push((entry.flags_at..entry.flags_at, vec!["#, fuzzy".to_string()])),
+      }
+    }
+    replaced.push((entry.msgstr_lines.clone(), lines));
+  }
+  replaced.sort_by_key(|(range, _)| (range.start, range.end));
+
+  let newline = if catalog.crlf { "\r\n" } else { "\n" };
+  let mut out = String::with_capacity(task.source.len());
+  let mut at = 0;
+  let mut push = |line: &str| {
+    out.push_str(line);
+    out.push_str(newline);
+  };
+  for (range, lines) in &replaced {
+    catalog.lines[at..range.start].iter().for_each(|l| push(l));
+    lines.iter().for_each(|l| push(l));
+    at = range.end;
+  }
+  catalog.lines[at..].iter().for_each(|l| push(l));
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// Writes the translated catalog of PO task `job_id` to `path`. Returns the
+/// messages left untranslated for losing a placeholder.
+#[tauri::command]
+pub async fn export_translated_po(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,182 @@
+use std::{
+  fs,
+  path::PathBuf,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+  time::Duration,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  backend::{self, Progress, Running},
+  config::StartupConfig,
+  os, priority,
+  status::BackendState,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Extra backend instances next to the primary one. They share the data
+/// dir (and therefore the task db), so any instance can run any task.
+#[derive(Default)]
+pub struct WorkerPool {
+  workers: Mutex<Vec<Worker>>,
+  next: AtomicUsize,
+}
+
+struct Worker {
+  index: u32,
+  running: Running,
+  port_file: PathBuf,
+  base_url: String,
+}
+
+fn launch_worker(app: &AppHandle, index: u32) -> Result<(), Box<dyn std::error::Error>> {
+  let port_file = backend::new_port_file(app)?;
+  let listen = backend::listen_addr(app, index)?;
+  let running = backend::spawn_process(
+    app,
+    &listen,
+    &port_file,
+    &format!("worker{index}:"),
+    Box::new(move |app, pid, _| on_worker_exit(app, index, pid)),
+  )?;
+
+  let progress = Progress::new(app, false);
+  let base_url = match backend::wait_for_backend(progress, &running, &port_file)
+    .and_then(|url| backend::check_version(progress, &url).map(|_| url))
+  {
+    Ok(url) => url,
+    Err(e) => {
+      backend::terminate_child(running, None, Duration::ZERO);
+      let _ = fs::remove_file(&port_file);
+      return Err(e);
+    }
+  };
+
+  let worker = Worker {
+    index,
+    running,
+    port_file,
+    base_url,
+  };
+  if backend::is_stopping(app) {
+    stop_worker(app, worker);
+    return Ok(());
+  }
+  app
+    .state::<WorkerPool>()
+    .workers
+    .lock()
+    .unwrap()
+    .push(worker);
+  priority::on_launch(app);
+  Ok(())
+}
+
+/// Keeps trying to bring worker `index` up, backing off between attempts.
+fn keep_worker_up(app: AppHandle, index: u32, initial_delay: Duration) {
+  std::thread::spawn(move || {
+    let mut backoff = initial_delay;
+    loop {
+      std::thread::sleep(backoff);
+      if backend::is_stopping(&app) {
+        return;
+      }
+      match launch_worker(&app, index) {
+        Ok(()) => return,
+        Err(_) => backoff = (backoff * 2).max(Duration::from_secs(1)).min(MAX_BACKOFF),
+      }
+    }
+  });
+}
+
+fn on_worker_exit(app: &AppHandle, index: u32, pid: u32) {
+  let pool = app.state::<WorkerPool>();
+  let removed = {
+    let mut workers = pool.workers.lock().unwrap();
+    workers
+      .iter()
+      .position(|w| w.running.pid() == pid)
+      .map(|i| workers.remove(i))
+  };
+  // not in the pool: deliberately stopped or died during its handshake
+  let Some(worker) = removed else {
+    return;
+  };
+  let _ = fs::remove_file(&worker.port_file);
+  if !backend::is_stopping(app) {
+    keep_worker_up(app.clone(), index, Duration::from_secs(1));
+  }
+}
+
+fn stop_worker(app: &AppHandle, worker: Worker) {
+  let grace = app.state::<StartupConfig>().shutdown_grace();
+  backend::terminate_child(worker.running, Some(&worker.base_url), grace);
+  let _ = fs::remove_file(&worker.port_file);
+}
+
+/// Starts `backend_workers - 1` extra instances (the primary counts as one).
+/// Does nothing when using an external backend.
+pub fn start(app: &AppHandle) {
+  let cfg = app.state::<StartupConfig>();
+  if cfg.backend_url.is_some() {
+    return;
+  }
+  for index in 1..cfg.backend_workers.max(1) {
+    keep_worker_up(app.clone(), index, Duration::ZERO);
+  }
+}
+
+pub fn stop_all(app: &AppHandle) {
+  let workers: Vec<Worker> = app
+    .state::<WorkerPool>()
+    .workers
+    .lock()
+    .unwrap()
+    .drain(..)
+    .collect();
+  for worker in workers {
+    stop_worker(app, worker);
+  }
+}
+
+/// Last-resort teardown for [`crate::cleanup`]: kills every worker tree
+/// without waiting and removes their port files. Skips the pool if its
+/// lock is held, rather than risk deadlocking a panicking thread.
+pub fn emergency_stop(app: &AppHandle) {
+  let pool = app.state::<WorkerPool>();
+  let Ok(workers) = pool.workers.try_lock() else {
+    return;
+  };
+  for worker in workers.iter() {
+    os::kill_tree(worker.running.pid());
+    let _ = fs::remove_file(&worker.port_file);
+  }
+}
+
+pub fn pids(app: &AppHandle) -> Vec<u32> {
+  let pool = app.state::<WorkerPool>();
+  let workers = pool.workers.lock().unwrap();
+  workers.iter().map(|w| w.running.pid()).collect()
+}
+
+/// Round-robins over the primary backend and all ready workers. The
+/// frontend asks for a URL per translation run to spread the load.
+#[tauri::command]
+pub fn pick_backend_url(app: AppHandle) -> Option<String> {
+  let mut urls: Vec<String> = app.state::<BackendState>().base_url().into_iter().collect();
+  let pool = app.state::<WorkerPool>();
+  {
+    let mut workers = pool.workers.lock().unwrap();
+    workers.sort_by_key(|w| w.index);
+    urls.extend(workers.iter().map(|w| w.base_url.clone()));
+  }
+  if urls.is_empty() {
+    return None;
+  }
+  let i = pool.next.fetch_add(1, Ordering::Relaxed) % urls.len();
+  Some(urls.swap_remove(i))
+}
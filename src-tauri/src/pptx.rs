@@ -0,0 +1,157 @@
+//! PowerPoint decks read and rebuilt in the shell, like `docx`. Segments
+//! are the paragraphs of each slide's shapes, group shapes and tables,
+//! followed by its speaker notes, slide by slide in presentation order.
+//! Only slide and notes parts are rewritten, so layouts, masters, themes
+//! and media stay as they were.
+//!
+//! Locators are `pptx:<part>#<paragraph index>`, e.g.
+//! `pptx:ppt/slides/slide3.xml#4`.
+
+use quick_xml::{events::Event, Reader};
+use serde::Serialize;
+use std::{collections::HashMap, io::Cursor, path::Path};
+use tauri::{AppHandle, Emitter};
+use zip::ZipArchive;
+
+use crate::{
+  ooxml::{self, Markup},
+  protected,
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+const DRAWING: Markup = Markup {
+  paragraph: b"a:p",
+  run: b"a:r",
+  run_properties: b"a:rPr",
+  text: b"a:t",
+  line_break: b"a:br",
+  break_in_run: false,
+  tab: None,
+  preserve_space: false,
+  revisions: None,
+};
+const PRESENTATION: &str = "ppt/presentation.xml";
+
+/// Payload of `pptx-export-progress`; `done` of `total` slides written.
+#[derive(Clone, Serialize)]
+struct Progress<'a> {
+  job_id: &'a str,
+  done: usize,
+  total: usize,
+}
+
+/// Each slide's part with its notes part, if any, in presentation order.
+fn slides(
+  archive: &mut ZipArchive<Cursor<&[u8]>>,
+) -> Result<Vec<(String, Option<String>)>, String> {
+  let targets: HashMap<String, String> = ooxml::relationships(archive, PRESENTATION)?
+    .into_iter()
+    .collect();
+  let xml = ooxml::read_part(archive, PRESENTATION)?;
+  let mut reader = Reader::from_reader(xml.as_slice());
+  let mut ids = Vec::new();
+  loop {
+    match reader
+      .read_event()
+      .map_err(|e| format!("Invalid presentation: {e}"))?
+    {
+      Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"p:sldId" => {
+        if let Ok(Some(id)) = e.try_get_attribute("r:id") {
+          ids.push(String::from_utf8_lossy(&id.value).into_owned());
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  let mut out = Vec::new();
+  for id in ids {
+    let Some(slide) = targets.get(&id) else {
+      continue;
+    };
+    let notes = ooxml::relationships(archive, slide)?
+      .into_iter()
+      .map(|(_, target)| target)
+      .find(|target| target.starts_with("ppt/notesSlides/"));
+    out.push((slide.clone(), notes));
+  }
+  Ok(out)
+}
+
+fn parse_locator(locator: &str) -> Option<(&str, usize)> {
+  let (part, index) = locator.strip_prefix("pptx:")?.rsplit_once('#')?;
+  Some((part, index.parse().ok()?))
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = protected::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut archive = ooxml::open(&data)?;
+  let mut out = Vec::new();
+  for (slide, notes) in slides(&mut archive)? {
+    for part in std::iter::once(slide).chain(notes) {
+      let xml = ooxml::read_part(&mut archive, &part)?;
+      for (index, text) in ooxml::paragraphs(&xml, &DRAWING)? {
+        // slide numbers and other fields without words
+        if !text.chars().any(char::is_alphabetic) {
+          continue;
+        }
+        out.push(Segment {
+          locator: format!("pptx:{part}#{index}"),
+          text: text.trim().to_string(),
+        });
+      }
+    }
+  }
+  Ok(out)
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let mut by_part: HashMap<&str, HashMap<usize, String>> = HashMap::new();
+  for (locator, text) in &blocks {
+    if let Some((part, index)) = parse_locator(locator) {
+      by_part.entry(part).or_default().insert(index, text.clone());
+    }
+  }
+  let mut archive = ooxml::open(&source).map_err(invalid)?;
+  let slides = slides(&mut archive).map_err(invalid)?;
+  let total = slides.len();
+  let progress = |done| {
+    let _ = app.emit(
+      "pptx-export-progress",
+      Progress {
+        job_id,
+        done,
+        total,
+      },
+    );
+  };
+  progress(0);
+  let mut replaced = HashMap::new();
+  for (done, (slide, notes)) in slides.into_iter().enumerate() {
+    for part in std::iter::once(slide).chain(notes) {
+      let Some(texts) = by_part.get(part.as_str()) else {
+        continue;
+      };
+      let xml = ooxml::read_part(&mut archive, &part).map_err(invalid)?;
+      let rewritten = ooxml::replace(&xml, &DRAWING, texts).map_err(invalid)?;
+      replaced.insert(part, rewritten);
+    }
+    progress(done + 1);
+  }
+  ooxml::rewrite(&source, path, &replaced).map_err(invalid)
+}
+
+/// Writes the translated copy of PowerPoint task `job_id` to `path`,
+/// reporting `pptx-export-progress` events as slides are done.
+#[tauri::command]
+pub async fn export_translated_pptx(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
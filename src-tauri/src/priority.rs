@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{backend, logs::BackendLog, os, pool};
+
+/// Whether backends run at background (below-normal CPU, low I/O) priority.
+pub struct PriorityState(AtomicBool);
+
+impl PriorityState {
+  pub fn new(background: bool) -> Self {
+    Self(AtomicBool::new(background))
+  }
+}
+
+/// All backend pids plus their descendants.
+fn backend_process_tree(app: &AppHandle) -> Vec<u32> {
+  let mut roots: Vec<u32> = backend::current_pid(app).into_iter().collect();
+  roots.extend(pool::pids(app));
+  os::process_tree(&roots)
+}
+
+/// Called after each successful launch, once the interpreter process
+/// exists. Fresh processes already run at normal priority.
+pub fn on_launch(app: &AppHandle) {
+  if !app.state::<PriorityState>().0.load(Ordering::SeqCst) {
+    return;
+  }
+  if let Err(e) = apply(app) {
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot set backend priority: {e}").as_bytes(),
+    );
+  }
+}
+
+/// Applies the current priority to every backend process.
+fn apply(app: &AppHandle) -> Result<(), String> {
+  let background = app.state::<PriorityState>().0.load(Ordering::SeqCst);
+  let mut errors = Vec::new();
+  for pid in backend_process_tree(app) {
+    if let Err(e) = os::set_background_priority(pid, background) {
+      errors.push(format!("pid {pid}: {e}"));
+    }
+  }
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors.join("; "))
+  }
+}
+
+/// Toggles background priority at runtime. On Unix, going back to normal
+/// priority needs privileges; restart the backend if that fails.
+#[tauri::command]
+pub fn set_backend_priority(
+  app: AppHandle,
+  state: State<PriorityState>,
+  background: bool,
+) -> Result<(), String> {
+  state.0.store(background, Ordering::SeqCst);
+  apply(&app)
+}
@@ -0,0 +1,161 @@
+//! Per-client profiles: named bundles of provider (`base_url`, `model`),
+//! language pair (`direction`), glossary and prompt template, kept in the
+//! backend's database. Activating one switches the backend's provider
+//! settings and the default direction, and emits `active-profile-changed`
+//! with the profile (or `null`).
+
+use serde_json::{json, Value};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+  proxy::{self, ProxyError},
+  settings::SettingsState,
+  transport,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A JSON request to the backend's profile (or translation memory) API.
+pub fn call(
+  app: &AppHandle,
+  method: &str,
+  path: &str,
+  body: Option<Value>,
+) -> Result<Value, ProxyError> {
+  let base = proxy::target_base(app, false)
+    .ok_or_else(|| ProxyError::new("not-ready", "backend is not running"))?;
+  let (headers, body) = match body {
+    Some(body) => (
+      vec![("content-type".to_string(), "application/json".to_string())],
+      serde_json::to_vec(&body).unwrap_or_default(),
+    ),
+    None => (Vec::new(), Vec::new()),
+  };
+  let resp = transport::request(method, &format!("{base}{path}"), &headers, body, TIMEOUT)
+    .map_err(|e| ProxyError::from_io(&e))?;
+  if !resp.is_success() {
+    return Err(ProxyError::from_response(&resp));
+  }
+  serde_json::from_slice(&resp.body).map_err(|e| ProxyError::new("http", e.to_string()))
+}
+
+pub fn check_id(id: &str) -> Result<(), ProxyError> {
+  if id.starts_with("prof_") && id[5..].chars().all(|c| c.is_ascii_alphanumeric()) {
+    Ok(())
+  } else {
+    Err(ProxyError::new(
+      "invalid-request",
+      format!("Invalid profile id: {id}"),
+    ))
+  }
+}
+
+fn announce(app: &AppHandle, profile: &Value) {
+  if let Some(direction) = profile["direction"].as_str() {
+    let _ = app
+      .state::<SettingsState>()
+      .update(app, json!({ "direction": direction }));
+  }
+  let _ = app.emit("active-profile-changed", profile);
+}
+
+/// Also re-applies an already active profile after it was edited.
+pub fn activate(app: &AppHandle, id: Option<&str>) -> Result<Value, ProxyError> {
+  let profile = call(
+    app,
+    "PUT",
+    "/api/profiles/active",
+    Some(json!({ "id": id })),
+  )?["active"]
+    .take();
+  announce(app, &profile);
+  Ok(profile)
+}
+
+async fn blocking<T: Send + 'static>(
+  f: impl FnOnce() -> Result<T, ProxyError> + Send + 'static,
+) -> Result<T, ProxyError> {
+  tauri::async_runtime::spawn_blocking(f)
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// `{active, profiles}`, sorted by name.
+#[tauri::command]
+pub async fn list_profiles(app: AppHandle) -> Result<Value, ProxyError> {
+  blocking(move || call(&app, "GET", "/api/profiles", None)).await
+}
+
+/// Creates a profile from `profile` (`name` required; `base_url`, `model`,
+/// `direction`, `glossary` as `[{source, target}]`, `prompt_template`
+/// with `{target}` and `{text}` placeholders).
+#[tauri::command]
+pub async fn create_profile(app: AppHandle, profile: Value) -> Result<Value, ProxyError> {
+  blocking(move || call(&app, "POST", "/api/profiles", Some(profile))).await
+}
+
+/// Changes the fields present in `profile`.
+#[tauri::command]
+pub async fn update_profile(
+  app: AppHandle,
+  id: String,
+  profile: Value,
+) -> Result<Value, ProxyError> {
+  blocking(move || {
+    check_id(&id)?;
+    let updated = call(&app, "PATCH", &format!("/api/profiles/{id}"), Some(profile))?;
+    let active = call(&app, "GET", "/api/profiles", None)?;
+    if active["active"].as_str() == Some(id.as_str()) {
+      activate(&app, Some(&id))?;
+    }
+    Ok(updated)
+  })
+  .await
+}
+
+/// Copies a profile under `name` (default: "<name> (copy)").
+#[tauri::command]
+pub async fn clone_profile(
+  app: AppHandle,
+  id: String,
+  name: Option<String>,
+) -> Result<Value, ProxyError> {
+  blocking(move || {
+    check_id(&id)?;
+    call(
+      &app,
+      "POST",
+      &format!("/api/profiles/{id}/clone"),
+      Some(json!({ "name": name })),
+    )
+  })
+  .await
+}
+
+#[tauri::command]
+pub async fn delete_profile(app: AppHandle, id: String) -> Result<(), ProxyError> {
+  blocking(move || {
+    check_id(&id)?;
+    let was_active =
+      call(&app, "GET", "/api/profiles", None)?["active"].as_str() == Some(id.as_str());
+    call(&app, "DELETE", &format!("/api/profiles/{id}"), None)?;
+    if was_active {
+      announce(&app, &Value::Null);
+    }
+    Ok(())
+  })
+  .await
+}
+
+/// Makes `id` the active profile, or none with `null`. Returns it.
+#[tauri::command]
+pub async fn activate_profile(app: AppHandle, id: Option<String>) -> Result<Value, ProxyError> {
+  blocking(move || {
+    if let Some(id) = &id {
+      check_id(id)?;
+    }
+    activate(&app, id.as_deref())
+  })
+  .await
+}
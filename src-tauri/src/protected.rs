@@ -0,0 +1,454 @@
+//! Password-protected documents: PDFs with a user password, and Office
+//! files saved with a password, which are OLE compound files around the
+//! encrypted package (ECMA-376 agile encryption, Office 2010 and later).
+//!
+//! Intake asks for the password with a `password-required` event and
+//! waits for `provide_document_password`; a wrong one asks again, with
+//! `retry` set. The document is decrypted in memory for its parser only
+//! (see [`read`]): the task gets the file as it is on disk, so no
+//! plaintext copy of it is ever written. The password is kept in memory
+//! under the task id, for exports to decrypt the task's source again;
+//! after a restart they ask for it the same way.
+
+use aes::cipher::{block_padding::NoPadding, BlockDecryptMut, KeyIvInit};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use quick_xml::{events::Event, Reader};
+use serde::Serialize;
+use sha2::Digest;
+use std::{
+  collections::HashMap,
+  fs,
+  io::{Cursor, Read},
+  path::{Path, PathBuf},
+  sync::{mpsc, Arc, Mutex},
+  time::Duration,
+};
+use tauri::{AppHandle, Emitter};
+
+use crate::proxy::ProxyError;
+
+/// How long intake waits for a password before giving up.
+const WAIT: Duration = Duration::from_secs(300);
+const CFB_SIGNATURE: &[u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+/// Bytes the encrypted package is encrypted in, each with its own IV.
+const SEGMENT: usize = 4096;
+/// Block keys of the agile key derivation, for the verifier input, its
+/// hash and the package key.
+const VERIFIER_INPUT_BLOCK: [u8; 8] = [0xfe, 0xa7, 0xd2, 0x76, 0x3b, 0x4b, 0x9e, 0x79];
+const VERIFIER_HASH_BLOCK: [u8; 8] = [0xd7, 0xaa, 0x0f, 0x6d, 0x30, 0x61, 0x34, 0x4e];
+const KEY_BLOCK: [u8; 8] = [0x14, 0x6e, 0x0b, 0xe7, 0xab, 0xac, 0xd0, 0xd6];
+/// Most hash rounds ECMA-376 allows the password key to take; a document
+/// asking for more would keep a worker busy for nothing.
+const MAX_SPIN_COUNT: usize = 10_000_000;
+
+/// Document id to the intake waiting for its password.
+static WAITING: Mutex<Option<HashMap<String, mpsc::Sender<String>>>> = Mutex::new(None);
+/// Document or task id to its password, for this session.
+static PASSWORDS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+/// Documents decrypted for reading, by path.
+static UNLOCKED: Mutex<Option<HashMap<PathBuf, Arc<Vec<u8>>>>> = Mutex::new(None);
+
+/// Payload of `password-required`.
+#[derive(Clone, Serialize)]
+struct PasswordRequired<'a> {
+  doc_id: &'a str,
+  name: &'a str,
+  /// Whether a password given before was wrong.
+  retry: bool,
+}
+
+enum Failure {
+  WrongPassword,
+  Other(String),
+}
+
+fn other(e: impl std::fmt::Display) -> Failure {
+  Failure::Other(e.to_string())
+}
+
+/// Whether `data` opens only with a password. PDFs encrypted with an empty
+/// user password (only to restrict printing or copying) open without one.
+fn needs_password(data: &[u8]) -> bool {
+  if data.starts_with(CFB_SIGNATURE) {
+    return cfb::CompoundFile::open(Cursor::new(data))
+      .is_ok_and(|file| file.is_stream("/EncryptedPackage"));
+  }
+  let pdf = data[..data.len().min(1024)]
+    .windows(5)
+    .any(|w| w == b"%PDF-");
+  if !pdf || !data.windows(8).any(|w| w == b"/Encrypt") {
+    return false;
+  }
+  pdf_extract::Document::load_mem(data).is_ok_and(|mut doc| {
+    doc.is_encrypted()
+      && matches!(
+        doc.decrypt(""),
+        Err(pdf_extract::Error::Decryption(
+          pdf_extract::encryption::DecryptionError::IncorrectPassword
+        ))
+      )
+  })
+}
+
+fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>, Failure> {
+  if data.starts_with(CFB_SIGNATURE) {
+    return decrypt_office(data, password);
+  }
+  let mut doc = pdf_extract::Document::load_mem(data).map_err(other)?;
+  match doc.decrypt(password) {
+    Err(pdf_extract::Error::Decryption(
+      pdf_extract::encryption::DecryptionError::IncorrectPassword,
+    )) => return Err(Failure::WrongPassword),
+    Err(e) => return Err(other(e)),
+    Ok(()) => {}
+  }
+  let mut out = Vec::with_capacity(data.len());
+  doc.save_to(&mut out).map_err(other)?;
+  Ok(out)
+}
+
+fn hash(algorithm: &str, parts: &[&[u8]]) -> Result<Vec<u8>, Failure> {
+  fn run<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = D::new();
+    for part in parts {
+      hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+  }
+  Ok(match algorithm {
+    "SHA512" => run::<sha2::Sha512>(parts),
+    "SHA384" => run::<sha2::Sha384>(parts),
+    "SHA256" => run::<sha2::Sha256>(parts),
+    "SHA1" => run::<sha1::Sha1>(parts),
+    other => return Err(Failure::Other(format!("Unsupported hash {other}"))),
+  })
+}
+
+/// `bytes` cut or padded with 0x36 to `len`, as keys and IVs are.
+fn sized(mut bytes: Vec<u8>, len: usize) -> Vec<u8> {
+  bytes.resize(len, 0x36);
+  bytes
+}
+
+fn aes_cbc(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, Failure> {
+  fn run<C>(key: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Failure>
+  where
+    cbc::Decryptor<C>: KeyIvInit + BlockDecryptMut,
+    C: aes::cipher::BlockDecrypt + aes::cipher::BlockCipher,
+  {
+    cbc::Decryptor::<C>::new_from_slices(key, iv)
+      .map_err(other)?
+      .decrypt_padded_mut::<NoPadding>(data)
+      .map_err(|_| Failure::Other("The encrypted data is cut short".to_string()))?;
+    Ok(())
+  }
+  let mut out = data.to_vec();
+  match key.len() {
+    16 => run::<aes::Aes128>(key, iv, &mut out)?,
+    24 => run::<aes::Aes192>(key, iv, &mut out)?,
+    32 => run::<aes::Aes256>(key, iv, &mut out)?,
+    n => return Err(Failure::Other(format!("Unsupported key size {n}"))),
+  }
+  Ok(out)
+}
+
+/// The attributes of an `EncryptionInfo` element.
+struct Params(HashMap<String, String>);
+
+impl Params {
+  fn text(&self, key: &str) -> Result<&str, Failure> {
+    self
+      .0
+      .get(key)
+      .map(String::as_str)
+      .ok_or_else(|| Failure::Other(format!("The encryption info has no {key}")))
+  }
+
+  fn number(&self, key: &str) -> Result<usize, Failure> {
+    self.text(key)?.parse().map_err(other)
+  }
+
+  fn bytes(&self, key: &str) -> Result<Vec<u8>, Failure> {
+    STANDARD.decode(self.text(key)?).map_err(other)
+  }
+}
+
+/// The `keyData` and password `encryptedKey` elements of an agile
+/// `EncryptionInfo`.
+fn agile_params(xml: &[u8]) -> Result<(Params, Params), Failure> {
+  let mut reader = Reader::from_reader(xml);
+  let (mut key_data, mut encrypted_key) = (None, None);
+  loop {
+    match reader.read_event().map_err(other)? {
+      Event::Start(e) | Event::Empty(e) => {
+        let slot = match e.local_name().as_ref() {
+          b"keyData" => &mut key_data,
+          b"encryptedKey" => &mut encrypted_key,
+          _ => continue,
+        };
+        let attributes = e
+          .attributes()
+          .flatten()
+          .map(|a| {
+            let key = String::from_utf8_lossy(a.key.local_name().as_ref()).into_owned();
+            (key, String::from_utf8_lossy(&a.value).into_owned())
+          })
+          .collect();
+        slot.get_or_insert(Params(attributes));
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  match (key_data, encrypted_key) {
+    (Some(k), Some(e)) => Ok((k, e)),
+    _ => Err(Failure::Other(
+      "The document is not protected with a password".to_string(),
+    )),
+  }
+}
+
+fn decrypt_office(data: &[u8], password: &str) -> Result<Vec<u8>, Failure> {
+  let mut file = cfb::CompoundFile::open(Cursor::new(data)).map_err(other)?;
+  let mut read = |name: &str| {
+    let mut out = Vec::new();
+    file
+      .open_stream(name)
+      .and_then(|mut s| s.read_to_end(&mut out))
+      .map_err(other)?;
+    Ok::<_, Failure>(out)
+  };
+  let info = read("/EncryptionInfo")?;
+  let package = read("/EncryptedPackage")?;
+  if info.len() < 8 || info[..4] != [4, 0, 4, 0] {
+    return Err(Failure::Other(
+      "Only documents protected by Office 2010 or later can be opened".to_string(),
+    ));
+  }
+  let (key_data, encrypted_key) = agile_params(&info[8..])?;
+
+  // the key the package key is encrypted with, from the password
+  let algorithm = encrypted_key.text("hashAlgorithm")?;
+  let salt = encrypted_key.bytes("saltValue")?;
+  let key_bytes = encrypted_key.number("keyBits")? / 8;
+  let block_size = encrypted_key.number("blockSize")?;
+  let iv = sized(salt.clone(), block_size);
+  let utf16: Vec<u8> = password.encode_utf16().flat_map(u16::to_le_bytes).collect();
+  let spin_count = encrypted_key.number("spinCount")?;
+  if spin_count > MAX_SPIN_COUNT {
+    return Err(Failure::Other(format!(
+      "The document asks for {spin_count} key derivation rounds, over the {MAX_SPIN_COUNT} allowed"
+    )));
+  }
+  let mut h = hash(algorithm, &[&salt, &utf16])?;
+  for i in 0..spin_count as u32 {
+    h = hash(algorithm, &[&i.to_le_bytes(), &h])?;
+  }
+  let key = |block: &[u8]| Ok::<_, Failure>(sized(hash(algorithm, &[&h, block])?, key_bytes));
+
+  let verifier = aes_cbc(
+    &key(&VERIFIER_INPUT_BLOCK)?,
+    &iv,
+    &encrypted_key.bytes("encryptedVerifierHashInput")?,
+  )?;
+  let verifier = &verifier[..verifier.len().min(salt.len())];
+  let verifier_hash = aes_cbc(
+    &key(&VERIFIER_HASH_BLOCK)?,
+    &iv,
+    &encrypted_key.bytes("encryptedVerifierHashValue")?,
+  )?;
+  let expected = hash(algorithm, &[verifier])?;
+  if verifier_hash.get(..expected.len()) != Some(&expected[..]) {
+    return Err(Failure::WrongPassword);
+  }
+  let package_key = aes_cbc(
+    &key(&KEY_BLOCK)?,
+    &iv,
+    &encrypted_key.bytes("encryptedKeyValue")?,
+  )?;
+  let package_key = &package_key[..(key_data.number("keyBits")? / 8).min(package_key.len())];
+
+  let size = package
+    .get(..8)
+    .map(|b| u64::from_le_bytes(b.try_into().unwrap_or_default()) as usize)
+    .ok_or_else(|| Failure::Other("The encrypted package is empty".to_string()))?;
+  let package_algorithm = key_data.text("hashAlgorithm")?;
+  let package_salt = key_data.bytes("saltValue")?;
+  let package_block = key_data.number("blockSize")?;
+  let mut out = Vec::with_capacity(package.len());
+  for (i, segment) in package[8..].chunks(SEGMENT).enumerate() {
+    let iv = hash(
+      package_algorithm,
+      &[&package_salt, &(i as u32).to_le_bytes()],
+    )?;
+    out.extend(aes_cbc(package_key, &sized(iv, package_block), segment)?);
+  }
+  if out.len() < size {
+    return Err(Failure::Other(
+      "The encrypted package is cut short".to_string(),
+    ));
+  }
+  out.truncate(size);
+  Ok(out)
+}
+
+fn lock<T>(
+  map: &Mutex<Option<HashMap<String, T>>>,
+) -> std::sync::MutexGuard<'_, Option<HashMap<String, T>>> {
+  map.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `data` decrypted with the password of `doc_id`: the one given for it
+/// already (used up, callers keep it again under their own id), or one
+/// asked for and waited on.
+fn obtain(
+  app: &AppHandle,
+  doc_id: &str,
+  name: &str,
+  data: &[u8],
+) -> Result<(Vec<u8>, String), ProxyError> {
+  let mut retry = false;
+  loop {
+    let known = lock(&PASSWORDS).as_mut().and_then(|p| p.remove(doc_id));
+    let password = match known {
+      Some(password) if !retry => password,
+      _ => {
+        let (sender, receiver) = mpsc::channel();
+        lock(&WAITING)
+          .get_or_insert_with(HashMap::new)
+          .insert(doc_id.to_string(), sender);
+        let _ = app.emit(
+          "password-required",
+          PasswordRequired {
+            doc_id,
+            name,
+            retry,
+          },
+        );
+        let answer = receiver.recv_timeout(WAIT);
+        lock(&WAITING)
+          .get_or_insert_with(HashMap::new)
+          .remove(doc_id);
+        match answer {
+          Ok(password) if !password.is_empty() => password,
+          _ => {
+            return Err(ProxyError::new(
+              "password-required",
+              format!("{name} is password protected and no password was given"),
+            ))
+          }
+        }
+      }
+    };
+    match decrypt(data, &password) {
+      Ok(plain) => return Ok((plain, password)),
+      Err(Failure::WrongPassword) => retry = true,
+      Err(Failure::Other(e)) => {
+        return Err(ProxyError::new(
+          "invalid-request",
+          format!("Cannot decrypt {name}: {e}"),
+        ))
+      }
+    }
+  }
+}
+
+/// Keeps `password` in memory as the one of `doc_id` (a document or a
+/// task), for this session.
+pub fn remember(doc_id: &str, password: &str) {
+  lock(&PASSWORDS)
+    .get_or_insert_with(HashMap::new)
+    .insert(doc_id.to_string(), password.to_string());
+}
+
+/// A document decrypted for reading; [`read`] gives its plaintext until
+/// this is dropped.
+pub struct Unlocked {
+  path: PathBuf,
+  pub password: String,
+}
+
+impl Drop for Unlocked {
+  fn drop(&mut self) {
+    let mut unlocked = UNLOCKED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(map) = unlocked.as_mut() {
+      map.remove(&self.path);
+    }
+  }
+}
+
+/// Decrypts the document at `path`, read as `extension`, if it is
+/// password protected, asking for the password under `doc_id`. `None` if
+/// it is not.
+pub fn unlock(
+  app: &AppHandle,
+  doc_id: &str,
+  path: &Path,
+  extension: &str,
+) -> Result<Option<Unlocked>, ProxyError> {
+  if !matches!(extension, "pdf" | "docx" | "pptx" | "xlsx") {
+    return Ok(None);
+  }
+  let data = fs::read(path)
+    .map_err(|e| ProxyError::new("invalid-request", format!("{}: {e}", path.display())))?;
+  if !needs_password(&data) {
+    return Ok(None);
+  }
+  let name = path
+    .file_name()
+    .map(|n| n.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let (plain, password) = obtain(app, doc_id, &name, &data)?;
+  UNLOCKED
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .get_or_insert_with(HashMap::new)
+    .insert(path.to_path_buf(), Arc::new(plain));
+  Ok(Some(Unlocked {
+    path: path.to_path_buf(),
+    password,
+  }))
+}
+
+/// The document at `path`, decrypted if it is [`unlock`]ed.
+pub fn read(path: &Path) -> std::io::Result<Arc<Vec<u8>>> {
+  let unlocked = UNLOCKED
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .as_ref()
+    .and_then(|map| map.get(path).cloned());
+  match unlocked {
+    Some(data) => Ok(data),
+    None => fs::read(path).map(Arc::new),
+  }
+}
+
+/// The source of task `job_id`, decrypted if it is password protected.
+pub fn source(app: &AppHandle, job_id: &str, data: Vec<u8>) -> Result<Vec<u8>, ProxyError> {
+  if !needs_password(&data) {
+    return Ok(data);
+  }
+  let (plain, password) = obtain(
+    app,
+    job_id,
+    &format!("The document of task {job_id}"),
+    &data,
+  )?;
+  remember(job_id, &password);
+  Ok(plain)
+}
+
+/// Gives the password of document `doc_id` (the id of the upload or task
+/// that asked with `password-required`); an empty one cancels. Given
+/// before it is asked for, it is kept for when it is.
+#[tauri::command]
+pub fn provide_document_password(doc_id: String, password: String) {
+  let waiting = lock(&WAITING).as_mut().and_then(|w| w.remove(&doc_id));
+  match waiting {
+    Some(sender) => {
+      let _ = sender.send(password);
+    }
+    None if !password.is_empty() => remember(&doc_id, &password),
+    None => {}
+  }
+}
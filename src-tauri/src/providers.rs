@@ -0,0 +1,545 @@
+//! Configured translation providers (OpenAI, Azure OpenAI, DeepL, Google,
+//! Azure Translator, Anthropic, Ollama or a local OpenAI-compatible server
+//! such as llama.cpp's), each with its endpoint, the credential store entry
+//! holding its key, known models and a rate limit. `validate_provider` asks the backend for one
+//! cheap authenticated request, so a bad key shows up before a job rather
+//! than halfway in.
+//!
+//! All but Anthropic's can also be called by the shell itself, through the
+//! [`Translator`] of their kind: see `direct`. Ollama's models are listed
+//! and pulled by the shell too, with `list_ollama_models` and
+//! `pull_ollama_model`.
+
+mod azure;
+mod deepl;
+mod google;
+mod ollama;
+mod openai;
+
+use reqwest::blocking::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+  collections::BTreeMap,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+  config, keychain, outbound, paths,
+  proxy::{self, ProxyError},
+  ratelimit::{self, ProviderLimit},
+  transport,
+};
+
+const KINDS: &[&str] = &[
+  "openai",
+  "azure",
+  "deepl",
+  "google",
+  "azure-translator",
+  "anthropic",
+  "ollama",
+  "local",
+];
+const VALIDATE_TIMEOUT: Duration = Duration::from_secs(30);
+/// For one request of a [`Translator`].
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Minimum gap between two `ollama-pull-progress` events of one step.
+const PULL_PROGRESS_EVERY: Duration = Duration::from_millis(250);
+
+static PROVIDERS: Mutex<Vec<ProviderConfig>> = Mutex::new(Vec::new());
+
+/// One entry of `providers` in `startup.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderConfig {
+  /// `prov_<hex>`; assigned on first save.
+  #[serde(default)]
+  pub id: String,
+  /// One of [`KINDS`].
+  pub kind: String,
+  pub name: String,
+  /// API root; saving with none fills in the kind's public endpoint.
+  /// Required for `azure` (the resource URL) and `local`.
+  #[serde(default)]
+  pub endpoint: String,
+  /// Credential store entry with the key (see `set_api_key`); empty uses
+  /// the endpoint's host.
+  #[serde(default)]
+  pub key_ref: String,
+  /// Models (DeepL/Google/Azure Translator: none) to offer; refreshed by
+  /// validation and, for Ollama, by pulls.
+  #[serde(default)]
+  pub models: Vec<String>,
+  /// The Azure region of an `azure-translator` resource, e.g. `westeurope`;
+  /// empty for global ones.
+  #[serde(default)]
+  pub region: String,
+  /// Applied to the endpoint's host, like `set_rate_limits`.
+  #[serde(default)]
+  pub rate_limit: Option<ProviderLimit>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Validation {
+  ok: bool,
+  status: Option<u16>,
+  message: String,
+  latency_ms: Option<u64>,
+  models: Vec<String>,
+  languages: Vec<String>,
+}
+
+fn default_endpoint(kind: &str) -> &'static str {
+  match kind {
+    "openai" => "https://api.openai.com/v1",
+    "deepl" => "https://api-free.deepl.com",
+    "google" => "https://translation.googleapis.com",
+    "azure-translator" => "https://api.cognitive.microsofttranslator.com",
+    "ollama" => "http://localhost:11434",
+    "anthropic" => "https://api.anthropic.com",
+    _ => "",
+  }
+}
+
+impl ProviderConfig {
+  pub fn endpoint(&self) -> &str {
+    match self.endpoint.trim() {
+      "" => default_endpoint(&self.kind),
+      endpoint => endpoint,
+    }
+  }
+
+  pub fn host(&self) -> Option<String> {
+    reqwest::Url::parse(self.endpoint())
+      .ok()?
+      .host_str()
+      .map(str::to_ascii_lowercase)
+  }
+
+  /// Entry in the credential store that holds this provider's key.
+  pub fn key_ref(&self) -> Option<String> {
+    match self.key_ref.trim() {
+      "" => self.host(),
+      key_ref => Some(key_ref.to_ascii_lowercase()),
+    }
+  }
+
+  fn normalized(mut self) -> Self {
+    self.kind = self.kind.trim().to_ascii_lowercase();
+    self.name = self.name.trim().to_string();
+    self.endpoint = self.endpoint().trim_end_matches('/').to_string();
+    self.key_ref = self.key_ref.trim().to_ascii_lowercase();
+    self.region = self.region.trim().to_ascii_lowercase();
+    self.models = self
+      .models
+      .into_iter()
+      .map(|m| m.trim().to_string())
+      .filter(|m| !m.is_empty())
+      .collect();
+    self
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    if !KINDS.contains(&self.kind.as_str()) {
+      return Err(format!(
+        "Unknown provider kind {}: use {}",
+        self.kind,
+        KINDS.join(", ")
+      ));
+    }
+    if self.name.is_empty() {
+      return Err("Provider name is required".to_string());
+    }
+    if self.endpoint().is_empty() {
+      return Err(format!("{} providers need an endpoint", self.kind));
+    }
+    let url = reqwest::Url::parse(self.endpoint()).map_err(|e| format!("Invalid endpoint: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+      return Err("The endpoint must be an http(s) URL".to_string());
+    }
+    if let Some(limit) = &self.rate_limit {
+      if limit.requests_per_minute == 0 || limit.burst == 0 {
+        return Err("The rate limit must allow at least one request".to_string());
+      }
+    }
+    Ok(())
+  }
+}
+
+pub fn init(providers: Vec<ProviderConfig>) {
+  *PROVIDERS.lock().unwrap() = providers;
+}
+
+pub fn find(id: &str) -> Result<ProviderConfig, String> {
+  PROVIDERS
+    .lock()
+    .unwrap()
+    .iter()
+    .find(|p| p.id == id)
+    .cloned()
+    .ok_or_else(|| format!("Unknown provider {id}"))
+}
+
+fn new_id() -> Result<String, String> {
+  let mut raw = [0u8; 8];
+  getrandom::getrandom(&mut raw).map_err(|e| format!("No randomness available: {e}"))?;
+  Ok(format!(
+    "prov_{}",
+    raw.iter().map(|b| format!("{b:02x}")).collect::<String>()
+  ))
+}
+
+fn save(app: &AppHandle, providers: &[ProviderConfig]) -> Result<(), String> {
+  let data_dir = paths::data_dir(app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(providers).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "providers", value)
+    .map_err(|e| format!("Cannot save providers: {e}"))
+}
+
+#[tauri::command]
+pub fn list_providers() -> Vec<ProviderConfig> {
+  PROVIDERS.lock().unwrap().clone()
+}
+
+/// Adds `provider` (empty `id`) or replaces the one with its `id`, and
+/// applies its rate limit. Returns it as saved.
+#[tauri::command]
+pub fn save_provider(app: AppHandle, provider: ProviderConfig) -> Result<ProviderConfig, String> {
+  let mut provider = provider.normalized();
+  provider.validate()?;
+  let mut providers = PROVIDERS.lock().unwrap().clone();
+  let previous = providers.iter().position(|p| p.id == provider.id);
+  if previous.is_none() {
+    if !provider.id.is_empty() {
+      return Err(format!("Unknown provider {}", provider.id));
+    }
+    provider.id = new_id()?;
+  }
+  if providers
+    .iter()
+    .any(|p| p.id != provider.id && p.name.eq_ignore_ascii_case(&provider.name))
+  {
+    return Err(format!("A provider named {} exists", provider.name));
+  }
+  let old = previous.map(|i| providers[i].clone());
+  match previous {
+    Some(i) => providers[i] = provider.clone(),
+    None => providers.push(provider.clone()),
+  }
+  save(&app, &providers)?;
+  *PROVIDERS.lock().unwrap() = providers;
+
+  // limits set for the host by other means stay unless this provider had one
+  if let Some(old) = old.filter(|p| p.rate_limit.is_some()) {
+    if let Some(host) = old.host() {
+      ratelimit::set_provider_limit(&app, &host, None)?;
+    }
+  }
+  if let (Some(host), Some(limit)) = (provider.host(), &provider.rate_limit) {
+    ratelimit::set_provider_limit(&app, &host, Some(limit.clone()))?;
+  }
+  Ok(provider)
+}
+
+/// Removes the provider and its rate limit; its key stays in the store
+/// (see `delete_api_key`), as other providers may share it.
+#[tauri::command]
+pub fn delete_provider(app: AppHandle, id: String) -> Result<(), String> {
+  let removed = find(&id)?;
+  let mut providers = PROVIDERS.lock().unwrap().clone();
+  providers.retain(|p| p.id != id);
+  save(&app, &providers)?;
+  *PROVIDERS.lock().unwrap() = providers;
+  if let Some(host) = removed.host() {
+    if removed.rate_limit.is_some() {
+      ratelimit::set_provider_limit(&app, &host, None)?;
+    }
+  }
+  Ok(())
+}
+
+/// Replaces the models of provider `id`, if it can be saved.
+fn set_models(app: &AppHandle, id: &str, models: Vec<String>) {
+  let mut providers = PROVIDERS.lock().unwrap().clone();
+  if let Some(p) = providers.iter_mut().find(|p| p.id == id) {
+    p.models = models;
+  }
+  if save(app, &providers).is_ok() {
+    *PROVIDERS.lock().unwrap() = providers;
+  }
+}
+
+fn validate(app: &AppHandle, provider: &ProviderConfig) -> Result<Validation, ProxyError> {
+  let base = proxy::target_base(app, false)
+    .ok_or_else(|| ProxyError::new("not-ready", "backend is not running"))?;
+  let key_ref = provider.key_ref().unwrap_or_default();
+  let mut headers = BTreeMap::new();
+  headers.insert("content-type".to_string(), "application/json".to_string());
+  let headers = keychain::request_headers(headers, Some(&key_ref));
+  let body = json!({
+    "kind": provider.kind,
+    "endpoint": provider.endpoint(),
+    "key_ref": key_ref,
+    "region": provider.region,
+  });
+  let resp = transport::request(
+    "POST",
+    &format!("{base}/api/providers/validate"),
+    &headers,
+    serde_json::to_vec(&body).unwrap_or_default(),
+    VALIDATE_TIMEOUT,
+  )
+  .map_err(|e| ProxyError::from_io(&e))?;
+  if !resp.is_success() {
+    return Err(ProxyError::from_response(&resp));
+  }
+  serde_json::from_slice(&resp.body).map_err(|e| ProxyError::new("http", e.to_string()))
+}
+
+/// Makes one cheap live request with the provider's stored key and reports
+/// whether it worked, with the models and target languages the provider
+/// lists. A successful check also refreshes the provider's model list.
+#[tauri::command]
+pub async fn validate_provider(
+  app: AppHandle,
+  profile_id: String,
+) -> Result<Validation, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let provider = find(&profile_id).map_err(|e| ProxyError::new("invalid-request", e))?;
+    let result = validate(&app, &provider)?;
+    if result.ok && !result.models.is_empty() {
+      set_models(&app, &profile_id, result.models.clone());
+    }
+    Ok(result)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// A provider's API as the shell calls it, for translating without the
+/// backend.
+pub trait Translator: Send {
+  /// Texts sent in one request at most, and characters of them.
+  fn batch(&self) -> (usize, usize);
+  /// Estimated tokens (see `segmentation`) of the longest text one request
+  /// takes, for models with a small context window; longer ones are sent
+  /// in chunks.
+  fn max_tokens(&self) -> Option<usize> {
+    None
+  }
+  /// The translations of `texts`, in order, from `source` into `target`
+  /// (e.g. `zh`, `en`).
+  fn translate(
+    &self,
+    texts: &[String],
+    source: &str,
+    target: &str,
+  ) -> Result<Vec<String>, ProxyError>;
+}
+
+/// The [`Translator`] for `provider`, with its stored key; `model` is for
+/// the kinds that have models (the deployment, for Azure OpenAI).
+pub fn translator(provider: &ProviderConfig, model: &str) -> Result<Box<dyn Translator>, String> {
+  let key = provider.key_ref().and_then(|k| keychain::api_key(&k));
+  let needs_key = || {
+    key
+      .clone()
+      .ok_or_else(|| format!("No API key is stored for {}", provider.name))
+  };
+  let needs_model = || match model.trim() {
+    "" => Err(format!("{} needs a model", provider.name)),
+    model => Ok(model.to_string()),
+  };
+  let endpoint = provider.endpoint();
+  Ok(match provider.kind.as_str() {
+    "openai" => Box::new(openai::OpenAi::compatible(
+      endpoint,
+      Some(needs_key()?),
+      needs_model()?,
+    )),
+    "local" => Box::new(openai::OpenAi::compatible(endpoint, key, needs_model()?)),
+    "azure" => Box::new(openai::OpenAi::azure(
+      endpoint,
+      needs_key()?,
+      &needs_model()?,
+    )),
+    "deepl" => Box::new(deepl::DeepL::new(endpoint, needs_key()?)),
+    "google" => Box::new(google::Google::new(endpoint, needs_key()?)),
+    "azure-translator" => Box::new(azure::AzureTranslator::new(
+      endpoint,
+      needs_key()?,
+      &provider.region,
+    )),
+    "ollama" => {
+      Box::new(ollama::Ollama::new(endpoint, key, needs_model()?).map_err(|e| e.message)?)
+    }
+    kind => return Err(format!("{kind} providers are only called by the backend")),
+  })
+}
+
+/// The Ollama provider `id`, with its stored key if it has one.
+fn ollama_provider(id: &str) -> Result<(ProviderConfig, Option<String>), ProxyError> {
+  let provider = find(id).map_err(|e| ProxyError::new("invalid-request", e))?;
+  if provider.kind != "ollama" {
+    return Err(ProxyError::new(
+      "invalid-request",
+      format!("{} is not an Ollama server", provider.name),
+    ));
+  }
+  let key = provider.key_ref().and_then(|k| keychain::api_key(&k));
+  Ok((provider, key))
+}
+
+/// The models Ollama provider `provider_id` has pulled, which become its
+/// models to offer.
+#[tauri::command]
+pub async fn list_ollama_models(
+  app: AppHandle,
+  provider_id: String,
+) -> Result<Vec<ollama::Model>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let (provider, key) = ollama_provider(&provider_id)?;
+    let models = ollama::models(provider.endpoint(), key)?;
+    set_models(
+      &app,
+      &provider_id,
+      models.iter().map(|m| m.name.clone()).collect(),
+    );
+    Ok(models)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Payload of `ollama-pull-progress`.
+#[derive(Clone, Serialize)]
+struct PullProgress<'a> {
+  provider_id: &'a str,
+  model: &'a str,
+  #[serde(flatten)]
+  step: ollama::PullStatus,
+}
+
+/// Has Ollama provider `provider_id` download `model` (e.g. `qwen2.5:7b`),
+/// reporting each step as `ollama-pull-progress` events; returns its
+/// models once the model is there.
+#[tauri::command]
+pub async fn pull_ollama_model(
+  app: AppHandle,
+  provider_id: String,
+  model: String,
+) -> Result<Vec<ollama::Model>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let (provider, key) = ollama_provider(&provider_id)?;
+    let mut last: Option<(String, Instant)> = None;
+    ollama::pull(provider.endpoint(), key.clone(), model.trim(), |step| {
+      let due = last
+        .as_ref()
+        .is_none_or(|(status, at)| *status != step.status || at.elapsed() >= PULL_PROGRESS_EVERY);
+      if due {
+        last = Some((step.status.clone(), Instant::now()));
+        let _ = app.emit(
+          "ollama-pull-progress",
+          PullProgress {
+            provider_id: &provider_id,
+            model: &model,
+            step,
+          },
+        );
+      }
+    })?;
+    let models = ollama::models(provider.endpoint(), key)?;
+    set_models(
+      &app,
+      &provider_id,
+      models.iter().map(|m| m.name.clone()).collect(),
+    );
+    Ok(models)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Posts `body` to a provider and returns its answer; errors carry the
+/// provider's own message.
+fn post(url: &str, headers: &[(&str, String)], body: &Value) -> Result<Value, ProxyError> {
+  answer(send(url, headers, Some(body), Some(TRANSLATE_TIMEOUT))?)
+}
+
+/// [`post`] for a GET of `url`.
+fn get(url: &str, headers: &[(&str, String)]) -> Result<Value, ProxyError> {
+  answer(send(url, headers, None, Some(TRANSLATE_TIMEOUT))?)
+}
+
+/// Posts `body` to `url`, or gets it without one; `timeout` is for the
+/// whole answer, `None` for answers streamed as they are made.
+fn send(
+  url: &str,
+  headers: &[(&str, String)],
+  body: Option<&Value>,
+  timeout: Option<Duration>,
+) -> Result<Response, ProxyError> {
+  let client = outbound::builder()
+    .and_then(|b| b.timeout(timeout).build())
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?;
+  let request = match body {
+    Some(body) => client.post(url).json(body),
+    None => client.get(url),
+  };
+  let request = headers
+    .iter()
+    .fold(request, |r, (name, value)| r.header(*name, value));
+  request.send().map_err(|e| {
+    if e.is_timeout() {
+      ProxyError::new(
+        "timeout",
+        format!("the provider did not answer in time: {e}"),
+      )
+    } else {
+      ProxyError::new("unreachable", format!("provider unreachable: {e}"))
+    }
+  })
+}
+
+/// The JSON `resp` carries, or the provider's error.
+fn answer(resp: Response) -> Result<Value, ProxyError> {
+  let status = resp.status().as_u16();
+  let text = resp
+    .text()
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?;
+  let value: Option<Value> = serde_json::from_str(&text).ok();
+  if !(200..300).contains(&status) {
+    return Err(failed(status, value.as_ref(), &text));
+  }
+  value.ok_or_else(|| unexpected(&text))
+}
+
+fn failed(status: u16, value: Option<&Value>, text: &str) -> ProxyError {
+  let message = value
+    .and_then(|v| {
+      v["error"]["message"]
+        .as_str()
+        .or(v["error"].as_str())
+        .or(v["message"].as_str())
+    })
+    .unwrap_or(text);
+  ProxyError {
+    kind: if status == 429 {
+      "rate-limited"
+    } else {
+      "http"
+    },
+    status: Some(status),
+    message: message.to_string(),
+  }
+}
+
+fn unexpected(answer: &str) -> ProxyError {
+  let answer: String = answer.chars().take(200).collect();
+  ProxyError::new(
+    "http",
+    format!("unexpected answer from the provider: {answer}"),
+  )
+}
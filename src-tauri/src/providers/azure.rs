@@ -0,0 +1,72 @@
+//! Azure AI Translator (version 3.0 of the text translation API), global
+//! or in a region.
+
+use serde_json::{json, Value};
+
+use super::{post, unexpected, Translator};
+use crate::proxy::ProxyError;
+
+pub struct AzureTranslator {
+  endpoint: String,
+  key: String,
+  region: String,
+}
+
+impl AzureTranslator {
+  pub fn new(endpoint: &str, key: String, region: &str) -> Self {
+    Self {
+      endpoint: endpoint.trim_end_matches('/').to_string(),
+      key,
+      region: region.to_string(),
+    }
+  }
+}
+
+fn language(code: &str) -> &str {
+  match code {
+    "zh" => "zh-Hans",
+    code => code,
+  }
+}
+
+impl Translator for AzureTranslator {
+  /// Texts and characters per request as documented.
+  fn batch(&self) -> (usize, usize) {
+    (1000, 50_000)
+  }
+
+  fn translate(
+    &self,
+    texts: &[String],
+    source: &str,
+    target: &str,
+  ) -> Result<Vec<String>, ProxyError> {
+    let url = format!(
+      "{}/translate?api-version=3.0&from={}&to={}",
+      self.endpoint,
+      language(source),
+      language(target)
+    );
+    let mut headers = vec![("ocp-apim-subscription-key", self.key.clone())];
+    if !self.region.is_empty() {
+      headers.push(("ocp-apim-subscription-region", self.region.clone()));
+    }
+    let body = Value::Array(texts.iter().map(|t| json!({ "Text": t })).collect());
+    let answer = post(&url, &headers, &body)?;
+    answer
+      .as_array()
+      .map(|found| {
+        found
+          .iter()
+          .map(|t| {
+            t["translations"][0]["text"]
+              .as_str()
+              .unwrap_or_default()
+              .to_string()
+          })
+          .collect::<Vec<_>>()
+      })
+      .filter(|found| found.len() == texts.len())
+      .ok_or_else(|| unexpected(&answer.to_string()))
+  }
+}
@@ -0,0 +1,64 @@
+//! DeepL's translate API, on the free or the paid endpoint.
+
+use serde_json::json;
+
+use super::{post, unexpected, Translator};
+use crate::proxy::ProxyError;
+
+pub struct DeepL {
+  url: String,
+  key: String,
+}
+
+impl DeepL {
+  pub fn new(endpoint: &str, key: String) -> Self {
+    Self {
+      url: format!("{}/v2/translate", endpoint.trim_end_matches('/')),
+      key,
+    }
+  }
+}
+
+/// DeepL's code for target language `code`, which for some languages
+/// names the variant.
+fn target_lang(code: &str) -> String {
+  match code {
+    "en" => "EN-US".to_string(),
+    "zh" => "ZH-HANS".to_string(),
+    "pt" => "PT-PT".to_string(),
+    code => code.to_ascii_uppercase(),
+  }
+}
+
+impl Translator for DeepL {
+  /// Texts per request as documented, characters to keep under its
+  /// 128 KiB a request.
+  fn batch(&self) -> (usize, usize) {
+    (50, 30_000)
+  }
+
+  fn translate(
+    &self,
+    texts: &[String],
+    source: &str,
+    target: &str,
+  ) -> Result<Vec<String>, ProxyError> {
+    let body = json!({
+      "text": texts,
+      "source_lang": source.to_ascii_uppercase(),
+      "target_lang": target_lang(target),
+    });
+    let headers = [("authorization", format!("DeepL-Auth-Key {}", self.key))];
+    let answer = post(&self.url, &headers, &body)?;
+    answer["translations"]
+      .as_array()
+      .map(|found| {
+        found
+          .iter()
+          .map(|t| t["text"].as_str().unwrap_or_default().to_string())
+          .collect::<Vec<_>>()
+      })
+      .filter(|found| found.len() == texts.len())
+      .ok_or_else(|| unexpected(&answer.to_string()))
+  }
+}
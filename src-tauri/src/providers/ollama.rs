@@ -0,0 +1,209 @@
+//! Ollama's own API, for models run on this machine or the local network,
+//! so nothing of a document leaves it. Each text is one chat request with
+//! the instructions the OpenAI-compatible servers get (see `openai`).
+//!
+//! A model translates within its context window, which Ollama keeps at a
+//! default size however large the model's own is: the window is asked for
+//! explicitly, the model's length up to [`MAX_CONTEXT`], and texts are cut
+//! so that the instructions, the text and a translation of twice its
+//! length fit.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader};
+
+use super::{failed, get, openai, post, send, unexpected, Translator};
+use crate::proxy::ProxyError;
+
+/// Largest context window asked for, for the memory a window takes.
+const MAX_CONTEXT: usize = 8192;
+/// Ollama's window for models that do not say theirs.
+const DEFAULT_CONTEXT: usize = 2048;
+/// Tokens of the instructions and chat framing.
+const PROMPT_TOKENS: usize = 100;
+
+pub struct Ollama {
+  base: String,
+  headers: Vec<(&'static str, String)>,
+  model: String,
+  context: usize,
+}
+
+fn headers(key: Option<String>) -> Vec<(&'static str, String)> {
+  key
+    .map(|key| ("authorization", format!("Bearer {key}")))
+    .into_iter()
+    .collect()
+}
+
+impl Ollama {
+  /// `model` at the server at `endpoint`, which is asked its context
+  /// length.
+  pub fn new(endpoint: &str, key: Option<String>, model: String) -> Result<Self, ProxyError> {
+    let base = endpoint.trim_end_matches('/').to_string();
+    let headers = headers(key);
+    let shown = post(
+      &format!("{base}/api/show"),
+      &headers,
+      &json!({ "model": model }),
+    )?;
+    Ok(Self {
+      context: context_length(&shown).min(MAX_CONTEXT),
+      base,
+      headers,
+      model,
+    })
+  }
+}
+
+/// The window a model was made with (`num_ctx` in its parameters), else
+/// the length its architecture takes (`<arch>.context_length`).
+fn context_length(shown: &Value) -> usize {
+  let parameter = shown["parameters"].as_str().and_then(|parameters| {
+    parameters.lines().find_map(|line| {
+      let mut words = line.split_whitespace();
+      (words.next() == Some("num_ctx"))
+        .then(|| words.next()?.parse().ok())
+        .flatten()
+    })
+  });
+  let architecture = shown["model_info"].as_object().and_then(|info| {
+    info
+      .iter()
+      .find(|(key, _)| key.ends_with(".context_length"))
+      .and_then(|(_, value)| value.as_u64())
+      .map(|n| n as usize)
+  });
+  parameter.or(architecture).unwrap_or(DEFAULT_CONTEXT)
+}
+
+impl Translator for Ollama {
+  fn batch(&self) -> (usize, usize) {
+    (1, usize::MAX)
+  }
+
+  fn max_tokens(&self) -> Option<usize> {
+    Some((self.context.saturating_sub(PROMPT_TOKENS) / 3).max(1))
+  }
+
+  fn translate(
+    &self,
+    texts: &[String],
+    _source: &str,
+    target: &str,
+  ) -> Result<Vec<String>, ProxyError> {
+    texts
+      .iter()
+      .map(|text| {
+        let body = json!({
+          "model": self.model,
+          "messages": openai::messages(text, target),
+          "stream": false,
+          "options": {
+            "temperature": 0.2,
+            "num_ctx": self.context,
+          },
+        });
+        let answer = post(&format!("{}/api/chat", self.base), &self.headers, &body)?;
+        answer["message"]["content"]
+          .as_str()
+          .map(|t| t.trim().to_string())
+          .ok_or_else(|| unexpected(&answer.to_string()))
+      })
+      .collect()
+  }
+}
+
+#[derive(Serialize)]
+pub struct Model {
+  pub name: String,
+  /// Bytes on disk.
+  pub size: u64,
+  /// E.g. `8.0B`.
+  pub parameters: Option<String>,
+  /// E.g. `Q4_K_M`.
+  pub quantization: Option<String>,
+}
+
+/// The models the server at `endpoint` has.
+pub fn models(endpoint: &str, key: Option<String>) -> Result<Vec<Model>, ProxyError> {
+  let base = endpoint.trim_end_matches('/');
+  let answer = get(&format!("{base}/api/tags"), &headers(key))?;
+  let text = |value: &Value| value.as_str().map(str::to_string);
+  answer["models"]
+    .as_array()
+    .map(|models| {
+      models
+        .iter()
+        .map(|m| Model {
+          name: text(&m["name"]).unwrap_or_default(),
+          size: m["size"].as_u64().unwrap_or(0),
+          parameters: text(&m["details"]["parameter_size"]),
+          quantization: text(&m["details"]["quantization_level"]),
+        })
+        .collect()
+    })
+    .ok_or_else(|| unexpected(&answer.to_string()))
+}
+
+/// One step of a pull, as Ollama reports it.
+#[derive(Clone, Serialize)]
+pub struct PullStatus {
+  /// E.g. `pulling manifest`, `pulling <digest>`, `verifying sha256
+  /// digest`, `success`.
+  pub status: String,
+  /// Bytes of the layer being pulled, when it is one.
+  pub completed: Option<u64>,
+  pub total: Option<u64>,
+}
+
+/// Downloads `model` to the server at `endpoint`, handing each step to
+/// `progress`; returns once the model is there.
+pub fn pull(
+  endpoint: &str,
+  key: Option<String>,
+  model: &str,
+  mut progress: impl FnMut(PullStatus),
+) -> Result<(), ProxyError> {
+  let base = endpoint.trim_end_matches('/');
+  let body = json!({ "model": model, "stream": true });
+  let resp = send(
+    &format!("{base}/api/pull"),
+    &headers(key),
+    Some(&body),
+    None,
+  )?;
+  let status = resp.status().as_u16();
+  if !(200..300).contains(&status) {
+    let text = resp.text().unwrap_or_default();
+    return Err(failed(
+      status,
+      serde_json::from_str(&text).ok().as_ref(),
+      &text,
+    ));
+  }
+  for line in BufReader::new(resp).lines() {
+    let line = line
+      .map_err(|e| ProxyError::new("unreachable", format!("the pull of {model} broke off: {e}")))?;
+    let Ok(step) = serde_json::from_str::<Value>(&line) else {
+      continue;
+    };
+    if let Some(error) = step["error"].as_str() {
+      return Err(ProxyError::new("http", error.to_string()));
+    }
+    let status = step["status"].as_str().unwrap_or_default().to_string();
+    let done = status == "success";
+    progress(PullStatus {
+      status,
+      completed: step["completed"].as_u64(),
+      total: step["total"].as_u64(),
+    });
+    if done {
+      return Ok(());
+    }
+  }
+  Err(ProxyError::new(
+    "unreachable",
+    format!("the pull of {model} ended before it was done"),
+  ))
+}
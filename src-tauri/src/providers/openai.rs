@@ -0,0 +1,106 @@
+//! OpenAI's chat completions, as OpenAI, Azure OpenAI and local servers
+//! (Ollama, LM Studio, vLLM) serve them. Each text is one request, with
+//! the instructions the backend gives but without its glossary and
+//! profiles.
+
+use serde_json::{json, Value};
+
+use super::{post, unexpected, Translator};
+use crate::proxy::ProxyError;
+
+const SYSTEM: &str = "You are a professional translator. Output only the translation. \
+  Do not add explanations or any extra text. \
+  Preserve numbers, units, symbols, and formatting as much as possible. \
+  Keep placeholders such as ⟦1⟧ and ⟦/1⟧ exactly as they are, around the same words.";
+const API_VERSION: &str = "2024-10-21";
+
+pub struct OpenAi {
+  url: String,
+  headers: Vec<(&'static str, String)>,
+  /// `None` for Azure, where the deployment in the URL is the model.
+  model: Option<String>,
+}
+
+impl OpenAi {
+  /// OpenAI or a server like it; `/v1` is added to the endpoint when it
+  /// has none, as the backend does.
+  pub fn compatible(endpoint: &str, key: Option<String>, model: String) -> Self {
+    let base = endpoint.trim_end_matches('/');
+    let base = if base.ends_with("/v1") {
+      base.to_string()
+    } else {
+      format!("{base}/v1")
+    };
+    Self {
+      url: format!("{base}/chat/completions"),
+      headers: key
+        .map(|key| ("authorization", format!("Bearer {key}")))
+        .into_iter()
+        .collect(),
+      model: Some(model),
+    }
+  }
+
+  /// An Azure OpenAI resource at `endpoint`, with the model deployed as
+  /// `deployment`.
+  pub fn azure(endpoint: &str, key: String, deployment: &str) -> Self {
+    Self {
+      url: format!(
+        "{}/openai/deployments/{deployment}/chat/completions?api-version={API_VERSION}",
+        endpoint.trim_end_matches('/')
+      ),
+      headers: vec![("api-key", key)],
+      model: None,
+    }
+  }
+}
+
+fn language(code: &str) -> &str {
+  match code {
+    "en" => "English",
+    "zh" => "Chinese",
+    code => code,
+  }
+}
+
+/// The chat messages asking for `text` in `target`.
+pub fn messages(text: &str, target: &str) -> Value {
+  json!([
+    {"role": "system", "content": SYSTEM},
+    {
+      "role": "user",
+      "content": format!("Translate the following text into {}:\n\n{text}", language(target)),
+    },
+  ])
+}
+
+impl Translator for OpenAi {
+  fn batch(&self) -> (usize, usize) {
+    (1, usize::MAX)
+  }
+
+  fn translate(
+    &self,
+    texts: &[String],
+    _source: &str,
+    target: &str,
+  ) -> Result<Vec<String>, ProxyError> {
+    texts
+      .iter()
+      .map(|text| {
+        let mut body = json!({
+          "messages": messages(text, target),
+          "temperature": 0.2,
+        });
+        if let Some(model) = &self.model {
+          body["model"] = Value::String(model.clone());
+        }
+        let answer = post(&self.url, &self.headers, &body)?;
+        answer["choices"][0]["message"]["content"]
+          .as_str()
+          .map(|t| t.trim().to_string())
+          .ok_or_else(|| unexpected(&answer.to_string()))
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,236 @@
+//! `proxy_request`: the webview's single way to talk to the backend. The
+//! shell knows where the backend listens, so the frontend needs neither the
+//! port nor CORS; logging and retries live here instead of in every caller.
+
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::BTreeMap,
+  io,
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  keychain,
+  logs::BackendLog,
+  pool,
+  ratelimit::{self, RateLimiter},
+  status::BackendState,
+  transport,
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+/// Requests slower than this are logged even when they succeed.
+const SLOW_REQUEST: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+pub struct ProxyRequest {
+  pub method: String,
+  /// Path and query on the backend, e.g. `/api/tasks?limit=5`.
+  pub path: String,
+  #[serde(default)]
+  pub headers: BTreeMap<String, String>,
+  #[serde(default)]
+  pub body: Option<Vec<u8>>,
+  #[serde(default)]
+  pub timeout_ms: Option<u64>,
+  /// Spread over the worker pool instead of always using the primary.
+  #[serde(default)]
+  pub balance: bool,
+  /// Host of the translation provider the backend will call for this
+  /// request, so that provider's rate limit and stored API key apply.
+  #[serde(default)]
+  pub provider: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProxyResponse {
+  pub status: u16,
+  pub headers: BTreeMap<String, String>,
+  pub body: Vec<u8>,
+}
+
+/// Why a proxied request failed; `kind` is `invalid-request`, `not-ready`,
+/// `rate-limited`, `timeout`, `unreachable` or `http` (the backend answered
+/// non-2xx).
+#[derive(Debug, Serialize)]
+pub struct ProxyError {
+  pub kind: &'static str,
+  pub status: Option<u16>,
+  pub message: String,
+}
+
+impl ProxyError {
+  pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+    Self {
+      kind,
+      status: None,
+      message: message.into(),
+    }
+  }
+
+  pub fn from_io(e: &io::Error) -> Self {
+    let timed_out = matches!(
+      e.kind(),
+      io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    ) || e
+      .get_ref()
+      .and_then(|inner| inner.downcast_ref::<reqwest::Error>())
+      .is_some_and(reqwest::Error::is_timeout);
+    if timed_out {
+      Self::new("timeout", format!("backend did not answer in time: {e}"))
+    } else {
+      Self::new("unreachable", format!("backend unreachable: {e}"))
+    }
+  }
+
+  /// FastAPI puts the reason in `{"detail": ...}`; fall back to the raw body.
+  pub fn from_response(resp: &transport::Response) -> Self {
+    let message = serde_json::from_slice::<serde_json::Value>(&resp.body)
+      .ok()
+      .and_then(|v| match v.get("detail")? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+      })
+      .unwrap_or_else(|| String::from_utf8_lossy(&resp.body).into_owned());
+    Self {
+      kind: "http",
+      status: Some(resp.status),
+      message,
+    }
+  }
+}
+
+/// An `invalid-request` error.
+pub fn invalid(message: impl Into<String>) -> ProxyError {
+  ProxyError::new("invalid-request", message)
+}
+
+/// Only these are safe to send twice.
+fn is_idempotent(method: &str) -> bool {
+  matches!(method, "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE")
+}
+
+/// Takes a concurrency slot and, for provider calls, a rate-limit token,
+/// waiting at most `timeout` for both.
+pub fn throttle(
+  app: &AppHandle,
+  req: &ProxyRequest,
+  timeout: Duration,
+) -> Result<ratelimit::Permit, ProxyError> {
+  let permit = ratelimit::acquire(app, timeout).map_err(|e| ProxyError::new("rate-limited", e))?;
+  if let Some(provider) = &req.provider {
+    app
+      .state::<RateLimiter>()
+      .throttle(&provider.trim().to_ascii_lowercase(), timeout)
+      .map_err(|e| ProxyError::new("rate-limited", e))?;
+  }
+  Ok(permit)
+}
+
+pub fn target_base(app: &AppHandle, balance: bool) -> Option<String> {
+  if balance {
+    pool::pick_backend_url(app.clone())
+  } else {
+    app.state::<BackendState>().base_url()
+  }
+}
+
+/// Sends `req` to the backend, retrying idempotent requests on transport
+/// errors and 503s (e.g. while a restart is in progress).
+fn forward(app: &AppHandle, req: ProxyRequest) -> Result<ProxyResponse, ProxyError> {
+  let method = req.method.to_ascii_uppercase();
+  if !req.path.starts_with('/') {
+    return Err(ProxyError::new(
+      "invalid-request",
+      format!("path must start with '/': {}", req.path),
+    ));
+  }
+  let timeout = req
+    .timeout_ms
+    .map(Duration::from_millis)
+    .unwrap_or(DEFAULT_TIMEOUT);
+  let _permit = throttle(app, &req, timeout)?;
+  let headers = keychain::request_headers(req.headers, req.provider.as_deref());
+  let body = req.body.unwrap_or_default();
+  let attempts = if is_idempotent(&method) {
+    MAX_ATTEMPTS
+  } else {
+    1
+  };
+  let log = app.state::<BackendLog>();
+  let start = Instant::now();
+
+  let mut attempt = 0;
+  let result = loop {
+    attempt += 1;
+    let Some(base) = target_base(app, req.balance) else {
+      break Err(ProxyError::new("not-ready", "backend is not running"));
+    };
+    let url = format!("{base}{}", req.path);
+    let retry = match transport::request(&method, &url, &headers, body.clone(), timeout) {
+      Ok(resp) if resp.status == 503 && attempt < attempts => true,
+      Ok(resp) if resp.is_success() => break Ok(resp),
+      Ok(resp) => break Err(ProxyError::from_response(&resp)),
+      Err(_) if attempt < attempts => true,
+      Err(e) => break Err(ProxyError::from_io(&e)),
+    };
+    if retry {
+      log.append(
+        "proxy",
+        format!("{method} {} failed (attempt {attempt}), retrying", req.path).as_bytes(),
+      );
+      std::thread::sleep(RETRY_BACKOFF * attempt);
+    }
+  };
+
+  let elapsed = start.elapsed();
+  match &result {
+    Err(e) => log.append(
+      "proxy",
+      format!(
+        "{method} {} -> {} {}: {}",
+        req.path,
+        e.kind,
+        e.status.map(|s| s.to_string()).unwrap_or_default(),
+        e.message
+      )
+      .as_bytes(),
+    ),
+    Ok(resp) if elapsed > SLOW_REQUEST => log.append(
+      "proxy",
+      format!(
+        "{method} {} -> {} in {}ms",
+        req.path,
+        resp.status,
+        elapsed.as_millis()
+      )
+      .as_bytes(),
+    ),
+    Ok(_) => {}
+  }
+
+  result.map(|resp| ProxyResponse {
+    status: resp.status,
+    headers: resp
+      .headers
+      .into_iter()
+      .map(|(k, v)| (k.to_ascii_lowercase(), v))
+      .collect(),
+    body: resp.body,
+  })
+}
+
+/// Forwards one HTTP request from the webview to the backend. Non-2xx
+/// answers come back as an `http` [`ProxyError`] carrying the status.
+#[tauri::command]
+pub async fn proxy_request(
+  app: AppHandle,
+  request: ProxyRequest,
+) -> Result<ProxyResponse, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || forward(&app, request))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
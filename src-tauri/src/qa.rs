@@ -0,0 +1,237 @@
+//! `run_qa`: checks of a task's translations a reviewer would otherwise
+//! make by eye. Each block is checked on its own, and each problem found
+//! is an issue with a severity: `error` for what breaks the document or
+//! changes its facts (numbers and placeholders lost), `warning` for what
+//! is likely wrong (numbers added, tags changed, text left untranslated,
+//! glossary terms missed or forbidden ones used, a translation far longer
+//! or shorter than its source) and `info` for cosmetics (doubled spaces).
+//!
+//! Numbers are compared digits for digits, full-width ones as ASCII and
+//! thousands separators aside; numbers written out in words are not seen.
+//! Length is compared in tokens (see `segmentation`), which
+//! evens out scripts, and only for blocks of some length.
+
+use regex::Regex;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::LazyLock};
+use tauri::AppHandle;
+
+use crate::{
+  glossary,
+  placeholders::{self, Piece},
+  proxy::ProxyError,
+  segmentation,
+  segments::{self, Row},
+};
+
+/// Markup left in the text itself, e.g. in Markdown or plain text.
+static TAGS: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"</?[A-Za-z][\w:.-]*(?:\s[^<>]*)?/?>").unwrap());
+/// Blocks with fewer source tokens are not checked for length.
+const MIN_LENGTH_TOKENS: usize = 8;
+/// Translation tokens per source token outside which the length is flagged.
+const LENGTH_RATIO: (f64, f64) = (0.33, 3.0);
+
+#[derive(Serialize)]
+pub struct Issue {
+  pub block_id: String,
+  /// Of the block in the document, counted from 0.
+  pub index: usize,
+  /// `numbers`, `placeholders`, `tags`, `untranslated`, `terminology`,
+  /// `length` or `spaces`.
+  pub check: &'static str,
+  /// `error`, `warning` or `info`.
+  pub severity: &'static str,
+  pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct QaReport {
+  /// Blocks checked.
+  pub segments: usize,
+  pub errors: usize,
+  pub warnings: usize,
+  /// In document order.
+  pub issues: Vec<Issue>,
+}
+
+/// `text` without its placeholders, and the placeholders as written.
+fn split_placeholders(text: &str) -> (String, Vec<String>) {
+  let mut plain = String::new();
+  let mut tags = Vec::new();
+  for piece in placeholders::pieces(text) {
+    match piece {
+      Piece::Text(t) => plain.push_str(t),
+      Piece::Tag(number, closing) => tags.push(placeholders::marker(number, closing)),
+    }
+  }
+  (plain, tags)
+}
+
+/// The numbers in `text`, canonical: ASCII digits, no thousands separators.
+fn numbers(text: &str) -> Vec<String> {
+  let chars: Vec<char> = text
+    .chars()
+    .map(|c| match c {
+      '０'..='９' => char::from(b'0' + (c as u32 - '０' as u32) as u8),
+      '．' => '.',
+      '，' => ',',
+      c => c,
+    })
+    .collect();
+  let mut out = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    if !chars[i].is_ascii_digit() {
+      i += 1;
+      continue;
+    }
+    let mut number = String::new();
+    while i < chars.len() {
+      let c = chars[i];
+      let joins = matches!(c, '.' | ',') && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+      if c.is_ascii_digit() || joins {
+        if c != ',' {
+          number.push(c);
+        }
+        i += 1;
+      } else {
+        break;
+      }
+    }
+    out.push(number);
+  }
+  out
+}
+
+/// What `a` has more of than `b`.
+fn missing_from(a: &[String], b: &[String]) -> Vec<String> {
+  let mut counts: BTreeMap<&str, i32> = BTreeMap::new();
+  for item in a {
+    *counts.entry(item).or_default() += 1;
+  }
+  for item in b {
+    *counts.entry(item).or_default() -= 1;
+  }
+  counts
+    .into_iter()
+    .flat_map(|(item, n)| std::iter::repeat_n(item.to_string(), n.max(0) as usize))
+    .collect()
+}
+
+fn check(direction: &str, index: usize, row: &Row, out: &mut Vec<Issue>) {
+  let mut issue = |check, severity, message: String| {
+    out.push(Issue {
+      block_id: row.id.clone(),
+      index,
+      check,
+      severity,
+      message,
+    })
+  };
+  let Some(translation) = &row.translation else {
+    issue("untranslated", "warning", "Not translated".to_string());
+    return;
+  };
+  let (source_text, source_tags) = split_placeholders(&row.source);
+  let (translated_text, translated_tags) = split_placeholders(translation);
+
+  if row.source.trim() == translation.trim() && source_text.chars().any(char::is_alphabetic) {
+    issue(
+      "untranslated",
+      "warning",
+      "The translation is the source text".to_string(),
+    );
+  }
+
+  let (source_numbers, translated_numbers) = (numbers(&source_text), numbers(&translated_text));
+  for number in missing_from(&source_numbers, &translated_numbers) {
+    issue(
+      "numbers",
+      "error",
+      format!("{number} is missing or altered"),
+    );
+  }
+  for number in missing_from(&translated_numbers, &source_numbers) {
+    issue(
+      "numbers",
+      "warning",
+      format!("{number} is not in the source"),
+    );
+  }
+
+  for tag in missing_from(&source_tags, &translated_tags) {
+    issue("placeholders", "error", format!("{tag} was dropped"));
+  }
+  for tag in missing_from(&translated_tags, &source_tags) {
+    issue(
+      "placeholders",
+      "error",
+      format!("{tag} is not in the source"),
+    );
+  }
+  let markup = |text: &str| -> Vec<String> {
+    TAGS
+      .find_iter(text)
+      .map(|m| m.as_str().to_string())
+      .collect()
+  };
+  let (source_markup, translated_markup) = (markup(&source_text), markup(&translated_text));
+  for tag in missing_from(&source_markup, &translated_markup) {
+    issue("tags", "warning", format!("{tag} was dropped or changed"));
+  }
+  for tag in missing_from(&translated_markup, &source_markup) {
+    issue("tags", "warning", format!("{tag} is not in the source"));
+  }
+
+  for violation in glossary::violations(direction, index, row) {
+    let message = match violation.kind {
+      "missing" => format!(
+        "\"{}\" is not translated as \"{}\"",
+        violation.source, violation.target
+      ),
+      _ => format!("Uses the forbidden term \"{}\"", violation.target),
+    };
+    issue("terminology", "warning", message);
+  }
+
+  let source_tokens = segmentation::tokens(&source_text);
+  if source_tokens >= MIN_LENGTH_TOKENS {
+    let ratio = segmentation::tokens(&translated_text) as f64 / source_tokens as f64;
+    if !(LENGTH_RATIO.0..=LENGTH_RATIO.1).contains(&ratio) {
+      issue(
+        "length",
+        "warning",
+        format!("The translation is {ratio:.1} times as long as the source"),
+      );
+    }
+  }
+
+  if translated_text.contains("  ") && !source_text.contains("  ") {
+    issue("spaces", "info", "Doubled spaces".to_string());
+  }
+}
+
+fn run(app: &AppHandle, job_id: &str) -> Result<QaReport, ProxyError> {
+  let direction = segments::info(app, job_id)?.direction;
+  let rows = segments::rows(app, job_id)?;
+  let mut issues = Vec::new();
+  for (index, row) in rows.iter().enumerate() {
+    check(&direction, index, row, &mut issues);
+  }
+  let count = |severity| issues.iter().filter(|i| i.severity == severity).count();
+  Ok(QaReport {
+    segments: rows.len(),
+    errors: count("error"),
+    warnings: count("warning"),
+    issues,
+  })
+}
+
+/// The issues found in the translations of task `job_id`.
+#[tauri::command]
+pub async fn run_qa(app: AppHandle, job_id: String) -> Result<QaReport, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || run(&app, &job_id))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+use std::{
+  fmt, io,
+  path::Path,
+  sync::atomic::{AtomicBool, Ordering},
+};
+use tauri::{AppHandle, Emitter};
+
+// Windows: "Operation did not complete successfully because the file
+// contains a virus" / "... and was deleted".
+const ERROR_VIRUS_INFECTED: i32 = 225;
+const ERROR_VIRUS_DELETED: i32 = 226;
+
+/// Set once the backend binary was seen on disk this session, so a missing
+/// file afterwards points at quarantine rather than a broken install.
+static SEEN: AtomicBool = AtomicBool::new(false);
+
+/// Payload of the `backend-blocked` event and the launch error when
+/// security software (antivirus, SmartScreen, Gatekeeper) seems to have
+/// blocked or removed the backend. Relaunching won't help.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendBlocked {
+  pub path: String,
+  /// `virus-detected`, `access-denied` or `quarantined`.
+  pub cause: &'static str,
+  pub detail: String,
+  pub remediation: String,
+}
+
+impl fmt::Display for BackendBlocked {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Backend {} was blocked ({}): {}. {}",
+      self.path, self.cause, self.detail, self.remediation
+    )
+  }
+}
+
+impl std::error::Error for BackendBlocked {}
+
+fn remediation(path: &Path) -> String {
+  let dir = path
+    .parent()
+    .map(|d| d.display().to_string())
+    .unwrap_or_default();
+  if cfg!(windows) {
+    format!(
+      "Windows Security or your antivirus likely quarantined the backend. Restore it under \
+       Windows Security > Virus & threat protection > Protection history, add an exclusion \
+       for {dir}, then restart the app (or reinstall it)."
+    )
+  } else if cfg!(target_os = "macos") {
+    "macOS or your security software blocked the backend. Allow it under System Settings > \
+     Privacy & Security, or reinstall the app."
+      .to_string()
+  } else {
+    format!(
+      "Security software or file permissions prevent running the backend. Check that the \
+       files in {dir} are present and executable, or reinstall the app."
+    )
+  }
+}
+
+fn blocked(path: &Path, cause: &'static str, detail: String) -> BackendBlocked {
+  BackendBlocked {
+    path: path.display().to_string(),
+    cause,
+    detail,
+    remediation: remediation(path),
+  }
+}
+
+/// Remembers that the binary was present. Returns the quarantine error if
+/// it was present before but is gone now.
+pub fn check_present(path: &Path) -> Result<(), BackendBlocked> {
+  if path.is_file() {
+    SEEN.store(true, Ordering::SeqCst);
+    return Ok(());
+  }
+  if SEEN.load(Ordering::SeqCst) {
+    return Err(blocked(
+      path,
+      "quarantined",
+      "the file disappeared after it was used earlier".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+/// Classifies a spawn error; `None` for ordinary failures.
+pub fn diagnose(path: &Path, err: &io::Error) -> Option<BackendBlocked> {
+  match err.raw_os_error() {
+    Some(ERROR_VIRUS_INFECTED) | Some(ERROR_VIRUS_DELETED) if cfg!(windows) => {
+      return Some(blocked(path, "virus-detected", err.to_string()));
+    }
+    _ => {}
+  }
+  match err.kind() {
+    io::ErrorKind::PermissionDenied => Some(blocked(path, "access-denied", err.to_string())),
+    io::ErrorKind::NotFound if SEEN.load(Ordering::SeqCst) => {
+      Some(blocked(path, "quarantined", err.to_string()))
+    }
+    _ => None,
+  }
+}
+
+/// Emits `backend-blocked` and boxes the error for the launch path.
+pub fn report(app: &AppHandle, e: BackendBlocked) -> Box<dyn std::error::Error> {
+  let _ = app.emit("backend-blocked", e.clone());
+  Box::new(e)
+}
@@ -0,0 +1,232 @@
+//! Throttling for traffic that ends up at a translation provider: a cap on
+//! requests forwarded to the backend at once, and a token bucket per
+//! provider host for forwarded requests that name their provider. The batch
+//! translator calls providers from inside the backend, so it gets the same
+//! per-provider limits via [`ENV`], split across the worker processes.
+
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::{Condvar, Mutex},
+  time::{Duration, Instant},
+};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+  config::{self, StartupConfig},
+  paths,
+};
+
+pub const ENV: &str = "MVP_RATE_LIMITS";
+
+/// `rate_limits` in `startup.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+  /// Requests forwarded to the backend at once; 0 means no cap.
+  pub max_concurrent: u32,
+  /// Limits by provider host, e.g. `api.openai.com`.
+  pub providers: BTreeMap<String, ProviderLimit>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderLimit {
+  pub requests_per_minute: u32,
+  /// Requests allowed back to back after an idle period.
+  #[serde(default = "default_burst")]
+  pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+  1
+}
+
+impl RateLimitConfig {
+  pub fn validate(&self) -> Result<(), String> {
+    for (host, limit) in &self.providers {
+      if host.trim().is_empty() {
+        return Err("Provider host must not be empty".to_string());
+      }
+      if limit.requests_per_minute == 0 || limit.burst == 0 {
+        return Err(format!("Limits for {host} must allow at least one request"));
+      }
+    }
+    Ok(())
+  }
+}
+
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+pub struct RateLimiter {
+  config: Mutex<RateLimitConfig>,
+  in_flight: Mutex<u32>,
+  released: Condvar,
+  buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Holds one of the `max_concurrent` slots until dropped; owns the app
+/// handle so streams can keep it on their relay thread.
+pub struct Permit(Option<AppHandle>);
+
+impl Drop for Permit {
+  fn drop(&mut self) {
+    if let Some(app) = &self.0 {
+      let limiter = app.state::<RateLimiter>();
+      *limiter.in_flight.lock().unwrap() -= 1;
+      limiter.released.notify_one();
+    }
+  }
+}
+
+impl RateLimiter {
+  pub fn new(config: RateLimitConfig) -> Self {
+    Self {
+      config: Mutex::new(config),
+      in_flight: Mutex::new(0),
+      released: Condvar::new(),
+      buckets: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn config(&self) -> RateLimitConfig {
+    self.config.lock().unwrap().clone()
+  }
+
+  /// Takes a token from `provider`'s bucket, sleeping until one is free
+  /// but no longer than `timeout`. Unknown providers are not limited.
+  pub fn throttle(&self, provider: &str, timeout: Duration) -> Result<(), String> {
+    let Some(limit) = self.config.lock().unwrap().providers.get(provider).cloned() else {
+      return Ok(());
+    };
+    let rate = f64::from(limit.requests_per_minute) / 60.0;
+    let capacity = f64::from(limit.burst);
+    let deadline = Instant::now() + timeout;
+    loop {
+      let wait = {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(provider.to_string()).or_insert(Bucket {
+          tokens: capacity,
+          updated: Instant::now(),
+        });
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+          bucket.tokens -= 1.0;
+          return Ok(());
+        }
+        Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+      };
+      if Instant::now() + wait > deadline {
+        return Err(format!(
+          "rate limit for {provider} ({}/min) exceeded",
+          limit.requests_per_minute
+        ));
+      }
+      std::thread::sleep(wait);
+    }
+  }
+}
+
+/// Waits up to `timeout` for one of the `max_concurrent` slots.
+pub fn acquire(app: &AppHandle, timeout: Duration) -> Result<Permit, String> {
+  let limiter = app.state::<RateLimiter>();
+  let cap = limiter.config.lock().unwrap().max_concurrent;
+  if cap == 0 {
+    return Ok(Permit(None));
+  }
+  let deadline = Instant::now() + timeout;
+  let mut in_flight = limiter.in_flight.lock().unwrap();
+  while *in_flight >= cap {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+      return Err(format!("{cap} requests already in flight"));
+    }
+    in_flight = limiter.released.wait_timeout(in_flight, left).unwrap().0;
+  }
+  *in_flight += 1;
+  Ok(Permit(Some(app.clone())))
+}
+
+/// The per-provider limits for one backend process, as JSON in [`ENV`].
+/// Each process keeps its own buckets, so the rate is divided between the
+/// `backend_workers`.
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let workers = app.state::<StartupConfig>().backend_workers.max(1);
+  let providers: BTreeMap<String, ProviderLimit> = app
+    .state::<RateLimiter>()
+    .config()
+    .providers
+    .into_iter()
+    .map(|(host, limit)| {
+      let share = ProviderLimit {
+        requests_per_minute: (limit.requests_per_minute / workers).max(1),
+        burst: (limit.burst / workers).max(1),
+      };
+      (host, share)
+    })
+    .collect();
+  let mut env = BTreeMap::new();
+  if !providers.is_empty() {
+    if let Ok(json) = serde_json::to_string(&providers) {
+      env.insert(ENV.to_string(), json);
+    }
+  }
+  env
+}
+
+#[tauri::command]
+pub fn get_rate_limits(state: State<RateLimiter>) -> RateLimitConfig {
+  state.config()
+}
+
+/// Saves and applies rate limits. The proxy uses them at once; the
+/// backend's batch translator from its next (re)start.
+#[tauri::command]
+pub fn set_rate_limits(
+  app: AppHandle,
+  state: State<RateLimiter>,
+  mut limits: RateLimitConfig,
+) -> Result<RateLimitConfig, String> {
+  limits.providers = limits
+    .providers
+    .into_iter()
+    .map(|(host, limit)| (host.trim().to_ascii_lowercase(), limit))
+    .collect();
+  limits.validate()?;
+  apply(&app, &state, &limits)?;
+  Ok(limits)
+}
+
+fn apply(app: &AppHandle, state: &RateLimiter, limits: &RateLimitConfig) -> Result<(), String> {
+  let data_dir = paths::data_dir(app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(limits).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "rate_limits", value)
+    .map_err(|e| format!("Cannot save rate limits: {e}"))?;
+  *state.config.lock().unwrap() = limits.clone();
+  state.buckets.lock().unwrap().clear();
+  state.released.notify_all();
+  Ok(())
+}
+
+/// Sets (or with `None` removes) the limit for one provider host, keeping
+/// the others.
+pub fn set_provider_limit(
+  app: &AppHandle,
+  host: &str,
+  limit: Option<ProviderLimit>,
+) -> Result<(), String> {
+  let state = app.state::<RateLimiter>();
+  let mut limits = state.config();
+  let host = host.trim().to_ascii_lowercase();
+  match limit {
+    Some(limit) => limits.providers.insert(host, limit),
+    None => limits.providers.remove(&host),
+  };
+  limits.validate()?;
+  apply(app, &state, &limits)
+}
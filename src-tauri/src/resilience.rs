@@ -0,0 +1,50 @@
+//! Retry and circuit-breaker settings for the backend's provider calls.
+//! The backend does the retrying (it makes the calls) and reports breaker
+//! changes as `provider` messages on the job-events socket, which the shell
+//! re-emits as `provider-status`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::{AppHandle, Manager};
+
+use crate::config::StartupConfig;
+
+pub const ENV: &str = "MVP_PROVIDER_RETRY";
+
+/// `provider_retry` in `startup.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+  /// Retries per call after 429s, 5xx and connection errors.
+  pub max_retries: u32,
+  /// First backoff step; doubled per retry, jittered, capped at
+  /// `max_delay_ms`. A provider's `Retry-After` takes precedence.
+  pub base_delay_ms: u64,
+  pub max_delay_ms: u64,
+  /// Consecutive failures after which calls to the provider fail fast.
+  pub breaker_threshold: u32,
+  /// How long the breaker stays open before one trial call.
+  pub breaker_cooldown_secs: u64,
+}
+
+impl Default for RetryConfig {
+  fn default() -> Self {
+    Self {
+      max_retries: 5,
+      base_delay_ms: 1000,
+      max_delay_ms: 60_000,
+      breaker_threshold: 5,
+      breaker_cooldown_secs: 60,
+    }
+  }
+}
+
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let mut cfg = app.state::<StartupConfig>().provider_retry.clone();
+  cfg.breaker_threshold = cfg.breaker_threshold.max(1);
+  let mut env = BTreeMap::new();
+  if let Ok(json) = serde_json::to_string(&cfg) {
+    env.insert(ENV.to_string(), json);
+  }
+  env
+}
@@ -0,0 +1,808 @@
+//! Localization resources read and rebuilt in the shell: JSON (the nested
+//! `{"key": "text"}` trees of i18next, vue-i18n and the like), Flutter
+//! ARB, YAML (Rails, Symfony) and Apple `.strings`. Segments are the
+//! string values; keys, numbers, booleans and ARB's `@` metadata are not
+//! sent. A translation replaces only its value as written, so key order,
+//! comments and layout stay as they were.
+//!
+//! Format placeholders (`{name}`, `{{name}}`, `%s`, `%1$@`, `%{name}`) and
+//! the syntax of ICU plurals and selects go to the translator as `⟦1⟧`; a
+//! value that loses one stays untranslated and is reported. An ARB file's
+//! `@@locale`, and a YAML file's only root key when it names the source
+//! language (Rails' `en:`), are set to the target language.
+//!
+//! Locators are `<format>:<start>-<end>` (`json`, `arb`, `yaml`,
+//! `strings`), the byte range of the value as written, quotes included.
+//! `.strings` files may be UTF-16; their ranges are in the text as UTF-8.
+
+use std::{fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{formats, unlock, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+  Json,
+  Arb,
+  Yaml,
+  Strings,
+}
+
+impl Kind {
+  fn of(path: &Path) -> Option<Kind> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    match extension.as_str() {
+      "json" => Some(Kind::Json),
+      "arb" => Some(Kind::Arb),
+      "yml" | "yaml" => Some(Kind::Yaml),
+      "strings" => Some(Kind::Strings),
+      _ => None,
+    }
+  }
+
+  fn prefix(self) -> &'static str {
+    match self {
+      Kind::Json => "json",
+      Kind::Arb => "arb",
+      Kind::Yaml => "yaml",
+      Kind::Strings => "strings",
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+  /// With its byte order mark, if any, in the text.
+  Utf8,
+  Utf16Le,
+  Utf16Be,
+}
+
+/// How a value is written.
+enum Style {
+  /// A JSON or `.strings` string.
+  Quoted,
+  /// YAML scalars.
+  Plain,
+  Double,
+  Single,
+  /// A YAML `|` or `>` block, its lines indented by `indent` spaces.
+  Block {
+    indent: usize,
+    folded: bool,
+  },
+}
+
+struct Value {
+  /// As written, quotes included.
+  range: Range<usize>,
+  text: String,
+  style: Style,
+}
+
+struct Resource {
+  text: String,
+  encoding: Encoding,
+  values: Vec<Value>,
+  /// ARB's `@@locale` value, or the root key of a YAML file keyed by its
+  /// language.
+  locale: Option<Range<usize>>,
+}
+
+fn line_of(text: &str, at: usize) -> usize {
+  text[..at.min(text.len())].matches('\n').count() + 1
+}
+
+fn decode(data: &[u8]) -> Result<(String, Encoding), String> {
+  let utf16 = |bytes: &[u8], encoding: Encoding| {
+    let units = bytes.chunks_exact(2).map(|pair| match encoding {
+      Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+      _ => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units)
+      .collect::<Result<String, _>>()
+      .map(|text| (text, encoding))
+      .map_err(|_| "The file is not valid UTF-16".to_string())
+  };
+  match data {
+    [0xFF, 0xFE, rest @ ..] => utf16(rest, Encoding::Utf16Le),
+    [0xFE, 0xFF, rest @ ..] => utf16(rest, Encoding::Utf16Be),
+    _ => String::from_utf8(data.to_vec())
+      .map(|text| (text, Encoding::Utf8))
+      .map_err(|_| "The file is not UTF-8 text".to_string()),
+  }
+}
+
+fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+  match encoding {
+    Encoding::Utf8 => text.as_bytes().to_vec(),
+    Encoding::Utf16Le => "\u{feff}"
+      .encode_utf16()
+      .chain(text.encode_utf16())
+      .flat_map(u16::to_le_bytes)
+      .collect(),
+    Encoding::Utf16Be => "\u{feff}"
+      .encode_utf16()
+      .chain(text.encode_utf16())
+      .flat_map(u16::to_be_bytes)
+      .collect(),
+  }
+}
+
+/// The end of the double-quoted string at `at`, on one line.
+fn quoted_end(b: &[u8], at: usize) -> Option<usize> {
+  let mut i = at + 1;
+  loop {
+    match b.get(i)? {
+      b'"' => return Some(i + 1),
+      b'\\' => i += 2,
+      b'\n' => return None,
+      _ => i += 1,
+    }
+  }
+}
+
+/// The text of a JSON, `.strings` or YAML double-quoted string, between
+/// its quotes. Escapes a format does not know are kept as written.
+fn unescape(inner: &str) -> Option<String> {
+  let mut out = String::with_capacity(inner.len());
+  let mut chars = inner.chars();
+  let hex = |chars: &mut std::str::Chars, digits: usize| {
+    let code: String = chars.take(digits).collect();
+    u32::from_str_radix(&code, 16)
+      .ok()
+      .filter(|_| code.len() == digits)
+  };
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next()? {
+      'n' => out.push('\n'),
+      't' => out.push('\t'),
+      'r' => out.push('\r'),
+      'b' => out.push('\u{8}'),
+      'f' => out.push('\u{c}'),
+      '0' => out.push('\0'),
+      'x' => out.push(char::from_u32(hex(&mut chars, 2)?)?),
+      'u' | 'U' => {
+        let mut code = hex(&mut chars, 4)?;
+        // a surrogate pair, as JSON writes characters past U+FFFF
+        if (0xD800..0xDC00).contains(&code) {
+          let rest = chars.as_str();
+          let low = rest
+            .strip_prefix("\\u")
+            .and_then(|r| r.get(..4))
+            .and_then(|r| u32::from_str_radix(r, 16).ok())
+            .filter(|low| (0xDC00..0xE000).contains(low))?;
+          chars.nth(5);
+          code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+        }
+        out.push(char::from_u32(code)?);
+      }
+      c @ ('"' | '\\' | '/' | '\'') => out.push(c),
+      c => {
+        out.push('\\');
+        out.push(c);
+      }
+    }
+  }
+  Some(out)
+}
+
+/// `text` as a double-quoted string, as JSON, `.strings` and YAML read it.
+fn quote(text: &str) -> String {
+  let mut out = String::with_capacity(text.len() + 2);
+  out.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  out
+}
+
+struct Json<'a> {
+  text: &'a str,
+  b: &'a [u8],
+  i: usize,
+  arb: bool,
+  values: Vec<Value>,
+  locale: Option<Range<usize>>,
+}
+
+impl Json<'_> {
+  fn error(&self, what: &str) -> String {
+    format!(
+      "Invalid JSON at line {}: {what}",
+      line_of(self.text, self.i)
+    )
+  }
+
+  fn space(&mut self) {
+    while self.b.get(self.i).is_some_and(u8::is_ascii_whitespace) {
+      self.i += 1;
+    }
+  }
+
+  fn at(&mut self) -> Option<u8> {
+    self.space();
+    self.b.get(self.i).copied()
+  }
+
+  fn string(&mut self) -> Result<(Range<usize>, String), String> {
+    let start = self.i;
+    let end = quoted_end(self.b, start).ok_or_else(|| self.error("unterminated string"))?;
+    let text = unescape(&self.text[start + 1..end - 1]).ok_or_else(|| self.error("bad escape"))?;
+    self.i = end;
+    Ok((start..end, text))
+  }
+
+  /// Reads a value, recording its strings unless `skip`.
+  fn value(&mut self, depth: usize, skip: bool) -> Result<(), String> {
+    match self.at() {
+      Some(b'{') => {
+        self.i += 1;
+        if self.at() == Some(b'}') {
+          self.i += 1;
+          return Ok(());
+        }
+        loop {
+          if self.at() != Some(b'"') {
+            return Err(self.error("expected a key"));
+          }
+          let (_, key) = self.string()?;
+          if self.at() != Some(b':') {
+            return Err(self.error("expected `:`"));
+          }
+          self.i += 1;
+          // descriptions, placeholder examples and the like
+          let meta = self.arb && depth == 0 && key.starts_with('@');
+          if key == "@@locale" && meta && self.at() == Some(b'"') {
+            self.locale = Some(self.string()?.0);
+          } else {
+            self.value(depth + 1, skip || meta)?;
+          }
+          match self.at() {
+            Some(b',') => self.i += 1,
+            Some(b'}') => {
+              self.i += 1;
+              return Ok(());
+            }
+            _ => return Err(self.error("expected `,` or `}`")),
+          }
+        }
+      }
+      Some(b'[') => {
+        self.i += 1;
+        if self.at() == Some(b']') {
+          self.i += 1;
+          return Ok(());
+        }
+        loop {
+          self.value(depth + 1, skip)?;
+          match self.at() {
+            Some(b',') => self.i += 1,
+            Some(b']') => {
+              self.i += 1;
+              return Ok(());
+            }
+            _ => return Err(self.error("expected `,` or `]`")),
+          }
+        }
+      }
+      Some(b'"') => {
+        let (range, text) = self.string()?;
+        if !skip {
+          self.values.push(Value {
+            range,
+            text,
+            style: Style::Quoted,
+          });
+        }
+        Ok(())
+      }
+      Some(_) => {
+        let start = self.i;
+        while self
+          .b
+          .get(self.i)
+          .is_some_and(|c| !b",]} \t\r\n".contains(c))
+        {
+          self.i += 1;
+        }
+        if self.i == start {
+          return Err(self.error("unexpected character"));
+        }
+        Ok(())
+      }
+      None => Err(self.error("unexpected end of file")),
+    }
+  }
+}
+
+fn json(text: &str, arb: bool) -> Result<(Vec<Value>, Option<Range<usize>>), String> {
+  let mut json = Json {
+    text,
+    b: text.as_bytes(),
+    i: text.len() - text.trim_start_matches('\u{feff}').len(),
+    arb,
+    values: Vec::new(),
+    locale: None,
+  };
+  json.value(0, false)?;
+  if json.at().is_some() {
+    return Err(json.error("text after the end"));
+  }
+  Ok((json.values, json.locale))
+}
+
+/// Skips whitespace and `/* */` and `//` comments.
+fn strings_space(text: &str, mut i: usize) -> usize {
+  loop {
+    let rest = &text[i..];
+    let trimmed = rest.trim_start();
+    i += rest.len() - trimmed.len();
+    if trimmed.starts_with("/*") {
+      i += trimmed.find("*/").map_or(trimmed.len(), |n| n + 2);
+    } else if trimmed.starts_with("//") {
+      i += trimmed.find('\n').unwrap_or(trimmed.len());
+    } else {
+      return i;
+    }
+  }
+}
+
+fn strings(text: &str) -> Result<Vec<Value>, String> {
+  let b = text.as_bytes();
+  let error =
+    |at: usize, what: &str| format!("Invalid strings file at line {}: {what}", line_of(text, at));
+  let mut values = Vec::new();
+  let mut i = text.len() - text.trim_start_matches('\u{feff}').len();
+  loop {
+    i = strings_space(text, i);
+    if i >= b.len() {
+      return Ok(values);
+    }
+    i = match b[i] {
+      b'"' => quoted_end(b, i).ok_or_else(|| error(i, "unterminated key"))?,
+      _ => {
+        i + text[i..]
+          .find(|c: char| c.is_whitespace() || c == '=')
+          .unwrap_or(0)
+      }
+    };
+    i = strings_space(text, i);
+    if b.get(i) != Some(&b'=') {
+      return Err(error(i, "expected `=`"));
+    }
+    i = strings_space(text, i + 1);
+    if b.get(i) != Some(&b'"') {
+      return Err(error(i, "expected a string"));
+    }
+    let end = quoted_end(b, i).ok_or_else(|| error(i, "unterminated string"))?;
+    let value = unescape(&text[i + 1..end - 1]).ok_or_else(|| error(i, "bad escape"))?;
+    values.push(Value {
+      range: i..end,
+      text: value,
+      style: Style::Quoted,
+    });
+    i = strings_space(text, end);
+    if b.get(i) != Some(&b';') {
+      return Err(error(i, "expected `;`"));
+    }
+    i += 1;
+  }
+}
+
+/// Booleans, nulls and numbers, which YAML does not read as strings.
+fn yaml_literal(text: &str) -> bool {
+  let lower = text.to_ascii_lowercase();
+  matches!(
+    lower.as_str(),
+    "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~"
+  ) || lower.parse::<f64>().is_ok()
+    || lower.starts_with("0x")
+}
+
+/// The length of the mapping key `rest` starts with and the position
+/// after its `:`.
+fn yaml_key(rest: &str) -> Option<(usize, usize)> {
+  let b = rest.as_bytes();
+  let len = match b.first()? {
+    b'"' => quoted_end(b, 0)?,
+    b'\'' => rest[1..].find('\'')? + 2,
+    b'[' | b'{' | b'#' | b'&' | b'*' | b'!' | b'|' | b'>' => return None,
+    _ => (0..b.len()).find(|&i| b[i] == b':' && matches!(b.get(i + 1), None | Some(b' ')))?,
+  };
+  (b.get(len) == Some(&b':') && matches!(b.get(len + 1), None | Some(b' ')))
+    .then_some((len, len + 1))
+}
+
+fn yaml(text: &str) -> (Vec<Value>, Option<Range<usize>>) {
+  let mut lines = Vec::new();
+  let mut start = 0;
+  for line in text.split_inclusive('\n') {
+    lines.push((start, line.trim_end_matches(['\n', '\r'])));
+    start += line.len();
+  }
+  let indent_of = |line: &str| line.len() - line.trim_start_matches(' ').len();
+  let mut values = Vec::new();
+  // top-level keys, and whether each holds a mapping
+  let mut roots = Vec::new();
+  let mut n = 0;
+  while n < lines.len() {
+    let (start, line) = lines[n];
+    n += 1;
+    let indent = indent_of(line);
+    let content = line[indent..].trim_start_matches('\u{feff}');
+    if content.is_empty() || content.starts_with(['#', '%']) || content.starts_with("---") {
+      continue;
+    }
+    let mut at = line.len() - content.len();
+    let mut item = false;
+    while &line[at..] == "-" || line[at..].starts_with("- ") {
+      item = true;
+      at += 1;
+      at += indent_of(&line[at..]);
+    }
+    if at >= line.len() {
+      continue;
+    }
+    // the column the value's continuation lines must be indented past
+    let column = at;
+    let at = match yaml_key(&line[at..]) {
+      Some((len, after)) => {
+        let rest = line[at + after..].trim();
+        if indent == 0 && !item {
+          roots.push((
+            start + at..start + at + len,
+            rest.is_empty() || rest.starts_with('#'),
+          ));
+        }
+        at + after
+      }
+      None if item => at,
+      // continuation lines of multi-line scalars, left as they are
+      None => continue,
+    };
+    let at = at + indent_of(&line[at..]);
+    let rest = &line[at..];
+    let Some(&first) = rest.as_bytes().first() else {
+      continue;
+    };
+    let (range, value, style) = match first {
+      b'|' | b'>' => {
+        let header = rest[1..].split('#').next().unwrap_or_default().trim();
+        if !header.chars().all(|c| matches!(c, '-' | '+' | '0'..='9')) {
+          continue;
+        }
+        let Some(block_indent) = lines[n..]
+          .iter()
+          .find(|(_, l)| !l.trim().is_empty())
+          .map(|(_, l)| indent_of(l))
+          .filter(|&i| i > indent)
+        else {
+          continue;
+        };
+        let mut last = None;
+        let mut m = n;
+        while m < lines.len() {
+          let l = lines[m].1;
+          if !l.trim().is_empty() {
+            if indent_of(l) < block_indent {
+              break;
+            }
+            last = Some(m);
+          }
+          m += 1;
+        }
+        let Some(last) = last else {
+          continue;
+        };
+        let folded = first == b'>';
+        let block = lines[n..=last]
+          .iter()
+          .map(|(_, l)| l.get(block_indent..).unwrap_or_default());
+        let mut value = String::new();
+        if folded {
+          for l in block {
+            if l.is_empty() {
+              value.push('\n');
+            } else {
+              if !value.is_empty() && !value.ends_with('\n') {
+                value.push(' ');
+              }
+              value.push_str(l);
+            }
+          }
+        } else {
+          value = block.collect::<Vec<_>>().join("\n");
+        }
+        let range = lines[n].0..lines[last].0 + lines[last].1.len();
+        n = last + 1;
+        let style = Style::Block {
+          indent: block_indent,
+          folded,
+        };
+        (range, value, style)
+      }
+      b'"' => {
+        let Some(end) = quoted_end(rest.as_bytes(), 0) else {
+          continue;
+        };
+        let Some(value) = unescape(&rest[1..end - 1]) else {
+          continue;
+        };
+        (start + at..start + at + end, value, Style::Double)
+      }
+      b'\'' => {
+        let b = rest.as_bytes();
+        let mut i = 1;
+        let end = loop {
+          match b.get(i) {
+            Some(b'\'') if b.get(i + 1) == Some(&b'\'') => i += 2,
+            Some(b'\'') => break Some(i + 1),
+            Some(_) => i += 1,
+            None => break None,
+          }
+        };
+        let Some(end) = end else {
+          continue;
+        };
+        let value = rest[1..end - 1].replace("''", "'");
+        (start + at..start + at + end, value, Style::Single)
+      }
+      b'[' | b'{' | b'&' | b'*' | b'!' | b'#' | b'@' | b'`' | b'%' => continue,
+      _ => {
+        let value = rest.split(" #").next().unwrap_or_default().trim_end();
+        // a plain scalar that goes on over the next lines
+        let continued = lines[n..]
+          .iter()
+          .find(|(_, l)| !l.trim().is_empty())
+          .is_some_and(|(_, l)| indent_of(l) > column && !l.trim_start().starts_with('#'));
+        if continued || yaml_literal(value) {
+          continue;
+        }
+        (
+          start + at..start + at + value.len(),
+          value.to_string(),
+          Style::Plain,
+        )
+      }
+    };
+    values.push(Value {
+      range,
+      text: value,
+      style,
+    });
+  }
+  let locale = match roots.as_slice() {
+    [(range, true)] => Some(range.clone()),
+    _ => None,
+  };
+  (values, locale)
+}
+
+fn parse(data: &[u8], kind: Kind) -> Result<Resource, String> {
+  let (text, encoding) = decode(data)?;
+  let (values, locale) = match kind {
+    Kind::Json => json(&text, false)?,
+    Kind::Arb => json(&text, true)?,
+    Kind::Yaml => yaml(&text),
+    Kind::Strings => (strings(&text)?, None),
+  };
+  Ok(Resource {
+    text,
+    encoding,
+    values,
+    locale,
+  })
+}
+
+fn locator(kind: Kind, range: &Range<usize>) -> String {
+  format!("{}:{}-{}", kind.prefix(), range.start, range.end)
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let kind = Kind::of(path).ok_or("Unsupported resource file")?;
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let resource = parse(&data, kind)?;
+  let mut out = Vec::new();
+  for value in &resource.values {
+    let (text, _) = formats(&value.text);
+    // numbers, placeholders alone and other values without words, and links
+    let link = value.text.contains("://") && !value.text.contains(char::is_whitespace);
+    if text.chars().any(char::is_alphabetic) && !link {
+      out.push(Segment {
+        locator: locator(kind, &value.range),
+        text,
+      });
+    }
+  }
+  Ok(out)
+}
+
+/// Whether YAML reads `text` unquoted as this same string.
+fn yaml_plain(text: &str) -> bool {
+  !text.is_empty()
+    && text == text.trim()
+    && !text.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+    && !text.contains(": ")
+    && !text.contains(" #")
+    && !text.ends_with(':')
+    && !text.contains(|c: char| c.is_control())
+    && !yaml_literal(text)
+}
+
+/// `text` written as `style` writes it.
+fn write(style: &Style, text: &str, newline: &str) -> String {
+  match style {
+    Style::Quoted | Style::Double => quote(text),
+    Style::Plain if yaml_plain(text) => text.to_string(),
+    Style::Plain => quote(text),
+    Style::Single if text.contains(|c: char| c.is_control()) => quote(text),
+    Style::Single => format!("'{}'", text.replace('\'', "''")),
+    &Style::Block { indent, folded } => {
+      let pad = " ".repeat(indent);
+      // a folded block reads one line break from a blank line
+      let breaks = if folded { "\n\n" } else { "\n" };
+      text
+        .trim_end_matches('\n')
+        .replace('\n', breaks)
+        .split('\n')
+        .map(|line| match line {
+          "" => String::new(),
+          line => format!("{pad}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join(newline)
+    }
+  }
+}
+
+/// The target language's code for a file's locale that names the source
+/// language of `direction`, as `kind` writes it.
+fn target_locale(current: &str, kind: Kind, direction: &str) -> Option<String> {
+  let (from, to) = direction.split_once("->")?;
+  let current = current.trim_matches(['"', '\'']).to_ascii_lowercase();
+  if !current.starts_with(from) {
+    return None;
+  }
+  Some(match (kind, to) {
+    (Kind::Arb, to) => quote(to),
+    (_, "zh") => "zh-CN".to_string(),
+    (_, to) => to.to_string(),
+  })
+}
+
+fn export(
+  app: &AppHandle,
+  job_id: &str,
+  path: &Path,
+  kind: Kind,
+) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let direction = segments::info(app, job_id)?.direction;
+  let translations = task.translations();
+  let resource = parse(&task.source, kind).map_err(invalid)?;
+  let newline = if resource.text.contains("\r\n") {
+    "\r\n"
+  } else {
+    "\n"
+  };
+
+  let mut warnings = Vec::new();
+  let mut replaced = Vec::new();
+  for value in &resource.values {
+    let sent = formats(&value.text).0;
+    let translation = translations
+      .get(locator(kind, &value.range).as_str())
+      // not translated yet
+      .filter(|t| **t != sent);
+    let Some(translation) = translation else {
+      continue;
+    };
+    match unlock(&value.text, translation) {
+      Some(text) => replaced.push((value.range.clone(), write(&value.style, &text, newline))),
+      None => warnings.push(Warning {
+        excerpt: value.text.chars().take(40).collect(),
+        message: "placeholders did not survive translation; left untranslated".to_string(),
+      }),
+    }
+  }
+  if let Some(range) = &resource.locale {
+    if let Some(locale) = target_locale(&resource.text[range.clone()], kind, &direction) {
+      replaced.push((range.clone(), locale));
+    }
+  }
+  replaced.sort_by_key(|(range, _)| range.start);
+
+  let mut out = String::with_capacity(resource.text.len());
+  let mut at = 0;
+  for (range, text) in &replaced {
+    out.push_str(&resource.text[at..range.start]);
+    out.push_str(text);
+    at = range.end;
+  }
+  out.push_str(&resource.text[at..]);
+  fs::write(path, encode(&out, resource.encoding))
+    .map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// [`export`] in the format the extension of `path` names.
+pub fn export_to(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let kind = Kind::of(path).ok_or_else(|| {
+    ProxyError::new(
+      "invalid-request",
+      format!("{} is not a resource file", path.display()),
+    )
+  })?;
+  export(app, job_id, path, kind)
+}
+
+/// Writes the translated JSON resources of task `job_id` to `path`. Returns
+/// the values left untranslated for losing a placeholder.
+#[tauri::command]
+pub async fn export_translated_json(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path), Kind::Json))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Writes the translated ARB file of task `job_id` to `path`, its
+/// `@@locale` set to the target language.
+#[tauri::command]
+pub async fn export_translated_arb(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path), Kind::Arb))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Writes the translated YAML resources of task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_yaml(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path), Kind::Yaml))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Writes the translated `.strings` file of task `job_id` to `path`, in
+/// the source's encoding.
+#[tauri::command]
+pub async fn export_translated_strings(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || {
+    export(&app, &job_id, Path::new(&path), Kind::Strings)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,157 @@
+//! Bilingual Word documents of a task, for reviewers who read source and
+//! translation together instead of in two files side by side. Any task
+//! can be written so, whatever its document type, in one of two layouts:
+//! `columns`, a table with a row per segment and the source on the left,
+//! or `interleaved`, each source paragraph in grey above its translation.
+//! Segments not translated yet keep an empty translation.
+//!
+//! The document is built from scratch in the plainest WordprocessingML, so
+//! Word and LibreOffice open it the same way.
+
+use quick_xml::escape::partial_escape;
+use std::{fs::File, io::Write, path::Path};
+use tauri::AppHandle;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{proxy::ProxyError, segments};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#;
+const RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+/// A4 with 2 cm margins.
+const SECTION: &str = r#"<w:sectPr><w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1134" w:right="1134" w:bottom="1134" w:left="1134" w:header="567" w:footer="567" w:gutter="0"/></w:sectPr>"#;
+/// Of the source in the interleaved layout.
+const SOURCE_COLOR: &str = "6E6E6E";
+
+#[derive(Clone, Copy)]
+enum Layout {
+  Columns,
+  Interleaved,
+}
+
+/// A run of `text`, line breaks kept, in `properties` (`<w:rPr>` content).
+fn run(text: &str, properties: &str) -> String {
+  let mut out = format!("<w:r><w:rPr>{properties}</w:rPr>");
+  for (i, line) in text.split('\n').enumerate() {
+    if i > 0 {
+      out.push_str("<w:br/>");
+    }
+    // characters XML 1.0 cannot hold at all
+    let line: String = line.chars().filter(|&c| c == '\t' || c >= ' ').collect();
+    out.push_str(&format!(
+      "<w:t xml:space=\"preserve\">{}</w:t>",
+      partial_escape(&line)
+    ));
+  }
+  out.push_str("</w:r>");
+  out
+}
+
+fn paragraph(text: &str, properties: &str, spacing_after: u32) -> String {
+  format!(
+    "<w:p><w:pPr><w:spacing w:after=\"{spacing_after}\"/></w:pPr>{}</w:p>",
+    run(text, properties)
+  )
+}
+
+fn cell(content: &str) -> String {
+  format!("<w:tc><w:tcPr><w:tcW w:w=\"2500\" w:type=\"pct\"/></w:tcPr>{content}</w:tc>")
+}
+
+fn document(app: &AppHandle, job_id: &str, layout: Layout) -> Result<String, ProxyError> {
+  let rows = segments::rows(app, job_id)?;
+  let info = segments::info(app, job_id)?;
+  let (source, target) = info
+    .direction
+    .split_once("->")
+    .unwrap_or(("source", "target"));
+  let mut body = paragraph(&info.filename, "<w:b/><w:sz w:val=\"28\"/>", 240);
+  match layout {
+    Layout::Columns => {
+      let border =
+        |side: &str| format!("<w:{side} w:val=\"single\" w:sz=\"4\" w:color=\"BFBFBF\"/>");
+      body.push_str(&format!(
+        "<w:tbl><w:tblPr><w:tblW w:w=\"5000\" w:type=\"pct\"/><w:tblBorders>{}</w:tblBorders><w:tblLayout w:type=\"fixed\"/></w:tblPr><w:tblGrid><w:gridCol w:w=\"4819\"/><w:gridCol w:w=\"4819\"/></w:tblGrid>",
+        ["top", "left", "bottom", "right", "insideH", "insideV"]
+          .map(border)
+          .concat()
+      ));
+      // repeated on every page
+      body.push_str(&format!(
+        "<w:tr><w:trPr><w:tblHeader/></w:trPr>{}{}</w:tr>",
+        cell(&paragraph(&format!("Source ({source})"), "<w:b/>", 0)),
+        cell(&paragraph(&format!("Target ({target})"), "<w:b/>", 0)),
+      ));
+      for row in &rows {
+        body.push_str(&format!(
+          "<w:tr><w:trPr><w:cantSplit/></w:trPr>{}{}</w:tr>",
+          cell(&paragraph(&row.source, "", 0)),
+          cell(&paragraph(
+            row.translation.as_deref().unwrap_or_default(),
+            "",
+            0
+          )),
+        ));
+      }
+      body.push_str("</w:tbl><w:p/>");
+    }
+    Layout::Interleaved => {
+      let grey = format!("<w:color w:val=\"{SOURCE_COLOR}\"/>");
+      for row in &rows {
+        body.push_str(&paragraph(&row.source, &grey, 0));
+        body.push_str(&paragraph(
+          row.translation.as_deref().unwrap_or_default(),
+          "",
+          240,
+        ));
+      }
+    }
+  }
+  Ok(format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\"><w:body>{body}{SECTION}</w:body></w:document>"
+  ))
+}
+
+fn write(path: &Path, document: &str) -> Result<(), String> {
+  let write_err = |e: &dyn std::fmt::Display| format!("Cannot write {}: {e}", path.display());
+  let mut zip = ZipWriter::new(File::create(path).map_err(|e| write_err(&e))?);
+  let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+  for (name, data) in [
+    ("[Content_Types].xml", CONTENT_TYPES),
+    ("_rels/.rels", RELS),
+    ("word/document.xml", document),
+  ] {
+    zip.start_file(name, options).map_err(|e| write_err(&e))?;
+    zip.write_all(data.as_bytes()).map_err(|e| write_err(&e))?;
+  }
+  zip.finish().map_err(|e| write_err(&e))?;
+  Ok(())
+}
+
+/// Writes task `job_id`, whatever its document type, to `path` as a
+/// bilingual Word document in `layout` (`columns` or `interleaved`).
+#[tauri::command]
+pub async fn export_bilingual_docx(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+  layout: String,
+) -> Result<(), ProxyError> {
+  let layout = match layout.as_str() {
+    "columns" => Layout::Columns,
+    "interleaved" => Layout::Interleaved,
+    other => {
+      return Err(ProxyError::new(
+        "invalid-request",
+        format!("Unknown layout {other}: use columns or interleaved"),
+      ))
+    }
+  };
+  tauri::async_runtime::spawn_blocking(move || {
+    let document = document(&app, &job_id, layout)?;
+    write(Path::new(&path), &document).map_err(|e| ProxyError::new("invalid-request", e))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
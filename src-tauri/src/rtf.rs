@@ -0,0 +1,660 @@
+//! RTF documents read and rebuilt in the shell, like `latex`: the
+//! translated copy is the source with each segment's text spliced in, so
+//! fonts, colours, styles, tables and page setup stay byte for byte.
+//!
+//! Segments are paragraphs and table cells. Inside one, a group of
+//! formatting around words (`{\b bold}`) goes to the translator as
+//! `⟦1⟧bold⟦/1⟧`, and formatting switched on or off between words
+//! (`\i`, `\i0`), fields, bookmarks and line breaks as `⟦2⟧`. A translation
+//! whose placeholders did not all survive keeps the source text and is
+//! reported. Font, colour and style tables, pictures, objects, document
+//! properties and anything marked `\*` are never translated.
+//!
+//! Text is read in the code page of its font (`\fcharset`) or of the
+//! document (`\ansicpg`), and from `\u` escapes; translations are written
+//! as `\u` escapes, which every reader since Word 97 understands.
+//!
+//! Locators are `rtf:<start>-<end>`, the byte range of the segment's text
+//! in the source.
+
+use encoding_rs::{
+  Encoding, BIG5, EUC_KR, GBK, MACINTOSH, SHIFT_JIS, UTF_8, WINDOWS_1252, WINDOWS_874,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  ops::Range,
+  path::Path,
+};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{balanced, marker, pieces, Piece, Role, Tag, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+/// Groups whose content is not text, besides those marked `\*`.
+const DESTINATIONS: &[&str] = &[
+  "fonttbl",
+  "colortbl",
+  "stylesheet",
+  "info",
+  "pict",
+  "object",
+  "listtable",
+  "listoverridetable",
+  "revtbl",
+  "rsidtbl",
+  "filetbl",
+  "generator",
+  "fldinst",
+  "themedata",
+  "colorschememapping",
+  "datastore",
+  "latentstyles",
+  "xmlnstbl",
+  "bkmkstart",
+  "bkmkend",
+  "nonshppict",
+  "shpinst",
+  "userprops",
+  "docvar",
+];
+/// Control words that end a paragraph, and so a segment.
+const BREAKS: &[&str] = &[
+  "par", "sect", "page", "column", "cell", "row", "nestcell", "nestrow",
+];
+/// Control words that stand for a character.
+const CHARACTERS: &[(&str, char)] = &[
+  ("emdash", '—'),
+  ("endash", '–'),
+  ("lquote", '‘'),
+  ("rquote", '’'),
+  ("ldblquote", '“'),
+  ("rdblquote", '”'),
+  ("bullet", '•'),
+  ("emspace", '\u{2003}'),
+  ("enspace", '\u{2002}'),
+  ("qmspace", '\u{2005}'),
+];
+
+enum Token<'a> {
+  Open,
+  Close,
+  /// A control word and its parameter.
+  Word(&'a str, Option<i32>),
+  /// `\` and a character other than a letter.
+  Symbol(u8),
+  /// `\'hh`, a byte in the current code page.
+  Hex(u8),
+  Byte(u8),
+}
+
+/// The token at `at` and where it ends; a control word's delimiting
+/// space belongs to it.
+fn token(b: &[u8], at: usize) -> (Token<'_>, usize) {
+  match b[at] {
+    b'{' => (Token::Open, at + 1),
+    b'}' => (Token::Close, at + 1),
+    b'\\' if b.get(at + 1).is_some_and(u8::is_ascii_alphabetic) => {
+      let mut end = at + 1;
+      while b.get(end).is_some_and(u8::is_ascii_alphabetic) {
+        end += 1;
+      }
+      let word = std::str::from_utf8(&b[at + 1..end]).unwrap_or_default();
+      let digits = end;
+      if b.get(end) == Some(&b'-') && b.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        end += 1;
+      }
+      while b.get(end).is_some_and(u8::is_ascii_digit) {
+        end += 1;
+      }
+      let param = std::str::from_utf8(&b[digits..end])
+        .ok()
+        .and_then(|p| p.parse().ok());
+      if b.get(end) == Some(&b' ') {
+        end += 1;
+      }
+      (Token::Word(word, param), end)
+    }
+    b'\\' if b.get(at + 1) == Some(&b'\'') => {
+      let hex = b
+        .get(at + 2..at + 4)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .and_then(|h| u8::from_str_radix(h, 16).ok());
+      match hex {
+        Some(byte) => (Token::Hex(byte), at + 4),
+        None => (Token::Symbol(b'\''), at + 2),
+      }
+    }
+    b'\\' if at + 1 < b.len() => (Token::Symbol(b[at + 1]), at + 2),
+    c => (Token::Byte(c), at + 1),
+  }
+}
+
+/// The end of the group opened at `at`.
+fn group_end(b: &[u8], at: usize) -> usize {
+  let mut depth = 0;
+  let mut i = at;
+  while i < b.len() {
+    match b[i] {
+      b'\\' => i += 1,
+      b'{' => depth += 1,
+      b'}' => {
+        depth -= 1;
+        if depth == 0 {
+          return i + 1;
+        }
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+  b.len()
+}
+
+fn codepage(number: i32) -> Option<&'static Encoding> {
+  match number {
+    936 => Some(GBK),
+    950 => Some(BIG5),
+    932 => Some(SHIFT_JIS),
+    949 => Some(EUC_KR),
+    874 => Some(WINDOWS_874),
+    1250..=1258 => Encoding::for_label(format!("windows-{number}").as_bytes()),
+    10000 => Some(MACINTOSH),
+    65001 => Some(UTF_8),
+    _ => None,
+  }
+}
+
+/// The code page of font character set `number`; `None` for the
+/// document's own.
+fn charset(number: i32) -> Option<&'static Encoding> {
+  codepage(match number {
+    128 => 932,
+    129 => 949,
+    134 => 936,
+    136 => 950,
+    161 => 1253,
+    162 => 1254,
+    163 => 1258,
+    177 => 1255,
+    178 => 1256,
+    186 => 1257,
+    204 => 1251,
+    222 => 874,
+    238 => 1250,
+    _ => return None,
+  })
+}
+
+enum Item {
+  /// Characters, and whether they are in the source as they are.
+  Text(String, bool),
+  /// Formatting, fields and the like; left out at a segment's edges.
+  Whole,
+  /// `{` and the formatting right after it.
+  Open,
+  /// The `}` of an `Open`, with its index when in the same run.
+  Close(Option<usize>),
+}
+
+struct Unit {
+  range: Range<usize>,
+  /// The text to translate, placeholders included.
+  text: String,
+  /// Placeholder `n` at `n - 1`.
+  tags: Vec<Tag>,
+}
+
+#[derive(Clone)]
+struct State {
+  /// Characters a `\u` escape is followed by for older readers.
+  uc: usize,
+  encoding: &'static Encoding,
+}
+
+struct Scanner<'a> {
+  b: &'a [u8],
+  /// Font number to its code page, `None` for the document's.
+  fonts: HashMap<i32, Option<&'static Encoding>>,
+  /// The document's code page.
+  ansi: &'static Encoding,
+  states: Vec<State>,
+  /// Per open group, its `Open` index in `run` if it opened there.
+  groups: Vec<Option<usize>>,
+  run: Vec<(Range<usize>, Item)>,
+  /// `\'hh` and 8-bit bytes not decoded yet, a character may take two.
+  bytes: Vec<u8>,
+  bytes_range: Range<usize>,
+  /// A `\u` escape for the first half of a surrogate pair, and where it
+  /// starts.
+  high: Option<(u16, usize)>,
+  out: Vec<Unit>,
+}
+
+impl Scanner<'_> {
+  fn state(&mut self) -> &mut State {
+    if self.states.is_empty() {
+      self.states.push(State {
+        uc: 1,
+        encoding: self.ansi,
+      });
+    }
+    self.states.last_mut().unwrap()
+  }
+
+  fn text(&mut self, range: Range<usize>, text: String, raw: bool) {
+    match self.run.last_mut() {
+      Some((last, Item::Text(before, true))) if raw && last.end == range.start => {
+        before.push_str(&text);
+        last.end = range.end;
+      }
+      _ => self.run.push((range, Item::Text(text, raw))),
+    }
+  }
+
+  fn flush(&mut self) {
+    if self.bytes.is_empty() {
+      return;
+    }
+    let encoding = self.state().encoding;
+    let text = encoding
+      .decode_without_bom_handling(&self.bytes)
+      .0
+      .into_owned();
+    self.bytes.clear();
+    let range = self.bytes_range.clone();
+    self.text(range, text, false);
+  }
+
+  fn byte(&mut self, byte: u8, range: Range<usize>) {
+    if self.bytes.is_empty() {
+      self.bytes_range = range;
+    } else {
+      self.bytes_range.end = range.end;
+    }
+    self.bytes.push(byte);
+  }
+
+  /// Formatting that changes how text is read.
+  fn control(&mut self, word: &str, param: Option<i32>) {
+    match word {
+      "uc" => self.state().uc = param.unwrap_or(1).max(0) as usize,
+      "ansicpg" => {
+        self.ansi = param.and_then(codepage).unwrap_or(WINDOWS_1252);
+        let ansi = self.ansi;
+        self.state().encoding = ansi;
+      }
+      "f" => {
+        let font = param.and_then(|n| self.fonts.get(&n).copied().flatten());
+        let encoding = font.unwrap_or(self.ansi);
+        self.state().encoding = encoding;
+      }
+      _ => {}
+    }
+  }
+
+  fn font_table(&mut self, range: Range<usize>) {
+    let mut at = range.start;
+    let mut font = None;
+    while at < range.end {
+      let (token, end) = token(self.b, at);
+      match token {
+        Token::Word("f", Some(number)) => {
+          font = Some(number);
+          self.fonts.entry(number).or_insert(None);
+        }
+        Token::Word("fcharset", Some(set)) => {
+          if let Some(font) = font {
+            self.fonts.insert(font, charset(set));
+          }
+        }
+        Token::Word("cpg", Some(page)) => {
+          if let Some(font) = font {
+            self.fonts.insert(font, codepage(page));
+          }
+        }
+        _ => {}
+      }
+      at = end;
+    }
+  }
+
+  /// A `\uN` escape at `start` and the characters that stand in for it;
+  /// returns where they end.
+  fn unicode(&mut self, start: usize, value: i32, mut end: usize) -> usize {
+    for _ in 0..self.state().uc {
+      if end >= self.b.len() {
+        break;
+      }
+      match token(self.b, end) {
+        (Token::Hex(_) | Token::Byte(_) | Token::Symbol(_), next) => end = next,
+        _ => break,
+      }
+    }
+    let unit = (value.rem_euclid(0x10000)) as u16;
+    match (unit, self.high.take()) {
+      (0xD800..=0xDBFF, _) => self.high = Some((unit, start)),
+      (0xDC00..=0xDFFF, Some((high, from))) => {
+        if let Some(c) = char::decode_utf16([high, unit]).next().and_then(Result::ok) {
+          self.text(from..end, c.to_string(), false);
+        }
+      }
+      _ => {
+        if let Some(c) = char::from_u32(unit as u32) {
+          self.text(start..end, c.to_string(), false);
+        }
+      }
+    }
+    end
+  }
+
+  fn end_run(&mut self) {
+    self.flush();
+    self.groups.iter_mut().for_each(|g| *g = None);
+    let run = std::mem::take(&mut self.run);
+    if let Some(unit) = unit(self.b, &run) {
+      self.out.push(unit);
+    }
+  }
+
+  fn scan(mut self) -> Vec<Unit> {
+    let b = self.b;
+    let mut at = 0;
+    while at < b.len() {
+      let (read, end) = token(b, at);
+      match read {
+        Token::Hex(byte) => {
+          self.byte(byte, at..end);
+          at = end;
+          continue;
+        }
+        Token::Byte(byte) if byte >= 0x80 => {
+          self.byte(byte, at..end);
+          at = end;
+          continue;
+        }
+        _ => self.flush(),
+      }
+      match read {
+        Token::Open => {
+          let next = (end < b.len()).then(|| token(b, end).0);
+          let destination = match next {
+            Some(Token::Symbol(b'*')) => true,
+            Some(Token::Word(word, _)) => DESTINATIONS.contains(&word),
+            _ => false,
+          };
+          if destination {
+            let close = group_end(b, at);
+            if matches!(next, Some(Token::Word("fonttbl", _))) {
+              self.font_table(at..close);
+            }
+            self.run.push((at..close, Item::Whole));
+            at = close;
+            continue;
+          }
+          let state = self.state().clone();
+          self.states.push(state);
+          // the group's formatting goes with its brace
+          let mut open_end = end;
+          while open_end < b.len() {
+            match token(b, open_end) {
+              (Token::Word(word, param), next)
+                if !BREAKS.contains(&word)
+                  && !matches!(word, "u" | "line" | "tab")
+                  && !CHARACTERS.iter().any(|(w, _)| *w == word) =>
+              {
+                self.control(word, param);
+                open_end = next;
+              }
+              _ => break,
+            }
+          }
+          self.groups.push(Some(self.run.len()));
+          self.run.push((at..open_end, Item::Open));
+          at = open_end;
+          continue;
+        }
+        Token::Close => {
+          if self.states.len() > 1 {
+            self.states.pop();
+          }
+          let index = self.groups.pop().flatten();
+          self.run.push((at..end, Item::Close(index)));
+        }
+        Token::Word(word, _) if BREAKS.contains(&word) => self.end_run(),
+        Token::Symbol(b'\n' | b'\r') => self.end_run(),
+        Token::Word("u", Some(value)) => {
+          at = self.unicode(at, value, end);
+          continue;
+        }
+        Token::Word(word, param) => match CHARACTERS.iter().find(|(w, _)| *w == word) {
+          Some((_, c)) => self.text(at..end, c.to_string(), false),
+          None => {
+            self.control(word, param);
+            self.run.push((at..end, Item::Whole));
+          }
+        },
+        Token::Symbol(c @ (b'\\' | b'{' | b'}')) => {
+          self.text(at..end, (c as char).to_string(), false)
+        }
+        Token::Symbol(b'~') => self.text(at..end, "\u{a0}".to_string(), false),
+        Token::Symbol(b'_') => self.text(at..end, "\u{2011}".to_string(), false),
+        // an optional hyphen
+        Token::Symbol(b'-') => self.text(at..end, String::new(), false),
+        Token::Symbol(_) => self.run.push((at..end, Item::Whole)),
+        // line ends in the source are not text
+        Token::Byte(b'\r' | b'\n') => {}
+        Token::Byte(c) => self.text(at..end, (c as char).to_string(), true),
+        Token::Hex(_) => {}
+      }
+      at = end;
+    }
+    self.end_run();
+    self.out
+  }
+}
+
+/// The segment of `run`, trimmed of whitespace, formatting and unpaired
+/// braces at its edges.
+fn unit(source: &[u8], run: &[(Range<usize>, Item)]) -> Option<Unit> {
+  let matched: HashSet<usize> = run
+    .iter()
+    .filter_map(|(_, item)| match item {
+      Item::Close(Some(index)) => Some(*index),
+      _ => None,
+    })
+    .collect();
+  let is_edge = |n: usize| match &run[n].1 {
+    Item::Text(text, _) => text.trim().is_empty(),
+    Item::Whole | Item::Close(None) => true,
+    Item::Open => !matched.contains(&n),
+    Item::Close(Some(_)) => false,
+  };
+  let first = (0..run.len()).find(|&n| !is_edge(n))?;
+  let last = (0..run.len()).rfind(|&n| !is_edge(n))?;
+  if !run[first..=last]
+    .iter()
+    .any(|(_, item)| matches!(item, Item::Text(text, _) if !text.trim().is_empty()))
+  {
+    return None;
+  }
+  let trimmed = |(range, item): &(Range<usize>, Item)| match item {
+    Item::Text(_, true) => {
+      let text = &source[range.clone()];
+      let start = text.iter().take_while(|c| c.is_ascii_whitespace()).count();
+      let end = text
+        .iter()
+        .rev()
+        .take_while(|c| c.is_ascii_whitespace())
+        .count();
+      range.start + start..range.end - end
+    }
+    _ => range.clone(),
+  };
+  let range = trimmed(&run[first]).start..trimmed(&run[last]).end;
+
+  let mut text = String::new();
+  let mut tags: Vec<Tag> = Vec::new();
+  // placeholder number of each paired opening, by run position
+  let mut numbers: HashMap<usize, usize> = HashMap::new();
+  for (n, (range, item)) in run.iter().enumerate().take(last + 1).skip(first) {
+    let role = match item {
+      Item::Text(t, _) => {
+        text.push_str(t);
+        continue;
+      }
+      Item::Close(Some(index)) => {
+        let number = numbers[index];
+        tags[number - 1].1 = Role::Element(range.clone());
+        text.push_str(&marker(number, true));
+        continue;
+      }
+      Item::Open if matched.contains(&n) => {
+        numbers.insert(n, tags.len() + 1);
+        // the closing brace comes later
+        Role::Whole
+      }
+      Item::Open => Role::Opens,
+      Item::Close(None) => Role::Closes,
+      Item::Whole => Role::Whole,
+    };
+    tags.push((range.clone(), role));
+    text.push_str(&marker(tags.len(), false));
+  }
+  Some(Unit {
+    range,
+    text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+    tags,
+  })
+}
+
+fn units(source: &[u8]) -> Vec<Unit> {
+  Scanner {
+    b: source,
+    fonts: HashMap::new(),
+    ansi: WINDOWS_1252,
+    states: Vec::new(),
+    groups: Vec::new(),
+    run: Vec::new(),
+    bytes: Vec::new(),
+    bytes_range: 0..0,
+    high: None,
+    out: Vec::new(),
+  }
+  .scan()
+}
+
+/// Translated text as RTF: markup characters escaped, the rest of ASCII as
+/// it is, and other characters as `\u` escapes in a group of their own
+/// that says one `?` follows each.
+fn escape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  let mut wide = false;
+  for c in text.chars() {
+    match c {
+      '\\' | '{' | '}' => {
+        out.push('\\');
+        out.push(c);
+      }
+      '\n' => out.push_str("\\line "),
+      '\t' => out.push_str("\\tab "),
+      c if c.is_ascii_control() => {}
+      c if c.is_ascii() => out.push(c),
+      c => {
+        wide = true;
+        for unit in c.encode_utf16(&mut [0; 2]) {
+          out.push_str(&format!("\\u{}?", *unit as i16));
+        }
+      }
+    }
+  }
+  if wide {
+    format!("{{\\uc1 {out}}}")
+  } else {
+    out
+  }
+}
+
+fn read(data: &[u8]) -> Result<&[u8], String> {
+  if data.starts_with(b"{\\rtf") {
+    Ok(data)
+  } else {
+    Err("Not an RTF document".to_string())
+  }
+}
+
+fn locator(range: &Range<usize>) -> String {
+  format!("rtf:{}-{}", range.start, range.end)
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  Ok(
+    units(read(&data)?)
+      .into_iter()
+      // numbers, lone placeholders and other segments without words
+      .filter(|u| u.text.chars().any(char::is_alphabetic))
+      .map(|u| Segment {
+        locator: locator(&u.range),
+        text: u.text,
+      })
+      .collect(),
+  )
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let translations = task.translations();
+  let source = read(&task.source).map_err(invalid)?;
+  let mut warnings = Vec::new();
+  let mut out = Vec::with_capacity(source.len());
+  let mut at = 0;
+  for unit in units(source) {
+    let Some(translation) = translations.get(locator(&unit.range).as_str()) else {
+      continue;
+    };
+    let pieces = pieces(translation);
+    if !balanced(&pieces, &unit.tags) {
+      warnings.push(Warning {
+        excerpt: unit.text.chars().take(40).collect(),
+        message: "placeholders for formatting did not survive translation; kept the source"
+          .to_string(),
+      });
+      continue;
+    }
+    out.extend_from_slice(&source[at..unit.range.start]);
+    for piece in pieces {
+      match piece {
+        Piece::Text(text) => out.extend_from_slice(escape(text).as_bytes()),
+        Piece::Tag(number, false) => {
+          out.extend_from_slice(&source[unit.tags[number - 1].0.clone()])
+        }
+        Piece::Tag(number, true) => {
+          if let (_, Role::Element(end)) = &unit.tags[number - 1] {
+            out.extend_from_slice(&source[end.clone()]);
+          }
+        }
+      }
+    }
+    at = unit.range.end;
+  }
+  out.extend_from_slice(&source[at..]);
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// Writes the translated copy of RTF task `job_id` to `path`. Returns the
+/// segments kept in the source language.
+#[tauri::command]
+pub async fn export_translated_rtf(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
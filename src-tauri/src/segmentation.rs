@@ -0,0 +1,477 @@
+//! Sentence segmentation and chunking, the same for every format. Texts
+//! are split into sentences by SRX-style rules: ordered pairs of patterns
+//! for the text before and after a position, the first pair matching
+//! there deciding whether it is a break. Exceptions for abbreviations of
+//! the source language come first, then the breaks after `.`, `!`, `?`
+//! and `…` followed by white space, after `。`, `！` and `？` (Chinese and
+//! Japanese do not space sentences), and at line breaks. Closing quotes
+//! and brackets stay with the sentence they end.
+//!
+//! Segments of over `max_segment_tokens` tokens are sent as chunks of
+//! whole sentences, a sentence too long for one chunk cut between words.
+//! A chunk's locator is its segment's with `~<n>` appended (`~<n>n` when a
+//! line break came before it); [`merge`] puts the translations back
+//! together for export. Nothing is cut inside a placeholder pair (see
+//! `placeholders`), so each chunk's markup comes back whole.
+//!
+//! Tokens are counted with tiktoken's BPE vocabularies: segment limits in
+//! `cl100k_base` ([`tokens`]), cost estimates in the encoding of the model
+//! when tiktoken knows it ([`tokens_for`]). Other models' tokens are
+//! estimated: text is split the way `cl100k_base` splits it first (words
+//! with their leading space, numbers of up to three digits, runs of
+//! punctuation, whitespace), each piece then costing what that vocabulary
+//! typically gives it: a common word is one token, longer or non-English
+//! words more, a CJK character a little over one.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Range, sync::OnceLock};
+use tauri::State;
+
+use crate::{
+  pdftext::is_cjk,
+  placeholders::{CLOSE, OPEN},
+  segments::Segment,
+  settings::SettingsState,
+};
+
+/// Abbreviations after which a full stop does not end a sentence.
+const ABBREVIATIONS: [(&str, &[&str]); 4] = [
+  (
+    "en",
+    &[
+      "Mr", "Mrs", "Ms", "Dr", "Prof", "Sr", "Jr", "St", "Mt", "vs", "e\\.g", "i\\.e", "cf", "Inc",
+      "Ltd", "Co", "Corp", "No", "Nos", "Fig", "Figs", "Vol", "pp", "approx", "Jan", "Feb", "Mar",
+      "Apr", "Jun", "Jul", "Aug", "Sep", "Sept", "Oct", "Nov", "Dec", "U\\.S", "a\\.m", "p\\.m",
+    ],
+  ),
+  (
+    "de",
+    &[
+      "z\\.B", "bzw", "usw", "Nr", "ca", "Dr", "Hr", "Fr", "vgl", "d\\.h", "u\\.a", "S", "Abb",
+      "evtl", "ggf", "inkl", "Jh",
+    ],
+  ),
+  (
+    "fr",
+    &[
+      "M", "MM", "Mme", "Mmes", "Mlle", "Dr", "p\\.ex", "cf", "env", "av", "apr", "n°", "vol",
+    ],
+  ),
+  (
+    "es",
+    &[
+      "Sr", "Sra", "Srta", "Dr", "Dra", "p\\.ej", "pág", "núm", "aprox", "etc", "Ud", "Uds",
+    ],
+  ),
+];
+const CLOSERS: &str = "\"'”’»)\\]」』）》〕】";
+
+/// One SRX rule: whether a position is a break when `before` matches the
+/// text up to it and `after` the text from it.
+struct Rule {
+  split: bool,
+  before: Regex,
+  /// Anchored at the position; `None` matches anything.
+  after: Option<Regex>,
+}
+
+impl Rule {
+  fn new(split: bool, before: &str, after: Option<&str>) -> Self {
+    Self {
+      split,
+      before: Regex::new(before).expect("segmentation rule"),
+      after: after.map(|a| Regex::new(&format!("^(?:{a})")).expect("segmentation rule")),
+    }
+  }
+}
+
+/// The rules for `lang`, e.g. `en` or `zh-CN`.
+fn rules(lang: &str) -> &'static [Rule] {
+  static RULES: OnceLock<Vec<(&str, Vec<Rule>)>> = OnceLock::new();
+  let all = RULES.get_or_init(|| {
+    let mut all: Vec<(&str, Vec<Rule>)> = ABBREVIATIONS
+      .iter()
+      .map(|(lang, words)| {
+        let words = words.join("|");
+        (
+          *lang,
+          vec![Rule::new(false, &format!(r"\b(?:{words})\."), Some(r"\s"))],
+        )
+      })
+      .chain([("", Vec::new())])
+      .collect();
+    for (_, rules) in &mut all {
+      rules.extend([
+        // initials, J. R. R. Tolkien
+        Rule::new(false, r"\b\p{Lu}\.", Some(r"\s+\p{Lu}")),
+        // a full stop followed by a lowercase word is no sentence end
+        Rule::new(false, &format!(r"\.[{CLOSERS}]*"), Some(r"\s+\p{Ll}")),
+        Rule::new(true, &format!(r"[.!?…]+[{CLOSERS}]*"), Some(r"\s")),
+        Rule::new(true, &format!(r"[。！？]+[{CLOSERS}]*"), None),
+        Rule::new(true, r"\n", None),
+      ]);
+    }
+    all
+  });
+  let primary = lang
+    .split(['-', '_'])
+    .next()
+    .unwrap_or_default()
+    .to_ascii_lowercase();
+  all
+    .iter()
+    .find(|(l, _)| *l == primary)
+    .or_else(|| all.iter().find(|(l, _)| l.is_empty()))
+    .map(|(_, rules)| rules.as_slice())
+    .unwrap_or_default()
+}
+
+/// Ranges of `text` nothing may be cut inside: each placeholder, and
+/// everything between the two of a pair.
+fn guarded(text: &str) -> Vec<Range<usize>> {
+  let mut out = Vec::new();
+  let mut open: Vec<(usize, usize)> = Vec::new();
+  let mut from = 0;
+  while let Some(start) = text[from..].find(OPEN).map(|i| from + i) {
+    let Some(end) = text[start..]
+      .find(CLOSE)
+      .map(|i| start + i + CLOSE.len_utf8())
+    else {
+      break;
+    };
+    let inside = &text[start + OPEN.len_utf8()..end - CLOSE.len_utf8()];
+    out.push(start..end);
+    match inside.strip_prefix('/').map(str::parse::<usize>) {
+      Some(Ok(number)) => {
+        if let Some(at) = open.iter().rposition(|(n, _)| *n == number) {
+          out.push(open[at].1..end);
+          open.truncate(at);
+        }
+      }
+      _ => {
+        if let Ok(number) = inside.parse() {
+          open.push((number, start));
+        }
+      }
+    }
+    from = end;
+  }
+  out
+}
+
+fn allowed(guards: &[Range<usize>], pos: usize) -> bool {
+  !guards.iter().any(|g| g.start < pos && pos < g.end)
+}
+
+/// Where `text` breaks into sentences, as byte offsets.
+fn breaks(text: &str, lang: &str) -> Vec<usize> {
+  let guards = guarded(text);
+  let mut decided = BTreeMap::new();
+  for rule in rules(lang) {
+    for m in rule.before.find_iter(text) {
+      let pos = m.end();
+      if pos == 0 || pos >= text.len() {
+        continue;
+      }
+      if rule.after.as_ref().is_none_or(|a| a.is_match(&text[pos..])) {
+        decided.entry(pos).or_insert(rule.split);
+      }
+    }
+  }
+  decided
+    .into_iter()
+    .filter(|&(pos, split)| split && allowed(&guards, pos))
+    .map(|(pos, _)| pos)
+    .collect()
+}
+
+/// Tokens per CJK character.
+const CJK_TOKENS: f64 = 1.3;
+
+/// Tokens of one piece of the split in [`estimated_tokens`].
+fn piece_tokens(piece: &str) -> f64 {
+  let mut cjk = 0;
+  let mut ascii_letters = 0;
+  let mut other_letters = 0;
+  let mut ascii_symbols = 0;
+  let mut other_symbols = 0;
+  for c in piece.chars() {
+    if c.is_whitespace() {
+    } else if is_cjk(c) && c.is_alphanumeric() {
+      cjk += 1;
+    } else if c.is_ascii_alphabetic() {
+      ascii_letters += 1;
+    } else if c.is_alphanumeric() {
+      other_letters += 1;
+    } else if c.is_ascii() {
+      ascii_symbols += 1;
+    } else {
+      other_symbols += 1;
+    }
+  }
+  if piece.chars().next().is_some_and(|c| c.is_ascii_digit()) {
+    return 1.0;
+  }
+  let letters = ascii_letters + 2 * other_letters;
+  let word = if letters == 0 {
+    0.0
+  } else {
+    (letters as f64 / 7.0).ceil()
+  };
+  // the leading space or symbol of a word goes with it
+  let symbols = if letters + cjk > 0 {
+    0.0
+  } else {
+    (ascii_symbols as f64 / 2.0).ceil() + other_symbols as f64
+  };
+  (word + cjk as f64 * CJK_TOKENS + symbols).max(1.0)
+}
+
+/// The token count of `text` in `cl100k_base`.
+pub fn tokens(text: &str) -> usize {
+  tiktoken_rs::cl100k_base_singleton().count_ordinary(text)
+}
+
+/// The token count of `text` for `model`, estimated for models tiktoken
+/// does not know.
+pub fn tokens_for(text: &str, model: &str) -> usize {
+  match tiktoken_rs::tokenizer::get_tokenizer(&model.to_ascii_lowercase())
+    .map(tiktoken_rs::bpe_for_tokenizer)
+  {
+    Some(Ok(bpe)) => bpe.count_ordinary(text),
+    _ => estimated_tokens(text),
+  }
+}
+
+/// The estimated token count of `text`.
+fn estimated_tokens(text: &str) -> usize {
+  // tiktoken's split pattern without its lookahead, which leaves the last
+  // space of a run to the next word; the count comes out the same
+  static PIECES: OnceLock<Regex> = OnceLock::new();
+  let pieces = PIECES.get_or_init(|| {
+    Regex::new(
+      r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+",
+    )
+    .unwrap()
+  });
+  pieces
+    .find_iter(text)
+    .map(|piece| piece_tokens(piece.as_str()))
+    .sum::<f64>()
+    .round() as usize
+}
+
+/// `range` of `text` without the white space around it.
+fn trimmed(text: &str, range: Range<usize>) -> Range<usize> {
+  let piece = &text[range.clone()];
+  let start = range.start + (piece.len() - piece.trim_start().len());
+  start..start + piece.trim().len()
+}
+
+/// The sentences of `text`, white space between them left out.
+pub fn sentences(text: &str, lang: &str) -> Vec<Range<usize>> {
+  let mut out = Vec::new();
+  let mut start = 0;
+  for end in breaks(text, lang).into_iter().chain([text.len()]) {
+    let range = trimmed(text, start..end);
+    if !range.is_empty() {
+      out.push(range);
+    }
+    start = end;
+  }
+  out
+}
+
+/// Cuts `range` of `text` into pieces of at most `max_tokens`, between
+/// words or after CJK characters and commas where it can. Counts add up
+/// across those places, as no word spans them.
+fn cut(text: &str, range: Range<usize>, max_tokens: usize, out: &mut Vec<Range<usize>>) {
+  let guards = guarded(text);
+  let mut start = range.start;
+  // the last place to cut at, and the tokens from `start` up to it
+  let mut last = range.start;
+  let mut counted = 0;
+  let mut prev = None;
+  let places = text[range.clone()]
+    .char_indices()
+    .map(|(offset, c)| (range.start + offset, Some(c)))
+    .chain([(range.end, None)]);
+  for (pos, c) in places {
+    let place = c.is_none()
+      || (prev.is_some_and(|p: char| p.is_whitespace() || is_cjk(p) || p == ',')
+        && c.is_some_and(|c| !c.is_whitespace())
+        && allowed(&guards, pos));
+    prev = c;
+    if !place {
+      continue;
+    }
+    let step = tokens(&text[last..pos]);
+    if counted + step > max_tokens && last > start {
+      out.push(trimmed(text, start..last));
+      start = last;
+      counted = 0;
+    }
+    counted += step;
+    last = pos;
+  }
+  out.push(trimmed(text, start..range.end));
+}
+
+/// `text` in chunks of whole sentences of at most `max_tokens` each,
+/// longer sentences [`cut`].
+pub fn chunks(text: &str, lang: &str, max_tokens: usize) -> Vec<Range<usize>> {
+  let mut out = Vec::new();
+  let mut current: Option<(Range<usize>, usize)> = None;
+  for sentence in sentences(text, lang) {
+    let count = tokens(&text[sentence.clone()]);
+    if let Some((range, counted)) = current.take() {
+      if counted + count <= max_tokens {
+        current = Some((range.start..sentence.end, counted + count));
+        continue;
+      }
+      out.push(range);
+    }
+    if count > max_tokens {
+      cut(text, sentence, max_tokens, &mut out);
+    } else {
+      current = Some((sentence, count));
+    }
+  }
+  out.extend(current.map(|(range, _)| range));
+  out
+}
+
+/// `segments` with those over `max_tokens` (when not 0) replaced by their
+/// chunks.
+pub fn split(segments: Vec<Segment>, lang: &str, max_tokens: usize) -> Vec<Segment> {
+  if max_tokens == 0 {
+    return segments;
+  }
+  let mut out = Vec::with_capacity(segments.len());
+  for segment in segments {
+    if tokens(&segment.text) <= max_tokens {
+      out.push(segment);
+      continue;
+    }
+    let mut end = 0;
+    for (n, chunk) in chunks(&segment.text, lang, max_tokens)
+      .into_iter()
+      .enumerate()
+    {
+      let newline = if segment.text[end..chunk.start].contains('\n') {
+        "n"
+      } else {
+        ""
+      };
+      out.push(Segment {
+        locator: format!("{}~{}{newline}", segment.locator, n + 1),
+        text: segment.text[chunk.clone()].to_string(),
+      });
+      end = chunk.end;
+    }
+  }
+  out
+}
+
+/// A chunk locator's segment locator, number and whether a line break
+/// came before it.
+fn parse_chunk(locator: &str) -> Option<(&str, usize, bool)> {
+  let (base, tail) = locator.rsplit_once('~')?;
+  let (number, newline) = match tail.strip_suffix('n') {
+    Some(number) => (number, true),
+    None => (tail, false),
+  };
+  Some((base, number.parse().ok()?, newline))
+}
+
+/// `blocks` (locator and text) with the chunks of a segment joined back
+/// into one block under the segment's locator.
+pub fn merge(blocks: Vec<(String, String)>) -> Vec<(String, String)> {
+  let mut out: Vec<(String, String)> = Vec::with_capacity(blocks.len());
+  let mut chunked = None;
+  for (locator, text) in blocks {
+    let Some((base, number, newline)) = parse_chunk(&locator) else {
+      chunked = None;
+      out.push((locator, text));
+      continue;
+    };
+    match out.last_mut() {
+      Some((last, joined)) if number > 1 && chunked.as_deref() == Some(base) && last == base => {
+        let text = text.trim();
+        let cjk =
+          joined.chars().last().is_some_and(is_cjk) || text.chars().next().is_some_and(is_cjk);
+        if newline {
+          joined.push('\n');
+        } else if !cjk && !joined.is_empty() {
+          joined.push(' ');
+        }
+        joined.push_str(text);
+      }
+      _ => {
+        chunked = Some(base.to_string());
+        out.push((base.to_string(), text.trim().to_string()));
+      }
+    }
+  }
+  out
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct SegmentOptions {
+  /// Defaults to `max_segment_tokens` in the settings.
+  max_tokens: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct Piece {
+  text: String,
+  /// Character offsets into the text, end exclusive.
+  start: usize,
+  end: usize,
+  tokens: usize,
+}
+
+#[derive(Serialize)]
+pub struct Segmentation {
+  sentences: Vec<Piece>,
+  /// What a segment of this text would be sent as.
+  chunks: Vec<Piece>,
+  max_tokens: usize,
+}
+
+/// How `text` in language `lang` splits into sentences and chunks.
+#[tauri::command]
+pub fn segment_text(
+  settings: State<SettingsState>,
+  text: String,
+  lang: String,
+  opts: Option<SegmentOptions>,
+) -> Segmentation {
+  let max_tokens = opts
+    .unwrap_or_default()
+    .max_tokens
+    .unwrap_or_else(|| settings.get().max_segment_tokens);
+  let chars = |byte: usize| text[..byte].chars().count();
+  let piece = |range: Range<usize>| Piece {
+    text: text[range.clone()].to_string(),
+    start: chars(range.start),
+    end: chars(range.end),
+    tokens: tokens(&text[range]),
+  };
+  let chunks = if max_tokens == 0 {
+    sentences(&text, &lang)
+      .into_iter()
+      .reduce(|a, b| a.start..b.end)
+      .into_iter()
+      .collect()
+  } else {
+    chunks(&text, &lang, max_tokens)
+  };
+  Segmentation {
+    sentences: sentences(&text, &lang).into_iter().map(piece).collect(),
+    chunks: chunks.into_iter().map(piece).collect(),
+    max_tokens,
+  }
+}
@@ -0,0 +1,351 @@
+//! Documents the shell segments itself instead of the backend: PDFs
+//! (`pdftext`), Word files (`docx`), PowerPoint decks (`pptx`), Excel
+//! workbooks (`xlsx`), OpenDocument files (`odf`), EPUB books (`epub`), images (`imagetext`), plain
+//! text (`plaintext`), CSV and TSV tables (`delimited`), subtitles
+//! (`subtitles`), Markdown (`markdown`), HTML (`html`), LaTeX (`latex`),
+//! RTF (`rtf`), gettext catalogs (`po`), XLIFF (`xliff`) and localization resources
+//! (`resources`). Their segments go up with the upload; for export, the
+//! shell fetches the task's source and its blocks back and rebuilds the
+//! document from the locators.
+//!
+//! Plain text, tables and subtitles can also be [`stream`]ed: read a
+//! buffer at a time and their segments handed on in batches, for files
+//! too large to hold.
+//!
+//! Documents translated by the shell itself (see `direct`) are [`hold`]
+//! as local tasks while they are exported, read back here in place of the
+//! backend's.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, io::BufReader, path::Path, sync::Mutex, time::Duration};
+use tauri::AppHandle;
+
+use crate::{
+  charset, delimited, docx, epub, formats, html, imagetext, latex, markdown, ocr, odf, pdfexport,
+  pdftext, plaintext, po, pptx, protected,
+  proxy::{self, ProxyError},
+  resources, rtf, segmentation, subtitles, transport, xliff, xlsx,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+const BLOCKS_PAGE: usize = 2000;
+/// Segments [`stream`] hands on at a time.
+const STREAM_BATCH: usize = 500;
+
+static LOCAL: Mutex<Option<HashMap<String, Local>>> = Mutex::new(None);
+
+/// A block of text to translate, as sent with an upload.
+#[derive(Serialize)]
+pub struct Segment {
+  /// Where the text is, in the format's own terms; see the format modules.
+  pub locator: String,
+  pub text: String,
+}
+
+/// The segments of the document at `path`, or `None` for formats the
+/// backend reads itself. `cell_range` narrows workbooks to some cells;
+/// `review` says what becomes of Word comments and tracked changes;
+/// `po_retranslate` sends catalog entries that have a translation too;
+/// `ocr` reads images and scanned PDF pages.
+pub fn extract(
+  path: &Path,
+  cell_range: Option<&str>,
+  review: docx::Review,
+  po_retranslate: bool,
+  ocr: &ocr::Session,
+) -> Result<Option<Vec<Segment>>, String> {
+  // by content, so misnamed files reach the right parser
+  let extension = formats::route(path);
+  let segments = match extension.as_str() {
+    "pdf" => pdftext::segments(path, ocr)?,
+    "docx" => docx::segments(path, review)?,
+    "pptx" => pptx::segments(path)?,
+    "xlsx" => xlsx::segments(path, cell_range)?,
+    "odt" | "ods" | "odp" => odf::segments(path)?,
+    "epub" => epub::segments(path)?,
+    "png" | "jpg" | "jpeg" => imagetext::segments(path, ocr)?,
+    "srt" | "vtt" => subtitles::segments(path)?,
+    "txt" => plaintext::segments(path)?,
+    "csv" | "tsv" => delimited::segments(path)?,
+    "md" | "markdown" => markdown::segments(path)?,
+    "html" | "htm" => html::segments(path)?,
+    "tex" => latex::segments(path)?,
+    "rtf" => rtf::segments(path)?,
+    "po" | "pot" => po::segments(path, po_retranslate)?,
+    "xlf" | "xliff" => xliff::segments(path)?,
+    "json" | "arb" | "yml" | "yaml" | "strings" => resources::segments(path)?,
+    _ => return Ok(None),
+  };
+  if segments.is_empty() {
+    return Err(match extension.as_str() {
+      "pdf" => "The PDF has no text to translate, not even on its scanned pages".to_string(),
+      "png" | "jpg" | "jpeg" => "No text was found in the image".to_string(),
+      "xlsx" if cell_range.is_some_and(|r| !r.trim().is_empty()) => {
+        "The selected cells have no text to translate".to_string()
+      }
+      "po" | "pot" if !po_retranslate => {
+        "Every message of the catalog is translated already".to_string()
+      }
+      _ => "The document has no text to translate".to_string(),
+    });
+  }
+  Ok(Some(segments))
+}
+
+/// Writes the translated copy of task `job_id` to `path`, rebuilt in the
+/// format its extension names (images with the translation drawn over
+/// them); returns how many segments came out with a warning.
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<usize, ProxyError> {
+  let none = |()| 0;
+  match formats::extension(path).as_str() {
+    "pdf" => pdfexport::export(app, job_id, path).map(none),
+    "docx" => docx::export(app, job_id, path).map(none),
+    "pptx" => pptx::export(app, job_id, path).map(none),
+    "xlsx" => xlsx::export(app, job_id, path).map(none),
+    "odt" | "ods" | "odp" => odf::export(app, job_id, path).map(none),
+    "epub" => epub::export(app, job_id, path).map(none),
+    "png" | "jpg" | "jpeg" => imagetext::export(app, job_id, path, false).map(none),
+    "srt" | "vtt" => subtitles::export_to(app, job_id, path).map(|w| w.len()),
+    "txt" => plaintext::export(app, job_id, path).map(none),
+    "csv" | "tsv" => delimited::export(app, job_id, path).map(none),
+    "md" | "markdown" => markdown::export(app, job_id, path).map(none),
+    "html" | "htm" => html::export(app, job_id, path).map(|w| w.len()),
+    "tex" => latex::export(app, job_id, path).map(|w| w.len()),
+    "rtf" => rtf::export(app, job_id, path).map(|w| w.len()),
+    "po" | "pot" => po::export(app, job_id, path).map(|w| w.len()),
+    "xlf" | "xliff" => xliff::export(app, job_id, path).map(|w| w.len()),
+    "json" | "arb" | "yml" | "yaml" | "strings" => {
+      resources::export_to(app, job_id, path).map(|w| w.len())
+    }
+    other => Err(ProxyError::new(
+      "invalid-request",
+      format!(".{other} documents cannot be written"),
+    )),
+  }
+}
+
+/// Whether long segments of documents read as `extension` may be sent in
+/// chunks (see `segmentation`): not image regions, subtitle cues, or the
+/// entries of catalogs, XLIFF files and resources, which are translated
+/// one for one.
+pub fn chunked(extension: &str) -> bool {
+  !matches!(
+    extension,
+    "png"
+      | "jpg"
+      | "jpeg"
+      | "srt"
+      | "vtt"
+      | "po"
+      | "pot"
+      | "xlf"
+      | "xliff"
+      | "json"
+      | "arb"
+      | "yml"
+      | "yaml"
+      | "strings"
+  )
+}
+
+/// Whether documents read as `extension` can be [`stream`]ed.
+pub fn streams(extension: &str) -> bool {
+  matches!(extension, "txt" | "csv" | "tsv" | "srt" | "vtt")
+}
+
+/// The segments of the document at `path`, read as `extension` (one that
+/// [`streams`]), handed to `batch` a few hundred at a time with the bytes
+/// of the file read so far. Returns how many there were.
+pub fn stream(
+  path: &Path,
+  extension: &str,
+  mut batch: impl FnMut(Vec<Segment>, u64) -> Result<(), String>,
+) -> Result<usize, String> {
+  let reader =
+    charset::Reader::open(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let read = reader.progress();
+  let mut reader = BufReader::new(reader);
+  let mut pending = Vec::with_capacity(STREAM_BATCH);
+  let mut count = 0;
+  let mut emit = |segment| {
+    pending.push(segment);
+    count += 1;
+    if pending.len() < STREAM_BATCH {
+      return Ok(());
+    }
+    batch(std::mem::take(&mut pending), read.get())
+  };
+  match extension {
+    "txt" => plaintext::stream(&mut reader, &mut emit)?,
+    "csv" | "tsv" => delimited::stream(&mut reader, &mut emit)?,
+    "srt" | "vtt" => subtitles::stream(&mut reader, extension, &mut emit)?,
+    _ => return Err(format!(".{extension} documents cannot be streamed")),
+  }
+  if !pending.is_empty() {
+    batch(pending, read.get())?;
+  }
+  Ok(count)
+}
+
+fn get(base: &str, path: &str) -> Result<Vec<u8>, ProxyError> {
+  let resp = transport::request("GET", &format!("{base}{path}"), &[], Vec::new(), TIMEOUT)
+    .map_err(|e| ProxyError::from_io(&e))?;
+  if !resp.is_success() {
+    return Err(ProxyError::from_response(&resp));
+  }
+  Ok(resp.body)
+}
+
+/// A task read back for export.
+pub struct Task {
+  /// The uploaded document.
+  pub source: Vec<u8>,
+  /// Each block's locator with its translation, or its source text where
+  /// there is none yet; chunks of a segment are joined back.
+  pub blocks: Vec<(String, String)>,
+}
+
+impl Task {
+  /// Each block's translation by its locator.
+  pub fn translations(&self) -> HashMap<&str, &str> {
+    self
+      .blocks
+      .iter()
+      .map(|(locator, text)| (locator.as_str(), text.as_str()))
+      .collect()
+  }
+}
+
+/// The backend's address, once `job_id` is known to be a task id.
+fn task_base(app: &AppHandle, job_id: &str) -> Result<String, ProxyError> {
+  if job_id.is_empty()
+    || !job_id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '_')
+  {
+    return Err(ProxyError::new(
+      "invalid-request",
+      format!("Invalid job id: {job_id}"),
+    ));
+  }
+  proxy::target_base(app, false)
+    .ok_or_else(|| ProxyError::new("not-ready", "backend is not running"))
+}
+
+fn json<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, ProxyError> {
+  serde_json::from_slice(body).map_err(|e| ProxyError::new("http", e.to_string()))
+}
+
+/// A block of a task as the backend lists it.
+#[derive(Clone)]
+pub struct Row {
+  pub id: String,
+  /// `None` for blocks the backend segmented itself.
+  pub locator: Option<String>,
+  /// `pending`, `translated` (by the model) or `edited` (by hand).
+  pub status: String,
+  pub source: String,
+  pub translation: Option<String>,
+}
+
+/// Every block of task `job_id`, in document order.
+pub fn rows(app: &AppHandle, job_id: &str) -> Result<Vec<Row>, ProxyError> {
+  if let Some(rows) = local(job_id, |task| task.rows.clone()) {
+    return Ok(rows);
+  }
+  let base = task_base(app, job_id)?;
+  let text = |row: &Value, key: &str| {
+    row[key]
+      .as_str()
+      .filter(|t| !t.trim().is_empty())
+      .map(str::to_string)
+  };
+  let mut out = Vec::new();
+  loop {
+    let body = get(
+      &base,
+      &format!(
+        "/api/tasks/{job_id}/blocks?offset={}&limit={BLOCKS_PAGE}",
+        out.len()
+      ),
+    )?;
+    let page: Vec<Value> = json(&body)?;
+    out.extend(page.iter().map(|row| Row {
+      id: row["id"].as_str().unwrap_or_default().to_string(),
+      locator: row["locator"].as_str().map(str::to_string),
+      status: row["status"].as_str().unwrap_or_default().to_string(),
+      source: row["source_text"].as_str().unwrap_or_default().to_string(),
+      translation: text(row, "translated_text"),
+    }));
+    if page.len() < BLOCKS_PAGE {
+      return Ok(out);
+    }
+  }
+}
+
+/// The document task `job_id` was created from.
+pub fn source(app: &AppHandle, job_id: &str) -> Result<Vec<u8>, ProxyError> {
+  if let Some(source) = local(job_id, |task| task.source.clone()) {
+    return Ok(source);
+  }
+  let base = task_base(app, job_id)?;
+  get(&base, &format!("/api/tasks/{job_id}/source"))
+}
+
+/// Task `job_id` as exports need it.
+pub fn fetch(app: &AppHandle, job_id: &str) -> Result<Task, ProxyError> {
+  let source = protected::source(app, job_id, source(app, job_id)?)?;
+  let blocks = rows(app, job_id)?
+    .into_iter()
+    .filter_map(|row| Some((row.locator?, row.translation.unwrap_or(row.source))))
+    .collect();
+  Ok(Task {
+    source,
+    blocks: segmentation::merge(blocks),
+  })
+}
+
+/// What the backend knows of a task besides its blocks.
+#[derive(Clone, Deserialize)]
+pub struct TaskInfo {
+  /// The uploaded document's name.
+  pub filename: String,
+  /// E.g. `en->zh`.
+  pub direction: String,
+}
+
+/// Task `job_id`'s name and direction.
+pub fn info(app: &AppHandle, job_id: &str) -> Result<TaskInfo, ProxyError> {
+  if let Some(info) = local(job_id, |task| task.info.clone()) {
+    return Ok(info);
+  }
+  let base = task_base(app, job_id)?;
+  json(&get(&base, &format!("/api/tasks/{job_id}"))?)
+}
+
+/// A document translated by the shell, as a task.
+pub struct Local {
+  pub info: TaskInfo,
+  pub source: Vec<u8>,
+  pub rows: Vec<Row>,
+}
+
+fn local<T>(job_id: &str, read: impl FnOnce(&Local) -> T) -> Option<T> {
+  LOCAL.lock().unwrap().as_ref()?.get(job_id).map(read)
+}
+
+/// Makes `task` readable as task `job_id` until it is [`release`]d.
+pub fn hold(job_id: &str, task: Local) {
+  LOCAL
+    .lock()
+    .unwrap()
+    .get_or_insert_with(HashMap::new)
+    .insert(job_id.to_string(), task);
+}
+
+pub fn release(job_id: &str) {
+  if let Some(tasks) = LOCAL.lock().unwrap().as_mut() {
+    tasks.remove(job_id);
+  }
+}
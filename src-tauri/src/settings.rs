@@ -0,0 +1,282 @@
+//! User preferences that the UI changes at run time, in
+//! `<data dir>/settings.json`. Unlike `startup.json` (read once at launch)
+//! these apply immediately: `update_settings` saves and broadcasts a
+//! `settings-changed` event, so every window shows the same values.
+//!
+//! The file carries a `version`; [`MIGRATIONS`] brings files written by
+//! older releases up to [`VERSION`] before they are parsed.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::{config, jobs, output};
+
+pub const VERSION: u32 = 1;
+const FILE: &str = "settings.json";
+const MIN_SEGMENT_TOKENS: usize = 16;
+
+/// `MIGRATIONS[n]` turns a version `n` document into version `n + 1`.
+const MIGRATIONS: &[fn(&mut Value)] = &[
+  // 0: written before the file was versioned; same fields
+  |_| {},
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  pub version: u32,
+  /// Default translation direction for new tasks, e.g. `en->zh`.
+  pub direction: String,
+  /// `system`, `light` or `dark`.
+  pub theme: String,
+  /// Folder the document picker opens in.
+  pub last_document_dir: Option<String>,
+  /// Folder translated documents were last saved to.
+  pub last_output_dir: Option<String>,
+  /// Encrypt block and translation-memory texts in the task database,
+  /// translation-cache entries and the paths and errors of queued jobs;
+  /// applied (and existing data converted) on backend restart, for jobs
+  /// already queued at the next launch.
+  pub encrypt_at_rest: bool,
+  /// Subtitle limits translations are wrapped to and checked against, in
+  /// columns (CJK characters count two): per line, lines per cue, and
+  /// columns per second a cue is shown.
+  pub subtitle_line_width: usize,
+  pub subtitle_max_lines: usize,
+  pub subtitle_max_cps: f64,
+  /// Send gettext entries that already have a translation too, not only
+  /// empty and fuzzy ones.
+  pub po_retranslate: bool,
+  /// Tesseract languages scanned pages are read in, e.g. `chi_tra+eng`;
+  /// empty for the task's source language.
+  pub ocr_languages: String,
+  /// Names translated copies saved without a path, see `output`.
+  pub output_template: String,
+  /// Where they go; `None` for next to the document translated.
+  pub output_dir: Option<String>,
+  /// When the file exists: `overwrite`, `skip` or `rename`.
+  pub output_conflict: String,
+  /// Segments of more tokens are sent in chunks of sentences,
+  /// see `segmentation`; 0 sends them whole.
+  pub max_segment_tokens: usize,
+  /// Lowest score (50 to 100) a translation memory match is reused at,
+  /// see `tmx`; 0 for no lookups. Applied on backend restart.
+  pub tm_min_match: u32,
+  /// Segments a task translates at once, up to `jobs::MAX_CONCURRENCY`,
+  /// unless its job says; sent with every run the shell starts.
+  pub segment_concurrency: u32,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      version: VERSION,
+      direction: "en->zh".to_string(),
+      theme: "system".to_string(),
+      last_document_dir: None,
+      last_output_dir: None,
+      encrypt_at_rest: false,
+      subtitle_line_width: 42,
+      subtitle_max_lines: 2,
+      subtitle_max_cps: 17.0,
+      po_retranslate: false,
+      ocr_languages: String::new(),
+      output_template: "{stem}.{target_lang}.{ext}".to_string(),
+      output_dir: None,
+      output_conflict: "rename".to_string(),
+      max_segment_tokens: 400,
+      tm_min_match: 75,
+      segment_concurrency: 1,
+    }
+  }
+}
+
+impl Settings {
+  /// `self` with the keys present in `doc` replaced.
+  fn with(&self, doc: Value) -> serde_json::Result<Self> {
+    let mut full = serde_json::to_value(self)?;
+    config::merge(&mut full, doc);
+    serde_json::from_value(full)
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    if !matches!(self.direction.as_str(), "en->zh" | "zh->en") {
+      return Err(format!("Unsupported direction {}", self.direction));
+    }
+    if !matches!(self.theme.as_str(), "system" | "light" | "dark") {
+      return Err(format!(
+        "Unknown theme {}: use system, light or dark",
+        self.theme
+      ));
+    }
+    if self.subtitle_line_width == 0 || self.subtitle_max_lines == 0 || self.subtitle_max_cps <= 0.0
+    {
+      return Err("Subtitle limits must be positive".to_string());
+    }
+    if !self
+      .ocr_languages
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '+')
+    {
+      return Err(format!(
+        "Invalid OCR languages {}: use Tesseract codes joined by +, e.g. chi_tra+eng",
+        self.ocr_languages
+      ));
+    }
+    output::validate_template(&self.output_template)?;
+    if !matches!(
+      self.output_conflict.as_str(),
+      "overwrite" | "skip" | "rename"
+    ) {
+      return Err(format!(
+        "Unknown output conflict rule {}: use overwrite, skip or rename",
+        self.output_conflict
+      ));
+    }
+    if (1..MIN_SEGMENT_TOKENS).contains(&self.max_segment_tokens) {
+      return Err(format!(
+        "Segments must be allowed at least {MIN_SEGMENT_TOKENS} tokens, or 0 to send them whole"
+      ));
+    }
+    if (1..50).contains(&self.tm_min_match) || self.tm_min_match > 100 {
+      return Err(format!(
+        "Translation memory matches must score 50 to 100 to be reused, or 0 for none; got {}",
+        self.tm_min_match
+      ));
+    }
+    jobs::check_concurrency(self.segment_concurrency)?;
+    if self
+      .output_dir
+      .as_deref()
+      .is_some_and(|dir| !Path::new(dir).is_absolute())
+    {
+      return Err("The output folder must be an absolute path".to_string());
+    }
+    Ok(())
+  }
+}
+
+pub struct SettingsState {
+  path: PathBuf,
+  /// What missing or reset keys fall back to.
+  defaults: Settings,
+  current: Mutex<Settings>,
+}
+
+/// Upgrades `doc` in place; documents from newer releases are left alone
+/// and read as far as this one understands them.
+fn migrate(doc: &mut Value) {
+  let mut version = doc["version"].as_u64().unwrap_or(0) as usize;
+  while let Some(step) = MIGRATIONS.get(version) {
+    step(doc);
+    version += 1;
+    doc["version"] = Value::from(version);
+  }
+}
+
+/// Writes next to the target and renames, so a crash never leaves a
+/// half-written file behind.
+fn write_atomic(path: &Path, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  let tmp = path.with_extension("json.tmp");
+  fs::write(&tmp, serde_json::to_vec_pretty(settings)?)?;
+  fs::rename(&tmp, path)?;
+  Ok(())
+}
+
+impl SettingsState {
+  /// Reads (and if needed migrates) the settings file; a missing or
+  /// unreadable file yields the defaults, with `default_direction` from the
+  /// startup config.
+  pub fn load(data_dir: &Path, default_direction: &str) -> Self {
+    let path = data_dir.join(FILE);
+    let mut defaults = Settings {
+      direction: default_direction.to_string(),
+      ..Default::default()
+    };
+    if defaults.validate().is_err() {
+      defaults = Settings::default();
+    }
+    let mut current = fs::read_to_string(&path)
+      .ok()
+      .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+      .filter(Value::is_object)
+      .and_then(|mut doc| {
+        let stored = doc["version"].as_u64().unwrap_or(0);
+        migrate(&mut doc);
+        let settings = defaults.with(doc).ok()?;
+        if stored < VERSION as u64 {
+          let _ = write_atomic(&path, &settings);
+        }
+        Some(settings)
+      })
+      .unwrap_or_else(|| defaults.clone());
+    if current.validate().is_err() {
+      current = defaults.clone();
+    }
+    Self {
+      path,
+      defaults,
+      current: Mutex::new(current),
+    }
+  }
+
+  pub fn get(&self) -> Settings {
+    self.current.lock().unwrap().clone()
+  }
+
+  /// Applies `patch` (a JSON merge patch with the keys to change), saves
+  /// the result and emits `settings-changed` with the new settings.
+  pub fn update(&self, app: &AppHandle, patch: Value) -> Result<Settings, String> {
+    if !patch.is_object() {
+      return Err("patch must be an object".to_string());
+    }
+    if patch.get("version").is_some() {
+      return Err("version cannot be changed".to_string());
+    }
+    let mut current = self.current.lock().unwrap();
+    let mut doc = serde_json::to_value(&*current).map_err(|e| e.to_string())?;
+    if let Some(unknown) = patch
+      .as_object()
+      .unwrap()
+      .keys()
+      .find(|k| doc.get(k.as_str()).is_none())
+    {
+      return Err(format!("Unknown setting: {unknown}"));
+    }
+    config::merge(&mut doc, patch);
+    let mut updated = self.defaults.with(doc).map_err(|e| e.to_string())?;
+    updated.version = VERSION;
+    updated.validate()?;
+    if updated != *current {
+      write_atomic(&self.path, &updated).map_err(|e| format!("Cannot save settings: {e}"))?;
+      *current = updated.clone();
+      let _ = app.emit("settings-changed", &updated);
+    }
+    Ok(updated)
+  }
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<SettingsState>) -> Settings {
+  state.get()
+}
+
+/// See [`SettingsState::update`]; e.g. `{"theme": "dark"}`, or
+/// `{"theme": null}` to go back to the default.
+#[tauri::command]
+pub fn update_settings(
+  app: AppHandle,
+  state: State<SettingsState>,
+  patch: Value,
+) -> Result<Settings, String> {
+  state.update(&app, patch)
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Lifecycle of the primary backend. Changes are emitted as
+/// `backend-state` events.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BackendStatus {
+  #[default]
+  NotStarted,
+  Launching,
+  WaitingHealth,
+  Ready {
+    base_url: String,
+  },
+  /// Still serving, but health probes are slow or failing.
+  Degraded {
+    base_url: String,
+  },
+  /// Exited unexpectedly or failed its handshake; `code` is the exit code
+  /// if the process ended.
+  Crashed {
+    code: Option<i32>,
+  },
+}
+
+impl BackendStatus {
+  /// URL to send requests to, if the backend is serving.
+  pub fn base_url(&self) -> Option<&str> {
+    match self {
+      Self::Ready { base_url } | Self::Degraded { base_url } => Some(base_url),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct BackendState(Mutex<BackendStatus>);
+
+impl BackendState {
+  pub fn get(&self) -> BackendStatus {
+    self.0.lock().unwrap().clone()
+  }
+
+  pub fn base_url(&self) -> Option<String> {
+    self.0.lock().unwrap().base_url().map(str::to_string)
+  }
+}
+
+/// Replaces the status, emitting `backend-state` if it changed.
+pub fn set(app: &AppHandle, status: BackendStatus) {
+  update(app, |_| Some(status));
+}
+
+/// Applies `f` to the current status under the lock; `None` keeps it.
+pub fn update(app: &AppHandle, f: impl FnOnce(&BackendStatus) -> Option<BackendStatus>) {
+  let state = app.state::<BackendState>();
+  let changed = {
+    let mut current = state.0.lock().unwrap();
+    match f(&current) {
+      Some(next) if next != *current => {
+        *current = next.clone();
+        Some(next)
+      }
+      _ => None,
+    }
+  };
+  if let Some(status) = changed {
+    let _ = app.emit("backend-state", status);
+  }
+}
+
+#[tauri::command]
+pub fn get_backend_status(state: State<BackendState>) -> BackendStatus {
+  state.get()
+}
@@ -0,0 +1,236 @@
+//! Streaming variant of [`crate::proxy`]: the response body is relayed to
+//! the webview as `stream-chunk` events while it arrives, then a single
+//! `stream-done`. Server-sent events are split into one chunk per event.
+
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  io::Read,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{
+  keychain,
+  logs::BackendLog,
+  proxy::{self, ProxyError, ProxyRequest},
+  transport::{self, Abort},
+};
+
+/// Longest gap between two chunks before the stream is considered dead.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Aborts of the streams still in flight, by caller-chosen id.
+#[derive(Default)]
+pub struct StreamState(Mutex<HashMap<String, Arc<Abort>>>);
+
+#[derive(Clone, Serialize)]
+struct StreamChunk<'a> {
+  id: &'a str,
+  /// SSE `event:` name; `None` for plain chunked bodies.
+  event: Option<String>,
+  data: String,
+}
+
+#[derive(Clone, Serialize)]
+struct StreamDone<'a> {
+  id: &'a str,
+  cancelled: bool,
+  error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct StreamStarted {
+  pub status: u16,
+  pub content_type: Option<String>,
+}
+
+/// Splits the valid UTF-8 prefix off `buf`, keeping a trailing partial
+/// character for the next read.
+fn take_utf8(buf: &mut Vec<u8>) -> String {
+  let valid = match std::str::from_utf8(buf) {
+    Ok(s) => s.len(),
+    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+    Err(_) => buf.len(),
+  };
+  let rest = buf.split_off(valid);
+  let text = String::from_utf8_lossy(buf).into_owned();
+  *buf = rest;
+  text
+}
+
+/// Parses one SSE event block into (`event`, joined `data` lines).
+fn parse_event(block: &str) -> Option<(Option<String>, String)> {
+  let mut event = None;
+  let mut data: Vec<&str> = Vec::new();
+  for line in block.lines() {
+    let (field, value) = line.split_once(':').unwrap_or((line, ""));
+    let value = value.strip_prefix(' ').unwrap_or(value);
+    match field {
+      "event" => event = Some(value.to_string()),
+      "data" => data.push(value),
+      _ => {}
+    }
+  }
+  (!data.is_empty()).then(|| (event, data.join("\n")))
+}
+
+fn relay(app: &AppHandle, id: &str, mut body: Box<dyn Read + Send>, sse: bool, abort: &Abort) {
+  let emit = |event: Option<String>, data: String| {
+    let _ = app.emit("stream-chunk", StreamChunk { id, event, data });
+  };
+  let mut pending = Vec::new();
+  let mut text = String::new();
+  let mut buf = [0u8; 8192];
+  let error = loop {
+    match body.read(&mut buf) {
+      Ok(0) => break None,
+      Ok(n) => pending.extend_from_slice(&buf[..n]),
+      Err(_) if abort.is_aborted() => break None,
+      Err(e) => break Some(e.to_string()),
+    }
+    if abort.is_aborted() {
+      break None;
+    }
+    let chunk = take_utf8(&mut pending);
+    if !sse {
+      emit(None, chunk);
+      continue;
+    }
+    text.push_str(&chunk.replace("\r\n", "\n"));
+    while let Some(end) = text.find("\n\n") {
+      let block: String = text.drain(..end + 2).collect();
+      if let Some((event, data)) = parse_event(&block) {
+        emit(event, data);
+      }
+    }
+  };
+  if sse && error.is_none() {
+    if let Some((event, data)) = parse_event(&text) {
+      emit(event, data);
+    }
+  }
+  if let Some(e) = &error {
+    app
+      .state::<BackendLog>()
+      .append("proxy", format!("stream {id} failed: {e}").as_bytes());
+  }
+  let _ = app.emit(
+    "stream-done",
+    StreamDone {
+      id,
+      cancelled: abort.is_aborted(),
+      error,
+    },
+  );
+}
+
+fn start(app: &AppHandle, id: String, req: ProxyRequest) -> Result<StreamStarted, ProxyError> {
+  if !req.path.starts_with('/') {
+    return Err(ProxyError::new(
+      "invalid-request",
+      format!("path must start with '/': {}", req.path),
+    ));
+  }
+  let Some(base) = proxy::target_base(app, req.balance) else {
+    return Err(ProxyError::new("not-ready", "backend is not running"));
+  };
+  let state = app.state::<StreamState>();
+  let abort = Arc::new(Abort::default());
+  {
+    let mut streams = state.0.lock().unwrap();
+    if streams.contains_key(&id) {
+      return Err(ProxyError::new(
+        "invalid-request",
+        format!("stream {id} is already running"),
+      ));
+    }
+    streams.insert(id.clone(), abort.clone());
+  }
+  let forget = |app: &AppHandle, id: &str| {
+    app.state::<StreamState>().0.lock().unwrap().remove(id);
+  };
+
+  let idle = req
+    .timeout_ms
+    .map(Duration::from_millis)
+    .unwrap_or(IDLE_TIMEOUT);
+  let permit = match proxy::throttle(app, &req, idle) {
+    Ok(permit) => permit,
+    Err(e) => {
+      forget(app, &id);
+      return Err(e);
+    }
+  };
+  let headers = keychain::request_headers(req.headers, req.provider.as_deref());
+  let body = req.body.unwrap_or_default();
+  let url = format!("{base}{}", req.path);
+  let (head, mut reader) = match transport::open_stream(
+    &req.method.to_ascii_uppercase(),
+    &url,
+    &headers,
+    &body,
+    idle,
+    &abort,
+  ) {
+    Ok(opened) => opened,
+    Err(e) => {
+      forget(app, &id);
+      return Err(ProxyError::from_io(&e));
+    }
+  };
+
+  if !(200..300).contains(&head.status) {
+    let mut body = Vec::new();
+    let _ = reader.read_to_end(&mut body);
+    forget(app, &id);
+    return Err(ProxyError::from_response(&transport::Response {
+      status: head.status,
+      headers: head.headers,
+      body,
+    }));
+  }
+
+  let content_type = head.header("content-type").map(str::to_string);
+  let sse = content_type
+    .as_deref()
+    .is_some_and(|ct| ct.starts_with("text/event-stream"));
+  let app = app.clone();
+  std::thread::spawn(move || {
+    relay(&app, &id, reader, sse, &abort);
+    forget(&app, &id);
+    drop(permit);
+  });
+  Ok(StreamStarted {
+    status: head.status,
+    content_type,
+  })
+}
+
+/// Starts a streamed request. Subscribe to `stream-chunk` / `stream-done`
+/// (filtered by `id`) before calling, since chunks may arrive before this
+/// returns.
+#[tauri::command]
+pub async fn proxy_stream(
+  app: AppHandle,
+  id: String,
+  request: ProxyRequest,
+) -> Result<StreamStarted, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || start(&app, id, request))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Aborts a running stream; it still ends with `stream-done` (`cancelled`).
+/// Returns false if no such stream is running.
+#[tauri::command]
+pub fn cancel_stream(state: State<StreamState>, id: String) -> bool {
+  match state.0.lock().unwrap().get(&id) {
+    Some(abort) => {
+      abort.abort();
+      true
+    }
+    None => false,
+  }
+}
@@ -0,0 +1,409 @@
+//! SRT and WebVTT subtitles read and rebuilt in the shell. Segments are
+//! the cues' text; numbers, timings, cue settings and VTT's header, NOTE,
+//! STYLE and REGION blocks are written back as they were, so the
+//! translation plays at the source's times.
+//!
+//! Translations are wrapped to the subtitle limits in the settings and any
+//! cue still over them (too many lines, a line too wide, read too fast) is
+//! reported. Widths count CJK characters as two columns, so one set of
+//! limits suits both directions.
+//!
+//! Files in other encodings than UTF-8 (GBK, Big5, …, see `charset`) are
+//! read in theirs and written as UTF-8 with a byte order mark.
+//!
+//! Locators are `srt:<cue>` and `vtt:<cue>`, cues counted from 0. Large
+//! files are segmented a cue at a time by [`stream`], to the same locators.
+
+use encoding_rs::UTF_8;
+use serde::Serialize;
+use std::{fs, io::BufRead, path::Path};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  charset,
+  pdftext::is_cjk,
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+  settings::SettingsState,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+  Srt,
+  Vtt,
+}
+
+impl Kind {
+  fn prefix(self) -> &'static str {
+    match self {
+      Kind::Srt => "srt",
+      Kind::Vtt => "vtt",
+    }
+  }
+}
+
+struct Cue {
+  /// Lines up to and including the timing line.
+  head: Vec<String>,
+  start_ms: u64,
+  end_ms: u64,
+  text: Vec<String>,
+}
+
+enum Block {
+  Cue(Cue),
+  /// The VTT header, comments, styles and anything else that is not a cue.
+  Other(Vec<String>),
+}
+
+struct Subtitles {
+  bom: bool,
+  crlf: bool,
+  blocks: Vec<Block>,
+}
+
+/// A cue over the limits after wrapping.
+#[derive(Serialize)]
+pub struct Warning {
+  /// 1-based, as players and editors number cues.
+  pub cue: usize,
+  /// The cue's start, e.g. `00:01:02,500`.
+  pub start: String,
+  pub message: String,
+}
+
+/// `hh:mm:ss,mmm` (SRT), `hh:mm:ss.mmm` or `mm:ss.mmm` (VTT).
+fn timestamp(text: &str) -> Option<u64> {
+  let (clock, millis) = text.trim().rsplit_once([',', '.'])?;
+  let mut seconds = 0;
+  for part in clock.split(':') {
+    seconds = seconds * 60 + part.parse::<u64>().ok()?;
+  }
+  Some(seconds * 1000 + millis.parse::<u64>().ok()?)
+}
+
+fn timing(line: &str) -> Option<(u64, u64)> {
+  let (start, rest) = line.split_once("-->")?;
+  let end = rest.split_whitespace().next()?;
+  Some((timestamp(start)?, timestamp(end)?))
+}
+
+/// The block of the non-blank `lines` between two blank ones.
+fn block(lines: Vec<String>) -> Block {
+  // the timing is the first line, or the second after a number or id
+  let at = lines
+    .iter()
+    .take(2)
+    .position(|l| l.contains("-->"))
+    .filter(|_| !lines[0].starts_with("NOTE"));
+  match at.and_then(|at| Some((at, timing(&lines[at])?))) {
+    Some((at, (start_ms, end_ms))) => {
+      let mut head = lines;
+      let text = head.split_off(at + 1);
+      Block::Cue(Cue {
+        head,
+        start_ms,
+        end_ms,
+        text,
+      })
+    }
+    None => Block::Other(lines),
+  }
+}
+
+fn parse(data: &[u8], kind: Kind) -> Result<Subtitles, String> {
+  let decoded = charset::decode(data);
+  // written back as UTF-8, marked so players do not take it for the old code page
+  let bom = decoded.bom || decoded.encoding != UTF_8;
+  let text = decoded.text.as_str();
+  if kind == Kind::Vtt && !text.starts_with("WEBVTT") {
+    return Err("Not a WebVTT file: it must start with WEBVTT".to_string());
+  }
+  let crlf = text.contains("\r\n");
+  let mut blocks = Vec::new();
+  let mut lines: Vec<String> = Vec::new();
+  for line in text.lines().chain(std::iter::once("")) {
+    if !line.trim().is_empty() {
+      lines.push(line.to_string());
+      continue;
+    }
+    if lines.is_empty() {
+      continue;
+    }
+    blocks.push(block(std::mem::take(&mut lines)));
+  }
+  Ok(Subtitles { bom, crlf, blocks })
+}
+
+fn cues(subtitles: &Subtitles) -> impl Iterator<Item = &Cue> {
+  subtitles.blocks.iter().filter_map(|b| match b {
+    Block::Cue(cue) => Some(cue),
+    Block::Other(_) => None,
+  })
+}
+
+/// Columns `text` takes on screen, leaving out `<i>`-style tags and SRT's
+/// `{\an8}` overrides.
+fn width(text: &str) -> usize {
+  let mut depth = 0;
+  let mut columns = 0;
+  for c in text.chars() {
+    match c {
+      '<' | '{' => depth += 1,
+      '>' | '}' if depth > 0 => depth -= 1,
+      _ if depth > 0 => {}
+      c if is_cjk(c) => columns += 2,
+      _ => columns += 1,
+    }
+  }
+  columns
+}
+
+/// The lines as one text to translate: wrapped lines join with a space,
+/// dialogue lines (`- ...`) stay on lines of their own.
+fn join(lines: &[String]) -> String {
+  let mut out = String::new();
+  for line in lines {
+    let line = line.trim();
+    if !out.is_empty() {
+      out.push(if line.starts_with('-') { '\n' } else { ' ' });
+    }
+    out.push_str(line);
+  }
+  out
+}
+
+/// Punctuation no line should start with.
+fn closes(c: char) -> bool {
+  "，。！？、；：）」』》—…,.!?;:)".contains(c)
+}
+
+/// `text` broken greedily into lines of at most `limit` columns, between
+/// words or between CJK characters; words wider than a line stay whole.
+fn wrap(text: &str, limit: usize) -> Vec<String> {
+  let mut out = Vec::new();
+  for paragraph in text.split('\n') {
+    // pieces that may start a line, each with whether a space precedes it
+    let mut pieces: Vec<(bool, String)> = Vec::new();
+    let mut space = false;
+    for c in paragraph.trim().chars() {
+      if c.is_whitespace() {
+        space = true;
+        continue;
+      }
+      match pieces.last_mut() {
+        Some((_, piece)) if !space && (closes(c) || !is_cjk(c) && !piece.ends_with(is_cjk)) => {
+          piece.push(c)
+        }
+        _ => {
+          pieces.push((space, c.to_string()));
+          space = false;
+        }
+      }
+    }
+    let mut line = String::new();
+    for (spaced, piece) in pieces {
+      let separator = if spaced && !line.is_empty() { " " } else { "" };
+      if !line.is_empty() && width(&line) + separator.len() + width(&piece) > limit {
+        out.push(std::mem::take(&mut line));
+      } else {
+        line.push_str(separator);
+      }
+      line.push_str(&piece);
+    }
+    out.push(line);
+  }
+  out
+}
+
+fn read(path: &Path, kind: Kind) -> Result<Subtitles, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  parse(&data, kind)
+}
+
+fn kind_of(path: &Path) -> Kind {
+  match path.extension().and_then(|e| e.to_str()) {
+    Some(e) if e.eq_ignore_ascii_case("vtt") => Kind::Vtt,
+    _ => Kind::Srt,
+  }
+}
+
+/// The segment of cue `index`; `None` for music notes and other cues
+/// without words.
+fn segment(kind: Kind, index: usize, cue: &Cue) -> Option<Segment> {
+  let text = join(&cue.text);
+  text.chars().any(char::is_alphabetic).then(|| Segment {
+    locator: format!("{}:{index}", kind.prefix()),
+    text,
+  })
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let kind = kind_of(path);
+  let subtitles = read(path, kind)?;
+  Ok(
+    cues(&subtitles)
+      .enumerate()
+      .filter_map(|(index, cue)| segment(kind, index, cue))
+      .collect(),
+  )
+}
+
+/// [`segments`] of the `srt` or `vtt` subtitles `reader` gives, handed to
+/// `emit` a cue at a time; only the cue being read is held.
+pub fn stream(
+  reader: &mut dyn BufRead,
+  extension: &str,
+  emit: &mut dyn FnMut(Segment) -> Result<(), String>,
+) -> Result<(), String> {
+  let kind = if extension == "vtt" {
+    Kind::Vtt
+  } else {
+    Kind::Srt
+  };
+  let mut line = String::new();
+  let mut lines = Vec::new();
+  let mut index = 0;
+  let mut first = true;
+  loop {
+    line.clear();
+    let read = reader
+      .read_line(&mut line)
+      .map_err(|e| format!("Cannot read the subtitles: {e}"))?;
+    let text = line.strip_suffix('\n').unwrap_or(&line);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    if std::mem::take(&mut first) && kind == Kind::Vtt && !text.starts_with("WEBVTT") {
+      return Err("Not a WebVTT file: it must start with WEBVTT".to_string());
+    }
+    if read > 0 && !text.trim().is_empty() {
+      lines.push(text.to_string());
+      continue;
+    }
+    if !lines.is_empty() {
+      if let Block::Cue(cue) = block(std::mem::take(&mut lines)) {
+        if let Some(segment) = segment(kind, index, &cue) {
+          emit(segment)?;
+        }
+        index += 1;
+      }
+    }
+    if read == 0 {
+      return Ok(());
+    }
+  }
+}
+
+fn export(
+  app: &AppHandle,
+  job_id: &str,
+  path: &Path,
+  kind: Kind,
+) -> Result<Vec<Warning>, ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let translations: std::collections::HashMap<usize, &str> = blocks
+    .iter()
+    .filter_map(|(locator, text)| {
+      let index = locator.strip_prefix(kind.prefix())?.strip_prefix(':')?;
+      Some((index.parse().ok()?, text.as_str()))
+    })
+    .collect();
+  let settings = app.state::<SettingsState>().get();
+  let mut subtitles = parse(&source, kind).map_err(invalid)?;
+  let mut warnings = Vec::new();
+  let mut index = 0;
+  for block in &mut subtitles.blocks {
+    let Block::Cue(cue) = block else {
+      continue;
+    };
+    let warn = |message: String| Warning {
+      cue: index + 1,
+      start: cue.head[cue.head.len() - 1]
+        .split("-->")
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string(),
+      message,
+    };
+    if let Some(text) = translations.get(&index) {
+      let lines = wrap(text, settings.subtitle_line_width);
+      if lines.len() > settings.subtitle_max_lines {
+        warnings.push(warn(format!(
+          "{} lines (limit {})",
+          lines.len(),
+          settings.subtitle_max_lines
+        )));
+      }
+      if let Some(wide) = lines
+        .iter()
+        .map(|l| width(l))
+        .find(|w| *w > settings.subtitle_line_width)
+      {
+        warnings.push(warn(format!(
+          "a line of {wide} columns (limit {})",
+          settings.subtitle_line_width
+        )));
+      }
+      let seconds = cue.end_ms.saturating_sub(cue.start_ms) as f64 / 1000.0;
+      let columns: usize = lines.iter().map(|l| width(l)).sum();
+      if seconds > 0.0 && columns as f64 / seconds > settings.subtitle_max_cps {
+        warnings.push(warn(format!(
+          "{:.1} characters per second (limit {})",
+          columns as f64 / seconds,
+          settings.subtitle_max_cps
+        )));
+      }
+      cue.text = lines;
+    }
+    index += 1;
+  }
+
+  let newline = if subtitles.crlf { "\r\n" } else { "\n" };
+  let mut out = String::new();
+  if subtitles.bom {
+    out.push('\u{feff}');
+  }
+  for block in &subtitles.blocks {
+    let lines = match block {
+      Block::Cue(cue) => cue.head.iter().chain(&cue.text).collect::<Vec<_>>(),
+      Block::Other(lines) => lines.iter().collect(),
+    };
+    for line in lines {
+      out.push_str(line);
+      out.push_str(newline);
+    }
+    out.push_str(newline);
+  }
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// [`export`] as SRT or WebVTT, by the extension of `path`.
+pub fn export_to(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  export(app, job_id, path, kind_of(path))
+}
+
+/// Writes the translated copy of SRT task `job_id` to `path`. Returns the
+/// cues over the subtitle limits.
+#[tauri::command]
+pub async fn export_translated_srt(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path), Kind::Srt))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Like [`export_translated_srt`], for WebVTT tasks.
+#[tauri::command]
+pub async fn export_translated_vtt(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path), Kind::Vtt))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,529 @@
+//! TMX 1.4 files in and out of the backend's translation memory.
+//!
+//! Import pairs each translation unit's source variant (the header's or
+//! unit's `srclang`, else the first) with every other variant, and sends
+//! them in batches; a pair already in the memory is updated rather than
+//! added. Export writes the pairs matching a filter, a unit per source
+//! text with all its targets.
+//!
+//! The memory has columns for languages, texts, dates and creator; the
+//! rest of a unit (its `tuid` and other attributes, `prop`s and `note`s,
+//! the variants' own, and the inline codes of a `seg`) goes in its `meta`
+//! and is written back as it was.
+//!
+//! Before a block goes to a provider, the backend looks it up in the
+//! memory (see `tm_lookup` there): a unit with the same source is reused,
+//! and the best fuzzy match scoring at least `tm_min_match` (see
+//! `settings`, passed as [`ENV`]) goes into the prompt. Blocks edited by
+//! hand are added to the memory, and what it gave each task is in the
+//! task's `leverage`.
+
+use quick_xml::{
+  escape::{escape, partial_escape, unescape},
+  events::{BytesStart, Event},
+  Reader,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, fs};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+  profiles,
+  proxy::{invalid, ProxyError},
+  settings::SettingsState,
+};
+
+pub const ENV: &str = "MVP_TM_MIN_MATCH";
+
+/// Units sent per request, and read per page (the backend's limit).
+const BATCH: usize = 1000;
+
+/// Inline codes, whose content is markup rather than text.
+const CODES: &[&[u8]] = &[b"bpt", b"ept", b"ph", b"it", b"ut"];
+
+/// Unit attributes the memory keeps as columns.
+const COLUMNS: &[&str] = &["creationdate", "creationid", "changedate"];
+
+/// What an element has besides its text.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Extras {
+  attributes: Vec<(String, String)>,
+  /// `type` and value.
+  props: Vec<(String, String)>,
+  notes: Vec<String>,
+  /// A `seg` with inline codes, as written.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  seg: Option<String>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Meta {
+  unit: Extras,
+  source: Extras,
+  target: Extras,
+}
+
+/// A pair as the memory has it.
+#[derive(Serialize, Deserialize)]
+struct Unit {
+  source_lang: String,
+  target_lang: String,
+  source_text: String,
+  target_text: String,
+  #[serde(default)]
+  project: String,
+  #[serde(default)]
+  creator: String,
+  /// TMX dates, `20240131T093000Z`.
+  #[serde(default)]
+  created: String,
+  #[serde(default)]
+  changed: String,
+  #[serde(default)]
+  meta: Meta,
+}
+
+struct Variant {
+  lang: String,
+  text: String,
+  extras: Extras,
+}
+
+#[derive(Default)]
+struct Tu {
+  srclang: Option<String>,
+  extras: Extras,
+  variants: Vec<Variant>,
+}
+
+#[derive(Serialize)]
+pub struct TmxImport {
+  /// Pairs read from the file.
+  pub units: usize,
+  pub added: u64,
+  pub updated: u64,
+  /// Translation units without a source and a target.
+  pub skipped: usize,
+}
+
+/// Which pairs [`export_tmx`] writes; empty fields match all.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct TmFilter {
+  /// `en` also matches `en-US`.
+  pub source_lang: String,
+  pub target_lang: String,
+  pub project: String,
+  /// First and last day (`YYYY-MM-DD`, UTC) of the last change.
+  pub since: String,
+  pub until: String,
+}
+
+pub fn backend_env(app: &AppHandle) -> BTreeMap<String, String> {
+  let min_match = app.state::<SettingsState>().get().tm_min_match;
+  BTreeMap::from([(ENV.to_string(), min_match.to_string())])
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid TMX: {e}")
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+  e.try_get_attribute(key)
+    .ok()
+    .flatten()
+    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+fn attributes(e: &BytesStart, except: &[&str]) -> Vec<(String, String)> {
+  e.attributes()
+    .flatten()
+    .filter_map(|a| {
+      let key = String::from_utf8_lossy(a.key.as_ref()).into_owned();
+      let value = a.unescape_value().ok()?.into_owned();
+      (!except.contains(&key.as_str())).then_some((key, value))
+    })
+    .collect()
+}
+
+/// TMX files are often UTF-16.
+fn decode(data: &[u8]) -> Result<String, String> {
+  let utf16 = |bytes: &[u8], be: bool| {
+    let units = bytes.chunks_exact(2).map(|pair| match be {
+      true => u16::from_be_bytes([pair[0], pair[1]]),
+      false => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units)
+      .collect::<Result<String, _>>()
+      .map_err(|_| xml_err("not valid UTF-16"))
+  };
+  match data {
+    [0xFF, 0xFE, rest @ ..] => utf16(rest, false),
+    [0xFE, 0xFF, rest @ ..] => utf16(rest, true),
+    _ => String::from_utf8(data.to_vec())
+      .map(|text| text.trim_start_matches('\u{feff}').to_string())
+      .map_err(|_| xml_err("not UTF-8 or UTF-16")),
+  }
+}
+
+/// The text of a `seg`'s content, without its inline codes, and whether
+/// it has any.
+fn seg_text(raw: &str) -> Result<(String, bool), String> {
+  let mut reader = Reader::from_reader(raw.as_bytes());
+  let mut text = String::new();
+  let mut codes = false;
+  loop {
+    match reader.read_event().map_err(xml_err)? {
+      Event::Text(t) => text.push_str(&t.unescape().map_err(xml_err)?),
+      Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t)),
+      Event::Start(e) if CODES.contains(&e.local_name().as_ref()) => {
+        reader.read_to_end(e.name()).map_err(xml_err)?;
+        codes = true;
+      }
+      // `<hi>`, whose content is text
+      Event::Start(_) | Event::Empty(_) => codes = true,
+      Event::Eof => return Ok((text, codes)),
+      _ => {}
+    }
+  }
+}
+
+/// The pairs of `tu`, or none without a source and a target.
+fn pairs(tu: Tu, srclang: &str, project: &str) -> Vec<Unit> {
+  let srclang = tu.srclang.as_deref().unwrap_or(srclang);
+  let source = tu
+    .variants
+    .iter()
+    .position(|v| v.lang.eq_ignore_ascii_case(srclang))
+    .unwrap_or(0);
+  let Some(source) = tu
+    .variants
+    .get(source)
+    .filter(|v| !v.text.trim().is_empty())
+  else {
+    return Vec::new();
+  };
+  let column = |key: &str| {
+    value_of(&tu.extras.attributes, key)
+      .or_else(|| value_of(&source.extras.attributes, key))
+      .unwrap_or_default()
+  };
+  let mut unit_extras = tu.extras.clone();
+  unit_extras
+    .attributes
+    .retain(|(k, _)| !COLUMNS.contains(&k.as_str()));
+  tu.variants
+    .iter()
+    .filter(|v| !v.lang.eq_ignore_ascii_case(&source.lang) && !v.text.trim().is_empty())
+    .map(|target| Unit {
+      source_lang: source.lang.clone(),
+      target_lang: target.lang.clone(),
+      source_text: source.text.clone(),
+      target_text: target.text.clone(),
+      project: project.to_string(),
+      creator: column("creationid"),
+      created: column("creationdate"),
+      changed: column("changedate"),
+      meta: Meta {
+        unit: unit_extras.clone(),
+        source: source.extras.clone(),
+        target: target.extras.clone(),
+      },
+    })
+    .collect()
+}
+
+fn value_of(attributes: &[(String, String)], key: &str) -> Option<String> {
+  attributes
+    .iter()
+    .find(|(k, _)| k == key)
+    .map(|(_, v)| v.clone())
+}
+
+/// The pairs of a TMX file, for `project`, and the number of translation
+/// units without one.
+fn parse(data: &[u8], project: &str) -> Result<(Vec<Unit>, usize), String> {
+  let text = decode(data)?;
+  let mut reader = Reader::from_reader(text.as_bytes());
+  let mut srclang = String::new();
+  let mut units = Vec::new();
+  let mut skipped = 0;
+  let mut tu: Option<Tu> = None;
+  let mut variant: Option<Variant> = None;
+  let mut seen_tmx = false;
+  loop {
+    let event = reader.read_event().map_err(xml_err)?;
+    match &event {
+      Event::Start(e) | Event::Empty(e) => {
+        let start = matches!(event, Event::Start(_));
+        match e.local_name().as_ref() {
+          b"tmx" => seen_tmx = true,
+          b"header" => srclang = attribute(e, "srclang").unwrap_or_default(),
+          b"tu" => {
+            tu = Some(Tu {
+              srclang: attribute(e, "srclang").filter(|l| l != "*all*"),
+              extras: Extras {
+                attributes: attributes(e, &[]),
+                ..Extras::default()
+              },
+              variants: Vec::new(),
+            })
+          }
+          b"tuv" => {
+            variant = Some(Variant {
+              lang: attribute(e, "xml:lang")
+                .or_else(|| attribute(e, "lang"))
+                .unwrap_or_default(),
+              text: String::new(),
+              extras: Extras {
+                attributes: attributes(e, &["xml:lang", "lang"]),
+                ..Extras::default()
+              },
+            })
+          }
+          name @ (b"prop" | b"note") if start => {
+            let prop = name == b"prop";
+            let kind = attribute(e, "type").unwrap_or_default();
+            let raw = reader.read_text(e.name()).map_err(xml_err)?;
+            let value = unescape(&raw).map_err(xml_err)?.into_owned();
+            // the header's are about the file
+            let extras = match (&mut variant, &mut tu) {
+              (Some(v), _) => &mut v.extras,
+              (None, Some(tu)) => &mut tu.extras,
+              (None, None) => continue,
+            };
+            if prop {
+              extras.props.push((kind, value));
+            } else {
+              extras.notes.push(value);
+            }
+          }
+          b"seg" if start => {
+            let span = reader.read_to_end(e.name()).map_err(xml_err)?;
+            let raw = &text[span.start as usize..span.end as usize];
+            let (plain, codes) = seg_text(raw)?;
+            if let Some(v) = &mut variant {
+              v.text = plain;
+              v.extras.seg = codes.then(|| raw.to_string());
+            }
+          }
+          _ => {}
+        }
+      }
+      Event::End(e) => match e.local_name().as_ref() {
+        b"tuv" => {
+          if let (Some(tu), Some(v)) = (&mut tu, variant.take()) {
+            tu.variants.push(v);
+          }
+        }
+        b"tu" => {
+          if let Some(tu) = tu.take() {
+            let found = pairs(tu, &srclang, project);
+            skipped += usize::from(found.is_empty());
+            units.extend(found);
+          }
+        }
+        _ => {}
+      },
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  if !seen_tmx {
+    return Err(xml_err("no <tmx> element"));
+  }
+  Ok((units, skipped))
+}
+
+fn push_extras(out: &mut String, indent: &str, extras: &Extras) {
+  for (kind, value) in &extras.props {
+    out.push_str(&format!(
+      "{indent}<prop type=\"{}\">{}</prop>\n",
+      escape(kind.as_str()),
+      partial_escape(value.as_str())
+    ));
+  }
+  for note in &extras.notes {
+    out.push_str(&format!(
+      "{indent}<note>{}</note>\n",
+      partial_escape(note.as_str())
+    ));
+  }
+}
+
+fn push_attributes(out: &mut String, attributes: &[(String, String)]) {
+  for (key, value) in attributes {
+    out.push_str(&format!(" {key}=\"{}\"", escape(value.as_str())));
+  }
+}
+
+fn push_variant(out: &mut String, lang: &str, text: &str, extras: &Extras) {
+  out.push_str(&format!("      <tuv xml:lang=\"{}\"", escape(lang)));
+  push_attributes(out, &extras.attributes);
+  out.push_str(">\n");
+  push_extras(out, "        ", extras);
+  let seg = match &extras.seg {
+    Some(raw) => raw.clone(),
+    None => partial_escape(text).into_owned(),
+  };
+  out.push_str(&format!("        <seg>{seg}</seg>\n      </tuv>\n"));
+}
+
+/// `units` as a TMX 1.4 file; pairs in a row with the same source and
+/// unit become one unit.
+fn write_tmx(units: &[Unit], srclang: &str) -> String {
+  let mut out = format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tmx version=\"1.4\">\n  <header \
+     creationtool=\"AI Document Translator\" creationtoolversion=\"{}\" segtype=\"sentence\" \
+     o-tmf=\"aidt\" adminlang=\"en\" srclang=\"{}\" datatype=\"plaintext\"/>\n  <body>\n",
+    env!("CARGO_PKG_VERSION"),
+    escape(srclang)
+  );
+  let mut i = 0;
+  while i < units.len() {
+    let first = &units[i];
+    let same = units[i..]
+      .iter()
+      .take_while(|u| {
+        u.source_lang == first.source_lang
+          && u.source_text == first.source_text
+          && u.meta.unit == first.meta.unit
+          && u.meta.source == first.meta.source
+          && u.creator == first.creator
+          && u.created == first.created
+          && u.changed == first.changed
+      })
+      .count();
+    out.push_str("    <tu");
+    push_attributes(&mut out, &first.meta.unit.attributes);
+    for (key, value) in [
+      ("creationdate", &first.created),
+      ("creationid", &first.creator),
+      ("changedate", &first.changed),
+    ] {
+      if !value.is_empty() {
+        out.push_str(&format!(" {key}=\"{}\"", escape(value.as_str())));
+      }
+    }
+    out.push_str(">\n");
+    push_extras(&mut out, "      ", &first.meta.unit);
+    push_variant(
+      &mut out,
+      &first.source_lang,
+      &first.source_text,
+      &first.meta.source,
+    );
+    for unit in &units[i..i + same] {
+      push_variant(
+        &mut out,
+        &unit.target_lang,
+        &unit.target_text,
+        &unit.meta.target,
+      );
+    }
+    out.push_str("    </tu>\n");
+    i += same;
+  }
+  out.push_str("  </body>\n</tmx>\n");
+  out
+}
+
+fn import(app: &AppHandle, path: &str, project: Option<String>) -> Result<TmxImport, ProxyError> {
+  let data = fs::read(path).map_err(|e| invalid(format!("Cannot read {path}: {e}")))?;
+  // the file's name, without `.tmx`
+  let project = project.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| {
+    std::path::Path::new(path)
+      .file_stem()
+      .map(|s| s.to_string_lossy().into_owned())
+      .unwrap_or_default()
+  });
+  let (units, skipped) = parse(&data, project.trim()).map_err(invalid)?;
+  let mut report = TmxImport {
+    units: units.len(),
+    added: 0,
+    updated: 0,
+    skipped,
+  };
+  for batch in units.chunks(BATCH) {
+    let counts = profiles::call(
+      app,
+      "POST",
+      "/api/tm/units",
+      Some(json!({ "units": batch })),
+    )?;
+    report.added += counts["added"].as_u64().unwrap_or_default();
+    report.updated += counts["updated"].as_u64().unwrap_or_default();
+  }
+  Ok(report)
+}
+
+fn export(app: &AppHandle, path: &str, filter: &TmFilter) -> Result<usize, ProxyError> {
+  let mut units: Vec<Unit> = Vec::new();
+  loop {
+    let mut url = reqwest::Url::parse("http://backend/api/tm/units").expect("static URL");
+    url
+      .query_pairs_mut()
+      .append_pair("source_lang", &filter.source_lang)
+      .append_pair("target_lang", &filter.target_lang)
+      .append_pair("project", &filter.project)
+      .append_pair("since", &filter.since)
+      .append_pair("until", &filter.until)
+      .append_pair("offset", &units.len().to_string())
+      .append_pair("limit", &BATCH.to_string());
+    let page = profiles::call(
+      app,
+      "GET",
+      &format!("{}?{}", url.path(), url.query().unwrap_or_default()),
+      None,
+    )?;
+    let page: Vec<Unit> =
+      serde_json::from_value(page).map_err(|e| ProxyError::new("http", e.to_string()))?;
+    let last = page.len() < BATCH;
+    units.extend(page);
+    if last {
+      break;
+    }
+  }
+  let srclang = match filter.source_lang.trim() {
+    "" if units
+      .windows(2)
+      .all(|w| w[0].source_lang == w[1].source_lang) =>
+    {
+      units.first().map_or("*all*", |u| u.source_lang.as_str())
+    }
+    "" => "*all*",
+    lang => lang,
+  };
+  fs::write(path, write_tmx(&units, srclang))
+    .map_err(|e| invalid(format!("Cannot write {path}: {e}")))?;
+  Ok(units.len())
+}
+
+/// Adds the pairs of the TMX file at `path` to the translation memory, under
+/// `project` (by default the file's name).
+#[tauri::command]
+pub async fn import_tmx(
+  app: AppHandle,
+  path: String,
+  project: Option<String>,
+) -> Result<TmxImport, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || import(&app, &path, project))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// Writes the translation memory's pairs matching `filter` to `path` as TMX
+/// 1.4. Returns how many.
+#[tauri::command]
+pub async fn export_tmx(
+  app: AppHandle,
+  path: String,
+  filter: TmFilter,
+) -> Result<usize, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &path, &filter))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
@@ -0,0 +1,730 @@
+//! How the shell and the webview reach a backend: TCP on 127.0.0.1, or a
+//! Unix domain socket in a private directory. The webview can't open
+//! sockets, so for UDS backends it fetches `mvp-backend://localhost/<socket>/...`
+//! and the shell forwards those requests.
+
+use std::{
+  fs,
+  io::{self, BufRead, BufReader, Read, Write},
+  net::{Shutdown, TcpStream},
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+  },
+  time::Duration,
+};
+use tauri::{
+  http::{self, header},
+  Runtime, UriSchemeContext, UriSchemeResponder,
+};
+
+use crate::{applock, auth, clientcert, outbound};
+
+/// URI scheme the webview uses for UDS backends.
+pub const SCHEME: &str = "mvp-backend";
+
+/// `sun_path` is 104-108 bytes depending on the OS.
+const MAX_SOCKET_PATH: usize = 100;
+
+/// Timeout for requests forwarded from the webview.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(300);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SOCKET_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where a backend should listen.
+pub enum Listen {
+  Tcp(u16),
+  Unix(PathBuf),
+}
+
+pub struct Response {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl Response {
+  pub fn is_success(&self) -> bool {
+    (200..300).contains(&self.status)
+  }
+}
+
+/// Sets up `<data dir>/run` (owner-only) for backend sockets and clears
+/// sockets left over from earlier sessions.
+pub fn init(data_dir: &Path) {
+  let dir = data_dir.join("run");
+  if let Ok(entries) = fs::read_dir(&dir) {
+    for entry in entries.flatten() {
+      let _ = fs::remove_file(entry.path());
+    }
+  }
+  let _ = fs::create_dir_all(&dir);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = fs::set_permissions(&dir, fs::Permissions::from_mode(0o700));
+  }
+  let _ = SOCKET_DIR.set(dir);
+}
+
+/// A fresh socket path, or `None` where UDS can't be used: on Windows
+/// (uvicorn serves neither named pipes nor AF_UNIX there) or when the
+/// data dir is too deep for `sun_path`.
+pub fn new_socket_path() -> Option<PathBuf> {
+  if cfg!(not(unix)) {
+    return None;
+  }
+  let path = SOCKET_DIR.get()?.join(format!(
+    "backend-{}.sock",
+    &uuid::Uuid::new_v4().simple().to_string()[..12]
+  ));
+  (path.as_os_str().len() <= MAX_SOCKET_PATH).then_some(path)
+}
+
+/// Base URL of the backend listening on `socket`.
+pub fn socket_base_url(socket: &Path) -> String {
+  let name = socket.file_name().unwrap_or_default().to_string_lossy();
+  format!("{SCHEME}://localhost/{name}")
+}
+
+/// Splits a `mvp-backend://localhost/<socket>/<path>` URL into the socket
+/// path and the request target.
+fn resolve_socket(url: &str) -> Option<(PathBuf, String)> {
+  let rest = url.strip_prefix(SCHEME)?.strip_prefix("://")?;
+  let (_host, rest) = rest.split_once('/')?;
+  let (name, target) = match rest.find(['/', '?']) {
+    Some(i) => (&rest[..i], &rest[i..]),
+    None => (rest, ""),
+  };
+  if name.is_empty() || name.contains("..") {
+    return None;
+  }
+  let target = match target {
+    "" => "/".to_string(),
+    t if t.starts_with('?') => format!("/{t}"),
+    t => t.to_string(),
+  };
+  Some((SOCKET_DIR.get()?.join(name), target))
+}
+
+/// Local backends are always reached directly; remote ones (`backend_url`)
+/// go through the configured proxy and present the client certificate.
+fn client_builder(url: &str) -> reqwest::Result<reqwest::blocking::ClientBuilder> {
+  let loopback = reqwest::Url::parse(url)
+    .ok()
+    .and_then(|u| u.host_str().map(|h| h.trim_matches(['[', ']']).to_string()))
+    .is_some_and(|h| {
+      h.eq_ignore_ascii_case("localhost")
+        || h
+          .parse::<std::net::IpAddr>()
+          .is_ok_and(|ip| ip.is_loopback())
+    });
+  if loopback {
+    Ok(reqwest::blocking::Client::builder().no_proxy())
+  } else {
+    let builder = outbound::builder()?;
+    Ok(match clientcert::identity() {
+      Some(identity) => builder.identity(identity),
+      None => builder,
+    })
+  }
+}
+
+/// One HTTP request to `url`, over TCP or the backend's socket, carrying
+/// the shell's auth token.
+pub fn request(
+  method: &str,
+  url: &str,
+  headers: &[(String, String)],
+  body: Vec<u8>,
+  timeout: Duration,
+) -> io::Result<Response> {
+  let headers = &auth::with_token(headers);
+  if let Some((socket, target)) = resolve_socket(url) {
+    return unix_request(&socket, method, &target, headers, &body, timeout);
+  }
+  let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(io::Error::other)?;
+  let mut req = client_builder(url)
+    .and_then(|b| b.build())
+    .map_err(io::Error::other)?
+    .request(method, url)
+    .timeout(timeout)
+    .body(body);
+  for (name, value) in headers {
+    req = req.header(name, value);
+  }
+  let resp = req.send().map_err(io::Error::other)?;
+  let status = resp.status().as_u16();
+  let headers = resp
+    .headers()
+    .iter()
+    .map(|(k, v)| {
+      (
+        k.to_string(),
+        String::from_utf8_lossy(v.as_bytes()).into_owned(),
+      )
+    })
+    .collect();
+  let body = resp.bytes().map_err(io::Error::other)?.to_vec();
+  Ok(Response {
+    status,
+    headers,
+    body,
+  })
+}
+
+pub fn get(url: &str, timeout: Duration) -> io::Result<Response> {
+  request("GET", url, &[], Vec::new(), timeout)
+}
+
+pub fn post(url: &str, timeout: Duration) -> io::Result<Response> {
+  request("POST", url, &[], Vec::new(), timeout)
+}
+
+/// Whether anything accepts connections at `base_url`: a TCP connect to
+/// its host and port, or a connect to its socket.
+pub fn reachable(base_url: &str, timeout: Duration) -> bool {
+  if let Some((socket, _)) = resolve_socket(base_url) {
+    #[cfg(unix)]
+    return std::os::unix::net::UnixStream::connect(socket).is_ok();
+    #[cfg(not(unix))]
+    return false;
+  }
+  let Some(addrs) = reqwest::Url::parse(base_url)
+    .ok()
+    .and_then(|u| u.socket_addrs(|| None).ok())
+  else {
+    return false;
+  };
+  addrs
+    .iter()
+    .any(|addr| std::net::TcpStream::connect_timeout(addr, timeout).is_ok())
+}
+
+#[cfg(unix)]
+fn unix_request(
+  socket: &Path,
+  method: &str,
+  target: &str,
+  headers: &[(String, String)],
+  body: &[u8],
+  timeout: Duration,
+) -> io::Result<Response> {
+  use std::os::unix::net::UnixStream;
+
+  let mut stream = UnixStream::connect(socket)?;
+  stream.set_read_timeout(Some(timeout))?;
+  stream.set_write_timeout(Some(timeout))?;
+  write_request(&mut stream, method, target, "localhost", headers, body)?;
+
+  let mut raw = Vec::new();
+  stream.read_to_end(&mut raw)?;
+  parse_response(&raw)
+}
+
+#[cfg(not(unix))]
+fn unix_request(
+  _socket: &Path,
+  _method: &str,
+  _target: &str,
+  _headers: &[(String, String)],
+  _body: &[u8],
+  _timeout: Duration,
+) -> io::Result<Response> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "Unix domain sockets are not supported on this platform",
+  ))
+}
+
+/// Writes a `Connection: close` HTTP/1.1 request with a fixed-length body.
+fn write_request(
+  out: &mut impl Write,
+  method: &str,
+  target: &str,
+  host: &str,
+  headers: &[(String, String)],
+  body: &[u8],
+) -> io::Result<()> {
+  let mut head = format!(
+    "{method} {target} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nContent-Length: {}\r\n",
+    body.len()
+  );
+  for (name, value) in headers {
+    let lower = name.to_ascii_lowercase();
+    if !matches!(
+      lower.as_str(),
+      "host" | "connection" | "content-length" | "transfer-encoding"
+    ) {
+      head.push_str(&format!("{name}: {value}\r\n"));
+    }
+  }
+  head.push_str("\r\n");
+  out.write_all(head.as_bytes())?;
+  out.write_all(body)
+}
+
+fn bad_response(msg: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Parses a complete `Connection: close` HTTP/1.1 response.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn parse_response(raw: &[u8]) -> io::Result<Response> {
+  let split = raw
+    .windows(4)
+    .position(|w| w == b"\r\n\r\n")
+    .ok_or_else(|| bad_response("truncated response head"))?;
+  let head = String::from_utf8_lossy(&raw[..split]);
+  let mut lines = head.split("\r\n");
+  let status = lines
+    .next()
+    .and_then(|l| l.split_whitespace().nth(1))
+    .and_then(|s| s.parse().ok())
+    .ok_or_else(|| bad_response("malformed status line"))?;
+  let headers: Vec<(String, String)> = lines
+    .filter_map(|l| l.split_once(':'))
+    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+    .collect();
+  let header = |name: &str| {
+    headers
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  };
+
+  let rest = &raw[split + 4..];
+  let body = if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+    decode_chunked(rest)?
+  } else if let Some(len) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+    rest
+      .get(..len)
+      .ok_or_else(|| bad_response("truncated body"))?
+      .to_vec()
+  } else {
+    rest.to_vec()
+  };
+  let headers = headers
+    .into_iter()
+    .filter(|(k, _)| !k.eq_ignore_ascii_case("transfer-encoding"))
+    .collect();
+  Ok(Response {
+    status,
+    headers,
+    body,
+  })
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn decode_chunked(mut data: &[u8]) -> io::Result<Vec<u8>> {
+  let mut body = Vec::new();
+  loop {
+    let eol = data
+      .windows(2)
+      .position(|w| w == b"\r\n")
+      .ok_or_else(|| bad_response("truncated chunk size"))?;
+    let size_line = String::from_utf8_lossy(&data[..eol]);
+    let size_hex = size_line.split(';').next().unwrap_or("").trim();
+    let size =
+      usize::from_str_radix(size_hex, 16).map_err(|_| bad_response("malformed chunk size"))?;
+    data = &data[eol + 2..];
+    if size == 0 {
+      return Ok(body);
+    }
+    let chunk = data
+      .get(..size)
+      .ok_or_else(|| bad_response("truncated chunk"))?;
+    body.extend_from_slice(chunk);
+    data = data.get(size + 2..).unwrap_or_default();
+  }
+}
+
+/// Handler for the `mvp-backend://` scheme: forwards the webview's request
+/// to the backend socket named in the URL.
+pub fn handle_protocol<R: Runtime>(
+  _ctx: UriSchemeContext<'_, R>,
+  request: http::Request<Vec<u8>>,
+  responder: UriSchemeResponder,
+) {
+  if applock::is_locked() {
+    responder.respond(
+      http::Response::builder()
+        .status(http::StatusCode::LOCKED)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(b"the app is locked".to_vec())
+        .unwrap(),
+    );
+    return;
+  }
+  std::thread::spawn(move || {
+    let url = request.uri().to_string();
+    let headers: Vec<(String, String)> = request
+      .headers()
+      .iter()
+      .map(|(k, v)| {
+        (
+          k.to_string(),
+          String::from_utf8_lossy(v.as_bytes()).into_owned(),
+        )
+      })
+      .collect();
+    let method = request.method().to_string();
+    let result = request_with(&method, &url, &headers, request.into_body());
+
+    let response = match result {
+      Ok(resp) => {
+        let mut builder = http::Response::builder().status(resp.status);
+        for (name, value) in &resp.headers {
+          if !name.eq_ignore_ascii_case("content-length")
+            && !name.eq_ignore_ascii_case("connection")
+          {
+            builder = builder.header(name, value);
+          }
+        }
+        builder.body(resp.body)
+      }
+      Err(e) => http::Response::builder()
+        .status(http::StatusCode::BAD_GATEWAY)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(format!("backend unreachable: {e}").into_bytes()),
+    };
+    match response {
+      Ok(r) => responder.respond(r),
+      Err(e) => responder.respond(
+        http::Response::builder()
+          .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+          .body(e.to_string().into_bytes())
+          .unwrap(),
+      ),
+    }
+  });
+}
+
+fn request_with(
+  method: &str,
+  url: &str,
+  headers: &[(String, String)],
+  body: Vec<u8>,
+) -> io::Result<Response> {
+  if resolve_socket(url).is_none() {
+    return Err(bad_response("not a backend socket URL"));
+  }
+  request(method, url, headers, body, FORWARD_TIMEOUT)
+}
+
+/// A raw connection to a backend, for callers that speak their own
+/// protocol over it (streamed bodies, WebSockets).
+pub enum Conn {
+  Tcp(TcpStream),
+  #[cfg(unix)]
+  Unix(std::os::unix::net::UnixStream),
+}
+
+impl Conn {
+  pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+    match self {
+      Self::Tcp(s) => s.set_read_timeout(timeout),
+      #[cfg(unix)]
+      Self::Unix(s) => s.set_read_timeout(timeout),
+    }
+  }
+
+  pub fn try_clone(&self) -> io::Result<Self> {
+    Ok(match self {
+      Self::Tcp(s) => Self::Tcp(s.try_clone()?),
+      #[cfg(unix)]
+      Self::Unix(s) => Self::Unix(s.try_clone()?),
+    })
+  }
+
+  pub fn shutdown(&self) {
+    let _ = match self {
+      Self::Tcp(s) => s.shutdown(Shutdown::Both),
+      #[cfg(unix)]
+      Self::Unix(s) => s.shutdown(Shutdown::Both),
+    };
+  }
+}
+
+impl Read for Conn {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Self::Tcp(s) => s.read(buf),
+      #[cfg(unix)]
+      Self::Unix(s) => s.read(buf),
+    }
+  }
+}
+
+impl Write for Conn {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Self::Tcp(s) => s.write(buf),
+      #[cfg(unix)]
+      Self::Unix(s) => s.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Self::Tcp(s) => s.flush(),
+      #[cfg(unix)]
+      Self::Unix(s) => s.flush(),
+    }
+  }
+}
+
+pub struct Connected {
+  pub conn: Conn,
+  /// Value for the `Host` header.
+  pub host: String,
+  /// Path and query to request.
+  pub target: String,
+}
+
+/// Opens a connection to the backend serving `url`, over its socket or
+/// plain TCP. `None` for `https` URLs, which only reqwest can talk to.
+pub fn connect(url: &str) -> io::Result<Option<Connected>> {
+  if let Some((socket, target)) = resolve_socket(url) {
+    #[cfg(unix)]
+    return Ok(Some(Connected {
+      conn: Conn::Unix(std::os::unix::net::UnixStream::connect(socket)?),
+      host: "localhost".to_string(),
+      target,
+    }));
+    #[cfg(not(unix))]
+    {
+      let _ = (socket, target);
+      return Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported on this platform",
+      ));
+    }
+  }
+  let parsed = reqwest::Url::parse(url).map_err(io::Error::other)?;
+  if parsed.scheme() != "http" {
+    return Ok(None);
+  }
+  let host = parsed
+    .host_str()
+    .ok_or_else(|| bad_response("URL has no host"))?;
+  let port = parsed.port_or_known_default().unwrap_or(80);
+  let addr = parsed
+    .socket_addrs(|| None)?
+    .into_iter()
+    .next()
+    .ok_or_else(|| bad_response("URL does not resolve"))?;
+  let target = match parsed.query() {
+    Some(q) => format!("{}?{q}", parsed.path()),
+    None => parsed.path().to_string(),
+  };
+  Ok(Some(Connected {
+    conn: Conn::Tcp(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?),
+    host: format!("{host}:{port}"),
+    target,
+  }))
+}
+
+/// Cancels an in-flight [`open_stream`]: shuts its socket down so a
+/// blocked read returns at once. HTTPS backends go through reqwest, where
+/// only the flag is available and the reader stops at the next chunk.
+#[derive(Default)]
+pub struct Abort {
+  cancelled: AtomicBool,
+  socket: Mutex<Option<Conn>>,
+}
+
+impl Abort {
+  pub fn abort(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+    if let Some(socket) = self.socket.lock().unwrap().as_ref() {
+      socket.shutdown();
+    }
+  }
+
+  pub fn is_aborted(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  fn attach(&self, socket: Conn) {
+    let mut guard = self.socket.lock().unwrap();
+    if self.is_aborted() {
+      socket.shutdown();
+    }
+    *guard = Some(socket);
+  }
+}
+
+pub struct StreamHead {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+}
+
+impl StreamHead {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+}
+
+/// Sends a request and returns the response head plus a reader over the
+/// (de-chunked) body, for responses consumed as they arrive. `idle` bounds
+/// each read, not the whole exchange.
+pub fn open_stream(
+  method: &str,
+  url: &str,
+  headers: &[(String, String)],
+  body: &[u8],
+  idle: Duration,
+  abort: &Abort,
+) -> io::Result<(StreamHead, Box<dyn Read + Send>)> {
+  let headers = &auth::with_token(headers);
+  let Some(Connected {
+    mut conn,
+    host,
+    target,
+  }) = connect(url)?
+  else {
+    return reqwest_stream(method, url, headers, body);
+  };
+  conn.set_read_timeout(Some(idle))?;
+  abort.attach(conn.try_clone()?);
+  write_request(&mut conn, method, &target, &host, headers, body)?;
+  read_stream(BufReader::new(conn))
+}
+
+fn reqwest_stream(
+  method: &str,
+  url: &str,
+  headers: &[(String, String)],
+  body: &[u8],
+) -> io::Result<(StreamHead, Box<dyn Read + Send>)> {
+  let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(io::Error::other)?;
+  let client = client_builder(url)
+    .map_err(io::Error::other)?
+    .connect_timeout(CONNECT_TIMEOUT)
+    .timeout(None)
+    .build()
+    .map_err(io::Error::other)?;
+  let mut req = client.request(method, url).body(body.to_vec());
+  for (name, value) in headers {
+    req = req.header(name, value);
+  }
+  let resp = req.send().map_err(io::Error::other)?;
+  let head = StreamHead {
+    status: resp.status().as_u16(),
+    headers: resp
+      .headers()
+      .iter()
+      .map(|(k, v)| {
+        (
+          k.to_string(),
+          String::from_utf8_lossy(v.as_bytes()).into_owned(),
+        )
+      })
+      .collect(),
+  };
+  Ok((head, Box::new(resp)))
+}
+
+fn read_stream<R: BufRead + Send + 'static>(
+  mut reader: R,
+) -> io::Result<(StreamHead, Box<dyn Read + Send>)> {
+  let mut status_line = String::new();
+  reader.read_line(&mut status_line)?;
+  let status = status_line
+    .split_whitespace()
+    .nth(1)
+    .and_then(|s| s.parse().ok())
+    .ok_or_else(|| bad_response("malformed status line"))?;
+  let mut headers = Vec::new();
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+      return Err(bad_response("truncated response head"));
+    }
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some((k, v)) = line.split_once(':') {
+      headers.push((k.trim().to_string(), v.trim().to_string()));
+    }
+  }
+  let head = StreamHead { status, headers };
+
+  let body: Box<dyn Read + Send> = if head
+    .header("transfer-encoding")
+    .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+  {
+    Box::new(Chunked {
+      inner: reader,
+      left: 0,
+      done: false,
+    })
+  } else if let Some(len) = head
+    .header("content-length")
+    .and_then(|v| v.parse::<u64>().ok())
+  {
+    Box::new(reader.take(len))
+  } else {
+    Box::new(reader)
+  };
+  let head = StreamHead {
+    headers: head
+      .headers
+      .into_iter()
+      .filter(|(k, _)| !k.eq_ignore_ascii_case("transfer-encoding"))
+      .collect(),
+    ..head
+  };
+  Ok((head, body))
+}
+
+/// Incremental decoder for `Transfer-Encoding: chunked` bodies.
+struct Chunked<R> {
+  inner: R,
+  left: usize,
+  done: bool,
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.done || buf.is_empty() {
+      return Ok(0);
+    }
+    if self.left == 0 {
+      let mut line = String::new();
+      self.inner.read_line(&mut line)?;
+      let size_hex = line.split(';').next().unwrap_or("").trim();
+      let size =
+        usize::from_str_radix(size_hex, 16).map_err(|_| bad_response("malformed chunk size"))?;
+      if size == 0 {
+        self.done = true;
+        return Ok(0);
+      }
+      self.left = size;
+    }
+    let want = buf.len().min(self.left);
+    let n = self.inner.read(&mut buf[..want])?;
+    if n == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "truncated chunk",
+      ));
+    }
+    self.left -= n;
+    if self.left == 0 {
+      let mut crlf = [0u8; 2];
+      self.inner.read_exact(&mut crlf)?;
+    }
+    Ok(n)
+  }
+}
@@ -0,0 +1,245 @@
+//! `update_backend`: swaps in a newer backend binary while the app runs.
+//! What to install comes from a manifest (`{"url", "sha256"}`) signed with
+//! Ed25519; its signature, base64 in `<manifest url>.sig`, is checked
+//! against the public key pinned in the build (`MVP_UPDATE_PUBLIC_KEY`,
+//! base64), and the binary against the manifest's hash. Builds without a
+//! key install no updates.
+
+use base64::Engine;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::{
+  fs::{self, File},
+  io::{Read, Write},
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{backend, boxed_err, integrity, outbound, paths};
+
+/// Public key update manifests are signed with, pinned at build time.
+const PUBLIC_KEY: Option<&str> = option_env!("MVP_UPDATE_PUBLIC_KEY");
+
+/// What a signed manifest says to install.
+#[derive(Deserialize)]
+struct Manifest {
+  url: String,
+  sha256: String,
+}
+
+/// Serializes `update_backend` calls; they share the staging path.
+#[derive(Default)]
+pub struct UpdateState(Mutex<()>);
+
+/// Payload of the `backend-update` event.
+#[derive(Clone, Serialize)]
+struct UpdateProgress {
+  stage: &'static str,
+  downloaded: u64,
+  total: Option<u64>,
+}
+
+/// Updated backends live in `<data dir>/backend/`, next to the hash they
+/// were verified against; the bundled sidecar stays untouched as the
+/// fallback.
+struct Paths {
+  current: PathBuf,
+  current_hash: PathBuf,
+  staged: PathBuf,
+  previous: PathBuf,
+  previous_hash: PathBuf,
+}
+
+impl Paths {
+  fn new(app: &AppHandle) -> Result<Self, Box<dyn std::error::Error>> {
+    let dir = paths::data_dir(app)?.join("backend");
+    let exe = format!("mvp_backend{}", std::env::consts::EXE_SUFFIX);
+    Ok(Self {
+      current_hash: dir.join("mvp_backend.sha256"),
+      staged: dir.join("mvp_backend.download"),
+      previous: dir.join(format!("{exe}.old")),
+      previous_hash: dir.join("mvp_backend.sha256.old"),
+      current: dir.join(exe),
+    })
+  }
+}
+
+/// The installed update, if there is one. Like the sidecar, it is checked
+/// against its recorded hash before every launch.
+pub fn installed(app: &AppHandle) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+  let paths = Paths::new(app)?;
+  if !paths.current.is_file() {
+    return Ok(None);
+  }
+  let expected = fs::read_to_string(&paths.current_hash).unwrap_or_default();
+  integrity::verify_against(&paths.current, &expected).map_err(|e| {
+    let _ = app.emit("backend-integrity-failed", e.clone());
+    Box::new(e) as Box<dyn std::error::Error>
+  })?;
+  Ok(Some(paths.current))
+}
+
+fn emit(app: &AppHandle, stage: &'static str, downloaded: u64, total: Option<u64>) {
+  let _ = app.emit(
+    "backend-update",
+    UpdateProgress {
+      stage,
+      downloaded,
+      total,
+    },
+  );
+}
+
+fn download(app: &AppHandle, url: &str, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+  if let Some(parent) = dest.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let mut resp = outbound::client()?.get(url).send()?.error_for_status()?;
+  let total = resp.content_length();
+  let mut file = File::create(dest)?;
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut downloaded = 0u64;
+  loop {
+    let n = resp.read(&mut buf)?;
+    if n == 0 {
+      break;
+    }
+    file.write_all(&buf[..n])?;
+    downloaded += n as u64;
+    emit(app, "downloading", downloaded, total);
+  }
+  file.sync_all()?;
+  Ok(())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+  Ok(())
+}
+
+/// Moves the current update (if any) aside and the staged binary into its
+/// place. Only runs while no backend process is alive, which Windows needs
+/// to replace the file.
+fn install(paths: &Paths, sha256: &str) -> Result<(), Box<dyn std::error::Error>> {
+  let _ = fs::remove_file(&paths.previous);
+  let _ = fs::remove_file(&paths.previous_hash);
+  if paths.current.exists() {
+    fs::rename(&paths.current, &paths.previous)?;
+    let _ = fs::rename(&paths.current_hash, &paths.previous_hash);
+  }
+  if let Err(e) = fs::rename(&paths.staged, &paths.current) {
+    rollback(paths);
+    return Err(e.into());
+  }
+  fs::write(&paths.current_hash, sha256)?;
+  Ok(())
+}
+
+/// Restores the previous update, or falls back to the bundled sidecar when
+/// the failed one was the first.
+fn rollback(paths: &Paths) {
+  let _ = fs::remove_file(&paths.current);
+  let _ = fs::remove_file(&paths.current_hash);
+  if paths.previous.exists() {
+    let _ = fs::rename(&paths.previous, &paths.current);
+    let _ = fs::rename(&paths.previous_hash, &paths.current_hash);
+  }
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+  let resp = outbound::client()?.get(url).send()?.error_for_status()?;
+  Ok(resp.bytes()?.to_vec())
+}
+
+/// The manifest at `manifest_url`, once its signature checks out.
+fn signed_manifest(manifest_url: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+  let key =
+    PUBLIC_KEY.ok_or("This build has no update key, so it cannot verify backend updates")?;
+  let b64 = base64::engine::general_purpose::STANDARD;
+  let key = b64
+    .decode(key.trim())
+    .map_err(|e| boxed_err(format!("The pinned update key is not base64: {e}")))?;
+  let manifest =
+    fetch(manifest_url).map_err(|e| boxed_err(format!("Update manifest download failed: {e}")))?;
+  let signature = fetch(&format!("{manifest_url}.sig"))
+    .map_err(|e| boxed_err(format!("Update signature download failed: {e}")))?;
+  let signature = b64
+    .decode(String::from_utf8_lossy(&signature).trim())
+    .map_err(|e| boxed_err(format!("The update signature is not base64: {e}")))?;
+  UnparsedPublicKey::new(&ED25519, &key)
+    .verify(&manifest, &signature)
+    .map_err(|_| boxed_err("The update manifest is not signed with the pinned key"))?;
+  serde_json::from_slice(&manifest).map_err(|e| boxed_err(format!("Invalid update manifest: {e}")))
+}
+
+fn update(app: &AppHandle, manifest_url: &str) -> Result<String, Box<dyn std::error::Error>> {
+  let paths = Paths::new(app)?;
+  let manifest = signed_manifest(manifest_url)?;
+  let sha256 = manifest.sha256.trim().to_ascii_lowercase();
+
+  emit(app, "downloading", 0, None);
+  if let Err(e) = download(app, &manifest.url, &paths.staged) {
+    let _ = fs::remove_file(&paths.staged);
+    return Err(boxed_err(format!("Backend download failed: {e}")));
+  }
+  emit(app, "verifying", 0, None);
+  if let Err(e) = integrity::verify_against(&paths.staged, &sha256) {
+    let _ = fs::remove_file(&paths.staged);
+    return Err(Box::new(e));
+  }
+  make_executable(&paths.staged)?;
+
+  emit(app, "swapping", 0, None);
+  let mut installed = false;
+  let result = backend::replace(app, || {
+    install(&paths, &sha256)?;
+    installed = true;
+    Ok(())
+  });
+  match result {
+    Ok(base_url) => {
+      let _ = fs::remove_file(&paths.previous);
+      let _ = fs::remove_file(&paths.previous_hash);
+      emit(app, "done", 0, None);
+      Ok(base_url)
+    }
+    Err(e) if installed => {
+      emit(app, "rolling-back", 0, None);
+      let _ = backend::replace(app, || {
+        rollback(&paths);
+        Ok(())
+      });
+      Err(boxed_err(format!("Updated backend failed to start: {e}")))
+    }
+    Err(e) => Err(e),
+  }
+}
+
+/// Downloads the backend the signed manifest at `manifest_url` names,
+/// checks it against the manifest's hash, and swaps it in for the running
+/// one without restarting the app. Reports `backend-update` progress
+/// events; a new binary that doesn't come up is rolled back.
+#[tauri::command]
+pub async fn update_backend(app: AppHandle, manifest_url: String) -> Result<String, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let state: State<UpdateState> = app.state();
+    let _guard = state
+      .0
+      .try_lock()
+      .map_err(|_| "A backend update is already running".to_string())?;
+    let result = update(&app, &manifest_url).map_err(|e| e.to_string());
+    if let Err(e) = &result {
+      let _ = app.emit("backend-update-failed", e.clone());
+    }
+    result
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,368 @@
+//! `upload_document`: sends a document from disk to the backend without it
+//! passing through the webview. Files over `upload_chunk_mb` go up in
+//! chunks of that size via `/api/uploads`, each retried on its own; smaller
+//! ones in a single multipart request. Progress is reported as
+//! `upload-progress` events. PDFs, Word files and PowerPoint decks are
+//! segmented here (see `segments`) and their blocks sent along.
+//!
+//! Plain text, tables and subtitles from `stream_segment_mb` on are never
+//! held whole: they go up in chunks, then their segments are streamed to
+//! `/api/uploads/{id}/segments` in batches, reported as `segment-progress`
+//! events with the bytes read.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+  fs::{self, File},
+  io::{Read, Seek, SeekFrom},
+  path::Path,
+  time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::{
+  config::StartupConfig,
+  docx, formats, ocr, output, protected,
+  proxy::{self, ProxyError},
+  segmentation,
+  segments::{self, Segment},
+  settings::SettingsState,
+  transport,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+const CHUNK_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Serialize)]
+struct UploadProgress<'a> {
+  id: &'a str,
+  sent: u64,
+  total: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct SegmentProgress<'a> {
+  id: &'a str,
+  /// Bytes of the document read.
+  read: u64,
+  total: u64,
+  /// Segments sent so far.
+  segments: usize,
+}
+
+fn multipart(fields: &[(&str, &str)], file: Option<(&str, &[u8])>) -> (String, Vec<u8>) {
+  let boundary = format!("----mvp{}", uuid::Uuid::new_v4().simple());
+  let mut body = Vec::new();
+  for (name, value) in fields {
+    body.extend_from_slice(
+      format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n")
+        .as_bytes(),
+    );
+  }
+  if let Some((filename, data)) = file {
+    let filename = filename.replace('"', "_");
+    body.extend_from_slice(
+      format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n"
+      )
+      .as_bytes(),
+    );
+    body.extend_from_slice(data);
+    body.extend_from_slice(b"\r\n");
+  }
+  body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+  (format!("multipart/form-data; boundary={boundary}"), body)
+}
+
+fn send(
+  method: &str,
+  url: &str,
+  content_type: &str,
+  body: Vec<u8>,
+) -> Result<transport::Response, ProxyError> {
+  let headers = [("content-type".to_string(), content_type.to_string())];
+  let resp = transport::request(method, url, &headers, body, TIMEOUT)
+    .map_err(|e| ProxyError::from_io(&e))?;
+  if resp.is_success() {
+    Ok(resp)
+  } else {
+    Err(ProxyError::from_response(&resp))
+  }
+}
+
+fn json(resp: &transport::Response) -> Result<Value, ProxyError> {
+  serde_json::from_slice(&resp.body).map_err(|e| ProxyError::new("http", e.to_string()))
+}
+
+/// Appends `file` to the upload in `chunk`-sized requests.
+fn send_chunks(
+  app: &AppHandle,
+  id: &str,
+  url: &str,
+  file: &mut File,
+  size: u64,
+  chunk: u64,
+) -> Result<(), ProxyError> {
+  let io_err = |e: std::io::Error| ProxyError::new("invalid-request", e.to_string());
+  let mut offset = 0u64;
+  while offset < size {
+    let mut buf = Vec::with_capacity(chunk.min(size - offset) as usize);
+    file.seek(SeekFrom::Start(offset)).map_err(io_err)?;
+    file
+      .by_ref()
+      .take(chunk)
+      .read_to_end(&mut buf)
+      .map_err(io_err)?;
+    let mut attempt = 0;
+    offset = loop {
+      attempt += 1;
+      match send(
+        "PUT",
+        &format!("{url}?offset={offset}"),
+        "application/octet-stream",
+        buf.clone(),
+      ) {
+        Ok(resp) => break json(&resp)?["received"].as_u64().unwrap_or(offset),
+        // the previous attempt landed but its answer was lost
+        Err(e) if e.status == Some(409) => {
+          break e
+            .message
+            .rsplit(' ')
+            .next()
+            .and_then(|n| n.parse().ok())
+            .ok_or(e)?
+        }
+        Err(e) if e.status.is_none() && attempt < CHUNK_ATTEMPTS => {
+          std::thread::sleep(RETRY_BACKOFF * attempt);
+        }
+        Err(e) => return Err(e),
+      }
+    };
+    let _ = app.emit(
+      "upload-progress",
+      UploadProgress {
+        id,
+        sent: offset,
+        total: size,
+      },
+    );
+  }
+  Ok(())
+}
+
+/// Streams the segments of the document at `path`, read as `extension`,
+/// to the upload at `url`; returns how many there were.
+fn send_segments(
+  app: &AppHandle,
+  id: &str,
+  url: &str,
+  path: &Path,
+  extension: &str,
+  size: u64,
+  split: impl Fn(Vec<Segment>) -> Vec<Segment>,
+) -> Result<usize, ProxyError> {
+  let mut failed = None;
+  let mut sent = 0;
+  let streamed = segments::stream(path, extension, |batch, read| {
+    let batch = split(batch);
+    sent += batch.len();
+    let body = serde_json::to_vec(&batch).unwrap_or_default();
+    send("POST", &format!("{url}/segments"), "application/json", body).map_err(|e| {
+      let message = e.message.clone();
+      failed = Some(e);
+      message
+    })?;
+    let _ = app.emit(
+      "segment-progress",
+      SegmentProgress {
+        id,
+        read,
+        total: size,
+        segments: sent,
+      },
+    );
+    Ok(())
+  });
+  let count = streamed.map_err(|e| {
+    failed
+      .take()
+      .unwrap_or_else(|| ProxyError::new("invalid-request", e))
+  })?;
+  if count == 0 {
+    return Err(ProxyError::new(
+      "invalid-request",
+      "The document has no text to translate",
+    ));
+  }
+  Ok(count)
+}
+
+pub fn upload(
+  app: &AppHandle,
+  id: &str,
+  path: &Path,
+  direction: &str,
+  cell_range: Option<&str>,
+  review: docx::Review,
+) -> Result<Value, ProxyError> {
+  let io_err =
+    |e: std::io::Error| ProxyError::new("invalid-request", format!("{}: {e}", path.display()));
+  let size = fs::metadata(path).map_err(io_err)?.len();
+  let name = path
+    .file_name()
+    .ok_or_else(|| ProxyError::new("invalid-request", "path has no file name"))?;
+  // named for what it is, so the backend takes a misnamed file too
+  let read_as = formats::route(path);
+  let filename = if read_as.is_empty() || read_as == formats::extension(path) {
+    name.to_string_lossy().into_owned()
+  } else {
+    Path::new(name)
+      .with_extension(&read_as)
+      .to_string_lossy()
+      .into_owned()
+  };
+  let base = proxy::target_base(app, false)
+    .ok_or_else(|| ProxyError::new("not-ready", "backend is not running"))?;
+  let config = app.state::<StartupConfig>();
+  let chunk = config.upload_chunk_mb.max(1) * 1024 * 1024;
+  let streamed =
+    size >= config.stream_segment_mb.max(1) * 1024 * 1024 && segments::streams(&read_as);
+  let settings = app.state::<SettingsState>().get();
+  let po_retranslate = settings.po_retranslate;
+  // long segments go as chunks, in formats whose entries may be split
+  let source_lang = direction.split_once("->").map_or("", |(s, _)| s);
+  let max_tokens = if segments::chunked(&read_as) {
+    settings.max_segment_tokens
+  } else {
+    0
+  };
+  let ocr = ocr::Session::new(app, id, direction);
+  // read decrypted, sent as it is
+  let unlocked = protected::unlock(app, id, path, &read_as)?;
+  let segments = if streamed {
+    None
+  } else {
+    segments::extract(path, cell_range, review, po_retranslate, &ocr)
+      .map_err(|e| ProxyError::new("invalid-request", e))?
+      .map(|s| segmentation::split(s, source_lang, max_tokens))
+      .map(|s| serde_json::to_string(&s).unwrap_or_default())
+  };
+  let password = unlocked.as_ref().map(|u| u.password.clone());
+  drop(unlocked);
+  let keep_password = |created: &Value| {
+    if let (Some(password), Some(task_id)) = (&password, created["task_id"].as_str()) {
+      protected::remember(task_id, password);
+    }
+  };
+  let mut fields = vec![("direction", direction)];
+  if let Some(segments) = &segments {
+    fields.push(("segments", segments.as_str()));
+  }
+  let progress = |sent| {
+    let _ = app.emit(
+      "upload-progress",
+      UploadProgress {
+        id,
+        sent,
+        total: size,
+      },
+    );
+  };
+  progress(0);
+
+  if size <= chunk && !streamed {
+    let data = fs::read(path).map_err(io_err)?;
+    let (content_type, body) = multipart(&fields, Some((&filename, &data)));
+    let resp = send("POST", &format!("{base}/api/tasks"), &content_type, body)?;
+    progress(size);
+    return json(&resp).inspect(keep_password);
+  }
+
+  let mut file = File::open(path).map_err(io_err)?;
+  let started = send(
+    "POST",
+    &format!("{base}/api/uploads"),
+    "application/json",
+    serde_json::to_vec(&serde_json::json!({ "filename": filename, "size": size }))
+      .unwrap_or_default(),
+  )?;
+  let upload_id = json(&started)?["upload_id"]
+    .as_str()
+    .unwrap_or_default()
+    .to_string();
+  let upload_url = format!("{base}/api/uploads/{upload_id}");
+  let result = send_chunks(app, id, &upload_url, &mut file, size, chunk).and_then(|()| {
+    if streamed {
+      send_segments(app, id, &upload_url, path, &read_as, size, |batch| {
+        segmentation::split(batch, source_lang, max_tokens)
+      })?;
+    }
+    fields.push(("upload_id", &upload_id));
+    let (content_type, body) = multipart(&fields, None);
+    json(&send(
+      "POST",
+      &format!("{base}/api/tasks"),
+      &content_type,
+      body,
+    )?)
+  });
+  if result.is_err() {
+    let _ = transport::request("DELETE", &upload_url, &[], Vec::new(), TIMEOUT);
+  }
+  result.inspect(keep_password)
+}
+
+/// Asks for a document with the native file dialog, starting in the
+/// folder of the last pick; `None` if cancelled.
+#[tauri::command]
+pub async fn pick_document(app: AppHandle) -> Option<String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    let settings = app.state::<SettingsState>();
+    let mut dialog = app.dialog().file().add_filter("Word document", &["docx"]);
+    if let Some(dir) = settings.get().last_document_dir {
+      dialog = dialog.set_directory(dir);
+    }
+    let path = dialog.blocking_pick_file()?.into_path().ok()?;
+    if let Some(dir) = path.parent() {
+      let _ = settings.update(
+        &app,
+        serde_json::json!({ "last_document_dir": dir.display().to_string() }),
+      );
+    }
+    Some(path.display().to_string())
+  })
+  .await
+  .ok()
+  .flatten()
+}
+
+/// Creates a translation task from the document at `path`, reporting
+/// `upload-progress` events tagged with `id`. Returns the backend's answer
+/// (`task_id`, `blocks`). `cell_range` limits a workbook to some cells,
+/// e.g. `Sheet1!A1:D50, Notes`; `review` (`translate` or `keep`) says
+/// whether a Word document's comments and tracked changes are translated.
+#[tauri::command]
+pub async fn upload_document(
+  app: AppHandle,
+  id: String,
+  path: String,
+  direction: String,
+  cell_range: Option<String>,
+  review: Option<String>,
+) -> Result<Value, ProxyError> {
+  let review =
+    docx::Review::parse(review.as_deref()).map_err(|e| ProxyError::new("invalid-request", e))?;
+  tauri::async_runtime::spawn_blocking(move || {
+    let path = Path::new(&path);
+    let created = upload(&app, &id, path, &direction, cell_range.as_deref(), review)?;
+    if let Some(task_id) = created["task_id"].as_str() {
+      output::remember(task_id, path);
+    }
+    Ok(created)
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
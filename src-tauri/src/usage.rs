@@ -0,0 +1,276 @@
+//! Provider usage and cost. The backend keeps the ledger (one row per
+//! provider call in its SQLite database, served at `/api/usage`); the shell
+//! prices it with the user's rates and warns once per month when the
+//! month's estimated cost passes `monthly_budget`.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{config, logs::BackendLog, paths, status::BackendState, transport};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Rough tokens per character, for providers that don't report tokens.
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// `usage` in `startup.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageConfig {
+  /// Estimated cost per calendar month that triggers a warning; 0 for none.
+  pub monthly_budget: f64,
+  pub currency: String,
+  /// Rates by provider host, or `host/model` to override one model.
+  pub prices: BTreeMap<String, Price>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Price {
+  pub input_per_million_tokens: f64,
+  pub output_per_million_tokens: f64,
+}
+
+pub struct UsageState {
+  config: Mutex<UsageConfig>,
+  /// Start of the month already warned about.
+  warned_month: Mutex<Option<i64>>,
+}
+
+impl UsageState {
+  pub fn new(config: UsageConfig) -> Self {
+    Self {
+      config: Mutex::new(config),
+      warned_month: Mutex::new(None),
+    }
+  }
+
+  pub fn config(&self) -> UsageConfig {
+    self.config.lock().unwrap().clone()
+  }
+}
+
+#[derive(Deserialize)]
+struct Ledger {
+  since: f64,
+  rows: Vec<LedgerRow>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct LedgerRow {
+  provider: String,
+  model: String,
+  requests: u64,
+  prompt_tokens: u64,
+  completion_tokens: u64,
+  chars_in: u64,
+  chars_out: u64,
+  /// Of the calls the provider reported no tokens for.
+  estimated_requests: u64,
+  estimated_chars_in: u64,
+  estimated_chars_out: u64,
+}
+
+#[derive(Serialize)]
+pub struct UsageRow {
+  pub provider: String,
+  pub model: String,
+  pub requests: u64,
+  pub prompt_tokens: u64,
+  pub completion_tokens: u64,
+  pub chars_in: u64,
+  pub chars_out: u64,
+  /// The provider reported no tokens for some calls; their counts are
+  /// derived from characters.
+  pub tokens_estimated: bool,
+  /// `None` when no price is configured for this provider.
+  pub cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct UsageReport {
+  pub period: String,
+  /// Unix time the period started at (local midnight).
+  pub since: f64,
+  pub rows: Vec<UsageRow>,
+  pub total_cost: f64,
+  pub currency: String,
+  pub monthly_budget: f64,
+}
+
+#[derive(Clone, Serialize)]
+struct BudgetExceeded {
+  month_cost: f64,
+  budget: f64,
+  currency: String,
+}
+
+/// The rates set for `model` at `provider`, or for all its models.
+pub fn price<'a>(cfg: &'a UsageConfig, provider: &str, model: &str) -> Option<&'a Price> {
+  cfg
+    .prices
+    .get(&format!("{provider}/{model}"))
+    .or_else(|| cfg.prices.get(provider))
+}
+
+fn fetch(app: &AppHandle, period: &str, task_id: Option<&str>) -> Result<Ledger, String> {
+  if !matches!(period, "day" | "week" | "month" | "all") {
+    return Err(format!(
+      "Unknown period {period}: use day, week, month or all"
+    ));
+  }
+  let task_id = task_id.unwrap_or_default();
+  if !task_id
+    .chars()
+    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+  {
+    return Err(format!("Invalid task id: {task_id}"));
+  }
+  let base = app
+    .state::<BackendState>()
+    .base_url()
+    .ok_or("backend is not running")?;
+  let url = format!("{base}/api/usage?period={period}&task_id={task_id}");
+  let resp =
+    transport::request("GET", &url, &[], Vec::new(), TIMEOUT).map_err(|e| e.to_string())?;
+  if !resp.is_success() {
+    return Err(format!("usage ledger answered {}", resp.status));
+  }
+  serde_json::from_slice(&resp.body).map_err(|e| e.to_string())
+}
+
+fn report(app: &AppHandle, period: &str, task_id: Option<&str>) -> Result<UsageReport, String> {
+  let ledger = fetch(app, period, task_id)?;
+  let cfg = app.state::<UsageState>().config.lock().unwrap().clone();
+  let rows: Vec<UsageRow> = ledger
+    .rows
+    .into_iter()
+    .map(|row| {
+      // the calls with tokens as reported, the others estimated
+      let estimated = row.estimated_requests > 0;
+      let input = row.prompt_tokens + (row.estimated_chars_in as f64 / CHARS_PER_TOKEN) as u64;
+      let output =
+        row.completion_tokens + (row.estimated_chars_out as f64 / CHARS_PER_TOKEN) as u64;
+      let cost = price(&cfg, &row.provider, &row.model).map(|p| {
+        (input as f64 * p.input_per_million_tokens + output as f64 * p.output_per_million_tokens)
+          / 1_000_000.0
+      });
+      UsageRow {
+        provider: row.provider,
+        model: row.model,
+        requests: row.requests,
+        prompt_tokens: input,
+        completion_tokens: output,
+        chars_in: row.chars_in,
+        chars_out: row.chars_out,
+        tokens_estimated: estimated,
+        cost,
+      }
+    })
+    .collect();
+  let report = UsageReport {
+    period: period.to_string(),
+    since: ledger.since,
+    total_cost: rows.iter().filter_map(|r| r.cost).sum(),
+    rows,
+    currency: cfg.currency,
+    monthly_budget: cfg.monthly_budget,
+  };
+  if period == "month" && task_id.is_none() {
+    warn_if_over(app, &report);
+  }
+  Ok(report)
+}
+
+/// Emits `usage-budget-exceeded` the first time a month goes over budget.
+fn warn_if_over(app: &AppHandle, month: &UsageReport) {
+  if month.monthly_budget <= 0.0 || month.total_cost <= month.monthly_budget {
+    return;
+  }
+  let key = month.since as i64;
+  {
+    let state = app.state::<UsageState>();
+    let mut warned = state.warned_month.lock().unwrap();
+    if *warned == Some(key) {
+      return;
+    }
+    *warned = Some(key);
+  }
+  app.state::<BackendLog>().append(
+    "usage",
+    format!(
+      "monthly budget exceeded: {:.2} of {:.2} {}",
+      month.total_cost, month.monthly_budget, month.currency
+    )
+    .as_bytes(),
+  );
+  let _ = app.emit(
+    "usage-budget-exceeded",
+    BudgetExceeded {
+      month_cost: month.total_cost,
+      budget: month.monthly_budget,
+      currency: month.currency.clone(),
+    },
+  );
+}
+
+/// Re-checks the month's spend in the background, e.g. after a job ended.
+pub fn check_budget(app: &AppHandle) {
+  let budget = app
+    .state::<UsageState>()
+    .config
+    .lock()
+    .unwrap()
+    .monthly_budget;
+  if budget <= 0.0 {
+    return;
+  }
+  let app = app.clone();
+  std::thread::spawn(move || {
+    let _ = report(&app, "month", None);
+  });
+}
+
+/// Usage for `period` (`day`, `week`, `month` or `all`), optionally for
+/// one task, with the estimated cost per provider and model.
+#[tauri::command]
+pub async fn get_usage_report(
+  app: AppHandle,
+  period: String,
+  task_id: Option<String>,
+) -> Result<UsageReport, String> {
+  tauri::async_runtime::spawn_blocking(move || report(&app, &period, task_id.as_deref()))
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn get_usage_config(state: State<UsageState>) -> UsageConfig {
+  state.config.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_usage_config(
+  app: AppHandle,
+  state: State<UsageState>,
+  config: UsageConfig,
+) -> Result<UsageConfig, String> {
+  let valid = |v: f64| v.is_finite() && v >= 0.0;
+  if !valid(config.monthly_budget)
+    || !config
+      .prices
+      .values()
+      .all(|p| valid(p.input_per_million_tokens) && valid(p.output_per_million_tokens))
+  {
+    return Err("Budget and prices must be zero or positive numbers".to_string());
+  }
+  let data_dir = paths::data_dir(&app).map_err(|e| e.to_string())?;
+  let value = serde_json::to_value(&config).map_err(|e| e.to_string())?;
+  config::persist_key(&data_dir, "usage", value)
+    .map_err(|e| format!("Cannot save usage settings: {e}"))?;
+  *state.config.lock().unwrap() = config.clone();
+  *state.warned_month.lock().unwrap() = None;
+  check_budget(&app);
+  Ok(config)
+}
@@ -0,0 +1,70 @@
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+use crate::transport;
+
+/// Backend releases this shell can talk to.
+pub const SUPPORTED_BACKEND: &str = ">=0.1.0, <0.2.0";
+
+#[derive(Deserialize)]
+struct VersionInfo {
+  version: String,
+}
+
+/// Payload of the `backend-incompatible` event; also the launch error, so
+/// the supervisor can tell it apart from a crash and stop retrying.
+#[derive(Debug, Clone, Serialize)]
+pub struct IncompatibleBackend {
+  /// `None` if the backend predates `/api/version`.
+  pub backend_version: Option<String>,
+  pub required: String,
+  pub reason: String,
+}
+
+impl fmt::Display for IncompatibleBackend {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Incompatible backend {} (requires {}): {}",
+      self.backend_version.as_deref().unwrap_or("<unknown>"),
+      self.required,
+      self.reason
+    )
+  }
+}
+
+impl std::error::Error for IncompatibleBackend {}
+
+fn incompatible(backend_version: Option<String>, reason: impl Into<String>) -> IncompatibleBackend {
+  IncompatibleBackend {
+    backend_version,
+    required: SUPPORTED_BACKEND.to_string(),
+    reason: reason.into(),
+  }
+}
+
+/// Queries `/api/version` and checks it against [`SUPPORTED_BACKEND`].
+pub fn check(base_url: &str) -> Result<Version, IncompatibleBackend> {
+  let resp = transport::get(&format!("{}/api/version", base_url), Duration::from_secs(3))
+    .map_err(|e| incompatible(None, format!("version query failed: {e}")))?;
+  if !resp.is_success() {
+    return Err(incompatible(
+      None,
+      format!("/api/version returned {}", resp.status),
+    ));
+  }
+  let info: VersionInfo = serde_json::from_slice(&resp.body)
+    .map_err(|e| incompatible(None, format!("malformed /api/version response: {e}")))?;
+
+  let version = Version::parse(&info.version)
+    .map_err(|e| incompatible(Some(info.version.clone()), format!("not a version: {e}")))?;
+  let req = VersionReq::parse(SUPPORTED_BACKEND).expect("valid version requirement");
+  if !req.matches(&version) {
+    return Err(incompatible(
+      Some(info.version),
+      "version outside supported range",
+    ));
+  }
+  Ok(version)
+}
@@ -0,0 +1,431 @@
+//! Hot folders: documents dropped into one are translated unattended.
+//! Each folder is watched (with `notify`) for changes right in it, not in
+//! its subfolders; on one the shell looks at the folder's files, and a
+//! document that can be translated and has kept its size and modification
+//! time for `SETTLE` is queued as a job (see `jobs`) with the folder's
+//! profile and direction. Once translated, the
+//! document and its translated copy (named by the output template) go to
+//! the folder's `done` subfolder. A document whose job fails or is
+//! cancelled stays where it is and is not queued again until it changes
+//! or the app restarts.
+//!
+//! Folders on network shares, where OS notifications miss changes made from
+//! other machines, are polled every `POLL` instead (see
+//! `os::network_share`), and so are folders notifications cannot be had
+//! for. Hidden files and Office lock files (`~$report.docx`) are left
+//! alone.
+//!
+//! A tray icon shows, while any folder is watched, how many of its
+//! documents are waiting; what happens to each is reported as a
+//! `watch-notification` event, which the window shows as a system
+//! notification. The folders are kept in `<data dir>/watch-folders.json`.
+
+use notify::{
+  event::{AccessKind, AccessMode},
+  Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Mutex, OnceLock,
+  },
+  thread,
+  time::{Duration, Instant, SystemTime},
+};
+use tauri::{
+  tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
+  AppHandle, Emitter, Manager,
+};
+
+use crate::{
+  formats,
+  jobs::{self, Placement},
+  logs::BackendLog,
+  os, profiles,
+  proxy::{invalid, ProxyError},
+  settings::SettingsState,
+};
+
+/// How often folders on network shares are looked at.
+const POLL: Duration = Duration::from_secs(2);
+/// How often documents settling or being translated are looked at again.
+const TICK: Duration = Duration::from_secs(1);
+/// How long a file must stay unchanged before it is taken as complete.
+const SETTLE: Duration = Duration::from_secs(3);
+const DONE: &str = "done";
+const TRAY: &str = "watch";
+
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+static FOLDERS: Mutex<Vec<WatchFolder>> = Mutex::new(Vec::new());
+/// Wakes the watching thread with the folder that changed, or `None` when
+/// the folders did.
+static WAKE: OnceLock<Sender<Option<String>>> = OnceLock::new();
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WatchFolder {
+  path: String,
+  /// Translates the folder's documents instead of the active profile.
+  profile_id: Option<String>,
+  /// Defaults to the one in the settings when the document is queued.
+  direction: Option<String>,
+}
+
+/// Payload of `watch-notification`.
+#[derive(Clone, Serialize)]
+struct Notification {
+  folder: String,
+  path: String,
+  /// `queued`, `translated` or `failed`.
+  kind: &'static str,
+  message: String,
+}
+
+/// A file seen in a folder, as it was when last looked at.
+struct Seen {
+  size: u64,
+  modified: Option<SystemTime>,
+  /// When it was first seen like this.
+  since: Instant,
+  stage: Stage,
+}
+
+enum Stage {
+  Settling,
+  /// With the job translating it.
+  Queued(String),
+  /// Translated, failed or not translatable; left alone unless it changes.
+  Handled,
+}
+
+impl Seen {
+  fn new(size: u64, modified: Option<SystemTime>) -> Self {
+    Self {
+      size,
+      modified,
+      since: Instant::now(),
+      stage: Stage::Settling,
+    }
+  }
+}
+
+fn folders() -> Vec<WatchFolder> {
+  FOLDERS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn save(app: &AppHandle, folders: &[WatchFolder]) {
+  let Some(file) = FILE.get() else {
+    return;
+  };
+  let written = serde_json::to_vec_pretty(folders)
+    .map_err(|e| e.to_string())
+    .and_then(|data| fs::write(file, data).map_err(|e| e.to_string()));
+  if let Err(e) = written {
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot save the watch folders: {e}").as_bytes(),
+    );
+  }
+}
+
+/// Loads the watched folders; called once at startup.
+pub fn init(data_dir: &Path) {
+  let file = data_dir.join("watch-folders.json");
+  let saved: Vec<WatchFolder> = fs::read(&file)
+    .ok()
+    .and_then(|data| serde_json::from_slice(&data).ok())
+    .unwrap_or_default();
+  *FOLDERS.lock().unwrap_or_else(|e| e.into_inner()) = saved;
+  let _ = FILE.set(file);
+}
+
+/// Whether the file named `name` is one to pick up.
+fn candidate(name: &str) -> bool {
+  !name.starts_with('.') && !name.starts_with("~$")
+}
+
+fn notify(app: &AppHandle, folder: &str, path: &Path, kind: &'static str, message: String) {
+  let _ = app.emit(
+    "watch-notification",
+    Notification {
+      folder: folder.to_string(),
+      path: path.display().to_string(),
+      kind,
+      message,
+    },
+  );
+}
+
+/// Shows the tray icon with `waiting` documents while folders are
+/// watched, and removes it when none are.
+fn tray(app: &AppHandle, watched: usize, waiting: usize) {
+  if watched == 0 {
+    let _ = app.remove_tray_by_id(TRAY);
+    return;
+  }
+  let tooltip = format!("Watching {watched} folders, {waiting} documents waiting");
+  if let Some(tray) = app.tray_by_id(TRAY) {
+    let _ = tray.set_tooltip(Some(&tooltip));
+    return;
+  }
+  let mut builder = TrayIconBuilder::with_id(TRAY)
+    .tooltip(&tooltip)
+    .on_tray_icon_event(|tray, event| {
+      if let TrayIconEvent::Click {
+        button: MouseButton::Left,
+        button_state: MouseButtonState::Up,
+        ..
+      } = event
+      {
+        if let Some(window) = tray.app_handle().get_webview_window("main") {
+          let _ = window.show();
+          let _ = window.set_focus();
+        }
+      }
+    });
+  if let Some(icon) = app.default_window_icon() {
+    builder = builder.icon(icon.clone());
+  }
+  if let Err(e) = builder.build(app) {
+    app.state::<BackendLog>().append(
+      "shell",
+      format!("cannot show the tray icon: {e}").as_bytes(),
+    );
+  }
+}
+
+fn wake(folder: Option<String>) {
+  if let Some(wake) = WAKE.get() {
+    let _ = wake.send(folder);
+  }
+}
+
+/// Watches the folder at `path` through OS notifications, or by polling it
+/// when it is on a network share or there are none for it.
+fn watcher(path: &str) -> notify::Result<Box<dyn Watcher + Send>> {
+  let folder = path.to_string();
+  let handler = move |event: notify::Result<Event>| {
+    let Ok(event) = event else {
+      return;
+    };
+    // reading a file is no change, closing it after writing is
+    if matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write))
+    {
+      return;
+    }
+    wake(Some(folder.clone()));
+  };
+  let dir = Path::new(path);
+  if !os::network_share(dir) {
+    if let Ok(mut watcher) = RecommendedWatcher::new(handler.clone(), Config::default()) {
+      if watcher.watch(dir, RecursiveMode::NonRecursive).is_ok() {
+        return Ok(Box::new(watcher));
+      }
+    }
+  }
+  let mut watcher = PollWatcher::new(handler, Config::default().with_poll_interval(POLL))?;
+  watcher.watch(dir, RecursiveMode::NonRecursive)?;
+  Ok(Box::new(watcher))
+}
+
+/// Looks at the files in `folder`: queues the documents that have
+/// settled.
+fn scan(app: &AppHandle, folder: &WatchFolder, seen: &mut HashMap<PathBuf, Seen>) {
+  let dir = Path::new(&folder.path);
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  let mut present = Vec::new();
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let Ok(meta) = entry.metadata() else {
+      continue;
+    };
+    if !meta.is_file() || !candidate(&entry.file_name().to_string_lossy()) {
+      continue;
+    }
+    present.push(path.clone());
+    let modified = meta.modified().ok();
+    let file = seen
+      .entry(path.clone())
+      .or_insert_with(|| Seen::new(meta.len(), modified));
+    if file.size != meta.len() || file.modified != modified {
+      // still being written, or changed since it was handled
+      *file = Seen::new(meta.len(), modified);
+      continue;
+    }
+    if !matches!(file.stage, Stage::Settling)
+      || file.since.elapsed() < SETTLE
+      || jobs::pending(&path)
+    {
+      continue;
+    }
+    if !formats::format(&formats::route(&path)).is_some_and(|f| f.translatable) {
+      file.stage = Stage::Handled;
+      continue;
+    }
+    let direction = folder
+      .direction
+      .clone()
+      .unwrap_or_else(|| app.state::<SettingsState>().get().direction);
+    let placement = Placement {
+      output_dir: Some(dir.join(DONE)),
+      profile_id: folder.profile_id.clone(),
+      done_dir: Some(dir.join(DONE)),
+      ..Default::default()
+    };
+    let job = jobs::enqueue(app, vec![(path.clone(), placement)], &direction, 0).remove(0);
+    file.stage = Stage::Queued(job.id().to_string());
+    notify(
+      app,
+      &folder.path,
+      &path,
+      "queued",
+      format!("Translating {}", path.display()),
+    );
+  }
+  seen.retain(|path, _| present.contains(path));
+}
+
+/// Reports the documents of `folder` whose jobs ended, moved to `done` or
+/// left in place.
+fn report(app: &AppHandle, folder: &WatchFolder, seen: &mut HashMap<PathBuf, Seen>) {
+  for (path, file) in seen.iter_mut() {
+    let Stage::Queued(id) = &file.stage else {
+      continue;
+    };
+    match jobs::outcome(id) {
+      None => continue,
+      Some(Ok(output)) => notify(
+        app,
+        &folder.path,
+        path,
+        "translated",
+        match output {
+          Some(output) => format!("Translated to {output}"),
+          None => "Translated; the translated copy exists and was kept".to_string(),
+        },
+      ),
+      Some(Err(e)) => notify(app, &folder.path, path, "failed", e),
+    }
+    file.stage = Stage::Handled;
+  }
+}
+
+fn watch(app: AppHandle, woken: Receiver<Option<String>>) {
+  let mut seen: HashMap<String, HashMap<PathBuf, Seen>> = HashMap::new();
+  let mut watchers: HashMap<String, Box<dyn Watcher + Send>> = HashMap::new();
+  // folders changed since they were last looked at
+  let mut changed = HashSet::new();
+  loop {
+    let folders = folders();
+    seen.retain(|path, _| folders.iter().any(|f| &f.path == path));
+    watchers.retain(|path, _| folders.iter().any(|f| &f.path == path));
+    for folder in &folders {
+      if watchers.contains_key(&folder.path) {
+        continue;
+      }
+      // looked at whole once watched, and every `TICK` until it can be
+      match watcher(&folder.path) {
+        Ok(watcher) => {
+          watchers.insert(folder.path.clone(), watcher);
+        }
+        Err(e) if !seen.contains_key(&folder.path) => app.state::<BackendLog>().append(
+          "shell",
+          format!(
+            "cannot watch {}, looking at it every second: {e}",
+            folder.path
+          )
+          .as_bytes(),
+        ),
+        Err(_) => {}
+      }
+      changed.insert(folder.path.clone());
+    }
+    for folder in &folders {
+      let files = seen.entry(folder.path.clone()).or_default();
+      let settling = files.values().any(|f| matches!(f.stage, Stage::Settling));
+      if changed.remove(&folder.path) || settling {
+        scan(&app, folder, files);
+      }
+      report(&app, folder, files);
+    }
+    let waiting = seen
+      .values()
+      .flat_map(HashMap::values)
+      .filter(|file| matches!(file.stage, Stage::Queued(_)))
+      .count();
+    let app_for_tray = app.clone();
+    let watched = folders.len();
+    // tray icons belong to the main thread
+    let _ = app.run_on_main_thread(move || tray(&app_for_tray, watched, waiting));
+    let busy = watchers.len() < folders.len()
+      || seen
+        .values()
+        .flat_map(HashMap::values)
+        .any(|file| !matches!(file.stage, Stage::Handled));
+    let first = if busy {
+      woken.recv_timeout(TICK).ok()
+    } else {
+      woken.recv().ok()
+    };
+    changed.extend(first.into_iter().chain(woken.try_iter()).flatten());
+  }
+}
+
+pub fn spawn(app: AppHandle) {
+  let (wake, woken) = mpsc::channel();
+  let _ = WAKE.set(wake);
+  thread::spawn(move || watch(app, woken));
+}
+
+/// Watches the folder at `path`, its documents translated with profile
+/// `profile_id` (the active one when `None`) in `direction` (the one in
+/// the settings when `None`). Watching a folder again changes those.
+#[tauri::command]
+pub fn add_watch_folder(
+  app: AppHandle,
+  path: String,
+  profile_id: Option<String>,
+  direction: Option<String>,
+) -> Result<Vec<WatchFolder>, ProxyError> {
+  if !Path::new(&path).is_dir() {
+    return Err(invalid(format!("{path} is not a folder")));
+  }
+  if let Some(id) = &profile_id {
+    profiles::check_id(id)?;
+  }
+  if let Some(direction) = &direction {
+    if !matches!(direction.as_str(), "en->zh" | "zh->en") {
+      return Err(invalid(format!("Unsupported direction {direction}")));
+    }
+  }
+  let mut folders = FOLDERS.lock().unwrap_or_else(|e| e.into_inner());
+  folders.retain(|f| f.path != path);
+  folders.push(WatchFolder {
+    path,
+    profile_id,
+    direction,
+  });
+  save(&app, &folders);
+  wake(None);
+  Ok(folders.clone())
+}
+
+/// Stops watching the folder at `path`; jobs already queued from it run.
+#[tauri::command]
+pub fn remove_watch_folder(app: AppHandle, path: String) -> Vec<WatchFolder> {
+  let mut folders = FOLDERS.lock().unwrap_or_else(|e| e.into_inner());
+  folders.retain(|f| f.path != path);
+  save(&app, &folders);
+  wake(None);
+  folders.clone()
+}
+
+#[tauri::command]
+pub fn list_watch_folders() -> Vec<WatchFolder> {
+  folders()
+}
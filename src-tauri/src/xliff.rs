@@ -0,0 +1,526 @@
+//! XLIFF, the exchange format of CAT tools (Trados, memoQ and the like),
+//! both ways.
+//!
+//! As a document: XLIFF 1.2 and 2.0 files are read and rebuilt in the
+//! shell like `html`. Segments are the `<source>` of each 1.2 trans-unit
+//! or 2.0 segment that has no translation yet (no or an empty `<target>`,
+//! or a `new`, `needs-translation` or `initial` state), except in units
+//! marked `translate="no"`. Inline codes go to the translator as
+//! placeholders, `⟦1⟧here⟦/1⟧` for `<g>`, `<pc>` and `<mrk>` and `⟦2⟧`
+//! for the rest; a translation that loses one is left out and reported.
+//! Translations are written as `<target>`s with the state set, a machine
+//! translation's being `needs-review-translation` (`mt-suggestion`) in
+//! 1.2 and `translated` in 2.0; everything else stays byte for byte.
+//! Locators are `xlf:<n>`, trans-units or segments counted from 0.
+//!
+//! For any task: [`export_xliff`] writes its blocks as an XLIFF file of
+//! either version, source and translation side by side, placeholders as
+//! inline codes.
+
+use quick_xml::{
+  escape::{escape, partial_escape},
+  events::{BytesStart, Event},
+  Reader,
+};
+use std::{fs, ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::{
+  placeholders::{balanced, marker, pieces, Piece, Role, Tag, Warning},
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+/// Inline codes that wrap text.
+const PAIRED: &[&[u8]] = &[b"g", b"pc", b"mrk"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Version {
+  V1,
+  V2,
+}
+
+struct Unit {
+  /// Where the `<source>` element starts.
+  source_start: usize,
+  /// The `<target>` element, or an empty range where one goes.
+  target: Range<usize>,
+  /// The target's start tag (or empty element).
+  target_tag: Option<Range<usize>>,
+  /// XLIFF 2.0: the `<segment>` start tag, which holds the state.
+  segment_tag: Option<Range<usize>>,
+  text: String,
+  tags: Vec<Tag>,
+  wanted: bool,
+}
+
+struct Document {
+  version: Version,
+  units: Vec<Unit>,
+  /// `<file>` (1.2) or `<xliff>` (2.0) tags without a target language.
+  language_tags: Vec<Range<usize>>,
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+  e.try_get_attribute(key)
+    .ok()
+    .flatten()
+    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+fn xml_err(e: impl std::fmt::Display) -> String {
+  format!("Invalid XLIFF: {e}")
+}
+
+/// Reads the content of a `<source>`, up to and including `</source>`.
+/// Returns its text with placeholders, and the tags.
+fn inline(reader: &mut Reader<&[u8]>) -> Result<(String, Vec<Tag>), String> {
+  let mut text = String::new();
+  let mut tags: Vec<Tag> = Vec::new();
+  // placeholder numbers of the open paired codes
+  let mut open: Vec<usize> = Vec::new();
+  loop {
+    let start = reader.buffer_position() as usize;
+    let event = reader.read_event().map_err(xml_err)?;
+    let end = reader.buffer_position() as usize;
+    match event {
+      Event::Text(t) => text.push_str(&t.unescape().map_err(xml_err)?),
+      Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t)),
+      Event::Start(e) if PAIRED.contains(&e.local_name().as_ref()) => {
+        tags.push((start..end, Role::Whole));
+        open.push(tags.len());
+        text.push_str(&marker(tags.len(), false));
+      }
+      // native code, kept with what is inside it
+      Event::Start(e) => {
+        reader.read_to_end(e.name()).map_err(xml_err)?;
+        tags.push((start..reader.buffer_position() as usize, Role::Whole));
+        text.push_str(&marker(tags.len(), false));
+      }
+      Event::Empty(_) => {
+        tags.push((start..end, Role::Whole));
+        text.push_str(&marker(tags.len(), false));
+      }
+      Event::End(_) => match open.pop() {
+        Some(number) => {
+          tags[number - 1].1 = Role::Element(start..end);
+          text.push_str(&marker(number, true));
+        }
+        // `</source>`
+        None => return Ok((text.trim().to_string(), tags)),
+      },
+      Event::Eof => return Err(xml_err("a source is not closed")),
+      _ => {}
+    }
+  }
+}
+
+fn parse(data: &[u8]) -> Result<Document, String> {
+  let mut reader = Reader::from_reader(data);
+  let mut version = Version::V1;
+  let mut units = Vec::new();
+  let mut language_tags = Vec::new();
+  let mut stack: Vec<Vec<u8>> = Vec::new();
+  let mut skipped = false;
+  let mut segment_tag = None;
+  let mut unit: Option<Unit> = None;
+  // a 1.2 target's state, or a 2.0 segment's
+  let mut state = None;
+  loop {
+    let start = reader.buffer_position() as usize;
+    let event = reader.read_event().map_err(xml_err)?;
+    let end = reader.buffer_position() as usize;
+    match &event {
+      Event::Start(e) | Event::Empty(e) => {
+        let name = e.local_name().as_ref().to_vec();
+        let in_segment = stack.last().is_some_and(|parent| {
+          parent.as_slice()
+            == match version {
+              Version::V1 => b"trans-unit".as_slice(),
+              Version::V2 => b"segment",
+            }
+        });
+        match name.as_slice() {
+          b"xliff" if attribute(e, "version").is_some_and(|v| v.starts_with('2')) => {
+            version = Version::V2;
+            if attribute(e, "trgLang").is_none() {
+              language_tags.push(start..end);
+            }
+          }
+          b"file" if version == Version::V1 && attribute(e, "target-language").is_none() => {
+            language_tags.push(start..end)
+          }
+          b"trans-unit" | b"unit" => skipped = attribute(e, "translate").as_deref() == Some("no"),
+          b"segment" => {
+            segment_tag = Some(start..end);
+            state = attribute(e, "state");
+          }
+          b"source" if in_segment && matches!(event, Event::Start(_)) => {
+            let (text, tags) = inline(&mut reader)?;
+            let after = reader.buffer_position() as usize;
+            unit = Some(Unit {
+              source_start: start,
+              target: after..after,
+              target_tag: None,
+              segment_tag: segment_tag.clone(),
+              text,
+              tags,
+              wanted: false,
+            });
+            continue;
+          }
+          b"target" if in_segment => {
+            let content = match &event {
+              Event::Start(e) => {
+                let span = reader.read_to_end(e.name()).map_err(xml_err)?;
+                span.start as usize..span.end as usize
+              }
+              _ => end..end,
+            };
+            if version == Version::V1 {
+              state = attribute(e, "state");
+            }
+            if let Some(unit) = unit.as_mut() {
+              unit.target = start..reader.buffer_position() as usize;
+              unit.target_tag = Some(start..end);
+              unit.wanted = data[content].iter().all(u8::is_ascii_whitespace);
+            }
+            continue;
+          }
+          _ => {}
+        }
+        if matches!(event, Event::Start(_)) {
+          stack.push(name);
+        }
+      }
+      Event::End(e) => {
+        stack.pop();
+        let ends_unit = match version {
+          Version::V1 => e.local_name().as_ref() == b"trans-unit",
+          Version::V2 => e.local_name().as_ref() == b"segment",
+        };
+        if ends_unit {
+          if let Some(mut unit) = unit.take() {
+            let untranslated = matches!(
+              state.as_deref(),
+              Some("new" | "needs-translation" | "initial")
+            );
+            unit.wanted = !skipped && (unit.target_tag.is_none() || unit.wanted || untranslated);
+            units.push(unit);
+          }
+          state = None;
+          segment_tag = None;
+        }
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+  Ok(Document {
+    version,
+    units,
+    language_tags,
+  })
+}
+
+/// `tag` with attribute `key` set to `value`, added if missing.
+fn set_attribute(tag: &str, key: &str, value: &str) -> String {
+  let value = escape(value);
+  for quote in ['"', '\''] {
+    let pattern = format!("{key}={quote}");
+    let found = tag
+      .match_indices(&pattern)
+      .map(|(at, _)| at)
+      .find(|&at| tag[..at].ends_with(char::is_whitespace));
+    if let Some(at) = found {
+      let from = at + pattern.len();
+      if let Some(length) = tag[from..].find(quote) {
+        return format!("{}{value}{}", &tag[..from], &tag[from + length..]);
+      }
+    }
+  }
+  let end = tag.len() - if tag.ends_with("/>") { 2 } else { 1 };
+  format!("{} {key}=\"{value}\"{}", tag[..end].trim_end(), &tag[end..])
+}
+
+/// Source and target language codes for a task's direction.
+fn languages(direction: &str) -> (&'static str, &'static str) {
+  match direction {
+    "zh->en" => ("zh-CN", "en"),
+    _ => ("en", "zh-CN"),
+  }
+}
+
+pub fn segments(path: &Path) -> Result<Vec<Segment>, String> {
+  let data = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  Ok(
+    parse(&data)?
+      .units
+      .into_iter()
+      .enumerate()
+      // numbers, lone codes and other sources without words
+      .filter(|(_, u)| u.wanted && u.text.chars().any(char::is_alphabetic))
+      .map(|(n, u)| Segment {
+        locator: format!("xlf:{n}"),
+        text: u.text,
+      })
+      .collect(),
+  )
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<Vec<Warning>, ProxyError> {
+  let task = segments::fetch(app, job_id)?;
+  let direction = segments::info(app, job_id)?.direction;
+  let translations = task.translations();
+  let document = parse(&task.source).map_err(invalid)?;
+  let text = std::str::from_utf8(&task.source).map_err(|_| invalid("XLIFF files must be UTF-8"))?;
+  let slice = |range: &Range<usize>| &text[range.clone()];
+
+  let mut warnings = Vec::new();
+  let mut replaced: Vec<(Range<usize>, String)> = Vec::new();
+  let (_, target_language) = languages(&direction);
+  let language_key = match document.version {
+    Version::V1 => "target-language",
+    Version::V2 => "trgLang",
+  };
+  for range in &document.language_tags {
+    let tag = set_attribute(slice(range), language_key, target_language);
+    replaced.push((range.clone(), tag));
+  }
+  for (n, unit) in document.units.iter().enumerate() {
+    let translation = translations
+      .get(format!("xlf:{n}").as_str())
+      // not translated yet
+      .filter(|t| unit.wanted && **t != unit.text);
+    let Some(translation) = translation else {
+      continue;
+    };
+    let pieces = pieces(translation);
+    if !balanced(&pieces, &unit.tags) {
+      warnings.push(Warning {
+        excerpt: unit.text.chars().take(40).collect(),
+        message: "inline codes did not survive translation; left untranslated".to_string(),
+      });
+      continue;
+    }
+    let mut content = String::new();
+    for piece in pieces {
+      match piece {
+        Piece::Text(text) => content.push_str(&partial_escape(text)),
+        Piece::Tag(number, false) => content.push_str(slice(&unit.tags[number - 1].0)),
+        Piece::Tag(number, true) => {
+          if let (_, Role::Element(end)) = &unit.tags[number - 1] {
+            content.push_str(slice(end));
+          }
+        }
+      }
+    }
+
+    // the existing target's tag keeps its attributes
+    let tag = match &unit.target_tag {
+      Some(range) => {
+        let tag = slice(range);
+        match tag.strip_suffix("/>") {
+          Some(open) => format!("{}>", open.trim_end()),
+          None => tag.to_string(),
+        }
+      }
+      None => "<target>".to_string(),
+    };
+    let name = tag[1..]
+      .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+      .next()
+      .unwrap_or("target")
+      .to_string();
+    let tag = match document.version {
+      Version::V1 => set_attribute(
+        &set_attribute(&tag, "state", "needs-review-translation"),
+        "state-qualifier",
+        "mt-suggestion",
+      ),
+      Version::V2 => tag,
+    };
+    let mut element = format!("{tag}{content}</{name}>");
+    if unit.target_tag.is_none() {
+      // on a line of its own, indented like the source
+      let line = text[..unit.source_start].rfind('\n').map_or(0, |i| i + 1);
+      let indent = &text[line..unit.source_start];
+      if indent.trim().is_empty() {
+        element = format!("\n{indent}{element}");
+      }
+    }
+    replaced.push((unit.target.clone(), element));
+    if let (Version::V2, Some(range)) = (document.version, &unit.segment_tag) {
+      replaced.push((
+        range.clone(),
+        set_attribute(slice(range), "state", "translated"),
+      ));
+    }
+  }
+  replaced.sort_by_key(|(range, _)| (range.start, range.end));
+
+  let mut out = String::with_capacity(text.len());
+  let mut at = 0;
+  for (range, with) in replaced {
+    out.push_str(&text[at..range.start]);
+    out.push_str(&with);
+    at = range.end;
+  }
+  out.push_str(&text[at..]);
+  fs::write(path, out).map_err(|e| invalid(format!("Cannot write {}: {e}", path.display())))?;
+  Ok(warnings)
+}
+
+/// Writes the translated copy of XLIFF task `job_id` to `path`. Returns the
+/// units left untranslated for losing an inline code.
+#[tauri::command]
+pub async fn export_translated_xlf(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<Vec<Warning>, ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
+
+/// `text` with its placeholders as inline codes: pairs that nest as
+/// `<g>`/`<pc>`, others as standalone codes.
+fn codes(text: &str, version: Version) -> String {
+  let pieces = pieces(text);
+  let closed: Vec<usize> = pieces
+    .iter()
+    .filter_map(|p| match p {
+      Piece::Tag(number, true) => Some(*number),
+      _ => None,
+    })
+    .collect();
+  let mut open = Vec::new();
+  let nested = pieces.iter().all(|p| match *p {
+    Piece::Tag(number, false) if closed.contains(&number) => {
+      open.push(number);
+      true
+    }
+    Piece::Tag(number, true) => open.pop() == Some(number),
+    _ => true,
+  }) && open.is_empty();
+  let mut out = String::new();
+  for piece in pieces {
+    let code = match (piece, version) {
+      (Piece::Text(text), _) => {
+        out.push_str(&partial_escape(text));
+        continue;
+      }
+      (Piece::Tag(n, false), Version::V1) if closed.contains(&n) && nested => {
+        format!("<g id=\"{n}\">")
+      }
+      (Piece::Tag(n, false), Version::V2) if closed.contains(&n) && nested => {
+        format!("<pc id=\"{n}\">")
+      }
+      (Piece::Tag(_, true), Version::V1) if nested => "</g>".to_string(),
+      (Piece::Tag(_, true), Version::V2) if nested => "</pc>".to_string(),
+      (Piece::Tag(n, false), Version::V1) if closed.contains(&n) => format!("<bx id=\"{n}\"/>"),
+      (Piece::Tag(n, false), Version::V2) if closed.contains(&n) => format!("<sc id=\"{n}\"/>"),
+      (Piece::Tag(n, true), Version::V1) => format!("<ex id=\"{n}\"/>"),
+      (Piece::Tag(n, true), Version::V2) => format!("<ec startRef=\"{n}\"/>"),
+      (Piece::Tag(n, false), Version::V1) => format!("<x id=\"{n}\"/>"),
+      (Piece::Tag(n, false), Version::V2) => format!("<ph id=\"{n}\"/>"),
+    };
+    out.push_str(&code);
+  }
+  out
+}
+
+/// Task `job_id`'s blocks as an XLIFF `version` document.
+fn write_xliff(app: &AppHandle, job_id: &str, version: Version) -> Result<String, ProxyError> {
+  let info = segments::info(app, job_id)?;
+  let rows = segments::rows(app, job_id)?;
+  let (source_language, target_language) = languages(&info.direction);
+  let original = escape(&info.filename);
+  let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+  match version {
+    Version::V1 => out.push_str(&format!(
+      "<xliff version=\"1.2\" xmlns=\"urn:oasis:names:tc:xliff:document:1.2\">\n  \
+       <file original=\"{original}\" source-language=\"{source_language}\" \
+       target-language=\"{target_language}\" datatype=\"plaintext\">\n    <body>\n"
+    )),
+    Version::V2 => out.push_str(&format!(
+      "<xliff version=\"2.0\" xmlns=\"urn:oasis:names:tc:xliff:document:2.0\" \
+       srcLang=\"{source_language}\" trgLang=\"{target_language}\">\n  \
+       <file id=\"f1\" original=\"{original}\">\n"
+    )),
+  }
+  for (n, row) in rows.iter().enumerate() {
+    let id = n + 1;
+    let name = row
+      .locator
+      .as_deref()
+      .map(|l| format!(" resname=\"{}\"", escape(l)))
+      .unwrap_or_default();
+    let source = codes(&row.source, version);
+    let target = row.translation.as_deref().map(|t| codes(t, version));
+    match version {
+      Version::V1 => {
+        out.push_str(&format!(
+          "      <trans-unit id=\"{id}\"{name}>\n        <source>{source}</source>\n"
+        ));
+        if let Some(target) = target {
+          let state = match row.status.as_str() {
+            "edited" => "state=\"translated\"",
+            _ => "state=\"needs-review-translation\" state-qualifier=\"mt-suggestion\"",
+          };
+          out.push_str(&format!("        <target {state}>{target}</target>\n"));
+        }
+        out.push_str("      </trans-unit>\n");
+      }
+      Version::V2 => {
+        let name = name.replace(" resname=", " name=");
+        let state = if target.is_some() {
+          "translated"
+        } else {
+          "initial"
+        };
+        out.push_str(&format!(
+          "    <unit id=\"u{id}\"{name}>\n      <segment state=\"{state}\">\n        \
+           <source>{source}</source>\n"
+        ));
+        if let Some(target) = target {
+          out.push_str(&format!("        <target>{target}</target>\n"));
+        }
+        out.push_str("      </segment>\n    </unit>\n");
+      }
+    }
+  }
+  out.push_str(match version {
+    Version::V1 => "    </body>\n  </file>\n</xliff>\n",
+    Version::V2 => "  </file>\n</xliff>\n",
+  });
+  Ok(out)
+}
+
+/// Writes task `job_id`, whatever its document type, to `path` as XLIFF
+/// `version` (`1.2` or `2.0`) for a CAT tool.
+#[tauri::command]
+pub async fn export_xliff(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+  version: String,
+) -> Result<(), ProxyError> {
+  let version = match version.as_str() {
+    "1.2" => Version::V1,
+    "2.0" => Version::V2,
+    other => {
+      return Err(ProxyError::new(
+        "invalid-request",
+        format!("Unsupported XLIFF version {other}: use 1.2 or 2.0"),
+      ))
+    }
+  };
+  tauri::async_runtime::spawn_blocking(move || {
+    let xliff = write_xliff(&app, &job_id, version)?;
+    fs::write(&path, xliff).map_err(|e| invalid(format!("Cannot write {path}: {e}")))
+  })
+  .await
+  .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
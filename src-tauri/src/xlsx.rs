@@ -0,0 +1,404 @@
+//! Excel workbooks read and rebuilt in the shell, like `docx`. Only text
+//! cells are translated: numbers, dates, booleans and formulas (including
+//! their cached text results) are left alone, and an optional cell range
+//! narrows the cells further.
+//!
+//! Shared strings used only by selected cells are translated in place, so
+//! their rich text keeps its formatting; locators are `xlsx:s#<index>`.
+//! Other selected text cells (inline strings, or shared strings also used
+//! outside the range) become inline strings of their own, keeping the
+//! cell's style; locators are `xlsx:<sheet part>!<cell>`, e.g.
+//! `xlsx:xl/worksheets/sheet2.xml!B7`.
+
+use quick_xml::{
+  events::{BytesStart, BytesText, Event},
+  Reader, Writer,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  io::Cursor,
+  path::Path,
+};
+use tauri::AppHandle;
+use zip::ZipArchive;
+
+use crate::{
+  ooxml::{self, Markup},
+  protected,
+  proxy::{invalid, ProxyError},
+  segments::{self, Segment},
+};
+
+const WORKBOOK: &str = "xl/workbook.xml";
+const SHARED_STRINGS: &str = "xl/sharedStrings.xml";
+/// A shared string item read as a paragraph of rich text runs.
+const STRING_ITEM: Markup = Markup {
+  paragraph: b"si",
+  run: b"r",
+  run_properties: b"rPr",
+  text: b"t",
+  line_break: b"br",
+  break_in_run: true,
+  tab: None,
+  preserve_space: true,
+  revisions: None,
+};
+
+/// One entry of a cell range: `Sheet1!A1:D50`, `'My sheet'!B:B`, `A1:C9`
+/// (every sheet) or a sheet name alone. Bounds are 1-based; `None` is
+/// open-ended.
+struct Area {
+  sheet: Option<String>,
+  columns: (Option<u32>, Option<u32>),
+  rows: (Option<u32>, Option<u32>),
+}
+
+/// A cell's column and row, either of which may be missing in a range
+/// (`B:B`, `3:5`).
+fn cell_ref(reference: &str) -> Option<(Option<u32>, Option<u32>)> {
+  let reference = reference.trim().replace('$', "");
+  let split = reference
+    .find(|c: char| c.is_ascii_digit())
+    .unwrap_or(reference.len());
+  let (letters, digits) = reference.split_at(split);
+  if letters.is_empty() && digits.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+    return None;
+  }
+  let column = (!letters.is_empty()).then(|| {
+    letters
+      .to_ascii_uppercase()
+      .bytes()
+      .fold(0, |n, b| n * 26 + u32::from(b - b'A' + 1))
+  });
+  let row = if digits.is_empty() {
+    None
+  } else {
+    Some(digits.parse().ok()?)
+  };
+  Some((column, row))
+}
+
+impl Area {
+  fn parse(entry: &str) -> Result<Area, String> {
+    let entry = entry.trim();
+    let (sheet, cells) = match entry.rsplit_once('!') {
+      Some((sheet, cells)) => (Some(sheet), Some(cells)),
+      // `B7`, `A1:C9`, `B:B`; letters alone name a sheet
+      None
+        if (entry.contains(':') || entry.contains(|c: char| c.is_ascii_digit()))
+          && !entry.starts_with('\'')
+          && entry.split(':').all(|r| cell_ref(r).is_some()) =>
+      {
+        (None, Some(entry))
+      }
+      None => (Some(entry), None),
+    };
+    let sheet = sheet.map(|s| s.trim().trim_matches('\'').replace("''", "'"));
+    let mut area = Area {
+      sheet,
+      columns: (None, None),
+      rows: (None, None),
+    };
+    if let Some(cells) = cells {
+      let invalid = || format!("Invalid cell range {entry}");
+      let (from, to) = cells.split_once(':').unwrap_or((cells, cells));
+      let (c0, r0) = cell_ref(from).ok_or_else(invalid)?;
+      let (c1, r1) = cell_ref(to).ok_or_else(invalid)?;
+      area.columns = (c0, c1);
+      area.rows = (r0, r1);
+    }
+    Ok(area)
+  }
+
+  fn contains(&self, sheet: &str, column: u32, row: u32) -> bool {
+    let within = |(low, high): (Option<u32>, Option<u32>), n: u32| {
+      low.is_none_or(|low| n >= low) && high.is_none_or(|high| n <= high)
+    };
+    self.sheet.as_deref().is_none_or(|s| s == sheet)
+      && within(self.columns, column)
+      && within(self.rows, row)
+  }
+}
+
+/// A text cell that is not a formula.
+struct TextCell {
+  reference: String,
+  /// Shared string index, or `None` for an inline string.
+  shared: Option<usize>,
+  inline_text: String,
+}
+
+/// The workbook's sheets as (name, part), in tab order.
+fn sheets(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Vec<(String, String)>, String> {
+  let targets: HashMap<String, String> = ooxml::relationships(archive, WORKBOOK)?
+    .into_iter()
+    .collect();
+  let xml = ooxml::read_part(archive, WORKBOOK)?;
+  let mut reader = Reader::from_reader(xml.as_slice());
+  let mut out = Vec::new();
+  loop {
+    match reader
+      .read_event()
+      .map_err(|e| format!("Invalid workbook: {e}"))?
+    {
+      Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+        if let (Some(name), Some(part)) = (
+          attribute(&e, "name"),
+          attribute(&e, "r:id").and_then(|id| targets.get(&id).cloned()),
+        ) {
+          out.push((name, part));
+        }
+      }
+      Event::Eof => return Ok(out),
+      _ => {}
+    }
+  }
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+  e.try_get_attribute(key)
+    .ok()
+    .flatten()
+    .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// The sheet's text cells, in document (row) order.
+fn text_cells(xml: &[u8]) -> Result<Vec<TextCell>, String> {
+  let err = |e: quick_xml::Error| format!("Invalid worksheet: {e}");
+  let mut reader = Reader::from_reader(xml);
+  let mut out = Vec::new();
+  // reference and type of the open cell, whether it has a formula, its value
+  let mut cell: Option<(String, String, bool, String)> = None;
+  let (mut in_value, mut in_text) = (false, false);
+  loop {
+    match reader.read_event().map_err(err)? {
+      Event::Start(e) if e.name().as_ref() == b"c" => {
+        cell = Some((
+          attribute(&e, "r").unwrap_or_default(),
+          attribute(&e, "t").unwrap_or_default(),
+          false,
+          String::new(),
+        ));
+      }
+      Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"f" => {
+        if let Some(cell) = cell.as_mut() {
+          cell.2 = true;
+        }
+      }
+      Event::Start(e) if e.name().as_ref() == b"v" => in_value = true,
+      Event::End(e) if e.name().as_ref() == b"v" => in_value = false,
+      // inline strings, skipping phonetic runs
+      Event::Start(e) if e.name().as_ref() == b"t" => in_text = true,
+      Event::End(e) if e.name().as_ref() == b"t" => in_text = false,
+      Event::Start(e) if e.name().as_ref() == b"rPh" => in_text = false,
+      Event::Text(t) if in_value || in_text => {
+        if let Some(cell) = cell.as_mut() {
+          cell.3.push_str(&t.unescape().map_err(err)?);
+        }
+      }
+      Event::End(e) if e.name().as_ref() == b"c" => {
+        let Some((reference, kind, formula, value)) = cell.take() else {
+          continue;
+        };
+        if formula {
+          continue;
+        }
+        match kind.as_str() {
+          "s" => {
+            if let Ok(index) = value.trim().parse() {
+              out.push(TextCell {
+                reference,
+                shared: Some(index),
+                inline_text: String::new(),
+              });
+            }
+          }
+          "inlineStr" => out.push(TextCell {
+            reference,
+            shared: None,
+            inline_text: value,
+          }),
+          _ => {}
+        }
+      }
+      Event::Eof => return Ok(out),
+      _ => {}
+    }
+  }
+}
+
+fn worth_translating(text: &str) -> bool {
+  text.chars().any(char::is_alphabetic)
+}
+
+/// The workbook's text cells within `cell_range` (comma- or
+/// semicolon-separated areas; empty selects every cell).
+pub fn segments(path: &Path, cell_range: Option<&str>) -> Result<Vec<Segment>, String> {
+  let areas = cell_range
+    .unwrap_or_default()
+    .split([',', ';'])
+    .filter(|a| !a.trim().is_empty())
+    .map(Area::parse)
+    .collect::<Result<Vec<_>, _>>()?;
+  let data = protected::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+  let mut archive = ooxml::open(&data)?;
+  let shared: HashMap<usize, String> = if archive.index_for_name(SHARED_STRINGS).is_some() {
+    let xml = ooxml::read_part(&mut archive, SHARED_STRINGS)?;
+    ooxml::paragraphs(&xml, &STRING_ITEM)?.into_iter().collect()
+  } else {
+    HashMap::new()
+  };
+
+  // (sheet part, cell, selected) for every text cell
+  let mut cells = Vec::new();
+  let sheets = sheets(&mut archive)?;
+  for (name, part) in &sheets {
+    let xml = ooxml::read_part(&mut archive, part)?;
+    for cell in text_cells(&xml)? {
+      let selected = match cell_ref(&cell.reference) {
+        Some((Some(column), Some(row))) => {
+          areas.is_empty() || areas.iter().any(|a| a.contains(name, column, row))
+        }
+        _ => areas.is_empty(),
+      };
+      cells.push((part.as_str(), cell, selected));
+    }
+  }
+  if let Some(missing) = areas
+    .iter()
+    .filter_map(|a| a.sheet.as_ref())
+    .find(|s| !sheets.iter().any(|(name, _)| name == *s))
+  {
+    return Err(format!("The workbook has no sheet named {missing}"));
+  }
+
+  let unselected: HashSet<usize> = cells
+    .iter()
+    .filter(|(_, _, selected)| !selected)
+    .filter_map(|(_, cell, _)| cell.shared)
+    .collect();
+  let mut emitted = HashSet::new();
+  let mut out = Vec::new();
+  for (part, cell, selected) in &cells {
+    if !selected {
+      continue;
+    }
+    match cell.shared {
+      Some(index) if !unselected.contains(&index) => {
+        let Some(text) = shared.get(&index).filter(|t| worth_translating(t)) else {
+          continue;
+        };
+        if emitted.insert(index) {
+          out.push(Segment {
+            locator: format!("xlsx:s#{index}"),
+            text: text.trim().to_string(),
+          });
+        }
+      }
+      shared_index => {
+        let text = match shared_index {
+          Some(index) => shared.get(&index).map(String::as_str).unwrap_or_default(),
+          None => cell.inline_text.as_str(),
+        };
+        if worth_translating(text) {
+          out.push(Segment {
+            locator: format!("xlsx:{part}!{}", cell.reference),
+            text: text.trim().to_string(),
+          });
+        }
+      }
+    }
+  }
+  Ok(out)
+}
+
+/// The sheet with the cells in `texts` turned into inline strings holding
+/// their translation; their style and everything else stay.
+fn replace_cells(xml: &[u8], texts: &HashMap<&str, &str>) -> Result<Vec<u8>, String> {
+  let err = |e: &dyn std::fmt::Display| format!("Invalid worksheet: {e}");
+  let mut reader = Reader::from_reader(xml);
+  let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+  // inside a replaced cell, whose old content is dropped
+  let mut replacing = false;
+  loop {
+    let event = reader.read_event().map_err(|e| err(&e))?;
+    match &event {
+      Event::Start(e) if e.name().as_ref() == b"c" => {
+        let reference = attribute(e, "r").unwrap_or_default();
+        if let Some(text) = texts.get(reference.as_str()) {
+          let mut start = BytesStart::new("c");
+          start.extend_attributes(e.attributes().flatten().filter(|a| a.key.as_ref() != b"t"));
+          start.push_attribute(("t", "inlineStr"));
+          let mut t = BytesStart::new("t");
+          t.push_attribute(("xml:space", "preserve"));
+          for event in [
+            Event::Start(start),
+            Event::Start(BytesStart::new("is")),
+            Event::Start(t),
+            Event::Text(BytesText::new(text)),
+            Event::End(BytesStart::new("t").to_end().into_owned()),
+            Event::End(BytesStart::new("is").to_end().into_owned()),
+          ] {
+            writer.write_event(event).map_err(|e| err(&e))?;
+          }
+          replacing = true;
+          continue;
+        }
+      }
+      Event::End(e) if e.name().as_ref() == b"c" => replacing = false,
+      Event::Eof => break,
+      _ if replacing => continue,
+      _ => {}
+    }
+    writer.write_event(event).map_err(|e| err(&e))?;
+  }
+  Ok(writer.into_inner())
+}
+
+pub fn export(app: &AppHandle, job_id: &str, path: &Path) -> Result<(), ProxyError> {
+  let segments::Task { source, blocks } = segments::fetch(app, job_id)?;
+  let mut shared = HashMap::new();
+  let mut cells: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+  for (locator, text) in &blocks {
+    let Some(at) = locator.strip_prefix("xlsx:") else {
+      continue;
+    };
+    if let Some(index) = at.strip_prefix("s#").and_then(|i| i.parse().ok()) {
+      shared.insert(index, text.clone());
+    } else if let Some((part, reference)) = at.rsplit_once('!') {
+      cells
+        .entry(part)
+        .or_default()
+        .insert(reference, text.as_str());
+    }
+  }
+  let mut archive = ooxml::open(&source).map_err(invalid)?;
+  let mut replaced = HashMap::new();
+  if !shared.is_empty() {
+    let xml = ooxml::read_part(&mut archive, SHARED_STRINGS).map_err(invalid)?;
+    replaced.insert(
+      SHARED_STRINGS.to_string(),
+      ooxml::replace(&xml, &STRING_ITEM, &shared).map_err(invalid)?,
+    );
+  }
+  for (part, texts) in &cells {
+    let xml = ooxml::read_part(&mut archive, part).map_err(invalid)?;
+    replaced.insert(
+      part.to_string(),
+      replace_cells(&xml, texts).map_err(invalid)?,
+    );
+  }
+  ooxml::rewrite(&source, path, &replaced).map_err(invalid)
+}
+
+/// Writes the translated copy of Excel task `job_id` to `path`.
+#[tauri::command]
+pub async fn export_translated_xlsx(
+  app: AppHandle,
+  job_id: String,
+  path: String,
+) -> Result<(), ProxyError> {
+  tauri::async_runtime::spawn_blocking(move || export(&app, &job_id, Path::new(&path)))
+    .await
+    .map_err(|e| ProxyError::new("unreachable", e.to_string()))?
+}
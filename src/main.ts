@@ -1,7 +1,9 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 let BASE = "";
 let currentTaskId: string | null = null;
+let shellFormat: string | null = null;
 let currentBlockId: string | null = null;
 
 let pollTaskTimer: number | null = null;
@@ -10,36 +12,172 @@ let pollBlocksTimer: number | null = null;
 let blocksLoaded = false;
 let blocksCache: any[] = [];
 
-async function getBaseUrl() {
-  const v = await invoke<string | null>("get_backend_base_url");
-  if (!v) throw new Error("Backend not ready.");
-  BASE = v;
+const STARTUP_STAGES: Record<string, string> = {
+  spawning: "launching backend",
+  "waiting-port-file": "waiting for backend port",
+  "waiting-health": "waiting for backend to respond"
+};
+
+// Resolves once the backend finished its startup handshake (it runs in the background).
+function waitForBackend(): Promise<void> {
+  return new Promise((resolve, reject) => {
+    const unlisten: UnlistenFn[] = [];
+    let settled = false;
+    const finish = (err?: string) => {
+      if (settled) return;
+      settled = true;
+      unlisten.forEach((f) => f());
+      if (err) reject(new Error(err));
+      else resolve();
+    };
+
+    (async () => {
+      unlisten.push(await listen<{ stage: string; elapsed_ms: number }>("backend-starting", (e) => {
+        const label = STARTUP_STAGES[e.payload.stage] || e.payload.stage;
+        setText("settingsHint", `Starting backend: ${label} (${(e.payload.elapsed_ms / 1000).toFixed(1)}s)`);
+      }));
+      unlisten.push(await listen<{ base_url: string }>("backend-ready", (e) => {
+        BASE = e.payload.base_url;
+        finish();
+      }));
+      unlisten.push(await listen<{ error: string }>("backend-failed", (e) => finish(e.payload.error)));
+
+      // startup may have completed before we subscribed
+      const url = await invoke<string | null>("get_backend_base_url");
+      if (url) {
+        BASE = url;
+        return finish();
+      }
+      const err = await invoke<string | null>("get_backend_startup_error");
+      if (err) finish(err);
+    })().catch((e) => finish(String(e?.message || e)));
+  });
+}
+
+type LockStatus = { enabled: boolean; locked: boolean; idle_minutes: number };
+
+// The shell refuses other commands while locked and hides #app itself;
+// this only runs the lock screen.
+async function watchAppLock(): Promise<void> {
+  const unlocked = new Promise<void>((resolve) => {
+    listen("app-unlocked", () => resolve());
+  });
+  $("unlock").onclick = async () => {
+    const el = $("unlockPassword") as HTMLInputElement;
+    try {
+      await invoke("unlock", { password: el.value });
+      setText("lockHint", "");
+    } catch (e: any) {
+      setText("lockHint", String(e?.message || e));
+    }
+    el.value = "";
+  };
+  $("unlockPassword").onkeydown = (e: KeyboardEvent) => {
+    if (e.key === "Enter") $("unlock").click();
+  };
+  await listen("app-locked", () => $("unlockPassword").focus());
+
+  let lastReport = 0;
+  const report = () => {
+    if (Date.now() - lastReport < 30000) return;
+    lastReport = Date.now();
+    invoke("report_activity").catch(() => {});
+  };
+  for (const type of ["keydown", "mousedown", "mousemove", "wheel"]) {
+    window.addEventListener(type, report, { passive: true });
+  }
+
+  const s = await invoke<LockStatus>("get_lock_status");
+  ($("lockIdleMinutes") as HTMLInputElement).value = String(s.idle_minutes);
+  if (s.locked) {
+    $("unlockPassword").focus();
+    await unlocked;
+  }
+}
+
+type ProxyResponse = { status: number; headers: Record<string, string>; body: number[] };
+type ProxyError = { kind: string; status: number | null; message: string };
+
+// All backend calls go through the shell's proxy_request command.
+async function api(
+  method: string,
+  path: string,
+  opts: {
+    headers?: Record<string, string>;
+    body?: Uint8Array;
+    timeoutMs?: number;
+    balance?: boolean;
+    provider?: string | null;
+  } = {}
+): Promise<ProxyResponse> {
+  try {
+    return await invoke<ProxyResponse>("proxy_request", {
+      request: {
+        method,
+        path,
+        headers: opts.headers ?? {},
+        body: opts.body ? Array.from(opts.body) : null,
+        timeout_ms: opts.timeoutMs ?? null,
+        balance: opts.balance ?? false,
+        provider: opts.provider ?? null
+      }
+    });
+  } catch (e: any) {
+    const err = e as ProxyError;
+    throw new Error(err?.message ? (err.status ? `${err.status}: ${err.message}` : err.message) : String(e));
+  }
+}
+
+// Host of the provider in the Base URL field, for the shell's rate limits.
+function providerHost(): string | null {
+  try {
+    return new URL($("baseUrl").value.trim()).hostname.toLowerCase() || null;
+  } catch {
+    return null;
+  }
+}
+
+// Stands in for a key kept in the OS keychain; the real key never reaches
+// the webview, the shell adds it to provider-tagged requests.
+const STORED_KEY = "(stored in keychain)";
+
+/** Moves a freshly typed API key into the keychain, or checks one is there. */
+async function storeApiKey() {
+  const key = $("apiKey").value.trim();
+  const provider = providerHost();
+  if (!provider) throw new Error("Base URL is invalid.");
+  if (key && key !== STORED_KEY) {
+    await invoke("set_api_key", { provider, key });
+    $("apiKey").value = STORED_KEY;
+  } else if (!(await invoke<{ stored: boolean }>("get_api_key_status", { provider })).stored) {
+    throw new Error("API Key is required.");
+  }
+}
+
+async function showApiKeyStatus() {
+  const provider = providerHost();
+  const stored = provider && (await invoke<{ stored: boolean }>("get_api_key_status", { provider })).stored;
+  $("apiKey").value = stored ? STORED_KEY : "";
+}
+
+function jsonBody(body: any) {
+  return { headers: { "content-type": "application/json" }, body: new TextEncoder().encode(JSON.stringify(body)) };
+}
+
+function parseJson(r: ProxyResponse) {
+  return JSON.parse(new TextDecoder().decode(new Uint8Array(r.body)));
 }
 
 async function apiGet(path: string) {
-  const r = await fetch(`${BASE}${path}`);
-  if (!r.ok) throw new Error(await r.text());
-  return r.json();
+  return parseJson(await api("GET", path));
 }
 
 async function apiPostJson(path: string, body: any) {
-  const r = await fetch(`${BASE}${path}`, {
-    method: "POST",
-    headers: { "content-type": "application/json" },
-    body: JSON.stringify(body)
-  });
-  if (!r.ok) throw new Error(await r.text());
-  return r.json();
+  return parseJson(await api("POST", path, jsonBody(body)));
 }
 
 async function apiPatchJson(path: string, body: any) {
-  const r = await fetch(`${BASE}${path}`, {
-    method: "PATCH",
-    headers: { "content-type": "application/json" },
-    body: JSON.stringify(body)
-  });
-  if (!r.ok) throw new Error(await r.text());
-  return r.json();
+  return parseJson(await api("PATCH", path, jsonBody(body)));
 }
 
 function $(id: string) {
@@ -62,33 +200,50 @@ function setProgress(p: number, status: string) {
   if (st) st.textContent = status ? `Status: ${status}` : "";
 }
 
+// Progress comes from the shell's job-events bridge while it is connected;
+// timers are the fallback.
+let jobEventsConnected = false;
+let watchingTask = false;
+let lastBlocksDone = -1;
+
 function stopPolling() {
   if (pollTaskTimer) window.clearInterval(pollTaskTimer);
   if (pollBlocksTimer) window.clearInterval(pollBlocksTimer);
   pollTaskTimer = null;
   pollBlocksTimer = null;
+  watchingTask = false;
+}
+
+// Shows a task update; returns true once the task has ended.
+function applyTaskUpdate(t: any): boolean {
+  setProgress(t.progress ?? 0, t.status ?? "");
+  if (t.error) setText("progressHint", `Error: ${t.error}`);
+
+  if (t.status === "finished") {
+    // keep one more blocks refresh to show final state
+    setTimeout(() => refreshBlocks(false).catch(() => {}), 300);
+    return true;
+  }
+  return t.status === "error" || t.status === "cancelled";
 }
 
 function startPolling() {
   stopPolling();
+  watchingTask = true;
+
+  if (jobEventsConnected && currentTaskId) {
+    lastBlocksDone = -1;
+    invoke("send_job_message", { message: { type: "subscribe", task_id: currentTaskId } }).catch(() => {});
+    return;
+  }
 
   // Poll task every 1s (progress/status)
   pollTaskTimer = window.setInterval(async () => {
     try {
       if (!currentTaskId) return;
       const t = await apiGet(`/api/tasks/${currentTaskId}`);
-      setProgress(t.progress ?? 0, t.status ?? "");
-      if (t.error) setText("progressHint", `Error: ${t.error}`);
-
       // auto stop when finished/error
-      if (t.status === "finished") {
-        // keep one more blocks refresh to show final state
-        setTimeout(() => refreshBlocks(false).catch(() => {}), 300);
-        stopPolling();
-      }
-      if (t.status === "error") {
-        stopPolling();
-      }
+      if (applyTaskUpdate(t)) stopPolling();
     } catch (e: any) {
       // ignore transient errors
     }
@@ -106,6 +261,36 @@ function startPolling() {
   }, 2500);
 }
 
+async function listenJobEvents() {
+  await listen<any>("job-event", (e) => {
+    const m = e.payload;
+    if (m?.type !== "task" || m.task_id !== currentTaskId || !watchingTask) return;
+    if (blocksLoaded && m.blocks_done !== lastBlocksDone) refreshBlocks(true).catch(() => {});
+    lastBlocksDone = m.blocks_done;
+    if (applyTaskUpdate(m)) {
+      stopPolling();
+      invoke("send_job_message", { message: { type: "unsubscribe", task_id: m.task_id } }).catch(() => {});
+    }
+  });
+  // a block's translation as it streams in, until the next blocks refresh shows it saved
+  await listen<{ job_id: string | null; task_id: string; segment_id: string; text: string }>("segment-partial", (e) => {
+    const p = e.payload;
+    if (p.task_id !== currentTaskId) return;
+    const item = ($("blockList") as HTMLElement).querySelector<HTMLElement>(`[data-block-id="${CSS.escape(p.segment_id)}"]`);
+    const b = blocksCache.find((x: any) => x.id === p.segment_id);
+    if (item && b) item.textContent = `#${b.order_no} [translating] ${p.text.replace(/\s+/g, " ").slice(-60)}`;
+    if (p.segment_id === currentBlockId && (document.activeElement as any)?.id !== "dstText") {
+      $("dstText").value = p.text;
+    }
+  });
+  await listen<{ connected: boolean }>("job-events-connection", (e) => {
+    jobEventsConnected = e.payload.connected;
+    // switch between pushed updates and timers mid-task
+    if (watchingTask) startPolling();
+  });
+  jobEventsConnected = await invoke<boolean>("get_job_events_connected");
+}
+
 async function refreshBlocks(keepSelection: boolean) {
   if (!currentTaskId) return;
 
@@ -124,6 +309,7 @@ async function refreshBlocks(keepSelection: boolean) {
   for (const b of blocks) {
     const div = document.createElement("div");
     div.className = "item";
+    div.dataset.blockId = b.id;
     const src = (b.source_text || "").replace(/\s+/g, " ").slice(0, 60);
     div.textContent = `#${b.order_no} [${b.status}] ${src}`;
 
@@ -151,17 +337,130 @@ async function refreshBlocks(keepSelection: boolean) {
   }
 }
 
+async function showBackendFailure(msg: string) {
+  let tail = "";
+  try {
+    const lines = await invoke<string[]>("get_backend_logs", { lines: 20 });
+    if (lines.length) tail = `\n\nRecent backend output:\n${lines.join("\n")}`;
+  } catch {
+    // logs are best-effort
+  }
+  setText("settingsHint", msg + tail);
+}
+
+type BackendStatus =
+  | { state: "connected"; base_url: string }
+  | { state: "reconnecting"; attempt: number; exit_code: number | null }
+  | { state: "failed"; attempt: number; error: string }
+  | { state: "healthy"; latency_ms: number }
+  | { state: "degraded"; latency_ms: number | null; failures: number }
+  | { state: "down"; failures: number };
+
+type BackendLifecycle =
+  | { state: "not_started" }
+  | { state: "launching" }
+  | { state: "waiting_health" }
+  | { state: "ready"; base_url: string }
+  | { state: "degraded"; base_url: string }
+  | { state: "crashed"; code: number | null };
+
+function showBackendState(s: BackendLifecycle) {
+  const label: Record<BackendLifecycle["state"], string> = {
+    not_started: "not started",
+    launching: "launching",
+    waiting_health: "waiting for health check",
+    ready: "ready",
+    degraded: "degraded",
+    crashed: "crashed"
+  };
+  const extra = s.state === "crashed" && s.code !== null ? ` (exit code ${s.code})` : "";
+  setText("backendState", `Backend state: ${label[s.state]}${extra}`);
+}
+
+async function watchBackend() {
+  await listen<BackendLifecycle>("backend-state", (e) => showBackendState(e.payload));
+  showBackendState(await invoke<BackendLifecycle>("get_backend_status"));
+  await listen<{ backend_version: string | null; required: string; reason: string }>("backend-incompatible", (e) => {
+    const v = e.payload;
+    setText("settingsHint",
+      `Incompatible backend ${v.backend_version ?? "(unknown version)"}; this app requires ${v.required}.\n` +
+      `${v.reason}\nPlease reinstall the application so the backend matches.`);
+  });
+  await listen<{ path: string; expected: string; actual: string | null }>("backend-integrity-failed", (e) => {
+    const v = e.payload;
+    setText("settingsHint",
+      `The backend binary failed its integrity check and was not started.\n${v.path}\n` +
+      `expected sha256 ${v.expected}, found ${v.actual ?? "(unreadable)"}\n` +
+      `Please reinstall the application.`);
+  });
+  await listen<{ path: string; cause: string; detail: string; remediation: string }>("backend-blocked", (e) => {
+    const b = e.payload;
+    setText("settingsHint",
+      `The backend was blocked by security software (${b.cause}).\n${b.path}\n${b.detail}\n${b.remediation}`);
+  });
+  await listen<{ stage: string; downloaded: number; total: number | null }>("backend-update", (e) => {
+    const u = e.payload;
+    const pct = u.total ? ` ${Math.floor((u.downloaded / u.total) * 100)}%` : "";
+    setText("settingsHint", `Updating backend: ${u.stage}${pct}`);
+  });
+  await listen<BackendStatus>("backend-status", (e) => {
+    const s = e.payload;
+    if (s.state === "connected") {
+      BASE = s.base_url;
+      setText("settingsHint", `Backend reconnected: ${BASE}`);
+    } else if (s.state === "reconnecting") {
+      setText("settingsHint", `Backend stopped (exit code ${s.exit_code ?? "?"}), reconnecting... (attempt ${s.attempt})`);
+    } else if (s.state === "failed") {
+      showBackendFailure(`Backend restart failed (attempt ${s.attempt}): ${s.error}`);
+    } else if (s.state === "healthy") {
+      setText("backendHealth", `Backend: healthy (${s.latency_ms} ms)`);
+    } else if (s.state === "degraded") {
+      setText("backendHealth", s.latency_ms === null
+        ? `Backend: not responding (${s.failures} failed checks)`
+        : `Backend: slow (${s.latency_ms} ms)`);
+    } else {
+      setText("backendHealth", "Backend: down");
+      showBackendFailure(`Backend stopped responding after ${s.failures} health checks.`);
+    }
+  });
+}
+
+type BackendMetrics = { pid: number; cpu_percent: number; rss_bytes: number; uptime_secs: number };
+
+function startMetricsPolling() {
+  window.setInterval(async () => {
+    try {
+      const m = await invoke<BackendMetrics | null>("get_backend_metrics");
+      setText("backendMetrics", m
+        ? `CPU ${m.cpu_percent.toFixed(0)}% | RAM ${(m.rss_bytes / 1048576).toFixed(0)} MB | up ${Math.floor(m.uptime_secs / 60)} min`
+        : "");
+    } catch {
+      // metrics are informational only
+    }
+  }, 5000);
+}
+
 async function main() {
-  await getBaseUrl();
+  await watchAppLock();
+  await watchBackend();
+  await listenJobEvents();
+  setText("settingsHint", "Starting backend...");
+  await waitForBackend();
   setText("settingsHint", `Backend connected: ${BASE}`);
+  startMetricsPolling();
+  const startup: any = await invoke("get_startup_config");
+  ($("lowPriority") as HTMLInputElement).checked = !!startup.low_priority;
+  ($("backendArgs") as HTMLInputElement).value = (await invoke<string[]>("get_backend_args")).join(" ");
   setProgress(0, "");
 $("loadModels").onclick = async () => {
   try {
     const base_url = $("baseUrl").value.trim();
-    const api_key = $("apiKey").value.trim();
-    if (!base_url || !api_key) throw new Error("Base URL and API Key are required.");
+    if (!base_url) throw new Error("Base URL is required.");
+    await storeApiKey();
 
-    const out = await apiPostJson("/api/models", { base_url, api_key });
+    const out = parseJson(
+      await api("POST", "/api/models", { ...jsonBody({ base_url, api_key: "" }), provider: providerHost() })
+    );
     const models: string[] = out.models || [];
 
     const sel = $("model") as HTMLSelectElement;
@@ -186,23 +485,1078 @@ $("loadModels").onclick = async () => {
   $("saveSettings").onclick = async () => {
     try {
       const base_url = $("baseUrl").value.trim();
-      const api_key = $("apiKey").value.trim();
       const model = $("model").value.trim();
 
-      if (!base_url || !api_key || !model) {
+      if (!base_url || !model) {
         throw new Error("base_url / api_key / model are required.");
       }
-      const out = await apiPostJson("/api/settings", { base_url, api_key, model });
+      await storeApiKey();
+      const out = parseJson(
+        await api("POST", "/api/settings", { ...jsonBody({ base_url, api_key: "", model }), provider: providerHost() })
+      );
       setText("settingsHint", JSON.stringify(out, null, 2));
     } catch (e: any) {
       setText("settingsHint", String(e?.message || e));
     }
   };
 
+  $("baseUrl").addEventListener("change", showApiKeyStatus);
+  await showApiKeyStatus();
+
+  $("deleteApiKey").onclick = async () => {
+    try {
+      const provider = providerHost();
+      if (!provider) throw new Error("Base URL is invalid.");
+      await invoke("delete_api_key", { provider });
+      $("apiKey").value = "";
+      setText("settingsHint", `API key for ${provider} removed; restart the backend so running translations drop it too.`);
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  $("restartBackend").onclick = async () => {
+    try {
+      setText("settingsHint", "Restarting backend...");
+      BASE = await invoke<string>("restart_backend");
+      setText("settingsHint", `Backend restarted: ${BASE}`);
+    } catch (e: any) {
+      showBackendFailure(`Backend restart failed: ${String(e?.message || e)}`);
+    }
+  };
+
+  $("saveBackendArgs").onclick = async () => {
+    try {
+      const raw = ($("backendArgs") as HTMLInputElement).value.trim();
+      const args = await invoke<string[]>("set_backend_args", { args: raw ? raw.split(/\s+/) : [] });
+      ($("backendArgs") as HTMLInputElement).value = args.join(" ");
+      setText("settingsHint", "Backend flags saved; they apply after a backend restart.");
+    } catch (e: any) {
+      setText("settingsHint", `Invalid backend flags: ${String(e?.message || e)}`);
+    }
+  };
+
+  type ProxyConfig = { mode: string; url: string; bypass: string[]; username?: string; password?: string };
+  const showProxy = (p: ProxyConfig) => {
+    $("proxyMode").value = p.mode;
+    $("proxyUrl").value = p.url;
+    $("proxyBypass").value = p.bypass.join(", ");
+    $("proxyUser").value = p.username ?? "";
+    $("proxyPassword").value = p.password ?? "";
+  };
+  showProxy(await invoke<ProxyConfig>("get_proxy_config"));
+
+  $("saveProxy").onclick = async () => {
+    try {
+      const config: ProxyConfig = {
+        mode: $("proxyMode").value,
+        url: $("proxyUrl").value.trim(),
+        bypass: $("proxyBypass").value.split(",").map((s: string) => s.trim()).filter(Boolean),
+        username: $("proxyUser").value.trim(),
+        password: $("proxyPassword").value
+      };
+      await invoke<ProxyConfig>("set_proxy_config", { config });
+      // credentials go to the OS credential store, not the config file
+      showProxy(await invoke<ProxyConfig>("set_proxy_credentials", {
+        username: config.username,
+        password: config.password
+      }));
+      setText("settingsHint", "Proxy saved; restart the backend to apply it to translations.");
+    } catch (e: any) {
+      setText("settingsHint", `Invalid proxy settings: ${String(e?.message || e)}`);
+    }
+  };
+
+  type ProviderLimit = { requests_per_minute: number; burst: number };
+  type RateLimits = { max_concurrent: number; providers: Record<string, ProviderLimit> };
+  let rateLimits = await invoke<RateLimits>("get_rate_limits");
+  const showRateLimits = () => {
+    const limit = rateLimits.providers[providerHost() ?? ""];
+    $("maxConcurrent").value = rateLimits.max_concurrent ? String(rateLimits.max_concurrent) : "";
+    $("rateRpm").value = limit ? String(limit.requests_per_minute) : "";
+    $("rateBurst").value = limit ? String(limit.burst) : "";
+  };
+  showRateLimits();
+  $("baseUrl").addEventListener("change", showRateLimits);
+
+  $("saveRateLimits").onclick = async () => {
+    try {
+      const host = providerHost();
+      const rpm = Number($("rateRpm").value || 0);
+      const providers = { ...rateLimits.providers };
+      if (host) {
+        if (rpm > 0) providers[host] = { requests_per_minute: rpm, burst: Number($("rateBurst").value || 1) };
+        else delete providers[host];
+      }
+      const limits: RateLimits = { max_concurrent: Number($("maxConcurrent").value || 0), providers };
+      rateLimits = await invoke<RateLimits>("set_rate_limits", { limits });
+      showRateLimits();
+      setText("settingsHint", "Rate limits saved; restart the backend to apply them to batch translations.");
+    } catch (e: any) {
+      setText("settingsHint", `Invalid rate limits: ${String(e?.message || e)}`);
+    }
+  };
+
+  type ProviderConfig = {
+    id: string;
+    kind: string;
+    name: string;
+    endpoint: string;
+    key_ref: string;
+    models: string[];
+    region: string;
+    rate_limit: ProviderLimit | null;
+  };
+  type Validation = {
+    ok: boolean;
+    message: string;
+    latency_ms: number | null;
+    models: string[];
+    languages: string[];
+  };
+  let providerConfigs: ProviderConfig[] = [];
+  const selectedProvider = () => providerConfigs.find((p) => p.id === $("providerList").value);
+  const showProvider = (p?: ProviderConfig) => {
+    $("providerKind").value = p?.kind ?? "openai";
+    $("providerName").value = p?.name ?? "";
+    $("providerEndpoint").value = p?.endpoint ?? "";
+    $("providerKeyRef").value = p?.key_ref ?? "";
+    $("providerKey").value = "";
+    $("providerModels").value = (p?.models ?? []).join(", ");
+    $("providerRegion").value = p?.region ?? "";
+    $("providerRpm").value = p?.rate_limit ? String(p.rate_limit.requests_per_minute) : "";
+  };
+  const refreshProviders = async (select?: string) => {
+    providerConfigs = await invoke<ProviderConfig[]>("list_providers");
+    $("providerList").innerHTML = "";
+    const none = document.createElement("option");
+    none.value = "";
+    none.textContent = "(new provider)";
+    $("providerList").appendChild(none);
+    for (const p of providerConfigs) {
+      const opt = document.createElement("option");
+      opt.value = p.id;
+      opt.textContent = `${p.name} (${p.kind})`;
+      $("providerList").appendChild(opt);
+    }
+    $("providerList").value = select ?? "";
+    showProvider(selectedProvider());
+  };
+  $("providerList").onchange = () => showProvider(selectedProvider());
+  await refreshProviders();
+
+  $("saveProvider").onclick = async () => {
+    try {
+      const rpm = Number($("providerRpm").value || 0);
+      const saved = await invoke<ProviderConfig>("save_provider", {
+        provider: {
+          id: selectedProvider()?.id ?? "",
+          kind: $("providerKind").value,
+          name: $("providerName").value,
+          endpoint: $("providerEndpoint").value,
+          key_ref: $("providerKeyRef").value,
+          models: $("providerModels").value.split(",").map((s: string) => s.trim()).filter(Boolean),
+          region: $("providerRegion").value,
+          rate_limit: rpm > 0 ? { requests_per_minute: rpm, burst: selectedProvider()?.rate_limit?.burst ?? 1 } : null
+        }
+      });
+      const key = $("providerKey").value.trim();
+      if (key) {
+        await invoke("set_api_key", { provider: saved.key_ref || new URL(saved.endpoint).hostname, key });
+      }
+      rateLimits = await invoke<RateLimits>("get_rate_limits");
+      showRateLimits();
+      await refreshProviders(saved.id);
+      setText("settingsHint", `Provider ${saved.name} saved.`);
+    } catch (e: any) {
+      setText("settingsHint", `Could not save the provider: ${String(e?.message || e)}`);
+    }
+  };
+  $("deleteProvider").onclick = async () => {
+    const p = selectedProvider();
+    if (!p) return;
+    try {
+      await invoke("delete_provider", { id: p.id });
+      await refreshProviders();
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+  $("validateProvider").onclick = async () => {
+    const p = selectedProvider();
+    if (!p) return setText("settingsHint", "Save the provider first.");
+    setText("settingsHint", `Checking ${p.name}...`);
+    try {
+      const v = await invoke<Validation>("validate_provider", { profileId: p.id });
+      const found = [
+        v.models.length ? `${v.models.length} models` : "",
+        v.languages.length ? `languages: ${v.languages.join(", ")}` : ""
+      ].filter(Boolean).join("; ");
+      setText("settingsHint", v.ok
+        ? `${p.name} works (${v.latency_ms} ms)${found ? `: ${found}` : ""}.`
+        : `${p.name} failed: ${v.message}`);
+      await refreshProviders(p.id);
+    } catch (e: any) {
+      setText("settingsHint", `Check failed: ${String(e?.message || e)}`);
+    }
+  };
+
+  // models of the selected Ollama server, pulled onto it from here
+  type OllamaModel = { name: string; size: number; parameters: string | null; quantization: string | null };
+  const showOllamaModels = (models: OllamaModel[]) =>
+    setText("settingsHint", models.length
+      ? models.map((m) => `${m.name} (${(m.size / 2 ** 30).toFixed(1)} GB`
+        + [m.parameters, m.quantization].filter(Boolean).map((s) => `, ${s}`).join("") + ")").join("\n")
+      : "The server has no models yet.");
+  $("listOllamaModels").onclick = async () => {
+    const p = selectedProvider();
+    if (p?.kind !== "ollama") return setText("settingsHint", "Select a saved Ollama provider first.");
+    try {
+      showOllamaModels(await invoke<OllamaModel[]>("list_ollama_models", { providerId: p.id }));
+      await refreshProviders(p.id);
+    } catch (e: any) {
+      setText("settingsHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("pullOllamaModel").onclick = async () => {
+    const p = selectedProvider();
+    if (p?.kind !== "ollama") return setText("settingsHint", "Select a saved Ollama provider first.");
+    const model = $("pullModel").value.trim();
+    if (!model) return setText("settingsHint", "Enter the model to pull.");
+    const unlisten = await listen<{ provider_id: string; model: string; status: string; completed: number | null; total: number | null }>(
+      "ollama-pull-progress",
+      (e) => {
+        const s = e.payload;
+        if (s.provider_id !== p.id || s.model !== model) return;
+        const pct = s.total ? ` ${Math.floor(((s.completed ?? 0) / s.total) * 100)}%` : "";
+        setText("settingsHint", `Pulling ${model}: ${s.status}${pct}`);
+      }
+    );
+    try {
+      showOllamaModels(await invoke<OllamaModel[]>("pull_ollama_model", { providerId: p.id, model }));
+      await refreshProviders(p.id);
+    } catch (e: any) {
+      setText("settingsHint", `Could not pull ${model}: ${(e as ProxyError)?.message ?? String(e)}`);
+    } finally {
+      unlisten();
+    }
+  };
+
+  type ClientCert = { path: string; key_path: string; password: string };
+  const showClientCert = (c: ClientCert) => {
+    $("clientCertPath").value = c.path;
+    $("clientKeyPath").value = c.key_path;
+    $("clientCertPassword").value = c.password;
+  };
+  showClientCert(await invoke<ClientCert>("get_client_certificate"));
+
+  $("saveClientCert").onclick = async () => {
+    try {
+      const config: ClientCert = {
+        path: $("clientCertPath").value.trim(),
+        key_path: $("clientKeyPath").value.trim(),
+        password: $("clientCertPassword").value
+      };
+      showClientCert(await invoke<ClientCert>("set_client_certificate", { config }));
+      setText("settingsHint", config.path ? "Client certificate saved." : "Client certificate removed.");
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  type DnsConfig = { doh_url: string; hosts: Record<string, string[]> };
+  const showDns = (c: DnsConfig) => {
+    $("dohUrl").value = c.doh_url;
+    $("dnsHosts").value = Object.entries(c.hosts)
+      .map(([host, addrs]) => `${host} ${addrs.join(" ")}`)
+      .join("\n");
+  };
+  showDns(await invoke<DnsConfig>("get_dns_config"));
+
+  $("saveDns").onclick = async () => {
+    try {
+      const hosts: Record<string, string[]> = {};
+      for (const line of $("dnsHosts").value.split("\n")) {
+        const [host, ...addrs] = line.trim().split(/\s+/);
+        if (host) hosts[host] = addrs;
+      }
+      const config: DnsConfig = { doh_url: $("dohUrl").value.trim(), hosts };
+      showDns(await invoke<DnsConfig>("set_dns_config", { config }));
+      setText("settingsHint", "DNS settings saved; restart the backend to apply them to translations.");
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  type Profile = {
+    id: string;
+    name: string;
+    base_url: string;
+    model: string;
+    direction: string;
+    glossary: { source: string; target: string }[];
+    prompt_template: string;
+  };
+  let profiles: Profile[] = [];
+  const selectedProfile = () => profiles.find((p) => p.id === $("profileList").value);
+  const showProfile = (p?: Profile) => {
+    $("profileName").value = p?.name ?? "";
+    $("profileGlossary").value = (p?.glossary ?? []).map((g) => `${g.source} = ${g.target}`).join("\n");
+    $("profilePrompt").value = p?.prompt_template ?? "";
+  };
+  const refreshProfiles = async (select?: string) => {
+    const out = await invoke<{ active: string | null; profiles: Profile[] }>("list_profiles");
+    profiles = out.profiles;
+    $("profileList").innerHTML = "";
+    const none = document.createElement("option");
+    none.value = "";
+    none.textContent = "(new profile)";
+    $("profileList").appendChild(none);
+    for (const p of profiles) {
+      const opt = document.createElement("option");
+      opt.value = p.id;
+      opt.textContent = p.id === out.active ? `${p.name} (active)` : p.name;
+      $("profileList").appendChild(opt);
+    }
+    $("profileList").value = select ?? out.active ?? "";
+    showProfile(selectedProfile());
+  };
+  $("profileList").onchange = () => showProfile(selectedProfile());
+  const profileAction = (f: () => Promise<string | undefined>) => async () => {
+    try {
+      await refreshProfiles(await f());
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  $("saveProfile").onclick = profileAction(async () => {
+    const glossary = $("profileGlossary").value
+      .split("\n")
+      .map((line: string) => line.split("="))
+      .filter((parts: string[]) => parts[0].trim())
+      .map(([source, ...target]: string[]) => ({ source: source.trim(), target: target.join("=").trim() }));
+    const profile = {
+      name: $("profileName").value.trim(),
+      base_url: $("baseUrl").value.trim(),
+      model: $("model").value.trim(),
+      direction: $("direction").value,
+      glossary,
+      prompt_template: $("profilePrompt").value
+    };
+    const current = selectedProfile();
+    const saved = current
+      ? await invoke<Profile>("update_profile", { id: current.id, profile })
+      : await invoke<Profile>("create_profile", { profile });
+    return saved.id;
+  });
+  $("cloneProfile").onclick = profileAction(async () => {
+    const current = selectedProfile();
+    if (!current) throw new Error("Select a profile to clone.");
+    return (await invoke<Profile>("clone_profile", { id: current.id, name: null })).id;
+  });
+  $("deleteProfile").onclick = profileAction(async () => {
+    const current = selectedProfile();
+    if (!current) throw new Error("Select a profile to delete.");
+    await invoke("delete_profile", { id: current.id });
+    return "";
+  });
+  $("activateProfile").onclick = profileAction(async () => {
+    const current = selectedProfile();
+    await invoke("activate_profile", { id: current?.id ?? null });
+    return current?.id;
+  });
+  await listen<Profile | null>("active-profile-changed", async (e) => {
+    if (e.payload?.base_url) {
+      $("baseUrl").value = e.payload.base_url;
+      await showApiKeyStatus();
+    }
+    setText("settingsHint", e.payload ? `Profile ${e.payload.name} is active.` : "No profile is active.");
+  });
+  await refreshProfiles().catch(() => {});
+
+  $("exportBundle").onclick = async () => {
+    try {
+      await invoke("export_settings_bundle", { path: $("bundlePath").value.trim() });
+      setText("settingsHint", "Settings bundle saved (API keys and passwords are not included).");
+    } catch (e: any) {
+      setText("settingsHint", `Could not export: ${String(e?.message || e)}`);
+    }
+  };
+  $("importBundle").onclick = profileAction(async () => {
+    const out = await invoke<{ profiles_created: number; profiles_updated: number }>(
+      "import_settings_bundle", { path: $("bundlePath").value.trim() });
+    setText("settingsHint", `Imported: ${out.profiles_created} new and ${out.profiles_updated} updated profiles.`);
+    return undefined;
+  });
+
+  const refreshCaList = async () => {
+    const certs = await invoke<{ id: string; pem: string }[]>("list_ca_certificates");
+    $("caList").innerHTML = "";
+    for (const c of certs) {
+      const opt = document.createElement("option");
+      opt.value = c.id;
+      opt.textContent = `sha256 ${c.id.slice(0, 16)}…`;
+      $("caList").appendChild(opt);
+    }
+  };
+  await refreshCaList();
+
+  $("addCa").onclick = async () => {
+    try {
+      const ids = await invoke<string[]>("add_ca_certificate", { pem: $("caPem").value });
+      $("caPem").value = "";
+      await refreshCaList();
+      setText("settingsHint", `Added ${ids.length} certificate(s); restart the backend to apply them to translations.`);
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  $("removeCa").onclick = async () => {
+    try {
+      const id = $("caList").value;
+      if (!id) return;
+      await invoke("remove_ca_certificate", { id });
+      await refreshCaList();
+      setText("settingsHint", "Certificate removed.");
+    } catch (e: any) {
+      setText("settingsHint", String(e?.message || e));
+    }
+  };
+
+  $("diagnostics").onclick = async () => {
+    try {
+      const path = await invoke<string>("create_diagnostics_bundle");
+      setText("settingsHint", `Diagnostics bundle written to ${path}`);
+    } catch (e: any) {
+      setText("settingsHint", `Could not create diagnostics bundle: ${String(e?.message || e)}`);
+    }
+  };
+
+  type Price = { input_per_million_tokens: number; output_per_million_tokens: number };
+  type UsageConfig = { monthly_budget: number; currency: string; prices: Record<string, Price> };
+  type UsageReport = {
+    period: string;
+    rows: { provider: string; model: string; requests: number; prompt_tokens: number; completion_tokens: number;
+      tokens_estimated: boolean; cost: number | null }[];
+    total_cost: number;
+    currency: string;
+    monthly_budget: number;
+  };
+  let usageConfig = await invoke<UsageConfig>("get_usage_config");
+  const showUsageConfig = () => {
+    const p = usageConfig.prices[providerHost() ?? ""];
+    $("monthlyBudget").value = usageConfig.monthly_budget ? String(usageConfig.monthly_budget) : "";
+    $("currency").value = usageConfig.currency;
+    $("priceIn").value = p ? String(p.input_per_million_tokens) : "";
+    $("priceOut").value = p ? String(p.output_per_million_tokens) : "";
+  };
+  showUsageConfig();
+  $("baseUrl").addEventListener("change", showUsageConfig);
+
+  $("saveUsage").onclick = async () => {
+    try {
+      const host = providerHost();
+      const prices = { ...usageConfig.prices };
+      if (host) {
+        if ($("priceIn").value || $("priceOut").value) {
+          prices[host] = {
+            input_per_million_tokens: Number($("priceIn").value || 0),
+            output_per_million_tokens: Number($("priceOut").value || 0)
+          };
+        } else delete prices[host];
+      }
+      const config: UsageConfig = {
+        monthly_budget: Number($("monthlyBudget").value || 0),
+        currency: $("currency").value.trim(),
+        prices
+      };
+      usageConfig = await invoke<UsageConfig>("set_usage_config", { config });
+      showUsageConfig();
+      setText("settingsHint", "Usage prices and budget saved.");
+    } catch (e: any) {
+      setText("settingsHint", `Invalid usage settings: ${String(e?.message || e)}`);
+    }
+  };
+
+  $("usageReport").onclick = async () => {
+    try {
+      const period = $("usagePeriod").value;
+      const taskId = period === "task" ? currentTaskId : null;
+      if (period === "task" && !taskId) throw new Error("Please create a task first.");
+      const r = await invoke<UsageReport>("get_usage_report", { period: taskId ? "all" : period, taskId });
+      const lines = r.rows.map((row) =>
+        `${row.provider} ${row.model}: ${row.requests} calls, ${row.prompt_tokens} in / ${row.completion_tokens} out` +
+        `${row.tokens_estimated ? " tokens (estimated)" : " tokens"}` +
+        (row.cost === null ? ", no price set" : `, ${row.cost.toFixed(4)} ${r.currency}`));
+      const budget = r.monthly_budget ? ` (monthly budget ${r.monthly_budget} ${r.currency})` : "";
+      setText("usageStats", [...lines, `Total: ${r.total_cost.toFixed(4)} ${r.currency}${budget}`].join("\n"));
+    } catch (e: any) {
+      setText("usageStats", String(e?.message || e));
+    }
+  };
+
+  await listen<{ provider: string; state: string; failures: number; retry_at: number | null }>("provider-status", (e) => {
+    const p = e.payload;
+    if (p.state === "open") {
+      const at = p.retry_at ? new Date(p.retry_at * 1000).toLocaleTimeString() : "later";
+      setText("settingsHint", `${p.provider} is failing (${p.failures} errors in a row); pausing calls until ${at}.`);
+    } else if (p.state === "closed" && p.failures === 0) {
+      setText("settingsHint", `${p.provider} is answering again.`);
+    }
+  });
+
+  await listen<{ month_cost: number; budget: number; currency: string }>("usage-budget-exceeded", (e) => {
+    const u = e.payload;
+    setText("settingsHint",
+      `Monthly budget exceeded: ${u.month_cost.toFixed(2)} of ${u.budget.toFixed(2)} ${u.currency} spent this month.`);
+  });
+
+  type DownloadProgress = {
+    id: string;
+    state: string;
+    downloaded: number;
+    total: number | null;
+    path: string | null;
+    error: string | null;
+  };
+  const ASSET_ID = "asset";
+  await listen<DownloadProgress>("download-progress", (e) => {
+    const d = e.payload;
+    if (d.id !== ASSET_ID) return;
+    const mb = (d.downloaded / 1024 / 1024).toFixed(1);
+    const pct = d.total ? ` (${Math.floor((d.downloaded / d.total) * 100)}%)` : "";
+    const tail = d.state === "done" ? `: ${d.path}` : d.error ? `: ${d.error}` : "";
+    setText("assetProgress", `Download ${d.state}, ${mb} MB${pct}${tail}`);
+    if (d.state === "done") showOcrStatus();
+  });
+
+  type OcrStatus = { engine: string | null; error: string | null; languages: string[]; tessdata: string };
+  const showOcrStatus = () =>
+    invoke<OcrStatus>("get_ocr_status").then((s) => {
+      const packs = s.languages.length ? s.languages.join(", ") : "none";
+      setText("ocrStatus", s.engine
+        ? `OCR: ${s.engine}, language packs: ${packs} (downloaded ones go to ${s.tessdata})`
+        : `OCR: ${s.error}`);
+    }).catch(() => {});
+  showOcrStatus();
+
+  $("startAsset").onclick = async () => {
+    try {
+      await invoke("start_download", {
+        id: ASSET_ID,
+        url: $("assetUrl").value.trim(),
+        dest: $("assetDest").value.trim(),
+        sha256: $("assetSha").value.trim()
+      });
+    } catch (e: any) {
+      setText("assetProgress", String(e?.message || e));
+    }
+  };
+  $("pauseAsset").onclick = () => invoke("pause_download", { id: ASSET_ID }).catch(() => {});
+  $("cancelAsset").onclick = () => invoke("cancel_download", { id: ASSET_ID }).catch(() => {});
+
+  type CacheStats = { entries: number; bytes: number; max_bytes: number };
+  const showCache = (c: CacheStats) => {
+    const mb = (n: number) => (n / 1024 / 1024).toFixed(1);
+    setText("cacheStats", c.max_bytes
+      ? `Translation cache: ${c.entries} entries, ${mb(c.bytes)} / ${mb(c.max_bytes)} MB`
+      : "Translation cache: off");
+  };
+  invoke<CacheStats>("get_translation_cache_size").then(showCache).catch(() => {});
+
+  $("clearCache").onclick = async () => {
+    try {
+      showCache(await invoke<CacheStats>("clear_translation_cache"));
+    } catch (e: any) {
+      setText("settingsHint", `Could not clear the translation cache: ${String(e?.message || e)}`);
+    }
+  };
+
+  type Settings = {
+    version: number;
+    direction: string;
+    theme: string;
+    last_document_dir: string | null;
+    last_output_dir: string | null;
+    encrypt_at_rest: boolean;
+    subtitle_line_width: number;
+    subtitle_max_lines: number;
+    subtitle_max_cps: number;
+    po_retranslate: boolean;
+    ocr_languages: string;
+    output_template: string;
+    output_dir: string | null;
+    output_conflict: string;
+    tm_min_match: number;
+    segment_concurrency: number;
+  };
+  const subtitleLimits: [string, "subtitle_line_width" | "subtitle_max_lines" | "subtitle_max_cps"][] = [
+    ["subtitleLineWidth", "subtitle_line_width"],
+    ["subtitleMaxLines", "subtitle_max_lines"],
+    ["subtitleMaxCps", "subtitle_max_cps"],
+  ];
+  const showSettings = (s: Settings) => {
+    $("direction").value = s.direction;
+    $("theme").value = s.theme;
+    ($("encryptAtRest") as HTMLInputElement).checked = s.encrypt_at_rest;
+    ($("poRetranslate") as HTMLInputElement).checked = s.po_retranslate;
+    $("ocrLanguages").value = s.ocr_languages;
+    $("outputTemplate").value = s.output_template;
+    $("outputDir").value = s.output_dir ?? "";
+    $("outputConflict").value = s.output_conflict;
+    $("tmMinMatch").value = String(s.tm_min_match);
+    $("segmentConcurrency").value = String(s.segment_concurrency);
+    for (const [id, key] of subtitleLimits) $(id).value = String(s[key]);
+    document.documentElement.dataset.theme = s.theme;
+  };
+  showSettings(await invoke<Settings>("get_settings"));
+  // other windows change them too
+  await listen<Settings>("settings-changed", (e) => showSettings(e.payload));
+  const updateSettings = async (patch: Partial<Settings>) => {
+    try {
+      await invoke<Settings>("update_settings", { patch });
+    } catch (e: any) {
+      setText("settingsHint", `Could not save settings: ${String(e?.message || e)}`);
+    }
+  };
+  $("direction").onchange = () => updateSettings({ direction: $("direction").value });
+  $("theme").onchange = () => updateSettings({ theme: $("theme").value });
+  for (const [id, key] of subtitleLimits) {
+    $(id).onchange = () => updateSettings({ [key]: Number($(id).value) });
+  }
+  $("poRetranslate").onchange = () =>
+    updateSettings({ po_retranslate: ($("poRetranslate") as HTMLInputElement).checked });
+  $("ocrLanguages").onchange = () => updateSettings({ ocr_languages: $("ocrLanguages").value.trim() });
+  $("outputTemplate").onchange = () => updateSettings({ output_template: $("outputTemplate").value.trim() });
+  $("outputDir").onchange = () => updateSettings({ output_dir: $("outputDir").value.trim() || null });
+  $("outputConflict").onchange = () => updateSettings({ output_conflict: $("outputConflict").value });
+  $("tmMinMatch").onchange = async () => {
+    await updateSettings({ tm_min_match: Number($("tmMinMatch").value) });
+    setText("settingsHint", "Restart the backend to apply the new match threshold.");
+  };
+  $("segmentConcurrency").onchange = () =>
+    updateSettings({ segment_concurrency: Number($("segmentConcurrency").value) });
+  $("encryptAtRest").onchange = async () => {
+    await updateSettings({ encrypt_at_rest: ($("encryptAtRest") as HTMLInputElement).checked });
+    setText("settingsHint", "Restart the backend to convert existing data.");
+  };
+
+  $("saveAppLock").onclick = async () => {
+    const current = $("currentMasterPassword") as HTMLInputElement;
+    const next = $("masterPassword") as HTMLInputElement;
+    try {
+      const s = await invoke<LockStatus>("set_master_password", {
+        currentPassword: current.value || null,
+        password: next.value || null,
+        idleMinutes: Number(($("lockIdleMinutes") as HTMLInputElement).value) || 0
+      });
+      current.value = "";
+      next.value = "";
+      setText("settingsHint", s.enabled ? "App lock saved." : "App lock turned off.");
+    } catch (e: any) {
+      setText("settingsHint", `Could not save the app lock: ${String(e?.message || e)}`);
+    }
+  };
+  $("lockNow").onclick = () => invoke("lock_app");
+
+  $("lowPriority").onchange = async () => {
+    const el = $("lowPriority") as HTMLInputElement;
+    try {
+      await invoke("set_backend_priority", { background: el.checked });
+    } catch (e: any) {
+      setText("settingsHint", `Could not change backend priority: ${String(e?.message || e)}`);
+    }
+  };
+
+  // documents above this are read from disk by the shell instead of the webview
+  const chunkBytes = ((await invoke<{ upload_chunk_mb: number }>("get_startup_config")).upload_chunk_mb || 8) * 1024 * 1024;
+  let pickedPath: string | null = null;
+
+  type PickedDocument = {
+    path: string;
+    name: string;
+    size: number;
+    extension: string;
+    detected_type: string;
+    read_as: string;
+    translatable: boolean;
+  };
+
+  // plain text and subtitles: the detected encoding, with a preview to spot mojibake
+  type TextEncoding = { encoding: string; overridden: boolean; preview: string };
+  const showEncoding = (e: TextEncoding | null) => {
+    $("encodingRow").hidden = !e;
+    $("encodingPreview").hidden = !e;
+    if (!e) return;
+    $("textEncoding").value = e.encoding;
+    setText("encodingPreview", `${e.overridden ? "Read as" : "Detected"} ${e.encoding}:\n${e.preview}`);
+  };
+  const checkEncoding = async (path: string) => {
+    const text = /\.(txt|csv|tsv|srt|vtt)$/i.test(path);
+    showEncoding(text ? await invoke<TextEncoding>("get_text_encoding", { path }).catch(() => null) : null);
+  };
+  // scope of the chosen document before a task is created from it
+  type DocumentStats = {
+    pages: number | null;
+    scanned_pages: number;
+    segments: number;
+    words: number;
+    characters: number;
+    languages: { code: string; share: number }[];
+    direction: string | null;
+    non_translatable: number;
+  };
+  const showStats = async (path: string | null) => {
+    $("docStats").hidden = !path;
+    if (!path) return;
+    setText("docStats", "Analyzing…");
+    try {
+      const s = await invoke<DocumentStats>("analyze_document", { path });
+      if (path !== pickedPath) return;
+      const languages = s.languages.map((l) => `${l.code} ${Math.round(l.share * 100)}%`).join(", ") || "none";
+      const lines = [
+        (s.pages === null ? "" : `${s.pages} pages, `) + `${s.segments} segments, ${s.words} words, ${s.characters} characters`,
+        `Languages: ${languages}; ${Math.round(s.non_translatable * 100)}% numbers, links and placeholders`
+      ];
+      if (s.scanned_pages) lines.push(`${s.scanned_pages} scanned pages, read by OCR and not counted`);
+      // the source language is filled in from the document's
+      if (s.direction && s.direction !== $("direction").value) {
+        $("direction").value = s.direction;
+        await updateSettings({ direction: s.direction });
+        lines.push(`Direction set to ${s.direction} from the document's language`);
+      }
+      setText("docStats", lines.join("\n"));
+      const provider = providerHost();
+      const model = $("model").value.trim();
+      if (!provider || !model) return;
+      const est = await invoke<{
+        requests: number; input_tokens: number; output_tokens: number;
+        cost: number | null; currency: string; prices: string | null; seconds: number;
+      }>("estimate_job", { docId: path, provider, model });
+      if (path !== pickedPath) return;
+      const cost = est.cost === null ? "no price set for this model"
+        : `about ${est.cost.toFixed(est.cost < 1 ? 4 : 2)} ${est.currency}` + (est.prices === "list" ? " at list prices" : "");
+      lines.push(`Estimate with ${model}: ${est.input_tokens} tokens in, ${est.output_tokens} out, ${est.requests} requests; `
+        + `${cost}, ~${Math.ceil(est.seconds / 60)} min`);
+      setText("docStats", lines.join("\n"));
+    } catch (e: any) {
+      setText("docStats", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("rereadEncoding").onclick = async () => {
+    if (!pickedPath) return;
+    try {
+      showEncoding(await invoke<TextEncoding>("set_text_encoding", { path: pickedPath, encoding: $("textEncoding").value }));
+    } catch (e: any) {
+      setText("taskHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("detectEncoding").onclick = async () => {
+    if (!pickedPath) return;
+    showEncoding(await invoke<TextEncoding>("set_text_encoding", { path: pickedPath, encoding: null }).catch(() => null));
+  };
+  $("pickFile").onclick = async () => {
+    const picked = await invoke<PickedDocument[]>("pick_documents");
+    if (!picked.length) return;
+    const doc = picked[0];
+    if (doc.detected_type === "pdf") {
+      // read by the shell; the backend only gets the segmented text
+      const pages = await invoke<{ number: number; runs: { text: string }[] }[]>("extract_pdf_text", { path: doc.path })
+        .catch(() => []);
+      $("srcText").value = pages.map((p) => `--- page ${p.number} ---\n` + p.runs.map((r) => r.text).join("\n")).join("\n");
+    }
+    if (!doc.translatable) {
+      setText("taskHint", `${doc.name} looks like ${doc.detected_type}; only Word, PDF and PowerPoint documents can be translated for now.`);
+      return;
+    }
+    pickedPath = doc.path;
+    $("file").value = "";
+    await checkEncoding(doc.path);
+    void showStats(doc.path);
+    const more = picked.length > 1 ? ` (${picked.length - 1} more ignored: one document per task)` : "";
+    const misnamed = doc.read_as !== doc.extension ? `, read as .${doc.read_as} by its content` : "";
+    setText("taskHint", `Selected ${doc.name}, ${(doc.size / 1024).toFixed(0)} KB${misnamed}${more}`);
+  };
+  $("file").onchange = () => {
+    pickedPath = null;
+    showEncoding(null);
+    void showStats(null);
+  };
+
+  // the shell checks and hashes dropped files; it keeps accepted ones queued
+  // until a task is created from them, so dropping one twice is caught
+  type DroppedDocument = PickedDocument & { sha256: string };
+  let droppedHash: string | null = null;
+  await listen<{
+    accepted: DroppedDocument[];
+    duplicates: { path: string; queued_path: string }[];
+    rejected: { path: string; reason: string }[];
+  }>("documents-dropped", async (e) => {
+    const { accepted, duplicates, rejected } = e.payload;
+    const notes = [
+      ...rejected.map((r) => `${r.path}: ${r.reason}`),
+      ...duplicates.map((d) => `${d.path}: same content as ${d.queued_path}`)
+    ];
+    const doc = accepted[0];
+    if (doc) {
+      if (droppedHash) await invoke("dequeue_document", { sha256: droppedHash });
+      for (const other of accepted.slice(1)) {
+        await invoke("dequeue_document", { sha256: other.sha256 });
+        notes.push(`${other.path}: ignored, one document per task`);
+      }
+      pickedPath = doc.path;
+      droppedHash = doc.sha256;
+      await checkEncoding(doc.path);
+      void showStats(doc.path);
+      $("file").value = "";
+      notes.unshift(`Selected ${doc.name}, ${(doc.size / 1024).toFixed(0)} KB`);
+    }
+    setText("taskHint", notes.join("\n"));
+  });
+
+  // uploads and exports of password-protected documents wait for this
+  await listen<{ doc_id: string; name: string; retry: boolean }>("password-required", async (e) => {
+    const p = e.payload;
+    const question = p.retry ? `Wrong password for ${p.name}. Try again:` : `${p.name} is password protected. Password:`;
+    const password = window.prompt(question) ?? "";
+    await invoke("provide_document_password", { docId: p.doc_id, password });
+  });
+
+  await listen<{ id: string; sent: number; total: number }>("upload-progress", (e) => {
+    if (e.payload.id !== "task-upload" || !e.payload.total) return;
+    const u = e.payload;
+    setText("taskHint", `Uploading… ${Math.floor((u.sent / u.total) * 100)}%`);
+  });
+
+  // large text, tables and subtitles are segmented while they are read
+  await listen<{ id: string; read: number; total: number; segments: number }>("segment-progress", (e) => {
+    const p = e.payload;
+    if (p.id !== "task-upload" || !p.total) return;
+    setText("taskHint", `Segmenting… ${Math.floor((p.read / p.total) * 100)}% read, ${p.segments} segments`);
+  });
+
+  // ZIP archives: each checked entry becomes a task, and the translations
+  // go into a copy of the archive with the same layout
+  type ArchiveEntry = { name: string; size: number; format: string | null; translatable: boolean; reason: string | null };
+  let archiveId: string | null = null;
+  const showArchive = (entries: ArchiveEntry[]) => {
+    const list = $("archiveEntries") as HTMLElement;
+    list.innerHTML = "";
+    for (const entry of entries) {
+      const label = document.createElement("label");
+      const box = document.createElement("input");
+      box.type = "checkbox";
+      box.value = entry.name;
+      box.checked = entry.translatable;
+      box.disabled = !entry.translatable;
+      label.append(box, ` ${entry.name} (${(entry.size / 1024).toFixed(0)} KB${entry.format ? `, ${entry.format}` : ""}${entry.reason ? `: ${entry.reason}` : ""})`);
+      list.appendChild(document.createElement("div")).appendChild(label);
+    }
+    $("translateArchive").hidden = !entries.length;
+    $("closeArchive").hidden = !entries.length;
+  };
+  const closeArchive = async () => {
+    if (archiveId) await invoke("close_archive", { id: archiveId });
+    archiveId = null;
+    showArchive([]);
+  };
+  $("pickArchive").onclick = async () => {
+    const path = await invoke<string | null>("pick_archive");
+    if (!path) return;
+    await closeArchive();
+    try {
+      const listing = await invoke<{ id: string; entries: ArchiveEntry[] }>("open_archive", { path });
+      archiveId = listing.id;
+      showArchive(listing.entries);
+      const translatable = listing.entries.filter((e) => e.translatable).length;
+      setText("archiveHint", `${listing.entries.length} files, ${translatable} can be translated`);
+    } catch (e: any) {
+      setText("archiveHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("closeArchive").onclick = async () => {
+    await closeArchive();
+    setText("archiveHint", "");
+  };
+  $("translateArchive").onclick = async () => {
+    if (!archiveId) return;
+    const names = Array.from(document.querySelectorAll<HTMLInputElement>("#archiveEntries input:checked")).map((b) => b.value);
+    const id = archiveId;
+    archiveId = null;
+    showArchive([]);
+    try {
+      const result = await invoke<{ path: string; entries: { name: string; error: string | null; warnings: number }[] }>(
+        "translate_archive",
+        { id, names, direction: $("direction").value, path: null }
+      );
+      const lines = result.entries.map((e) =>
+        e.error ? `${e.name}: kept as it was, ${e.error}` : `${e.name}: translated${e.warnings ? `, ${e.warnings} warnings` : ""}`
+      );
+      setText("archiveHint", [`Saved ${result.path}`, ...lines].join("\n"));
+    } catch (e: any) {
+      setText("archiveHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  await listen<{ id: string; name: string; done: number; total: number; stage: string; progress: number }>(
+    "archive-progress",
+    (e) => {
+      const p = e.payload;
+      setText("archiveHint", `${p.done + 1} / ${p.total}: ${p.name}, ${p.stage} ${Math.floor(p.progress * 100)}%`);
+    }
+  );
+
+  // the job queue: documents translated in the background, a few at a time,
+  // and still queued after a restart
+  type Job = {
+    id: string;
+    path: string;
+    priority: number;
+    state: string;
+    stage: string | null;
+    progress: number;
+    output: string | null;
+    leverage: Record<string, { segments: number; words: number }> | null;
+    error: string | null;
+  };
+  const jobs = new Map<string, Job>();
+  const showJobs = () => {
+    const list = $("jobList") as HTMLElement;
+    list.innerHTML = "";
+    const sorted = [...jobs.values()].sort((a, b) => b.priority - a.priority);
+    for (const job of sorted) {
+      const row = list.appendChild(document.createElement("div"));
+      const name = job.path.split(/[\\/]/).pop();
+      // what the translation memory gave, e.g. "exact 12, 85-94 3"
+      const leverage = Object.entries(job.leverage ?? {}).map(([band, n]) => `${band} ${n.segments}`).join(", ");
+      const detail = job.stage ? `${job.stage} ${Math.floor(job.progress * 100)}%`
+        : (job.output ?? job.error ?? "") + (leverage ? ` (TM: ${leverage})` : "");
+      row.append(`${name} [${job.priority}] ${job.state}${detail ? `: ${detail}` : ""} `);
+      const actions: [string, string][] = [];
+      if (job.state === "queued" || job.state === "running") actions.push(["Pause", "pause_job"]);
+      // a cancelled job goes on from the blocks it had translated
+      if (job.state === "paused" || job.state === "cancelled") actions.push(["Resume", "resume_job"]);
+      if (["queued", "running", "paused"].includes(job.state)) actions.push(["Cancel", "cancel_job"]);
+      for (const [label, command] of actions) {
+        const button = row.appendChild(document.createElement("button"));
+        button.textContent = label;
+        button.onclick = () => invoke(command, { id: job.id }).catch(() => {});
+      }
+    }
+  };
+  $("queueDocuments").onclick = async () => {
+    const picked = await invoke<PickedDocument[]>("pick_documents");
+    const priority = Number($("jobPriority").value) || 0;
+    const concurrency = Number($("jobConcurrency").value) || null;
+    for (const doc of picked) {
+      await invoke("enqueue_job", { path: doc.path, direction: $("direction").value, priority, concurrency }).catch((e) =>
+        setText("archiveHint", (e as ProxyError)?.message ?? String(e))
+      );
+    }
+  };
+  $("translateFolder").onclick = async () => {
+    const path = await invoke<string | null>("pick_folder");
+    if (!path) return;
+    const patterns = (id: string) => $(id).value.split(/\s+/).filter(Boolean);
+    try {
+      const batch = await invoke<{ id: string; output_dir: string; jobs: Job[]; skipped: { path: string; reason: string }[] }>(
+        "translate_folder",
+        {
+          path,
+          options: {
+            include: patterns("folderInclude"),
+            exclude: patterns("folderExclude"),
+            direction: $("direction").value,
+            priority: Number($("jobPriority").value) || 0,
+          },
+        }
+      );
+      const skipped = batch.skipped.map((s) => `${s.path}: ${s.reason}`);
+      setText("folderHint", [`${batch.jobs.length} documents queued, to ${batch.output_dir}`, ...skipped].join("\n"));
+    } catch (e: any) {
+      setText("folderHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  await listen<{ id: string; total: number; done: number; failed: number; cancelled: number; progress: number }>(
+    "folder-progress",
+    (e) => {
+      const p = e.payload;
+      const failed = p.failed ? `, ${p.failed} failed` : "";
+      setText("folderHint", `${p.done} / ${p.total} translated${failed}, ${Math.floor(p.progress * 100)}%`);
+    }
+  );
+  // hot folders: documents dropped in are translated with the folder's
+  // profile and moved to its done/ subfolder
+  type WatchFolder = { path: string; profile_id: string | null; direction: string | null };
+  const showWatchFolders = (folders: WatchFolder[]) => {
+    const list = $("watchFolders") as HTMLElement;
+    list.innerHTML = "";
+    for (const folder of folders) {
+      const row = list.appendChild(document.createElement("div"));
+      const profile = profiles.find((p) => p.id === folder.profile_id)?.name ?? "active profile";
+      row.append(`${folder.path} (${profile}, ${folder.direction ?? "default direction"}) `);
+      const remove = row.appendChild(document.createElement("button"));
+      remove.textContent = "Stop Watching";
+      remove.onclick = async () => showWatchFolders(await invoke<WatchFolder[]>("remove_watch_folder", { path: folder.path }));
+    }
+  };
+  $("addWatchFolder").onclick = async () => {
+    const path = await invoke<string | null>("pick_folder");
+    if (!path) return;
+    if ("Notification" in window && Notification.permission === "default") await Notification.requestPermission();
+    try {
+      const folders = await invoke<WatchFolder[]>("add_watch_folder", {
+        path,
+        profileId: $("profileList").value || null,
+        direction: $("direction").value,
+      });
+      showWatchFolders(folders);
+      setText("watchHint", `Watching ${path}`);
+    } catch (e: any) {
+      setText("watchHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  await listen<{ folder: string; path: string; kind: string; message: string }>("watch-notification", (e) => {
+    setText("watchHint", e.payload.message);
+    if ("Notification" in window && Notification.permission === "granted") {
+      new Notification(e.payload.kind === "failed" ? "Translation failed" : "Watch folder", { body: e.payload.message });
+    }
+  });
+  showWatchFolders(await invoke<WatchFolder[]>("list_watch_folders"));
+
+  await listen<Job>("job-progress", (e) => {
+    jobs.set(e.payload.id, e.payload);
+    showJobs();
+  });
+  for (const job of await invoke<Job[]>("list_jobs")) jobs.set(job.id, job);
+  showJobs();
+
+  type OcrProgress = {
+    id: string;
+    page: number;
+    done: number;
+    total: number;
+    confidence: number | null;
+    blocks: number;
+    error: string | null;
+  };
+  // scanned PDF pages are read before the upload starts
+  await listen<OcrProgress>("ocr-progress", (e) => {
+    const p = e.payload;
+    if (p.id !== "task-upload") return;
+    const read = p.error
+      ?? `${p.blocks} blocks` + (p.confidence === null ? "" : `, ${Math.round(p.confidence)}% confidence`);
+    setText("taskHint", `OCR: scanned page ${p.done} of ${p.total} (page ${p.page}): ${read}`);
+  });
+
   $("createTask").onclick = async () => {
     try {
       const f: File | undefined = $("file").files?.[0];
-      if (!f) throw new Error("Please select a .docx file.");
+      if (!f && !pickedPath) throw new Error("Please select a .docx file.");
+      if (f && f.size > chunkBytes) {
+        throw new Error("This document is large: use \"Choose Large File…\" so it is uploaded from disk in chunks.");
+      }
       const direction = $("direction").value;
 
       // reset UI state
@@ -218,15 +1572,54 @@ $("loadModels").onclick = async () => {
       setText("progressCounts", "");
       setProgress(0, "created");
 
-      const form = new FormData();
-      form.append("file", f);
-      form.append("direction", direction);
+      let out: { task_id: string; blocks: number };
+      if (pickedPath) {
+        try {
+          const cellRange = $("cellRange").value.trim() || null;
+          const review = ($("docxReview") as HTMLSelectElement).value;
+          out = await invoke("upload_document", { id: "task-upload", path: pickedPath, direction, cellRange, review });
+          if (droppedHash) {
+            await invoke("dequeue_document", { sha256: droppedHash });
+            droppedHash = null;
+          }
+        } catch (e: any) {
+          throw new Error((e as ProxyError)?.message ?? String(e));
+        }
+      } else {
+        const form = new FormData();
+        form.append("file", f!);
+        form.append("direction", direction);
 
-      const r = await fetch(`${BASE}/api/tasks`, { method: "POST", body: form });
-      if (!r.ok) throw new Error(await r.text());
-      const out = await r.json();
+        // let the browser build the multipart body, then send its bytes
+        const req = new Request("http://localhost/", { method: "POST", body: form });
+        out = parseJson(await api("POST", "/api/tasks", {
+          headers: { "content-type": req.headers.get("content-type") || "" },
+          body: new Uint8Array(await req.arrayBuffer()),
+          timeoutMs: 120000
+        }));
+      }
 
       currentTaskId = out.task_id;
+      // documents read from disk were segmented by the shell, which also rebuilds them
+      // by content, like the shell read it, so misnamed files export back the same way
+      const readAs = pickedPath
+        ? await invoke<{ extension: string }>("detect_format", { path: pickedPath })
+            .then((d) => d.extension)
+            .catch(() => pickedPath!.split(".").pop() || "")
+        : "";
+      shellFormat = pickedPath
+        ? readAs
+            .toLowerCase()
+            .replace(/^markdown$/, "md")
+            .replace(/^htm$/, "html")
+            .replace(/^pot$/, "po")
+            .replace(/^xliff$/, "xlf")
+            .replace(/^yml$/, "yaml")
+            .replace(/^jpeg$/, "jpg")
+            // one export for all three OpenDocument formats
+            .replace(/^od[tsp]$/, "odf")
+            .replace(/^tsv$/, "csv")
+        : null;
       setText("taskHint", `Task created: ${currentTaskId}\nBlocks: ${out.blocks}`);
 
       // start polling immediately so user sees progress without clicking anything
@@ -236,14 +1629,49 @@ $("loadModels").onclick = async () => {
     }
   };
 
+  // translation runs requested while offline; started once back online
+  const queuedRuns: string[] = [];
+
+  const runTranslation = async (taskId: string) => {
+    // spread translation runs over the backend worker pool; all workers share one db
+    const { segment_concurrency: concurrency } = await invoke<{ segment_concurrency: number }>("get_settings");
+    const out = parseJson(await api("POST", `/api/tasks/${taskId}/run_translate`, { ...jsonBody({ concurrency }), balance: true }));
+    setText("progressHint", JSON.stringify(out, null, 2));
+
+    // ensure polling is on
+    if (taskId === currentTaskId) startPolling();
+  };
+
+  type NetworkStatus = { online: boolean | null; latency_ms: number | null; error: string | null };
+  let online: boolean | null = null;
+  const showNetwork = (n: NetworkStatus) => {
+    online = n.online;
+    setText("networkStatus", n.online === false ? `Offline: ${n.error ?? "no connection"}`
+      : n.online ? `Online (${n.latency_ms} ms)` : "");
+  };
+  await listen<NetworkStatus>("network-status", async (e) => {
+    showNetwork(e.payload);
+    if (!e.payload.online) return;
+    while (queuedRuns.length) {
+      const taskId = queuedRuns.shift()!;
+      try {
+        await runTranslation(taskId);
+      } catch (err: any) {
+        setText("progressHint", String(err?.message || err));
+      }
+    }
+  });
+  showNetwork(await invoke<NetworkStatus>("get_network_status"));
+
   $("runTranslate").onclick = async () => {
     try {
       if (!currentTaskId) throw new Error("Please create a task first.");
-      const out = await apiPostJson(`/api/tasks/${currentTaskId}/run_translate`, {});
-      setText("progressHint", JSON.stringify(out, null, 2));
-
-      // ensure polling is on
-      startPolling();
+      if (online === false) {
+        if (!queuedRuns.includes(currentTaskId)) queuedRuns.push(currentTaskId);
+        setText("progressHint", "Offline: translation queued; it starts when the connection is back.");
+        return;
+      }
+      await runTranslation(currentTaskId);
     } catch (e: any) {
       setText("progressHint", String(e?.message || e));
     }
@@ -276,14 +1704,121 @@ $("loadModels").onclick = async () => {
     }
   };
 
+  let liveStreamId: string | null = null;
+
+  $("retranslateBlock").onclick = async () => {
+    if (!currentTaskId || !currentBlockId) {
+      setText("blockHint", "Please select a block first.");
+      return;
+    }
+    const id = crypto.randomUUID();
+    const blockId = currentBlockId;
+    const unlisten: UnlistenFn[] = [];
+    const finish = (msg: string) => {
+      unlisten.forEach((f) => f());
+      liveStreamId = null;
+      $("retranslateBlock").disabled = false;
+      $("cancelRetranslate").disabled = true;
+      setText("blockHint", msg);
+    };
+
+    $("dstText").value = "";
+    unlisten.push(await listen<{ id: string; event: string | null; data: string }>("stream-chunk", (e) => {
+      if (e.payload.id !== id || currentBlockId !== blockId) return;
+      const data = JSON.parse(e.payload.data);
+      if (e.payload.event === "token") $("dstText").value += data;
+      else if (e.payload.event === "done") $("dstText").value = data.translated_text;
+      else if (e.payload.event === "error") setText("blockHint", `Error: ${data}`);
+    }));
+    unlisten.push(await listen<{ id: string; cancelled: boolean; error: string | null }>("stream-done", (e) => {
+      if (e.payload.id !== id) return;
+      finish(e.payload.cancelled ? "Retranslation stopped; the block was not changed."
+        : e.payload.error ? `Stream failed: ${e.payload.error}` : "Retranslated.");
+      if (blocksLoaded) refreshBlocks(true).catch(() => {});
+    }));
+
+    liveStreamId = id;
+    $("retranslateBlock").disabled = true;
+    $("cancelRetranslate").disabled = false;
+    setText("blockHint", "Translating...");
+    try {
+      await invoke("proxy_stream", {
+        id,
+        request: {
+          method: "POST",
+          path: `/api/tasks/${currentTaskId}/blocks/${blockId}/translate_stream`,
+          provider: providerHost()
+        }
+      });
+    } catch (e: any) {
+      finish(String(e?.message || e));
+    }
+  };
+
+  $("cancelRetranslate").onclick = async () => {
+    if (liveStreamId) await invoke("cancel_stream", { id: liveStreamId });
+  };
+
+  // how the shell splits the source into sentences, and into the chunks sent when it is long
+  $("previewSegments").onclick = async () => {
+    type Piece = { text: string; start: number; end: number; tokens: number };
+    try {
+      const text = $("srcText").value;
+      if (!text) throw new Error("Please select a block first.");
+      const detected = await invoke<{
+        lang: string | null;
+        script: string | null;
+        segments: { start: number; end: number; lang: string | null }[];
+      }>("detect_language", { text });
+      const lang = detected.lang ?? ($("direction").value || "zh->en").split("->")[0];
+      const s = await invoke<{ sentences: Piece[]; chunks: Piece[]; max_tokens: number }>("segment_text", { text, lang, opts: null });
+      const list = (pieces: Piece[]) => pieces.map((p, i) => `${i + 1}. [${p.tokens}] ${p.text}`).join("\n");
+      const languages = detected.segments.length > 1
+        ? detected.segments.map((g) => `${g.lang ?? "?"} ${g.start}-${g.end}`).join(", ")
+        : `${lang} (${detected.script ?? "no letters"})`;
+      setText("blockHint", `Language: ${languages}\n\n${s.sentences.length} sentences:\n${list(s.sentences)}\n\n`
+        + `${s.chunks.length} chunks of at most ${s.max_tokens} tokens:\n${list(s.chunks)}`);
+    } catch (e: any) {
+      setText("blockHint", String(e?.message || e));
+    }
+  };
+
+  // small jobs straight to the provider selected in the settings, for when the backend is not up
+  const directProvider = () => {
+    const p = selectedProvider();
+    if (!p) throw new Error("Select a saved provider in the settings first.");
+    const model = $("model").value;
+    return { providerId: p.id, model: p.models.includes(model) ? model : null, direction: $("direction").value };
+  };
+  $("translateDirect").onclick = async () => {
+    try {
+      const text = $("directText").value;
+      if (!text.trim()) throw new Error("Enter or paste the text to translate.");
+      setText("directHint", "Translating…");
+      const [out] = await invoke<string[]>("translate_text", { ...directProvider(), texts: [text] });
+      setText("directHint", out);
+    } catch (e: any) {
+      setText("directHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("translateFileDirect").onclick = async () => {
+    try {
+      if (!pickedPath) throw new Error("Choose a file first.");
+      setText("directHint", "Translating…");
+      const r = await invoke<{ path: string; action: string }>("translate_file", { ...directProvider(), path: pickedPath });
+      setText("directHint", r.action === "skip" ? `${r.path} exists and was left alone.` : `Saved ${r.path}`);
+    } catch (e: any) {
+      setText("directHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
   $("exportDocx").onclick = async () => {
     try {
       if (!currentTaskId) throw new Error("Please create a task first.");
 
-      const r = await fetch(`${BASE}/api/tasks/${currentTaskId}/export`);
-      if (!r.ok) throw new Error(await r.text());
+      const r = await api("GET", `/api/tasks/${currentTaskId}/export`, { timeoutMs: 120000 });
 
-      const blob = await r.blob();
+      const blob = new Blob([new Uint8Array(r.body)], { type: r.headers["content-type"] });
       const a = document.createElement("a");
       a.href = URL.createObjectURL(blob);
       a.download = "translated.docx";
@@ -295,9 +1830,253 @@ $("loadModels").onclick = async () => {
       setText("progressHint", String(e?.message || e));
     }
   };
+
+  for (const [event, unit] of [
+    ["pdf-export-progress", "page"],
+    ["pptx-export-progress", "slide"],
+    ["epub-export-progress", "chapter"],
+  ]) {
+    await listen<{ job_id: string; done: number; total: number }>(event, (e) => {
+      if (e.payload.job_id !== currentTaskId) return;
+      setText("progressHint", `Writing… ${unit} ${e.payload.done} of ${e.payload.total}`);
+    });
+  }
+
+  $("exportFile").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      if (!shellFormat) throw new Error("This task was uploaded from the browser: use Export DOCX.");
+      const path = $("exportPath").value.trim();
+      if (!path) {
+        // named and placed by the output settings
+        const out = await invoke<{ path: string; action: string; warnings: number }>("export_translated", {
+          jobId: currentTaskId,
+        });
+        setText("progressHint", out.action === "skip"
+          ? `${out.path} exists; skipped, as the output settings say`
+          : `Saved ${out.path}` + (out.warnings ? `, ${out.warnings} segments with warnings` : ""));
+        return;
+      }
+      // subtitle cues over the limits, HTML segments that lost their tags
+      type ExportWarning = { cue?: number; start?: string; excerpt?: string; message: string };
+      const warnings = await invoke<ExportWarning[] | null>(`export_translated_${shellFormat}`, {
+        jobId: currentTaskId,
+        path,
+        ...(shellFormat === "png" || shellFormat === "jpg"
+          ? { sideBySide: ($("imageSideBySide") as HTMLInputElement).checked }
+          : {}),
+      });
+      const report = (warnings || []).map((w) =>
+        w.cue !== undefined ? `Cue ${w.cue} (${w.start}): ${w.message}` : `"${w.excerpt}…": ${w.message}`,
+      );
+      setText("progressHint", report.length ? [`Saved ${path}, with warnings:`, ...report].join("\n") : `Saved ${path}`);
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  type OutputPath = { kind: string; path: string; action: string };
+  $("previewOutputs").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const paths = await invoke<OutputPath[]>("preview_output_paths", { jobId: currentTaskId });
+      setText("progressHint", paths.map((p) => `${p.kind}: ${p.path} (${p.action})`).join("\n"));
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  // where the output settings put export `kind` when no path is entered
+  const outputPath = async (kind: string) => {
+    const path = $("exportPath").value.trim();
+    if (path) return path;
+    const planned = (await invoke<OutputPath[]>("preview_output_paths", { jobId: currentTaskId })).find((p) => p.kind === kind);
+    if (planned?.action === "skip") throw new Error(`${planned.path} exists; skipped, as the output settings say`);
+    return planned?.path ?? "";
+  };
+
+  // any task, for review in a CAT tool
+  $("exportXliff").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const path = await outputPath("xliff");
+      if (!path) throw new Error("Enter where to save the XLIFF file, e.g. C:\\docs\\report.xlf");
+      await invoke("export_xliff", { jobId: currentTaskId, path, version: $("xliffVersion").value });
+      setText("progressHint", `Saved ${path}`);
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  // any task, source and target side by side for review in a spreadsheet
+  $("exportCsv").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const path = await outputPath("bilingual");
+      if (!path) throw new Error("Enter where to save the table, e.g. C:\\docs\\report.csv (or .tsv)");
+      await invoke("export_bilingual_csv", { jobId: currentTaskId, path, encoding: $("csvEncoding").value });
+      setText("progressHint", `Saved ${path}`);
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  // any task, source and translation in one Word document for review
+  $("exportReview").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const path = await outputPath("review");
+      if (!path) throw new Error("Enter where to save the document, e.g. C:\\docs\\report.review.docx");
+      await invoke("export_bilingual_docx", { jobId: currentTaskId, path, layout: $("reviewLayout").value });
+      setText("progressHint", `Saved ${path}`);
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  // what a retranslation changed: the task in the box is the older one
+  type TranslationDiff = {
+    unchanged: number;
+    changed: number;
+    added: number;
+    removed: number;
+    segments: { change: string; source: string; a: string | null; b: string | null; pieces: { op: string; text: string }[] }[];
+  };
+  $("diffTranslations").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const other = $("compareTask").value.trim();
+      if (!other) throw new Error("Enter the task to compare with.");
+      const d = await invoke<TranslationDiff>("diff_translations", { jobA: other, jobB: currentTaskId });
+      const mark = { same: (t: string) => t, removed: (t: string) => `[-${t}-]`, added: (t: string) => `{+${t}+}` } as Record<string, (t: string) => string>;
+      const lines = d.segments.map((s) =>
+        s.change === "changed"
+          ? `~ ${s.pieces.length ? s.pieces.map((p) => mark[p.op](p.text)).join("") : s.b ?? ""}`
+          : `${s.change === "added" ? "+" : "-"} ${s.source}`,
+      );
+      setText("progressHint", [`${d.changed} changed, ${d.added} added, ${d.removed} removed, ${d.unchanged} unchanged`, ...lines].join("\n"));
+    } catch (e: any) {
+      setText("progressHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  $("importTmx").onclick = async () => {
+    try {
+      const path = $("tmxPath").value.trim();
+      if (!path) throw new Error("Enter the TMX file to import.");
+      const r = await invoke<{ units: number; added: number; updated: number; skipped: number }>("import_tmx", {
+        path,
+        project: $("tmProject").value.trim() || null,
+      });
+      setText(
+        "tmHint",
+        `Imported ${r.units} pairs: ${r.added} new, ${r.updated} updated` +
+          (r.skipped ? `; ${r.skipped} units without a translation skipped` : ""),
+      );
+    } catch (e: any) {
+      setText("tmHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  // terms in every prompt of their direction, and checked in translations
+  type Term = { id: string; direction: string; source: string; target: string; case_sensitive: boolean; forbidden: boolean };
+  const showGlossary = (terms: Term[]) => {
+    const list = $("glossaryList") as HTMLElement;
+    list.innerHTML = "";
+    for (const t of terms) {
+      const row = list.appendChild(document.createElement("div"));
+      row.append(`${t.direction} ${t.source || "*"} ${t.forbidden ? "≠" : "="} ${t.target}${t.case_sensitive ? " (case)" : ""} `);
+      const button = row.appendChild(document.createElement("button"));
+      button.textContent = "Delete";
+      button.onclick = async () => showGlossary(await invoke<Term[]>("delete_term", { id: t.id }));
+    }
+  };
+  showGlossary(await invoke<Term[]>("list_glossary"));
+  $("saveTerm").onclick = async () => {
+    try {
+      await invoke("save_term", {
+        term: {
+          direction: $("direction").value,
+          source: $("termSource").value,
+          target: $("termTarget").value,
+          case_sensitive: $("termCase").checked,
+          forbidden: $("termForbidden").checked
+        }
+      });
+      $("termSource").value = $("termTarget").value = "";
+      showGlossary(await invoke<Term[]>("list_glossary"));
+    } catch (e: any) {
+      setText("glossaryHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("importGlossary").onclick = async () => {
+    try {
+      const path = $("glossaryPath").value.trim();
+      if (!path) throw new Error("Enter the CSV or TBX file to import.");
+      const r = await invoke<{ added: number; updated: number; skipped: number }>("import_glossary", {
+        path,
+        direction: $("direction").value
+      });
+      setText("glossaryHint", `${r.added} terms added, ${r.updated} updated` + (r.skipped ? `, ${r.skipped} skipped` : ""));
+      showGlossary(await invoke<Term[]>("list_glossary"));
+    } catch (e: any) {
+      setText("glossaryHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("checkGlossary").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const found = await invoke<{ index: number; kind: string; source: string; target: string }[]>("check_glossary", {
+        taskId: currentTaskId
+      });
+      setText("glossaryHint", found.length
+        ? found.map((v) => `Block ${v.index + 1}: ` + (v.kind === "missing"
+          ? `"${v.source}" not translated as "${v.target}"` : `uses forbidden "${v.target}"`)).join("\n")
+        : "No block breaks the glossary.");
+    } catch (e: any) {
+      setText("glossaryHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+  $("runQa").onclick = async () => {
+    try {
+      if (!currentTaskId) throw new Error("Please create a task first.");
+      const r = await invoke<{
+        segments: number;
+        errors: number;
+        warnings: number;
+        issues: { index: number; check: string; severity: string; message: string }[];
+      }>("run_qa", { jobId: currentTaskId });
+      setText("qaHint", [
+        `${r.segments} blocks: ${r.errors} errors, ${r.warnings} warnings`,
+        ...r.issues.map((i) => `Block ${i.index + 1} [${i.severity}] ${i.check}: ${i.message}`)
+      ].join("\n"));
+    } catch (e: any) {
+      setText("qaHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
+
+  // the project, languages and dates filter what is written
+  $("exportTmx").onclick = async () => {
+    try {
+      const path = $("tmxPath").value.trim();
+      if (!path) throw new Error("Enter where to save the TMX file.");
+      const count = await invoke<number>("export_tmx", {
+        path,
+        filter: {
+          source_lang: $("tmSourceLang").value.trim(),
+          target_lang: $("tmTargetLang").value.trim(),
+          project: $("tmProject").value.trim(),
+          since: $("tmSince").value,
+          until: $("tmUntil").value,
+        },
+      });
+      setText("tmHint", `Saved ${count} pairs to ${path}`);
+    } catch (e: any) {
+      setText("tmHint", (e as ProxyError)?.message ?? String(e));
+    }
+  };
 }
 
 main().catch((e) => {
-  setText("settingsHint", `Fatal error: ${String((e as any)?.message || e)}`);
+  showBackendFailure(`Fatal error: ${String((e as any)?.message || e)}`);
 });
 